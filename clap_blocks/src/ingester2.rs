@@ -1,6 +1,10 @@
 //! CLI config for the ingester using the RPC write path
 
-use std::path::PathBuf;
+use std::{
+    num::{NonZeroU64, NonZeroUsize},
+    path::PathBuf,
+    time::Duration,
+};
 
 /// CLI config for the ingester using the RPC write path
 #[derive(Debug, Clone, clap::Parser)]
@@ -20,6 +24,106 @@ pub struct Ingester2Config {
     )]
     pub wal_rotation_period_seconds: u64,
 
+    /// The maximum number of WAL segment files replayed concurrently at
+    /// startup. A value of 1 replays files one at a time, in order.
+    #[clap(
+        long = "wal-replay-concurrency",
+        env = "INFLUXDB_IOX_WAL_REPLAY_CONCURRENCY",
+        default_value = "1",
+        action
+    )]
+    pub wal_replay_concurrency: usize,
+
+    /// The maximum number of namespaces whose ops are applied concurrently
+    /// when replaying each WAL segment file at startup. The ops of a single
+    /// namespace are always applied in order.
+    #[clap(
+        long = "wal-replay-namespace-concurrency",
+        env = "INFLUXDB_IOX_WAL_REPLAY_NAMESPACE_CONCURRENCY",
+        default_value = "1",
+        action
+    )]
+    pub wal_replay_namespace_concurrency: usize,
+
+    /// The number of bytes of disk space to allocate for each WAL segment
+    /// file when it is created. Running out of disk space is then reported
+    /// when rotating the WAL instead of when appending to it. Disabled if not
+    /// set.
+    #[clap(
+        long = "wal-preallocate-bytes",
+        env = "INFLUXDB_IOX_WAL_PREALLOCATE_BYTES",
+        action
+    )]
+    pub wal_preallocate_bytes: Option<u64>,
+
+    /// The maximum total size in bytes of the WAL segment files. Once
+    /// reached, writes are rejected until persisted data is removed from the
+    /// WAL, rather than filling the disk. Unlimited if not set.
+    #[clap(long = "wal-max-bytes", env = "INFLUXDB_IOX_WAL_MAX_BYTES", action)]
+    pub wal_max_bytes: Option<u64>,
+
+    /// The total size in bytes of the WAL segment files at which the WAL is
+    /// rotated and all buffered data persisted ahead of the rotation period,
+    /// after which the oldest persisted segment files are deleted until the
+    /// WAL is below this size again. Should be set below `--wal-max-bytes`
+    /// so that writes are not rejected. Disabled if not set.
+    #[clap(
+        long = "wal-eviction-threshold-bytes",
+        env = "INFLUXDB_IOX_WAL_EVICTION_THRESHOLD_BYTES",
+        action
+    )]
+    pub wal_eviction_threshold_bytes: Option<u64>,
+
+    /// The number of outstanding persist jobs above which the periodic WAL
+    /// rotation is delayed until the backlog drains, rather than enqueueing
+    /// the persistence of yet more data. Never delayed if not set.
+    #[clap(
+        long = "wal-rotation-max-persist-backlog",
+        env = "INFLUXDB_IOX_WAL_ROTATION_MAX_PERSIST_BACKLOG",
+        action
+    )]
+    pub wal_rotation_max_persist_backlog: Option<usize>,
+
+    /// Reject writes while the WAL rotation is delayed by
+    /// `--wal-rotation-max-persist-backlog`.
+    #[clap(
+        long = "wal-rotation-backlog-reject-writes",
+        env = "INFLUXDB_IOX_WAL_ROTATION_BACKLOG_REJECT_WRITES",
+        action
+    )]
+    pub wal_rotation_backlog_reject_writes: bool,
+
+    /// The number of most recently created partitions read from the catalog
+    /// at startup and cached, avoiding a catalog request for the first write
+    /// to each of them.
+    #[clap(
+        long = "partition-cache-size",
+        env = "INFLUXDB_IOX_PARTITION_CACHE_SIZE",
+        default_value = "40000",
+        action
+    )]
+    pub partition_cache_size: usize,
+
+    /// The estimated memory in bytes that the partition cache may use. Only
+    /// the most recently created partitions that fit are cached. Unlimited if
+    /// not set.
+    #[clap(
+        long = "partition-cache-max-bytes",
+        env = "INFLUXDB_IOX_PARTITION_CACHE_MAX_BYTES",
+        action
+    )]
+    pub partition_cache_max_bytes: Option<usize>,
+
+    /// How long after startup the partitions that have not been written to
+    /// are evicted from the partition cache, such as "1h". Never evicted if
+    /// not set.
+    #[clap(
+        long = "partition-cache-ttl",
+        env = "INFLUXDB_IOX_PARTITION_CACHE_TTL",
+        value_parser = humantime::parse_duration
+    )]
+    pub partition_cache_ttl: Option<Duration>,
+
     /// Sets how many queries the ingester will handle simultaneously before
     /// rejecting further incoming requests.
     #[clap(
@@ -30,6 +134,39 @@ pub struct Ingester2Config {
     )]
     pub concurrent_query_limit: usize,
 
+    /// Adjust the number of queries handled simultaneously between
+    /// `--adaptive-query-concurrency-min` and `--concurrent-query-limit`,
+    /// decreasing it when queries take longer than
+    /// `--adaptive-query-target-latency` or the ingester is under memory
+    /// pressure, and increasing it otherwise.
+    #[clap(
+        long = "adaptive-query-concurrency",
+        env = "INFLUXDB_IOX_ADAPTIVE_QUERY_CONCURRENCY",
+        action
+    )]
+    pub adaptive_query_concurrency: bool,
+
+    /// The lowest number of queries handled simultaneously when
+    /// `--adaptive-query-concurrency` is enabled.
+    #[clap(
+        long = "adaptive-query-concurrency-min",
+        env = "INFLUXDB_IOX_ADAPTIVE_QUERY_CONCURRENCY_MIN",
+        default_value = "1",
+        action
+    )]
+    pub adaptive_query_concurrency_min: NonZeroUsize,
+
+    /// The query latency (including streaming the response) above which the
+    /// number of queries handled simultaneously is decreased when
+    /// `--adaptive-query-concurrency` is enabled.
+    #[clap(
+        long = "adaptive-query-target-latency",
+        env = "INFLUXDB_IOX_ADAPTIVE_QUERY_TARGET_LATENCY",
+        default_value = "5s",
+        value_parser = humantime::parse_duration
+    )]
+    pub adaptive_query_target_latency: Duration,
+
     /// The maximum number of persist tasks that can run simultaneously.
     #[clap(
         long = "persist-max-parallelism",
@@ -39,6 +176,38 @@ pub struct Ingester2Config {
     )]
     pub persist_max_parallelism: usize,
 
+    /// Adjust the number of persist tasks that run simultaneously between
+    /// `--persist-min-parallelism` and `--persist-max-parallelism`, starting
+    /// more when the queued persist tasks are not expected to complete within
+    /// `--persist-autoscale-target-latency`, and stopping idle ones otherwise.
+    #[clap(
+        long = "persist-autoscale",
+        env = "INFLUXDB_IOX_PERSIST_AUTOSCALE",
+        action
+    )]
+    pub persist_autoscale: bool,
+
+    /// The lowest number of persist tasks that run simultaneously when
+    /// `--persist-autoscale` is enabled.
+    #[clap(
+        long = "persist-min-parallelism",
+        env = "INFLUXDB_IOX_PERSIST_MIN_PARALLELISM",
+        default_value = "1",
+        action
+    )]
+    pub persist_min_parallelism: NonZeroUsize,
+
+    /// The duration within which the queued persist tasks should complete
+    /// when `--persist-autoscale` is enabled, above which more persist tasks
+    /// are run simultaneously.
+    #[clap(
+        long = "persist-autoscale-target-latency",
+        env = "INFLUXDB_IOX_PERSIST_AUTOSCALE_TARGET_LATENCY",
+        default_value = "30s",
+        value_parser = humantime::parse_duration
+    )]
+    pub persist_autoscale_target_latency: Duration,
+
     /// The maximum number of persist tasks that can be queued for each worker.
     ///
     /// Note that each partition is consistently hashed to the same worker -
@@ -65,4 +234,313 @@ pub struct Ingester2Config {
         action
     )]
     pub persist_submission_queue_depth: usize,
+
+    /// The approximate size in bytes of the data buffered for a single
+    /// partition at which it is persisted immediately, rather than at the
+    /// next WAL rotation. Disabled if not set.
+    #[clap(
+        long = "persist-hot-partition-bytes",
+        env = "INFLUXDB_IOX_PERSIST_HOT_PARTITION_BYTES",
+        action
+    )]
+    pub persist_hot_partition_bytes: Option<usize>,
+
+    /// The number of rows buffered for a single partition at which it is
+    /// persisted immediately, rather than at the next WAL rotation. Disabled
+    /// if not set.
+    #[clap(
+        long = "persist-hot-partition-rows",
+        env = "INFLUXDB_IOX_PERSIST_HOT_PARTITION_ROWS",
+        action
+    )]
+    pub persist_hot_partition_rows: Option<usize>,
+
+    /// The age of the data (by its timestamp), such as "1h", above which the
+    /// rows of a hot partition are persisted on their own, retaining the more
+    /// recent rows buffered until they too reach a persist trigger. All rows
+    /// are persisted if not set.
+    #[clap(
+        long = "persist-hot-partition-cold-age",
+        env = "INFLUXDB_IOX_PERSIST_HOT_PARTITION_COLD_AGE",
+        value_parser = humantime::parse_duration
+    )]
+    pub persist_hot_partition_cold_age: Option<Duration>,
+
+    /// The compression codec applied to the parquet files written by the
+    /// ingester: "zstd" for the smallest files, "snappy" for cheaper encoding
+    /// and decoding, or "uncompressed".
+    #[clap(
+        value_enum,
+        long = "persist-parquet-compression",
+        env = "INFLUXDB_IOX_PERSIST_PARQUET_COMPRESSION",
+        default_value = "zstd",
+        action
+    )]
+    pub persist_parquet_compression: ParquetCompression,
+
+    /// Disable the dictionary encoding of the columns of the parquet files
+    /// written by the ingester.
+    #[clap(
+        long = "disable-persist-parquet-dictionary",
+        env = "INFLUXDB_IOX_DISABLE_PERSIST_PARQUET_DICTIONARY",
+        action
+    )]
+    pub disable_persist_parquet_dictionary: bool,
+
+    /// Disable the bloom filters and page indexes written for the tag
+    /// columns of the parquet files written by the ingester, which allow
+    /// readers to skip data not matching a tag equality predicate.
+    #[clap(
+        long = "disable-persist-parquet-tag-indexes",
+        env = "INFLUXDB_IOX_DISABLE_PERSIST_PARQUET_TAG_INDEXES",
+        action
+    )]
+    pub disable_persist_parquet_tag_indexes: bool,
+
+    /// The approximate size in bytes above which the data persisted for a
+    /// partition is split by time range into multiple parquet files. The
+    /// size is estimated from the buffered data, usually overestimating the
+    /// file size. Disabled if not set.
+    #[clap(
+        long = "persist-max-parquet-file-bytes",
+        env = "INFLUXDB_IOX_PERSIST_MAX_PARQUET_FILE_BYTES",
+        action
+    )]
+    pub persist_max_parquet_file_bytes: Option<NonZeroUsize>,
+
+    /// The number of failed attempts at a catalog or object storage request
+    /// after which a persist job is quarantined. The data of a quarantined
+    /// job is retained in memory (and the WAL) until the job is re-enqueued
+    /// through the persist admin API and succeeds.
+    #[clap(
+        long = "persist-max-attempts",
+        env = "INFLUXDB_IOX_PERSIST_MAX_ATTEMPTS",
+        default_value = "10",
+        action
+    )]
+    pub persist_max_attempts: NonZeroUsize,
+
+    /// The maximum number of persist jobs of a single namespace executed at
+    /// once, preventing a namespace with enormous partitions from occupying
+    /// all the persist workers. Unlimited if not set.
+    #[clap(
+        long = "persist-namespace-max-jobs",
+        env = "INFLUXDB_IOX_PERSIST_NAMESPACE_MAX_JOBS",
+        action
+    )]
+    pub persist_namespace_max_jobs: Option<NonZeroUsize>,
+
+    /// Read back each parquet file uploaded by the ingester, checking it
+    /// holds the persisted data before the data is marked as persisted and
+    /// dropped from the WAL. Catches silent corruption during upload, at the
+    /// cost of reading every file back from object storage.
+    #[clap(
+        long = "persist-verify-parquet",
+        env = "INFLUXDB_IOX_PERSIST_VERIFY_PARQUET",
+        action
+    )]
+    pub persist_verify_parquet: bool,
+
+    /// The maximum size in bytes of the local cache of recently persisted
+    /// parquet files, kept in the `parquet_cache` subdirectory of the WAL
+    /// directory for co-located queriers to read (see the querier's
+    /// `--ingester-file-cache-dir`). Disabled if not set.
+    #[clap(
+        long = "persist-file-cache-bytes",
+        env = "INFLUXDB_IOX_PERSIST_FILE_CACHE_BYTES",
+        action
+    )]
+    pub persist_file_cache_bytes: Option<u64>,
+
+    /// The identity of this ingester recorded in the metadata of the parquet
+    /// files it persists, for auditing their origin. Defaults to the
+    /// `HOSTNAME` environment variable.
+    #[clap(long = "host-identity", env = "INFLUXDB_IOX_HOST_IDENTITY", action)]
+    pub host_identity: Option<String>,
+
+    /// The approximate size in bytes of the data buffered for a single
+    /// partition at which it is persisted by the write that reaches it, while
+    /// further writes are buffered anew. Disabled if not set.
+    #[clap(
+        long = "partition-buffer-max-bytes",
+        env = "INFLUXDB_IOX_PARTITION_BUFFER_MAX_BYTES",
+        action
+    )]
+    pub partition_buffer_max_bytes: Option<usize>,
+
+    /// The number of rows buffered for a single partition at which it is
+    /// persisted by the write that reaches it, while further writes are
+    /// buffered anew. Disabled if not set.
+    #[clap(
+        long = "partition-buffer-max-rows",
+        env = "INFLUXDB_IOX_PARTITION_BUFFER_MAX_ROWS",
+        action
+    )]
+    pub partition_buffer_max_rows: Option<usize>,
+
+    /// The approximate size in bytes of the data buffered across all
+    /// partitions above which the largest partitions are persisted to release
+    /// memory. Disabled if not set.
+    #[clap(
+        long = "buffer-soft-limit-bytes",
+        env = "INFLUXDB_IOX_BUFFER_SOFT_LIMIT_BYTES",
+        action
+    )]
+    pub buffer_soft_limit_bytes: Option<usize>,
+
+    /// The approximate size in bytes of the data buffered across all
+    /// partitions at which writes are rejected until persistence releases
+    /// memory, and WAL replay at startup waits. Should be set above
+    /// `--buffer-soft-limit-bytes` - if that is not set, the largest partitions
+    /// are persisted once this limit is reached. Unlimited if not set.
+    #[clap(
+        long = "buffer-hard-limit-bytes",
+        env = "INFLUXDB_IOX_BUFFER_HARD_LIMIT_BYTES",
+        action
+    )]
+    pub buffer_hard_limit_bytes: Option<usize>,
+
+    /// The number of rows each namespace may write per second, averaged over
+    /// time. Writes in excess of this rate are rejected, and the client told
+    /// when to retry. Unlimited if not set.
+    #[clap(
+        long = "namespace-write-rows-per-second",
+        env = "INFLUXDB_IOX_NAMESPACE_WRITE_ROWS_PER_SECOND",
+        action
+    )]
+    pub namespace_write_rows_per_second: Option<NonZeroU64>,
+
+    /// The number of bytes each namespace may write per second, averaged over
+    /// time. Writes in excess of this rate are rejected, and the client told
+    /// when to retry. Unlimited if not set.
+    #[clap(
+        long = "namespace-write-bytes-per-second",
+        env = "INFLUXDB_IOX_NAMESPACE_WRITE_BYTES_PER_SECOND",
+        action
+    )]
+    pub namespace_write_bytes_per_second: Option<NonZeroU64>,
+
+    /// The number of recent write idempotency keys remembered. A retried
+    /// write carrying the key of a write that was already applied is
+    /// acknowledged without being applied again. Disabled if not set.
+    #[clap(
+        long = "idempotency-cache-size",
+        env = "INFLUXDB_IOX_IDEMPOTENCY_CACHE_SIZE",
+        action
+    )]
+    pub idempotency_cache_size: Option<NonZeroUsize>,
+
+    /// The maximum number of bytes of buffered data returned by a single
+    /// query. A query exceeding it is failed with a "resource exhausted"
+    /// error. Unlimited if not set.
+    #[clap(
+        long = "query-memory-limit-bytes",
+        env = "INFLUXDB_IOX_QUERY_MEMORY_LIMIT_BYTES",
+        action
+    )]
+    pub query_memory_limit_bytes: Option<usize>,
+
+    /// The maximum number of bytes of buffered data held by the responses of
+    /// all in-flight queries. Queries are failed with a "resource exhausted"
+    /// error while it is reached. Unlimited if not set.
+    #[clap(
+        long = "query-memory-global-limit-bytes",
+        env = "INFLUXDB_IOX_QUERY_MEMORY_GLOBAL_LIMIT_BYTES",
+        action
+    )]
+    pub query_memory_global_limit_bytes: Option<usize>,
+
+    /// Expose the buffer debug gRPC service, describing the namespaces,
+    /// tables and partitions buffered in memory. Intended for diagnosing the
+    /// memory usage of the ingester.
+    #[clap(
+        long = "enable-buffer-debug-rpc",
+        env = "INFLUXDB_IOX_ENABLE_BUFFER_DEBUG_RPC",
+        action
+    )]
+    pub enable_buffer_debug_rpc: bool,
+
+    /// Expose the persist administration gRPC service, allowing the number of
+    /// persist tasks that run simultaneously to be changed at runtime.
+    #[clap(
+        long = "enable-persist-admin-rpc",
+        env = "INFLUXDB_IOX_ENABLE_PERSIST_ADMIN_RPC",
+        action
+    )]
+    pub enable_persist_admin_rpc: bool,
+
+    /// The compression applied to the Arrow Flight query responses sent to
+    /// the querier. Responses are only compressed for the callers that accept
+    /// the encoding, negotiated per call.
+    #[clap(
+        value_enum,
+        long = "query-response-compression",
+        env = "INFLUXDB_IOX_QUERY_RESPONSE_COMPRESSION",
+        default_value = "none",
+        action
+    )]
+    pub query_response_compression: QueryResponseCompression,
+
+    /// The addresses of the peer ingesters each write is replicated to
+    /// before it is acknowledged, such as
+    ///
+    /// "http://10.10.10.2:8083,http://10.10.10.3:8083"
+    ///
+    /// Writes are not replicated if not set.
+    #[clap(
+        long = "replication-peers",
+        env = "INFLUXDB_IOX_REPLICATION_PEERS",
+        use_value_delimiter = true,
+        action = clap::ArgAction::Append
+    )]
+    pub replication_peers: Vec<String>,
+
+    /// How many of the `--replication-peers` must acknowledge a write before
+    /// it is acknowledged: "all" of them, a "quorum" (a majority of the
+    /// ingesters, counting this one), or none, replicating writes
+    /// asynchronously ("async").
+    #[clap(
+        value_enum,
+        long = "replication-policy",
+        env = "INFLUXDB_IOX_REPLICATION_POLICY",
+        default_value = "all",
+        action
+    )]
+    pub replication_policy: ReplicationPolicy,
+}
+
+/// The compression codec applied to the persisted parquet files.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum ParquetCompression {
+    /// Files are not compressed.
+    Uncompressed,
+
+    /// Files are snappy compressed.
+    Snappy,
+
+    /// Files are zstd compressed.
+    Zstd,
+}
+
+/// The compression applied to the ingester query responses.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum QueryResponseCompression {
+    /// Responses are not compressed.
+    None,
+
+    /// Responses are gzip compressed.
+    Gzip,
+}
+
+/// How many replication peers must acknowledge a write.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReplicationPolicy {
+    /// Every peer must acknowledge the write.
+    All,
+
+    /// A majority of the ingesters, counting this one, must hold the write.
+    Quorum,
+
+    /// Writes are acknowledged without waiting for the peers.
+    Async,
 }
@@ -2,7 +2,13 @@
 use data_types::{IngesterMapping, ShardIndex};
 use serde::Deserialize;
 use snafu::{ResultExt, Snafu};
-use std::{collections::HashMap, fs, io, path::PathBuf, sync::Arc};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
 #[derive(Debug, Snafu)]
 #[allow(missing_docs)]
@@ -216,6 +222,96 @@ pub struct QuerierConfig {
     )]
     pub max_concurrent_queries: usize,
 
+    /// Limit the number of concurrent queries of each namespace.
+    ///
+    /// Queries in excess of the limit wait for the completion of another
+    /// query of their namespace, so that a single namespace can not occupy
+    /// all of the `--max-concurrent-queries` slots. If not specified, the
+    /// queries of a namespace are only limited by `--max-concurrent-queries`.
+    ///
+    /// Must be between 1 and 65535.
+    #[clap(
+        long = "max-concurrent-queries-per-namespace",
+        env = "INFLUXDB_IOX_MAX_CONCURRENT_QUERIES_PER_NAMESPACE",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..=u16::MAX as u64),
+        action
+    )]
+    pub max_concurrent_queries_per_namespace: Option<usize>,
+
+    /// Maximum number of queries of each namespace waiting for one of the
+    /// `--max-concurrent-queries-per-namespace` slots of their namespace.
+    ///
+    /// Further queries of the namespace are rejected immediately.
+    #[clap(
+        long = "max-queued-queries-per-namespace",
+        env = "INFLUXDB_IOX_MAX_QUEUED_QUERIES_PER_NAMESPACE",
+        default_value = "100",
+        action
+    )]
+    pub max_queued_queries_per_namespace: usize,
+
+    /// Size of the RAM pool used to cache query results in bytes.
+    ///
+    /// Results of repeated queries are served from the cache while the
+    /// parquet files the query reads are unchanged. Queries reading
+    /// unpersisted data are never cached. If not specified, query results are
+    /// not cached.
+    #[clap(
+        long = "query-result-cache-bytes",
+        env = "INFLUXDB_IOX_QUERY_RESULT_CACHE_BYTES",
+        action
+    )]
+    pub query_result_cache_bytes: Option<usize>,
+
+    /// Maximum duration a query result is cached for.
+    #[clap(
+        long = "query-result-cache-ttl",
+        env = "INFLUXDB_IOX_QUERY_RESULT_CACHE_TTL",
+        default_value = "1m",
+        value_parser = humantime::parse_duration,
+    )]
+    pub query_result_cache_ttl: Duration,
+
+    /// Memory budget in bytes for the execution of all queries.
+    ///
+    /// Queries that would exceed the budget fail with a "resources
+    /// exhausted" error instead of growing the memory usage of the querier
+    /// unbounded. If not specified, query memory usage is not limited.
+    #[clap(
+        long = "exec-mem-pool-bytes",
+        env = "INFLUXDB_IOX_EXEC_MEM_POOL_BYTES",
+        action
+    )]
+    pub exec_mem_pool_bytes: Option<usize>,
+
+    /// Memory budget in bytes for the execution of each query.
+    ///
+    /// Limits each query individually, so that a single expensive query can
+    /// not exhaust the budget of all other queries. If
+    /// `--exec-mem-pool-bytes` is also specified, each query reserves its
+    /// budget from it while executing, and further queries fail once it is
+    /// fully reserved.
+    #[clap(
+        long = "exec-per-query-mem-pool-bytes",
+        env = "INFLUXDB_IOX_EXEC_PER_QUERY_MEM_POOL_BYTES",
+        action
+    )]
+    pub exec_per_query_mem_pool_bytes: Option<usize>,
+
+    /// The local parquet file cache directory of a co-located ingester.
+    ///
+    /// Parquet files held in the cache (the `parquet_cache` subdirectory of
+    /// the WAL directory of an ingester run with
+    /// `--persist-file-cache-bytes`) are read from the local disk rather than
+    /// from object storage. If not specified, all files are read from object
+    /// storage.
+    #[clap(
+        long = "ingester-file-cache-dir",
+        env = "INFLUXDB_IOX_INGESTER_FILE_CACHE_DIR",
+        action
+    )]
+    pub ingester_file_cache_dir: Option<PathBuf>,
+
     /// Maximum bytes to scan for a table in a query (estimated).
     ///
     /// If IOx estimates that it will scan more than this many bytes
@@ -299,6 +395,45 @@ impl QuerierConfig {
         self.max_concurrent_queries
     }
 
+    /// Number of queries of each namespace allowed to run concurrently, if
+    /// limited.
+    pub fn max_concurrent_queries_per_namespace(&self) -> Option<usize> {
+        self.max_concurrent_queries_per_namespace
+    }
+
+    /// Number of queries of each namespace allowed to wait for a
+    /// concurrency slot of their namespace.
+    pub fn max_queued_queries_per_namespace(&self) -> usize {
+        self.max_queued_queries_per_namespace
+    }
+
+    /// Size of the RAM pool for query results in bytes, if results are
+    /// cached.
+    pub fn query_result_cache_bytes(&self) -> Option<usize> {
+        self.query_result_cache_bytes
+    }
+
+    /// Maximum duration a query result is cached for.
+    pub fn query_result_cache_ttl(&self) -> Duration {
+        self.query_result_cache_ttl
+    }
+
+    /// Memory budget in bytes for the execution of all queries, if any.
+    pub fn exec_mem_pool_bytes(&self) -> Option<usize> {
+        self.exec_mem_pool_bytes
+    }
+
+    /// Memory budget in bytes for the execution of each query, if any.
+    pub fn exec_per_query_mem_pool_bytes(&self) -> Option<usize> {
+        self.exec_per_query_mem_pool_bytes
+    }
+
+    /// The local parquet file cache directory of a co-located ingester, if
+    /// any.
+    pub fn ingester_file_cache_dir(&self) -> Option<&Path> {
+        self.ingester_file_cache_dir.as_deref()
+    }
+
     /// Query will error if it estimated that a single table will provide more
     /// than this many bytes.
     pub fn max_table_query_bytes(&self) -> usize {
@@ -442,6 +577,47 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_max_concurrent_queries_per_namespace() {
+        let actual = QuerierConfig::try_parse_from(["my_binary"]).unwrap();
+        assert_eq!(actual.max_concurrent_queries_per_namespace(), None);
+
+        let actual = QuerierConfig::try_parse_from([
+            "my_binary",
+            "--max-concurrent-queries-per-namespace",
+            "65535",
+        ])
+        .unwrap();
+        assert_eq!(actual.max_concurrent_queries_per_namespace(), Some(65535));
+
+        for value in ["0", "65536"] {
+            QuerierConfig::try_parse_from([
+                "my_binary",
+                "--max-concurrent-queries-per-namespace",
+                value,
+            ])
+            .unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_exec_mem_pool_bytes() {
+        let actual = QuerierConfig::try_parse_from(["my_binary"]).unwrap();
+        assert_eq!(actual.exec_mem_pool_bytes(), None);
+        assert_eq!(actual.exec_per_query_mem_pool_bytes(), None);
+
+        let actual = QuerierConfig::try_parse_from([
+            "my_binary",
+            "--exec-mem-pool-bytes",
+            "1000",
+            "--exec-per-query-mem-pool-bytes",
+            "100",
+        ])
+        .unwrap();
+        assert_eq!(actual.exec_mem_pool_bytes(), Some(1000));
+        assert_eq!(actual.exec_per_query_mem_pool_bytes(), Some(100));
+    }
+
     #[test]
     fn supply_json_value() {
         let actual = QuerierConfig::try_parse_from([
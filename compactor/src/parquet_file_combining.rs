@@ -663,6 +663,7 @@ impl CompactPlan {
                         max_sequence_number,
                         compaction_level: target_level,
                         sort_key: Some(sort_key.clone()),
+                        provenance: None,
                     };
 
                     debug!(
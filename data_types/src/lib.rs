@@ -798,6 +798,11 @@ impl PartitionKey {
     pub fn ptr_eq(&self, other: &Self) -> bool {
         Arc::ptr_eq(&self.0, &other.0)
     }
+
+    /// Returns the underlying string.
+    pub fn inner(&self) -> &str {
+        &self.0
+    }
 }
 
 impl Display for PartitionKey {
@@ -951,6 +956,25 @@ pub struct SkippedCompaction {
     pub limit_num_files_first_in_partition: i64,
 }
 
+/// Overrides of the triggers for persisting the data an ingester buffers for
+/// the tables of a namespace, or a single table within it.
+///
+/// A trigger left unset falls back to the namespace-wide policy (for a table
+/// policy) and then the ingester configuration.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, sqlx::FromRow)]
+pub struct PersistPolicy {
+    /// the namespace the policy applies to
+    pub namespace_id: NamespaceId,
+    /// the table the policy applies to, or `None` for all tables in the namespace
+    pub table_id: Option<TableId>,
+    /// the maximum age of the oldest data buffered for a partition, in milliseconds
+    pub max_age_ms: Option<i64>,
+    /// the maximum number of rows buffered for a partition
+    pub max_rows: Option<i64>,
+    /// the maximum approximate size of the data buffered for a partition, in bytes
+    pub max_bytes: Option<i64>,
+}
+
 /// Data object for a tombstone.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, sqlx::FromRow)]
 pub struct Tombstone {
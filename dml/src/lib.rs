@@ -38,6 +38,10 @@ pub struct DmlMeta {
 
     /// Bytes read from the wire
     bytes_read: Option<usize>,
+
+    /// Optional client-supplied key identifying all attempts to apply this
+    /// operation
+    idempotency_key: Option<String>,
 }
 
 impl DmlMeta {
@@ -53,6 +57,7 @@ impl DmlMeta {
             producer_ts: Some(producer_ts),
             span_ctx,
             bytes_read: Some(bytes_read),
+            idempotency_key: None,
         }
     }
 
@@ -63,6 +68,7 @@ impl DmlMeta {
             producer_ts: None,
             span_ctx,
             bytes_read: None,
+            idempotency_key: None,
         }
     }
 
@@ -92,6 +98,23 @@ impl DmlMeta {
         self.bytes_read
     }
 
+    /// Sets the tracing context of the operation
+    pub fn with_span_context(mut self, span_ctx: Option<SpanContext>) -> Self {
+        self.span_ctx = span_ctx;
+        self
+    }
+
+    /// Sets the client-supplied idempotency key of the operation
+    pub fn with_idempotency_key(mut self, key: impl Into<String>) -> Self {
+        self.idempotency_key = Some(key.into());
+        self
+    }
+
+    /// Gets the client-supplied idempotency key if any
+    pub fn idempotency_key(&self) -> Option<&str> {
+        self.idempotency_key.as_deref()
+    }
+
     /// Return the approximate memory size of the metadata, in bytes.
     ///
     /// This includes `Self`.
@@ -107,6 +130,11 @@ impl DmlMeta {
                 .as_ref()
                 .map(|_| std::mem::size_of::<SpanContext>())
                 .unwrap_or_default()
+            + self
+                .idempotency_key
+                .as_ref()
+                .map(|k| k.capacity())
+                .unwrap_or_default()
     }
 }
 
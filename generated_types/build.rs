@@ -54,9 +54,12 @@ fn generate_grpc_types(root: &Path) -> Result<()> {
         catalog_path.join("service.proto"),
         compactor_path.join("service.proto"),
         delete_path.join("service.proto"),
+        ingester_path.join("buffer_debug.proto"),
         ingester_path.join("parquet_metadata.proto"),
+        ingester_path.join("persist.proto"),
         ingester_path.join("query.proto"),
         ingester_path.join("write_info.proto"),
+        ingester_path.join("write_status.proto"),
         ingester_path.join("write.proto"),
         namespace_path.join("service.proto"),
         object_store_path.join("service.proto"),
@@ -94,6 +94,8 @@ pub async fn command(config: Config) -> Result<()> {
                     parquet_store.id(),
                     Arc::clone(parquet_store.object_store()),
                 )]),
+                mem_pool_size: None,
+                per_query_mem_pool_size: None,
             }));
             let time_provider = Arc::new(SystemProvider::new());
 
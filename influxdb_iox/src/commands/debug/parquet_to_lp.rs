@@ -1,7 +1,11 @@
 //! This module implements the `parquet_to_lp` CLI command
-use std::{io::BufWriter, path::PathBuf};
+use std::{path::PathBuf, sync::Arc};
 
-use observability_deps::tracing::info;
+use observability_deps::tracing::{debug, info};
+use parquet_to_line_protocol::{
+    ChunkedFileWriter, ConverterOptions, Filter, Format, LineProtocolFormat, OutputFormat,
+    Precision, Progress, Validation,
+};
 use snafu::{ResultExt, Snafu};
 
 #[derive(Debug, Snafu)]
@@ -16,58 +20,209 @@ pub enum Error {
     Conversion {
         source: parquet_to_line_protocol::Error,
     },
-    #[snafu(display("Cannot flush output: {}", message))]
-    Flush {
-        // flush error has the W writer in it, all we care about is the error
-        message: String,
-    },
+    #[snafu(display("{} only applies to line protocol output (--format lp)", option))]
+    LineProtocolOnly { option: &'static str },
 }
 
 /// Convert IOx Parquet files into InfluxDB line protocol format
 #[derive(Debug, clap::Parser)]
 pub struct Config {
-    /// Input file name
-    #[clap(value_parser)]
-    input: PathBuf,
+    /// Input file name(s). When several files of the same table are
+    /// given, their rows are merged, sorted and deduplicated.
+    #[clap(value_parser, required = true)]
+    input: Vec<PathBuf>,
 
     #[clap(long, short)]
     /// The path to which to write. If not specified writes to stdout
     output: Option<PathBuf>,
+
+    /// Only convert rows matching this predicate, e.g. `host='a'` or
+    /// `value>10`. May be repeated, in which case all predicates must
+    /// match.
+    #[clap(long = "filter", action = clap::ArgAction::Append, value_parser = parse_filter)]
+    filters: Vec<Filter>,
+
+    /// The output format: `lp` (line protocol), `csv` or `json` (one
+    /// JSON object per line)
+    #[clap(long, default_value = "lp", value_parser = parse_format)]
+    format: Format,
+
+    /// The precision of line protocol timestamps (`s`, `ms`, `us` or
+    /// `ns`, the default). Timestamps are rounded down to this
+    /// precision.
+    #[clap(long, value_parser = parse_precision)]
+    precision: Option<Precision>,
+
+    /// Start line protocol output with a `# precision=..` comment
+    #[clap(long)]
+    precision_hint: bool,
+
+    /// Check that each line of line protocol output parses back to
+    /// the row it was converted from: `off` (the default), `warn` (log
+    /// mismatched lines) or `strict` (fail the conversion)
+    #[clap(long, value_parser = parse_validation)]
+    validate: Option<Validation>,
+
+    /// Sort the output by time (ascending). Requires buffering all
+    /// the data in memory.
+    #[clap(long)]
+    sort_by_time: bool,
+
+    /// Split the output into several files (named after `--output`,
+    /// e.g. `out-00001.lp`) of at most this many bytes. Lines are
+    /// never split across files.
+    #[clap(long, requires = "output")]
+    max_file_bytes: Option<u64>,
+
+    /// Split the output into several files (named after `--output`,
+    /// e.g. `out-00001.lp`) of at most this many lines.
+    #[clap(long, requires = "output")]
+    max_file_lines: Option<u64>,
+}
+
+fn parse_filter(s: &str) -> Result<Filter, String> {
+    s.parse()
+        .map_err(|e: parquet_to_line_protocol::Error| e.to_string())
+}
+
+fn parse_format(s: &str) -> Result<Format, String> {
+    s.parse()
+        .map_err(|e: parquet_to_line_protocol::Error| e.to_string())
+}
+
+fn parse_precision(s: &str) -> Result<Precision, String> {
+    s.parse()
+        .map_err(|e: parquet_to_line_protocol::Error| e.to_string())
+}
+
+fn parse_validation(s: &str) -> Result<Validation, String> {
+    s.parse()
+        .map_err(|e: parquet_to_line_protocol::Error| e.to_string())
 }
 
 pub async fn command(config: Config) -> Result<(), Error> {
-    let Config { input, output } = config;
-    info!(?input, ?output, "Exporting parquet as line protocol");
-
-    if let Some(output) = output {
-        let path = &output;
-        let file = std::fs::File::create(path).context(FileSnafu {
-            operation: "open",
-            path,
-        })?;
-
-        let file = convert(input, file).await?;
-
-        file.sync_all().context(FileSnafu {
-            operation: "close",
-            path,
-        })?;
-    } else {
-        convert(input, std::io::stdout()).await?;
+    let Config {
+        input,
+        output,
+        filters,
+        format,
+        precision,
+        precision_hint,
+        validate,
+        sort_by_time,
+        max_file_bytes,
+        max_file_lines,
+    } = config;
+    info!(
+        ?input,
+        ?output,
+        ?filters,
+        ?format,
+        "Exporting parquet as line protocol"
+    );
+
+    let output_format: Arc<dyn OutputFormat> = match format {
+        Format::LineProtocol => Arc::new(
+            LineProtocolFormat::default()
+                .with_precision(precision.unwrap_or_default())
+                .with_precision_hint(precision_hint)
+                .with_validation(validate.unwrap_or_default()),
+        ),
+        format => {
+            // reject options that would silently be ignored
+            let option = [
+                (precision.is_some(), "--precision"),
+                (precision_hint, "--precision-hint"),
+                (validate.is_some(), "--validate"),
+            ]
+            .into_iter()
+            .find_map(|(set, option)| set.then_some(option));
+            if let Some(option) = option {
+                return LineProtocolOnlySnafu { option }.fail();
+            }
+
+            format.output_format()
+        }
+    };
+
+    let mut options = ConverterOptions::default()
+        .with_format(output_format)
+        .with_sort_by_time(sort_by_time)
+        .with_progress(Arc::new(|progress: &Progress| {
+            debug!(
+                rows_read = progress.rows_read,
+                bytes_written = progress.bytes_written,
+                batches_completed = progress.batches_completed,
+                "Conversion progress"
+            );
+        }));
+    if let Some(predicate) = parquet_to_line_protocol::conjunction(&filters) {
+        options = options.with_predicate(predicate);
+    }
+
+    let split_output = max_file_bytes.is_some() || max_file_lines.is_some();
+    match output {
+        Some(output) if split_output => {
+            let mut writer = ChunkedFileWriter::new(&output);
+            if let Some(max_file_bytes) = max_file_bytes {
+                writer = writer.with_max_bytes(max_file_bytes);
+            }
+            if let Some(max_file_lines) = max_file_lines {
+                writer = writer.with_max_lines(max_file_lines);
+            }
+
+            let writer = convert(input, &options, writer).await?;
+            let files = writer.finish().context(FileSnafu {
+                operation: "close",
+                path: &output,
+            })?;
+            info!(num_files = files.len(), "Wrote output files");
+        }
+        Some(output) => {
+            let path = &output;
+            let file = std::fs::File::create(path).context(FileSnafu {
+                operation: "open",
+                path,
+            })?;
+
+            let file = convert(input, &options, file).await?;
+
+            file.sync_all().context(FileSnafu {
+                operation: "close",
+                path,
+            })?;
+        }
+        None => {
+            convert(input, &options, std::io::stdout()).await?;
+        }
     }
 
     Ok(())
 }
 
 /// Does the actual conversion, returning the writer when done
-async fn convert<W: std::io::Write + Send>(input: PathBuf, writer: W) -> Result<W, Error> {
-    // use a buffered writer and ensure it is flushed
-    parquet_to_line_protocol::convert_file(input, BufWriter::new(writer))
-        .await
-        .context(ConversionSnafu)?
-        // flush the buffered writer
-        .into_inner()
-        .map_err(|e| Error::Flush {
-            message: e.to_string(),
-        })
+async fn convert<W: std::io::Write + Send>(
+    input: Vec<PathBuf>,
+    options: &ConverterOptions,
+    writer: W,
+) -> Result<W, Error> {
+    // the output is buffered and flushed by the converter
+    let (writer, stats) = match input.as_slice() {
+        [input] => parquet_to_line_protocol::convert_file_with_stats(input, options, writer).await,
+        inputs => {
+            parquet_to_line_protocol::convert_files_merged_with_stats(inputs, options, writer).await
+        }
+    }
+    .context(ConversionSnafu)?;
+
+    info!(
+        rows_converted = stats.rows_converted,
+        lines_emitted = stats.lines_emitted,
+        bytes_written = stats.bytes_written,
+        null_fields_skipped = stats.null_fields_skipped,
+        elapsed = ?stats.elapsed,
+        "Conversion complete"
+    );
+
+    Ok(writer)
 }
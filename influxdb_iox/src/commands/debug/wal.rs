@@ -0,0 +1,105 @@
+//! This module implements the `debug wal` CLI command
+use std::{io::Write, path::PathBuf};
+
+use comfy_table::{Cell, Table};
+use snafu::{ResultExt, Snafu};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Error reading WAL: {}", source))]
+    Wal { source: wal::Error },
+}
+
+/// Inspect the segment files of an ingester write-ahead log without
+/// modifying them
+#[derive(Debug, clap::Parser)]
+pub struct Config {
+    /// The WAL directory of the ingester
+    #[clap(value_parser)]
+    directory: PathBuf,
+
+    /// Print the decoded entries of the segment with this ID instead
+    /// of listing the segments
+    #[clap(long)]
+    dump: Option<u64>,
+
+    /// With --dump, skip the entries before the first one with a
+    /// sequence number at or above this one
+    #[clap(long, requires = "dump")]
+    from_sequence_number: Option<u64>,
+}
+
+pub async fn command(config: Config) -> Result<(), Error> {
+    let Config {
+        directory,
+        dump,
+        from_sequence_number,
+    } = config;
+
+    match dump {
+        Some(id) => {
+            let out = std::io::stdout();
+            wal::inspect::dump_segment(
+                &directory,
+                wal::SegmentId::new(id),
+                None,
+                from_sequence_number,
+                out,
+            )
+            .await
+            .context(WalSnafu)?;
+        }
+        None => {
+            let segments = wal::inspect::list_segments(&directory)
+                .await
+                .context(WalSnafu)?;
+
+            let mut summaries = Vec::with_capacity(segments.len());
+            for segment in &segments {
+                summaries.push(
+                    wal::inspect::summarize_segment(segment, None)
+                        .await
+                        .context(WalSnafu)?,
+                );
+            }
+
+            println!("{}", create_table(&summaries));
+        }
+    }
+
+    std::io::stdout().flush().ok();
+    Ok(())
+}
+
+/// Turn segment summaries into a table
+fn create_table(summaries: &[wal::inspect::SegmentSummary]) -> Table {
+    let mut table = Table::new();
+    table.load_preset("||--+-++|    ++++++");
+
+    let headers: Vec<_> = [
+        "id",
+        "size",
+        "entries",
+        "min_sequence_number",
+        "max_sequence_number",
+        "corrupt_entries",
+    ]
+    .into_iter()
+    .map(Cell::new)
+    .collect();
+    table.set_header(headers);
+
+    let optional = |v: Option<u64>| v.map(|v| v.to_string()).unwrap_or_default();
+    for summary in summaries {
+        table.add_row(vec![
+            Cell::new(summary.segment.id().to_string()),
+            Cell::new(summary.segment.size().to_string()),
+            Cell::new(summary.entry_count.to_string()),
+            Cell::new(optional(summary.min_sequence_number)),
+            Cell::new(optional(summary.max_sequence_number)),
+            Cell::new(summary.corrupt_entries.len().to_string()),
+        ]);
+    }
+
+    table
+}
@@ -28,7 +28,7 @@ use ioxd_router::create_router_server_type;
 use object_store::DynObjectStore;
 use observability_deps::tracing::*;
 use parquet_file::storage::{ParquetStorage, StorageId};
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 use thiserror::Error;
 use trace_exporters::TracingConfig;
 use trogging::cli::LoggingConfig;
@@ -449,6 +449,13 @@ impl Config {
             ram_pool_metadata_bytes: querier_ram_pool_metadata_bytes,
             ram_pool_data_bytes: querier_ram_pool_data_bytes,
             max_concurrent_queries: querier_max_concurrent_queries,
+            max_concurrent_queries_per_namespace: None,
+            max_queued_queries_per_namespace: 0, // will be ignored
+            query_result_cache_bytes: None,
+            query_result_cache_ttl: Duration::from_secs(60), // will be ignored
+            exec_mem_pool_bytes: None,
+            exec_per_query_mem_pool_bytes: None,
+            ingester_file_cache_dir: None,
             max_table_query_bytes: querier_max_table_query_bytes,
             ingester_circuit_breaker_threshold: u64::MAX, // never for all-in-one-mode
         };
@@ -541,6 +548,8 @@ pub async fn command(config: Config) -> Result<()> {
             parquet_store.id(),
             Arc::clone(parquet_store.object_store()),
         )]),
+        mem_pool_size: None,
+        per_query_mem_pool_size: None,
     }));
 
     info!("starting router");
@@ -107,6 +107,8 @@ pub async fn command(config: Config) -> Result<(), Error> {
             parquet_store.id(),
             Arc::clone(parquet_store.object_store()),
         )]),
+        mem_pool_size: None,
+        per_query_mem_pool_size: None,
     }));
     let time_provider = Arc::new(SystemProvider::new());
 
@@ -1,7 +1,7 @@
 //! Command line options for running an ingester for a router using the RPC write path to talk to.
 
 use super::main;
-use crate::process_info::setup_metric_registry;
+use crate::process_info::{setup_metric_registry, VERSION_STRING};
 use clap_blocks::{
     catalog_dsn::CatalogDsnConfig, ingester2::Ingester2Config, object_store::make_object_store,
     run_config::RunConfig,
@@ -92,6 +92,7 @@ pub async fn command(config: Config) -> Result<()> {
         &config.ingester_config,
         exec,
         ParquetStorage::new(object_store, StorageId::from("iox")),
+        &VERSION_STRING,
     )
     .await?;
 
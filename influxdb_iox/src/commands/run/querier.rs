@@ -7,7 +7,7 @@ use clap_blocks::{
     catalog_dsn::CatalogDsnConfig, object_store::make_object_store, querier::QuerierConfig,
     run_config::RunConfig,
 };
-use iox_query::exec::Executor;
+use iox_query::exec::{Executor, ExecutorConfig};
 use iox_time::{SystemProvider, TimeProvider};
 use ioxd_common::{
     server_type::{CommonServerState, CommonServerStateError},
@@ -17,7 +17,8 @@ use ioxd_querier::{create_querier_server_type, QuerierServerTypeArgs};
 use object_store::DynObjectStore;
 use object_store_metrics::ObjectStoreMetrics;
 use observability_deps::tracing::*;
-use std::sync::Arc;
+use querier::IngesterFileCacheStore;
+use std::{collections::HashMap, sync::Arc};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -89,6 +90,18 @@ pub async fn command(config: Config) -> Result<(), Error> {
         Arc::clone(&time_provider),
         &metric_registry,
     ));
+    // Read the parquet files cached by a co-located ingester from local disk.
+    let object_store: Arc<DynObjectStore> = match config.querier_config.ingester_file_cache_dir() {
+        Some(dir) => {
+            info!(dir=%dir.display(), "reading cached parquet files of ingester");
+            Arc::new(IngesterFileCacheStore::new(
+                object_store,
+                dir.to_path_buf(),
+                &metric_registry,
+            ))
+        }
+        None => object_store,
+    };
 
     let time_provider = Arc::new(SystemProvider::new());
 
@@ -99,7 +112,21 @@ pub async fn command(config: Config) -> Result<(), Error> {
     let ingester_addresses = config.querier_config.ingester_addresses()?;
     info!(?ingester_addresses, "using ingester addresses");
 
-    let exec = Arc::new(Executor::new(num_threads));
+    let mem_pool_size = config.querier_config.exec_mem_pool_bytes();
+    let per_query_mem_pool_size = config.querier_config.exec_per_query_mem_pool_bytes();
+    info!(
+        ?mem_pool_size,
+        ?per_query_mem_pool_size,
+        "using query memory budgets"
+    );
+
+    let exec = Arc::new(Executor::new_with_config(ExecutorConfig {
+        num_threads,
+        target_query_partitions: num_threads,
+        object_stores: HashMap::default(),
+        mem_pool_size,
+        per_query_mem_pool_size,
+    }));
 
     let server_type = create_querier_server_type(QuerierServerTypeArgs {
         common_state: &common_state,
@@ -178,6 +178,27 @@ async fn parquet_to_lp() {
     .await
 }
 
+#[test]
+fn parquet_to_lp_rejects_line_protocol_options() {
+    for args in [
+        &["--format", "csv", "--precision", "ms"][..],
+        &["--format", "json", "--precision-hint"][..],
+        &["--format", "csv", "--validate", "strict"][..],
+    ] {
+        Command::cargo_bin("influxdb_iox")
+            .unwrap()
+            .arg("debug")
+            .arg("parquet-to-lp")
+            .arg("file.parquet")
+            .args(args)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "only applies to line protocol output",
+            ));
+    }
+}
+
 /// Write, compact, then use the remote partition command
 #[tokio::test]
 async fn compact_and_get_remote_partition() {
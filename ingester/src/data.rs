@@ -464,6 +464,7 @@ impl Persister for IngesterData {
             max_sequence_number: batch_sequence_number_range.inclusive_max().unwrap(),
             compaction_level: CompactionLevel::Initial,
             sort_key: Some(data_sort_key),
+            provenance: None,
         };
 
         // Save the compacted data to a parquet file in object storage.
@@ -168,7 +168,7 @@ where
             }
         }
 
-        Ok(Response::new(proto::WriteResponse {}))
+        Ok(Response::new(proto::WriteResponse::default()))
     }
 }
 
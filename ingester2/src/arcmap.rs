@@ -163,6 +163,28 @@ where
         }
     }
 
+    /// Remove the value for `key`, returning it if it was initialised.
+    ///
+    /// Callers holding an [`Arc`] handle to the removed value retain it, and a
+    /// subsequent call to [`Self::get_or_insert_with()`] initialises a new
+    /// value for `key`.
+    pub(crate) fn remove<Q>(&self, key: &Q) -> Option<Arc<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.compute_hash(key);
+        match self
+            .map
+            .write()
+            .raw_entry_mut()
+            .from_hash(hash, Self::key_equal(key))
+        {
+            RawEntryMut::Occupied(v) => Some(v.remove()),
+            RawEntryMut::Vacant(_) => None,
+        }
+    }
+
     /// Return a state snapshot of all the values in this [`ArcMap`] in
     /// arbitrary order.
     ///
@@ -280,6 +302,23 @@ mod tests {
         assert_eq!(got, &["bananas", "platanos"]);
     }
 
+    #[test]
+    fn test_remove() {
+        let map = ArcMap::<String, usize>::default();
+
+        let key: &str = "bananas";
+        assert!(map.remove(key).is_none());
+
+        let got = map.get_or_insert_with(key, || Arc::new(42));
+        let removed = map.remove(key).expect("should remove initialised value");
+        assert!(Arc::ptr_eq(&got, &removed));
+        assert!(map.get(key).is_none());
+
+        // The key is initialised anew.
+        let got = map.get_or_insert_with(key, || Arc::new(13));
+        assert_eq!(*got, 13);
+    }
+
     #[test]
     #[should_panic = "inserting existing key"]
     fn test_insert_existing() {
@@ -0,0 +1,329 @@
+//! Accounting of the memory used by the data buffered in a [`BufferTree`].
+//!
+//! [`BufferTree`]: super::BufferTree
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use data_types::{NamespaceId, TableId};
+use metric::{Metric, U64Gauge};
+use parking_lot::Mutex;
+
+use super::partition::PartitionData;
+
+/// The limits on the memory used by the data buffered in the ingester.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct MemoryLimits {
+    /// The number of bytes above which the largest partitions are persisted
+    /// to release memory.
+    pub(crate) soft_limit_bytes: Option<usize>,
+
+    /// The number of bytes at which writes are rejected until persistence
+    /// releases memory.
+    pub(crate) hard_limit_bytes: Option<usize>,
+}
+
+/// The memory used by a single partition.
+#[derive(Debug)]
+pub(crate) struct PartitionUsage {
+    pub(crate) partition: Arc<Mutex<PartitionData>>,
+
+    /// The approximate size of the buffered data, excluding any data being
+    /// persisted, in bytes.
+    pub(crate) buffered_bytes: usize,
+}
+
+/// A point-in-time summary of the memory used by a set of partitions.
+#[derive(Debug, Default)]
+pub(crate) struct MemoryUsage {
+    /// The bytes buffered across all partitions, excluding data being
+    /// persisted.
+    buffered_bytes: usize,
+
+    /// The bytes held by in-flight persist operations, released as they
+    /// complete.
+    persisting_bytes: usize,
+
+    /// The total bytes held for each table, including data being persisted.
+    tables: HashMap<(NamespaceId, TableId), usize>,
+
+    partitions: Vec<PartitionUsage>,
+}
+
+impl MemoryUsage {
+    /// Measure the memory used by `partitions`.
+    ///
+    /// Each partition is locked in turn, so the result is not a consistent
+    /// snapshot of all partitions when writes are applied concurrently.
+    pub(crate) fn new(partitions: impl IntoIterator<Item = Arc<Mutex<PartitionData>>>) -> Self {
+        let mut usage = Self::default();
+
+        for p in partitions {
+            let (namespace_id, table_id, buffered_bytes, persisting_bytes) = {
+                let guard = p.lock();
+                (
+                    guard.namespace_id(),
+                    guard.table_id(),
+                    guard.buffered_bytes(),
+                    guard.persisting_bytes(),
+                )
+            };
+
+            usage.buffered_bytes += buffered_bytes;
+            usage.persisting_bytes += persisting_bytes;
+            *usage.tables.entry((namespace_id, table_id)).or_default() +=
+                buffered_bytes + persisting_bytes;
+            usage.partitions.push(PartitionUsage {
+                partition: p,
+                buffered_bytes,
+            });
+        }
+
+        usage
+    }
+
+    /// The total bytes held, including data being persisted.
+    pub(crate) fn total_bytes(&self) -> usize {
+        self.buffered_bytes + self.persisting_bytes
+    }
+
+    /// The bytes held by in-flight persist operations.
+    pub(crate) fn persisting_bytes(&self) -> usize {
+        self.persisting_bytes
+    }
+
+    /// The total bytes held for the specified table.
+    pub(crate) fn table_bytes(&self, namespace_id: NamespaceId, table_id: TableId) -> usize {
+        self.tables
+            .get(&(namespace_id, table_id))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The total bytes held for all tables in the specified namespace.
+    pub(crate) fn namespace_bytes(&self, namespace_id: NamespaceId) -> usize {
+        self.tables
+            .iter()
+            .filter(|((ns, _), _)| *ns == namespace_id)
+            .map(|(_, v)| *v)
+            .sum()
+    }
+
+    /// Consume `self`, returning the measured partitions ordered from the
+    /// most, to the least buffered bytes.
+    pub(crate) fn into_partitions_by_size(mut self) -> Vec<PartitionUsage> {
+        self.partitions
+            .sort_unstable_by(|a, b| b.buffered_bytes.cmp(&a.buffered_bytes));
+        self.partitions
+    }
+}
+
+/// Tracks the most recently observed [`MemoryUsage`] against the configured
+/// [`MemoryLimits`], and publishes it as metrics.
+#[derive(Debug)]
+pub(crate) struct MemoryTracker {
+    limits: MemoryLimits,
+
+    /// The total bytes held, as of the last call to [`Self::observe()`].
+    used_bytes: AtomicUsize,
+
+    buffered_bytes: U64Gauge,
+    persisting_bytes: U64Gauge,
+    table_bytes: Metric<U64Gauge>,
+
+    /// The tables with a non-zero value in `table_bytes`, reset to zero once
+    /// they no longer hold data.
+    reported_tables: Mutex<HashSet<(NamespaceId, TableId)>>,
+}
+
+impl MemoryTracker {
+    /// Initialise a new [`MemoryTracker`] enforcing `limits`, emitting metrics
+    /// to `metrics`.
+    pub(crate) fn new(limits: MemoryLimits, metrics: &metric::Registry) -> Self {
+        let bytes = metrics.register_metric::<U64Gauge>(
+            "ingester_buffer_memory_bytes",
+            "approximate size of the data held in the ingester buffer",
+        );
+        let buffered_bytes = bytes.recorder(&[("state", "buffered")]);
+        let persisting_bytes = bytes.recorder(&[("state", "persisting")]);
+
+        let table_bytes = metrics.register_metric::<U64Gauge>(
+            "ingester_table_buffer_memory_bytes",
+            "approximate size of the data held in the ingester buffer for each table",
+        );
+
+        Self {
+            limits,
+            used_bytes: AtomicUsize::new(0),
+            buffered_bytes,
+            persisting_bytes,
+            table_bytes,
+            reported_tables: Default::default(),
+        }
+    }
+
+    /// The configured [`MemoryLimits`].
+    pub(crate) fn limits(&self) -> MemoryLimits {
+        self.limits
+    }
+
+    /// Record `usage` as the current memory usage.
+    pub(crate) fn observe(&self, usage: &MemoryUsage) {
+        self.used_bytes
+            .store(usage.total_bytes(), Ordering::Relaxed);
+        self.buffered_bytes.set(usage.buffered_bytes as u64);
+        self.persisting_bytes.set(usage.persisting_bytes as u64);
+
+        let mut reported = self.reported_tables.lock();
+        for (namespace_id, table_id) in reported.drain() {
+            if !usage.tables.contains_key(&(namespace_id, table_id)) {
+                self.table_recorder(namespace_id, table_id).set(0);
+            }
+        }
+        for (&(namespace_id, table_id), &bytes) in &usage.tables {
+            self.table_recorder(namespace_id, table_id)
+                .set(bytes as u64);
+            reported.insert((namespace_id, table_id));
+        }
+    }
+
+    /// The total bytes held, as of the last observed [`MemoryUsage`].
+    pub(crate) fn used_bytes(&self) -> usize {
+        self.used_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if the last observed [`MemoryUsage`] reached the hard
+    /// limit.
+    pub(crate) fn is_over_hard_limit(&self) -> bool {
+        self.limits
+            .hard_limit_bytes
+            .map_or(false, |v| self.used_bytes() >= v)
+    }
+
+    fn table_recorder(&self, namespace_id: NamespaceId, table_id: TableId) -> U64Gauge {
+        self.table_bytes.recorder([
+            ("namespace_id", Cow::Owned(namespace_id.to_string())),
+            ("table_id", Cow::Owned(table_id.to_string())),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use data_types::{PartitionId, PartitionKey, SequenceNumber};
+    use metric::{Attributes, Metric};
+    use mutable_batch_lp::test_helpers::lp_to_mutable_batch;
+
+    use super::*;
+    use crate::{
+        buffer_tree::{namespace::NamespaceName, partition::SortKeyState, table::TableName},
+        deferred_load::DeferredLoad,
+    };
+
+    fn partition(id: i64, table_id: TableId, lp: &str) -> Arc<Mutex<PartitionData>> {
+        let mut p = PartitionData::new(
+            PartitionId::new(id),
+            PartitionKey::from("p"),
+            NamespaceId::new(1),
+            Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                NamespaceName::from("platanos")
+            })),
+            table_id,
+            Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                TableName::from("bananas")
+            })),
+            SortKeyState::Provided(None),
+        );
+        if !lp.is_empty() {
+            p.buffer_write(lp_to_mutable_batch(lp).1, SequenceNumber::new(1))
+                .expect("write should succeed");
+        }
+        Arc::new(Mutex::new(p))
+    }
+
+    fn table_bytes(metrics: &metric::Registry, table_id: TableId) -> u64 {
+        metrics
+            .get_instrument::<Metric<U64Gauge>>("ingester_table_buffer_memory_bytes")
+            .expect("metric not registered")
+            .get_observer(&Attributes::from([
+                ("namespace_id", Cow::Owned("1".to_string())),
+                ("table_id", Cow::Owned(table_id.to_string())),
+            ]))
+            .expect("no table metric")
+            .fetch()
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let small = partition(1, TableId::new(1), "bananas v=1i 1");
+        let large = partition(
+            2,
+            TableId::new(1),
+            &(0..100)
+                .map(|v| format!("bananas v={v}i {v}"))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+        let other = partition(3, TableId::new(2), "bananas v=1i 1");
+
+        // Persisting data is accounted, but no longer buffered.
+        let persisting = other.lock().mark_persisting().unwrap();
+        assert_eq!(other.lock().buffered_bytes(), 0);
+
+        let usage = MemoryUsage::new([Arc::clone(&small), Arc::clone(&large), other]);
+        assert!(usage.persisting_bytes() > 0);
+        assert_eq!(
+            usage.total_bytes(),
+            usage.table_bytes(NamespaceId::new(1), TableId::new(1))
+                + usage.table_bytes(NamespaceId::new(1), TableId::new(2))
+        );
+        assert_eq!(
+            usage.namespace_bytes(NamespaceId::new(1)),
+            usage.total_bytes()
+        );
+        assert_eq!(usage.namespace_bytes(NamespaceId::new(2)), 0);
+
+        let partitions = usage.into_partitions_by_size();
+        assert!(Arc::ptr_eq(&partitions[0].partition, &large));
+        assert!(Arc::ptr_eq(&partitions[1].partition, &small));
+        assert_eq!(partitions[2].buffered_bytes, 0);
+
+        drop(persisting);
+    }
+
+    #[test]
+    fn test_memory_tracker() {
+        let metrics = metric::Registry::default();
+        let p = partition(1, TableId::new(1), "bananas v=1i 1");
+        let bytes = p.lock().buffered_bytes();
+
+        let tracker = MemoryTracker::new(
+            MemoryLimits {
+                soft_limit_bytes: None,
+                hard_limit_bytes: Some(bytes),
+            },
+            &metrics,
+        );
+        assert!(!tracker.is_over_hard_limit());
+
+        tracker.observe(&MemoryUsage::new([Arc::clone(&p)]));
+        assert_eq!(tracker.used_bytes(), bytes);
+        assert!(tracker.is_over_hard_limit());
+        assert_eq!(table_bytes(&metrics, TableId::new(1)), bytes as u64);
+
+        // Tables no longer holding data are reset.
+        tracker.observe(&MemoryUsage::new([partition(2, TableId::new(2), "")]));
+        assert_eq!(tracker.used_bytes(), 0);
+        assert!(!tracker.is_over_hard_limit());
+        assert_eq!(table_bytes(&metrics, TableId::new(1)), 0);
+        assert_eq!(table_bytes(&metrics, TableId::new(2)), 0);
+    }
+}
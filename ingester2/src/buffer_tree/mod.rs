@@ -1,4 +1,6 @@
+pub(crate) mod memory;
 pub(crate) mod namespace;
+pub(crate) mod namespace_watch;
 pub(crate) mod partition;
 pub(crate) mod table;
 
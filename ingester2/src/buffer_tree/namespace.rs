@@ -6,9 +6,10 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use data_types::{NamespaceId, TableId};
-use dml::DmlOperation;
+use dml::{DmlOperation, DmlWrite};
 use metric::U64Counter;
-use observability_deps::tracing::warn;
+use observability_deps::tracing::debug;
+use predicate::Predicate;
 use trace::span::Span;
 
 use super::{
@@ -117,12 +118,37 @@ impl NamespaceData {
         &self.namespace_name
     }
 
+    /// Return the [`TableData`] for `table_id`, initialising it if it is not
+    /// yet buffered.
+    fn table_or_insert(&self, table_id: TableId) -> Arc<TableData> {
+        self.tables.get_or_insert_with(&table_id, || {
+            self.table_count.inc(1);
+            Arc::new(TableData::new(
+                table_id,
+                self.table_name_resolver.for_table(table_id),
+                self.namespace_id,
+                Arc::clone(&self.namespace_name),
+                Arc::clone(&self.partition_provider),
+            ))
+        })
+    }
+
+    /// Initialise the partitions `write` buffers data in, without buffering
+    /// it.
+    pub(super) async fn resolve_partitions(&self, write: &DmlWrite) {
+        for (table_id, _) in write.tables() {
+            self.table_or_insert(*table_id)
+                .resolve_partition(write.partition_key().clone())
+                .await;
+        }
+    }
+
     /// Obtain a snapshot of the tables within this [`NamespaceData`].
     ///
     /// NOTE: the snapshot is an atomic / point-in-time snapshot of the set of
     /// [`NamespaceData`], but the tables (and partitions) within them may
     /// change as they continue to buffer DML operations.
-    pub(super) fn tables(&self) -> Vec<Arc<TableData>> {
+    pub(crate) fn tables(&self) -> Vec<Arc<TableData>> {
         self.tables.values()
     }
 }
@@ -146,16 +172,7 @@ impl DmlSink for NamespaceData {
                 for (table_id, b) in write.into_tables() {
                     // Grab a reference to the table data, or insert a new
                     // TableData for it.
-                    let table_data = self.tables.get_or_insert_with(&table_id, || {
-                        self.table_count.inc(1);
-                        Arc::new(TableData::new(
-                            table_id,
-                            self.table_name_resolver.for_table(table_id),
-                            self.namespace_id,
-                            Arc::clone(&self.namespace_name),
-                            Arc::clone(&self.partition_provider),
-                        ))
-                    });
+                    let table_data = self.table_or_insert(table_id);
 
                     table_data
                         .buffer_table_write(sequence_number, b, partition_key.clone())
@@ -163,14 +180,26 @@ impl DmlSink for NamespaceData {
                 }
             }
             DmlOperation::Delete(delete) => {
-                // Deprecated delete support:
-                // https://github.com/influxdata/influxdb_iox/issues/5825
-                warn!(
+                // Ops are applied in sequence number order, so all data
+                // buffered so far was written before this delete and the
+                // predicate applies to all of it.
+                for table_data in self.tables() {
+                    if let Some(name) = delete.table_name() {
+                        // NOTE: this MAY involve querying the catalog.
+                        if table_data.table_name().get().await != *name {
+                            continue;
+                        }
+                    }
+
+                    table_data.apply_delete(delete.predicate());
+                }
+
+                debug!(
                     namespace_name=%self.namespace_name,
                     namespace_id=%self.namespace_id,
                     table_name=?delete.table_name(),
-                    sequence_number=?delete.meta().sequence(),
-                    "discarding unsupported delete op"
+                    %sequence_number,
+                    "applied delete"
                 );
             }
         }
@@ -188,6 +217,7 @@ impl QueryExec for NamespaceData {
         namespace_id: NamespaceId,
         table_id: TableId,
         columns: Vec<String>,
+        predicate: Option<Predicate>,
         span: Option<Span>,
     ) -> Result<Self::Response, QueryError> {
         assert_eq!(
@@ -204,7 +234,7 @@ impl QueryExec for NamespaceData {
         // a tracing delegate to emit a child span.
         Ok(QueryResponse::new(
             QueryExecTracing::new(inner, "table")
-                .query_exec(namespace_id, table_id, columns, span)
+                .query_exec(namespace_id, table_id, columns, predicate, span)
                 .await?,
         ))
     }
@@ -214,7 +244,8 @@ impl QueryExec for NamespaceData {
 mod tests {
     use std::{sync::Arc, time::Duration};
 
-    use data_types::{PartitionId, PartitionKey, ShardIndex};
+    use arrow_util::assert_batches_eq;
+    use data_types::{PartitionId, PartitionKey, SequenceNumber, ShardIndex};
     use metric::{Attributes, Metric};
 
     use super::*;
@@ -225,7 +256,7 @@ mod tests {
             table::{name_resolver::mock::MockTableNameProvider, TableName},
         },
         deferred_load::{self, DeferredLoad},
-        test_util::make_write_op,
+        test_util::{make_delete_op, make_write_op},
     };
 
     const SHARD_INDEX: ShardIndex = ShardIndex::new(24);
@@ -303,4 +334,100 @@ mod tests {
         assert_eq!(&**name, NAMESPACE_NAME);
         assert_eq!(ns.namespace_name().to_string(), NAMESPACE_NAME);
     }
+
+    #[tokio::test]
+    async fn test_namespace_apply_delete() {
+        let metrics = Arc::new(metric::Registry::default());
+
+        let partition_provider = Arc::new(MockPartitionProvider::default().with_partition(
+            PartitionData::new(
+                PartitionId::new(0),
+                PartitionKey::from("banana-split"),
+                NAMESPACE_ID,
+                Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                    NamespaceName::from(NAMESPACE_NAME)
+                })),
+                TABLE_ID,
+                Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                    TableName::from(TABLE_NAME)
+                })),
+                SortKeyState::Provided(None),
+            ),
+        ));
+
+        let ns = NamespaceData::new(
+            NAMESPACE_ID,
+            DeferredLoad::new(Duration::from_millis(1), async { NAMESPACE_NAME.into() }),
+            Arc::new(MockTableNameProvider::new(TABLE_NAME)),
+            partition_provider,
+            &metrics,
+        );
+
+        ns.apply(DmlOperation::Write(make_write_op(
+            &PartitionKey::from("banana-split"),
+            NAMESPACE_ID,
+            TABLE_NAME,
+            TABLE_ID,
+            0,
+            "bananas,city=Medford temp=55 1\nbananas,city=Medford temp=56 5",
+        )))
+        .await
+        .expect("buffer op should succeed");
+
+        // Delete all data between timestamps 1 and 2 in the table.
+        ns.apply(DmlOperation::Delete(make_delete_op(
+            NAMESPACE_ID,
+            Some(TABLE_NAME),
+            1,
+        )))
+        .await
+        .expect("delete op should succeed");
+
+        // Writes after the delete are unaffected by it.
+        ns.apply(DmlOperation::Write(make_write_op(
+            &PartitionKey::from("banana-split"),
+            NAMESPACE_ID,
+            TABLE_NAME,
+            TABLE_ID,
+            2,
+            "bananas,city=Medford temp=57 1",
+        )))
+        .await
+        .expect("buffer op should succeed");
+
+        // And deletes for other tables do not affect this one.
+        ns.apply(DmlOperation::Delete(make_delete_op(
+            NAMESPACE_ID,
+            Some("platanos"),
+            3,
+        )))
+        .await
+        .expect("delete op should succeed");
+
+        let partition = ns.table(TABLE_ID).unwrap().partitions().pop().unwrap();
+        let mut partition = partition.lock();
+
+        let data = partition
+            .get_query_data()
+            .expect("partition should have data");
+        assert_batches_eq!(
+            [
+                "+---------+------+--------------------------------+",
+                "| city    | temp | time                           |",
+                "+---------+------+--------------------------------+",
+                "| Medford | 56.0 | 1970-01-01T00:00:00.000000005Z |",
+                "| Medford | 57.0 | 1970-01-01T00:00:00.000000001Z |",
+                "+---------+------+--------------------------------+",
+            ],
+            &*data
+                .record_batches()
+                .iter()
+                .map(|v| (**v).clone())
+                .collect::<Vec<_>>()
+        );
+
+        // The persisted data records the last write applied to it.
+        let persisting = partition.mark_persisting().unwrap();
+        assert_eq!(persisting.max_sequence_number(), SequenceNumber::new(2));
+    }
 }
@@ -5,7 +5,7 @@ use data_types::NamespaceId;
 use iox_catalog::interface::Catalog;
 
 use super::NamespaceName;
-use crate::deferred_load::DeferredLoad;
+use crate::deferred_load::{DeferredLoad, DeferredLoadMetrics};
 
 /// An abstract provider of a [`DeferredLoad`] configured to fetch the
 /// [`NamespaceName`] of the specified [`NamespaceId`].
@@ -18,6 +18,7 @@ pub(crate) struct NamespaceNameResolver {
     max_smear: Duration,
     catalog: Arc<dyn Catalog>,
     backoff_config: BackoffConfig,
+    metrics: DeferredLoadMetrics,
 }
 
 impl NamespaceNameResolver {
@@ -25,11 +26,13 @@ impl NamespaceNameResolver {
         max_smear: Duration,
         catalog: Arc<dyn Catalog>,
         backoff_config: BackoffConfig,
+        metrics: &metric::Registry,
     ) -> Self {
         Self {
             max_smear,
             catalog,
             backoff_config,
+            metrics: DeferredLoadMetrics::new(metrics, "namespace_name"),
         }
     }
 
@@ -61,9 +64,10 @@ impl NamespaceNameResolver {
 
 impl NamespaceNameProvider for NamespaceNameResolver {
     fn for_namespace(&self, id: NamespaceId) -> DeferredLoad<NamespaceName> {
-        DeferredLoad::new(
+        DeferredLoad::new_with_metrics(
             self.max_smear,
             Self::fetch(id, Arc::clone(&self.catalog), self.backoff_config.clone()),
+            &self.metrics,
         )
     }
 }
@@ -126,6 +130,7 @@ mod tests {
             Duration::from_secs(10),
             Arc::clone(&catalog),
             backoff_config.clone(),
+            &metrics,
         ));
 
         let got = fetcher
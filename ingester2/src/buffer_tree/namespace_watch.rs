@@ -0,0 +1,175 @@
+//! Dropping the buffered data of namespaces deleted from the catalog.
+
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use iox_catalog::interface::Catalog;
+use observability_deps::tracing::*;
+use tokio::time::MissedTickBehavior;
+
+use super::BufferTree;
+
+/// How often the catalog is checked for deleted namespaces.
+pub(crate) const NAMESPACE_WATCH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Periodically check the catalog for the namespaces buffered in `buffer`,
+/// removing those that no longer exist from the [`BufferTree`].
+///
+/// The buffered data of a removed namespace is dropped without being
+/// persisted, and subsequent writes to it are rejected.
+pub(crate) async fn watch_deleted_namespaces(
+    buffer: Arc<BufferTree>,
+    catalog: Arc<dyn Catalog>,
+    interval: Duration,
+) {
+    let mut interval = tokio::time::interval(interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = remove_deleted_namespaces(&buffer, &*catalog).await {
+            warn!(error=%e, "failed to list namespaces for deletion check");
+        }
+    }
+}
+
+/// Remove the namespaces buffered in `buffer` that do not exist in `catalog`,
+/// returning the number removed.
+async fn remove_deleted_namespaces(
+    buffer: &BufferTree,
+    catalog: &dyn Catalog,
+) -> Result<usize, iox_catalog::interface::Error> {
+    // Snapshot the buffered namespaces BEFORE listing those in the catalog.
+    //
+    // A namespace is created in the catalog before it is written to, so every
+    // namespace in this snapshot existed before the listing started -
+    // snapshotting afterwards would race with the creation of a new
+    // namespace, and mistake it for a deleted one.
+    let buffered = buffer
+        .namespaces()
+        .into_iter()
+        .map(|v| v.namespace_id())
+        .collect::<Vec<_>>();
+    if buffered.is_empty() {
+        return Ok(0);
+    }
+
+    let existing = catalog
+        .repositories()
+        .await
+        .namespaces()
+        .list()
+        .await?
+        .into_iter()
+        .map(|v| v.id)
+        .collect::<HashSet<_>>();
+
+    let mut n = 0;
+    for namespace_id in buffered {
+        if existing.contains(&namespace_id) {
+            continue;
+        }
+
+        info!(%namespace_id, "namespace deleted, dropping buffered data");
+        if buffer.remove_namespace(namespace_id).is_some() {
+            n += 1;
+        }
+    }
+
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use data_types::{NamespaceId, PartitionId, PartitionKey, ShardIndex, TableId};
+    use dml::DmlOperation;
+    use iox_catalog::mem::MemCatalog;
+
+    use super::*;
+    use crate::{
+        buffer_tree::{
+            namespace::{name_resolver::mock::MockNamespaceNameProvider, NamespaceName},
+            partition::{resolver::mock::MockPartitionProvider, PartitionData, SortKeyState},
+            table::{name_resolver::mock::MockTableNameProvider, TableName},
+        },
+        deferred_load::DeferredLoad,
+        dml_sink::DmlSink,
+        test_util::{make_write_op, populate_catalog},
+    };
+
+    const TABLE_NAME: &str = "bananas";
+    const NAMESPACE_NAME: &str = "platanos";
+    const DELETED_NAMESPACE_ID: NamespaceId = NamespaceId::new(4242);
+
+    fn partition(namespace_id: NamespaceId, table_id: TableId, key: &str) -> PartitionData {
+        PartitionData::new(
+            PartitionId::new(namespace_id.get()),
+            PartitionKey::from(key),
+            namespace_id,
+            Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                NamespaceName::from(NAMESPACE_NAME)
+            })),
+            table_id,
+            Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                TableName::from(TABLE_NAME)
+            })),
+            SortKeyState::Provided(None),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_remove_deleted_namespaces() {
+        let catalog: Arc<dyn Catalog> =
+            Arc::new(MemCatalog::new(Arc::new(metric::Registry::default())));
+        let (_shard_id, namespace_id, table_id) =
+            populate_catalog(&*catalog, ShardIndex::new(1), NAMESPACE_NAME, TABLE_NAME).await;
+
+        let buffer = BufferTree::new(
+            Arc::new(MockNamespaceNameProvider::default()),
+            Arc::new(MockTableNameProvider::new(TABLE_NAME)),
+            Arc::new(
+                MockPartitionProvider::default()
+                    .with_partition(partition(namespace_id, table_id, "p1"))
+                    .with_partition(partition(DELETED_NAMESPACE_ID, table_id, "p2")),
+            ),
+            Default::default(),
+        );
+
+        for (id, key, seq) in [(namespace_id, "p1", 1), (DELETED_NAMESPACE_ID, "p2", 2)] {
+            buffer
+                .apply(DmlOperation::Write(make_write_op(
+                    &PartitionKey::from(key),
+                    id,
+                    TABLE_NAME,
+                    table_id,
+                    seq,
+                    r#"bananas,city=Madrid temp=55 22"#,
+                )))
+                .await
+                .expect("buffer op should succeed");
+        }
+
+        // Only the namespace missing from the catalog is removed.
+        let n = remove_deleted_namespaces(&buffer, &*catalog)
+            .await
+            .expect("listing namespaces should succeed");
+        assert_eq!(n, 1);
+        assert!(buffer.namespace(namespace_id).is_some());
+        assert!(buffer.namespace(DELETED_NAMESPACE_ID).is_none());
+        assert!(buffer.is_namespace_deleted(DELETED_NAMESPACE_ID));
+
+        // Subsequent ops for the deleted namespace are discarded.
+        buffer
+            .apply(DmlOperation::Write(make_write_op(
+                &PartitionKey::from("p2"),
+                DELETED_NAMESPACE_ID,
+                TABLE_NAME,
+                table_id,
+                3,
+                r#"bananas,city=Madrid temp=55 23"#,
+            )))
+            .await
+            .expect("discarding op should succeed");
+        assert!(buffer.namespace(DELETED_NAMESPACE_ID).is_none());
+    }
+}
@@ -1,14 +1,21 @@
 //! Partition level data buffer structures.
 
-use std::{collections::VecDeque, sync::Arc};
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use data_types::{NamespaceId, PartitionId, PartitionKey, SequenceNumber, TableId};
+use data_types::{
+    DeletePredicate, NamespaceId, PartitionId, PartitionKey, SequenceNumber, TableId,
+    TimestampMinMax,
+};
 use mutable_batch::MutableBatch;
 use observability_deps::tracing::*;
 use schema::sort::SortKey;
 
 use self::{
-    buffer::{traits::Queryable, BufferState, DataBuffer, Persisting},
+    buffer::{merge_timestamp_ranges, traits::Queryable, BufferState, DataBuffer, Persisting},
     persisting::{BatchIdent, PersistingData},
 };
 use super::{namespace::NamespaceName, table::TableName};
@@ -73,6 +80,9 @@ pub(crate) struct PartitionData {
     /// A [`DataBuffer`] for incoming writes.
     buffer: DataBuffer,
 
+    /// The time at which the first write in `buffer` was buffered, if any.
+    buffered_since: Option<Instant>,
+
     /// The currently persisting [`DataBuffer`] instances, if any.
     ///
     /// This queue is ordered from newest at the head, to oldest at the tail -
@@ -85,6 +95,11 @@ pub(crate) struct PartitionData {
     /// The number of persist operations started over the lifetime of this
     /// [`PartitionData`].
     started_persistence_count: BatchIdent,
+
+    /// The [`BatchIdent`] of the most recently completed persist operation.
+    ///
+    /// Persist operations complete in the order they are started.
+    completed_persistence_count: BatchIdent,
 }
 
 impl PartitionData {
@@ -108,8 +123,10 @@ impl PartitionData {
             table_id,
             table_name,
             buffer: DataBuffer::default(),
+            buffered_since: None,
             persisting: VecDeque::with_capacity(1),
             started_persistence_count: BatchIdent::default(),
+            completed_persistence_count: BatchIdent::default(),
         }
     }
 
@@ -121,6 +138,7 @@ impl PartitionData {
     ) -> Result<(), mutable_batch::Error> {
         // Buffer the write.
         self.buffer.buffer_write(mb, sequence_number)?;
+        self.buffered_since.get_or_insert_with(Instant::now);
 
         trace!(
             namespace_id = %self.namespace_id,
@@ -134,6 +152,26 @@ impl PartitionData {
         Ok(())
     }
 
+    /// Remove the rows matched by `predicate` from all data buffered in, or
+    /// currently persisting from, this partition.
+    ///
+    /// Writes subsequently buffered are unaffected by `predicate`.
+    pub(super) fn apply_delete(&mut self, predicate: &DeletePredicate) {
+        self.buffer.apply_delete(predicate);
+        for (_, p) in self.persisting.iter_mut() {
+            p.apply_delete(predicate);
+        }
+
+        trace!(
+            namespace_id = %self.namespace_id,
+            table_id = %self.table_id,
+            table_name = %self.table_name,
+            partition_id = %self.partition_id,
+            partition_key = %self.partition_key,
+            "applied delete"
+        );
+    }
+
     /// Return all data for this partition, ordered by the calls to
     /// [`PartitionData::buffer_write()`].
     pub(crate) fn get_query_data(&mut self) -> Option<QueryAdaptor> {
@@ -191,8 +229,35 @@ impl PartitionData {
     /// serialised (unless it can be known in advance no sort key update is
     /// necessary for a given persistence).
     pub(crate) fn mark_persisting(&mut self) -> Option<PersistingData> {
+        // The buffer is empty after this call, whether or not it held data.
+        self.buffered_since = None;
         let fsm = std::mem::take(&mut self.buffer).into_persisting()?;
 
+        Some(self.start_persisting(fsm))
+    }
+
+    /// Snapshot and mark the buffered rows with a timestamp before `cutoff`
+    /// (in nanoseconds since the epoch) as persisting, retaining the more
+    /// recent rows in the buffer.
+    ///
+    /// This method returns [`None`] if no rows are buffered before `cutoff`.
+    /// The persisting data is subject to the same invariants as
+    /// [`Self::mark_persisting()`].
+    ///
+    /// The writes split between the persisting and retained rows are not
+    /// persisted until the retained rows are, so the WAL segments containing
+    /// them are retained until then. The age of the retained rows (see
+    /// [`Self::buffered_age()`]) restarts - they were retained as recent,
+    /// and are persisted by a subsequent trigger.
+    pub(crate) fn mark_persisting_before(&mut self, cutoff: i64) -> Option<PersistingData> {
+        let fsm = self.buffer.split_persisting_before(cutoff)?;
+        self.buffered_since = (self.buffer.rows() > 0).then(Instant::now);
+
+        Some(self.start_persisting(fsm))
+    }
+
+    /// Mark the data in `fsm` as persisting, returning it for persistence.
+    fn start_persisting(&mut self, fsm: BufferState<Persisting>) -> PersistingData {
         // From this point on, all code MUST be infallible or the buffered data
         // contained within persisting may be dropped.
 
@@ -216,11 +281,15 @@ impl PartitionData {
         let data = PersistingData::new(
             QueryAdaptor::new(self.partition_id, fsm.get_query_data()),
             batch_ident,
+            fsm.min_sequence_number()
+                .expect("persisting data contains no writes"),
+            fsm.max_sequence_number()
+                .expect("persisting data contains no writes"),
         );
 
         self.persisting.push_front((batch_ident, fsm));
 
-        Some(data)
+        data
     }
 
     /// Mark this partition as having completed persistence of the specified
@@ -249,6 +318,7 @@ impl PartitionData {
             batch.batch_ident(),
             "out-of-order persist notification received"
         );
+        self.completed_persistence_count = old_ident;
 
         debug!(
             namespace_id = %self.namespace_id,
@@ -261,6 +331,102 @@ impl PartitionData {
         );
     }
 
+    /// Returns true if `batch` is the oldest batch of data being persisted
+    /// from this partition, which must be marked as persisted before any
+    /// other.
+    pub(crate) fn is_next_persisted(&self, batch: &PersistingData) -> bool {
+        self.persisting.back().map(|(ident, _)| *ident) == Some(batch.batch_ident())
+    }
+
+    /// Return the number of rows buffered in this partition, excluding
+    /// persisting data.
+    pub(crate) fn buffered_rows(&self) -> usize {
+        self.buffer.rows()
+    }
+
+    /// Return the approximate size of the data buffered in this partition, in
+    /// bytes, excluding persisting data.
+    pub(crate) fn buffered_bytes(&self) -> usize {
+        self.buffer.size()
+    }
+
+    /// Return how long ago the oldest write buffered in this partition
+    /// (excluding persisting data) was buffered, or [`None`] if no data is
+    /// buffered.
+    pub(crate) fn buffered_age(&self) -> Option<Duration> {
+        self.buffered_since.map(|v| v.elapsed())
+    }
+
+    /// Return the approximate size of the data held in this partition that is
+    /// in the process of being persisted, in bytes.
+    ///
+    /// This memory is released once the persist operation completes.
+    pub(crate) fn persisting_bytes(&self) -> usize {
+        self.persisting.iter().map(|(_, v)| v.size()).sum()
+    }
+
+    /// Return the number of batches of data in the process of being persisted.
+    pub(crate) fn persisting_batches(&self) -> usize {
+        self.persisting.len()
+    }
+
+    /// Return the range of the timestamps of the data buffered in, or
+    /// persisting from, this partition, or [`None`] if it holds no data.
+    pub(crate) fn timestamp_range(&self) -> Option<TimestampMinMax> {
+        merge_timestamp_ranges(
+            self.buffer.timestamp_range().into_iter().chain(
+                self.persisting
+                    .iter()
+                    .filter_map(|(_, p)| p.timestamp_range()),
+            ),
+        )
+    }
+
+    /// Return the largest [`SequenceNumber`] of the writes buffered in this
+    /// partition, excluding persisting data.
+    pub(crate) fn buffered_max_sequence_number(&self) -> Option<SequenceNumber> {
+        self.buffer.max_sequence_number()
+    }
+
+    /// Return the largest [`SequenceNumber`] of the data in the process of
+    /// being persisted from this partition.
+    pub(crate) fn persisting_max_sequence_number(&self) -> Option<SequenceNumber> {
+        self.persisting
+            .iter()
+            .filter_map(|(_, p)| p.max_sequence_number())
+            .max()
+    }
+
+    /// Return the smallest [`SequenceNumber`] of the writes buffered in, or
+    /// persisting from, this partition, or [`None`] if it holds no
+    /// unpersisted data.
+    pub(crate) fn min_unpersisted_sequence_number(&self) -> Option<SequenceNumber> {
+        self.buffer
+            .min_sequence_number()
+            .into_iter()
+            .chain(
+                self.persisting
+                    .iter()
+                    .filter_map(|(_, p)| p.min_sequence_number()),
+            )
+            .min()
+    }
+
+    /// Return the [`BatchIdent`] of the most recently started persist
+    /// operation.
+    pub(crate) fn started_persistence_count(&self) -> BatchIdent {
+        self.started_persistence_count
+    }
+
+    /// Return the [`BatchIdent`] of the most recently completed persist
+    /// operation.
+    ///
+    /// All persist operations up to and including a given [`BatchIdent`] are
+    /// complete once this value reaches it.
+    pub(crate) fn completed_persistence_count(&self) -> BatchIdent {
+        self.completed_persistence_count
+    }
+
     pub(crate) fn partition_id(&self) -> PartitionId {
         self.partition_id
     }
@@ -535,6 +701,148 @@ mod tests {
         }
     }
 
+    // Ensure the timestamp range & sequence numbers span the buffered and
+    // persisting data.
+    #[tokio::test]
+    async fn test_timestamp_range_sequence_numbers() {
+        let mut p = PartitionData::new(
+            PARTITION_ID,
+            PARTITION_KEY.clone(),
+            NamespaceId::new(3),
+            Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                NAMESPACE_NAME.clone()
+            })),
+            TableId::new(4),
+            Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                TABLE_NAME.clone()
+            })),
+            SortKeyState::Provided(None),
+        );
+
+        assert!(p.timestamp_range().is_none());
+        assert_eq!(p.buffered_max_sequence_number(), None);
+        assert_eq!(p.persisting_max_sequence_number(), None);
+        assert_eq!(p.min_unpersisted_sequence_number(), None);
+
+        let mb = lp_to_mutable_batch(r#"bananas,city=London people=2 10"#).1;
+        p.buffer_write(mb, SequenceNumber::new(1))
+            .expect("write should succeed");
+        let mb = lp_to_mutable_batch(r#"bananas,city=Paris people=3 30"#).1;
+        p.buffer_write(mb, SequenceNumber::new(2))
+            .expect("write should succeed");
+        assert_eq!(
+            p.min_unpersisted_sequence_number(),
+            Some(SequenceNumber::new(1))
+        );
+
+        let range = p.timestamp_range().expect("must have data");
+        assert_eq!((range.min, range.max), (10, 30));
+        assert_eq!(
+            p.buffered_max_sequence_number(),
+            Some(SequenceNumber::new(2))
+        );
+
+        // Begin persisting the partition, and buffer another write.
+        let persisting_data = p.mark_persisting().expect("must contain existing data");
+        let mb = lp_to_mutable_batch(r#"bananas,city=Madrid people=4 5"#).1;
+        p.buffer_write(mb, SequenceNumber::new(3))
+            .expect("write should succeed");
+
+        assert_eq!(p.persisting_batches(), 1);
+        let range = p.timestamp_range().expect("must have data");
+        assert_eq!((range.min, range.max), (5, 30));
+        assert_eq!(
+            p.buffered_max_sequence_number(),
+            Some(SequenceNumber::new(3))
+        );
+        assert_eq!(
+            p.persisting_max_sequence_number(),
+            Some(SequenceNumber::new(2))
+        );
+        assert_eq!(
+            p.min_unpersisted_sequence_number(),
+            Some(SequenceNumber::new(1))
+        );
+
+        // Once persisted, only the buffered data remains.
+        p.mark_persisted(persisting_data);
+
+        assert_eq!(p.persisting_batches(), 0);
+        let range = p.timestamp_range().expect("must have data");
+        assert_eq!((range.min, range.max), (5, 5));
+        assert_eq!(p.persisting_max_sequence_number(), None);
+        assert_eq!(
+            p.min_unpersisted_sequence_number(),
+            Some(SequenceNumber::new(3))
+        );
+    }
+
+    // Ensure marking only the older rows as persisting retains the recent rows
+    // (and the sequence numbers of the split writes) in the buffer.
+    #[tokio::test]
+    async fn test_mark_persisting_before() {
+        let mut p = PartitionData::new(
+            PARTITION_ID,
+            PARTITION_KEY.clone(),
+            NamespaceId::new(3),
+            Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                NAMESPACE_NAME.clone()
+            })),
+            TableId::new(4),
+            Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                TABLE_NAME.clone()
+            })),
+            SortKeyState::Provided(None),
+        );
+
+        let mb =
+            lp_to_mutable_batch("bananas,city=London people=2 10\nbananas,city=Paris people=3 30")
+                .1;
+        p.buffer_write(mb, SequenceNumber::new(1))
+            .expect("write should succeed");
+
+        // No rows are older than the cutoff.
+        assert!(p.mark_persisting_before(10).is_none());
+        assert_eq!(p.buffered_rows(), 2);
+
+        let persisting_data = p.mark_persisting_before(20).expect("must split rows");
+        assert_eq!(persisting_data.record_batches()[0].num_rows(), 1);
+        assert_eq!(
+            persisting_data.min_sequence_number(),
+            SequenceNumber::new(1)
+        );
+        assert_eq!(p.buffered_rows(), 1);
+        assert!(p.buffered_age().is_some());
+
+        // Both the persisting and retained rows are queryable.
+        let range = p.timestamp_range().expect("must have data");
+        assert_eq!((range.min, range.max), (10, 30));
+        assert_eq!(
+            p.get_query_data()
+                .expect("must have data")
+                .record_batches()
+                .iter()
+                .map(|b| b.num_rows())
+                .sum::<usize>(),
+            2
+        );
+
+        // The split write remains unpersisted until the retained rows are
+        // persisted.
+        p.mark_persisted(persisting_data);
+        assert_eq!(
+            p.min_unpersisted_sequence_number(),
+            Some(SequenceNumber::new(1))
+        );
+        let range = p.timestamp_range().expect("must have data");
+        assert_eq!((range.min, range.max), (30, 30));
+
+        let persisting_data = p.mark_persisting().expect("must have data");
+        p.mark_persisted(persisting_data);
+        assert_eq!(p.min_unpersisted_sequence_number(), None);
+        assert!(p.buffered_age().is_none());
+    }
+
     // Ensure the ordering of snapshots & persisting data is preserved such that
     // updates resolve correctly, and batch identifiers are correctly allocated
     // and validated in mark_persisted() calls
@@ -1,10 +1,11 @@
 use std::sync::Arc;
 
 use arrow::record_batch::RecordBatch;
-use data_types::SequenceNumber;
+use data_types::{DeletePredicate, SequenceNumber, TimestampMinMax};
 use mutable_batch::MutableBatch;
 
 mod always_some;
+mod delete;
 mod mutable_buffer;
 mod state_machine;
 pub(crate) mod traits;
@@ -55,6 +56,54 @@ impl DataBuffer {
         })
     }
 
+    /// Remove the rows matched by `predicate` from all data buffered so far.
+    pub(crate) fn apply_delete(&mut self, predicate: &DeletePredicate) {
+        self.0.mutate(|fsm| match fsm {
+            FsmState::Buffering(mut b) => {
+                b.apply_delete(predicate);
+                (FsmState::Buffering(b), ())
+            }
+        })
+    }
+
+    /// Return the number of rows buffered.
+    pub(crate) fn rows(&self) -> usize {
+        match &*self.0 {
+            FsmState::Buffering(b) => b.rows(),
+        }
+    }
+
+    /// Return the approximate size of the buffered data, in bytes.
+    pub(crate) fn size(&self) -> usize {
+        match &*self.0 {
+            FsmState::Buffering(b) => b.size(),
+        }
+    }
+
+    /// Return the range of the buffered timestamps, or [`None`] if no data is
+    /// buffered.
+    pub(crate) fn timestamp_range(&self) -> Option<TimestampMinMax> {
+        match &*self.0 {
+            FsmState::Buffering(b) => b.timestamp_range(),
+        }
+    }
+
+    /// Return the smallest [`SequenceNumber`] of the buffered writes, or
+    /// [`None`] if no writes have been buffered.
+    pub(crate) fn min_sequence_number(&self) -> Option<SequenceNumber> {
+        match &*self.0 {
+            FsmState::Buffering(b) => b.min_sequence_number(),
+        }
+    }
+
+    /// Return the largest [`SequenceNumber`] of the buffered writes, or
+    /// [`None`] if no writes have been buffered.
+    pub(crate) fn max_sequence_number(&self) -> Option<SequenceNumber> {
+        match &*self.0 {
+            FsmState::Buffering(b) => b.max_sequence_number(),
+        }
+    }
+
     /// Return all data for this buffer, ordered by the [`SequenceNumber`] from
     /// which it was buffered with.
     pub(crate) fn get_query_data(&mut self) -> Vec<Arc<RecordBatch>> {
@@ -68,6 +117,25 @@ impl DataBuffer {
         })
     }
 
+    /// Remove the buffered rows with a timestamp before `cutoff`, returning
+    /// them in a [`Persisting`] FSM, or [`None`] if there are no such rows.
+    ///
+    /// The remaining rows stay buffered, and continue to accept writes.
+    pub(crate) fn split_persisting_before(
+        &mut self,
+        cutoff: i64,
+    ) -> Option<BufferState<Persisting>> {
+        self.0.mutate(|fsm| match fsm {
+            FsmState::Buffering(b) => {
+                let (snapshot, b) = b.split_before(cutoff);
+                (
+                    FsmState::Buffering(b),
+                    snapshot.map(|v| v.into_persisting()),
+                )
+            }
+        })
+    }
+
     // Deconstruct the [`DataBuffer`] into the underlying FSM in a
     // [`Persisting`] state, if the buffer contains any data.
     pub(crate) fn into_persisting(self) -> Option<BufferState<Persisting>> {
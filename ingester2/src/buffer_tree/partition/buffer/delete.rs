@@ -0,0 +1,221 @@
+//! Materialisation of [`DeletePredicate`] against buffered [`RecordBatch`].
+
+use std::sync::Arc;
+
+use arrow::{
+    array::{Array, BooleanArray, TimestampNanosecondArray},
+    compute::{
+        and_kleene, filter_record_batch,
+        kernels::comparison::{
+            eq_dyn_bool_scalar, eq_dyn_scalar, eq_dyn_utf8_scalar, neq_dyn_bool_scalar,
+            neq_dyn_scalar, neq_dyn_utf8_scalar,
+        },
+    },
+    error::ArrowError,
+    record_batch::RecordBatch,
+};
+use data_types::{DeleteExpr, DeletePredicate, Op, Scalar};
+use schema::TIME_COLUMN_NAME;
+
+/// Remove the rows in `batch` matched by `predicate`.
+///
+/// Returns `batch` itself when no rows match, and [`None`] when all rows
+/// match.
+///
+/// A row is matched when its timestamp is within the predicate time range and
+/// it evaluates to true for all the predicate expressions - a row with a NULL
+/// (or absent) value for an expression column is never matched, following the
+/// SQL semantics the querier applies to tombstones.
+///
+/// # Panics
+///
+/// Panics if `batch` has no valid IOx `time` column.
+pub(super) fn apply_delete(
+    batch: &Arc<RecordBatch>,
+    predicate: &DeletePredicate,
+) -> Option<Arc<RecordBatch>> {
+    let time = batch
+        .column(
+            batch
+                .schema()
+                .index_of(TIME_COLUMN_NAME)
+                .expect("buffered data has no time column"),
+        )
+        .as_any()
+        .downcast_ref::<TimestampNanosecondArray>()
+        .expect("time column has unexpected type");
+
+    let mut matched = time
+        .iter()
+        .map(|t| t.map(|t| predicate.range.contains(t)))
+        .collect::<BooleanArray>();
+
+    for expr in &predicate.exprs {
+        let column = match batch.schema().index_of(expr.column()) {
+            Ok(idx) => Arc::clone(batch.column(idx)),
+            // All values of an absent column are NULL.
+            Err(_) => return Some(Arc::clone(batch)),
+        };
+
+        matched = match eval_expr(column.as_ref(), expr) {
+            Ok(v) => and_kleene(&matched, &v).expect("mask lengths always match"),
+            // The column type cannot be compared with the scalar, so no row
+            // can equal it.
+            Err(_) => return Some(Arc::clone(batch)),
+        };
+    }
+
+    // Retain every row not definitely matched by the predicate.
+    let retain = matched
+        .iter()
+        .map(|v| Some(v != Some(true)))
+        .collect::<BooleanArray>();
+
+    match retain.true_count() {
+        0 => None,
+        n if n == batch.num_rows() => Some(Arc::clone(batch)),
+        _ => Some(Arc::new(
+            filter_record_batch(batch, &retain).expect("failed to filter buffered data"),
+        )),
+    }
+}
+
+/// Evaluate `expr` for each row of `column`.
+fn eval_expr(column: &dyn Array, expr: &DeleteExpr) -> Result<BooleanArray, ArrowError> {
+    match (expr.op(), expr.scalar()) {
+        (Op::Eq, Scalar::Bool(v)) => eq_dyn_bool_scalar(column, *v),
+        (Op::Ne, Scalar::Bool(v)) => neq_dyn_bool_scalar(column, *v),
+        (Op::Eq, Scalar::I64(v)) => eq_dyn_scalar(column, *v),
+        (Op::Ne, Scalar::I64(v)) => neq_dyn_scalar(column, *v),
+        (Op::Eq, Scalar::F64(v)) => eq_dyn_scalar(column, v.into_inner()),
+        (Op::Ne, Scalar::F64(v)) => neq_dyn_scalar(column, v.into_inner()),
+        (Op::Eq, Scalar::String(v)) => eq_dyn_utf8_scalar(column, v),
+        (Op::Ne, Scalar::String(v)) => neq_dyn_utf8_scalar(column, v),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_util::assert_batches_eq;
+    use data_types::TimestampRange;
+    use mutable_batch_lp::test_helpers::lp_to_mutable_batch;
+    use schema::Projection;
+
+    use super::*;
+
+    fn batch(lp: &str) -> Arc<RecordBatch> {
+        Arc::new(lp_to_mutable_batch(lp).1.to_arrow(Projection::All).unwrap())
+    }
+
+    fn predicate(start: i64, end: i64, exprs: Vec<DeleteExpr>) -> DeletePredicate {
+        DeletePredicate {
+            range: TimestampRange::new(start, end),
+            exprs,
+        }
+    }
+
+    #[test]
+    fn test_apply_delete() {
+        let data = batch(
+            "\
+            bananas,region=asia v=1i 1\n\
+            bananas,region=europe v=2i 2\n\
+            bananas,region=asia v=3i 3\n\
+            bananas v=4i 2\n\
+            ",
+        );
+
+        // Time range only.
+        let got = apply_delete(&data, &predicate(2, 3, vec![])).unwrap();
+        assert_batches_eq!(
+            [
+                "+--------+--------------------------------+---+",
+                "| region | time                           | v |",
+                "+--------+--------------------------------+---+",
+                "| asia   | 1970-01-01T00:00:00.000000001Z | 1 |",
+                "| asia   | 1970-01-01T00:00:00.000000003Z | 3 |",
+                "+--------+--------------------------------+---+",
+            ],
+            &[(*got).clone()]
+        );
+
+        // A tag equality, which never matches the NULL region.
+        let got = apply_delete(
+            &data,
+            &predicate(
+                0,
+                10,
+                vec![DeleteExpr::new(
+                    "region".to_string(),
+                    Op::Eq,
+                    Scalar::String("asia".to_string()),
+                )],
+            ),
+        )
+        .unwrap();
+        assert_batches_eq!(
+            [
+                "+--------+--------------------------------+---+",
+                "| region | time                           | v |",
+                "+--------+--------------------------------+---+",
+                "| europe | 1970-01-01T00:00:00.000000002Z | 2 |",
+                "|        | 1970-01-01T00:00:00.000000002Z | 4 |",
+                "+--------+--------------------------------+---+",
+            ],
+            &[(*got).clone()]
+        );
+
+        // A field inequality, combined with a time range.
+        let got = apply_delete(
+            &data,
+            &predicate(
+                0,
+                3,
+                vec![DeleteExpr::new("v".to_string(), Op::Ne, Scalar::I64(2))],
+            ),
+        )
+        .unwrap();
+        assert_batches_eq!(
+            [
+                "+--------+--------------------------------+---+",
+                "| region | time                           | v |",
+                "+--------+--------------------------------+---+",
+                "| europe | 1970-01-01T00:00:00.000000002Z | 2 |",
+                "| asia   | 1970-01-01T00:00:00.000000003Z | 3 |",
+                "+--------+--------------------------------+---+",
+            ],
+            &[(*got).clone()]
+        );
+    }
+
+    #[test]
+    fn test_apply_delete_no_match() {
+        let data = batch("bananas,region=asia v=1i 1");
+
+        // Outside the time range.
+        let got = apply_delete(&data, &predicate(5, 10, vec![])).unwrap();
+        assert!(Arc::ptr_eq(&got, &data));
+
+        // Absent column.
+        let got = apply_delete(
+            &data,
+            &predicate(
+                0,
+                10,
+                vec![DeleteExpr::new(
+                    "platanos".to_string(),
+                    Op::Eq,
+                    Scalar::Bool(true),
+                )],
+            ),
+        )
+        .unwrap();
+        assert!(Arc::ptr_eq(&got, &data));
+    }
+
+    #[test]
+    fn test_apply_delete_all_rows() {
+        let data = batch("bananas,region=asia v=1i 1\nbananas,region=asia v=2i 2");
+        assert!(apply_delete(&data, &predicate(0, 10, vec![])).is_none());
+    }
+}
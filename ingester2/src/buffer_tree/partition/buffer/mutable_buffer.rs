@@ -1,8 +1,9 @@
 use std::sync::Arc;
 
 use arrow::record_batch::RecordBatch;
+use data_types::{Statistics, TimestampMinMax};
 use mutable_batch::MutableBatch;
-use schema::Projection;
+use schema::{Projection, TIME_COLUMN_NAME};
 
 /// A [`Buffer`] is an internal mutable buffer wrapper over a [`MutableBatch`]
 /// for the [`BufferState`] FSM.
@@ -51,6 +52,26 @@ impl Buffer {
         self.buffer.is_none()
     }
 
+    /// Returns the number of rows buffered.
+    pub(super) fn rows(&self) -> usize {
+        self.buffer.as_ref().map(|v| v.rows()).unwrap_or_default()
+    }
+
+    /// Returns the approximate size of the buffered data, in bytes.
+    pub(super) fn size(&self) -> usize {
+        self.buffer.as_ref().map(|v| v.size()).unwrap_or_default()
+    }
+
+    /// Returns the range of the buffered timestamps, or [`None`] if the buffer
+    /// is empty.
+    pub(super) fn timestamp_range(&self) -> Option<TimestampMinMax> {
+        let column = self.buffer.as_ref()?.column(TIME_COLUMN_NAME).ok()?;
+        match column.stats() {
+            Statistics::I64(v) => Some(TimestampMinMax::new(v.min?, v.max?)),
+            _ => None,
+        }
+    }
+
     pub(super) fn buffer(&self) -> Option<&MutableBatch> {
         self.buffer.as_ref()
     }
@@ -2,7 +2,8 @@
 use std::sync::Arc;
 
 use arrow::record_batch::RecordBatch;
-use data_types::SequenceNumber;
+use data_types::{SequenceNumber, TimestampMinMax};
+use iox_query::util::compute_timenanosecond_min_max_for_one_record_batch;
 use mutable_batch::MutableBatch;
 
 mod buffering;
@@ -14,6 +15,30 @@ pub(crate) use persisting::*;
 
 use super::traits::{Queryable, Writeable};
 
+/// Return the smallest range containing all of `ranges`, or [`None`] if
+/// `ranges` is empty.
+pub(crate) fn merge_timestamp_ranges<I>(ranges: I) -> Option<TimestampMinMax>
+where
+    I: IntoIterator<Item = TimestampMinMax>,
+{
+    ranges
+        .into_iter()
+        .reduce(|a, b| TimestampMinMax::new(a.min.min(b.min), a.max.max(b.max)))
+}
+
+/// Return the range of the timestamps in `batches`, or [`None`] if they
+/// contain no rows.
+fn batches_timestamp_range<'a, I>(batches: I) -> Option<TimestampMinMax>
+where
+    I: IntoIterator<Item = &'a Arc<RecordBatch>>,
+{
+    merge_timestamp_ranges(batches.into_iter().filter(|b| b.num_rows() > 0).map(|b| {
+        let (min, max) = compute_timenanosecond_min_max_for_one_record_batch(b)
+            .expect("buffered data has a valid time column");
+        TimestampMinMax::new(min, max)
+    }))
+}
+
 /// A result type for fallible transitions.
 ///
 /// The type system ensures the state machine is always returned to the caller,
@@ -29,8 +54,16 @@ pub(crate) enum Transition<A, B> {
 
 impl<A, B> Transition<A, B> {
     /// A helper function to construct [`Self::Ok`] variants.
-    pub(super) fn ok(v: A) -> Self {
-        Self::Ok(BufferState { state: v })
+    pub(super) fn ok(
+        v: A,
+        min_sequence_number: Option<SequenceNumber>,
+        max_sequence_number: Option<SequenceNumber>,
+    ) -> Self {
+        Self::Ok(BufferState {
+            state: v,
+            min_sequence_number,
+            max_sequence_number,
+        })
     }
 
     /// A helper function to construct [`Self::Unchanged`] variants.
@@ -67,6 +100,14 @@ impl<A, B> Transition<A, B> {
 #[derive(Debug)]
 pub(crate) struct BufferState<T> {
     state: T,
+
+    /// The smallest [`SequenceNumber`] of the writes buffered in this FSM, if
+    /// any.
+    min_sequence_number: Option<SequenceNumber>,
+
+    /// The largest [`SequenceNumber`] of the writes buffered in this FSM, if
+    /// any.
+    max_sequence_number: Option<SequenceNumber>,
 }
 
 impl BufferState<Buffering> {
@@ -74,10 +115,26 @@ impl BufferState<Buffering> {
     pub(super) fn new() -> Self {
         Self {
             state: Buffering::default(),
+            min_sequence_number: None,
+            max_sequence_number: None,
         }
     }
 }
 
+impl<T> BufferState<T> {
+    /// Return the smallest [`SequenceNumber`] of the writes buffered in this
+    /// FSM, or [`None`] if no writes have been buffered.
+    pub(crate) fn min_sequence_number(&self) -> Option<SequenceNumber> {
+        self.min_sequence_number
+    }
+
+    /// Return the largest [`SequenceNumber`] of the writes buffered in this
+    /// FSM, or [`None`] if no writes have been buffered.
+    pub(crate) fn max_sequence_number(&self) -> Option<SequenceNumber> {
+        self.max_sequence_number
+    }
+}
+
 /// A [`BufferState`] in a mutable state can accept writes and record their
 /// [`SequenceNumber`].
 impl<T> BufferState<T>
@@ -93,9 +150,11 @@ where
     pub(crate) fn write(
         &mut self,
         batch: MutableBatch,
-        _n: SequenceNumber,
+        n: SequenceNumber,
     ) -> Result<(), mutable_batch::Error> {
         self.state.write(batch)?;
+        self.min_sequence_number = Some(self.min_sequence_number.map_or(n, |v| v.min(n)));
+        self.max_sequence_number = self.max_sequence_number.max(Some(n));
         Ok(())
     }
 }
@@ -2,12 +2,20 @@
 
 use std::sync::Arc;
 
-use arrow::record_batch::RecordBatch;
+use arrow::{
+    array::{Array, BooleanArray, TimestampNanosecondArray},
+    compute::{filter_record_batch, not},
+    record_batch::RecordBatch,
+};
+use data_types::{DeletePredicate, TimestampMinMax};
 use mutable_batch::MutableBatch;
-use schema::Projection;
+use schema::{Projection, TIME_COLUMN_NAME};
 
-use super::{snapshot::Snapshot, BufferState, Transition};
+use super::{
+    batches_timestamp_range, merge_timestamp_ranges, snapshot::Snapshot, BufferState, Transition,
+};
 use crate::buffer_tree::partition::buffer::{
+    delete::apply_delete,
     mutable_buffer::Buffer,
     traits::{Queryable, Writeable},
 };
@@ -20,6 +28,16 @@ pub(crate) struct Buffering {
     /// This buffer MAY be empty when no writes have occured since transitioning
     /// to this state.
     buffer: Buffer,
+
+    /// Snapshots of previous buffer contents that had a delete applied to
+    /// them, ordered before the data in `buffer`.
+    ///
+    /// Writes buffered after a delete MUST NOT be affected by it, so the
+    /// contents of `buffer` are snapshot when a delete is applied, leaving it
+    /// empty for subsequent writes.
+    ///
+    /// Any snapshot in this array always contains at least one row.
+    snapshots: Vec<Arc<RecordBatch>>,
 }
 
 /// Implement on-demand querying of the buffered contents without storing the
@@ -43,10 +61,7 @@ impl Queryable for Buffering {
             )
         });
 
-        match data {
-            Some(v) => vec![v],
-            None => vec![],
-        }
+        self.snapshots.iter().cloned().chain(data).collect()
     }
 }
 
@@ -61,27 +76,254 @@ impl BufferState<Buffering> {
     ///
     /// This returns [`Transition::Unchanged`] if this buffer contains no data.
     pub(crate) fn snapshot(self) -> Transition<Snapshot, Buffering> {
-        if self.state.buffer.is_empty() {
+        if self.state.buffer.is_empty() && self.state.snapshots.is_empty() {
             // It is a logical error to snapshot an empty buffer.
             return Transition::unchanged(self);
         }
 
-        // Generate a snapshot from the buffer.
-        let snap = self
-            .state
-            .buffer
-            .snapshot()
-            .expect("snapshot of non-empty buffer should succeed");
+        // Generate a snapshot from the buffer, ordered after any snapshots
+        // generated when applying deletes.
+        let Buffering {
+            buffer,
+            mut snapshots,
+        } = self.state;
+        snapshots.extend(buffer.snapshot());
 
         // And transition to the WithSnapshot state.
-        Transition::ok(Snapshot::new(vec![snap]))
+        Transition::ok(
+            Snapshot::new(snapshots),
+            self.min_sequence_number,
+            self.max_sequence_number,
+        )
+    }
+
+    /// Returns the number of rows buffered.
+    pub(crate) fn rows(&self) -> usize {
+        self.state.buffer.rows()
+            + self
+                .state
+                .snapshots
+                .iter()
+                .map(|v| v.num_rows())
+                .sum::<usize>()
+    }
+
+    /// Returns the approximate size of the buffered data, in bytes.
+    pub(crate) fn size(&self) -> usize {
+        self.state.buffer.size()
+            + self
+                .state
+                .snapshots
+                .iter()
+                .flat_map(|v| v.columns())
+                .map(|v| v.get_array_memory_size())
+                .sum::<usize>()
+    }
+
+    /// Returns the range of the buffered timestamps, or [`None`] if no data is
+    /// buffered.
+    pub(crate) fn timestamp_range(&self) -> Option<TimestampMinMax> {
+        merge_timestamp_ranges(
+            batches_timestamp_range(&self.state.snapshots)
+                .into_iter()
+                .chain(self.state.buffer.timestamp_range()),
+        )
+    }
+
+    /// Split the buffered rows with a timestamp before `cutoff` into a
+    /// snapshot, retaining the rows at or after `cutoff` in the returned
+    /// buffer.
+    ///
+    /// The rows of a single write may be split between the two, so both
+    /// retain the [`SequenceNumber`] range of this buffer (unless no rows are
+    /// retained, when an empty buffer is returned). No snapshot is returned,
+    /// and all rows are retained, if no rows are before `cutoff`.
+    ///
+    /// [`SequenceNumber`]: data_types::SequenceNumber
+    pub(crate) fn split_before(mut self, cutoff: i64) -> (Option<BufferState<Snapshot>>, Self) {
+        match self.timestamp_range() {
+            Some(v) if v.min < cutoff => {}
+            _ => return (None, self),
+        }
+
+        let state = &mut self.state;
+        state
+            .snapshots
+            .extend(std::mem::take(&mut state.buffer).snapshot());
+
+        let (cold, recent): (Vec<_>, Vec<_>) = state
+            .snapshots
+            .iter()
+            .map(|b| split_batch(b, cutoff))
+            .unzip();
+        let cold = cold.into_iter().flatten().collect::<Vec<_>>();
+        let recent = recent.into_iter().flatten().collect::<Vec<_>>();
+
+        let retained = if recent.is_empty() {
+            BufferState::new()
+        } else {
+            BufferState {
+                state: Buffering {
+                    buffer: Buffer::default(),
+                    snapshots: recent,
+                },
+                min_sequence_number: self.min_sequence_number,
+                max_sequence_number: self.max_sequence_number,
+            }
+        };
+
+        if cold.is_empty() {
+            return (None, retained);
+        }
+
+        let snapshot = BufferState {
+            state: Snapshot::new(cold),
+            min_sequence_number: self.min_sequence_number,
+            max_sequence_number: self.max_sequence_number,
+        };
+
+        (Some(snapshot), retained)
+    }
+
+    /// Remove the rows matched by `predicate` from all data buffered so far.
+    ///
+    /// Writes subsequently buffered are unaffected by `predicate`.
+    pub(crate) fn apply_delete(&mut self, predicate: &DeletePredicate) {
+        let state = &mut self.state;
+        state
+            .snapshots
+            .extend(std::mem::take(&mut state.buffer).snapshot());
+        state.snapshots = state
+            .snapshots
+            .iter()
+            .filter_map(|b| apply_delete(b, predicate))
+            .collect();
     }
 }
 
+/// Split `batch` into the rows with a timestamp before `cutoff`, and the rows
+/// at or after it, returning [`None`] for an empty part.
+fn split_batch(
+    batch: &Arc<RecordBatch>,
+    cutoff: i64,
+) -> (Option<Arc<RecordBatch>>, Option<Arc<RecordBatch>>) {
+    let time = batch
+        .column(
+            batch
+                .schema()
+                .index_of(TIME_COLUMN_NAME)
+                .expect("buffered data has no time column"),
+        )
+        .as_any()
+        .downcast_ref::<TimestampNanosecondArray>()
+        .expect("time column has unexpected type");
+
+    let cold = time
+        .iter()
+        .map(|t| Some(t.map_or(false, |t| t < cutoff)))
+        .collect::<BooleanArray>();
+
+    let filter = |mask: &BooleanArray| match mask.true_count() {
+        0 => None,
+        n if n == batch.num_rows() => Some(Arc::clone(batch)),
+        _ => Some(Arc::new(
+            filter_record_batch(batch, mask).expect("failed to filter buffered data"),
+        )),
+    };
+
+    let recent = not(&cold).expect("failed to negate time mask");
+    (filter(&cold), filter(&recent))
+}
+
 #[cfg(test)]
 mod tests {
+    use arrow_util::assert_batches_sorted_eq;
+    use data_types::SequenceNumber;
+    use mutable_batch_lp::test_helpers::lp_to_mutable_batch;
+
     use super::*;
 
+    fn buffer(lines: &[&str]) -> BufferState<Buffering> {
+        let mut b = BufferState::new();
+        for (i, line) in lines.iter().enumerate() {
+            b.write(
+                lp_to_mutable_batch(line).1,
+                SequenceNumber::new(i as i64 + 1),
+            )
+            .unwrap();
+        }
+        b
+    }
+
+    #[test]
+    fn test_split_before() {
+        let lines = [
+            "bananas,tag=A v=1 10\nbananas,tag=B v=2 30",
+            "bananas,tag=C v=3 20\nbananas,tag=D v=4 5",
+        ];
+
+        let (snapshot, retained) = buffer(&lines).split_before(20);
+        let snapshot = snapshot.expect("rows before the cutoff must be split");
+        assert_batches_sorted_eq!(
+            [
+                "+-----+--------------------------------+---+",
+                "| tag | time                           | v |",
+                "+-----+--------------------------------+---+",
+                "| A   | 1970-01-01T00:00:00.000000010Z | 1 |",
+                "| D   | 1970-01-01T00:00:00.000000005Z | 4 |",
+                "+-----+--------------------------------+---+",
+            ],
+            &snapshot
+                .get_query_data()
+                .iter()
+                .map(|v| v.as_ref().clone())
+                .collect::<Vec<_>>()
+        );
+        assert_batches_sorted_eq!(
+            [
+                "+-----+--------------------------------+---+",
+                "| tag | time                           | v |",
+                "+-----+--------------------------------+---+",
+                "| B   | 1970-01-01T00:00:00.000000030Z | 2 |",
+                "| C   | 1970-01-01T00:00:00.000000020Z | 3 |",
+                "+-----+--------------------------------+---+",
+            ],
+            &retained
+                .get_query_data()
+                .iter()
+                .map(|v| v.as_ref().clone())
+                .collect::<Vec<_>>()
+        );
+
+        // Both parts may contain rows of either write.
+        for (min, max) in [
+            (
+                snapshot.min_sequence_number(),
+                snapshot.max_sequence_number(),
+            ),
+            (
+                retained.min_sequence_number(),
+                retained.max_sequence_number(),
+            ),
+        ] {
+            assert_eq!(min, Some(SequenceNumber::new(1)));
+            assert_eq!(max, Some(SequenceNumber::new(2)));
+        }
+        assert_eq!(retained.rows(), 2);
+
+        // Nothing is split if no rows are before the cutoff.
+        let (snapshot, retained) = buffer(&lines).split_before(5);
+        assert!(snapshot.is_none());
+        assert_eq!(retained.rows(), 4);
+
+        // And nothing is retained if all rows are.
+        let (snapshot, retained) = buffer(&lines).split_before(31);
+        assert_eq!(snapshot.unwrap().get_query_data().len(), 2);
+        assert_eq!(retained.rows(), 0);
+        assert_eq!(retained.min_sequence_number(), None);
+        assert_eq!(retained.max_sequence_number(), None);
+    }
+
     #[test]
     fn test_empty_buffer_does_not_snapshot() {
         let b = BufferState::new();
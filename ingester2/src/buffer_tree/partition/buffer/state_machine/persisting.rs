@@ -2,17 +2,19 @@
 
 use std::sync::Arc;
 
-use arrow::record_batch::RecordBatch;
+use arrow::{array::Array, record_batch::RecordBatch};
+use data_types::{DeletePredicate, TimestampMinMax};
 
-use super::BufferState;
-use crate::buffer_tree::partition::buffer::traits::Queryable;
+use super::{batches_timestamp_range, BufferState};
+use crate::buffer_tree::partition::buffer::{delete::apply_delete, traits::Queryable};
 
 /// An immutable set of [`RecordBatch`] in the process of being persisted.
 #[derive(Debug)]
 pub(crate) struct Persisting {
     /// Snapshots generated from previous buffer contents to be persisted.
     ///
-    /// INVARIANT: this array is always non-empty.
+    /// INVARIANT: this array is non-empty when entering this state, but MAY
+    /// become empty if a delete removes all the rows within it.
     snapshots: Vec<Arc<RecordBatch>>,
 }
 
@@ -33,4 +35,34 @@ impl BufferState<Persisting> {
     pub(super) fn into_data(self) -> Vec<Arc<RecordBatch>> {
         self.state.snapshots
     }
+
+    /// Returns the approximate size of the data held, in bytes.
+    pub(crate) fn size(&self) -> usize {
+        self.state
+            .snapshots
+            .iter()
+            .flat_map(|v| v.columns())
+            .map(|v| v.get_array_memory_size())
+            .sum()
+    }
+
+    /// Returns the range of the timestamps of the data held, or [`None`] if it
+    /// contains no rows.
+    pub(crate) fn timestamp_range(&self) -> Option<TimestampMinMax> {
+        batches_timestamp_range(&self.state.snapshots)
+    }
+
+    /// Remove the rows matched by `predicate` from the queryable data.
+    ///
+    /// The data already handed to the persist task is unaffected - the delete
+    /// is materialised for the persisted file by the querier, as the file
+    /// records a maximum sequence number lower than that of the delete.
+    pub(crate) fn apply_delete(&mut self, predicate: &DeletePredicate) {
+        self.state.snapshots = self
+            .state
+            .snapshots
+            .iter()
+            .filter_map(|b| apply_delete(b, predicate))
+            .collect();
+    }
 }
@@ -36,6 +36,8 @@ impl BufferState<Snapshot> {
         assert!(!self.state.snapshots.is_empty());
         BufferState {
             state: Persisting::new(self.state.snapshots),
+            min_sequence_number: self.min_sequence_number,
+            max_sequence_number: self.max_sequence_number,
         }
     }
 }
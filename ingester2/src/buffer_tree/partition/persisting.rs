@@ -1,12 +1,14 @@
 use std::fmt::Display;
 
+use data_types::SequenceNumber;
+
 use crate::query_adaptor::QueryAdaptor;
 
 /// An opaque generational identifier of a buffer in a [`PartitionData`].
 ///
 /// [`PartitionData`]: super::PartitionData
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
-pub(super) struct BatchIdent(u64);
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct BatchIdent(u64);
 
 impl BatchIdent {
     /// Return the next unique value.
@@ -15,10 +17,9 @@ impl BatchIdent {
         Self(self.0)
     }
 
-    /// Only for tests, this allows reading the opaque identifier to assert the
-    /// value changing between persist ops.
-    #[cfg(test)]
-    pub(super) fn get(&self) -> u64 {
+    /// Read the opaque identifier, allowing the value to be observed changing
+    /// between persist ops.
+    pub(crate) fn get(&self) -> u64 {
         self.0
     }
 }
@@ -41,17 +42,46 @@ impl Display for BatchIdent {
 pub(crate) struct PersistingData {
     data: QueryAdaptor,
     batch_ident: BatchIdent,
+
+    /// The smallest [`SequenceNumber`] of the writes in `data`.
+    min_sequence_number: SequenceNumber,
+
+    /// The largest [`SequenceNumber`] of the writes in `data`.
+    max_sequence_number: SequenceNumber,
 }
 
 impl PersistingData {
-    pub(super) fn new(data: QueryAdaptor, batch_ident: BatchIdent) -> Self {
-        Self { data, batch_ident }
+    pub(super) fn new(
+        data: QueryAdaptor,
+        batch_ident: BatchIdent,
+        min_sequence_number: SequenceNumber,
+        max_sequence_number: SequenceNumber,
+    ) -> Self {
+        Self {
+            data,
+            batch_ident,
+            min_sequence_number,
+            max_sequence_number,
+        }
     }
 
-    pub(super) fn batch_ident(&self) -> BatchIdent {
+    pub(crate) fn batch_ident(&self) -> BatchIdent {
         self.batch_ident
     }
 
+    /// Return the smallest [`SequenceNumber`] of the writes in this batch.
+    pub(crate) fn min_sequence_number(&self) -> SequenceNumber {
+        self.min_sequence_number
+    }
+
+    /// Return the largest [`SequenceNumber`] of the writes in this batch.
+    ///
+    /// Deletes with a greater [`SequenceNumber`] were not applied to this
+    /// data.
+    pub(crate) fn max_sequence_number(&self) -> SequenceNumber {
+        self.max_sequence_number
+    }
+
     pub(crate) fn query_adaptor(&self) -> QueryAdaptor {
         self.data.clone()
     }
@@ -1,10 +1,16 @@
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    mem::size_of,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use async_trait::async_trait;
 use backoff::BackoffConfig;
 use data_types::{NamespaceId, Partition, PartitionId, PartitionKey, SequenceNumber, TableId};
 use iox_catalog::interface::Catalog;
-use observability_deps::tracing::debug;
+use metric::{U64Counter, U64Gauge};
+use observability_deps::tracing::{debug, info};
 use parking_lot::Mutex;
 
 use super::r#trait::PartitionProvider;
@@ -14,9 +20,18 @@ use crate::{
         partition::{resolver::SortKeyResolver, PartitionData, SortKeyState},
         table::TableName,
     },
-    deferred_load::DeferredLoad,
+    deferred_load::{DeferredLoad, DeferredLoadMetrics},
 };
 
+/// The estimated memory overhead of a cached entry, excluding the partition
+/// key it shares with the other entries of the same partition key.
+const ENTRY_BYTES: usize = size_of::<TableId>() + size_of::<PartitionId>();
+
+/// The estimated memory overhead of a partition key held in the cache.
+fn key_bytes(key: &PartitionKey) -> usize {
+    size_of::<PartitionKey>() + key.inner().len()
+}
+
 /// A read-through cache mapping `(table_id, partition_key)` tuples to
 /// `(partition_id, max_sequence_number)`.
 ///
@@ -25,7 +40,7 @@ use crate::{
 /// queries during startup, eliminating them from the ingest hot path in the
 /// common startup case (an ingester restart with no new partitions to add).
 ///
-/// # Memory Overhead
+/// # Memory Overhead
 ///
 /// Excluding map overhead, and assuming partition keys in the form
 /// "YYYY-MM-DD", each entry takes:
@@ -41,6 +56,19 @@ use crate::{
 /// memory overhead for items that were hit. This is the expected (only valid!)
 /// usage pattern.
 ///
+/// # Eviction
+///
+/// If a maximum size is configured, the most recently created partitions are
+/// cached until the estimated memory overhead of the entries reaches it, and
+/// the remainder are never cached.
+///
+/// Entries that are never hit (partitions that receive no writes after the
+/// ingester starts) would otherwise be held for the lifetime of the ingester.
+/// If a TTL is configured, all the remaining entries are evicted once it
+/// elapses, on the next lookup - once the ingester has been running for a
+/// while, the partitions that receive writes have already been resolved, and
+/// the remaining entries are likely stale.
+///
 /// # Deferred Sort Key Loading
 ///
 /// This cache does NOT cache the [`SortKey`] for each [`PartitionData`], as the
@@ -58,7 +86,30 @@ pub(crate) struct PartitionCache<T> {
     inner: T,
 
     /// Cached entries.
-    ///
+    entries: Mutex<Entries>,
+
+    /// The time after which all cached entries are evicted, if any.
+    expires_at: Option<Instant>,
+
+    /// Data needed to construct the [`SortKeyResolver`] for cached entries.
+    catalog: Arc<dyn Catalog>,
+    backoff_config: BackoffConfig,
+    /// The maximum amount of time a [`SortKeyResolver`] may wait until
+    /// pre-fetching the sort key in the background.
+    max_smear: Duration,
+
+    /// Metrics recording the deferred loading of sort keys.
+    sort_key_metrics: DeferredLoadMetrics,
+
+    hits: U64Counter,
+    misses: U64Counter,
+    entries_gauge: U64Gauge,
+    bytes_gauge: U64Gauge,
+}
+
+/// The cached entries, and their estimated memory overhead.
+#[derive(Debug, Default)]
+struct Entries {
     /// First lookup level is the shared partition keys - this eliminates
     /// needing to share the string key per table_id in memory which would be
     /// the case if inverted. This is cheaper (memory) than using Arc refs to
@@ -66,56 +117,113 @@ pub(crate) struct PartitionCache<T> {
     ///
     /// It's also likely a smaller N (more tables than partition keys) making it
     /// a faster search for cache misses.
-    #[allow(clippy::type_complexity)]
-    entries: Mutex<HashMap<PartitionKey, HashMap<TableId, PartitionId>>>,
+    map: HashMap<PartitionKey, HashMap<TableId, PartitionId>>,
 
-    /// Data needed to construct the [`SortKeyResolver`] for cached entries.
-    catalog: Arc<dyn Catalog>,
-    backoff_config: BackoffConfig,
-    /// The maximum amount of time a [`SortKeyResolver`] may wait until
-    /// pre-fetching the sort key in the background.
-    max_smear: Duration,
+    /// The number of `(partition_key, table_id)` entries in `map`.
+    len: usize,
+
+    /// The estimated memory overhead of the entries, in bytes.
+    bytes: usize,
 }
 
 impl<T> PartitionCache<T> {
     /// Initialise a [`PartitionCache`] containing the specified partitions.
     ///
+    /// If `max_bytes` is set, only the most recently created partitions whose
+    /// entries fit within it are cached. If `ttl` is set, all the entries are
+    /// evicted once it elapses.
+    ///
     /// Any cache miss is passed through to `inner`.
     ///
     /// Any cache hit returns a [`PartitionData`] configured with a
     /// [`SortKeyState::Deferred`] for deferred key loading in the background.
     /// The [`SortKeyResolver`] is initialised with the given `catalog`,
-    /// `backoff_config`, and `max_smear` maximal load wait duration.
+    /// `backoff_config`, and `max_smear` maximal load wait duration, and the
+    /// deferred loads are recorded in `metrics`.
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new<P>(
         inner: T,
         partitions: P,
+        max_bytes: Option<usize>,
+        ttl: Option<Duration>,
         max_smear: Duration,
         catalog: Arc<dyn Catalog>,
         backoff_config: BackoffConfig,
+        metrics: &metric::Registry,
     ) -> Self
     where
         P: IntoIterator<Item = Partition>,
     {
-        let mut entries = HashMap::<PartitionKey, HashMap<TableId, PartitionId>>::new();
-        for p in partitions.into_iter() {
+        // Partition IDs are assigned in creation order - admit the most
+        // recently created partitions first.
+        let mut partitions = partitions.into_iter().collect::<Vec<_>>();
+        partitions.sort_unstable_by(|a, b| b.id.cmp(&a.id));
+        let n_partitions = partitions.len();
+
+        let mut entries = Entries::default();
+        for p in partitions {
+            let cost = match entries.map.contains_key(&p.partition_key) {
+                true => ENTRY_BYTES,
+                false => ENTRY_BYTES + key_bytes(&p.partition_key),
+            };
+            if max_bytes.map_or(false, |max| entries.bytes + cost > max) {
+                break;
+            }
+
             entries
+                .map
                 .entry(p.partition_key)
                 .or_default()
                 .insert(p.table_id, p.id);
+            entries.len += 1;
+            entries.bytes += cost;
+        }
+
+        if entries.len < n_partitions {
+            info!(
+                cached = entries.len,
+                evicted = n_partitions - entries.len,
+                "partition cache size limit reached"
+            );
         }
 
         // Minimise the overhead of the maps.
-        for tables in entries.values_mut() {
+        for tables in entries.map.values_mut() {
             tables.shrink_to_fit();
         }
-        entries.shrink_to_fit();
+        entries.map.shrink_to_fit();
+
+        let lookups = metrics.register_metric::<U64Counter>(
+            "ingester_partition_cache_lookups",
+            "number of partition cache lookups, by result",
+        );
+        let entries_gauge = metrics
+            .register_metric::<U64Gauge>(
+                "ingester_partition_cache_entries",
+                "number of partitions held in the partition cache",
+            )
+            .recorder(&[]);
+        let bytes_gauge = metrics
+            .register_metric::<U64Gauge>(
+                "ingester_partition_cache_bytes",
+                "estimated memory overhead of the partitions held in the partition cache",
+            )
+            .recorder(&[]);
+        entries_gauge.set(entries.len as _);
+        bytes_gauge.set(entries.bytes as _);
 
         Self {
             entries: Mutex::new(entries),
+            expires_at: ttl.map(|v| Instant::now() + v),
             inner,
             catalog,
             backoff_config,
             max_smear,
+            sort_key_metrics: DeferredLoadMetrics::new(metrics, "sort_key"),
+            hits: lookups.recorder(&[("result", "hit")]),
+            misses: lookups.recorder(&[("result", "miss")]),
+            entries_gauge,
+            bytes_gauge,
         }
     }
 
@@ -128,6 +236,14 @@ impl<T> PartitionCache<T> {
     ) -> Option<(PartitionKey, PartitionId)> {
         let mut entries = self.entries.lock();
 
+        if self.expires_at.map_or(false, |v| Instant::now() >= v) && entries.len > 0 {
+            info!(evicted = entries.len, "partition cache entries expired");
+            *entries = Entries::default();
+            self.entries_gauge.set(0);
+            self.bytes_gauge.set(0);
+            return None;
+        }
+
         // Look up the partition key provided by the caller.
         //
         // If the partition key is a hit, clone the key from the map and return
@@ -135,19 +251,26 @@ impl<T> PartitionCache<T> {
         // effective reuse of the same partition key str across all hits for it
         // and is more memory efficient than using the caller-provided partition
         // key in the PartitionData.
-        let key = entries.get_key_value(partition_key)?.0.clone();
-        let partition = entries.get_mut(partition_key).unwrap();
+        let key = entries.map.get_key_value(partition_key)?.0.clone();
+        let partition = entries.map.get_mut(partition_key).unwrap();
 
         let e = partition.remove(&table_id)?;
+        let mut released = ENTRY_BYTES;
 
         // As a entry was removed, check if it is now empty.
         if partition.is_empty() {
-            entries.remove(partition_key);
-            entries.shrink_to_fit();
+            entries.map.remove(partition_key);
+            entries.map.shrink_to_fit();
+            released += key_bytes(&key);
         } else {
             partition.shrink_to_fit();
         }
 
+        entries.len -= 1;
+        entries.bytes -= released;
+        self.entries_gauge.set(entries.len as _);
+        self.bytes_gauge.set(entries.bytes as _);
+
         Some((key, e))
     }
 }
@@ -170,9 +293,10 @@ where
 
         if let Some((key, partition_id)) = self.find(table_id, &partition_key) {
             debug!(%table_id, %partition_key, "partition cache hit");
+            self.hits.inc(1);
 
             // Initialise a deferred resolver for the sort key.
-            let sort_key_resolver = DeferredLoad::new(
+            let sort_key_resolver = DeferredLoad::new_with_metrics(
                 self.max_smear,
                 SortKeyResolver::new(
                     partition_id,
@@ -180,6 +304,7 @@ where
                     self.backoff_config.clone(),
                 )
                 .fetch(),
+                &self.sort_key_metrics,
             );
 
             // Use the returned partition key instead of the callers - this
@@ -197,6 +322,7 @@ where
         }
 
         debug!(%table_id, %partition_key, "partition cache miss");
+        self.misses.inc(1);
 
         // Otherwise delegate to the catalog / inner impl.
         self.inner
@@ -214,6 +340,7 @@ where
 #[cfg(test)]
 mod tests {
     use iox_catalog::mem::MemCatalog;
+    use metric::Metric;
 
     use super::*;
     use crate::{
@@ -237,9 +364,12 @@ mod tests {
         PartitionCache::new(
             inner,
             partitions,
+            None,
+            None,
             Duration::from_secs(10_000_000),
             Arc::new(MemCatalog::new(Arc::new(metric::Registry::default()))),
             BackoffConfig::default(),
+            &metric::Registry::default(),
         )
     }
 
@@ -320,7 +450,9 @@ mod tests {
         assert_eq!(*got.partition_key(), PartitionKey::from(PARTITION_KEY));
 
         // The cache should have been cleaned up as it was consumed.
-        assert!(cache.entries.lock().is_empty());
+        assert!(cache.entries.lock().map.is_empty());
+        assert_eq!(cache.entries.lock().len, 0);
+        assert_eq!(cache.entries.lock().bytes, 0);
 
         // Assert the partition key from the cache was used for the lifetime of
         // the partition, so that it is shared with the cache + other partitions
@@ -423,4 +555,131 @@ mod tests {
         assert_eq!(got.table_id(), other_table);
         assert_eq!(&**got.table_name().get().await, TABLE_NAME);
     }
+
+    fn partition(id: i64, table_id: TableId, key: &str) -> Partition {
+        Partition {
+            id: PartitionId::new(id),
+            shard_id: TRANSITION_SHARD_ID,
+            table_id,
+            partition_key: key.into(),
+            sort_key: Default::default(),
+            persisted_sequence_number: Default::default(),
+        }
+    }
+
+    async fn get(
+        cache: &PartitionCache<MockPartitionProvider>,
+        table_id: TableId,
+        key: &str,
+    ) -> PartitionData {
+        cache
+            .get_partition(
+                key.into(),
+                NAMESPACE_ID,
+                Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                    NamespaceName::from(NAMESPACE_NAME)
+                })),
+                table_id,
+                Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                    TableName::from(TABLE_NAME)
+                })),
+            )
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_max_bytes() {
+        // Enough space for both tables of the newest partition key, and no
+        // more.
+        let max_bytes = 2 * ENTRY_BYTES + key_bytes(&PartitionKey::from("newest"));
+        let metrics = metric::Registry::default();
+        let cache = PartitionCache::new(
+            MockPartitionProvider::default(),
+            [
+                partition(1, TABLE_ID, "oldest"),
+                partition(3, TABLE_ID, "newest"),
+                partition(2, TableId::new(4), "newest"),
+            ],
+            Some(max_bytes),
+            None,
+            Duration::from_secs(10_000_000),
+            Arc::new(MemCatalog::new(Arc::new(metric::Registry::default()))),
+            BackoffConfig::default(),
+            &metrics,
+        );
+
+        {
+            let entries = cache.entries.lock();
+            assert_eq!(entries.len, 2);
+            assert_eq!(entries.bytes, max_bytes);
+            assert!(!entries.map.contains_key(&PartitionKey::from("oldest")));
+        }
+
+        let got = get(&cache, TABLE_ID, "newest").await;
+        assert_eq!(got.partition_id(), PartitionId::new(3));
+
+        let gauge = |name: &'static str| {
+            metrics
+                .get_instrument::<Metric<U64Gauge>>(name)
+                .expect("metric not registered")
+                .get_observer(&metric::Attributes::from(&[]))
+                .expect("no metric observer")
+                .fetch()
+        };
+        assert_eq!(gauge("ingester_partition_cache_entries"), 1);
+        assert_eq!(gauge("ingester_partition_cache_bytes"), ENTRY_BYTES as u64);
+
+        let hits = metrics
+            .get_instrument::<Metric<U64Counter>>("ingester_partition_cache_lookups")
+            .expect("metric not registered")
+            .get_observer(&metric::Attributes::from(&[("result", "hit")]))
+            .expect("no metric observer")
+            .fetch();
+        assert_eq!(hits, 1);
+    }
+
+    #[tokio::test]
+    async fn test_ttl() {
+        let data = PartitionData::new(
+            PartitionId::new(99),
+            PARTITION_KEY.into(),
+            NAMESPACE_ID,
+            Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                NamespaceName::from(NAMESPACE_NAME)
+            })),
+            TABLE_ID,
+            Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                TableName::from(TABLE_NAME)
+            })),
+            SortKeyState::Provided(None),
+        );
+        let inner = MockPartitionProvider::default().with_partition(data);
+
+        let metrics = metric::Registry::default();
+        let cache = PartitionCache::new(
+            inner,
+            [partition(PARTITION_ID.get(), TABLE_ID, PARTITION_KEY)],
+            None,
+            Some(Duration::ZERO),
+            Duration::from_secs(10_000_000),
+            Arc::new(MemCatalog::new(Arc::new(metric::Registry::default()))),
+            BackoffConfig::default(),
+            &metrics,
+        );
+
+        // The expired entry is evicted, and the lookup passed to the inner
+        // provider.
+        let got = get(&cache, TABLE_ID, PARTITION_KEY).await;
+        assert_eq!(got.partition_id(), PartitionId::new(99));
+        assert!(cache.entries.lock().map.is_empty());
+        assert_eq!(cache.entries.lock().bytes, 0);
+
+        let misses = metrics
+            .get_instrument::<Metric<U64Counter>>("ingester_partition_cache_lookups")
+            .expect("metric not registered")
+            .get_observer(&metric::Attributes::from(&[("result", "miss")]))
+            .expect("no metric observer")
+            .fetch();
+        assert_eq!(misses, 1);
+    }
 }
@@ -1,10 +1,12 @@
-use std::sync::Arc;
+use std::{collections::HashSet, sync::Arc};
 
 use async_trait::async_trait;
-use data_types::{NamespaceId, TableId};
-use dml::DmlOperation;
+use data_types::{NamespaceId, SequenceNumber, TableId};
+use dml::{DmlOperation, DmlWrite};
 use metric::U64Counter;
-use parking_lot::Mutex;
+use observability_deps::tracing::*;
+use parking_lot::{Mutex, RwLock};
+use predicate::Predicate;
 use trace::span::Span;
 
 use super::{
@@ -65,6 +67,14 @@ use crate::{
 /// partition. Once a partition has been read, the data within it is immutable
 /// from the caller's perspective, and subsequent writes DO NOT become visible.
 ///
+/// # Deleted Namespaces
+///
+/// A namespace removed with [`BufferTree::remove_namespace()`] is dropped from
+/// the tree along with its buffered data, and any subsequent op for it is
+/// discarded rather than buffered. Partitions already enqueued for
+/// persistence, and queries already executing, retain their reference to the
+/// data until they complete.
+///
 /// [`TableData`]: crate::buffer_tree::table::TableData
 /// [`PartitionData`]: crate::buffer_tree::partition::PartitionData
 #[derive(Debug)]
@@ -92,8 +102,13 @@ pub(crate) struct BufferTree {
     /// [`TableData`]: crate::buffer_tree::table::TableData
     table_name_resolver: Arc<dyn TableNameProvider>,
 
+    /// The IDs of the namespaces removed from this [`BufferTree`], whose ops
+    /// are discarded.
+    deleted_namespaces: RwLock<HashSet<NamespaceId>>,
+
     metrics: Arc<metric::Registry>,
     namespace_count: U64Counter,
+    deleted_namespace_count: U64Counter,
 }
 
 impl BufferTree {
@@ -110,14 +125,22 @@ impl BufferTree {
                 "Number of namespaces known to the ingester",
             )
             .recorder(&[]);
+        let deleted_namespace_count = metrics
+            .register_metric::<U64Counter>(
+                "ingester_namespaces_deleted",
+                "Number of deleted namespaces dropped from the ingester",
+            )
+            .recorder(&[]);
 
         Self {
             namespaces: Default::default(),
             namespace_name_resolver,
             table_name_resolver,
+            deleted_namespaces: Default::default(),
             metrics,
             partition_provider,
             namespace_count,
+            deleted_namespace_count,
         }
     }
 
@@ -126,6 +149,76 @@ impl BufferTree {
         self.namespaces.get(&namespace_id)
     }
 
+    /// Drop the namespace identified by `namespace_id` and its buffered data
+    /// from this [`BufferTree`], returning it if it was buffered.
+    ///
+    /// All subsequent ops for the namespace are discarded.
+    pub(crate) fn remove_namespace(&self, namespace_id: NamespaceId) -> Option<Arc<NamespaceData>> {
+        // Mark the namespace as deleted before removing it, so that an op
+        // concurrently re-initialising it observes the deletion once applied
+        // (see the DmlSink impl below).
+        if self.deleted_namespaces.write().insert(namespace_id) {
+            self.deleted_namespace_count.inc(1);
+        }
+        self.namespaces.remove(&namespace_id)
+    }
+
+    /// Returns true if the namespace identified by `namespace_id` has been
+    /// removed with [`BufferTree::remove_namespace()`].
+    pub(crate) fn is_namespace_deleted(&self, namespace_id: NamespaceId) -> bool {
+        self.deleted_namespaces.read().contains(&namespace_id)
+    }
+
+    /// Return the [`NamespaceData`] for `namespace_id`, initialising it if it
+    /// is not yet buffered.
+    fn namespace_or_insert(&self, namespace_id: NamespaceId) -> Arc<NamespaceData> {
+        self.namespaces.get_or_insert_with(&namespace_id, || {
+            // Increase the metric that records the number of namespaces
+            // buffered in this ingester instance.
+            self.namespace_count.inc(1);
+
+            Arc::new(NamespaceData::new(
+                namespace_id,
+                self.namespace_name_resolver.for_namespace(namespace_id),
+                Arc::clone(&self.table_name_resolver),
+                Arc::clone(&self.partition_provider),
+                &self.metrics,
+            ))
+        })
+    }
+
+    /// Initialise the namespace, tables and partitions `write` buffers data
+    /// in, resolving them from the catalog where necessary, without buffering
+    /// the data.
+    ///
+    /// This allows the catalog requests of a write to be made (and bounded in
+    /// time) before it is committed to the WAL.
+    pub(crate) async fn resolve_partitions(&self, write: &DmlWrite) {
+        let namespace_id = write.namespace_id();
+        if self.is_namespace_deleted(namespace_id) {
+            return;
+        }
+
+        self.namespace_or_insert(namespace_id)
+            .resolve_partitions(write)
+            .await;
+
+        // The namespace may have been removed while its partitions were being
+        // resolved, after it had been (re-)initialised above.
+        if self.is_namespace_deleted(namespace_id) {
+            self.namespaces.remove(&namespace_id);
+        }
+    }
+
+    /// Obtain a snapshot of the namespaces within this [`BufferTree`].
+    ///
+    /// NOTE: the snapshot is an atomic / point-in-time snapshot of the set of
+    /// namespaces, but the tables (and partitions) within them may change as
+    /// they continue to buffer DML operations.
+    pub(crate) fn namespaces(&self) -> Vec<Arc<NamespaceData>> {
+        self.namespaces.values()
+    }
+
     /// Iterate over a snapshot of [`PartitionData`] in the tree.
     ///
     /// This iterator will iterate over a consistent snapshot of namespaces
@@ -145,6 +238,15 @@ impl BufferTree {
             .flat_map(|v| v.tables())
             .flat_map(|v| v.partitions())
     }
+
+    /// Return the smallest [`SequenceNumber`] of the data buffered in, or
+    /// persisting from, any partition in the tree, or [`None`] if all the
+    /// data applied to the tree has been persisted.
+    pub(crate) fn min_unpersisted_sequence_number(&self) -> Option<SequenceNumber> {
+        self.partitions()
+            .filter_map(|p| p.lock().min_unpersisted_sequence_number())
+            .min()
+    }
 }
 
 #[async_trait]
@@ -153,21 +255,22 @@ impl DmlSink for BufferTree {
 
     async fn apply(&self, op: DmlOperation) -> Result<(), Self::Error> {
         let namespace_id = op.namespace_id();
-        let namespace_data = self.namespaces.get_or_insert_with(&namespace_id, || {
-            // Increase the metric that records the number of namespaces
-            // buffered in this ingester instance.
-            self.namespace_count.inc(1);
+        if self.is_namespace_deleted(namespace_id) {
+            debug!(%namespace_id, "discarding op for deleted namespace");
+            return Ok(());
+        }
 
-            Arc::new(NamespaceData::new(
-                namespace_id,
-                self.namespace_name_resolver.for_namespace(namespace_id),
-                Arc::clone(&self.table_name_resolver),
-                Arc::clone(&self.partition_provider),
-                &self.metrics,
-            ))
-        });
+        let namespace_data = self.namespace_or_insert(namespace_id);
+
+        let res = namespace_data.apply(op).await;
 
-        namespace_data.apply(op).await
+        // The namespace may have been removed while this op was being applied,
+        // after it had been (re-)initialised above.
+        if self.is_namespace_deleted(namespace_id) {
+            self.namespaces.remove(&namespace_id);
+        }
+
+        res
     }
 }
 
@@ -180,6 +283,7 @@ impl QueryExec for BufferTree {
         namespace_id: NamespaceId,
         table_id: TableId,
         columns: Vec<String>,
+        predicate: Option<Predicate>,
         span: Option<Span>,
     ) -> Result<Self::Response, QueryError> {
         // Extract the namespace if it exists.
@@ -190,7 +294,7 @@ impl QueryExec for BufferTree {
         // Delegate query execution to the namespace, wrapping the execution in
         // a tracing delegate to emit a child span.
         QueryExecTracing::new(inner, "namespace")
-            .query_exec(namespace_id, table_id, columns, span)
+            .query_exec(namespace_id, table_id, columns, predicate, span)
             .await
     }
 }
@@ -201,7 +305,10 @@ mod tests {
 
     use assert_matches::assert_matches;
     use data_types::{PartitionId, PartitionKey};
-    use datafusion::{assert_batches_eq, assert_batches_sorted_eq};
+    use datafusion::{
+        assert_batches_eq, assert_batches_sorted_eq,
+        prelude::{col, lit},
+    };
     use futures::{StreamExt, TryStreamExt};
     use metric::{Attributes, Metric};
 
@@ -332,7 +439,7 @@ mod tests {
 
                     // Execute the query against NAMESPACE_ID and TABLE_ID
                     let batches = buf
-                        .query_exec(NAMESPACE_ID, TABLE_ID, vec![], None)
+                        .query_exec(NAMESPACE_ID, TABLE_ID, vec![], None, None)
                         .await
                         .expect("query should succeed")
                         .into_record_batches()
@@ -842,7 +949,7 @@ mod tests {
 
         // Query the empty tree
         let err = buf
-            .query_exec(NAMESPACE_ID, TABLE_ID, vec![], None)
+            .query_exec(NAMESPACE_ID, TABLE_ID, vec![], None, None)
             .await
             .expect_err("query should fail");
         assert_matches!(err, QueryError::NamespaceNotFound(ns) => {
@@ -863,7 +970,7 @@ mod tests {
 
         // Ensure an unknown table errors
         let err = buf
-            .query_exec(NAMESPACE_ID, TableId::new(1234), vec![], None)
+            .query_exec(NAMESPACE_ID, TableId::new(1234), vec![], None, None)
             .await
             .expect_err("query should fail");
         assert_matches!(err, QueryError::TableNotFound(ns, t) => {
@@ -872,11 +979,188 @@ mod tests {
         });
 
         // Ensure a valid namespace / table does not error
-        buf.query_exec(NAMESPACE_ID, TABLE_ID, vec![], None)
+        buf.query_exec(NAMESPACE_ID, TABLE_ID, vec![], None, None)
             .await
             .expect("namespace / table should exist");
     }
 
+    /// Ensure the partitions and snapshots containing no data within the time
+    /// range of the query predicate are not returned.
+    #[tokio::test]
+    async fn test_query_time_range_pruning() {
+        let new_partition = |id, key: &str| {
+            PartitionData::new(
+                PartitionId::new(id),
+                PartitionKey::from(key),
+                NAMESPACE_ID,
+                Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                    NamespaceName::from(NAMESPACE_NAME)
+                })),
+                TABLE_ID,
+                Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                    TableName::from(TABLE_NAME)
+                })),
+                SortKeyState::Provided(None),
+            )
+        };
+        let partition_provider = Arc::new(
+            MockPartitionProvider::default()
+                .with_partition(new_partition(0, "p1"))
+                .with_partition(new_partition(1, "p2")),
+        );
+
+        let buf = BufferTree::new(
+            Arc::new(MockNamespaceNameProvider::default()),
+            Arc::new(MockTableNameProvider::new(TABLE_NAME)),
+            partition_provider,
+            Arc::new(metric::Registry::default()),
+        );
+
+        let write = |key: &str, seq, lp: &str| {
+            DmlOperation::Write(make_write_op(
+                &PartitionKey::from(key),
+                NAMESPACE_ID,
+                TABLE_NAME,
+                TABLE_ID,
+                seq,
+                lp,
+            ))
+        };
+
+        // Partition p1 contains a persisting snapshot outside of the range,
+        // and buffered data within it.
+        buf.apply(write("p1", 0, "bananas,region=Asturias temp=35 10"))
+            .await
+            .expect("failed to write data");
+        let _persisting = buf
+            .namespace(NAMESPACE_ID)
+            .unwrap()
+            .table(TABLE_ID)
+            .unwrap()
+            .get_partition_by_key(&PartitionKey::from("p1"))
+            .unwrap()
+            .lock()
+            .mark_persisting()
+            .expect("must have data");
+        buf.apply(write("p1", 1, "bananas,region=Madrid temp=25 500"))
+            .await
+            .expect("failed to write data");
+
+        // Partition p2 contains only data outside of the range.
+        buf.apply(write("p2", 2, "bananas,region=Iceland temp=5 5000"))
+            .await
+            .expect("failed to write data");
+
+        let buf = &buf;
+        let query = move |predicate| async move {
+            buf.query_exec(NAMESPACE_ID, TABLE_ID, vec![], predicate, None)
+                .await
+                .expect("query should succeed")
+                .into_record_batches()
+                .try_collect::<Vec<_>>()
+                .await
+                .expect("query failed")
+        };
+
+        let batches = query(Some(Predicate::new().with_range(400, 1000))).await;
+        assert_batches_sorted_eq!(
+            [
+                "+--------+------+-------------------------------+",
+                "| region | temp | time                          |",
+                "+--------+------+-------------------------------+",
+                "| Madrid | 25   | 1970-01-01T00:00:00.000000500 |",
+                "+--------+------+-------------------------------+",
+            ],
+            &batches
+        );
+
+        // A predicate without a time range returns all the data.
+        let batches = query(Some(Predicate::new())).await;
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 3);
+
+        // As does a query without a predicate.
+        let batches = query(None).await;
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 3);
+    }
+
+    /// Ensure the rows not matching the time range or tag equality
+    /// expressions of the query predicate are not returned.
+    #[tokio::test]
+    async fn test_query_predicate_filtering() {
+        let buf = BufferTree::new(
+            Arc::new(MockNamespaceNameProvider::default()),
+            Arc::new(MockTableNameProvider::new(TABLE_NAME)),
+            Arc::new(
+                MockPartitionProvider::default().with_partition(PartitionData::new(
+                    PartitionId::new(0),
+                    PartitionKey::from("p1"),
+                    NAMESPACE_ID,
+                    Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                        NamespaceName::from(NAMESPACE_NAME)
+                    })),
+                    TABLE_ID,
+                    Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                        TableName::from(TABLE_NAME)
+                    })),
+                    SortKeyState::Provided(None),
+                )),
+            ),
+            Arc::new(metric::Registry::default()),
+        );
+
+        buf.apply(DmlOperation::Write(make_write_op(
+            &PartitionKey::from("p1"),
+            NAMESPACE_ID,
+            TABLE_NAME,
+            TABLE_ID,
+            0,
+            "bananas,region=Asturias temp=35 10\n\
+            bananas,region=Madrid temp=25 500\n\
+            bananas,region=Madrid temp=15 900\n\
+            bananas,region=Asturias temp=20 600",
+        )))
+        .await
+        .expect("failed to write data");
+
+        let buf = &buf;
+        let query = move |predicate| async move {
+            buf.query_exec(NAMESPACE_ID, TABLE_ID, vec![], Some(predicate), None)
+                .await
+                .expect("query should succeed")
+                .into_record_batches()
+                .try_collect::<Vec<_>>()
+                .await
+                .expect("query failed")
+        };
+
+        // The expression on the "temp" field is not evaluated by the ingester.
+        let batches = query(
+            Predicate::new()
+                .with_range(400, 800)
+                .with_expr(col("region").eq(lit("Madrid")))
+                .with_expr(col("temp").gt(lit(100.0))),
+        )
+        .await;
+        assert_batches_sorted_eq!(
+            [
+                "+--------+------+-------------------------------+",
+                "| region | temp | time                          |",
+                "+--------+------+-------------------------------+",
+                "| Madrid | 25   | 1970-01-01T00:00:00.000000500 |",
+                "+--------+------+-------------------------------+",
+            ],
+            &batches
+        );
+
+        // A tag value that does not exist returns no data.
+        let batches = query(Predicate::new().with_expr(col("region").eq(lit("Narnia")))).await;
+        assert!(batches.is_empty());
+
+        // Equality expressions against non-tag columns are not evaluated.
+        let batches = query(Predicate::new().with_expr(col("temp").eq(lit("Narnia")))).await;
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 4);
+    }
+
     /// This test asserts the read consistency properties defined in the
     /// [`BufferTree`] type docs.
     ///
@@ -950,7 +1234,7 @@ mod tests {
         // Execute a query of the buffer tree, generating the result stream, but
         // DO NOT consume it.
         let stream = buf
-            .query_exec(NAMESPACE_ID, TABLE_ID, vec![], None)
+            .query_exec(NAMESPACE_ID, TABLE_ID, vec![], None, None)
             .await
             .expect("query should succeed")
             .into_partition_stream();
@@ -5,10 +5,13 @@ pub(crate) mod name_resolver;
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use data_types::{NamespaceId, PartitionId, PartitionKey, SequenceNumber, TableId};
+use data_types::{
+    DeletePredicate, NamespaceId, PartitionId, PartitionKey, SequenceNumber, TableId,
+};
 use datafusion_util::MemoryStream;
 use mutable_batch::MutableBatch;
 use parking_lot::{Mutex, RwLock};
+use predicate::Predicate;
 use schema::Projection;
 use trace::span::{Span, SpanRecorder};
 
@@ -151,14 +154,28 @@ impl TableData {
         batch: MutableBatch,
         partition_key: PartitionKey,
     ) -> Result<(), mutable_batch::Error> {
+        let partition_data = self.resolve_partition(partition_key).await;
+
+        partition_data.lock().buffer_write(batch, sequence_number)?;
+
+        Ok(())
+    }
+
+    /// Return the [`PartitionData`] for `partition_key`, initialising it with
+    /// the partition provider (which MAY query the catalog) if it is not yet
+    /// buffered.
+    pub(super) async fn resolve_partition(
+        &self,
+        partition_key: PartitionKey,
+    ) -> Arc<Mutex<PartitionData>> {
         let p = self.partition_data.read().by_key(&partition_key);
-        let partition_data = match p {
+        match p {
             Some(p) => p,
             None => {
                 let p = self
                     .partition_provider
                     .get_partition(
-                        partition_key.clone(),
+                        partition_key,
                         self.namespace_id,
                         Arc::clone(&self.namespace_name),
                         self.table_id,
@@ -171,11 +188,17 @@ impl TableData {
                 // thread has already initialised the partition.
                 self.partition_data.write().try_insert(p)
             }
-        };
-
-        partition_data.lock().buffer_write(batch, sequence_number)?;
+        }
+    }
 
-        Ok(())
+    /// Remove the rows matched by `predicate` from the data buffered in all
+    /// partitions of this table.
+    ///
+    /// Writes subsequently buffered are unaffected by `predicate`.
+    pub(super) fn apply_delete(&self, predicate: &DeletePredicate) {
+        for p in self.partitions() {
+            p.lock().apply_delete(predicate);
+        }
     }
 
     /// Return a mutable reference to all partitions buffered for this table.
@@ -233,6 +256,7 @@ impl QueryExec for TableData {
         namespace_id: NamespaceId,
         table_id: TableId,
         columns: Vec<String>,
+        predicate: Option<Predicate>,
         span: Option<Span>,
     ) -> Result<Self::Response, QueryError> {
         assert_eq!(self.table_id, table_id, "buffer tree index inconsistency");
@@ -241,17 +265,55 @@ impl QueryExec for TableData {
             "buffer tree index inconsistency"
         );
 
+        // Only rows matching the time range and tag equality expressions of
+        // the predicate (if any) are returned - the remaining expressions are
+        // applied by the querier.
+        let range = predicate.as_ref().and_then(|p| p.range);
+
         // Gather the partition data from all of the partitions in this table.
         let partitions = self.partitions().into_iter().filter_map(move |p| {
             let mut span = SpanRecorder::new(span.clone().map(|s| s.child("partition read")));
 
             let (id, data) = {
                 let mut p = p.lock();
+
+                // Skip partitions containing no data within the time range,
+                // without generating a snapshot of their buffered data.
+                if let Some(range) = range {
+                    if !p.timestamp_range().map_or(false, |v| v.overlaps(range)) {
+                        span.ok("partition pruned");
+                        return None;
+                    }
+                }
+
                 (p.partition_id(), p.get_query_data()?)
             };
             assert_eq!(id, data.partition_id());
 
-            // Project the data if necessary
+            // Discard the snapshots containing no data within the time range.
+            let data = match range {
+                Some(range) => data.prune_time_range(range)?,
+                None => data,
+            };
+
+            // Discard the rows not matching the predicate, before paying the
+            // cost of deduplicating them.
+            let data = match &predicate {
+                Some(predicate) => data.filter(predicate)?,
+                None => data,
+            };
+
+            // A partition being persisted returns both the persisting and the
+            // newly buffered data, which may contain the same rows - never
+            // return duplicate rows for a partition.
+            let data = data.deduplicate();
+
+            // Only the requested columns (if any) are returned, so that a query
+            // against a subset of the columns of a wide table does not transfer
+            // the remaining columns to the querier.
+            //
+            // This MUST happen after deduplication, which relies on the primary
+            // key columns.
             let columns = columns.iter().map(String::as_str).collect::<Vec<_>>();
             let selection = if columns.is_empty() {
                 Projection::All
@@ -259,13 +321,14 @@ impl QueryExec for TableData {
                 Projection::Some(columns.as_ref())
             };
 
-            let ret = PartitionResponse::new(
-                Box::pin(MemoryStream::new(
-                    data.project_selection(selection).into_iter().collect(),
-                )),
-                id,
-                None,
-            );
+            // Skip partitions containing none of the requested columns.
+            let batches = data.project_selection(selection);
+            if batches.is_empty() {
+                span.ok("no selected columns");
+                return None;
+            }
+
+            let ret = PartitionResponse::new(Box::pin(MemoryStream::new(batches)), id, None);
 
             span.ok("read partition data");
             Some(ret)
@@ -280,11 +343,16 @@ mod tests {
     use std::{sync::Arc, time::Duration};
 
     use data_types::PartitionId;
+    use datafusion::assert_batches_sorted_eq;
+    use futures::TryStreamExt;
     use mutable_batch_lp::lines_to_batches;
 
     use super::*;
-    use crate::buffer_tree::partition::{
-        resolver::mock::MockPartitionProvider, PartitionData, SortKeyState,
+    use crate::{
+        buffer_tree::partition::{
+            resolver::mock::MockPartitionProvider, PartitionData, SortKeyState,
+        },
+        query::response::QueryResponse,
     };
 
     const TABLE_NAME: &str = "bananas";
@@ -352,4 +420,178 @@ mod tests {
             .is_some());
         assert!(table.partition_data.read().by_id(PARTITION_ID).is_some());
     }
+
+    #[tokio::test]
+    async fn test_query_deduplicates_persisting_data() {
+        let partition_provider = Arc::new(MockPartitionProvider::default().with_partition(
+            PartitionData::new(
+                PARTITION_ID,
+                PARTITION_KEY.into(),
+                NAMESPACE_ID,
+                Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                    NamespaceName::from("platanos")
+                })),
+                TABLE_ID,
+                Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                    TableName::from(TABLE_NAME)
+                })),
+                SortKeyState::Provided(None),
+            ),
+        ));
+
+        let table = TableData::new(
+            TABLE_ID,
+            DeferredLoad::new(Duration::from_secs(1), async {
+                TableName::from(TABLE_NAME)
+            }),
+            NAMESPACE_ID,
+            Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                NamespaceName::from("platanos")
+            })),
+            partition_provider,
+        );
+
+        let write = |seq: i64, lp: &str| {
+            let batch = lines_to_batches(lp, 0).unwrap().remove(TABLE_NAME).unwrap();
+            table.buffer_table_write(SequenceNumber::new(seq), batch, PARTITION_KEY.into())
+        };
+
+        write(
+            1,
+            "bananas,region=asia v=1i,w=1.5 10\nbananas,region=europe v=2i 20",
+        )
+        .await
+        .expect("buffer op should succeed");
+
+        // Begin persisting the data, and overwrite one of the persisting rows
+        // in the buffer, leaving a field unset.
+        let _persisting = table
+            .partition(PARTITION_ID)
+            .unwrap()
+            .lock()
+            .mark_persisting()
+            .expect("partition has data");
+
+        write(
+            2,
+            "bananas,region=asia v=3i 10\nbananas,region=asia v=4i 30",
+        )
+        .await
+        .expect("buffer op should succeed");
+
+        let response = table
+            .query_exec(NAMESPACE_ID, TABLE_ID, vec![], None, None)
+            .await
+            .expect("query should succeed");
+        let batches = QueryResponse::new(response)
+            .into_record_batches()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("query should succeed");
+
+        // The overlapping persisting & buffered data is merged into a single
+        // batch containing one row per primary key.
+        assert_eq!(batches.len(), 1);
+
+        assert_batches_sorted_eq!(
+            [
+                "+--------+-------------------------------+---+-----+",
+                "| region | time                          | v | w   |",
+                "+--------+-------------------------------+---+-----+",
+                "| asia   | 1970-01-01T00:00:00.000000010 | 3 | 1.5 |",
+                "| asia   | 1970-01-01T00:00:00.000000030 | 4 |     |",
+                "| europe | 1970-01-01T00:00:00.000000020 | 2 |     |",
+                "+--------+-------------------------------+---+-----+",
+            ],
+            &batches
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_projection() {
+        let partition_provider = Arc::new(MockPartitionProvider::default().with_partition(
+            PartitionData::new(
+                PARTITION_ID,
+                PARTITION_KEY.into(),
+                NAMESPACE_ID,
+                Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                    NamespaceName::from("platanos")
+                })),
+                TABLE_ID,
+                Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                    TableName::from(TABLE_NAME)
+                })),
+                SortKeyState::Provided(None),
+            ),
+        ));
+
+        let table = TableData::new(
+            TABLE_ID,
+            DeferredLoad::new(Duration::from_secs(1), async {
+                TableName::from(TABLE_NAME)
+            }),
+            NAMESPACE_ID,
+            Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                NamespaceName::from("platanos")
+            })),
+            partition_provider,
+        );
+
+        let batch = lines_to_batches("bananas,region=asia v=1i,w=1.5,x=true 10", 0)
+            .unwrap()
+            .remove(TABLE_NAME)
+            .unwrap();
+        table
+            .buffer_table_write(SequenceNumber::new(1), batch, PARTITION_KEY.into())
+            .await
+            .expect("buffer op should succeed");
+
+        // Only the requested columns are returned, in the requested order,
+        // ignoring any column that does not exist.
+        let response = table
+            .query_exec(
+                NAMESPACE_ID,
+                TABLE_ID,
+                vec!["w".to_string(), "time".to_string(), "missing".to_string()],
+                None,
+                None,
+            )
+            .await
+            .expect("query should succeed");
+        let batches = QueryResponse::new(response)
+            .into_record_batches()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("query should succeed");
+
+        assert_batches_sorted_eq!(
+            [
+                "+-----+-------------------------------+",
+                "| w   | time                          |",
+                "+-----+-------------------------------+",
+                "| 1.5 | 1970-01-01T00:00:00.000000010 |",
+                "+-----+-------------------------------+",
+            ],
+            &batches
+        );
+
+        // A projection containing none of the buffered columns returns no
+        // partitions.
+        let response = table
+            .query_exec(
+                NAMESPACE_ID,
+                TABLE_ID,
+                vec!["missing".to_string()],
+                None,
+                None,
+            )
+            .await
+            .expect("query should succeed");
+        let batches = QueryResponse::new(response)
+            .into_record_batches()
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("query should succeed");
+        assert!(batches.is_empty());
+    }
 }
@@ -5,7 +5,7 @@ use data_types::TableId;
 use iox_catalog::interface::Catalog;
 
 use super::TableName;
-use crate::deferred_load::DeferredLoad;
+use crate::deferred_load::{DeferredLoad, DeferredLoadMetrics};
 
 /// An abstract provider of a [`DeferredLoad`] configured to fetch the
 /// [`TableName`] of the specified [`TableId`].
@@ -18,6 +18,7 @@ pub(crate) struct TableNameResolver {
     max_smear: Duration,
     catalog: Arc<dyn Catalog>,
     backoff_config: BackoffConfig,
+    metrics: DeferredLoadMetrics,
 }
 
 impl TableNameResolver {
@@ -25,11 +26,13 @@ impl TableNameResolver {
         max_smear: Duration,
         catalog: Arc<dyn Catalog>,
         backoff_config: BackoffConfig,
+        metrics: &metric::Registry,
     ) -> Self {
         Self {
             max_smear,
             catalog,
             backoff_config,
+            metrics: DeferredLoadMetrics::new(metrics, "table_name"),
         }
     }
 
@@ -61,9 +64,10 @@ impl TableNameResolver {
 
 impl TableNameProvider for TableNameResolver {
     fn for_table(&self, id: TableId) -> DeferredLoad<TableName> {
-        DeferredLoad::new(
+        DeferredLoad::new_with_metrics(
             self.max_smear,
             Self::fetch(id, Arc::clone(&self.catalog), self.backoff_config.clone()),
+            &self.metrics,
         )
     }
 }
@@ -126,6 +130,7 @@ mod tests {
             Duration::from_secs(10),
             Arc::clone(&catalog),
             backoff_config.clone(),
+            &metrics,
         ));
 
         let got = fetcher
@@ -1,8 +1,13 @@
 //! Generic deferred execution of arbitrary [`Future`]'s.
 
-use std::{fmt::Display, sync::Arc, time::Duration};
+use std::{
+    fmt::Display,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use futures::Future;
+use metric::{DurationHistogram, U64Counter, U64Gauge};
 use observability_deps::tracing::*;
 use parking_lot::Mutex;
 use rand::Rng;
@@ -19,6 +24,81 @@ use tokio::{
 /// the deferred value.
 pub(crate) const UNRESOLVED_DISPLAY_STRING: &str = "<unresolved>";
 
+/// Metrics recording the behaviour of a set of [`DeferredLoad`] instances,
+/// labelled with the `kind` of value they resolve.
+///
+/// These metrics describe how many values are pre-fetched in the background
+/// before they are needed, versus how many are loaded on demand (typically at
+/// persist time), allowing the maximum background wait to be tuned.
+#[derive(Debug, Clone)]
+pub(crate) struct DeferredLoadMetrics {
+    kind: &'static str,
+
+    /// The number of values resolved after the background timer fired.
+    resolved_background: U64Counter,
+    /// The number of values resolved after a demand call woke the loader.
+    resolved_demand: U64Counter,
+
+    /// The duration of the loader [`Future`] execution.
+    load_duration: DurationHistogram,
+    /// The duration callers of [`DeferredLoad::get()`] spent waiting for an
+    /// unresolved value.
+    demand_wait_duration: DurationHistogram,
+
+    /// The number of values not yet resolved.
+    outstanding: U64Gauge,
+}
+
+impl DeferredLoadMetrics {
+    /// Register the [`DeferredLoad`] metrics for values of `kind` in
+    /// `metrics`.
+    pub(crate) fn new(metrics: &metric::Registry, kind: &'static str) -> Self {
+        let resolved = metrics.register_metric::<U64Counter>(
+            "ingester_deferred_load_resolved",
+            "number of deferred values resolved, by the trigger of the load",
+        );
+        let load_duration = metrics.register_metric::<DurationHistogram>(
+            "ingester_deferred_load_duration",
+            "duration of the execution of the deferred value loader",
+        );
+        let demand_wait_duration = metrics.register_metric::<DurationHistogram>(
+            "ingester_deferred_load_demand_wait_duration",
+            "duration callers spent waiting for an unresolved deferred value",
+        );
+        let outstanding = metrics.register_metric::<U64Gauge>(
+            "ingester_deferred_load_outstanding",
+            "number of deferred values not yet resolved",
+        );
+
+        Self {
+            kind,
+            resolved_background: resolved.recorder(&[("kind", kind), ("trigger", "background")]),
+            resolved_demand: resolved.recorder(&[("kind", kind), ("trigger", "demand")]),
+            load_duration: load_duration.recorder(&[("kind", kind)]),
+            demand_wait_duration: demand_wait_duration.recorder(&[("kind", kind)]),
+            outstanding: outstanding.recorder(&[("kind", kind)]),
+        }
+    }
+}
+
+/// A guard owned by the background task of a [`DeferredLoad`], decrementing
+/// the outstanding gauge once the task completes or is aborted.
+#[derive(Debug)]
+struct OutstandingGuard(U64Gauge);
+
+impl OutstandingGuard {
+    fn new(gauge: U64Gauge) -> Self {
+        gauge.inc(1);
+        Self(gauge)
+    }
+}
+
+impl Drop for OutstandingGuard {
+    fn drop(&mut self) {
+        self.0.dec(1);
+    }
+}
+
 /// The states of a [`DeferredLoad`] instance.
 #[derive(Debug)]
 enum State<T> {
@@ -59,6 +139,7 @@ pub(crate) struct DeferredLoad<T> {
     /// and MUST always be [`Some`] once the mutex is released.
     value: Arc<Mutex<Option<State<T>>>>,
     handle: JoinHandle<()>,
+    metrics: Option<DeferredLoadMetrics>,
 }
 
 impl<T> std::fmt::Debug for DeferredLoad<T>
@@ -69,6 +150,7 @@ where
         f.debug_struct("DeferredLoad")
             .field("value", &self.value)
             .field("handle", &self.handle)
+            .field("metrics", &self.metrics)
             .finish()
     }
 }
@@ -164,6 +246,26 @@ where
     /// between `[0, max_wait)` before attempting to pre-fetch `T` by executing
     /// the provided future.
     pub(crate) fn new<F>(max_wait: Duration, loader: F) -> Self
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        Self::new_inner(max_wait, loader, None)
+    }
+
+    /// Construct a [`DeferredLoad`] as [`DeferredLoad::new()`] does,
+    /// recording the resolution of `T` in `metrics`.
+    pub(crate) fn new_with_metrics<F>(
+        max_wait: Duration,
+        loader: F,
+        metrics: &DeferredLoadMetrics,
+    ) -> Self
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        Self::new_inner(max_wait, loader, Some(metrics.clone()))
+    }
+
+    fn new_inner<F>(max_wait: Duration, loader: F, metrics: Option<DeferredLoadMetrics>) -> Self
     where
         F: Future<Output = T> + Send + 'static,
     {
@@ -181,21 +283,48 @@ where
         // before fetching the sort key.
         let handle = tokio::spawn({
             let value = Arc::clone(&value);
+            let metrics = metrics.clone();
+            let outstanding = metrics
+                .as_ref()
+                .map(|m| OutstandingGuard::new(m.outstanding.clone()));
             async move {
+                // Hold the guard for the lifetime of the task, so it is
+                // released whether the task completes or is aborted.
+                let _guard = outstanding;
+
                 // Sleep for the random duration, or until a demand call is
                 // made.
-                tokio::select! {
+                let demand = tokio::select! {
                     _ = tokio::time::sleep(wait_for) => {
                         trace!("timeout woke loader task");
+                        false
                     }
                     _ = rx => {
                         trace!("demand call woke loader task");
+                        true
                     }
-                }
+                };
 
                 // Execute the user-provided future to resolve the actual value.
+                let started = Instant::now();
                 let v = loader.await;
 
+                if let Some(metrics) = &metrics {
+                    let duration = started.elapsed();
+                    metrics.load_duration.record(duration);
+                    if demand {
+                        metrics.resolved_demand.inc(1);
+                    } else {
+                        metrics.resolved_background.inc(1);
+                    }
+                    debug!(
+                        kind = metrics.kind,
+                        demand,
+                        ?duration,
+                        "resolved deferred value"
+                    );
+                }
+
                 // And attempt to update the value container, if it hasn't
                 // already resolved.
                 //
@@ -230,7 +359,11 @@ where
             }
         });
 
-        Self { value, handle }
+        Self {
+            value,
+            handle,
+            metrics,
+        }
     }
 }
 
@@ -271,7 +404,11 @@ where
         };
 
         // Wait for the background task to complete resolving the value.
+        let started = Instant::now();
         waker.notified().await;
+        if let Some(metrics) = &self.metrics {
+            metrics.demand_wait_duration.record(started.elapsed());
+        }
 
         match self.value.lock().as_ref().unwrap() {
             State::Unresolved(_) | State::Loading(_) => unreachable!(),
@@ -293,6 +430,7 @@ mod tests {
     use std::sync::Arc;
 
     use futures::{executor::block_on, future, pin_mut};
+    use metric::{Attributes, Metric};
     use test_helpers::timeout::FutureTimeout;
 
     use super::*;
@@ -509,4 +647,84 @@ mod tests {
         let _ = d.get().with_timeout_panic(TIMEOUT).await;
         d.prefetch_now();
     }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let registry = metric::Registry::default();
+        let metrics = DeferredLoadMetrics::new(&registry, "bananas");
+
+        let outstanding = || {
+            registry
+                .get_instrument::<Metric<U64Gauge>>("ingester_deferred_load_outstanding")
+                .expect("failed to find metric")
+                .get_observer(&Attributes::from(&[("kind", "bananas")]))
+                .expect("failed to find attributes")
+                .fetch()
+        };
+        let resolved = |trigger| {
+            registry
+                .get_instrument::<Metric<U64Counter>>("ingester_deferred_load_resolved")
+                .expect("failed to find metric")
+                .get_observer(&Attributes::from(&[
+                    ("kind", "bananas"),
+                    ("trigger", trigger),
+                ]))
+                .expect("failed to find attributes")
+                .fetch()
+        };
+        let histogram = |name| {
+            registry
+                .get_instrument::<Metric<DurationHistogram>>(name)
+                .expect("failed to find metric")
+                .get_observer(&Attributes::from(&[("kind", "bananas")]))
+                .expect("failed to find attributes")
+                .fetch()
+                .sample_count()
+        };
+
+        let d = DeferredLoad::new_with_metrics(LONG_LONG_TIME, async { 42 }, &metrics);
+        assert_eq!(outstanding(), 1);
+
+        // A demand load is recorded, along with the time spent waiting for it.
+        assert_eq!(d.get().with_timeout_panic(TIMEOUT).await, 42);
+        assert_eq!(resolved("demand"), 1);
+        assert_eq!(resolved("background"), 0);
+        assert_eq!(histogram("ingester_deferred_load_duration"), 1);
+        assert_eq!(histogram("ingester_deferred_load_demand_wait_duration"), 1);
+
+        // The background task exits once the value is resolved.
+        async {
+            while outstanding() != 0 {
+                tokio::task::yield_now().await;
+            }
+        }
+        .with_timeout_panic(TIMEOUT)
+        .await;
+
+        // A background load does not record a demand wait.
+        let d = DeferredLoad::new_with_metrics(Duration::from_millis(1), async { 24 }, &metrics);
+        async {
+            while resolved("background") != 1 {
+                tokio::task::yield_now().await;
+            }
+        }
+        .with_timeout_panic(TIMEOUT)
+        .await;
+        assert_eq!(d.get().with_timeout_panic(TIMEOUT).await, 24);
+        assert_eq!(histogram("ingester_deferred_load_duration"), 2);
+        assert_eq!(histogram("ingester_deferred_load_demand_wait_duration"), 1);
+
+        // Dropping an unresolved value releases it from the outstanding count.
+        let d = DeferredLoad::new_with_metrics(LONG_LONG_TIME, async { 1 }, &metrics);
+        assert_eq!(outstanding(), 1);
+        drop(d);
+        async {
+            while outstanding() != 0 {
+                tokio::task::yield_now().await;
+            }
+        }
+        .with_timeout_panic(TIMEOUT)
+        .await;
+        assert_eq!(resolved("demand"), 1);
+    }
 }
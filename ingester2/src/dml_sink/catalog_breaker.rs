@@ -0,0 +1,506 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use dml::{DmlOperation, DmlWrite};
+use metric::{U64Counter, U64Gauge};
+use observability_deps::tracing::*;
+use parking_lot::Mutex;
+use tokio::time::Instant;
+
+use super::{DmlError, DmlSink};
+use crate::buffer_tree::BufferTree;
+
+/// The configuration of a [`CatalogBreakerSink`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CatalogBreakerConfig {
+    /// The maximum duration of the catalog requests of a write, after which
+    /// the catalog is considered unavailable for it.
+    pub(crate) timeout: Duration,
+
+    /// The number of consecutive timed out ops after which the breaker opens.
+    pub(crate) failure_threshold: usize,
+
+    /// How long the breaker stays open before a single op is let through to
+    /// probe the catalog.
+    pub(crate) open_duration: Duration,
+}
+
+impl Default for CatalogBreakerConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            failure_threshold: 3,
+            open_duration: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Ops are passed through, counting the consecutive failures.
+    Closed { failures: usize },
+    /// Ops are rejected until the deadline.
+    Open { until: Instant },
+    /// A single op is passed through to probe the catalog.
+    HalfOpen { probing: bool },
+}
+
+/// A [`DmlSink`] decorator that bounds the duration of the catalog requests
+/// in the write path, and rejects ops with [`DmlError::CatalogUnavailable`]
+/// without waiting while the catalog is considered unavailable.
+///
+/// A write to a partition not yet buffered in the [`BufferTree`] requires the
+/// partition to be resolved from the catalog, which is retried until it
+/// succeeds, so an unavailable catalog otherwise stalls the write
+/// indefinitely. These partitions are resolved into the [`BufferTree`] by this
+/// sink, bounded by [`CatalogBreakerConfig::timeout`], before the write is
+/// passed to the inner sink. A write that times out is rejected before it
+/// reaches the WAL, so it can safely be retried by the client. Writes to
+/// buffered partitions are always passed through.
+///
+/// Once [`CatalogBreakerConfig::failure_threshold`] consecutive writes time
+/// out, the breaker opens and rejects writes requiring the catalog for
+/// [`CatalogBreakerConfig::open_duration`], after which a single write is let
+/// through - closing the breaker if its partitions resolve in time, or
+/// opening it again otherwise.
+///
+/// Deletes record their tombstones in the catalog once committed to the WAL,
+/// so they are not bounded by the timeout, but are rejected while the breaker
+/// is not closed.
+#[derive(Debug)]
+pub(crate) struct CatalogBreakerSink<T> {
+    inner: T,
+    buffer: Arc<BufferTree>,
+    config: CatalogBreakerConfig,
+    state: Mutex<State>,
+
+    open: U64Gauge,
+    timeouts: U64Counter,
+    rejected: U64Counter,
+}
+
+impl<T> CatalogBreakerSink<T> {
+    /// Initialise a new [`CatalogBreakerSink`] that passes ops through to `T`,
+    /// which buffers them in `buffer`.
+    pub(crate) fn new(
+        inner: T,
+        buffer: Arc<BufferTree>,
+        config: CatalogBreakerConfig,
+        metrics: &metric::Registry,
+    ) -> Self {
+        let open = metrics
+            .register_metric::<U64Gauge>(
+                "ingester_catalog_circuit_open",
+                "set to 1 while writes requiring the catalog are rejected",
+            )
+            .recorder(&[]);
+        let timeouts = metrics
+            .register_metric::<U64Counter>(
+                "ingester_catalog_write_timeout",
+                "number of writes requiring the catalog that exceeded the timeout",
+            )
+            .recorder(&[]);
+        let rejected = metrics
+            .register_metric::<U64Counter>(
+                "ingester_catalog_unavailable_rejected",
+                "number of writes rejected while the catalog circuit is open",
+            )
+            .recorder(&[]);
+
+        Self {
+            inner,
+            buffer,
+            config,
+            state: Mutex::new(State::Closed { failures: 0 }),
+            open,
+            timeouts,
+            rejected,
+        }
+    }
+
+    /// Returns true if buffering `w` requires a partition to be resolved from
+    /// the catalog.
+    fn needs_catalog(&self, w: &DmlWrite) -> bool {
+        let namespace = match self.buffer.namespace(w.namespace_id()) {
+            Some(v) => v,
+            None => return true,
+        };
+
+        w.tables().any(|(table_id, _)| {
+            namespace
+                .table(*table_id)
+                .and_then(|t| t.get_partition_by_key(w.partition_key()))
+                .is_none()
+        })
+    }
+
+    /// Returns true if the breaker is open, or probing the catalog.
+    fn is_open(&self) -> bool {
+        !matches!(*self.state.lock(), State::Closed { .. })
+    }
+
+    /// Acquire an [`Attempt`] to resolve a write from the catalog, or return
+    /// [`None`] if the breaker is open.
+    fn try_acquire(&self) -> Option<Attempt<'_, T>> {
+        let mut state = self.state.lock();
+        let probe = match *state {
+            State::Closed { .. } => false,
+            State::Open { until } if Instant::now() >= until => {
+                info!("probing catalog availability");
+                *state = State::HalfOpen { probing: true };
+                true
+            }
+            State::HalfOpen { probing: false } => {
+                *state = State::HalfOpen { probing: true };
+                true
+            }
+            State::Open { .. } | State::HalfOpen { probing: true } => return None,
+        };
+
+        Some(Attempt {
+            sink: self,
+            probe,
+            finished: false,
+        })
+    }
+
+    /// Record the outcome of resolving a write from the catalog.
+    fn record(&self, success: bool) {
+        let mut state = self.state.lock();
+        let next = match (*state, success) {
+            (State::Closed { .. }, true) => State::Closed { failures: 0 },
+            (_, true) => {
+                info!("catalog available, closing circuit");
+                State::Closed { failures: 0 }
+            }
+            (State::Closed { failures }, false) if failures + 1 < self.config.failure_threshold => {
+                State::Closed {
+                    failures: failures + 1,
+                }
+            }
+            (State::Open { until }, false) => State::Open { until },
+            (_, false) => {
+                warn!(
+                    open_duration = ?self.config.open_duration,
+                    "catalog unavailable, rejecting writes that require it"
+                );
+                State::Open {
+                    until: Instant::now() + self.config.open_duration,
+                }
+            }
+        };
+
+        self.open.set(match next {
+            State::Closed { .. } => 0,
+            _ => 1,
+        });
+        *state = next;
+    }
+}
+
+/// A write being resolved from the catalog, recording its outcome in the
+/// breaker.
+///
+/// If dropped without an outcome, a probe is released without changing the
+/// state of the breaker.
+#[derive(Debug)]
+struct Attempt<'a, T> {
+    sink: &'a CatalogBreakerSink<T>,
+    probe: bool,
+    finished: bool,
+}
+
+impl<'a, T> Attempt<'a, T> {
+    fn finish(mut self, success: bool) {
+        self.finished = true;
+        self.sink.record(success);
+    }
+}
+
+impl<'a, T> Drop for Attempt<'a, T> {
+    fn drop(&mut self) {
+        if self.probe && !self.finished {
+            let mut state = self.sink.state.lock();
+            if *state == (State::HalfOpen { probing: true }) {
+                *state = State::HalfOpen { probing: false };
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<T> DmlSink for CatalogBreakerSink<T>
+where
+    T: DmlSink,
+{
+    type Error = DmlError;
+
+    async fn apply(&self, op: DmlOperation) -> Result<(), Self::Error> {
+        let write = match &op {
+            DmlOperation::Write(w) if self.needs_catalog(w) => w,
+            DmlOperation::Write(_) => return self.inner.apply(op).await.map_err(Into::into),
+            DmlOperation::Delete(_) => {
+                if self.is_open() {
+                    self.rejected.inc(1);
+                    return Err(DmlError::CatalogUnavailable);
+                }
+                return self.inner.apply(op).await.map_err(Into::into);
+            }
+        };
+
+        let attempt = match self.try_acquire() {
+            Some(v) => v,
+            None => {
+                self.rejected.inc(1);
+                return Err(DmlError::CatalogUnavailable);
+            }
+        };
+
+        // Only the catalog requests are bounded - once resolved, the write is
+        // committed to the WAL & buffered without the catalog.
+        let resolve = self.buffer.resolve_partitions(write);
+        if tokio::time::timeout(self.config.timeout, resolve)
+            .await
+            .is_err()
+        {
+            self.timeouts.inc(1);
+            attempt.finish(false);
+            return Err(DmlError::CatalogUnavailable);
+        }
+        attempt.finish(true);
+
+        self.inner.apply(op).await.map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use assert_matches::assert_matches;
+    use data_types::{NamespaceId, PartitionId, PartitionKey, TableId};
+    use metric::Metric;
+
+    use super::*;
+    use crate::{
+        buffer_tree::{
+            namespace::{name_resolver::mock::MockNamespaceNameProvider, NamespaceName},
+            partition::{resolver::PartitionProvider, PartitionData, SortKeyState},
+            table::{name_resolver::mock::MockTableNameProvider, TableName},
+        },
+        deferred_load::DeferredLoad,
+        test_util::{make_delete_op, make_write_op},
+    };
+
+    const TIMEOUT: Duration = Duration::from_millis(50);
+    const OPEN_DURATION: Duration = Duration::from_millis(100);
+
+    /// A [`PartitionProvider`] that takes far longer than [`TIMEOUT`] to
+    /// resolve each partition while `slow` is true.
+    #[derive(Debug, Default)]
+    struct SlowPartitionProvider {
+        slow: Mutex<bool>,
+    }
+
+    #[async_trait]
+    impl PartitionProvider for SlowPartitionProvider {
+        async fn get_partition(
+            &self,
+            partition_key: PartitionKey,
+            namespace_id: NamespaceId,
+            namespace_name: Arc<DeferredLoad<NamespaceName>>,
+            table_id: TableId,
+            table_name: Arc<DeferredLoad<TableName>>,
+        ) -> PartitionData {
+            let slow = *self.slow.lock();
+            if slow {
+                tokio::time::sleep(TIMEOUT * 10).await;
+            }
+            PartitionData::new(
+                PartitionId::new(1),
+                partition_key,
+                namespace_id,
+                namespace_name,
+                table_id,
+                table_name,
+                SortKeyState::Provided(None),
+            )
+        }
+    }
+
+    /// A [`DmlSink`] counting the ops applied to it, which takes far longer
+    /// than [`TIMEOUT`] to apply each op while `slow` is true.
+    #[derive(Debug, Default)]
+    struct SlowSink {
+        slow: Mutex<bool>,
+        applied: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl DmlSink for SlowSink {
+        type Error = DmlError;
+
+        async fn apply(&self, _op: DmlOperation) -> Result<(), DmlError> {
+            let slow = *self.slow.lock();
+            if slow {
+                tokio::time::sleep(TIMEOUT * 10).await;
+            }
+            self.applied.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    /// Initialise a [`CatalogBreakerSink`] over an empty [`BufferTree`],
+    /// resolving partitions with `partitions`.
+    fn new_sink(
+        inner: Arc<SlowSink>,
+        partitions: Arc<SlowPartitionProvider>,
+        failure_threshold: usize,
+        metrics: &metric::Registry,
+    ) -> CatalogBreakerSink<Arc<SlowSink>> {
+        let buffer = Arc::new(BufferTree::new(
+            Arc::new(MockNamespaceNameProvider::default()),
+            Arc::new(MockTableNameProvider::new("bananas")),
+            partitions,
+            Default::default(),
+        ));
+
+        CatalogBreakerSink::new(
+            inner,
+            buffer,
+            CatalogBreakerConfig {
+                timeout: TIMEOUT,
+                failure_threshold,
+                open_duration: OPEN_DURATION,
+            },
+            metrics,
+        )
+    }
+
+    /// A write to the partition `partition_key`, which requires the catalog
+    /// until the partition is buffered.
+    fn write(partition_key: &str) -> DmlOperation {
+        DmlOperation::Write(make_write_op(
+            &PartitionKey::from(partition_key),
+            NamespaceId::new(1),
+            "bananas",
+            TableId::new(2),
+            42,
+            "bananas,region=asia v=1i 1",
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_catalog_breaker() {
+        let metrics = metric::Registry::default();
+        let inner = Arc::new(SlowSink::default());
+        let partitions = Arc::new(SlowPartitionProvider::default());
+        let sink = new_sink(Arc::clone(&inner), Arc::clone(&partitions), 2, &metrics);
+
+        sink.apply(write("p1")).await.expect("catalog available");
+        assert_eq!(inner.applied.load(Ordering::SeqCst), 1);
+
+        // Writes time out until the failure threshold is reached, after which
+        // they are rejected immediately.
+        *partitions.slow.lock() = true;
+        for key in ["p2", "p3"] {
+            assert_matches!(
+                sink.apply(write(key)).await,
+                Err(DmlError::CatalogUnavailable)
+            );
+        }
+        let started = Instant::now();
+        assert_matches!(
+            sink.apply(write("p4")).await,
+            Err(DmlError::CatalogUnavailable)
+        );
+        assert!(started.elapsed() < TIMEOUT);
+
+        // None of the rejected writes were passed on to be committed to the
+        // WAL.
+        assert_eq!(inner.applied.load(Ordering::SeqCst), 1);
+
+        // Writes to buffered partitions do not require the catalog.
+        sink.apply(write("p1"))
+            .await
+            .expect("partition is buffered");
+        assert_eq!(inner.applied.load(Ordering::SeqCst), 2);
+
+        let get = |name: &'static str| {
+            metrics
+                .get_instrument::<Metric<U64Counter>>(name)
+                .expect("metric not registered")
+                .get_observer(&metric::Attributes::from(&[]))
+                .expect("no metric observer")
+                .fetch()
+        };
+        assert_eq!(get("ingester_catalog_write_timeout"), 2);
+        assert_eq!(get("ingester_catalog_unavailable_rejected"), 1);
+
+        // Once open for long enough, a successful probe closes the breaker.
+        *partitions.slow.lock() = false;
+        tokio::time::sleep(OPEN_DURATION).await;
+        sink.apply(write("p2")).await.expect("probe should succeed");
+        sink.apply(write("p3"))
+            .await
+            .expect("breaker should be closed");
+        assert_eq!(*sink.state.lock(), State::Closed { failures: 0 });
+        assert_eq!(inner.applied.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_failed_probe_reopens() {
+        let inner = Arc::new(SlowSink::default());
+        let partitions = Arc::new(SlowPartitionProvider::default());
+        *partitions.slow.lock() = true;
+        let sink = new_sink(
+            Arc::clone(&inner),
+            Arc::clone(&partitions),
+            1,
+            &metric::Registry::default(),
+        );
+
+        assert_matches!(
+            sink.apply(write("p1")).await,
+            Err(DmlError::CatalogUnavailable)
+        );
+        assert_matches!(*sink.state.lock(), State::Open { .. });
+
+        // Deletes are rejected while the breaker is open.
+        let delete = DmlOperation::Delete(make_delete_op(NamespaceId::new(1), None, 43));
+        assert_matches!(sink.apply(delete).await, Err(DmlError::CatalogUnavailable));
+
+        // The probe times out, opening the breaker again.
+        tokio::time::sleep(OPEN_DURATION).await;
+        assert_matches!(
+            sink.apply(write("p1")).await,
+            Err(DmlError::CatalogUnavailable)
+        );
+        assert_matches!(*sink.state.lock(), State::Open { .. });
+        assert_eq!(inner.applied.load(Ordering::SeqCst), 0);
+    }
+
+    /// Applying a write after its partitions are resolved is not bounded by
+    /// the timeout, as it may already be committed to the WAL.
+    #[tokio::test]
+    async fn test_slow_inner_not_timed_out() {
+        let metrics = metric::Registry::default();
+        let inner = Arc::new(SlowSink::default());
+        *inner.slow.lock() = true;
+        let sink = new_sink(
+            Arc::clone(&inner),
+            Arc::new(SlowPartitionProvider::default()),
+            1,
+            &metrics,
+        );
+
+        sink.apply(write("p1")).await.expect("write should succeed");
+        assert_eq!(inner.applied.load(Ordering::SeqCst), 1);
+        assert_eq!(*sink.state.lock(), State::Closed { failures: 0 });
+
+        // Deletes are passed through while the breaker is closed.
+        let delete = DmlOperation::Delete(make_delete_op(NamespaceId::new(1), None, 43));
+        sink.apply(delete).await.expect("delete should succeed");
+        assert_eq!(inner.applied.load(Ordering::SeqCst), 2);
+    }
+}
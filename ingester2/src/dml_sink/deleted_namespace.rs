@@ -0,0 +1,95 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use dml::DmlOperation;
+
+use super::{DmlError, DmlSink};
+use crate::buffer_tree::BufferTree;
+
+/// A [`DmlSink`] decorator that rejects ops for namespaces removed from the
+/// [`BufferTree`] with [`DmlError::NamespaceDeleted`], before they are
+/// committed to the WAL.
+#[derive(Debug)]
+pub(crate) struct DeletedNamespaceSink<T> {
+    inner: T,
+    buffer: Arc<BufferTree>,
+}
+
+impl<T> DeletedNamespaceSink<T> {
+    /// Initialise a new [`DeletedNamespaceSink`] that passes ops for the
+    /// namespaces not removed from `buffer` through to `T`.
+    pub(crate) fn new(inner: T, buffer: Arc<BufferTree>) -> Self {
+        Self { inner, buffer }
+    }
+}
+
+#[async_trait]
+impl<T> DmlSink for DeletedNamespaceSink<T>
+where
+    T: DmlSink,
+{
+    type Error = DmlError;
+
+    async fn apply(&self, op: DmlOperation) -> Result<(), Self::Error> {
+        let namespace_id = op.namespace_id();
+        if self.buffer.is_namespace_deleted(namespace_id) {
+            return Err(DmlError::NamespaceDeleted(namespace_id));
+        }
+        self.inner.apply(op).await.map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use data_types::{NamespaceId, PartitionKey, TableId};
+
+    use super::*;
+    use crate::{
+        buffer_tree::{
+            namespace::name_resolver::mock::MockNamespaceNameProvider,
+            partition::resolver::mock::MockPartitionProvider,
+            table::name_resolver::mock::MockTableNameProvider,
+        },
+        dml_sink::mock_sink::MockDmlSink,
+        test_util::make_write_op,
+    };
+
+    #[tokio::test]
+    async fn test_deleted_namespace_sink() {
+        let buffer = Arc::new(BufferTree::new(
+            Arc::new(MockNamespaceNameProvider::default()),
+            Arc::new(MockTableNameProvider::new("bananas")),
+            Arc::new(MockPartitionProvider::default()),
+            Default::default(),
+        ));
+        let inner = Arc::new(MockDmlSink::default().with_apply_return([Ok(())]));
+        let sink = DeletedNamespaceSink::new(Arc::clone(&inner), Arc::clone(&buffer));
+
+        let op = |namespace_id| {
+            DmlOperation::Write(make_write_op(
+                &PartitionKey::from("p1"),
+                namespace_id,
+                "bananas",
+                TableId::new(2),
+                42,
+                "bananas,region=asia v=1i 1",
+            ))
+        };
+
+        buffer.remove_namespace(NamespaceId::new(1));
+
+        assert_matches!(
+            sink.apply(op(NamespaceId::new(1))).await,
+            Err(DmlError::NamespaceDeleted(id)) => {
+                assert_eq!(id, NamespaceId::new(1));
+            }
+        );
+        assert!(inner.get_calls().is_empty());
+
+        sink.apply(op(NamespaceId::new(2)))
+            .await
+            .expect("ops for other namespaces should be accepted");
+        assert_eq!(inner.get_calls().len(), 1);
+    }
+}
@@ -0,0 +1,78 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use dml::DmlOperation;
+
+use super::{DmlError, DmlSink, SequenceTracker};
+
+/// A [`DmlSink`] decorator that marks the sequence number of each op as
+/// durable in a [`SequenceTracker`], before passing it to the inner
+/// [`DmlSink`].
+///
+/// This decorator must be wrapped by the [`WalSink`], so every op it observes
+/// is committed to the WAL. The op is no longer reported as durable once the
+/// [`SequenceGapSink`] sharing the [`SequenceTracker`] completes it.
+///
+/// [`WalSink`]: crate::wal::wal_sink::WalSink
+/// [`SequenceGapSink`]: super::SequenceGapSink
+#[derive(Debug)]
+pub(crate) struct DurableSink<T> {
+    inner: T,
+    tracker: Arc<SequenceTracker>,
+}
+
+impl<T> DurableSink<T> {
+    /// Initialise a new [`DurableSink`] that passes ops through to `T`,
+    /// marking them as durable in `tracker`.
+    pub(crate) fn new(inner: T, tracker: Arc<SequenceTracker>) -> Self {
+        Self { inner, tracker }
+    }
+}
+
+#[async_trait]
+impl<T> DmlSink for DurableSink<T>
+where
+    T: DmlSink,
+{
+    type Error = DmlError;
+
+    async fn apply(&self, op: DmlOperation) -> Result<(), Self::Error> {
+        if let Some(v) = op.meta().sequence() {
+            self.tracker.mark_durable(
+                u64::try_from(v.sequence_number.get()).expect("sequence number overflow"),
+            );
+        }
+
+        self.inner.apply(op).await.map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use data_types::{NamespaceId, PartitionKey, TableId};
+
+    use super::*;
+    use crate::{dml_sink::mock_sink::MockDmlSink, test_util::make_write_op};
+
+    #[tokio::test]
+    async fn test_durable_sink() {
+        let tracker = Arc::new(SequenceTracker::default());
+        let inner = Arc::new(MockDmlSink::default().with_apply_return([Ok(())]));
+        let sink = DurableSink::new(Arc::clone(&inner), Arc::clone(&tracker));
+
+        sink.apply(DmlOperation::Write(make_write_op(
+            &PartitionKey::from("p1"),
+            NamespaceId::new(1),
+            "bananas",
+            TableId::new(2),
+            42,
+            "bananas,region=asia v=1i 1",
+        )))
+        .await
+        .expect("apply should succeed");
+
+        assert_eq!(inner.get_calls().len(), 1);
+        assert!(tracker.is_durable(42));
+        assert!(!tracker.is_completed(42));
+    }
+}
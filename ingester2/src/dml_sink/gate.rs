@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use dml::DmlOperation;
+use tokio::sync::RwLock;
+
+use super::{DmlError, DmlSink};
+
+/// A switch that stops the [`GatedSink`] instances created from it accepting
+/// [`DmlOperation`], used to stop ingest during a graceful shutdown.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct WriteGate {
+    /// Set to true once closed.
+    ///
+    /// Each op holds a read lock while it is applied, so acquiring the write
+    /// lock waits for all in-flight ops to complete.
+    closed: Arc<RwLock<bool>>,
+}
+
+impl WriteGate {
+    /// Wrap `inner` in a [`GatedSink`] controlled by this gate.
+    pub(crate) fn sink<T>(&self, inner: T) -> GatedSink<T> {
+        GatedSink {
+            inner,
+            gate: self.clone(),
+        }
+    }
+
+    /// Reject all subsequent ops, resolving once all in-flight ops have been
+    /// applied.
+    pub(crate) async fn close(&self) {
+        *self.closed.write().await = true;
+    }
+}
+
+/// A [`DmlSink`] decorator that passes ops through to the inner [`DmlSink`]
+/// until its [`WriteGate`] is closed, after which it returns
+/// [`DmlError::ShuttingDown`].
+#[derive(Debug)]
+pub(crate) struct GatedSink<T> {
+    inner: T,
+    gate: WriteGate,
+}
+
+#[async_trait]
+impl<T> DmlSink for GatedSink<T>
+where
+    T: DmlSink,
+{
+    type Error = DmlError;
+
+    async fn apply(&self, op: DmlOperation) -> Result<(), Self::Error> {
+        // Hold the read lock until the op is applied, so closing the gate
+        // waits for it.
+        let closed = self.gate.closed.read().await;
+        if *closed {
+            return Err(DmlError::ShuttingDown);
+        }
+
+        self.inner.apply(op).await.map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use assert_matches::assert_matches;
+    use data_types::{NamespaceId, PartitionKey, TableId};
+    use futures::FutureExt;
+
+    use super::*;
+    use crate::{dml_sink::mock_sink::MockDmlSink, test_util::make_write_op};
+
+    fn op() -> DmlOperation {
+        DmlOperation::Write(make_write_op(
+            &PartitionKey::from("p1"),
+            NamespaceId::new(1),
+            "bananas",
+            TableId::new(2),
+            42,
+            "bananas,region=asia v=1i 1",
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_gate() {
+        let inner = Arc::new(MockDmlSink::default().with_apply_return([Ok(())]));
+        let gate = WriteGate::default();
+        let sink = gate.sink(Arc::clone(&inner));
+
+        sink.apply(op()).await.expect("open gate should accept ops");
+
+        gate.close().await;
+        assert_matches!(sink.apply(op()).await, Err(DmlError::ShuttingDown));
+
+        // Only the op applied before closing reached the inner sink.
+        assert_eq!(inner.get_calls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_close_waits_for_in_flight() {
+        let gate = WriteGate::default();
+
+        // Simulate an in-flight op.
+        let in_flight = gate.closed.read().await;
+
+        let close = gate.close();
+        tokio::pin!(close);
+        assert!((&mut close).now_or_never().is_none());
+
+        drop(in_flight);
+        tokio::time::timeout(Duration::from_secs(5), close)
+            .await
+            .expect("close should complete once ops are applied");
+    }
+}
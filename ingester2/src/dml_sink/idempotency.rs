@@ -0,0 +1,189 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    num::NonZeroUsize,
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use data_types::NamespaceId;
+use dml::DmlOperation;
+use observability_deps::tracing::*;
+use parking_lot::Mutex;
+
+use super::{DmlError, DmlSink};
+
+/// The idempotency key of an op, scoped to its namespace.
+type Key = (NamespaceId, String);
+
+/// The most recently seen idempotency keys, and whether the op they identify
+/// has been applied.
+#[derive(Debug, Default)]
+struct SeenKeys {
+    /// Each key maps to a lock held while its op is being applied, and
+    /// protecting the flag recording if it has been applied successfully.
+    keys: HashMap<Key, Arc<tokio::sync::Mutex<bool>>>,
+
+    /// The keys in `keys`, in insertion order.
+    order: VecDeque<Key>,
+}
+
+/// A [`DmlSink`] decorator that drops a [`DmlOperation`] carrying an
+/// idempotency key (see [`DmlMeta::idempotency_key()`]) that has already been
+/// applied by the inner [`DmlSink`], returning success without applying it
+/// again.
+///
+/// This allows a client to safely retry an op after an ambiguous failure (such
+/// as a timeout) without the risk of applying it twice. Concurrent ops with
+/// the same key are applied at most once - the duplicates wait for the first
+/// to complete, and are applied only if it failed.
+///
+/// Only the most recent `capacity` keys are remembered, and the keys do not
+/// survive a restart of the ingester. Ops without an idempotency key are
+/// always passed through.
+///
+/// [`DmlMeta::idempotency_key()`]: dml::DmlMeta::idempotency_key
+#[derive(Debug)]
+pub(crate) struct IdempotencySink<T> {
+    inner: T,
+    capacity: Option<NonZeroUsize>,
+    seen: Mutex<SeenKeys>,
+}
+
+impl<T> IdempotencySink<T> {
+    /// Initialise a new [`IdempotencySink`] remembering up to `capacity` keys
+    /// of the ops passed through to `T`.
+    ///
+    /// If `capacity` is [`None`], all ops are passed through.
+    pub(crate) fn new(inner: T, capacity: Option<NonZeroUsize>) -> Self {
+        Self {
+            inner,
+            capacity,
+            seen: Default::default(),
+        }
+    }
+
+    /// Return the lock for `key`, remembering it (and evicting the oldest key
+    /// if at `capacity`) if not already seen.
+    fn entry(&self, key: Key, capacity: NonZeroUsize) -> Arc<tokio::sync::Mutex<bool>> {
+        let mut seen = self.seen.lock();
+        if let Some(v) = seen.keys.get(&key) {
+            return Arc::clone(v);
+        }
+
+        if seen.order.len() >= capacity.get() {
+            if let Some(oldest) = seen.order.pop_front() {
+                seen.keys.remove(&oldest);
+            }
+        }
+
+        let entry = Arc::new(tokio::sync::Mutex::new(false));
+        seen.keys.insert(key.clone(), Arc::clone(&entry));
+        seen.order.push_back(key);
+        entry
+    }
+}
+
+#[async_trait]
+impl<T> DmlSink for IdempotencySink<T>
+where
+    T: DmlSink,
+{
+    type Error = DmlError;
+
+    async fn apply(&self, op: DmlOperation) -> Result<(), Self::Error> {
+        let (capacity, key) = match (self.capacity, op.meta().idempotency_key()) {
+            (Some(c), Some(k)) => (c, k.to_string()),
+            _ => return self.inner.apply(op).await.map_err(Into::into),
+        };
+
+        let namespace_id = op.namespace_id();
+        let entry = self.entry((namespace_id, key), capacity);
+
+        // Serialise ops with the same key.
+        let mut applied = entry.lock().await;
+        if *applied {
+            debug!(
+                %namespace_id,
+                key = op.meta().idempotency_key(),
+                "dropping duplicate op"
+            );
+            return Ok(());
+        }
+
+        self.inner.apply(op).await.map_err(Into::into)?;
+        *applied = true;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use data_types::{PartitionKey, TableId};
+
+    use super::*;
+    use crate::{dml_sink::mock_sink::MockDmlSink, test_util::make_write_op};
+
+    fn write(key: Option<&str>) -> DmlOperation {
+        let mut w = make_write_op(
+            &PartitionKey::from("p1"),
+            NamespaceId::new(1),
+            "bananas",
+            TableId::new(2),
+            42,
+            "bananas,region=asia v=1i 1",
+        );
+        if let Some(key) = key {
+            w.set_meta(w.meta().clone().with_idempotency_key(key));
+        }
+        DmlOperation::Write(w)
+    }
+
+    #[tokio::test]
+    async fn test_idempotency() {
+        let inner = Arc::new(MockDmlSink::default().with_apply_return([
+            Ok(()),
+            Err(DmlError::ShuttingDown),
+            Ok(()),
+            Ok(()),
+            Ok(()),
+            Ok(()),
+            Ok(()),
+        ]));
+        let sink = IdempotencySink::new(Arc::clone(&inner), NonZeroUsize::new(2));
+
+        // A duplicate of an applied op is dropped.
+        sink.apply(write(Some("a"))).await.unwrap();
+        sink.apply(write(Some("a"))).await.unwrap();
+        assert_eq!(inner.get_calls().len(), 1);
+
+        // A retry of a failed op is applied.
+        assert_matches!(
+            sink.apply(write(Some("b"))).await,
+            Err(DmlError::ShuttingDown)
+        );
+        sink.apply(write(Some("b"))).await.unwrap();
+        assert_eq!(inner.get_calls().len(), 3);
+
+        // Ops without a key are always applied.
+        sink.apply(write(None)).await.unwrap();
+        sink.apply(write(None)).await.unwrap();
+        assert_eq!(inner.get_calls().len(), 5);
+
+        // Inserting "c" evicts "a", the oldest key.
+        sink.apply(write(Some("c"))).await.unwrap();
+        sink.apply(write(Some("a"))).await.unwrap();
+        assert_eq!(inner.get_calls().len(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_disabled() {
+        let inner = Arc::new(MockDmlSink::default().with_apply_return([Ok(()), Ok(())]));
+        let sink = IdempotencySink::new(Arc::clone(&inner), None);
+
+        sink.apply(write(Some("a"))).await.unwrap();
+        sink.apply(write(Some("a"))).await.unwrap();
+        assert_eq!(inner.get_calls().len(), 2);
+    }
+}
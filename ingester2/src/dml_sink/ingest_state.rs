@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use dml::DmlOperation;
+
+use super::{DmlError, DmlSink};
+use crate::ingest_state::IngestState;
+
+/// A [`DmlSink`] decorator that rejects writes with [`DmlError::Overloaded`]
+/// while any condition is set in an [`IngestState`], applying backpressure to
+/// clients until the ingester can make durable progress on them.
+///
+/// If constructed with [`IngestStateSink::new_blocking()`], writes instead
+/// wait for all conditions to clear before being passed through - this is used
+/// to apply backpressure to the WAL replay, which cannot be retried later.
+///
+/// Deletes are always passed through, as they never increase the memory used
+/// or the persist workload.
+#[derive(Debug)]
+pub(crate) struct IngestStateSink<T> {
+    inner: T,
+    state: Arc<IngestState>,
+    blocking: bool,
+}
+
+impl<T> IngestStateSink<T> {
+    /// Initialise a new [`IngestStateSink`] that passes ops through to `T`
+    /// while no condition is set in `state`.
+    pub(crate) fn new(inner: T, state: Arc<IngestState>) -> Self {
+        Self {
+            inner,
+            state,
+            blocking: false,
+        }
+    }
+
+    /// Initialise a new [`IngestStateSink`] that passes ops through to `T`,
+    /// waiting for any condition set in `state` to clear first.
+    pub(crate) fn new_blocking(inner: T, state: Arc<IngestState>) -> Self {
+        Self {
+            inner,
+            state,
+            blocking: true,
+        }
+    }
+}
+
+#[async_trait]
+impl<T> DmlSink for IngestStateSink<T>
+where
+    T: DmlSink,
+{
+    type Error = DmlError;
+
+    async fn apply(&self, op: DmlOperation) -> Result<(), Self::Error> {
+        if matches!(op, DmlOperation::Write(_)) {
+            if self.blocking {
+                self.state.wait_clear().await;
+            } else {
+                self.state.read()?;
+            }
+        }
+
+        self.inner.apply(op).await.map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use assert_matches::assert_matches;
+    use data_types::{NamespaceId, PartitionKey, TableId};
+    use futures::FutureExt;
+
+    use super::*;
+    use crate::{
+        dml_sink::mock_sink::MockDmlSink,
+        ingest_state::IngestStateError,
+        test_util::{make_delete_op, make_write_op},
+    };
+
+    fn write() -> DmlOperation {
+        DmlOperation::Write(make_write_op(
+            &PartitionKey::from("p1"),
+            NamespaceId::new(1),
+            "bananas",
+            TableId::new(2),
+            42,
+            "bananas,region=asia v=1i 1",
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_ingest_state_sink() {
+        let metrics = metric::Registry::default();
+        let state = Arc::new(IngestState::new(&metrics));
+
+        let inner = Arc::new(MockDmlSink::default().with_apply_return([Ok(()), Ok(()), Ok(())]));
+        let sink = IngestStateSink::new(Arc::clone(&inner), Arc::clone(&state));
+
+        sink.apply(write()).await.expect("write should be accepted");
+
+        state.set(IngestStateError::MemoryLimit);
+        assert_matches!(
+            sink.apply(write()).await,
+            Err(DmlError::Overloaded(IngestStateError::MemoryLimit))
+        );
+
+        // Deletes are accepted.
+        sink.apply(DmlOperation::Delete(make_delete_op(
+            NamespaceId::new(1),
+            None,
+            43,
+        )))
+        .await
+        .expect("delete should be accepted");
+
+        state.unset(IngestStateError::MemoryLimit);
+        sink.apply(write()).await.expect("write should be accepted");
+
+        assert_matches!(
+            *inner.get_calls(),
+            [
+                DmlOperation::Write(_),
+                DmlOperation::Delete(_),
+                DmlOperation::Write(_)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_blocking_ingest_state_sink() {
+        let metrics = metric::Registry::default();
+        let state = Arc::new(IngestState::new(&metrics));
+
+        let inner = Arc::new(MockDmlSink::default().with_apply_return([Ok(())]));
+        let sink = IngestStateSink::new_blocking(Arc::clone(&inner), Arc::clone(&state));
+
+        state.set(IngestStateError::MemoryLimit);
+
+        let apply = sink.apply(write());
+        tokio::pin!(apply);
+        assert!((&mut apply).now_or_never().is_none());
+        assert!(inner.get_calls().is_empty());
+
+        state.unset(IngestStateError::MemoryLimit);
+        tokio::time::timeout(Duration::from_secs(5), apply)
+            .await
+            .expect("write should be applied once the condition clears")
+            .expect("write should be accepted");
+        assert_eq!(inner.get_calls().len(), 1);
+    }
+}
@@ -1,5 +1,47 @@
 mod r#trait;
 pub(crate) use r#trait::*;
 
+mod catalog_breaker;
+pub(crate) use catalog_breaker::*;
+
+mod deleted_namespace;
+pub(crate) use deleted_namespace::*;
+
+mod durable;
+pub(crate) use durable::*;
+
+mod gate;
+pub(crate) use gate::*;
+
+mod idempotency;
+pub(crate) use idempotency::*;
+
+mod ingest_state;
+pub(crate) use ingest_state::*;
+
+mod partition_cap;
+pub(crate) use partition_cap::*;
+
+mod rate_limit;
+pub(crate) use rate_limit::*;
+
+mod readiness;
+pub(crate) use readiness::*;
+
+mod replication;
+pub(crate) use replication::*;
+
+mod schema_validation;
+pub(crate) use schema_validation::*;
+
+mod sequence_gap;
+pub(crate) use sequence_gap::*;
+
+mod tombstone;
+pub(crate) use tombstone::*;
+
+mod tracing;
+pub(crate) use tracing::*;
+
 #[cfg(test)]
 pub(crate) mod mock_sink;
@@ -0,0 +1,267 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use data_types::TableId;
+use dml::DmlOperation;
+use metric::U64Counter;
+use observability_deps::tracing::*;
+use trace::span::{SpanExt, SpanRecorder};
+
+use super::{DmlError, DmlSink};
+use crate::{
+    buffer_tree::BufferTree,
+    persist::{handle::PersistHandle, hot_partition::HotPartitionThresholds},
+};
+
+/// A [`DmlSink`] decorator that enforces per-partition caps on the buffered
+/// rows and bytes, by enqueuing a partition for persistence as soon as a write
+/// applied by the inner [`DmlSink`] takes it over a cap.
+///
+/// Marking the partition as persisting swaps in a fresh buffer, so writes to
+/// the partition continue to be accepted while the capped data is persisted,
+/// and no partition grows unboundedly between the periodic checks of the hot
+/// partition and memory pressure persist tasks.
+///
+/// If the persist queue is full, the write waits for space to become
+/// available in it, recorded as a "persist enqueue" span in the trace of the
+/// write.
+#[derive(Debug)]
+pub(crate) struct PartitionCapSink<T> {
+    inner: T,
+    buffer: Arc<BufferTree>,
+    persist: PersistHandle,
+    caps: HotPartitionThresholds,
+
+    /// The number of partitions enqueued for persistence due to a cap.
+    persist_count: U64Counter,
+}
+
+impl<T> PartitionCapSink<T> {
+    /// Initialise a new [`PartitionCapSink`] that passes ops through to `T`,
+    /// which buffers them in `buffer`, and enqueues the partitions exceeding
+    /// `caps` into `persist`.
+    pub(crate) fn new(
+        inner: T,
+        buffer: Arc<BufferTree>,
+        persist: PersistHandle,
+        caps: HotPartitionThresholds,
+        metrics: &metric::Registry,
+    ) -> Self {
+        let persist_count = metrics
+            .register_metric::<U64Counter>(
+                "ingester_partition_cap_persist",
+                "number of partitions persisted for exceeding the buffered data cap",
+            )
+            .recorder(&[]);
+
+        Self {
+            inner,
+            buffer,
+            persist,
+            caps,
+            persist_count,
+        }
+    }
+}
+
+#[async_trait]
+impl<T> DmlSink for PartitionCapSink<T>
+where
+    T: DmlSink,
+{
+    type Error = DmlError;
+
+    async fn apply(&self, op: DmlOperation) -> Result<(), Self::Error> {
+        let written = match &op {
+            DmlOperation::Write(w) if self.caps.is_enabled() => Some((
+                w.namespace_id(),
+                w.partition_key().clone(),
+                w.tables().map(|(id, _)| *id).collect::<Vec<TableId>>(),
+                w.meta().span_context().cloned(),
+            )),
+            _ => None,
+        };
+
+        self.inner.apply(op).await.map_err(Into::into)?;
+
+        let (namespace_id, partition_key, table_ids, span_ctx) = match written {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        let namespace = match self.buffer.namespace(namespace_id) {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        for table_id in table_ids {
+            let partition = match namespace
+                .table(table_id)
+                .and_then(|t| t.get_partition_by_key(&partition_key))
+            {
+                Some(v) => v,
+                None => continue,
+            };
+
+            let data = {
+                let mut guard = partition.lock();
+                let buffered_bytes = guard.buffered_bytes();
+                let buffered_rows = guard.buffered_rows();
+                if !self.caps.is_hot(buffered_bytes, buffered_rows) {
+                    continue;
+                }
+
+                debug!(
+                    partition_id = %guard.partition_id(),
+                    buffered_bytes,
+                    buffered_rows,
+                    "partition exceeds buffer cap, persisting"
+                );
+
+                match guard.mark_persisting() {
+                    Some(v) => v,
+                    None => continue,
+                }
+            };
+
+            self.persist_count.inc(1);
+
+            // The completion notification is not needed - the WAL rotation
+            // waits for all persist operations to complete.
+            let mut recorder = SpanRecorder::new(span_ctx.child_span("persist enqueue"));
+            self.persist.queue_persist(partition, data).await;
+            recorder.ok("persist enqueued");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{num::NonZeroUsize, time::Duration};
+
+    use data_types::{NamespaceId, PartitionId, PartitionKey};
+    use iox_catalog::mem::MemCatalog;
+    use iox_query::exec::Executor;
+    use metric::{Metric, U64Counter};
+    use object_store::memory::InMemory;
+    use parquet_file::storage::{ParquetStorage, StorageId};
+
+    use super::*;
+    use crate::{
+        buffer_tree::{
+            namespace::name_resolver::mock::MockNamespaceNameProvider,
+            partition::{resolver::mock::MockPartitionProvider, PartitionData, SortKeyState},
+            table::name_resolver::mock::MockTableNameProvider,
+        },
+        deferred_load::DeferredLoad,
+        ingest_state::IngestState,
+        init::PersistWorkers,
+        persist::priority::OldestFirst,
+        test_util::make_write_op,
+    };
+
+    const NAMESPACE_ID: NamespaceId = NamespaceId::new(42);
+    const TABLE_ID: TableId = TableId::new(44);
+
+    fn write(lp: &str) -> DmlOperation {
+        DmlOperation::Write(make_write_op(
+            &PartitionKey::from("p1"),
+            NAMESPACE_ID,
+            "bananas",
+            TABLE_ID,
+            42,
+            lp,
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_partition_cap() {
+        let metrics = Arc::new(metric::Registry::default());
+
+        let buffer = Arc::new(BufferTree::new(
+            Arc::new(MockNamespaceNameProvider::default()),
+            Arc::new(MockTableNameProvider::new("bananas")),
+            Arc::new(
+                MockPartitionProvider::default().with_partition(PartitionData::new(
+                    PartitionId::new(1),
+                    PartitionKey::from("p1"),
+                    NAMESPACE_ID,
+                    Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                        "platanos".into()
+                    })),
+                    TABLE_ID,
+                    Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                        "bananas".into()
+                    })),
+                    SortKeyState::Provided(None),
+                )),
+            ),
+            Arc::clone(&metrics),
+        ));
+
+        // The persist actor is never run - the enqueued partition is held in
+        // the submission queue.
+        let (persist, _actor) = PersistHandle::new(
+            1,
+            PersistWorkers::Fixed(1),
+            1,
+            Arc::new(OldestFirst),
+            Arc::new(Executor::new_testing()),
+            ParquetStorage::new(Arc::new(InMemory::new()), StorageId::from("iox")),
+            Arc::new(MemCatalog::new(Arc::clone(&metrics))),
+            Arc::new(IngestState::new(&metrics)),
+            None,
+            NonZeroUsize::new(10).unwrap(),
+            None,
+            false,
+            None,
+            None,
+            Default::default(),
+            &metrics,
+        );
+
+        let sink = PartitionCapSink::new(
+            Arc::clone(&buffer),
+            Arc::clone(&buffer),
+            persist,
+            HotPartitionThresholds {
+                max_bytes: None,
+                max_rows: Some(2),
+            },
+            &metrics,
+        );
+
+        let partition = || {
+            buffer
+                .namespace(NAMESPACE_ID)
+                .unwrap()
+                .table(TABLE_ID)
+                .unwrap()
+                .get_partition_by_key(&PartitionKey::from("p1"))
+                .unwrap()
+        };
+
+        // Under the cap, the data remains buffered.
+        sink.apply(write("bananas v=1i 1")).await.unwrap();
+        assert_eq!(partition().lock().buffered_rows(), 1);
+
+        // Reaching the cap enqueues the data for persistence, leaving an empty
+        // buffer to accept further writes.
+        sink.apply(write("bananas v=2i 2")).await.unwrap();
+        assert_eq!(partition().lock().buffered_rows(), 0);
+        assert!(partition().lock().persisting_bytes() > 0);
+
+        sink.apply(write("bananas v=3i 3")).await.unwrap();
+        assert_eq!(partition().lock().buffered_rows(), 1);
+
+        let count = metrics
+            .get_instrument::<Metric<U64Counter>>("ingester_partition_cap_persist")
+            .expect("metric not registered")
+            .get_observer(&metric::Attributes::from(&[]))
+            .expect("no metric observer")
+            .fetch();
+        assert_eq!(count, 1);
+    }
+}
@@ -0,0 +1,257 @@
+use std::{collections::HashMap, num::NonZeroU64, time::Duration};
+
+use async_trait::async_trait;
+use data_types::NamespaceId;
+use dml::DmlOperation;
+use iox_time::{SystemProvider, Time, TimeProvider};
+use parking_lot::Mutex;
+
+use super::{DmlError, DmlSink};
+
+/// The per-namespace write rate limits enforced by a [`RateLimitSink`].
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct RateLimits {
+    /// The number of rows each namespace may write per second.
+    pub(crate) rows_per_second: Option<NonZeroU64>,
+
+    /// The number of bytes each namespace may write per second.
+    pub(crate) bytes_per_second: Option<NonZeroU64>,
+}
+
+/// A token bucket holding at most one second of budget.
+///
+/// The bucket is allowed to go into debt, so that a write larger than the
+/// per-second budget is admitted once the bucket has any budget, rather than
+/// never.
+#[derive(Debug)]
+struct TokenBucket {
+    /// The budget replenished per second, and the capacity of the bucket.
+    rate: f64,
+    tokens: f64,
+    last_refill: Time,
+}
+
+impl TokenBucket {
+    fn new(rate: NonZeroU64, now: Time) -> Self {
+        let rate = rate.get() as f64;
+        Self {
+            rate,
+            tokens: rate,
+            last_refill: now,
+        }
+    }
+
+    /// Replenish the budget accrued since the last call, returning how long
+    /// to wait before any budget is available, or [`None`] if some is.
+    fn refill(&mut self, now: Time) -> Option<Duration> {
+        if let Some(elapsed) = now.checked_duration_since(self.last_refill) {
+            self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.rate);
+            self.last_refill = now;
+        }
+
+        if self.tokens > 0.0 {
+            return None;
+        }
+        Some(Duration::from_secs_f64(-self.tokens / self.rate))
+    }
+
+    fn take(&mut self, n: u64) {
+        self.tokens -= n as f64;
+    }
+}
+
+/// The [`TokenBucket`] instances of a single namespace.
+#[derive(Debug)]
+struct NamespaceBuckets {
+    rows: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+}
+
+/// A [`DmlSink`] decorator that enforces per-namespace [`RateLimits`] on the
+/// rows and bytes written, rejecting writes in excess of them with
+/// [`DmlError::RateLimited`] before passing them to the inner [`DmlSink`].
+///
+/// Deletes are never rate limited.
+#[derive(Debug)]
+pub(crate) struct RateLimitSink<T, P = SystemProvider> {
+    inner: T,
+    limits: RateLimits,
+    buckets: Mutex<HashMap<NamespaceId, NamespaceBuckets>>,
+    time_provider: P,
+}
+
+impl<T> RateLimitSink<T> {
+    /// Initialise a new [`RateLimitSink`] that enforces `limits` and passes
+    /// admitted ops through to `T`.
+    pub(crate) fn new(inner: T, limits: RateLimits) -> Self {
+        Self::new_with_time_provider(inner, limits, Default::default())
+    }
+}
+
+impl<T, P> RateLimitSink<T, P>
+where
+    P: TimeProvider,
+{
+    fn new_with_time_provider(inner: T, limits: RateLimits, time_provider: P) -> Self {
+        Self {
+            inner,
+            limits,
+            buckets: Default::default(),
+            time_provider,
+        }
+    }
+
+    /// Consume the budget for a write of `rows` rows and `bytes` bytes to
+    /// `namespace_id`, returning how long to wait before retrying if the
+    /// namespace has no budget remaining.
+    fn try_acquire(&self, namespace_id: NamespaceId, rows: u64, bytes: u64) -> Option<Duration> {
+        let now = self.time_provider.now();
+
+        let mut buckets = self.buckets.lock();
+        let buckets = buckets
+            .entry(namespace_id)
+            .or_insert_with(|| NamespaceBuckets {
+                rows: self
+                    .limits
+                    .rows_per_second
+                    .map(|v| TokenBucket::new(v, now)),
+                bytes: self
+                    .limits
+                    .bytes_per_second
+                    .map(|v| TokenBucket::new(v, now)),
+            });
+
+        let retry_after = [buckets.rows.as_mut(), buckets.bytes.as_mut()]
+            .into_iter()
+            .flatten()
+            .filter_map(|b| b.refill(now))
+            .max();
+        if retry_after.is_some() {
+            return retry_after;
+        }
+
+        if let Some(b) = buckets.rows.as_mut() {
+            b.take(rows);
+        }
+        if let Some(b) = buckets.bytes.as_mut() {
+            b.take(bytes);
+        }
+
+        None
+    }
+}
+
+#[async_trait]
+impl<T, P> DmlSink for RateLimitSink<T, P>
+where
+    T: DmlSink,
+    P: TimeProvider,
+{
+    type Error = DmlError;
+
+    async fn apply(&self, op: DmlOperation) -> Result<(), Self::Error> {
+        if let DmlOperation::Write(w) = &op {
+            let rows = w.tables().map(|(_, b)| b.rows() as u64).sum();
+            let namespace_id = w.namespace_id();
+
+            if let Some(retry_after) = self.try_acquire(namespace_id, rows, w.size() as u64) {
+                return Err(DmlError::RateLimited {
+                    namespace_id,
+                    retry_after,
+                });
+            }
+        }
+
+        self.inner.apply(op).await.map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use assert_matches::assert_matches;
+    use data_types::{PartitionKey, TableId};
+    use iox_time::MockProvider;
+
+    use super::*;
+    use crate::{
+        dml_sink::mock_sink::MockDmlSink,
+        test_util::{make_delete_op, make_write_op},
+    };
+
+    fn write(namespace_id: NamespaceId, lp: &str) -> DmlOperation {
+        DmlOperation::Write(make_write_op(
+            &PartitionKey::from("p1"),
+            namespace_id,
+            "bananas",
+            TableId::new(2),
+            42,
+            lp,
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_rows_rate_limit() {
+        let time = Arc::new(MockProvider::new(Time::from_timestamp_nanos(0)));
+        let inner =
+            Arc::new(MockDmlSink::default().with_apply_return([Ok(()), Ok(()), Ok(()), Ok(())]));
+        let sink = RateLimitSink::new_with_time_provider(
+            Arc::clone(&inner),
+            RateLimits {
+                rows_per_second: NonZeroU64::new(2),
+                bytes_per_second: None,
+            },
+            Arc::clone(&time),
+        );
+
+        let ns = NamespaceId::new(1);
+        let lp = "bananas v=1i 1\nbananas v=2i 2\nbananas v=3i 3";
+
+        // A write exceeding the budget is admitted into debt.
+        sink.apply(write(ns, lp)).await.unwrap();
+
+        // Which must be repaid before the next write.
+        let err = sink.apply(write(ns, lp)).await.unwrap_err();
+        assert_matches!(err, DmlError::RateLimited { namespace_id, retry_after } => {
+            assert_eq!(namespace_id, ns);
+            assert_eq!(retry_after, Duration::from_millis(500));
+        });
+
+        // Other namespaces have their own budget, and deletes are not limited.
+        sink.apply(write(NamespaceId::new(2), lp)).await.unwrap();
+        sink.apply(DmlOperation::Delete(make_delete_op(ns, None, 43)))
+            .await
+            .unwrap();
+
+        time.inc(Duration::from_millis(500));
+        assert_matches!(
+            sink.apply(write(ns, lp)).await,
+            Err(DmlError::RateLimited { .. })
+        );
+
+        time.inc(Duration::from_millis(1));
+        sink.apply(write(ns, lp)).await.unwrap();
+        assert_eq!(inner.get_calls().len(), 4);
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let mut b = TokenBucket::new(NonZeroU64::new(10).unwrap(), Time::from_timestamp_nanos(0));
+        assert_eq!(b.refill(Time::from_timestamp_nanos(0)), None);
+
+        b.take(15);
+        assert_eq!(
+            b.refill(Time::from_timestamp_nanos(0)),
+            Some(Duration::from_millis(500))
+        );
+
+        // The budget is capped at one second.
+        assert_eq!(b.refill(Time::from_timestamp_nanos(10_000_000_000)), None);
+        b.take(10);
+        assert_eq!(
+            b.refill(Time::from_timestamp_nanos(10_000_000_000)),
+            Some(Duration::ZERO)
+        );
+    }
+}
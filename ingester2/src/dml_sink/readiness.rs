@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use dml::DmlOperation;
+
+use super::{DmlError, DmlSink};
+use crate::init::ReadinessProbe;
+
+/// A [`DmlSink`] decorator that rejects all ops with [`DmlError::NotReady`]
+/// until its [`ReadinessProbe`] reports the ingester as ready, preventing
+/// writes from being applied while the WAL is being replayed.
+#[derive(Debug)]
+pub(crate) struct ReadinessSink<T> {
+    inner: T,
+    probe: Arc<ReadinessProbe>,
+}
+
+impl<T> ReadinessSink<T> {
+    /// Initialise a new [`ReadinessSink`] that passes ops through to `T` once
+    /// `probe` is ready.
+    pub(crate) fn new(inner: T, probe: Arc<ReadinessProbe>) -> Self {
+        Self { inner, probe }
+    }
+}
+
+#[async_trait]
+impl<T> DmlSink for ReadinessSink<T>
+where
+    T: DmlSink,
+{
+    type Error = DmlError;
+
+    async fn apply(&self, op: DmlOperation) -> Result<(), Self::Error> {
+        self.probe.check().map_err(DmlError::NotReady)?;
+        self.inner.apply(op).await.map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use data_types::{NamespaceId, PartitionKey, TableId};
+
+    use super::*;
+    use crate::{dml_sink::mock_sink::MockDmlSink, test_util::make_write_op, Readiness};
+
+    #[tokio::test]
+    async fn test_readiness_sink() {
+        let probe = Arc::new(ReadinessProbe::new(&metric::Registry::default()));
+        let inner = Arc::new(MockDmlSink::default().with_apply_return([Ok(())]));
+        let sink = ReadinessSink::new(Arc::clone(&inner), Arc::clone(&probe));
+
+        let op = || {
+            DmlOperation::Write(make_write_op(
+                &PartitionKey::from("p1"),
+                NamespaceId::new(1),
+                "bananas",
+                TableId::new(2),
+                42,
+                "bananas,region=asia v=1i 1",
+            ))
+        };
+
+        assert_matches!(
+            sink.apply(op()).await,
+            Err(DmlError::NotReady(Readiness::ReplayingWal { .. }))
+        );
+        assert!(inner.get_calls().is_empty());
+
+        probe.set_ready();
+        sink.apply(op())
+            .await
+            .expect("ready sink should accept ops");
+        assert_eq!(inner.get_calls().len(), 1);
+    }
+}
@@ -0,0 +1,389 @@
+use std::{fmt::Debug, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use dml::{DmlOperation, DmlWrite};
+use futures::{stream::FuturesUnordered, StreamExt};
+use generated_types::influxdata::iox::ingester::v1::{
+    write_service_client::WriteServiceClient, WriteRequest,
+};
+use metric::U64Counter;
+use mutable_batch_pb::encode::encode_write;
+use observability_deps::tracing::*;
+use tonic::transport::Channel;
+
+use super::{DmlError, DmlSink};
+use crate::init::ReplicationPolicy;
+
+/// The gRPC metadata key carrying the idempotency key of a write, which the
+/// peer uses to drop retries of a write it has already applied.
+const IDEMPOTENCY_KEY_HEADER: &str = "iox-idempotency-key";
+
+/// The bound on the duration of a replication RPC request, including the time
+/// taken to send the request and wait for the response.
+const REPLICATION_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl ReplicationPolicy {
+    /// The number of acknowledgements required from `n_peers`.
+    fn required_acks(&self, n_peers: usize) -> usize {
+        match self {
+            Self::All => n_peers,
+            Self::Quorum => (n_peers + 1) / 2,
+            Self::Async => 0,
+        }
+    }
+}
+
+/// A client replicating writes to a peer ingester.
+#[async_trait]
+pub(crate) trait ReplicaClient: Debug + Send + Sync {
+    /// Write `op` to the peer and wait for it to be acknowledged.
+    async fn replicate(
+        &self,
+        op: WriteRequest,
+        idempotency_key: Option<String>,
+    ) -> Result<(), tonic::Status>;
+}
+
+/// An implementation of [`ReplicaClient`] for the tonic gRPC client.
+#[async_trait]
+impl ReplicaClient for WriteServiceClient<Channel> {
+    async fn replicate(
+        &self,
+        op: WriteRequest,
+        idempotency_key: Option<String>,
+    ) -> Result<(), tonic::Status> {
+        let mut req = tonic::Request::new(op);
+        if let Some(key) = idempotency_key.and_then(|v| v.parse().ok()) {
+            req.metadata_mut().insert(IDEMPOTENCY_KEY_HEADER, key);
+        }
+
+        WriteServiceClient::write(&mut self.clone(), req).await?;
+        Ok(())
+    }
+}
+
+/// A peer ingester, and the replication outcome metrics for it.
+#[derive(Debug)]
+struct Peer<C> {
+    addr: String,
+    client: C,
+    ok: U64Counter,
+    error: U64Counter,
+}
+
+impl<C> Peer<C>
+where
+    C: ReplicaClient,
+{
+    async fn replicate(&self, op: WriteRequest, idempotency_key: Option<String>) -> bool {
+        let res = tokio::time::timeout(
+            REPLICATION_TIMEOUT,
+            self.client.replicate(op, idempotency_key),
+        )
+        .await;
+
+        match res {
+            Ok(Ok(())) => {
+                self.ok.inc(1);
+                true
+            }
+            Ok(Err(e)) => {
+                warn!(error=%e, peer=%self.addr, "failed to replicate op to peer");
+                self.error.inc(1);
+                false
+            }
+            Err(_) => {
+                warn!(peer=%self.addr, "timeout replicating op to peer");
+                self.error.inc(1);
+                false
+            }
+        }
+    }
+}
+
+/// A [`DmlSink`] decorator that forwards each write applied by the inner
+/// [`DmlSink`] to a set of peer ingesters, waiting for the number of
+/// acknowledgements required by the [`ReplicationPolicy`] before returning.
+///
+/// The op is applied locally first - when wrapping the WAL, only ops committed
+/// to the local WAL are replicated. If too few peers acknowledge the op, an
+/// error is returned although the op remains applied locally, and the client
+/// is expected to retry the write (which is deduplicated by the local and peer
+/// ingesters if it carries an idempotency key).
+///
+/// Requests to the peers not needed to satisfy the policy continue in the
+/// background after the op is acknowledged.
+///
+/// Deletes are not replicated, as the peer write RPC does not carry them.
+#[derive(Debug)]
+pub(crate) struct ReplicationSink<T, C> {
+    inner: T,
+    peers: Vec<Arc<Peer<C>>>,
+    policy: ReplicationPolicy,
+}
+
+impl<T, C> ReplicationSink<T, C> {
+    /// Initialise a new [`ReplicationSink`] that passes ops through to `T`,
+    /// and replicates the writes to the `peers` (identified by their address)
+    /// according to `policy`.
+    pub(crate) fn new(
+        inner: T,
+        peers: impl IntoIterator<Item = (String, C)>,
+        policy: ReplicationPolicy,
+        metrics: &metric::Registry,
+    ) -> Self {
+        let replicated = metrics.register_metric::<U64Counter>(
+            "ingester_replicated_ops",
+            "number of ops replicated to each peer ingester, by outcome",
+        );
+
+        let peers = peers
+            .into_iter()
+            .map(|(addr, client)| {
+                Arc::new(Peer {
+                    ok: replicated
+                        .recorder([("peer", addr.clone().into()), ("result", "success".into())]),
+                    error: replicated
+                        .recorder([("peer", addr.clone().into()), ("result", "error".into())]),
+                    addr,
+                    client,
+                })
+            })
+            .collect();
+
+        Self {
+            inner,
+            peers,
+            policy,
+        }
+    }
+}
+
+#[async_trait]
+impl<T, C> DmlSink for ReplicationSink<T, C>
+where
+    T: DmlSink,
+    C: ReplicaClient + 'static,
+{
+    type Error = DmlError;
+
+    async fn apply(&self, op: DmlOperation) -> Result<(), Self::Error> {
+        let write = match &op {
+            DmlOperation::Write(w) if !self.peers.is_empty() => Some(replication_request(w)),
+            _ => None,
+        };
+
+        self.inner.apply(op).await.map_err(Into::into)?;
+
+        let (req, idempotency_key) = match write {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        // Each request runs in its own task, so that the requests not waited
+        // for continue after this call returns (or is cancelled).
+        let mut acks = self
+            .peers
+            .iter()
+            .map(|peer| {
+                let peer = Arc::clone(peer);
+                let req = req.clone();
+                let idempotency_key = idempotency_key.clone();
+                tokio::spawn(async move { peer.replicate(req, idempotency_key).await })
+            })
+            .collect::<FuturesUnordered<_>>();
+
+        let required = self.policy.required_acks(self.peers.len());
+        let mut acked = 0;
+        while acked < required {
+            match acks.next().await {
+                Some(Ok(true)) => acked += 1,
+                Some(Ok(false)) => {}
+                Some(Err(e)) => error!(error=%e, "replication task panicked"),
+                None => return Err(DmlError::Replication { acked, required }),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Build the [`WriteRequest`] replicating `w`, and its idempotency key.
+fn replication_request(w: &DmlWrite) -> (WriteRequest, Option<String>) {
+    let req = WriteRequest {
+        payload: Some(encode_write(w.namespace_id().get(), w)),
+    };
+    (req, w.meta().idempotency_key().map(ToString::to_string))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use assert_matches::assert_matches;
+    use data_types::{NamespaceId, PartitionKey, TableId};
+    use parking_lot::Mutex;
+    use tokio::sync::Notify;
+
+    use super::*;
+    use crate::{dml_sink::mock_sink::MockDmlSink, test_util::make_write_op};
+
+    /// A [`ReplicaClient`] returning the configured results, optionally
+    /// blocking until notified.
+    #[derive(Debug, Default)]
+    struct MockReplicaClient {
+        calls: Mutex<Vec<WriteRequest>>,
+        ret: Mutex<VecDeque<Result<(), tonic::Status>>>,
+        block: Option<Arc<Notify>>,
+    }
+
+    impl MockReplicaClient {
+        fn with_ret(self, ret: impl Into<VecDeque<Result<(), tonic::Status>>>) -> Self {
+            *self.ret.lock() = ret.into();
+            self
+        }
+
+        fn with_block(mut self, notify: Arc<Notify>) -> Self {
+            self.block = Some(notify);
+            self
+        }
+    }
+
+    #[async_trait]
+    impl ReplicaClient for Arc<MockReplicaClient> {
+        async fn replicate(
+            &self,
+            op: WriteRequest,
+            _idempotency_key: Option<String>,
+        ) -> Result<(), tonic::Status> {
+            self.calls.lock().push(op);
+            if let Some(n) = &self.block {
+                n.notified().await;
+            }
+            self.ret
+                .lock()
+                .pop_front()
+                .expect("no mock value to return")
+        }
+    }
+
+    fn write() -> DmlOperation {
+        DmlOperation::Write(make_write_op(
+            &PartitionKey::from("p1"),
+            NamespaceId::new(1),
+            "bananas",
+            TableId::new(2),
+            42,
+            "bananas,region=asia v=1i 1",
+        ))
+    }
+
+    fn sink(
+        peers: &[Arc<MockReplicaClient>],
+        policy: ReplicationPolicy,
+    ) -> ReplicationSink<Arc<MockDmlSink>, Arc<MockReplicaClient>> {
+        ReplicationSink::new(
+            Arc::new(MockDmlSink::default().with_apply_return([Ok(())])),
+            peers
+                .iter()
+                .enumerate()
+                .map(|(i, c)| (format!("peer-{i}"), Arc::clone(c))),
+            policy,
+            &metric::Registry::default(),
+        )
+    }
+
+    #[test]
+    fn test_required_acks() {
+        assert_eq!(ReplicationPolicy::All.required_acks(3), 3);
+        assert_eq!(ReplicationPolicy::Quorum.required_acks(1), 1);
+        assert_eq!(ReplicationPolicy::Quorum.required_acks(2), 1);
+        assert_eq!(ReplicationPolicy::Quorum.required_acks(3), 2);
+        assert_eq!(ReplicationPolicy::Async.required_acks(3), 0);
+    }
+
+    #[tokio::test]
+    async fn test_replicate_all() {
+        let peers = [
+            Arc::new(MockReplicaClient::default().with_ret([Ok(())])),
+            Arc::new(MockReplicaClient::default().with_ret([Ok(())])),
+        ];
+        let sink = sink(&peers, ReplicationPolicy::All);
+
+        sink.apply(write())
+            .await
+            .expect("replication should succeed");
+
+        for p in &peers {
+            let calls = p.calls.lock();
+            assert_eq!(calls.len(), 1);
+            let payload = calls[0].payload.as_ref().unwrap();
+            assert_eq!(payload.database_id, 1);
+            assert_eq!(payload.partition_key, "p1");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replicate_all_peer_error() {
+        let peers = [
+            Arc::new(MockReplicaClient::default().with_ret([Ok(())])),
+            Arc::new(
+                MockReplicaClient::default().with_ret([Err(tonic::Status::unavailable("bananas"))]),
+            ),
+        ];
+        let sink = sink(&peers, ReplicationPolicy::All);
+
+        assert_matches!(
+            sink.apply(write()).await,
+            Err(DmlError::Replication {
+                acked: 1,
+                required: 2
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replicate_quorum() {
+        // The blocked peer is not needed for a quorum of 3 peers.
+        let notify = Arc::new(Notify::new());
+        let blocked = Arc::new(
+            MockReplicaClient::default()
+                .with_ret([Ok(())])
+                .with_block(Arc::clone(&notify)),
+        );
+        let peers = [
+            Arc::new(MockReplicaClient::default().with_ret([Ok(())])),
+            Arc::new(MockReplicaClient::default().with_ret([Ok(())])),
+            Arc::clone(&blocked),
+        ];
+        let sink = sink(&peers, ReplicationPolicy::Quorum);
+
+        sink.apply(write()).await.expect("quorum should be reached");
+    }
+
+    #[tokio::test]
+    async fn test_replicate_async() {
+        let peers = [Arc::new(
+            MockReplicaClient::default().with_ret([Err(tonic::Status::unavailable("bananas"))]),
+        )];
+        let sink = sink(&peers, ReplicationPolicy::Async);
+
+        sink.apply(write())
+            .await
+            .expect("async replication never fails");
+    }
+
+    #[tokio::test]
+    async fn test_local_error_not_replicated() {
+        let peer = Arc::new(MockReplicaClient::default());
+        let sink = ReplicationSink::new(
+            Arc::new(MockDmlSink::default().with_apply_return([Err(DmlError::ShuttingDown)])),
+            [("peer".to_string(), Arc::clone(&peer))],
+            ReplicationPolicy::All,
+            &metric::Registry::default(),
+        );
+
+        assert_matches!(sink.apply(write()).await, Err(DmlError::ShuttingDown));
+        assert!(peer.calls.lock().is_empty());
+    }
+}
@@ -0,0 +1,218 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use backoff::{Backoff, BackoffConfig};
+use data_types::{ColumnType, TableId};
+use dml::{DmlOperation, DmlWrite};
+use iox_catalog::interface::Catalog;
+use parking_lot::Mutex;
+
+use super::{DmlError, DmlSink};
+
+/// The type of each column in a table, keyed by column name.
+type TableColumns = HashMap<String, ColumnType>;
+
+/// A [`DmlSink`] decorator that checks the column types of a [`DmlWrite`]
+/// against the known schema of each table it writes to, rejecting a write
+/// containing a column of a different type with [`DmlError::SchemaConflict`]
+/// before it is passed to the inner [`DmlSink`].
+///
+/// The schema of a table is loaded from the [`Catalog`] the first time it is
+/// written to, and extended with the columns of each accepted write. Columns
+/// not yet known are accepted, as the router creates them in the catalog
+/// before the write reaches the ingester.
+///
+/// Deletes are always passed through.
+#[derive(Debug)]
+pub(crate) struct SchemaValidationSink<T> {
+    inner: T,
+    catalog: Arc<dyn Catalog>,
+    backoff_config: BackoffConfig,
+    tables: Mutex<HashMap<TableId, TableColumns>>,
+}
+
+impl<T> SchemaValidationSink<T> {
+    /// Initialise a new [`SchemaValidationSink`] that validates writes
+    /// against the table schemas in `catalog` before passing them through to
+    /// `T`.
+    pub(crate) fn new(inner: T, catalog: Arc<dyn Catalog>, backoff_config: BackoffConfig) -> Self {
+        Self {
+            inner,
+            catalog,
+            backoff_config,
+            tables: Default::default(),
+        }
+    }
+
+    /// Fetch the columns of `table_id` from the [`Catalog`], retrying
+    /// endlessly when errors occur.
+    async fn fetch(&self, table_id: TableId) -> TableColumns {
+        Backoff::new(&self.backoff_config)
+            .retry_all_errors("fetch table columns", || async {
+                let columns = self
+                    .catalog
+                    .repositories()
+                    .await
+                    .columns()
+                    .list_by_table_id(table_id)
+                    .await?
+                    .into_iter()
+                    .map(|c| (c.name, c.column_type))
+                    .collect();
+
+                Result::<_, iox_catalog::interface::Error>::Ok(columns)
+            })
+            .await
+            .expect("retry forever")
+    }
+
+    /// Validate the column types of `write`, recording any new columns if
+    /// they are all valid.
+    async fn validate(&self, write: &DmlWrite) -> Result<(), DmlError> {
+        // Load the schema of any table not yet seen, without holding the lock
+        // across the catalog query.
+        for (table_id, _) in write.tables() {
+            if self.tables.lock().contains_key(table_id) {
+                continue;
+            }
+            let columns = self.fetch(*table_id).await;
+            self.tables.lock().entry(*table_id).or_insert(columns);
+        }
+
+        let mut tables = self.tables.lock();
+
+        for (table_id, batch) in write.tables() {
+            let known = &tables[table_id];
+            for (name, column) in batch.columns() {
+                let got = ColumnType::from(column.influx_type());
+                match known.get(name) {
+                    Some(existing) if *existing != got => {
+                        return Err(DmlError::SchemaConflict {
+                            table_id: *table_id,
+                            column: name.clone(),
+                            existing: *existing,
+                            got,
+                        })
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        for (table_id, batch) in write.tables() {
+            let known = tables.get_mut(table_id).expect("table schema loaded");
+            for (name, column) in batch.columns() {
+                known
+                    .entry(name.clone())
+                    .or_insert_with(|| ColumnType::from(column.influx_type()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T> DmlSink for SchemaValidationSink<T>
+where
+    T: DmlSink,
+{
+    type Error = DmlError;
+
+    async fn apply(&self, op: DmlOperation) -> Result<(), Self::Error> {
+        if let DmlOperation::Write(w) = &op {
+            self.validate(w).await?;
+        }
+
+        self.inner.apply(op).await.map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use data_types::{NamespaceId, PartitionKey, ShardIndex};
+    use iox_catalog::mem::MemCatalog;
+
+    use super::*;
+    use crate::{
+        dml_sink::mock_sink::MockDmlSink,
+        test_util::{make_delete_op, make_write_op, populate_catalog},
+    };
+
+    fn write(namespace_id: NamespaceId, table_id: TableId, lp: &str) -> DmlOperation {
+        DmlOperation::Write(make_write_op(
+            &PartitionKey::from("p1"),
+            namespace_id,
+            "bananas",
+            table_id,
+            42,
+            lp,
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_schema_validation() {
+        let metrics = Arc::new(metric::Registry::default());
+        let catalog: Arc<dyn Catalog> = Arc::new(MemCatalog::new(metrics));
+        let (_shard_id, ns, table_id) =
+            populate_catalog(&*catalog, ShardIndex::new(1), "ns", "bananas").await;
+        catalog
+            .repositories()
+            .await
+            .columns()
+            .create_or_get("v", table_id, ColumnType::I64)
+            .await
+            .unwrap();
+
+        let inner = Arc::new(MockDmlSink::default().with_apply_return([Ok(()), Ok(()), Ok(())]));
+        let sink = SchemaValidationSink::new(
+            Arc::clone(&inner),
+            Arc::clone(&catalog),
+            BackoffConfig::default(),
+        );
+
+        // A write matching the catalog schema, adding a new column.
+        sink.apply(write(ns, table_id, "bananas v=1i,w=1.5 1"))
+            .await
+            .unwrap();
+
+        // A conflict with a column in the catalog.
+        let err = sink
+            .apply(write(ns, table_id, "bananas v=1.5 2"))
+            .await
+            .unwrap_err();
+        assert_matches!(err, DmlError::SchemaConflict { table_id: got_table, column, existing, got } => {
+            assert_eq!(got_table, table_id);
+            assert_eq!(column, "v");
+            assert_eq!(existing, ColumnType::I64);
+            assert_eq!(got, ColumnType::F64);
+        });
+
+        // A conflict with a column learned from a previous write.
+        assert_matches!(
+            sink.apply(write(ns, table_id, "bananas w=\"str\" 3")).await,
+            Err(DmlError::SchemaConflict { column, .. }) => {
+                assert_eq!(column, "w");
+            }
+        );
+
+        // Deletes are passed through.
+        sink.apply(DmlOperation::Delete(make_delete_op(ns, None, 43)))
+            .await
+            .unwrap();
+
+        sink.apply(write(ns, table_id, "bananas v=2i,w=2.5 4"))
+            .await
+            .unwrap();
+
+        assert_matches!(
+            *inner.get_calls(),
+            [
+                DmlOperation::Write(_),
+                DmlOperation::Delete(_),
+                DmlOperation::Write(_)
+            ]
+        );
+    }
+}
@@ -0,0 +1,474 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    ops::RangeInclusive,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use dml::DmlOperation;
+use metric::{U64Counter, U64Gauge};
+use observability_deps::tracing::*;
+use parking_lot::Mutex;
+
+use super::{DmlError, DmlSink};
+
+/// How often the outstanding sequence number gaps are reported.
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long a gap must be outstanding before it is reported, allowing for ops
+/// that complete out of order.
+const GAP_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// How long a gap is tracked before the sequence numbers in it are considered
+/// lost, bounding the state held by the [`SequenceTracker`].
+const GAP_EXPIRY: Duration = Duration::from_secs(300);
+
+/// The maximum number of gap ranges included in a log message.
+const MAX_LOGGED_GAPS: usize = 10;
+
+/// A [`DmlSink`] decorator that records the sequence number of each op in a
+/// [`SequenceTracker`] once the inner [`DmlSink`] completes it, successfully
+/// or not.
+///
+/// Every sequence number is assigned to exactly one op, so a sequence number
+/// that is never completed identifies an op whose [`DmlSink::apply()`] future
+/// was dropped before completing - such as a write cancelled after being
+/// committed to the WAL, but before it was buffered.
+///
+/// Rejected ops consume a sequence number too, so this decorator must wrap
+/// every [`DmlSink`] that may reject an op.
+#[derive(Debug)]
+pub(crate) struct SequenceGapSink<T> {
+    inner: T,
+    tracker: Arc<SequenceTracker>,
+}
+
+impl<T> SequenceGapSink<T> {
+    /// Initialise a new [`SequenceGapSink`] that passes ops through to `T`,
+    /// recording their completion in `tracker`.
+    pub(crate) fn new(inner: T, tracker: Arc<SequenceTracker>) -> Self {
+        Self { inner, tracker }
+    }
+}
+
+#[async_trait]
+impl<T> DmlSink for SequenceGapSink<T>
+where
+    T: DmlSink,
+{
+    type Error = DmlError;
+
+    async fn apply(&self, op: DmlOperation) -> Result<(), Self::Error> {
+        let sequence_number = op.meta().sequence().map(|v| v.sequence_number);
+
+        let res = self.inner.apply(op).await.map_err(Into::into);
+
+        if let Some(v) = sequence_number {
+            self.tracker
+                .observe(u64::try_from(v.get()).expect("sequence number overflow"));
+        }
+
+        res
+    }
+}
+
+/// A contiguous run of completed sequence numbers.
+#[derive(Debug, Clone, Copy)]
+struct Run {
+    /// The last sequence number in the run (inclusive).
+    end: u64,
+    /// When the earliest completed sequence number in this run was observed.
+    observed_at: Instant,
+}
+
+#[derive(Debug, Default)]
+struct TrackerState {
+    /// All sequence numbers up to (and including) this value have been
+    /// completed, or are no longer tracked.
+    ///
+    /// Initialised from the first observed sequence number, as the ops of a
+    /// previous instance of the ingester were never tracked.
+    watermark: Option<u64>,
+
+    /// The runs of completed sequence numbers beyond `watermark`, keyed by
+    /// their first sequence number.
+    ///
+    /// A gap precedes every run.
+    runs: BTreeMap<u64, Run>,
+
+    /// The sequence numbers of the ops committed to the WAL that have not yet
+    /// completed.
+    durable: HashSet<u64>,
+}
+
+/// A summary of the sequence number gaps outstanding at a point in time.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct GapReport {
+    /// The ranges of sequence numbers missing, lowest first.
+    pub(crate) gaps: Vec<RangeInclusive<u64>>,
+    /// The total number of sequence numbers missing.
+    pub(crate) missing: u64,
+    /// How long the oldest gap has been outstanding.
+    pub(crate) oldest_age: Option<Duration>,
+}
+
+/// Tracks the sequence numbers completed by a [`SequenceGapSink`], deriving
+/// the gaps between them.
+///
+/// The completed sequence numbers are held as contiguous runs, so the state is
+/// proportional to the number of gaps rather than the number of ops.
+#[derive(Debug, Default)]
+pub(crate) struct SequenceTracker {
+    state: Mutex<TrackerState>,
+}
+
+impl SequenceTracker {
+    /// Record the completion of the op assigned `n`.
+    pub(crate) fn observe(&self, n: u64) {
+        self.observe_at(n, Instant::now())
+    }
+
+    fn observe_at(&self, n: u64, now: Instant) {
+        let mut state = self.state.lock();
+        state.durable.remove(&n);
+        let watermark = *state.watermark.get_or_insert(n.saturating_sub(1));
+        if n <= watermark {
+            return;
+        }
+
+        let mut start = n;
+        let mut run = Run {
+            end: n,
+            observed_at: now,
+        };
+
+        // Merge with the run ending immediately before n.
+        if let Some((&s, prev)) = state.runs.range(..n).next_back() {
+            if prev.end >= n {
+                // Already observed.
+                return;
+            }
+            if prev.end + 1 == n {
+                start = s;
+                run.observed_at = prev.observed_at;
+                state.runs.remove(&s);
+            }
+        }
+
+        // Merge with the run starting immediately after n.
+        if let Some(next) = state.runs.remove(&(n + 1)) {
+            run.end = next.end;
+            run.observed_at = run.observed_at.min(next.observed_at);
+        }
+
+        // A run contiguous with the watermark closes the gap before it.
+        if start == watermark + 1 {
+            state.watermark = Some(run.end);
+        } else {
+            state.runs.insert(start, run);
+        }
+    }
+
+    /// Record the op assigned `n` as committed to the WAL, until it
+    /// completes.
+    pub(crate) fn mark_durable(&self, n: u64) {
+        self.state.lock().durable.insert(n);
+    }
+
+    /// Returns true if the op assigned `n` is committed to the WAL, but has not
+    /// yet completed.
+    pub(crate) fn is_durable(&self, n: u64) -> bool {
+        self.state.lock().durable.contains(&n)
+    }
+
+    /// Returns true if the op assigned `n` has completed, or `n` precedes the
+    /// ops tracked (assigned by a previous instance of the ingester, or in a
+    /// gap no longer tracked).
+    pub(crate) fn is_completed(&self, n: u64) -> bool {
+        let state = self.state.lock();
+        match state.watermark {
+            None => false,
+            Some(v) if n <= v => true,
+            Some(_) => state
+                .runs
+                .range(..=n)
+                .next_back()
+                .map_or(false, |(_, run)| run.end >= n),
+        }
+    }
+
+    /// Return the gaps, as of `now`, that have been outstanding for at least
+    /// `min_age`.
+    fn report(&self, now: Instant, min_age: Duration) -> GapReport {
+        let state = self.state.lock();
+        let watermark = match state.watermark {
+            Some(v) => v,
+            None => return GapReport::default(),
+        };
+
+        // A gap was first observed when any sequence number beyond it was
+        // completed.
+        let mut first_observed = Vec::with_capacity(state.runs.len());
+        let mut earliest: Option<Instant> = None;
+        for run in state.runs.values().rev() {
+            let t = earliest.map_or(run.observed_at, |v| v.min(run.observed_at));
+            earliest = Some(t);
+            first_observed.push(t);
+        }
+        first_observed.reverse();
+
+        let mut report = GapReport::default();
+        let mut gap_start = watermark + 1;
+        for ((&start, run), observed_at) in state.runs.iter().zip(first_observed) {
+            let age = now.saturating_duration_since(observed_at);
+            if age >= min_age {
+                report.gaps.push(gap_start..=start - 1);
+                report.missing += start - gap_start;
+                report.oldest_age = Some(report.oldest_age.map_or(age, |v| v.max(age)));
+            }
+            gap_start = run.end + 1;
+        }
+
+        report
+    }
+
+    /// Stop tracking the gaps that have been outstanding for at least
+    /// `max_age` as of `now`, returning the ranges of sequence numbers in
+    /// them.
+    fn expire(&self, now: Instant, max_age: Duration) -> Vec<RangeInclusive<u64>> {
+        let expired = self.report(now, max_age).gaps;
+
+        // The oldest gaps are the lowest, so expiring them advances the
+        // watermark past the run following the last expired gap.
+        if let Some(last) = expired.last() {
+            let mut state = self.state.lock();
+            let retained = state.runs.split_off(&(last.end() + 2));
+            let removed = std::mem::replace(&mut state.runs, retained);
+            state.watermark = removed.values().map(|r| r.end).max();
+
+            // The ops in the expired gaps never complete.
+            let watermark = state.watermark.unwrap_or_default();
+            state.durable.retain(|&n| n > watermark);
+        }
+
+        expired
+    }
+}
+
+/// Periodically report the sequence number gaps outstanding in `tracker` as
+/// metrics, logging the ranges missing, and stop tracking gaps outstanding for
+/// long enough that the ops are considered lost.
+pub(crate) async fn report_sequence_gaps(
+    tracker: Arc<SequenceTracker>,
+    metrics: Arc<metric::Registry>,
+) {
+    let gaps = metrics
+        .register_metric::<U64Gauge>(
+            "ingester_sequence_gaps",
+            "number of ranges of sequence numbers assigned to ops that have not completed",
+        )
+        .recorder(&[]);
+    let missing = metrics
+        .register_metric::<U64Gauge>(
+            "ingester_sequence_gap_ops",
+            "number of sequence numbers assigned to ops that have not completed",
+        )
+        .recorder(&[]);
+    let oldest_age = metrics
+        .register_metric::<U64Gauge>(
+            "ingester_sequence_gap_oldest_age_seconds",
+            "how long the oldest sequence number gap has been outstanding",
+        )
+        .recorder(&[]);
+    let lost = metrics
+        .register_metric::<U64Counter>(
+            "ingester_sequence_gap_lost_ops",
+            "number of sequence numbers assigned to ops that never completed",
+        )
+        .recorder(&[]);
+
+    let mut interval = tokio::time::interval(REPORT_INTERVAL);
+
+    loop {
+        interval.tick().await;
+        let now = Instant::now();
+
+        let report = tracker.report(now, GAP_GRACE_PERIOD);
+        gaps.set(report.gaps.len() as _);
+        missing.set(report.missing);
+        oldest_age.set(report.oldest_age.map_or(0, |v| v.as_secs()));
+
+        if !report.gaps.is_empty() {
+            warn!(
+                n_gaps = report.gaps.len(),
+                missing = report.missing,
+                oldest_age = ?report.oldest_age,
+                gaps = ?&report.gaps[..report.gaps.len().min(MAX_LOGGED_GAPS)],
+                "ops assigned sequence numbers have not completed"
+            );
+        }
+
+        let expired = tracker.expire(now, GAP_EXPIRY);
+        if !expired.is_empty() {
+            let n = expired.iter().map(|r| r.end() - r.start() + 1).sum::<u64>();
+            lost.inc(n);
+            error!(
+                n_ops = n,
+                gaps = ?&expired[..expired.len().min(MAX_LOGGED_GAPS)],
+                "ops assigned sequence numbers never completed"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use data_types::{NamespaceId, PartitionKey, TableId};
+
+    use super::*;
+    use crate::{dml_sink::mock_sink::MockDmlSink, test_util::make_write_op};
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn test_tracker_gaps() {
+        let tracker = SequenceTracker::default();
+        let t0 = Instant::now();
+
+        // No ops observed, no gaps.
+        assert_eq!(tracker.report(t0, Duration::ZERO), GapReport::default());
+        assert!(!tracker.is_completed(1));
+
+        tracker.observe_at(1, t0);
+        tracker.observe_at(2, t0);
+        tracker.observe_at(5, t0 + SECOND);
+        tracker.observe_at(6, t0 + SECOND);
+        tracker.observe_at(9, t0 + 2 * SECOND);
+
+        assert_eq!(
+            tracker.report(t0 + 3 * SECOND, Duration::ZERO),
+            GapReport {
+                gaps: vec![3..=4, 7..=8],
+                missing: 4,
+                oldest_age: Some(2 * SECOND),
+            }
+        );
+        assert!(tracker.is_completed(2));
+        assert!(!tracker.is_completed(3));
+        assert!(tracker.is_completed(6));
+        assert!(!tracker.is_completed(8));
+        assert!(tracker.is_completed(9));
+        assert!(!tracker.is_completed(10));
+
+        // Only the gaps outstanding for long enough are reported.
+        assert_eq!(
+            tracker.report(t0 + 3 * SECOND, 2 * SECOND),
+            GapReport {
+                gaps: vec![3..=4],
+                missing: 2,
+                oldest_age: Some(2 * SECOND),
+            }
+        );
+
+        // Completing an out-of-order op closes its gap.
+        tracker.observe_at(4, t0 + 3 * SECOND);
+        tracker.observe_at(3, t0 + 3 * SECOND);
+        tracker.observe_at(8, t0 + 3 * SECOND);
+        assert_eq!(
+            tracker.report(t0 + 3 * SECOND, Duration::ZERO),
+            GapReport {
+                gaps: vec![7..=7],
+                missing: 1,
+                oldest_age: Some(SECOND),
+            }
+        );
+
+        tracker.observe_at(7, t0 + 3 * SECOND);
+        assert_eq!(
+            tracker.report(t0 + 3 * SECOND, Duration::ZERO),
+            GapReport::default()
+        );
+        assert_eq!(tracker.state.lock().watermark, Some(9));
+        assert!(tracker.state.lock().runs.is_empty());
+    }
+
+    #[test]
+    fn test_tracker_expire() {
+        let tracker = SequenceTracker::default();
+        let t0 = Instant::now();
+
+        tracker.observe_at(1, t0);
+        tracker.observe_at(3, t0);
+        tracker.observe_at(6, t0 + 10 * SECOND);
+
+        // Only the oldest gap has expired.
+        assert_eq!(tracker.expire(t0 + 10 * SECOND, 10 * SECOND), vec![2..=2]);
+        assert_eq!(
+            tracker.report(t0 + 10 * SECOND, Duration::ZERO),
+            GapReport {
+                gaps: vec![4..=5],
+                missing: 2,
+                oldest_age: Some(Duration::ZERO),
+            }
+        );
+
+        // Completing an expired sequence number has no effect.
+        tracker.observe_at(2, t0 + 10 * SECOND);
+        assert_eq!(tracker.report(t0 + 10 * SECOND, Duration::ZERO).missing, 2);
+
+        // A durable op in an expired gap is no longer tracked.
+        tracker.mark_durable(4);
+        assert_eq!(tracker.expire(t0 + 20 * SECOND, 10 * SECOND), vec![4..=5]);
+        assert_eq!(tracker.state.lock().watermark, Some(6));
+        assert!(tracker.state.lock().runs.is_empty());
+        assert!(!tracker.is_durable(4));
+    }
+
+    #[test]
+    fn test_tracker_durable() {
+        let tracker = SequenceTracker::default();
+
+        tracker.mark_durable(1);
+        assert!(tracker.is_durable(1));
+        assert!(!tracker.is_completed(1));
+
+        // Completing the op stops it being reported as durable.
+        tracker.observe(1);
+        assert!(!tracker.is_durable(1));
+        assert!(tracker.is_completed(1));
+    }
+
+    #[tokio::test]
+    async fn test_sequence_gap_sink() {
+        let tracker = Arc::new(SequenceTracker::default());
+        let inner = Arc::new(MockDmlSink::default().with_apply_return([
+            Ok(()),
+            Err(DmlError::ShuttingDown),
+            Ok(()),
+        ]));
+        let sink = SequenceGapSink::new(Arc::clone(&inner), Arc::clone(&tracker));
+
+        let op = |n| {
+            DmlOperation::Write(make_write_op(
+                &PartitionKey::from("p1"),
+                NamespaceId::new(1),
+                "bananas",
+                TableId::new(2),
+                n,
+                "bananas,region=asia v=1i 1",
+            ))
+        };
+
+        sink.apply(op(1)).await.unwrap();
+        // Rejected ops are completed too.
+        assert_matches!(sink.apply(op(2)).await, Err(DmlError::ShuttingDown));
+        sink.apply(op(4)).await.unwrap();
+
+        assert_eq!(
+            tracker.report(Instant::now(), Duration::ZERO).gaps,
+            vec![3..=3]
+        );
+    }
+}
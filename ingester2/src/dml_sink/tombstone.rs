@@ -0,0 +1,205 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use backoff::{Backoff, BackoffConfig};
+use data_types::Timestamp;
+use dml::{DmlDelete, DmlOperation};
+use iox_catalog::interface::Catalog;
+use observability_deps::tracing::*;
+
+use super::DmlSink;
+use crate::TRANSITION_SHARD_ID;
+
+/// A [`DmlSink`] decorator that records a tombstone in the catalog for each
+/// table targeted by a [`DmlOperation::Delete`], before passing the op to the
+/// inner [`DmlSink`].
+///
+/// The inner [`DmlSink`] materialises the delete in the data buffered by the
+/// ingester, while the tombstone causes the querier to apply it to all parquet
+/// files persisted with a lower maximum sequence number.
+///
+/// Recording the tombstone is idempotent, allowing deletes to be replayed from
+/// the WAL through this sink.
+#[derive(Debug)]
+pub(crate) struct TombstoneSink<T> {
+    inner: T,
+    catalog: Arc<dyn Catalog>,
+    backoff_config: BackoffConfig,
+}
+
+impl<T> TombstoneSink<T> {
+    /// Initialise a new [`TombstoneSink`] that records tombstones in
+    /// `catalog`, and on success, passes the op through to `T`.
+    pub(crate) fn new(inner: T, catalog: Arc<dyn Catalog>, backoff_config: BackoffConfig) -> Self {
+        Self {
+            inner,
+            catalog,
+            backoff_config,
+        }
+    }
+
+    /// Record a tombstone for each table targeted by `delete`, retrying
+    /// catalog errors indefinitely.
+    async fn record_tombstones(&self, delete: &DmlDelete) {
+        let namespace_id = delete.namespace_id();
+        let sequence_number = delete
+            .meta()
+            .sequence()
+            .expect("applying unsequenced op")
+            .sequence_number;
+
+        // Resolve the tables the delete applies to.
+        let tables = Backoff::new(&self.backoff_config)
+            .retry_all_errors("resolve delete tables", || async {
+                let mut repos = self.catalog.repositories().await;
+                match delete.table_name() {
+                    Some(name) => repos
+                        .tables()
+                        .get_by_namespace_and_name(namespace_id, name)
+                        .await
+                        .map(|v| v.into_iter().collect::<Vec<_>>()),
+                    None => repos.tables().list_by_namespace_id(namespace_id).await,
+                }
+            })
+            .await
+            .expect("retry forever");
+
+        let predicate = delete.predicate();
+        let serialized_predicate = predicate.expr_sql_string();
+
+        for table in tables {
+            let tombstone = Backoff::new(&self.backoff_config)
+                .retry_all_errors("create tombstone", || async {
+                    self.catalog
+                        .repositories()
+                        .await
+                        .tombstones()
+                        .create_or_get(
+                            table.id,
+                            TRANSITION_SHARD_ID,
+                            sequence_number,
+                            Timestamp::new(predicate.range.start()),
+                            Timestamp::new(predicate.range.end()),
+                            &serialized_predicate,
+                        )
+                        .await
+                })
+                .await
+                .expect("retry forever");
+
+            debug!(
+                %namespace_id,
+                table_id = %table.id,
+                tombstone_id = %tombstone.id,
+                ?sequence_number,
+                "recorded delete tombstone"
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl<T> DmlSink for TombstoneSink<T>
+where
+    T: DmlSink,
+{
+    type Error = T::Error;
+
+    async fn apply(&self, op: DmlOperation) -> Result<(), Self::Error> {
+        if let DmlOperation::Delete(delete) = &op {
+            self.record_tombstones(delete).await;
+        }
+
+        self.inner.apply(op).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use data_types::{PartitionKey, ShardIndex};
+    use iox_catalog::mem::MemCatalog;
+
+    use super::*;
+    use crate::{
+        dml_sink::mock_sink::MockDmlSink,
+        test_util::{make_delete_op, make_write_op, populate_catalog},
+    };
+
+    const TABLE_NAME: &str = "bananas";
+    const NAMESPACE_NAME: &str = "platanos";
+
+    #[tokio::test]
+    async fn test_tombstone_sink() {
+        let metrics = Arc::new(metric::Registry::default());
+        let catalog: Arc<dyn Catalog> = Arc::new(MemCatalog::new(Arc::clone(&metrics)));
+        let (_shard_id, namespace_id, table_id) =
+            populate_catalog(&*catalog, ShardIndex::new(1), NAMESPACE_NAME, TABLE_NAME).await;
+        let other_table_id = catalog
+            .repositories()
+            .await
+            .tables()
+            .create_or_get("cherries", namespace_id)
+            .await
+            .unwrap()
+            .id;
+
+        let inner = Arc::new(MockDmlSink::default().with_apply_return([Ok(()), Ok(()), Ok(())]));
+        let sink = TombstoneSink::new(
+            Arc::clone(&inner),
+            Arc::clone(&catalog),
+            BackoffConfig::default(),
+        );
+
+        // Writes do not record tombstones.
+        sink.apply(DmlOperation::Write(make_write_op(
+            &PartitionKey::from("p1"),
+            namespace_id,
+            TABLE_NAME,
+            table_id,
+            1,
+            "bananas,region=asia v=1i 1",
+        )))
+        .await
+        .unwrap();
+
+        // A delete of a single table.
+        sink.apply(DmlOperation::Delete(make_delete_op(
+            namespace_id,
+            Some(TABLE_NAME),
+            2,
+        )))
+        .await
+        .unwrap();
+
+        // A delete of all tables.
+        sink.apply(DmlOperation::Delete(make_delete_op(namespace_id, None, 3)))
+            .await
+            .unwrap();
+
+        // All ops are passed through.
+        assert_eq!(inner.get_calls().len(), 3);
+
+        let mut repos = catalog.repositories().await;
+        let tombstones = repos.tombstones().list_by_table(table_id).await.unwrap();
+        assert_eq!(
+            tombstones
+                .iter()
+                .map(|t| (t.sequence_number.get(), t.min_time.get(), t.max_time.get()))
+                .collect::<Vec<_>>(),
+            [(2, 1, 2), (3, 1, 2)]
+        );
+
+        let tombstones = repos
+            .tombstones()
+            .list_by_table(other_table_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            tombstones
+                .iter()
+                .map(|t| t.sequence_number.get())
+                .collect::<Vec<_>>(),
+            [3]
+        );
+    }
+}
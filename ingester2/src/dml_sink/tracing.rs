@@ -0,0 +1,156 @@
+use std::borrow::Cow;
+
+use async_trait::async_trait;
+use dml::DmlOperation;
+use trace::span::SpanRecorder;
+
+use super::{DmlError, DmlSink};
+
+/// A tracing decorator over a [`DmlSink`] implementation.
+///
+/// This wrapper emits a child span of the op's tracing context covering the
+/// inner [`DmlSink::apply()`] call, and sets it as the parent of any spans
+/// emitted by the inner sink.
+///
+/// Constructing this decorator is cheap.
+#[derive(Debug)]
+pub(crate) struct DmlSinkTracing<T> {
+    inner: T,
+    name: Cow<'static, str>,
+}
+
+impl<T> DmlSinkTracing<T> {
+    pub(crate) fn new(inner: T, name: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            inner,
+            name: name.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl<T> DmlSink for DmlSinkTracing<T>
+where
+    T: DmlSink,
+{
+    type Error = DmlError;
+
+    async fn apply(&self, mut op: DmlOperation) -> Result<(), Self::Error> {
+        let span = op.meta().span_context().map(|c| c.child(self.name.clone()));
+        if let Some(span) = &span {
+            op.set_meta(op.meta().clone().with_span_context(Some(span.ctx.clone())));
+        }
+        let mut recorder = SpanRecorder::new(span);
+
+        match self.inner.apply(op).await {
+            Ok(()) => {
+                recorder.ok("apply complete");
+                Ok(())
+            }
+            Err(e) => {
+                let e: DmlError = e.into();
+                recorder.error(e.to_string());
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use assert_matches::assert_matches;
+    use data_types::{NamespaceId, PartitionKey, TableId};
+    use trace::{ctx::SpanContext, span::SpanStatus, RingBufferTraceCollector, TraceCollector};
+
+    use super::*;
+    use crate::{dml_sink::mock_sink::MockDmlSink, test_util::make_write_op};
+
+    #[track_caller]
+    fn assert_trace(name: impl Into<String>, status: SpanStatus, traces: &dyn TraceCollector) {
+        let traces = traces
+            .as_any()
+            .downcast_ref::<RingBufferTraceCollector>()
+            .expect("unexpected collector impl");
+
+        let name = name.into();
+        let span = traces
+            .spans()
+            .into_iter()
+            .find(|s| s.name == name)
+            .unwrap_or_else(|| panic!("tracing span {name} not found"));
+
+        assert_eq!(
+            span.status, status,
+            "span status does not match expected value"
+        );
+    }
+
+    fn op(span_ctx: Option<SpanContext>) -> DmlOperation {
+        let mut op = DmlOperation::Write(make_write_op(
+            &PartitionKey::from("p1"),
+            NamespaceId::new(42),
+            "bananas",
+            TableId::new(24),
+            1,
+            "bananas,region=asia v=1i 1",
+        ));
+        op.set_meta(op.meta().clone().with_span_context(span_ctx));
+        op
+    }
+
+    #[tokio::test]
+    async fn test_ok() {
+        let mock = Arc::new(MockDmlSink::default().with_apply_return([Ok(())]));
+
+        let traces: Arc<dyn TraceCollector> = Arc::new(RingBufferTraceCollector::new(5));
+        let span_ctx = SpanContext::new(Arc::clone(&traces));
+
+        // Drive the trace wrapper
+        DmlSinkTracing::new(Arc::clone(&mock), "bananas")
+            .apply(op(Some(span_ctx.clone())))
+            .await
+            .expect("wrapper should not modify result");
+
+        // Assert the trace showed up.
+        assert_trace("bananas", SpanStatus::Ok, &*traces);
+
+        // And the inner sink observed the child span as the op's context.
+        let calls = mock.get_calls();
+        let got = calls[0].meta().span_context().expect("op should be traced");
+        assert_eq!(got.trace_id, span_ctx.trace_id);
+        assert_eq!(got.parent_span_id, Some(span_ctx.span_id));
+    }
+
+    #[tokio::test]
+    async fn test_err() {
+        let mock = MockDmlSink::default()
+            .with_apply_return([Err(DmlError::NamespaceDeleted(NamespaceId::new(42)))]);
+
+        let traces: Arc<dyn TraceCollector> = Arc::new(RingBufferTraceCollector::new(5));
+        let span_ctx = SpanContext::new(Arc::clone(&traces));
+
+        // Drive the trace wrapper
+        let got = DmlSinkTracing::new(mock, "bananas")
+            .apply(op(Some(span_ctx)))
+            .await
+            .expect_err("wrapper should not modify result");
+        assert_matches!(got, DmlError::NamespaceDeleted(_));
+
+        // Assert the trace showed up.
+        assert_trace("bananas", SpanStatus::Err, &*traces);
+    }
+
+    #[tokio::test]
+    async fn test_untraced() {
+        let mock = Arc::new(MockDmlSink::default().with_apply_return([Ok(())]));
+
+        DmlSinkTracing::new(Arc::clone(&mock), "bananas")
+            .apply(op(None))
+            .await
+            .expect("wrapper should not modify result");
+
+        assert!(mock.get_calls()[0].meta().span_context().is_none());
+    }
+}
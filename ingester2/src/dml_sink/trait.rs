@@ -1,9 +1,12 @@
-use std::{error::Error, fmt::Debug, ops::Deref, sync::Arc};
+use std::{error::Error, fmt::Debug, ops::Deref, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
+use data_types::{ColumnType, NamespaceId, TableId};
 use dml::DmlOperation;
 use thiserror::Error;
 
+use crate::{ingest_state::IngestStateError, init::Readiness};
+
 #[derive(Debug, Error)]
 pub(crate) enum DmlError {
     /// An error applying a [`DmlOperation`] to a [`BufferTree`].
@@ -15,6 +18,66 @@ pub(crate) enum DmlError {
     /// An error appending the [`DmlOperation`] to the write-ahead log.
     #[error("wal commit failure: {0}")]
     Wal(#[from] wal::Error),
+
+    /// The ingester is overloaded, and rejects writes until it can make
+    /// durable progress on them.
+    #[error("ingester overloaded: {0}")]
+    Overloaded(#[from] IngestStateError),
+
+    /// The namespace has exceeded its write rate limit.
+    #[error("namespace {namespace_id} exceeded its write rate limit, retry after {retry_after:?}")]
+    RateLimited {
+        /// The rate limited namespace.
+        namespace_id: NamespaceId,
+        /// How long the client should wait before retrying.
+        retry_after: Duration,
+    },
+
+    /// The write contains a column of a different type to the same column in
+    /// the table schema.
+    #[error(
+        "column {column} in table {table_id} is of type {existing}, \
+        but the write contains type {got}"
+    )]
+    SchemaConflict {
+        /// The table containing the conflicting column.
+        table_id: TableId,
+        /// The name of the conflicting column.
+        column: String,
+        /// The type of the column in the table schema.
+        existing: ColumnType,
+        /// The type of the column in the write.
+        got: ColumnType,
+    },
+
+    /// The ingester is not yet ready to accept ops.
+    #[error("ingester not ready: {0}")]
+    NotReady(Readiness),
+
+    /// The ingester is shutting down and no longer accepts ops.
+    #[error("ingester is shutting down")]
+    ShuttingDown,
+
+    /// The op was applied, but too few peer ingesters acknowledged it to
+    /// satisfy the replication policy.
+    #[error("op replicated to {acked} peers, but {required} are required")]
+    Replication {
+        /// The number of peers that acknowledged the op.
+        acked: usize,
+        /// The number of peers required to acknowledge the op.
+        required: usize,
+    },
+
+    /// The op requires a catalog request, and the catalog is unavailable or
+    /// did not respond in time.
+    ///
+    /// The op was not committed to the WAL.
+    #[error("catalog unavailable")]
+    CatalogUnavailable,
+
+    /// The namespace has been deleted from the catalog.
+    #[error("namespace {0} has been deleted")]
+    NamespaceDeleted(NamespaceId),
 }
 
 /// A [`DmlSink`] handles [`DmlOperation`] instances in some abstract way.
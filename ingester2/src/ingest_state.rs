@@ -0,0 +1,214 @@
+//! A shared record of the conditions preventing the ingester from accepting
+//! writes.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use metric::U64Gauge;
+use thiserror::Error;
+use tokio::sync::Notify;
+
+/// A condition under which the ingester is overloaded, and rejects writes it
+/// cannot make durable progress on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub(crate) enum IngestStateError {
+    /// The persist submission queue is full.
+    #[error("persist queue is saturated")]
+    PersistSaturated,
+
+    /// The data buffered in memory has reached the hard limit.
+    #[error("buffer memory limit reached")]
+    MemoryLimit,
+
+    /// The WAL rotation is delayed until the persist backlog drains.
+    #[error("persist backlog too large")]
+    PersistBacklog,
+}
+
+impl IngestStateError {
+    /// All conditions, in the order they are reported by
+    /// [`IngestState::read()`].
+    const ALL: [Self; 3] = [
+        Self::PersistSaturated,
+        Self::MemoryLimit,
+        Self::PersistBacklog,
+    ];
+
+    /// A short, stable identifier of the condition.
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::PersistSaturated => "persist_saturated",
+            Self::MemoryLimit => "memory_limit",
+            Self::PersistBacklog => "persist_backlog",
+        }
+    }
+
+    fn bit(&self) -> usize {
+        match self {
+            Self::PersistSaturated => 1 << 0,
+            Self::MemoryLimit => 1 << 1,
+            Self::PersistBacklog => 1 << 2,
+        }
+    }
+}
+
+/// The set of [`IngestStateError`] conditions currently affecting the
+/// ingester.
+///
+/// Each condition is independently set and cleared by the subsystem observing
+/// it, and published as the `ingester_overloaded` gauge (1 while set, 0
+/// otherwise) with a `reason` attribute.
+#[derive(Debug)]
+pub(crate) struct IngestState {
+    /// A bitset of [`IngestStateError::bit()`].
+    state: AtomicUsize,
+
+    /// Notified when the last condition is cleared.
+    cleared: Notify,
+
+    persist_saturated: U64Gauge,
+    memory_limit: U64Gauge,
+    persist_backlog: U64Gauge,
+}
+
+impl IngestState {
+    /// Initialise a new [`IngestState`] with no conditions set, emitting
+    /// metrics to `metrics`.
+    pub(crate) fn new(metrics: &metric::Registry) -> Self {
+        let overloaded = metrics.register_metric::<U64Gauge>(
+            "ingester_overloaded",
+            "set to 1 while the ingester rejects writes for the given reason",
+        );
+
+        Self {
+            state: AtomicUsize::new(0),
+            cleared: Notify::new(),
+            persist_saturated: overloaded.recorder(&[("reason", "persist_saturated")]),
+            memory_limit: overloaded.recorder(&[("reason", "memory_limit")]),
+            persist_backlog: overloaded.recorder(&[("reason", "persist_backlog")]),
+        }
+    }
+
+    /// Set `condition`, returning true if it was not already set.
+    pub(crate) fn set(&self, condition: IngestStateError) -> bool {
+        let old = self.state.fetch_or(condition.bit(), Ordering::Relaxed);
+        self.gauge(condition).set(1);
+        old & condition.bit() == 0
+    }
+
+    /// Clear `condition`, returning true if it was set.
+    pub(crate) fn unset(&self, condition: IngestStateError) -> bool {
+        let old = self.state.fetch_and(!condition.bit(), Ordering::Relaxed);
+        self.gauge(condition).set(0);
+        if old & !condition.bit() == 0 {
+            self.cleared.notify_waiters();
+        }
+        old & condition.bit() != 0
+    }
+
+    /// Return an error if any condition is set.
+    pub(crate) fn read(&self) -> Result<(), IngestStateError> {
+        let state = self.state.load(Ordering::Relaxed);
+        match IngestStateError::ALL
+            .into_iter()
+            .find(|v| state & v.bit() != 0)
+        {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Wait until no condition is set.
+    pub(crate) async fn wait_clear(&self) {
+        loop {
+            // Register for the notification before checking the state, so a
+            // condition cleared in between is not missed.
+            let cleared = self.cleared.notified();
+            if self.read().is_ok() {
+                return;
+            }
+            cleared.await;
+        }
+    }
+
+    fn gauge(&self, condition: IngestStateError) -> &U64Gauge {
+        match condition {
+            IngestStateError::PersistSaturated => &self.persist_saturated,
+            IngestStateError::MemoryLimit => &self.memory_limit,
+            IngestStateError::PersistBacklog => &self.persist_backlog,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::FutureExt;
+    use metric::{Attributes, Metric};
+
+    use super::*;
+
+    fn overloaded(metrics: &metric::Registry, reason: &'static str) -> u64 {
+        metrics
+            .get_instrument::<Metric<U64Gauge>>("ingester_overloaded")
+            .expect("metric not registered")
+            .get_observer(&Attributes::from(&[("reason", reason)]))
+            .expect("no reason metric")
+            .fetch()
+    }
+
+    #[test]
+    fn test_ingest_state() {
+        let metrics = metric::Registry::default();
+        let state = IngestState::new(&metrics);
+        assert_eq!(state.read(), Ok(()));
+
+        assert!(state.set(IngestStateError::MemoryLimit));
+        assert!(!state.set(IngestStateError::MemoryLimit));
+        assert_eq!(state.read(), Err(IngestStateError::MemoryLimit));
+        assert_eq!(overloaded(&metrics, "memory_limit"), 1);
+        assert_eq!(overloaded(&metrics, "persist_saturated"), 0);
+
+        assert!(state.set(IngestStateError::PersistSaturated));
+        assert_eq!(state.read(), Err(IngestStateError::PersistSaturated));
+
+        assert!(state.unset(IngestStateError::PersistSaturated));
+        assert!(!state.unset(IngestStateError::PersistSaturated));
+        assert_eq!(state.read(), Err(IngestStateError::MemoryLimit));
+        assert_eq!(overloaded(&metrics, "persist_saturated"), 0);
+
+        assert!(state.unset(IngestStateError::MemoryLimit));
+        assert_eq!(state.read(), Ok(()));
+        assert_eq!(overloaded(&metrics, "memory_limit"), 0);
+
+        assert!(state.set(IngestStateError::PersistBacklog));
+        assert_eq!(state.read(), Err(IngestStateError::PersistBacklog));
+        assert_eq!(overloaded(&metrics, "persist_backlog"), 1);
+        assert!(state.unset(IngestStateError::PersistBacklog));
+        assert_eq!(state.read(), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_wait_clear() {
+        let state = IngestState::new(&metric::Registry::default());
+        state
+            .wait_clear()
+            .now_or_never()
+            .expect("no condition is set");
+
+        state.set(IngestStateError::MemoryLimit);
+        state.set(IngestStateError::PersistSaturated);
+
+        let wait = state.wait_clear();
+        tokio::pin!(wait);
+        assert!((&mut wait).now_or_never().is_none());
+
+        state.unset(IngestStateError::MemoryLimit);
+        assert!((&mut wait).now_or_never().is_none());
+
+        state.unset(IngestStateError::PersistSaturated);
+        tokio::time::timeout(Duration::from_secs(5), wait)
+            .await
+            .expect("wait should complete once all conditions are cleared");
+    }
+}
@@ -1,33 +1,153 @@
+mod readiness;
 mod wal_replay;
 
-use std::{path::PathBuf, sync::Arc, time::Duration};
+pub use readiness::Readiness;
+pub(crate) use readiness::ReadinessProbe;
+
+use std::{
+    num::{NonZeroU64, NonZeroUsize},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
 
 use arrow_flight::flight_service_server::{FlightService, FlightServiceServer};
 use backoff::BackoffConfig;
 use generated_types::influxdata::iox::{
     catalog::v1::catalog_service_server::{CatalogService, CatalogServiceServer},
-    ingester::v1::write_service_server::{WriteService, WriteServiceServer},
+    ingester::v1::{
+        buffer_debug_service_server::{BufferDebugService, BufferDebugServiceServer},
+        persist_service_server::{PersistService, PersistServiceServer},
+        write_service_client::WriteServiceClient,
+        write_service_server::{WriteService, WriteServiceServer},
+        write_status_service_server::{WriteStatusService, WriteStatusServiceServer},
+    },
 };
 use iox_catalog::interface::Catalog;
 use iox_query::exec::Executor;
-use parquet_file::storage::ParquetStorage;
+use observability_deps::tracing::*;
+use parking_lot::Mutex;
+use parquet_file::{
+    serialize::{ParquetCompression, WriterOptions},
+    storage::ParquetStorage,
+};
 use thiserror::Error;
-use wal::Wal;
+use tokio::sync::oneshot;
+use tonic::{codec::CompressionEncoding, transport::Endpoint};
+use wal::{DiskUsageThreshold, EvictionPolicy, Wal, WalOptions};
 
 use crate::{
     buffer_tree::{
+        memory::{MemoryLimits, MemoryTracker},
         namespace::name_resolver::{NamespaceNameProvider, NamespaceNameResolver},
+        namespace_watch::{watch_deleted_namespaces, NAMESPACE_WATCH_INTERVAL},
         partition::resolver::{CatalogPartitionResolver, PartitionCache, PartitionProvider},
         table::name_resolver::{TableNameProvider, TableNameResolver},
         BufferTree,
     },
-    persist::handle::PersistHandle,
+    dml_sink::{
+        report_sequence_gaps, CatalogBreakerConfig, CatalogBreakerSink, DeletedNamespaceSink,
+        DmlSinkTracing, DurableSink, IdempotencySink, IngestStateSink, PartitionCapSink,
+        RateLimitSink, RateLimits, ReadinessSink, ReplicationSink, SchemaValidationSink,
+        SequenceGapSink, SequenceTracker, TombstoneSink, WriteGate,
+    },
+    ingest_state::IngestState,
+    persist::{
+        file_cache::{ParquetFileCache, FILE_CACHE_DIRECTORY},
+        handle::PersistHandle,
+        hot_partition::{hot_partition_persist, HotPartitionThresholds},
+        memory_pressure::memory_pressure_persist,
+        pending::{PendingUploads, PENDING_UPLOADS_DIRECTORY},
+        policy::{refresh_persist_policies, PersistPolicies, POLICY_REFRESH_INTERVAL},
+        priority::OldestFirst,
+        state::report_persist_state,
+    },
+    query::{
+        memory_limit::{QueryExecMemoryLimit, QueryMemoryLimits},
+        readiness::QueryExecReadiness,
+    },
     server::grpc::GrpcDelegate,
     timestamp_oracle::TimestampOracle,
-    wal::{rotate_task::periodic_rotation, wal_sink::WalSink},
+    wal::{
+        rotate_task::{periodic_rotation, RotationBacklogLimit},
+        sequence_checkpoint::{periodic_checkpoint, read_checkpoint},
+        wal_sink::WalSink,
+    },
     TRANSITION_SHARD_ID,
 };
 
+/// The limit on the number of queries an ingester executes concurrently,
+/// rejecting queries in excess of it.
+///
+/// A query counts towards the limit until its response has been streamed to
+/// the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryConcurrency {
+    /// At most this many queries execute concurrently.
+    Fixed(usize),
+
+    /// The limit is adjusted between `min` and `max` (starting at `max`) as
+    /// queries complete - decreased when a query takes longer than
+    /// `target_latency` or the ingester is under memory pressure, and
+    /// increased otherwise.
+    Adaptive {
+        /// The lowest limit applied - must be at least 1.
+        min: usize,
+        /// The highest limit applied.
+        max: usize,
+        /// The latency above which a query is considered slow.
+        target_latency: Duration,
+    },
+}
+
+/// The number of persist worker tasks, each persisting one partition at a
+/// time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PersistWorkers {
+    /// Always run this many workers - must be at least 1.
+    Fixed(usize),
+
+    /// The number of workers is adjusted between `min` and `max` (starting at
+    /// `min`) - growing when the outstanding persist jobs are not expected to
+    /// complete within `target_latency`, and shrinking otherwise.
+    Autoscale {
+        /// The lowest number of workers - must be at least 1.
+        min: usize,
+        /// The highest number of workers.
+        max: usize,
+        /// The duration within which the outstanding persist jobs should
+        /// complete.
+        target_latency: Duration,
+    },
+}
+
+/// How many peers must acknowledge a replicated op before it is acknowledged
+/// to the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplicationPolicy {
+    /// Every peer must acknowledge the op.
+    All,
+
+    /// A majority of the ingesters, counting this one, must hold the op - for
+    /// `n` peers, `(n + 1) / 2` of them must acknowledge it.
+    Quorum,
+
+    /// The op is acknowledged without waiting for any peer - replication
+    /// failures are logged and counted, but not returned to the client.
+    Async,
+}
+
+/// The identity of an ingester, recorded as the provenance of the parquet
+/// files it persists.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IngesterIdentity {
+    /// The identity of the ingester host.
+    pub host: String,
+
+    /// The build version (and commit) of the ingester.
+    pub version: String,
+}
+
 /// Acquire opaque handles to the Ingester RPC service implementations.
 ///
 /// This trait serves as the public crate API boundary - callers external to the
@@ -44,6 +164,12 @@ pub trait IngesterRpcInterface: Send + Sync + std::fmt::Debug {
     type WriteHandler: WriteService;
     /// The type of the [`FlightService`] implementation.
     type FlightHandler: FlightService;
+    /// The type of the [`BufferDebugService`] implementation.
+    type BufferDebugHandler: BufferDebugService;
+    /// The type of the [`PersistService`] implementation.
+    type PersistHandler: PersistService;
+    /// The type of the [`WriteStatusService`] implementation.
+    type WriteStatusHandler: WriteStatusService;
 
     /// Acquire an opaque handle to the Ingester's [`CatalogService`] RPC
     /// handler implementation.
@@ -54,25 +180,103 @@ pub trait IngesterRpcInterface: Send + Sync + std::fmt::Debug {
     fn write_service(&self) -> WriteServiceServer<Self::WriteHandler>;
 
     /// Acquire an opaque handle to the Ingester's Arrow Flight
-    /// [`FlightService`] RPC handler implementation, allowing at most
-    /// `max_simultaneous_requests` queries to be running at any one time.
+    /// [`FlightService`] RPC handler implementation, limiting the number of
+    /// queries running at any one time as configured by `concurrency`.
+    ///
+    /// If `compression` is provided, responses are compressed with it for
+    /// the callers that accept the encoding (negotiated per call), and
+    /// requests compressed with it are accepted.
     fn query_service(
         &self,
-        max_simultaneous_requests: usize,
+        concurrency: QueryConcurrency,
+        compression: Option<CompressionEncoding>,
     ) -> FlightServiceServer<Self::FlightHandler>;
+
+    /// Acquire an opaque handle to the Ingester's [`BufferDebugService`] RPC
+    /// handler implementation, describing the data buffered in memory.
+    ///
+    /// This service is intended for debugging, and should only be exposed
+    /// when explicitly enabled.
+    fn buffer_debug_service(&self) -> BufferDebugServiceServer<Self::BufferDebugHandler>;
+
+    /// Acquire an opaque handle to the Ingester's [`PersistService`] RPC
+    /// handler implementation, reconfiguring the persist workers at runtime.
+    ///
+    /// This service is intended for operators, and should only be exposed
+    /// when explicitly enabled.
+    fn persist_service(&self) -> PersistServiceServer<Self::PersistHandler>;
+
+    /// Acquire an opaque handle to the Ingester's [`WriteStatusService`] RPC
+    /// handler implementation, reporting whether a write accepted by the
+    /// [`WriteService`] is durable, buffered, or persisted.
+    fn write_status_service(&self) -> WriteStatusServiceServer<Self::WriteStatusHandler>;
+
+    /// Return the current [`Readiness`] of the ingester.
+    ///
+    /// Writes and queries are rejected with an "unavailable" status until the
+    /// ingester is [`Readiness::Ready`], so callers should not route traffic
+    /// to (or consider healthy) an ingester until then.
+    fn readiness(&self) -> Readiness;
 }
 
 /// A RAII guard to clean up `ingester2` instance resources when dropped.
+///
+/// Dropping the guard stops the ingester immediately, leaving any buffered
+/// data to be replayed from the WAL on the next start - call
+/// [`IngesterGuard::shutdown()`] first to persist it.
 #[must_use = "ingester stops when guard is dropped"]
 #[derive(Debug)]
 pub struct IngesterGuard<T> {
     rpc: T,
 
-    /// The handle of the periodic WAL rotation task.
+    /// The handle of the WAL replay & periodic WAL rotation task.
     ///
-    /// Aborted on drop.
-    rotation_task: tokio::task::JoinHandle<()>,
+    /// Aborted on drop, or taken and awaited by
+    /// [`IngesterGuard::shutdown()`].
+    rotation_task: Mutex<Option<tokio::task::JoinHandle<()>>>,
     persist_task: tokio::task::JoinHandle<()>,
+
+    /// The handle of the hot partition persist task.
+    ///
+    /// Aborted on drop.
+    hot_persist_task: tokio::task::JoinHandle<()>,
+
+    /// The handle of the task reading the persist policies from the catalog.
+    ///
+    /// Aborted on drop.
+    persist_policy_task: tokio::task::JoinHandle<()>,
+
+    /// The handle of the memory accounting task.
+    ///
+    /// Aborted on drop.
+    memory_task: tokio::task::JoinHandle<()>,
+
+    /// The handle of the sequence number checkpoint task.
+    ///
+    /// Aborted on drop.
+    checkpoint_task: tokio::task::JoinHandle<()>,
+
+    /// The handle of the sequence number gap reporting task.
+    ///
+    /// Aborted on drop.
+    sequence_gap_task: tokio::task::JoinHandle<()>,
+
+    /// The handle of the persist state reporting task.
+    ///
+    /// Aborted on drop.
+    persist_state_task: tokio::task::JoinHandle<()>,
+
+    /// The handle of the task dropping the namespaces deleted from the
+    /// catalog.
+    ///
+    /// Aborted on drop.
+    namespace_watch_task: tokio::task::JoinHandle<()>,
+
+    /// Stops the write path accepting writes when closed.
+    write_gate: WriteGate,
+
+    /// Signals the rotation task to perform a final rotation & persist.
+    shutdown_tx: Mutex<Option<oneshot::Sender<()>>>,
 }
 
 impl<T> IngesterGuard<T> {
@@ -80,11 +284,48 @@ impl<T> IngesterGuard<T> {
     pub fn rpc(&self) -> &T {
         &self.rpc
     }
+
+    /// Gracefully stop the ingester.
+    ///
+    /// Stops accepting writes (waiting for in-flight writes to complete),
+    /// rotates the WAL and persists all buffered partitions, resolving once
+    /// the persisted data has been committed to the catalog and the rotated
+    /// WAL segment dropped - the next start of the ingester has no WAL to
+    /// replay. If called during WAL replay, the replay is completed first.
+    ///
+    /// Queries continue to be served until the guard is dropped.
+    pub async fn shutdown(&self) {
+        info!("stopping ingest for shutdown");
+        self.write_gate.close().await;
+
+        if let Some(tx) = self.shutdown_tx.lock().take() {
+            // The rotation task only exits early by panicking, which is
+            // observed below, or if the WAL replay failed.
+            let _ = tx.send(());
+        }
+
+        let rotation_task = self.rotation_task.lock().take();
+        if let Some(task) = rotation_task {
+            match task.await {
+                Ok(()) => info!("ingester shutdown complete"),
+                Err(e) => error!(error=%e, "wal rotation task failed during shutdown"),
+            }
+        }
+    }
 }
 
 impl<T> Drop for IngesterGuard<T> {
     fn drop(&mut self) {
-        self.rotation_task.abort();
+        if let Some(task) = self.rotation_task.get_mut().as_ref() {
+            task.abort();
+        }
+        self.hot_persist_task.abort();
+        self.persist_policy_task.abort();
+        self.memory_task.abort();
+        self.checkpoint_task.abort();
+        self.sequence_gap_task.abort();
+        self.persist_state_task.abort();
+        self.namespace_watch_task.abort();
     }
 }
 
@@ -100,14 +341,172 @@ pub enum InitError {
     #[error("failed to initialise write-ahead log: {0}")]
     WalInit(#[from] wal::Error),
 
-    /// An error replaying the entries in the WAL.
-    #[error(transparent)]
-    WalReplay(Box<dyn std::error::Error>),
+    /// An error reading the sequence number checkpoint from the WAL
+    /// directory.
+    #[error("failed to read sequence number checkpoint: {0}")]
+    SequenceCheckpoint(std::io::Error),
+
+    /// An error reading the markers of uncommitted parquet file uploads from
+    /// the WAL directory.
+    #[error("failed to reconcile pending parquet uploads: {0}")]
+    PendingUploads(std::io::Error),
+
+    /// An error reading the local cache of persisted parquet files from the
+    /// WAL directory.
+    #[error("failed to initialise parquet file cache: {0}")]
+    FileCache(std::io::Error),
+
+    /// The address of a replication peer is invalid.
+    #[error("invalid replication peer address {addr}: {source}")]
+    ReplicationPeer {
+        /// The invalid peer address.
+        addr: String,
+        /// The reason the address is invalid.
+        source: tonic::transport::Error,
+    },
+}
+
+/// The configuration of an `ingester2` instance, passed to [`new()`].
+///
+/// The behaviour controlled by each option is described in detail by the
+/// documentation of [`new()`].
+#[derive(Debug, Clone)]
+pub struct IngesterConfig {
+    /// The maximum delay before the deferred loads of the values needed at
+    /// persist time are resolved in the background.
+    pub persist_background_fetch_time: Duration,
+
+    /// The number of most recently created partitions cached at startup.
+    pub partition_cache_size: usize,
+
+    /// The maximum memory used by the partitions cached at startup.
+    pub partition_cache_max_bytes: Option<usize>,
+
+    /// The duration after which a cached partition that has not been written
+    /// to is evicted.
+    pub partition_cache_ttl: Option<Duration>,
+
+    /// The directory holding the WAL segment files, and the ingester's other
+    /// local state.
+    pub wal_directory: PathBuf,
+
+    /// The interval between WAL rotations, each persisting all buffered data.
+    pub wal_rotation_period: Duration,
+
+    /// The number of WAL segment files replayed concurrently.
+    pub wal_replay_concurrency: usize,
+
+    /// The number of namespaces whose ops are applied concurrently while
+    /// replaying a WAL segment file.
+    pub wal_replay_namespace_concurrency: usize,
+
+    /// The disk space allocated for each new WAL segment file.
+    pub wal_preallocate_bytes: Option<u64>,
+
+    /// The total size of the WAL segment files at which writes are rejected.
+    pub wal_max_bytes: Option<u64>,
+
+    /// The total size of the WAL segment files triggering an early rotation.
+    pub wal_eviction_threshold_bytes: Option<u64>,
+
+    /// The number of outstanding persist jobs above which WAL rotation is
+    /// delayed.
+    pub wal_rotation_max_persist_backlog: Option<usize>,
+
+    /// Reject writes while WAL rotation is delayed by the persist backlog.
+    pub wal_rotation_backlog_reject_writes: bool,
+
+    /// The depth of the queue of persist jobs awaiting a worker.
+    pub persist_submission_queue_depth: usize,
+
+    /// The number of persist workers.
+    pub persist_workers: PersistWorkers,
+
+    /// The depth of the queue of persist jobs of each worker.
+    pub persist_worker_queue_depth: usize,
+
+    /// The buffered bytes at which a partition is persisted early.
+    pub persist_hot_partition_bytes: Option<usize>,
+
+    /// The buffered rows at which a partition is persisted early.
+    pub persist_hot_partition_rows: Option<usize>,
+
+    /// The age of the rows of a hot partition that are persisted early.
+    pub persist_hot_partition_cold_age: Option<Duration>,
+
+    /// The compression codec of persisted parquet files.
+    pub persist_parquet_compression: ParquetCompression,
+
+    /// Dictionary encode the columns of persisted parquet files.
+    pub persist_parquet_dictionary: bool,
+
+    /// Write bloom filters and page statistics for the tag columns of
+    /// persisted parquet files.
+    pub persist_parquet_tag_indexes: bool,
+
+    /// The estimated size above which the data of a partition is split into
+    /// several parquet files.
+    pub persist_max_file_bytes: Option<NonZeroUsize>,
+
+    /// The number of failed attempts after which a persist job is
+    /// quarantined.
+    pub persist_max_attempts: NonZeroUsize,
+
+    /// The number of persist jobs of a single namespace executed at once.
+    pub persist_namespace_max_jobs: Option<NonZeroUsize>,
+
+    /// Read back and verify each uploaded parquet file.
+    pub persist_verify_parquet: bool,
+
+    /// The size of the local cache of persisted parquet files.
+    pub persist_file_cache_bytes: Option<u64>,
+
+    /// The identity recorded in the metadata of persisted parquet files.
+    pub identity: IngesterIdentity,
+
+    /// The buffered bytes at which a write enqueues its partition for
+    /// persistence.
+    pub partition_buffer_max_bytes: Option<usize>,
+
+    /// The buffered rows at which a write enqueues its partition for
+    /// persistence.
+    pub partition_buffer_max_rows: Option<usize>,
+
+    /// The buffered bytes above which the largest partitions are persisted.
+    pub buffer_soft_limit_bytes: Option<usize>,
+
+    /// The buffered bytes at which writes are rejected.
+    pub buffer_hard_limit_bytes: Option<usize>,
+
+    /// The rate of rows written to each namespace above which writes are
+    /// rejected.
+    pub namespace_write_rows_per_second: Option<NonZeroU64>,
+
+    /// The rate of bytes written to each namespace above which writes are
+    /// rejected.
+    pub namespace_write_bytes_per_second: Option<NonZeroU64>,
+
+    /// The number of idempotency keys of recent writes remembered.
+    pub idempotency_cache_size: Option<NonZeroUsize>,
+
+    /// The data a single query may return.
+    pub query_memory_limit_bytes: Option<usize>,
+
+    /// The data the responses of all in-flight queries may hold.
+    pub query_memory_global_limit_bytes: Option<usize>,
+
+    /// The addresses of the ingesters each write is replicated to.
+    pub replication_peers: Vec<String>,
+
+    /// How many replication peers must acknowledge a write.
+    pub replication_policy: ReplicationPolicy,
 }
 
 /// Initialise a new `ingester2` instance, returning the gRPC service handler
 /// implementations to be bound by the caller.
 ///
+/// The options referred to below are the fields of [`IngesterConfig`].
+///
 /// ## WAL Replay
 ///
 /// Writes through an `ingester2` instance commit to a durable write-ahead log.
@@ -116,9 +515,34 @@ pub enum InitError {
 /// `wal_directory` are read assuming they are redo log files from the
 /// write-ahead log.
 ///
-/// These files are read and replayed fully before this function returns.
+/// These files are replayed in a background task after this function returns,
+/// during which writes and queries are rejected with an "unavailable" status,
+/// and the replay progress is reported by
+/// [`IngesterRpcInterface::readiness()`]. Up to `wal_replay_concurrency` files
+/// are replayed concurrently, and within each file, the ops of up to
+/// `wal_replay_namespace_concurrency` namespaces are applied concurrently.
 ///
-/// Any error during replay
+/// If `wal_preallocate_bytes` is set, the disk space for that many bytes of
+/// each new WAL segment file is allocated when it is created. If
+/// `wal_max_bytes` is set, writes are rejected once the WAL segment files
+/// total that size. If `wal_eviction_threshold_bytes` is set, reaching that
+/// size instead triggers an early WAL rotation and persistence of all buffered
+/// data, after which the oldest (now persisted) WAL segment files are deleted.
+///
+/// If `wal_rotation_max_persist_backlog` is set, the periodic WAL rotation is
+/// delayed while more than that many persist jobs are outstanding, rather than
+/// enqueueing yet more persist work behind them. If
+/// `wal_rotation_backlog_reject_writes` is also true, writes are rejected
+/// while the rotation is delayed, so the buffered data stops growing too.
+///
+/// Any error during replay is logged, and the ingester is reported as
+/// [`Readiness::ReplayFailed`] - it never becomes ready, and must be restarted.
+///
+/// The highest sequence number assigned to a write is periodically
+/// checkpointed into `wal_directory`, and new writes continue from the greater
+/// of the checkpoint and the highest sequence number replayed, so sequence
+/// numbers are never reused across restarts, even once the WAL segments
+/// containing them have been deleted.
 ///
 /// ## Deferred Loading for Persist Operations
 ///
@@ -144,25 +568,284 @@ pub enum InitError {
 /// value should be tuned to be slightly less than the interval between persist
 /// operations, but not so long that it causes catalog load spikes at persist
 /// time (which can be observed by the catalog instrumentation metrics).
-#[allow(clippy::too_many_arguments)]
+///
+/// The `ingester_deferred_load_resolved` metric reports how many values were
+/// resolved by the background timer versus on demand, alongside the load
+/// latency, the time callers spent waiting for demand loads, and the number of
+/// values still outstanding, labelled by the kind of value.
+///
+/// ## Persist Workers
+///
+/// Up to `persist_workers` partitions are persisted in parallel. When
+/// configured with [`PersistWorkers::Autoscale`], the number of workers grows
+/// and shrinks with the persist backlog. The bounds can be changed at runtime
+/// through the [`PersistService`] RPC, without restarting the ingester. Each
+/// worker persists the partitions holding the oldest data first, so a backlog
+/// delays the truncation of the WAL as little as possible.
+///
+/// The outstanding persist jobs (the partitions assigned to each worker and
+/// the stage of persistence they are in, the age of the oldest job, and the
+/// recent failed catalog requests) are described by the [`PersistService`]
+/// RPC and the `ingester_persist_*` metrics, distinguishing a persist backlog
+/// from a slow catalog. The time persist jobs spend queued, compacting,
+/// encoding and uploading each parquet file, the files, bytes and rows they
+/// write, and their failures by cause, are also reported as metrics. The
+/// partitions persisted (with the sequence number watermark of the persisted
+/// writes, and the parquet files written) can be streamed through the
+/// [`PersistService`] RPC, to await the durability of data in object storage.
+///
+/// ## Partition Cache
+///
+/// At startup, the `partition_cache_size` most recently created partitions are
+/// read from the catalog and cached, so that writes to them do not need to
+/// resolve the partition from the catalog. If `partition_cache_max_bytes` is
+/// set, only the most recent of these partitions that fit within that much
+/// memory are cached, and if `partition_cache_ttl` is set, the partitions
+/// that have not been written to are evicted once it elapses. The cache is
+/// described by the `ingester_partition_cache_*` metrics.
+///
+/// ## Hot Partition Persistence
+///
+/// If `persist_hot_partition_bytes` or `persist_hot_partition_rows` is set, a
+/// partition buffering at least that much data is persisted without waiting
+/// for the next WAL rotation. This bounds the memory used by workloads where
+/// a few partitions receive most of the writes, and is active during WAL
+/// replay.
+///
+/// If `persist_hot_partition_cold_age` is also set, only the rows of a hot
+/// partition with a timestamp older than that are persisted, retaining the
+/// recent rows buffered unless they alone reach a threshold. A partition
+/// receiving continuous writes then persists the time ranges it no longer
+/// receives, rather than persisting everything or postponing persistence.
+///
+/// ## Persist Policies
+///
+/// The hot partition thresholds, and a maximum age of the oldest buffered
+/// write, can be overridden for a namespace or a single table by the persist
+/// policies in the catalog, which are read periodically. A table policy takes precedence over the
+/// policy of its namespace, which takes precedence over the ingester
+/// configuration. This allows high-churn tables to persist aggressively while
+/// low-volume tables batch up - all buffered data is still persisted by each
+/// WAL rotation.
+///
+/// ## Parquet Files
+///
+/// The parquet files written by the persist workers are compressed with the
+/// `persist_parquet_compression` codec (zstd by default, at its default
+/// level), and their columns are dictionary encoded if
+/// `persist_parquet_dictionary` is true. Zstd produces considerably smaller
+/// files than snappy for tag-heavy data, reducing object storage costs, at the
+/// cost of more CPU time to encode and decode them.
+///
+/// If `persist_parquet_tag_indexes` is true, a bloom filter and page-level
+/// statistics (from which the page index is built) are written for each tag
+/// column, allowing the querier and external parquet readers to prune row
+/// groups and pages on tag equality predicates without decoding them, at the
+/// cost of slightly larger files.
+///
+/// If `persist_max_file_bytes` is set, the data of a partition estimated to
+/// exceed it (from its in-memory size, which usually overestimates the size
+/// of the compressed file) is split by time range into several parquet files,
+/// avoiding very large files that are costly for the queriers and compactor to
+/// load.
+///
+/// If `persist_verify_parquet` is true, each uploaded file is read back and
+/// checked to hold the row count and time range of the data it was written
+/// from before it is added to the catalog, and the data marked as persisted
+/// (allowing its WAL segments to be dropped). A file failing verification is
+/// uploaded again.
+///
+/// ## Persist Failures
+///
+/// The catalog and object storage requests made by a persist job are retried
+/// with a backoff. Once a job has failed `persist_max_attempts` times it is
+/// quarantined, together with the later jobs of its partition: their data
+/// remains buffered (and queryable), and the WAL segments containing it are
+/// retained, until an operator re-enqueues the jobs through the persist admin
+/// RPC. Quarantined jobs are reported by the
+/// `ingester_persist_quarantined_jobs` metric.
+///
+/// ## Uncommitted Uploads
+///
+/// Before a parquet file is uploaded, a marker is durably recorded in the
+/// `pending_uploads` subdirectory of `wal_directory`, and removed once the
+/// file is added to the catalog. During initialisation, the files left marked
+/// by a crash of a previous instance are resolved before any data is
+/// persisted: a file found in the catalog is kept, and any other file is
+/// deleted from object storage - its data was never marked as persisted, so it
+/// is replayed from the WAL and persisted again.
+///
+/// The objects of a namespace that are not referenced by the catalog for any
+/// other reason can be found (and deleted) through the [`PersistService`] RPC.
+///
+/// ## Provenance
+///
+/// The metadata of each persisted parquet file records the `identity` of the
+/// ingester, the range of sequence numbers of the writes it holds and when it
+/// was persisted, for auditing the origin of the data in a file.
+///
+/// ## Parquet File Cache
+///
+/// If `persist_file_cache_bytes` is set, a copy of each persisted parquet file
+/// is written to the `parquet_cache` subdirectory of `wal_directory`, holding
+/// at most that many bytes of the most recently persisted files. A querier
+/// sharing the directory (configured with `--ingester-file-cache-dir`) reads
+/// the cached copies rather than fetching recently persisted data from object
+/// storage; the cached files are listed through the [`PersistService`] RPC. The files cached by a previous instance are retained.
+///
+/// ## Namespace Persist Limits
+///
+/// If `persist_namespace_max_jobs` is set, at most that many persist jobs of a
+/// single namespace are executed at once, holding back its subsequent jobs
+/// until they complete. This prevents a namespace with enormous partitions
+/// from occupying all the persist workers and starving the persistence (and
+/// thus the WAL truncation) of other namespaces.
+///
+/// ## Partition Buffer Caps
+///
+/// If `partition_buffer_max_bytes` or `partition_buffer_max_rows` is set, a
+/// write taking a partition to that much buffered data enqueues the partition
+/// for persistence before it completes, and subsequent writes to the partition
+/// are buffered anew. Unlike hot partition persistence, which is checked
+/// periodically, this strictly bounds the data buffered per partition.
+///
+/// ## Memory Limits
+///
+/// The memory used by the buffered data is measured periodically. If
+/// `buffer_soft_limit_bytes` is set, the largest partitions are persisted when
+/// it is exceeded, and if `buffer_hard_limit_bytes` is set, writes are rejected
+/// once it is reached, until persistence releases memory. Writes are also
+/// rejected while the persist queue is saturated.
+///
+/// The persist tasks run during WAL replay, which instead of rejecting ops
+/// waits for these conditions to clear - an ingester whose WAL holds more data
+/// than the memory limits allow persists it as it is replayed.
+///
+/// ## Rate Limits
+///
+/// If `namespace_write_rows_per_second` or `namespace_write_bytes_per_second`
+/// is set, writes to each namespace in excess of that rate are rejected, and
+/// the client is told how long to wait before retrying.
+///
+/// ## Idempotent Writes
+///
+/// If `idempotency_cache_size` is set, the idempotency keys of up to that many
+/// recent writes are remembered, and a retried write carrying the key of a
+/// write that was already applied is acknowledged without applying it again.
+///
+/// ## Sequence Number Gaps
+///
+/// The sequence numbers assigned to writes that never complete (such as a
+/// write cancelled while being applied) are reported by the
+/// `ingester_sequence_gaps` family of metrics and logged, helping to detect
+/// writes committed to the WAL but lost from the buffer.
+///
+/// ## Catalog Availability
+///
+/// Writes to partitions not yet buffered require the partitions to be
+/// resolved from the catalog, which is retried until it succeeds. A write
+/// whose partitions are not resolved within a bounded time is rejected as the
+/// catalog being unavailable before it is committed to the WAL, and once
+/// several consecutive writes time out, writes requiring the catalog (and
+/// deletes) are rejected immediately for a while (reported by the
+/// `ingester_catalog_circuit_open` metric), without stalling writes to
+/// buffered partitions.
+///
+/// ## Deleted Namespaces
+///
+/// The catalog is checked periodically for the namespaces buffered in the
+/// ingester, and a namespace that no longer exists is dropped along with its
+/// buffered data (which is not persisted). Subsequent writes to it are
+/// rejected, and its ops are discarded when replaying the WAL.
+///
+/// ## Write Status
+///
+/// Each write accepted by the [`WriteService`] is assigned a sequence number,
+/// returned to the client. The [`WriteStatusService`] reports whether the
+/// write with a given sequence number is durable in the WAL, buffered (and
+/// readable by queries), or persisted to object storage, allowing callers to
+/// wait for a write to become readable before querying it.
+///
+/// ## Tracing
+///
+/// The tracing context of an RPC write is propagated through the write path,
+/// recording child spans for the WAL commit (including the fsync), applying
+/// the write to the buffer, and enqueuing any partition it takes over a
+/// buffer cap for persistence. This attributes the latency of a slow write to
+/// the disk, or to contention in the buffer or persist queue.
+///
+/// ## Replication
+///
+/// If `replication_peers` is not empty, each write committed to the WAL is
+/// also written to the ingesters at those addresses (such as
+/// `http://10.10.10.2:8083`), and is
+/// acknowledged once enough of them acknowledge it to satisfy the
+/// `replication_policy`. This allows a standby ingester to hold all the
+/// writes of this ingester, without sharing storage. Deletes are not
+/// replicated.
+///
+/// ## Query Memory Limits
+///
+/// If `query_memory_limit_bytes` is set, a query returning more than that much
+/// data is failed, and if `query_memory_global_limit_bytes` is set, queries
+/// are failed while the responses of all in-flight queries hold that much
+/// data, protecting the ingester from queries over large buffered tables.
 pub async fn new(
     catalog: Arc<dyn Catalog>,
     metrics: Arc<metric::Registry>,
-    persist_background_fetch_time: Duration,
-    wal_directory: PathBuf,
-    wal_rotation_period: Duration,
     persist_executor: Arc<Executor>,
-    persist_submission_queue_depth: usize,
-    persist_workers: usize,
-    persist_worker_queue_depth: usize,
     object_store: ParquetStorage,
+    config: IngesterConfig,
 ) -> Result<IngesterGuard<impl IngesterRpcInterface>, InitError> {
+    let IngesterConfig {
+        persist_background_fetch_time,
+        partition_cache_size,
+        partition_cache_max_bytes,
+        partition_cache_ttl,
+        wal_directory,
+        wal_rotation_period,
+        wal_replay_concurrency,
+        wal_replay_namespace_concurrency,
+        wal_preallocate_bytes,
+        wal_max_bytes,
+        wal_eviction_threshold_bytes,
+        wal_rotation_max_persist_backlog,
+        wal_rotation_backlog_reject_writes,
+        persist_submission_queue_depth,
+        persist_workers,
+        persist_worker_queue_depth,
+        persist_hot_partition_bytes,
+        persist_hot_partition_rows,
+        persist_hot_partition_cold_age,
+        persist_parquet_compression,
+        persist_parquet_dictionary,
+        persist_parquet_tag_indexes,
+        persist_max_file_bytes,
+        persist_max_attempts,
+        persist_namespace_max_jobs,
+        persist_verify_parquet,
+        persist_file_cache_bytes,
+        identity,
+        partition_buffer_max_bytes,
+        partition_buffer_max_rows,
+        buffer_soft_limit_bytes,
+        buffer_hard_limit_bytes,
+        namespace_write_rows_per_second,
+        namespace_write_bytes_per_second,
+        idempotency_cache_size,
+        query_memory_limit_bytes,
+        query_memory_global_limit_bytes,
+        replication_peers,
+        replication_policy,
+    } = config;
+
     // Initialise the deferred namespace name resolver.
     let namespace_name_provider: Arc<dyn NamespaceNameProvider> =
         Arc::new(NamespaceNameResolver::new(
             persist_background_fetch_time,
             Arc::clone(&catalog),
             BackoffConfig::default(),
+            &metrics,
         ));
 
     // Initialise the deferred table name resolver.
@@ -170,6 +853,7 @@ pub async fn new(
         persist_background_fetch_time,
         Arc::clone(&catalog),
         BackoffConfig::default(),
+        &metrics,
     ));
 
     // Read the most recently created partitions for the shards this ingester
@@ -182,7 +866,7 @@ pub async fn new(
         .repositories()
         .await
         .partitions()
-        .most_recent_n(40_000, &[TRANSITION_SHARD_ID])
+        .most_recent_n(partition_cache_size, &[TRANSITION_SHARD_ID])
         .await
         .map_err(InitError::PreWarmPartitions)?;
 
@@ -191,9 +875,12 @@ pub async fn new(
     let partition_provider = PartitionCache::new(
         partition_provider,
         recent_partitions,
+        partition_cache_max_bytes,
+        partition_cache_ttl,
         persist_background_fetch_time,
         Arc::clone(&catalog),
         BackoffConfig::default(),
+        &metrics,
     );
     let partition_provider: Arc<dyn PartitionProvider> = Arc::new(partition_provider);
 
@@ -204,21 +891,41 @@ pub async fn new(
         Arc::clone(&metrics),
     ));
 
-    // TODO: start hot-partition persist task before replaying the WAL
-    //
-    // By starting the persist task first, the ingester can persist files during
-    // WAL replay if necessary. This could happen if the configuration of the
-    // ingester was changed to persist smaller partitions in-between executions
-    // (such as if the ingester was OOMing during WAL replay, and the
-    // configuration was changed to mitigate it.)
+    // Drop the buffered data of namespaces deleted from the catalog, including
+    // those replayed from the WAL.
+    let namespace_watch_task = tokio::spawn(watch_deleted_namespaces(
+        Arc::clone(&buffer),
+        Arc::clone(&catalog),
+        NAMESPACE_WATCH_INTERVAL,
+    ));
 
-    // Initialise the WAL
-    let wal = Wal::new(wal_directory).await.map_err(InitError::WalInit)?;
+    // The conditions under which the ingester is overloaded and rejects
+    // writes, set by the persist subsystem and memory accounting.
+    let ingest_state = Arc::new(IngestState::new(&metrics));
 
-    // Replay the WAL log files, if any.
-    let max_sequence_number = wal_replay::replay(&wal, &buffer)
+    // Resolve the parquet files uploaded but not committed to the catalog by
+    // a previous instance, before any persist job can start.
+    let pending_uploads = PendingUploads::new(wal_directory.join(PENDING_UPLOADS_DIRECTORY))
+        .await
+        .map_err(InitError::PendingUploads)?;
+    pending_uploads
+        .reconcile(&*catalog, &object_store)
         .await
-        .map_err(|e| InitError::WalReplay(e.into()))?;
+        .map_err(InitError::PendingUploads)?;
+
+    // Load the local cache of persisted parquet files, if enabled.
+    let file_cache = match persist_file_cache_bytes {
+        Some(max_bytes) => Some(Arc::new(
+            ParquetFileCache::new(
+                wal_directory.join(FILE_CACHE_DIRECTORY),
+                max_bytes,
+                &metrics,
+            )
+            .await
+            .map_err(InitError::FileCache)?,
+        )),
+        None => None,
+    };
 
     // Spawn the persist workers to compact partition data, convert it into
     // Parquet files, and upload them to object storage.
@@ -226,38 +933,273 @@ pub async fn new(
         persist_submission_queue_depth,
         persist_workers,
         persist_worker_queue_depth,
+        Arc::new(OldestFirst),
         persist_executor,
-        object_store,
+        object_store.with_writer_options(WriterOptions {
+            compression: persist_parquet_compression,
+            dictionary_enabled: persist_parquet_dictionary,
+            tag_indexes: persist_parquet_tag_indexes,
+        }),
         Arc::clone(&catalog),
+        Arc::clone(&ingest_state),
+        persist_max_file_bytes,
+        persist_max_attempts,
+        persist_namespace_max_jobs,
+        persist_verify_parquet,
+        Some(pending_uploads),
+        file_cache,
+        identity,
+        &metrics,
     );
     let persist_task = tokio::spawn(persist_actor.run());
+    let persist_state_task = tokio::spawn(report_persist_state(
+        Arc::clone(persist_handle.state()),
+        Arc::clone(&metrics),
+    ));
 
-    // Build the chain of DmlSink that forms the write path.
-    let write_path = WalSink::new(Arc::clone(&buffer), wal.write_handle().await);
+    // Start the hot-partition persist task before replaying the WAL.
+    //
+    // By starting the persist task first, the ingester can persist files during
+    // WAL replay if necessary. This could happen if the configuration of the
+    // ingester was changed to persist smaller partitions in-between executions
+    // (such as if the ingester was OOMing during WAL replay, and the
+    // configuration was changed to mitigate it.)
+    let hot_partition_thresholds = HotPartitionThresholds {
+        max_bytes: persist_hot_partition_bytes,
+        max_rows: persist_hot_partition_rows,
+    };
+    let persist_policies = Arc::new(PersistPolicies::default());
+    let persist_policy_task = tokio::spawn(refresh_persist_policies(
+        Arc::clone(&persist_policies),
+        Arc::clone(&catalog),
+        POLICY_REFRESH_INTERVAL,
+    ));
+    let hot_persist_task = tokio::spawn(hot_partition_persist(
+        Arc::clone(&buffer),
+        persist_handle.clone(),
+        hot_partition_thresholds,
+        persist_policies,
+        persist_hot_partition_cold_age,
+    ));
 
-    // Spawn a background thread to periodically rotate the WAL segment file.
-    let handle = tokio::spawn(periodic_rotation(
-        wal,
-        wal_rotation_period,
+    // Start the memory accounting task, which also persists the largest
+    // partitions if the buffer exceeds the soft limit during WAL replay.
+    let memory_tracker = Arc::new(MemoryTracker::new(
+        MemoryLimits {
+            soft_limit_bytes: buffer_soft_limit_bytes,
+            hard_limit_bytes: buffer_hard_limit_bytes,
+        },
+        &metrics,
+    ));
+    let memory_task = tokio::spawn(memory_pressure_persist(
         Arc::clone(&buffer),
-        persist_handle,
+        persist_handle.clone(),
+        memory_tracker,
+        Arc::clone(&ingest_state),
+        Arc::clone(&metrics),
     ));
 
-    // Restore the highest sequence number from the WAL files, and default to 0
-    // if there were no files to replay.
+    // Initialise the WAL
+    let mut wal_options = WalOptions::default();
+    if let Some(bytes) = wal_preallocate_bytes {
+        wal_options = wal_options.with_preallocation(bytes);
+    }
+    if let Some(bytes) = wal_max_bytes {
+        wal_options = wal_options.with_max_total_bytes(bytes);
+    }
+    let wal = Wal::new_with_options(wal_directory.clone(), wal_options)
+        .await
+        .map_err(InitError::WalInit)?;
+
+    // Deletes are recorded as catalog tombstones (to be applied to persisted
+    // data by the querier) before being applied to the buffered data, both
+    // when replaying the WAL and in the write path.
     //
-    // This means sequence numbers are reused across different instances of an
-    // ingester, but they are only used for internal ordering of operations at
-    // runtime.
-    let timestamp = Arc::new(TimestampOracle::new(
-        max_sequence_number
-            .map(|v| u64::try_from(v.get()).expect("sequence number overflow"))
-            .unwrap_or(0),
+    // Partitions exceeding the buffer caps are enqueued for persistence as
+    // they are written to, which also bounds their size during WAL replay.
+    let buffer_sink = Arc::new(PartitionCapSink::new(
+        TombstoneSink::new(
+            Arc::clone(&buffer),
+            Arc::clone(&catalog),
+            BackoffConfig::default(),
+        ),
+        Arc::clone(&buffer),
+        persist_handle.clone(),
+        HotPartitionThresholds {
+            max_bytes: partition_buffer_max_bytes,
+            max_rows: partition_buffer_max_rows,
+        },
+        &metrics,
     ));
 
+    // Connect to the replication peers, establishing the connections on
+    // first use.
+    let replication_peers = replication_peers
+        .into_iter()
+        .map(|addr| {
+            Endpoint::from_shared(addr.clone())
+                .map(|v| (addr.clone(), WriteServiceClient::new(v.connect_lazy())))
+                .map_err(|source| InitError::ReplicationPeer { addr, source })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Writes and queries are rejected until the WAL has been replayed.
+    let readiness = Arc::new(ReadinessProbe::new(&metrics));
+
+    // The WAL replay waits while the ingester is overloaded, allowing the
+    // persist tasks started above to release memory, so a WAL holding more
+    // data than fits within the buffer memory limits can still be replayed.
+    let replay_sink =
+        IngestStateSink::new_blocking(Arc::clone(&buffer_sink), Arc::clone(&ingest_state));
+
+    // Build the chain of DmlSink that forms the write path.
+    //
+    // The completion of every op is recorded by the outermost sink, reporting
+    // the sequence numbers assigned to ops that never complete.
+    let sequence_tracker = Arc::new(SequenceTracker::default());
+    let sequence_gap_task = tokio::spawn(report_sequence_gaps(
+        Arc::clone(&sequence_tracker),
+        Arc::clone(&metrics),
+    ));
+    let write_gate = WriteGate::default();
+    let write_path = SequenceGapSink::new(
+        write_gate.sink(ReadinessSink::new(
+            IdempotencySink::new(
+                IngestStateSink::new(
+                    RateLimitSink::new(
+                        DeletedNamespaceSink::new(
+                            CatalogBreakerSink::new(
+                                SchemaValidationSink::new(
+                                    ReplicationSink::new(
+                                        WalSink::new(
+                                            DurableSink::new(
+                                                DmlSinkTracing::new(buffer_sink, "buffer apply"),
+                                                Arc::clone(&sequence_tracker),
+                                            ),
+                                            wal.write_handle().await,
+                                        ),
+                                        replication_peers,
+                                        replication_policy,
+                                        &metrics,
+                                    ),
+                                    Arc::clone(&catalog),
+                                    BackoffConfig::default(),
+                                ),
+                                Arc::clone(&buffer),
+                                CatalogBreakerConfig::default(),
+                                &metrics,
+                            ),
+                            Arc::clone(&buffer),
+                        ),
+                        RateLimits {
+                            rows_per_second: namespace_write_rows_per_second,
+                            bytes_per_second: namespace_write_bytes_per_second,
+                        },
+                    ),
+                    Arc::clone(&ingest_state),
+                ),
+                idempotency_cache_size,
+            ),
+            Arc::clone(&readiness),
+        )),
+        Arc::clone(&sequence_tracker),
+    );
+
+    // The sequence numbers of new writes continue from the highest sequence
+    // number checkpointed by a previous instance of the ingester, or the
+    // highest sequence number in the WAL files (set once they are replayed) if
+    // greater, defaulting to 0 if there is neither.
+    let checkpoint = read_checkpoint(&wal_directory)
+        .await
+        .map_err(InitError::SequenceCheckpoint)?;
+    let timestamp = Arc::new(TimestampOracle::new(checkpoint.unwrap_or(0)));
+    let checkpoint_task = tokio::spawn(periodic_checkpoint(wal_directory, Arc::clone(&timestamp)));
+
+    // Spawn a background task to replay the WAL log files, if any, and then
+    // periodically rotate the WAL segment file.
+    let backlog_limit = wal_rotation_max_persist_backlog.map(|max| RotationBacklogLimit {
+        max_outstanding_jobs: max,
+        backpressure: wal_rotation_backlog_reject_writes.then(|| Arc::clone(&ingest_state)),
+    });
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+    let handle = tokio::spawn({
+        let buffer = Arc::clone(&buffer);
+        let readiness = Arc::clone(&readiness);
+        let timestamp = Arc::clone(&timestamp);
+        let persist_handle = persist_handle.clone();
+        async move {
+            match wal_replay::replay(
+                &wal,
+                &replay_sink,
+                readiness.progress(),
+                wal_replay_concurrency,
+                wal_replay_namespace_concurrency,
+            )
+            .await
+            {
+                Ok(max_sequence_number) => {
+                    if let Some(v) = max_sequence_number {
+                        timestamp
+                            .advance(u64::try_from(v.get()).expect("sequence number overflow"));
+                    }
+                    readiness.set_ready();
+                    info!("ingester ready");
+                }
+                Err(e) => {
+                    error!(error=%e, "wal replay failed");
+                    readiness.set_failed();
+                    return;
+                }
+            }
+
+            periodic_rotation(
+                wal,
+                wal_rotation_period,
+                buffer,
+                persist_handle,
+                wal_eviction_threshold_bytes.map(|bytes| {
+                    Arc::new(DiskUsageThreshold::new(bytes)) as Arc<dyn EvictionPolicy>
+                }),
+                backlog_limit,
+                shutdown_rx,
+            )
+            .await
+        }
+    });
+
     Ok(IngesterGuard {
-        rpc: GrpcDelegate::new(Arc::new(write_path), buffer, timestamp, catalog, metrics),
-        rotation_task: handle,
+        rpc: GrpcDelegate::new(
+            Arc::new(write_path),
+            Arc::new(QueryExecReadiness::new(
+                QueryExecMemoryLimit::new(
+                    Arc::clone(&buffer),
+                    QueryMemoryLimits {
+                        per_query_bytes: query_memory_limit_bytes,
+                        global_bytes: query_memory_global_limit_bytes,
+                    },
+                    &metrics,
+                ),
+                Arc::clone(&readiness),
+            )),
+            buffer,
+            timestamp,
+            sequence_tracker,
+            catalog,
+            readiness,
+            ingest_state,
+            persist_handle,
+            metrics,
+        ),
+        rotation_task: Mutex::new(Some(handle)),
         persist_task,
+        hot_persist_task,
+        persist_policy_task,
+        memory_task,
+        checkpoint_task,
+        sequence_gap_task,
+        persist_state_task,
+        namespace_watch_task,
+        write_gate,
+        shutdown_tx: Mutex::new(Some(shutdown_tx)),
     })
 }
@@ -0,0 +1,152 @@
+//! Reporting of the ingester's readiness to serve writes and queries.
+
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use super::wal_replay::ReplayProgress;
+
+/// The readiness of an ingester to serve writes and queries, as reported by
+/// [`IngesterRpcInterface::readiness()`].
+///
+/// [`IngesterRpcInterface::readiness()`]: crate::IngesterRpcInterface::readiness
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Readiness {
+    /// The write-ahead log is being replayed - writes and queries are rejected
+    /// until it completes.
+    ReplayingWal {
+        /// The number of WAL segment files replayed so far.
+        segments_replayed: u64,
+        /// The number of WAL segment files to replay.
+        segments_total: u64,
+        /// The number of ops applied to the buffer so far.
+        ops_applied: u64,
+    },
+
+    /// Replaying the write-ahead log failed - the ingester will not become
+    /// ready without being restarted.
+    ReplayFailed,
+
+    /// The ingester is serving writes and queries.
+    Ready,
+}
+
+impl Readiness {
+    /// Returns true if the ingester is serving writes and queries.
+    pub fn is_ready(&self) -> bool {
+        matches!(self, Self::Ready)
+    }
+}
+
+impl Display for Readiness {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ReplayingWal {
+                segments_replayed,
+                segments_total,
+                ops_applied,
+            } => write!(
+                f,
+                "replaying WAL ({segments_replayed} of {segments_total} segments, \
+                {ops_applied} ops applied)"
+            ),
+            Self::ReplayFailed => write!(f, "WAL replay failed"),
+            Self::Ready => write!(f, "ready"),
+        }
+    }
+}
+
+const REPLAYING: u8 = 0;
+const READY: u8 = 1;
+const FAILED: u8 = 2;
+
+/// The shared state from which the current [`Readiness`] is derived, tracking
+/// the progress of the WAL replay until it completes.
+#[derive(Debug)]
+pub(crate) struct ReadinessProbe {
+    progress: ReplayProgress,
+    state: AtomicU8,
+}
+
+impl ReadinessProbe {
+    /// Initialise a new [`ReadinessProbe`] reporting the progress of the WAL
+    /// replay until [`ReadinessProbe::set_ready()`] is called, emitting replay
+    /// metrics to `metrics`.
+    pub(crate) fn new(metrics: &metric::Registry) -> Self {
+        Self {
+            progress: ReplayProgress::new(metrics),
+            state: AtomicU8::new(REPLAYING),
+        }
+    }
+
+    /// The progress of the WAL replay.
+    pub(crate) fn progress(&self) -> &ReplayProgress {
+        &self.progress
+    }
+
+    /// Mark the WAL replay as complete.
+    pub(crate) fn set_ready(&self) {
+        self.state.store(READY, Ordering::Release);
+    }
+
+    /// Mark the WAL replay as failed.
+    pub(crate) fn set_failed(&self) {
+        self.state.store(FAILED, Ordering::Release);
+    }
+
+    /// Return the current [`Readiness`].
+    pub(crate) fn readiness(&self) -> Readiness {
+        match self.state.load(Ordering::Acquire) {
+            READY => Readiness::Ready,
+            FAILED => Readiness::ReplayFailed,
+            _ => Readiness::ReplayingWal {
+                segments_replayed: self.progress.segments_replayed(),
+                segments_total: self.progress.segments_total(),
+                ops_applied: self.progress.ops_applied(),
+            },
+        }
+    }
+
+    /// Return an error describing the current [`Readiness`] if the ingester
+    /// is not ready.
+    pub(crate) fn check(&self) -> Result<(), Readiness> {
+        match self.readiness() {
+            Readiness::Ready => Ok(()),
+            v => Err(v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readiness() {
+        let probe = ReadinessProbe::new(&metric::Registry::default());
+
+        let readiness = probe.readiness();
+        assert_eq!(
+            readiness,
+            Readiness::ReplayingWal {
+                segments_replayed: 0,
+                segments_total: 0,
+                ops_applied: 0
+            }
+        );
+        assert_eq!(
+            readiness.to_string(),
+            "replaying WAL (0 of 0 segments, 0 ops applied)"
+        );
+        assert_eq!(probe.check(), Err(readiness));
+
+        probe.set_ready();
+        assert_eq!(probe.readiness(), Readiness::Ready);
+        assert!(probe.readiness().is_ready());
+        assert_eq!(probe.check(), Ok(()));
+
+        probe.set_failed();
+        assert_eq!(probe.check(), Err(Readiness::ReplayFailed));
+    }
+}
@@ -1,16 +1,28 @@
-use data_types::{NamespaceId, PartitionKey, Sequence, SequenceNumber, TableId};
-use dml::{DmlMeta, DmlOperation, DmlWrite};
-use generated_types::influxdata::iox::wal::v1::sequenced_wal_op::Op;
+use data_types::{NamespaceId, NonEmptyString, PartitionKey, Sequence, SequenceNumber, TableId};
+use dml::{DmlDelete, DmlMeta, DmlOperation, DmlWrite};
+use futures::{future, stream, StreamExt, TryStreamExt};
+use generated_types::{
+    google::{FieldViolation, FromOptionalField},
+    influxdata::iox::wal::v1::sequenced_wal_op::Op,
+};
+use metric::{U64Counter, U64Gauge};
 use mutable_batch_pb::decode::decode_database_batch;
 use observability_deps::tracing::*;
+use std::time::{Duration, Instant};
 use thiserror::Error;
-use wal::Wal;
+use tokio::sync::mpsc;
+use trace::ctx::{SpanContext, SpanId, TraceId};
+use wal::{ClosedSegment, SequencedWalOp, TraceContext, Wal};
 
 use crate::{
     dml_sink::{DmlError, DmlSink},
     TRANSITION_SHARD_INDEX,
 };
 
+/// The number of ops buffered for each namespace lane of a concurrent replay of
+/// a segment file.
+const REPLAY_LANE_DEPTH: usize = 100;
+
 /// Errors returned when replaying the write-ahead log.
 #[derive(Debug, Error)]
 pub(crate) enum WalReplayError {
@@ -26,6 +38,10 @@ pub(crate) enum WalReplayError {
     #[error("failed converting wal entry to dml operation: {0}")]
     MapToDml(#[from] mutable_batch_pb::decode::Error),
 
+    /// An error converting a WAL delete entry into a [`DmlOperation`].
+    #[error("failed converting wal delete entry to dml operation: {0}")]
+    MapDeleteToDml(#[from] FieldViolation),
+
     /// A failure to apply a [`DmlOperation`] from the WAL to the in-memory
     /// [`BufferTree`].
     ///
@@ -34,13 +50,117 @@ pub(crate) enum WalReplayError {
     Apply(#[from] DmlError),
 }
 
+/// Tracks the progress of a WAL replay, reporting it as metrics so operators
+/// can tell how long a restarting ingester will take to come up.
+#[derive(Debug)]
+pub(crate) struct ReplayProgress {
+    segments_total: U64Gauge,
+    segments_replayed: U64Counter,
+    bytes_total: U64Gauge,
+    bytes_read: U64Counter,
+    ops_applied: U64Counter,
+    started_at: Instant,
+}
+
+impl ReplayProgress {
+    pub(crate) fn new(metrics: &metric::Registry) -> Self {
+        let segments_total = metrics
+            .register_metric::<U64Gauge>(
+                "ingester_wal_replay_segments_total",
+                "Number of wal segment files to replay at startup",
+            )
+            .recorder(&[]);
+        let segments_replayed = metrics
+            .register_metric::<U64Counter>(
+                "ingester_wal_replay_segments_replayed",
+                "Number of wal segment files replayed at startup",
+            )
+            .recorder(&[]);
+        let bytes_total = metrics
+            .register_metric::<U64Gauge>(
+                "ingester_wal_replay_bytes_total",
+                "Total size of the wal segment files to replay at startup",
+            )
+            .recorder(&[]);
+        let bytes_read = metrics
+            .register_metric::<U64Counter>(
+                "ingester_wal_replay_bytes_read",
+                "Total size of the wal segment files replayed at startup",
+            )
+            .recorder(&[]);
+        let ops_applied = metrics
+            .register_metric::<U64Counter>(
+                "ingester_wal_replay_ops_applied",
+                "Number of wal ops applied to the buffer during replay",
+            )
+            .recorder(&[]);
+
+        Self {
+            segments_total,
+            segments_replayed,
+            bytes_total,
+            bytes_read,
+            ops_applied,
+            started_at: Instant::now(),
+        }
+    }
+
+    /// The number of segment files to replay.
+    pub(crate) fn segments_total(&self) -> u64 {
+        self.segments_total.fetch()
+    }
+
+    /// The number of segment files replayed so far.
+    pub(crate) fn segments_replayed(&self) -> u64 {
+        self.segments_replayed.fetch()
+    }
+
+    /// The number of ops applied so far.
+    pub(crate) fn ops_applied(&self) -> u64 {
+        self.ops_applied.fetch()
+    }
+
+    /// Estimates the time left to replay the remaining segment files,
+    /// assuming the rest of the bytes are replayed at the rate observed so
+    /// far. Returns [`None`] before the first file has been replayed.
+    pub(crate) fn estimated_remaining(&self) -> Option<Duration> {
+        let read = self.bytes_read.fetch();
+        if read == 0 {
+            return None;
+        }
+        let remaining = self.bytes_total.fetch().saturating_sub(read);
+        Some(
+            self.started_at
+                .elapsed()
+                .mul_f64(remaining as f64 / read as f64),
+        )
+    }
+}
+
 // TODO: tolerate WAL replay errors
 //
 // https://github.com/influxdata/influxdb_iox/issues/6283
 
 /// Replay all the entries in `wal` to `sink`, returning the maximum observed
-/// [`SequenceNumber`].
-pub(crate) async fn replay<T>(wal: &Wal, sink: &T) -> Result<Option<SequenceNumber>, WalReplayError>
+/// [`SequenceNumber`] and reporting progress to `progress`.
+///
+/// Up to `concurrency` segment files are read and applied concurrently. With a
+/// `concurrency` of 1 the files are replayed one at a time, oldest first;
+/// otherwise ops from different files are applied to `sink` in no particular
+/// order, relying on the [`BufferTree`] tolerating reordered writes.
+///
+/// Within each file, the ops for up to `namespace_concurrency` namespaces are
+/// applied concurrently, while the ops for any one namespace are applied in
+/// order.
+///
+/// [`BufferTree`]: crate::buffer_tree::BufferTree
+pub(crate) async fn replay<T>(
+    wal: &Wal,
+    sink: &T,
+    progress: &ReplayProgress,
+    concurrency: usize,
+    namespace_concurrency: usize,
+) -> Result<Option<SequenceNumber>, WalReplayError>
 where
     T: DmlSink,
 {
@@ -49,7 +169,7 @@ where
     // Read the set of files to replay.
     //
     // The WAL yields files ordered from oldest to newest, ensuring the ordering
-    // of this replay is correct.
+    // of a serial replay is correct.
     let files = read_handle.closed_segments().await;
     if files.is_empty() {
         info!("no wal replay files found");
@@ -57,141 +177,274 @@ where
     }
 
     let n_files = files.len();
-    info!(n_files, "found wal files for replay");
+    let total_bytes: u64 = files.iter().map(|f| f.size()).sum();
+    progress.segments_total.set(n_files as u64);
+    progress.bytes_total.set(total_bytes);
+    info!(
+        n_files,
+        total_bytes, concurrency, namespace_concurrency, "found wal files for replay"
+    );
 
     // Replay each file, keeping track of the last observed sequence number.
-    //
-    // Applying writes to the buffer can only happen monotonically and this is
-    // enforced within the buffer.
-    let mut max_sequence = None;
-    for (index, file) in files.into_iter().enumerate() {
-        // Map 0-based iter index to 1 based file count
-        let file_number = index + 1;
-
-        // Read the segment
-        let reader = read_handle
-            .reader_for_segment(file.id())
-            .await
-            .map_err(WalReplayError::OpenSegment)?;
+    let max_sequence = stream::iter(files.into_iter().enumerate())
+        .map(|(index, file)| {
+            // Map 0-based iter index to 1 based file count
+            replay_segment(
+                wal,
+                file,
+                index + 1,
+                n_files,
+                sink,
+                progress,
+                namespace_concurrency,
+            )
+        })
+        .buffer_unordered(concurrency.max(1))
+        .try_fold(None, |max_sequence, file_max_sequence| {
+            future::ready(Ok(max_sequence.max(file_max_sequence)))
+        })
+        .await?;
 
-        // Emit a log entry so progress can be tracked (and a problematic file
-        // be identified should an explosion happen during replay).
-        info!(
+    info!(
+        max_sequence_number = ?max_sequence,
+        n_files = progress.segments_replayed(),
+        ops_applied = progress.ops_applied(),
+        elapsed = ?progress.started_at.elapsed(),
+        "wal replay complete"
+    );
+
+    Ok(max_sequence)
+}
+
+/// Replay the segment `file`, deleting it if it turns out to be empty.
+/// Returns the highest sequence number observed in the file, or [`None`] if
+/// the file was empty.
+async fn replay_segment<T>(
+    wal: &Wal,
+    file: ClosedSegment,
+    file_number: usize,
+    n_files: usize,
+    sink: &T,
+    progress: &ReplayProgress,
+    namespace_concurrency: usize,
+) -> Result<Option<SequenceNumber>, WalReplayError>
+where
+    T: DmlSink,
+{
+    // Read the segment, skipping any damaged entries rather than failing
+    // the replay (and preventing the ingester from starting).
+    let reader = wal
+        .read_handle()
+        .recovering_reader_for_segment(file.id())
+        .await
+        .map_err(WalReplayError::OpenSegment)?;
+
+    // Emit a log entry so progress can be tracked (and a problematic file
+    // be identified should an explosion happen during replay).
+    info!(
+        file_number,
+        n_files,
+        file_id = %file.id(),
+        size = file.size(),
+        ops_applied = progress.ops_applied(),
+        estimated_remaining = ?progress.estimated_remaining(),
+        "replaying wal file"
+    );
+
+    // Replay this segment file
+    let max_sequence = replay_file(reader, sink, progress, namespace_concurrency).await?;
+    progress.segments_replayed.inc(1);
+    progress.bytes_read.inc(file.size());
+
+    if max_sequence.is_none() {
+        // This file was empty and should be deleted.
+        warn!(
             file_number,
             n_files,
             file_id = %file.id(),
             size = file.size(),
-            "replaying wal file"
+            "dropping empty wal segment",
         );
 
-        // Replay this segment file
-        match replay_file(reader, sink).await? {
-            v @ Some(_) => max_sequence = max_sequence.max(v),
-            None => {
-                // This file was empty and should be deleted.
-                warn!(
-                    file_number,
-                    n_files,
-                    file_id = %file.id(),
-                    size = file.size(),
-                    "dropping empty wal segment",
-                );
-
-                // TODO(dom:test): empty WAL replay
-
-                // A failure to delete an empty file should not prevent WAL
-                // replay from continuing.
-                if let Err(error) = wal.rotation_handle().delete(file.id()).await {
-                    error!(
-                        file_number,
-                        n_files,
-                        file_id = %file.id(),
-                        size = file.size(),
-                        %error,
-                        "error dropping empty wal segment",
-                    );
-                }
-            }
-        };
+        // TODO(dom:test): empty WAL replay
+
+        // A failure to delete an empty file should not prevent WAL
+        // replay from continuing.
+        if let Err(error) = wal.rotation_handle().delete(file.id()).await {
+            error!(
+                file_number,
+                n_files,
+                file_id = %file.id(),
+                size = file.size(),
+                %error,
+                "error dropping empty wal segment",
+            );
+        }
     }
 
-    info!(
-        max_sequence_number = ?max_sequence,
-        "wal replay complete"
-    );
-
     Ok(max_sequence)
 }
 
-/// Replay the entries in `file`, applying them to `buffer`. Returns the highest
+/// Replay the entries in `file`, applying them to `sink`. Returns the highest
 /// sequence number observed in the file, or [`None`] if the file was empty.
+///
+/// Ops for up to `namespace_concurrency` namespaces are applied concurrently.
+/// Ops for the same namespace are always applied in the order they were
+/// written, so a delete only ever affects the writes preceding it.
 async fn replay_file<T>(
     mut file: wal::ClosedSegmentFileReader,
     sink: &T,
+    progress: &ReplayProgress,
+    namespace_concurrency: usize,
 ) -> Result<Option<SequenceNumber>, WalReplayError>
 where
     T: DmlSink,
 {
-    let mut max_sequence = None;
-
-    loop {
-        let (sequence_number, op) = match file.next_op().await {
-            Ok(Some(v)) => (v.sequence_number, v.op),
-            Ok(None) => {
-                // This file is complete, return the last observed sequence
-                // number.
-                return Ok(max_sequence);
+    if namespace_concurrency <= 1 {
+        let mut max_sequence = None;
+        while let Some((sequence_number, op)) = read_op(&mut file).await? {
+            max_sequence = max_sequence.max(Some(sequence_number));
+            sink.apply(op).await.map_err(Into::<DmlError>::into)?;
+            progress.ops_applied.inc(1);
+        }
+        return Ok(max_sequence);
+    }
+
+    // Each namespace is assigned to one of `namespace_concurrency` lanes, each
+    // applying its ops in order.
+    let (lanes_tx, lanes_rx): (Vec<_>, Vec<_>) = (0..namespace_concurrency)
+        .map(|_| mpsc::channel::<DmlOperation>(REPLAY_LANE_DEPTH))
+        .unzip();
+
+    let apply = future::try_join_all(lanes_rx.into_iter().map(|mut rx| async move {
+        while let Some(op) = rx.recv().await {
+            sink.apply(op).await.map_err(Into::<DmlError>::into)?;
+            progress.ops_applied.inc(1);
+        }
+        Ok::<_, WalReplayError>(())
+    }));
+
+    // Dropping the senders once the file is read stops the lanes.
+    let read = async move {
+        let mut max_sequence = None;
+        while let Some((sequence_number, op)) = read_op(&mut file).await? {
+            max_sequence = max_sequence.max(Some(sequence_number));
+            let lane = op.namespace_id().get() as usize % namespace_concurrency;
+            if lanes_tx[lane].send(op).await.is_err() {
+                // The lane failed to apply an op, and its error is returned
+                // by the join below.
+                break;
             }
-            Err(e) => return Err(WalReplayError::ReadEntry(e)),
-        };
+        }
+        Ok::<_, WalReplayError>(max_sequence)
+    };
 
-        // For debug logging, emit a log line for each entry in the WAL file to
-        // help identify problematic WAL entries.
-        debug!(?op, sequence_number, "read wal op");
+    let (max_sequence, _) = future::try_join(read, apply).await?;
+    Ok(max_sequence)
+}
 
-        let sequence_number =
-            SequenceNumber::new(i64::try_from(sequence_number).expect("sequence number overflow"));
+/// Read the next op from `file`, returning its sequence number and the
+/// reconstructed [`DmlOperation`], or [`None`] once the file is complete.
+async fn read_op(
+    file: &mut wal::ClosedSegmentFileReader,
+) -> Result<Option<(SequenceNumber, DmlOperation)>, WalReplayError> {
+    let SequencedWalOp {
+        sequence_number,
+        op,
+        trace_context,
+        ingest_timestamp_ns,
+    } = match file.next_op().await {
+        Ok(Some(v)) => v,
+        Ok(None) => {
+            // Report any damaged entries that were skipped - the data
+            // they contained is lost.
+            let corrupt_entries = file
+                .take_corrupt_entries()
+                .await
+                .map_err(WalReplayError::ReadEntry)?;
+            for entry in corrupt_entries {
+                error!(
+                    offset = entry.offset,
+                    previous_sequence_number = ?entry.previous_sequence_number,
+                    reason = %entry.reason,
+                    "skipped corrupt wal entry during replay"
+                );
+            }
 
-        max_sequence = max_sequence.max(Some(sequence_number));
+            // This file is complete.
+            return Ok(None);
+        }
+        Err(e) => return Err(WalReplayError::ReadEntry(e)),
+    };
 
-        let op = match op {
-            Op::Write(w) => w,
-            Op::Delete(_) => unreachable!(),
-            Op::Persist(_) => unreachable!(),
-        };
+    // For debug logging, emit a log line for each entry in the WAL file to
+    // help identify problematic WAL entries.
+    debug!(?op, sequence_number, ?trace_context, "read wal op");
+
+    let sequence_number =
+        SequenceNumber::new(i64::try_from(sequence_number).expect("sequence number overflow"));
+
+    // Restore the ingest time and tracing context of the op, if they were
+    // recorded when it was written.
+    let meta = DmlMeta::sequenced(
+        Sequence {
+            shard_index: TRANSITION_SHARD_INDEX, // TODO: remove this from DmlMeta
+            sequence_number,
+        },
+        ingest_timestamp_ns
+            .map(iox_time::Time::from_timestamp_nanos)
+            .unwrap_or(iox_time::Time::MAX), // TODO: remove this from DmlMeta
+        trace_context.as_ref().and_then(decode_trace_context),
+        42, // TODO: remove this from DmlMeta
+    );
 
-        debug!(?op, sequence_number = sequence_number.get(), "apply wal op");
-
-        // Reconstruct the DML operation
-        let batches = decode_database_batch(&op)?;
-        let namespace_id = NamespaceId::new(op.database_id);
-        let partition_key = PartitionKey::from(op.partition_key);
-
-        let op = DmlWrite::new(
-            namespace_id,
-            batches
-                .into_iter()
-                .map(|(k, v)| (TableId::new(k), v))
-                .collect(),
-            partition_key,
-            // The tracing context should be propagated over the RPC boundary.
-            DmlMeta::sequenced(
-                Sequence {
-                    shard_index: TRANSITION_SHARD_INDEX, // TODO: remove this from DmlMeta
-                    sequence_number,
-                },
-                iox_time::Time::MAX, // TODO: remove this from DmlMeta
-                // TODO: A tracing context should be added for WAL replay.
-                None,
-                42, // TODO: remove this from DmlMeta
-            ),
-        );
+    // Reconstruct the DML operation
+    let op = match op {
+        Op::Write(w) => {
+            debug!(op = ?w, sequence_number = sequence_number.get(), "apply wal op");
+
+            let batches = decode_database_batch(&w)?;
+            DmlOperation::Write(DmlWrite::new(
+                NamespaceId::new(w.database_id),
+                batches
+                    .into_iter()
+                    .map(|(k, v)| (TableId::new(k), v))
+                    .collect(),
+                PartitionKey::from(w.partition_key),
+                meta,
+            ))
+        }
+        Op::Delete(d) => {
+            debug!(op = ?d, sequence_number = sequence_number.get(), "apply wal op");
+
+            DmlOperation::Delete(DmlDelete::new(
+                NamespaceId::new(d.database_id),
+                d.predicate.required("predicate")?,
+                NonEmptyString::new(d.table_name),
+                meta,
+            ))
+        }
+        Op::Persist(_) => unreachable!(),
+    };
 
-        // Apply the operation to the provided DML sink
-        sink.apply(DmlOperation::Write(op))
-            .await
-            .map_err(Into::<DmlError>::into)?;
-    }
+    Ok(Some((sequence_number, op)))
+}
+
+/// Restore the [`SpanContext`] recorded in a WAL entry, or [`None`] if it is
+/// not valid.
+///
+/// The restored context has no trace collector, so it identifies the trace and
+/// span of the original write but spans created from it are not recorded.
+fn decode_trace_context(ctx: &TraceContext) -> Option<SpanContext> {
+    let trace_id = (u128::from(ctx.trace_id_high) << 64) | u128::from(ctx.trace_id_low);
+    Some(SpanContext {
+        trace_id: TraceId::new(trace_id)?,
+        parent_span_id: SpanId::new(ctx.parent_span_id),
+        span_id: SpanId::new(ctx.span_id)?,
+        links: vec![],
+        collector: None,
+        sampled: ctx.sampled,
+    })
 }
 
 #[cfg(test)]
@@ -200,11 +453,11 @@ mod tests {
 
     use assert_matches::assert_matches;
     use data_types::{NamespaceId, PartitionKey, TableId};
-    use wal::Wal;
+    use wal::{test_utils::SegmentBuilder, SegmentId, Wal};
 
     use crate::{
         dml_sink::mock_sink::MockDmlSink,
-        test_util::{assert_dml_writes_eq, make_write_op},
+        test_util::{assert_dml_writes_eq, make_delete_op, make_write_op},
         wal::wal_sink::WalSink,
     };
 
@@ -292,12 +545,19 @@ mod tests {
 
         // Replay the results into a mock to capture the DmlWrites
         let mock_sink = MockDmlSink::default().with_apply_return(vec![Ok(()), Ok(()), Ok(())]);
-        let max_sequence_number = replay(&wal, &mock_sink)
+        let progress = ReplayProgress::new(&metric::Registry::default());
+        let max_sequence_number = replay(&wal, &mock_sink, &progress, 1, 1)
             .await
             .expect("failed to replay WAL");
 
         assert_eq!(max_sequence_number, Some(SequenceNumber::new(42)));
 
+        // Both files (the closed segment and the previously open segment)
+        // were replayed.
+        assert_eq!(progress.segments_replayed(), 2);
+        assert_eq!(progress.ops_applied(), 3);
+        assert_eq!(progress.estimated_remaining(), Some(Duration::ZERO));
+
         // Assert the ops were pushed into the DmlSink
         let ops = mock_sink.get_calls();
         assert_matches!(&*ops, &[DmlOperation::Write(ref w1),DmlOperation::Write(ref w2),DmlOperation::Write(ref w3)] => {
@@ -306,4 +566,307 @@ mod tests {
             assert_dml_writes_eq(w3.clone(), op3);
         })
     }
+
+    #[tokio::test]
+    async fn test_parallel_replay() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // Write one op to each of several segment files
+        {
+            let inner = Arc::new(
+                MockDmlSink::default().with_apply_return((0..4).map(|_| Ok(())).collect()),
+            );
+            let wal = Wal::new(dir.path())
+                .await
+                .expect("failed to initialise WAL");
+            let wal_sink = WalSink::new(Arc::clone(&inner), wal.write_handle().await);
+
+            for sequence_number in [1, 2, 3, 4] {
+                let op = make_write_op(
+                    &PartitionKey::from("p1"),
+                    NAMESPACE_ID,
+                    TABLE_NAME,
+                    TABLE_ID,
+                    sequence_number,
+                    r#"bananas,region=Madrid temp=35 4242424242"#,
+                );
+                wal_sink
+                    .apply(DmlOperation::Write(op))
+                    .await
+                    .expect("wal should not error");
+                wal.rotation_handle()
+                    .rotate()
+                    .await
+                    .expect("failed to rotate WAL file");
+            }
+        }
+
+        let wal = Wal::new(dir.path())
+            .await
+            .expect("failed to initialise WAL");
+
+        // Replay the four segments (and the empty, previously open one) two at
+        // a time.
+        let mock_sink = MockDmlSink::default().with_apply_return((0..4).map(|_| Ok(())).collect());
+        let progress = ReplayProgress::new(&metric::Registry::default());
+        let max_sequence_number = replay(&wal, &mock_sink, &progress, 2, 1)
+            .await
+            .expect("failed to replay WAL");
+
+        assert_eq!(max_sequence_number, Some(SequenceNumber::new(4)));
+        assert_eq!(progress.segments_replayed(), 5);
+        assert_eq!(progress.ops_applied(), 4);
+
+        // All the ops were applied, in some order
+        let mut sequence_numbers: Vec<_> = mock_sink
+            .get_calls()
+            .iter()
+            .map(|op| op.meta().sequence().unwrap().sequence_number.get())
+            .collect();
+        sequence_numbers.sort_unstable();
+        assert_eq!(sequence_numbers, [1, 2, 3, 4]);
+
+        // The empty segment was deleted
+        assert_eq!(wal.read_handle().closed_segments().await.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_namespace_parallel_replay() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // Interleave ops for three namespaces in a single segment file.
+        let ops: Vec<_> = (1..=9)
+            .map(|sequence_number| {
+                let namespace_id = NamespaceId::new(sequence_number % 3);
+                if sequence_number == 7 {
+                    return DmlOperation::Delete(make_delete_op(
+                        namespace_id,
+                        Some(TABLE_NAME),
+                        sequence_number,
+                    ));
+                }
+                DmlOperation::Write(make_write_op(
+                    &PartitionKey::from("p1"),
+                    namespace_id,
+                    TABLE_NAME,
+                    TABLE_ID,
+                    sequence_number,
+                    r#"bananas,region=Madrid temp=35 4242424242"#,
+                ))
+            })
+            .collect();
+
+        {
+            let inner = Arc::new(
+                MockDmlSink::default().with_apply_return((0..9).map(|_| Ok(())).collect()),
+            );
+            let wal = Wal::new(dir.path())
+                .await
+                .expect("failed to initialise WAL");
+            let wal_sink = WalSink::new(Arc::clone(&inner), wal.write_handle().await);
+            for op in ops {
+                wal_sink.apply(op).await.expect("wal should not error");
+            }
+        }
+
+        let wal = Wal::new(dir.path())
+            .await
+            .expect("failed to initialise WAL");
+
+        let mock_sink = MockDmlSink::default().with_apply_return((0..9).map(|_| Ok(())).collect());
+        let progress = ReplayProgress::new(&metric::Registry::default());
+        let max_sequence_number = replay(&wal, &mock_sink, &progress, 1, 2)
+            .await
+            .expect("failed to replay WAL");
+
+        assert_eq!(max_sequence_number, Some(SequenceNumber::new(9)));
+        assert_eq!(progress.ops_applied(), 9);
+
+        // The ops of each namespace were applied in order.
+        let calls = mock_sink.get_calls();
+        for namespace_id in 0..3 {
+            let sequence_numbers: Vec<_> = calls
+                .iter()
+                .filter(|op| op.namespace_id() == NamespaceId::new(namespace_id))
+                .map(|op| op.meta().sequence().unwrap().sequence_number.get())
+                .collect();
+            assert_eq!(sequence_numbers.len(), 3);
+            assert!(sequence_numbers.windows(2).all(|w| w[0] < w[1]));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_delete() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let write = make_write_op(
+            &PartitionKey::from("p1"),
+            NAMESPACE_ID,
+            TABLE_NAME,
+            TABLE_ID,
+            1,
+            r#"bananas,region=Madrid temp=35 4242424242"#,
+        );
+        let delete = make_delete_op(NAMESPACE_ID, Some(TABLE_NAME), 2);
+
+        {
+            let inner = Arc::new(MockDmlSink::default().with_apply_return(vec![Ok(()), Ok(())]));
+            let wal = Wal::new(dir.path())
+                .await
+                .expect("failed to initialise WAL");
+            let wal_sink = WalSink::new(Arc::clone(&inner), wal.write_handle().await);
+
+            wal_sink
+                .apply(DmlOperation::Write(write.clone()))
+                .await
+                .expect("wal should not error");
+            wal_sink
+                .apply(DmlOperation::Delete(delete.clone()))
+                .await
+                .expect("wal should not error");
+        }
+
+        let wal = Wal::new(dir.path())
+            .await
+            .expect("failed to initialise WAL");
+
+        let mock_sink = MockDmlSink::default().with_apply_return(vec![Ok(()), Ok(())]);
+        let progress = ReplayProgress::new(&metric::Registry::default());
+        let max_sequence_number = replay(&wal, &mock_sink, &progress, 1, 1)
+            .await
+            .expect("failed to replay WAL");
+
+        assert_eq!(max_sequence_number, Some(SequenceNumber::new(2)));
+        assert_eq!(progress.ops_applied(), 2);
+
+        // The delete is replayed after the write it follows
+        let ops = mock_sink.get_calls();
+        assert_matches!(&*ops, &[DmlOperation::Write(ref w), DmlOperation::Delete(ref d)] => {
+            assert_dml_writes_eq(w.clone(), write);
+            assert_eq!(d.namespace_id(), delete.namespace_id());
+            assert_eq!(d.table_name(), delete.table_name());
+            assert_eq!(d.predicate(), delete.predicate());
+            assert_eq!(
+                d.meta().sequence().unwrap().sequence_number,
+                SequenceNumber::new(2)
+            );
+        })
+    }
+
+    #[tokio::test]
+    async fn test_replay_damaged_segments() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let ops: Vec<_> = (1..=4)
+            .map(|sequence_number| {
+                make_write_op(
+                    &PartitionKey::from("p1"),
+                    NAMESPACE_ID,
+                    TABLE_NAME,
+                    TABLE_ID,
+                    sequence_number,
+                    r#"bananas,region=Madrid temp=35 4242424242"#,
+                )
+            })
+            .collect();
+        let wal_op = |op: &DmlWrite| wal::SequencedWalOp {
+            sequence_number: op.meta().sequence().unwrap().sequence_number.get() as u64,
+            trace_context: None,
+            ingest_timestamp_ns: None,
+            op: Op::Write(mutable_batch_pb::encode::encode_write(
+                NAMESPACE_ID.get(),
+                op,
+            )),
+        };
+
+        // A closed segment whose first entry was damaged on disk, and the
+        // previously open segment, torn part way through its last entry by a
+        // crash.
+        SegmentBuilder::new(SegmentId::new(0))
+            .with_op(wal_op(&ops[0]))
+            .with_op(wal_op(&ops[1]))
+            .with_corrupt_entry(0)
+            .build(dir.path());
+        SegmentBuilder::new(SegmentId::new(1))
+            .with_op(wal_op(&ops[2]))
+            .with_op(wal_op(&ops[3]))
+            .with_torn_tail(5)
+            .build(dir.path());
+
+        let wal = Wal::new(dir.path())
+            .await
+            .expect("failed to initialise WAL");
+        let mock_sink = MockDmlSink::default().with_apply_return(vec![Ok(()), Ok(())]);
+        let progress = ReplayProgress::new(&metric::Registry::default());
+        let max_sequence_number = replay(&wal, &mock_sink, &progress, 1, 1)
+            .await
+            .expect("failed to replay WAL");
+
+        // The intact ops are replayed
+        assert_eq!(max_sequence_number, Some(SequenceNumber::new(3)));
+        let ops_applied = mock_sink.get_calls();
+        assert_matches!(&*ops_applied, &[DmlOperation::Write(ref w2), DmlOperation::Write(ref w3)] => {
+            assert_dml_writes_eq(w2.clone(), ops[1].clone());
+            assert_dml_writes_eq(w3.clone(), ops[2].clone());
+        });
+    }
+
+    #[tokio::test]
+    async fn test_replay_op_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let span_ctx = SpanContext::new_with_optional_collector(None)
+            .child("write")
+            .ctx;
+        let mut op = make_write_op(
+            &PartitionKey::from("p1"),
+            NAMESPACE_ID,
+            TABLE_NAME,
+            TABLE_ID,
+            1,
+            r#"bananas,region=Madrid temp=35 4242424242"#,
+        );
+        op.set_meta(DmlMeta::sequenced(
+            *op.meta().sequence().unwrap(),
+            iox_time::Time::MIN,
+            Some(span_ctx.clone()),
+            42,
+        ));
+
+        {
+            let inner = Arc::new(MockDmlSink::default().with_apply_return(vec![Ok(())]));
+            let wal = Wal::new(dir.path())
+                .await
+                .expect("failed to initialise WAL");
+            let wal_sink = WalSink::new(Arc::clone(&inner), wal.write_handle().await);
+            wal_sink
+                .apply(DmlOperation::Write(op))
+                .await
+                .expect("wal should not error");
+        }
+
+        let wal = Wal::new(dir.path())
+            .await
+            .expect("failed to initialise WAL");
+        let mock_sink = MockDmlSink::default().with_apply_return(vec![Ok(())]);
+        let progress = ReplayProgress::new(&metric::Registry::default());
+        replay(&wal, &mock_sink, &progress, 1, 1)
+            .await
+            .expect("failed to replay WAL");
+
+        let ops = mock_sink.get_calls();
+        let meta = assert_matches!(&*ops, [DmlOperation::Write(w)] => w.meta());
+
+        // The tracing context of the write is restored
+        let got = meta.span_context().expect("no span context");
+        assert_eq!(got.trace_id, span_ctx.trace_id);
+        assert_eq!(got.span_id, span_ctx.span_id);
+        assert_eq!(got.parent_span_id, span_ctx.parent_span_id);
+        assert_eq!(got.sampled, span_ctx.sampled);
+
+        // As is the (wall clock) time it was written to the WAL
+        let ingest_time = meta.producer_ts().expect("no ingest time");
+        assert!(ingest_time > iox_time::Time::MIN);
+        assert!(ingest_time < iox_time::Time::MAX);
+    }
 }
@@ -1,12 +1,34 @@
-use std::sync::Arc;
+use std::{num::NonZeroUsize, sync::Arc, time::Instant};
 
+use data_types::{NamespaceId, PartitionId};
 use iox_catalog::interface::Catalog;
 use iox_query::exec::Executor;
+use metric::U64Gauge;
+use observability_deps::tracing::*;
 use parquet_file::storage::ParquetStorage;
 use sharder::JumpHash;
-use tokio::{sync::mpsc, task::JoinHandle};
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+    time::MissedTickBehavior,
+};
 
-use super::context::{Context, PersistRequest};
+use crate::init::IngesterIdentity;
+
+use super::{
+    audit::{PersistAudit, PersistAuditRecord, PersistOutcome},
+    completion::CompletionNotifier,
+    context::{Context, PersistRequest},
+    file_cache::ParquetFileCache,
+    metrics::PersistMetrics,
+    pending::PendingUploads,
+    priority::{PersistPriority, PriorityQueue},
+    quarantine::Quarantine,
+    scaling::{desired_workers, WorkerBounds, AUTOSCALE_INTERVAL},
+    serialize::PartitionSerializer,
+    state::PersistState,
+    throttle::NamespaceThrottle,
+};
 
 /// An actor implementation that fans out incoming persistence jobs to a set of
 /// workers.
@@ -21,57 +43,119 @@ pub(crate) struct PersistActor {
     /// THe state/dependencies shared across all worker tasks.
     inner: Arc<Inner>,
 
+    /// The worker tasks, indexed by their worker ID.
+    ///
+    /// Partitions are assigned to the first `n_active` workers - the remaining
+    /// workers are retiring, and are stopped once the jobs already assigned to
+    /// them complete.
+    workers: Vec<Worker>,
+    n_active: usize,
+    worker_queue_depth: usize,
+
+    /// The order in which each worker executes its queued jobs.
+    priority: Arc<dyn PersistPriority>,
+
     /// A consistent hash implementation used to consistently map buffers from
-    /// one partition to the same worker queue.
+    /// one partition to the same active worker ID.
     ///
     /// This ensures persistence is serialised per-partition, but in parallel
-    /// across partitions (up to the number of worker tasks).
-    persist_queues: JumpHash<mpsc::Sender<PersistRequest>>,
+    /// across partitions (up to the number of worker tasks). A partition with
+    /// outstanding jobs remains assigned to the same worker (see
+    /// [`PersistState::assign()`]) when the set of active workers changes.
+    router: JumpHash<usize>,
+
+    /// The bounds on the number of active workers, changed at runtime by the
+    /// [`PersistHandle`].
+    ///
+    /// [`PersistHandle`]: super::handle::PersistHandle
+    bounds: watch::Receiver<WorkerBounds>,
+
+    /// The jobs waiting for the dispatched job of their partition, and the
+    /// jobs held back by the per-namespace limit, dispatched as the jobs of
+    /// their partition / namespace complete (signalled through
+    /// `completions`).
+    serializer: PartitionSerializer<PersistRequest>,
+    throttle: NamespaceThrottle<PersistRequest>,
+    completions: mpsc::UnboundedReceiver<(NamespaceId, PartitionId)>,
 
-    /// Task handles for the worker tasks, aborted on drop of this
-    /// [`PersistActor`].
-    tasks: Vec<JoinHandle<()>>,
+    workers_gauge: U64Gauge,
 }
 
 impl Drop for PersistActor {
     fn drop(&mut self) {
         // Stop all background tasks when the actor goes out of scope.
-        self.tasks.iter().for_each(|v| v.abort())
+        self.workers.iter().for_each(|v| v.task.abort())
     }
 }
 
 impl PersistActor {
+    #[allow(clippy::too_many_arguments)]
     pub(super) fn new(
         rx: mpsc::Receiver<PersistRequest>,
         exec: Arc<Executor>,
         store: ParquetStorage,
         catalog: Arc<dyn Catalog>,
-        workers: usize,
+        bounds: watch::Receiver<WorkerBounds>,
         worker_queue_depth: usize,
+        priority: Arc<dyn PersistPriority>,
+        state: Arc<PersistState>,
+        quarantine: Arc<Quarantine>,
+        notifier: Arc<CompletionNotifier>,
+        audit: Arc<PersistAudit>,
+        max_file_bytes: Option<NonZeroUsize>,
+        max_attempts: NonZeroUsize,
+        namespace_max_jobs: Option<NonZeroUsize>,
+        verify_parquet: bool,
+        pending: Option<PendingUploads>,
+        file_cache: Option<Arc<ParquetFileCache>>,
+        identity: IngesterIdentity,
+        metrics: &metric::Registry,
     ) -> Self {
+        let (completions_tx, completions) = mpsc::unbounded_channel();
         let inner = Arc::new(Inner {
             exec,
             store,
             catalog,
+            state,
+            quarantine,
+            notifier,
+            audit,
+            max_file_bytes,
+            max_attempts,
+            verify_parquet,
+            pending,
+            file_cache,
+            identity,
+            metrics: PersistMetrics::new(metrics),
+            completions: completions_tx,
         });
 
-        let (tx_handles, tasks): (Vec<_>, Vec<_>) = (0..workers)
-            .map(|_| {
-                let inner = Arc::clone(&inner);
-                let (tx, rx) = mpsc::channel(worker_queue_depth);
-                (tx, tokio::spawn(run_task(inner, rx)))
-            })
-            .unzip();
+        let workers_gauge = metrics
+            .register_metric::<U64Gauge>(
+                "ingester_persist_workers",
+                "number of active persist worker tasks",
+            )
+            .recorder(&[]);
 
-        // TODO(test): N workers running
-        // TODO(test): queue depths
-
-        Self {
+        // Start with the minimum number of workers, scaling up as persist
+        // jobs are enqueued.
+        let n = bounds.borrow().min;
+        let mut s = Self {
             rx,
             inner,
-            persist_queues: JumpHash::new(tx_handles),
-            tasks,
-        }
+            workers: Vec::with_capacity(n),
+            n_active: 0,
+            worker_queue_depth,
+            priority,
+            router: JumpHash::new([0]),
+            bounds,
+            serializer: PartitionSerializer::new(metrics),
+            throttle: NamespaceThrottle::new(namespace_max_jobs, metrics),
+            completions,
+            workers_gauge,
+        };
+        s.resize(n);
+        s
     }
 
     /// Execute this actor task and block until all [`PersistHandle`] are
@@ -79,9 +163,118 @@ impl PersistActor {
     ///
     /// [`PersistHandle`]: super::handle::PersistHandle
     pub(crate) async fn run(mut self) {
-        while let Some(req) = self.rx.recv().await {
-            let tx = self.persist_queues.hash(req.partition_id());
-            tx.send(req).await.expect("persist worker has stopped;")
+        let mut autoscale = tokio::time::interval(AUTOSCALE_INTERVAL);
+        autoscale.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut last = self.inner.state.stats().snapshot();
+
+        loop {
+            tokio::select! {
+                req = self.rx.recv() => match req {
+                    Some(req) => {
+                        if let Some(req) = self.serializer.admit(req.partition_id(), req) {
+                            self.admit(req).await;
+                        }
+                    },
+                    None => return,
+                },
+                Some((namespace_id, partition_id)) = self.completions.recv() => {
+                    if let Some(req) = self.throttle.complete(namespace_id) {
+                        self.dispatch(req).await;
+                    }
+                    if let Some(req) = self.serializer.complete(partition_id) {
+                        self.admit(req).await;
+                    }
+                },
+                Ok(()) = self.bounds.changed() => {
+                    let bounds = *self.bounds.borrow();
+                    info!(
+                        min_workers = bounds.min,
+                        max_workers = bounds.max,
+                        target_latency = ?bounds.target_latency,
+                        "persist worker bounds changed"
+                    );
+                    self.resize(self.n_active.clamp(bounds.min, bounds.max));
+                },
+                _ = autoscale.tick() => {
+                    let now = self.inner.state.stats().snapshot();
+                    let mean_latency = (now.0 > last.0)
+                        .then(|| (now.1 - last.1) / (now.0 - last.0) as u32);
+                    last = now;
+
+                    let n = desired_workers(
+                        self.n_active,
+                        &self.bounds.borrow(),
+                        self.inner.state.stats().outstanding(),
+                        mean_latency,
+                    );
+                    self.resize(n);
+                },
+            }
+        }
+    }
+
+    /// Dispatch `req`, the only job of its partition admitted to the workers,
+    /// once the per-namespace limit allows.
+    async fn admit(&mut self, req: PersistRequest) {
+        if let Some(req) = self.throttle.admit(req.namespace_id(), req) {
+            self.dispatch(req).await;
+        }
+    }
+
+    /// Place `req` into the queue of the worker its partition is assigned to.
+    async fn dispatch(&mut self, req: PersistRequest) {
+        let partition_id = req.partition_id();
+        let id = self
+            .inner
+            .state
+            .assign(partition_id, || *self.router.hash(partition_id));
+
+        self.workers[id]
+            .queue
+            .push(partition_id, req.attributes(), req)
+            .await
+    }
+
+    /// Assign partitions to `n` workers, starting new workers as necessary,
+    /// and stopping idle surplus workers.
+    fn resize(&mut self, n: usize) {
+        if n != self.n_active {
+            if self.n_active > 0 {
+                info!(from = self.n_active, to = n, "resizing persist workers");
+            }
+
+            while self.workers.len() < n {
+                self.workers.push(Worker::new(
+                    Arc::clone(&self.inner),
+                    self.worker_queue_depth,
+                    Arc::clone(&self.priority),
+                ));
+            }
+
+            self.n_active = n;
+            self.router = JumpHash::new(0..n);
+            self.inner.state.stats().set_workers(n);
+            self.workers_gauge.set(n as _);
+        }
+
+        self.retire();
+    }
+
+    /// Stop the surplus workers that no partitions are assigned to.
+    ///
+    /// Worker IDs are contiguous, so a surplus worker with assigned partitions
+    /// keeps the idle surplus workers with lower IDs running until it too
+    /// becomes idle.
+    fn retire(&mut self) {
+        while self.workers.len() > self.n_active {
+            let id = self.workers.len() - 1;
+            if self.inner.state.is_assigned(id) {
+                break;
+            }
+
+            // Dropping the worker closes its queue, stopping the (idle) task.
+            self.workers.pop();
+            debug!(worker_id = id, "stopped persist worker");
         }
     }
 }
@@ -90,15 +283,226 @@ pub(super) struct Inner {
     pub(super) exec: Arc<Executor>,
     pub(super) store: ParquetStorage,
     pub(super) catalog: Arc<dyn Catalog>,
+    pub(super) state: Arc<PersistState>,
+
+    /// The jobs that exhausted their attempts, or are waiting for such a job
+    /// of their partition.
+    pub(super) quarantine: Arc<Quarantine>,
+
+    /// Notified of the data persisted by each completed job.
+    pub(super) notifier: Arc<CompletionNotifier>,
+
+    /// Records the outcome of each executed job.
+    pub(super) audit: Arc<PersistAudit>,
+
+    /// The estimated size of the data above which a partition is split into
+    /// multiple parquet files.
+    pub(super) max_file_bytes: Option<NonZeroUsize>,
+
+    /// The number of failed attempts at a request after which a persist job
+    /// is quarantined.
+    pub(super) max_attempts: NonZeroUsize,
+
+    /// Read back each uploaded parquet file, and check it holds the persisted
+    /// data before adding it to the catalog.
+    pub(super) verify_parquet: bool,
+
+    /// The markers of the parquet files uploaded but not yet added to the
+    /// catalog, if recorded.
+    pub(super) pending: Option<PendingUploads>,
+
+    /// The local cache the persisted parquet files are written through, if
+    /// enabled.
+    pub(super) file_cache: Option<Arc<ParquetFileCache>>,
+
+    /// The identity of this ingester, recorded in the provenance of the
+    /// persisted parquet files.
+    pub(super) identity: IngesterIdentity,
+
+    pub(super) metrics: PersistMetrics,
+
+    /// Signals the [`PersistActor`] when a job of a namespace and partition
+    /// completes or is quarantined.
+    pub(super) completions: mpsc::UnboundedSender<(NamespaceId, PartitionId)>,
+}
+
+/// A worker task, and its job queue.
+struct Worker {
+    queue: Arc<PriorityQueue<PersistRequest>>,
+    task: JoinHandle<()>,
+}
+
+impl Worker {
+    fn new(
+        inner: Arc<Inner>,
+        worker_queue_depth: usize,
+        priority: Arc<dyn PersistPriority>,
+    ) -> Self {
+        let queue = Arc::new(PriorityQueue::new(worker_queue_depth, priority));
+        Self {
+            task: tokio::spawn(run_task(inner, Arc::clone(&queue))),
+            queue,
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.queue.close();
+    }
+}
+
+async fn run_task(inner: Arc<Inner>, queue: Arc<PriorityQueue<PersistRequest>>) {
+    while let Some(req) = queue.pop().await {
+        let namespace_id = req.namespace_id();
+        let partition_id = req.partition_id();
+        run_job(&inner, req).await;
+
+        // Release the job's slot in the namespace limit, and the next job of
+        // its partition. The send fails only if the actor has stopped, when
+        // there is nothing left to dispatch.
+        let _ = inner.completions.send((namespace_id, partition_id));
+    }
 }
 
-async fn run_task(inner: Arc<Inner>, mut rx: mpsc::Receiver<PersistRequest>) {
-    while let Some(req) = rx.recv().await {
-        let ctx = Context::new(req, Arc::clone(&inner));
+/// Persist the data of `req`, quarantining it if it fails, and record the
+/// outcome in the audit log.
+async fn run_job(inner: &Arc<Inner>, req: PersistRequest) {
+    let namespace_id = req.namespace_id();
+    let partition_id = req.partition_id();
+    let job_id = req.job_id();
+    let (min_sequence_number, max_sequence_number) = req.sequence_number_range();
+    let started_at = Instant::now();
+    let audit = |outcome| {
+        inner.audit.record(PersistAuditRecord {
+            namespace_id,
+            partition_id,
+            job_id,
+            min_sequence_number,
+            max_sequence_number,
+            duration: started_at.elapsed(),
+            finished_at: Instant::now(),
+            outcome,
+        })
+    };
+
+    if let Some(enqueued_at) = inner.state.enqueued_at(job_id) {
+        inner
+            .metrics
+            .queue_duration
+            .record(started_at.saturating_duration_since(enqueued_at));
+    }
+
+    // The data of a partition must be persisted in order - a job enqueued
+    // after a quarantined job of its partition cannot run before it.
+    if !req.is_next_persisted() {
+        warn!(
+            %partition_id,
+            job_id,
+            "quarantining persist job waiting for an earlier job of its partition"
+        );
+        let error = "waiting for a quarantined persist job of the partition".to_string();
+        inner.state.remove(partition_id, job_id);
+        inner.metrics.job_failed("wait for earlier job");
+        audit(PersistOutcome::Quarantined {
+            error: error.clone(),
+        });
+        inner.quarantine.insert(req, error);
+        return;
+    }
+
+    match Context::new(req, Arc::clone(inner)).persist().await {
+        Ok(object_store_ids) => {
+            inner
+                .state
+                .complete(partition_id, job_id, started_at.elapsed());
+            audit(PersistOutcome::Persisted { object_store_ids });
+        }
+        Err((req, e)) => {
+            // The data remains buffered in the partition (and the WAL
+            // segments containing it are retained) until the job is
+            // re-enqueued and succeeds.
+            error!(
+                %partition_id,
+                job_id,
+                error=%e,
+                "persist job failed, quarantining"
+            );
+            inner.state.remove(partition_id, job_id);
+            inner.metrics.job_failed(e.operation());
+            audit(PersistOutcome::Quarantined {
+                error: e.to_string(),
+            });
+            inner.quarantine.insert(req, e.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use iox_catalog::mem::MemCatalog;
+    use object_store::memory::InMemory;
+    use parquet_file::storage::StorageId;
+
+    use super::*;
+    use crate::persist::{priority::OldestFirst, scaling::DEFAULT_TARGET_LATENCY};
+
+    #[tokio::test]
+    async fn test_resize_retains_assigned_workers() {
+        let metrics = metric::Registry::default();
+        let (_tx, rx) = mpsc::channel(1);
+        let (_bounds_tx, bounds) = watch::channel(WorkerBounds {
+            min: 1,
+            max: 4,
+            target_latency: DEFAULT_TARGET_LATENCY,
+        });
+
+        let mut actor = PersistActor::new(
+            rx,
+            Arc::new(Executor::new_testing()),
+            ParquetStorage::new(Arc::new(InMemory::new()), StorageId::from("iox")),
+            Arc::new(MemCatalog::new(Arc::new(metric::Registry::default()))),
+            bounds,
+            1,
+            Arc::new(OldestFirst),
+            Arc::new(PersistState::new(&metrics)),
+            Arc::new(Quarantine::new(&metrics)),
+            Default::default(),
+            Default::default(),
+            None,
+            NonZeroUsize::new(1).unwrap(),
+            None,
+            false,
+            None,
+            None,
+            Default::default(),
+            &metrics,
+        );
+        assert_eq!(actor.workers.len(), 1);
+
+        actor.resize(4);
+        assert_eq!(actor.workers.len(), 4);
+        assert_eq!(actor.inner.state.stats().workers(), 4);
+
+        // A partition with an outstanding job on worker 2 keeps it (and the
+        // workers before it) running when scaling down.
+        let partition_id = PartitionId::new(42);
+        let job_id = actor.inner.state.enqueue();
+        actor.inner.state.assign(partition_id, || 2);
+        actor.resize(1);
+        assert_eq!(actor.n_active, 1);
+        assert_eq!(actor.workers.len(), 3);
 
-        let compacted = ctx.compact().await;
-        let (sort_key_update, parquet_table_data) = ctx.upload(compacted).await;
-        ctx.update_database(sort_key_update, parquet_table_data)
-            .await;
+        // Once the job completes, the surplus workers are stopped.
+        actor
+            .inner
+            .state
+            .complete(partition_id, job_id, Duration::from_secs(1));
+        assert!(!actor.inner.state.is_assigned(2));
+        assert_eq!(actor.inner.state.stats().outstanding(), 0);
+        actor.resize(1);
+        assert_eq!(actor.workers.len(), 1);
     }
 }
@@ -0,0 +1,117 @@
+//! A bounded log of the outcomes of the persist jobs, for operators to
+//! determine when a partition was last persisted, and why it failed.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use data_types::{NamespaceId, PartitionId, SequenceNumber};
+use parking_lot::Mutex;
+use uuid::Uuid;
+
+/// The number of persist job outcomes retained by [`PersistAudit`].
+const MAX_AUDIT_RECORDS: usize = 1_000;
+
+/// The outcome of a persist job.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum PersistOutcome {
+    /// The job added its parquet files to the catalog.
+    Persisted {
+        /// The object store IDs of the parquet files written by the job.
+        object_store_ids: Vec<Uuid>,
+    },
+    /// The job was quarantined, either after exhausting its attempts or
+    /// behind an earlier quarantined job of its partition.
+    Quarantined { error: String },
+}
+
+/// The outcome of a single execution of a persist job.
+#[derive(Debug, Clone)]
+pub(crate) struct PersistAuditRecord {
+    pub(crate) namespace_id: NamespaceId,
+    pub(crate) partition_id: PartitionId,
+    pub(crate) job_id: u64,
+
+    /// The range of the [`SequenceNumber`] of the writes persisted (or not)
+    /// by the job.
+    pub(crate) min_sequence_number: SequenceNumber,
+    pub(crate) max_sequence_number: SequenceNumber,
+
+    /// How long the worker executed the job for.
+    pub(crate) duration: Duration,
+    pub(crate) finished_at: Instant,
+    pub(crate) outcome: PersistOutcome,
+}
+
+/// The outcomes of the most recently executed persist jobs, oldest first.
+///
+/// A job re-enqueued from the quarantine is recorded again when it is
+/// executed, so a partition may have several records for the same data.
+#[derive(Debug, Default)]
+pub(crate) struct PersistAudit {
+    records: Mutex<VecDeque<PersistAuditRecord>>,
+}
+
+impl PersistAudit {
+    /// Record the outcome of an executed persist job, evicting the oldest
+    /// record if [`MAX_AUDIT_RECORDS`] are already held.
+    pub(super) fn record(&self, record: PersistAuditRecord) {
+        let mut records = self.records.lock();
+        if records.len() == MAX_AUDIT_RECORDS {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// Return the records of `partition_id` (or of all partitions, if
+    /// [`None`]), oldest first.
+    pub(crate) fn records(&self, partition_id: Option<PartitionId>) -> Vec<PersistAuditRecord> {
+        self.records
+            .lock()
+            .iter()
+            .filter(|r| partition_id.map_or(true, |id| r.partition_id == id))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(partition_id: i64, job_id: u64) -> PersistAuditRecord {
+        PersistAuditRecord {
+            namespace_id: NamespaceId::new(1),
+            partition_id: PartitionId::new(partition_id),
+            job_id,
+            min_sequence_number: SequenceNumber::new(1),
+            max_sequence_number: SequenceNumber::new(2),
+            duration: Duration::from_secs(1),
+            finished_at: Instant::now(),
+            outcome: PersistOutcome::Persisted {
+                object_store_ids: vec![Uuid::nil()],
+            },
+        }
+    }
+
+    #[test]
+    fn test_audit_bounded() {
+        let audit = PersistAudit::default();
+        for job_id in 0..(MAX_AUDIT_RECORDS as u64 + 2) {
+            audit.record(record((job_id % 2) as i64, job_id));
+        }
+
+        // The oldest records are evicted.
+        let got = audit.records(None);
+        assert_eq!(got.len(), MAX_AUDIT_RECORDS);
+        assert_eq!(got.first().unwrap().job_id, 2);
+        assert_eq!(got.last().unwrap().job_id, MAX_AUDIT_RECORDS as u64 + 1);
+
+        // The records of a single partition are returned in order.
+        let got = audit.records(Some(PartitionId::new(1)));
+        assert_eq!(got.len(), MAX_AUDIT_RECORDS / 2);
+        assert!(got.iter().all(|r| r.partition_id == PartitionId::new(1)));
+        assert!(got.windows(2).all(|w| w[0].job_id < w[1].job_id));
+    }
+}
@@ -0,0 +1,63 @@
+//! Notifying subscribers of the partition data durably persisted.
+
+use data_types::{NamespaceId, PartitionId, SequenceNumber, TableId};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// The number of completions retained for a subscriber that has not yet
+/// received them, above which the subscriber misses the oldest completions.
+const COMPLETION_CHANNEL_CAPACITY: usize = 1_000;
+
+/// A persist job that completed, after adding its parquet files to the
+/// catalog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PersistCompletion {
+    pub(crate) namespace_id: NamespaceId,
+    pub(crate) table_id: TableId,
+    pub(crate) partition_id: PartitionId,
+
+    /// The largest [`SequenceNumber`] of the writes persisted by the job.
+    ///
+    /// The data of a partition is persisted in order, so all the writes to
+    /// the partition up to and including this watermark that were buffered
+    /// when the job was enqueued are durable in object storage.
+    pub(crate) max_sequence_number: SequenceNumber,
+
+    /// The object store IDs of the parquet files written by the job.
+    pub(crate) object_store_ids: Vec<Uuid>,
+}
+
+/// Broadcasts a [`PersistCompletion`] to all subscribers as each persist job
+/// completes.
+///
+/// Completions are not retained for future subscribers - a caller awaiting
+/// the persistence of some data must subscribe before its persist job is
+/// enqueued.
+#[derive(Debug)]
+pub(crate) struct CompletionNotifier {
+    tx: broadcast::Sender<PersistCompletion>,
+}
+
+impl Default for CompletionNotifier {
+    fn default() -> Self {
+        let (tx, _rx) = broadcast::channel(COMPLETION_CHANNEL_CAPACITY);
+        Self { tx }
+    }
+}
+
+impl CompletionNotifier {
+    /// Notify the current subscribers of `completion`.
+    pub(super) fn notify(&self, completion: PersistCompletion) {
+        // Sending fails only if there are no subscribers.
+        let _ = self.tx.send(completion);
+    }
+
+    /// Receive the completions of the persist jobs from now on.
+    ///
+    /// A subscriber that falls more than [`COMPLETION_CHANNEL_CAPACITY`]
+    /// completions behind misses the oldest, observing a
+    /// [`broadcast::error::RecvError::Lagged`] error.
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<PersistCompletion> {
+        self.tx.subscribe()
+    }
+}
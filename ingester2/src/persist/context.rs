@@ -1,31 +1,90 @@
-use std::sync::Arc;
+use std::{fmt::Display, future::Future, io, sync::Arc, time::Instant};
 
-use backoff::Backoff;
+use backoff::{Backoff, BackoffConfig};
 use data_types::{
     CompactionLevel, NamespaceId, ParquetFileParams, PartitionId, PartitionKey, SequenceNumber,
-    TableId,
+    TableId, TableSchema,
 };
+use datafusion::physical_plan::SendableRecordBatchStream;
 use iox_catalog::interface::get_table_schema_by_id;
 use iox_time::{SystemProvider, TimeProvider};
+use object_store::path::Path as ObjectPath;
 use observability_deps::tracing::*;
 use parking_lot::Mutex;
-use parquet_file::metadata::IoxMetadata;
+use parquet_file::{
+    metadata::{IoxMetadata, Provenance},
+    storage::UploadError,
+    ParquetFilePath,
+};
 use schema::sort::SortKey;
+use thiserror::Error;
 use tokio::sync::Notify;
 use uuid::Uuid;
 
 use crate::{
     buffer_tree::{
         namespace::NamespaceName,
-        partition::{persisting::PersistingData, PartitionData, SortKeyState},
+        partition::{
+            persisting::{BatchIdent, PersistingData},
+            PartitionData, SortKeyState,
+        },
         table::TableName,
     },
     deferred_load::DeferredLoad,
     persist::compact::{compact_persisting_batch, CompactedStream},
+    query_adaptor::QueryAdaptor,
     TRANSITION_SHARD_ID,
 };
 
-use super::actor::Inner;
+use super::{
+    actor::Inner,
+    completion::PersistCompletion,
+    metrics::WaitTimedStream,
+    priority::JobAttributes,
+    state::PersistStage,
+    verify::{verify_parquet, Expected, VerifyError},
+};
+
+/// A persist job that exhausted its attempts at `operation`.
+#[derive(Debug, Error)]
+#[error("{operation} failed after {attempts} attempts: {error}")]
+pub(super) struct PersistFailed {
+    operation: &'static str,
+    attempts: usize,
+    error: String,
+}
+
+impl PersistFailed {
+    /// The operation that exhausted the job's attempts.
+    pub(super) fn operation(&self) -> &'static str {
+        self.operation
+    }
+}
+
+/// A failed attempt at writing a parquet file.
+#[derive(Debug, Error)]
+enum WriteError {
+    #[error(transparent)]
+    Upload(#[from] UploadError),
+
+    #[error("uploaded file failed verification: {0}")]
+    Verify(#[from] VerifyError),
+
+    #[error("failed to write pending upload marker: {0}")]
+    Marker(io::Error),
+}
+
+impl WriteError {
+    /// The cause of the failure, as reported in metrics.
+    fn cause(&self) -> &'static str {
+        match self {
+            Self::Upload(UploadError::Upload(_)) => "upload",
+            Self::Upload(_) => "encode",
+            Self::Verify(_) => "verify",
+            Self::Marker(_) => "marker",
+        }
+    }
+}
 
 /// An internal type that contains all necessary information to run a persist task.
 ///
@@ -35,22 +94,79 @@ pub(super) struct PersistRequest {
     complete: Arc<Notify>,
     partition: Arc<Mutex<PartitionData>>,
     data: PersistingData,
+    job_id: u64,
 }
 
 impl PersistRequest {
-    pub(super) fn new(partition: Arc<Mutex<PartitionData>>, data: PersistingData) -> Self {
+    pub(super) fn new(
+        partition: Arc<Mutex<PartitionData>>,
+        data: PersistingData,
+        job_id: u64,
+    ) -> Self {
         Self {
             complete: Arc::new(Notify::default()),
             partition,
             data,
+            job_id,
         }
     }
 
+    /// Return the ID assigned to this job by the [`PersistState`].
+    ///
+    /// [`PersistState`]: super::state::PersistState
+    pub(super) fn job_id(&self) -> u64 {
+        self.job_id
+    }
+
+    /// Return the ID of the namespace of the persisting data.
+    pub(super) fn namespace_id(&self) -> NamespaceId {
+        self.partition.lock().namespace_id()
+    }
+
     /// Return the partition ID of the persisting data.
     pub(super) fn partition_id(&self) -> PartitionId {
         self.data.partition_id()
     }
 
+    /// Return the [`BatchIdent`] of the persisting data, which orders the
+    /// persist jobs of a partition.
+    pub(super) fn batch_ident(&self) -> BatchIdent {
+        self.data.batch_ident()
+    }
+
+    /// Returns true if the persisting data is the next to be persisted from
+    /// its partition.
+    ///
+    /// The persist jobs of a partition must complete in the order their data
+    /// was marked as persisting.
+    pub(super) fn is_next_persisted(&self) -> bool {
+        self.partition.lock().is_next_persisted(&self.data)
+    }
+
+    /// Return the smallest and largest [`SequenceNumber`] of the persisting
+    /// data.
+    pub(super) fn sequence_number_range(&self) -> (SequenceNumber, SequenceNumber) {
+        (
+            self.data.min_sequence_number(),
+            self.data.max_sequence_number(),
+        )
+    }
+
+    /// Return the properties of this job ordering it in a worker queue.
+    pub(super) fn attributes(&self) -> JobAttributes {
+        JobAttributes {
+            job_id: self.job_id,
+            min_sequence_number: self.data.min_sequence_number(),
+            size: self.data.estimate_size(),
+        }
+    }
+
+    /// Assign the job ID `job_id` to this request, when re-enqueuing it.
+    pub(super) fn with_job_id(mut self, job_id: u64) -> Self {
+        self.job_id = job_id;
+        self
+    }
+
     /// Obtain the completion notification handle for this request.
     ///
     /// This notification is fired once persistence is complete.
@@ -64,6 +180,11 @@ pub(super) struct Context {
     data: PersistingData,
     inner: Arc<Inner>,
 
+    /// The ID assigned to this job by the [`PersistState`].
+    ///
+    /// [`PersistState`]: super::state::PersistState
+    job_id: u64,
+
     /// IDs loaded from the partition at construction time.
     namespace_id: NamespaceId,
     table_id: TableId,
@@ -90,11 +211,22 @@ pub(super) struct Context {
     /// to need to update the sort key.
     sort_key: SortKeyState,
 
+    /// The failed attempts made by this job, shared by all the requests it
+    /// makes.
+    attempts: Mutex<Attempts>,
+
     /// A notification signal to indicate to the caller that this partition has
     /// persisted.
     complete: Arc<Notify>,
 }
 
+/// The failed attempts of a persist job, and the backoff before the next.
+#[derive(Debug)]
+struct Attempts {
+    failures: usize,
+    backoff: Backoff,
+}
+
 impl Context {
     /// Construct a persistence job [`Context`] from `req`.
     ///
@@ -116,6 +248,7 @@ impl Context {
                 partition: req.partition,
                 data: req.data,
                 inner,
+                job_id: req.job_id,
                 namespace_id: guard.namespace_id(),
                 table_id: guard.table_id(),
                 partition_id,
@@ -127,6 +260,10 @@ impl Context {
                 // during the execution of this persist.
                 sort_key: guard.sort_key().clone(),
 
+                attempts: Mutex::new(Attempts {
+                    failures: 0,
+                    backoff: Backoff::new(&BackoffConfig::default()),
+                }),
                 complete,
             }
         };
@@ -142,7 +279,111 @@ impl Context {
         s
     }
 
-    pub(super) async fn compact(&self) -> CompactedStream {
+    /// Persist the data of this job, and mark it as persisted in the
+    /// partition.
+    ///
+    /// Returns the object store IDs of the parquet files written.
+    ///
+    /// If any request made by the job fails more than the configured maximum
+    /// attempts, the job is abandoned and returned as a [`PersistRequest`]
+    /// alongside the error, leaving the data in the partition.
+    pub(super) async fn persist(self) -> Result<Vec<Uuid>, (PersistRequest, PersistFailed)> {
+        let res = match self.write_files().await {
+            Ok((sort_key_update, parquet_files)) => {
+                let object_store_ids = parquet_files.iter().map(|f| f.object_store_id).collect();
+                self.update_database(sort_key_update, parquet_files)
+                    .await
+                    .map(|()| object_store_ids)
+            }
+            Err(e) => Err(e),
+        };
+
+        match res {
+            Ok(object_store_ids) => {
+                self.mark_complete(object_store_ids.clone());
+                Ok(object_store_ids)
+            }
+            Err(e) => Err((self.into_request(), e)),
+        }
+    }
+
+    /// Split the persisting data into the parts to be written to separate
+    /// parquet files.
+    ///
+    /// If the data is estimated to exceed the configured maximum file size, it
+    /// is split into several parts covering consecutive time ranges.
+    fn split(&self) -> Vec<QueryAdaptor> {
+        assert!(!self.data.record_batches().is_empty());
+
+        let data = self.data.query_adaptor();
+        let parts = match self.inner.max_file_bytes {
+            Some(max) => data.split_time_ranges(max.get()),
+            None => vec![data],
+        };
+
+        if parts.len() > 1 {
+            info!(
+                namespace_id = %self.namespace_id,
+                namespace_name = %self.namespace_name,
+                table_id = %self.table_id,
+                table_name = %self.table_name,
+                partition_id = %self.partition_id,
+                partition_key = %self.partition_key,
+                n_files = parts.len(),
+                "splitting oversized partition into multiple parquet files"
+            );
+        }
+
+        parts
+    }
+
+    /// Compact and upload each part of the persisting data to a parquet file,
+    /// returning the sort key update (if any) and the catalog entries of the
+    /// files.
+    ///
+    /// Each part is compacted with the sort key extended by the parts before
+    /// it, so that the last sort key update covers the columns of all the
+    /// files.
+    async fn write_files(
+        &self,
+    ) -> Result<(Option<SortKey>, Vec<ParquetFileParams>), PersistFailed> {
+        // Read the table schema from the catalog to act as a map of column name
+        // -> column IDs.
+        let table_schema = self
+            .retry("get table schema", || async {
+                let mut repos = self.inner.catalog.repositories().await;
+                get_table_schema_by_id(self.table_id, repos.as_mut()).await
+            })
+            .await?;
+
+        let parts = self.split();
+        let mut sort_key = self.sort_key.get().await;
+        let mut sort_key_update = None;
+        let mut parquet_files = Vec::with_capacity(parts.len());
+        for part in parts {
+            let expected = self.inner.verify_parquet.then(|| Expected::new(&part));
+
+            // The compacted stream is consumed by a failed upload, so the part
+            // is compacted again before each attempt.
+            let (update, parquet_table_data) = self
+                .retry("upload parquet", || async {
+                    let compacted = self.compact(part.clone(), sort_key.clone()).await;
+                    self.upload(compacted, &table_schema, expected).await
+                })
+                .await?;
+
+            if let Some(update) = update {
+                sort_key = Some(update.clone());
+                sort_key_update = Some(update);
+            }
+            parquet_files.push(parquet_table_data);
+        }
+
+        Ok((sort_key_update, parquet_files))
+    }
+
+    /// Compact `part` with the partition sort key `sort_key`.
+    async fn compact(&self, part: QueryAdaptor, sort_key: Option<SortKey>) -> CompactedStream {
         debug!(
             namespace_id = %self.namespace_id,
             namespace_name = %self.namespace_name,
@@ -153,7 +394,9 @@ impl Context {
             "compacting partition"
         );
 
-        assert!(!self.data.record_batches().is_empty());
+        self.inner
+            .state
+            .set_stage(self.partition_id, PersistStage::Compacting);
 
         // Run a compaction sort the data and resolve any duplicate values.
         //
@@ -161,24 +404,36 @@ impl Context {
         // to be loaded before compaction starts.
         compact_persisting_batch(
             &self.inner.exec,
-            self.sort_key.get().await,
+            sort_key,
             self.table_name.get().await,
-            self.data.query_adaptor(),
+            part,
         )
         .await
         .expect("unable to compact persisting batch")
     }
 
-    pub(super) async fn upload(
+    /// Upload the `compacted` data to a parquet file in object storage,
+    /// returning the sort key update (if any) and the catalog entry of the
+    /// file.
+    ///
+    /// If `expected` is provided, the uploaded file is read back and checked
+    /// to hold the expected data before it is returned.
+    async fn upload(
         &self,
         compacted: CompactedStream,
-    ) -> (Option<SortKey>, ParquetFileParams) {
+        table_schema: &TableSchema,
+        expected: Option<Expected>,
+    ) -> Result<(Option<SortKey>, ParquetFileParams), WriteError> {
         let CompactedStream {
             stream: record_stream,
             catalog_sort_key_update,
             data_sort_key,
         } = compacted;
 
+        self.inner
+            .state
+            .set_stage(self.partition_id, PersistStage::Uploading);
+
         // Generate a UUID to uniquely identify this parquet file in
         // object storage.
         let object_store_id = Uuid::new_v4();
@@ -196,9 +451,10 @@ impl Context {
         );
 
         // Construct the metadata for this parquet file.
+        let now = SystemProvider::new().now();
         let iox_metadata = IoxMetadata {
             object_store_id,
-            creation_timestamp: SystemProvider::new().now(),
+            creation_timestamp: now,
             shard_id: TRANSITION_SHARD_ID,
             namespace_id: self.namespace_id,
             namespace_name: Arc::clone(&*self.namespace_name.get().await),
@@ -206,20 +462,81 @@ impl Context {
             table_name: Arc::clone(&*self.table_name.get().await),
             partition_id: self.partition_id,
             partition_key: self.partition_key.clone(),
-            max_sequence_number: SequenceNumber::new(0), // TODO: not ordered!
+            // Tombstones with a greater sequence number are applied to this
+            // file by the querier.
+            max_sequence_number: self.data.max_sequence_number(),
             compaction_level: CompactionLevel::Initial,
             sort_key: Some(data_sort_key),
+            provenance: Some(Provenance {
+                host: Arc::from(self.inner.identity.host.as_str()),
+                version: Arc::from(self.inner.identity.version.as_str()),
+                min_sequence_number: self.data.min_sequence_number(),
+                max_sequence_number: self.data.max_sequence_number(),
+                persisted_at: now,
+            }),
         };
 
-        // Save the compacted data to a parquet file in object storage.
-        //
-        // This call retries until it completes.
-        let (md, file_size) = self
+        // Durably mark the file as uploaded but uncommitted before the upload
+        // starts, so that it is found and resolved at the next startup should
+        // the ingester crash before the file is added to the catalog.
+        let path = self
             .inner
             .store
-            .upload(record_stream, &iox_metadata)
+            .object_store_path(&ParquetFilePath::from(&iox_metadata));
+        if let Some(pending) = &self.inner.pending {
+            pending
+                .begin(object_store_id, &path)
+                .await
+                .map_err(WriteError::Marker)?;
+        }
+
+        match self
+            .write_parquet(record_stream, &iox_metadata, table_schema, expected)
             .await
-            .expect("unexpected fatal persist error");
+        {
+            Ok(parquet_table_data) => Ok((catalog_sort_key_update, parquet_table_data)),
+            Err(e) => {
+                self.inner.metrics.write_failed(e.cause());
+                self.discard(object_store_id, &path).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Write the `record_stream` to the parquet file described by
+    /// `iox_metadata`, returning its catalog entry.
+    async fn write_parquet(
+        &self,
+        record_stream: SendableRecordBatchStream,
+        iox_metadata: &IoxMetadata,
+        table_schema: &TableSchema,
+        expected: Option<Expected>,
+    ) -> Result<ParquetFileParams, WriteError> {
+        let object_store_id = iox_metadata.object_store_id;
+        let metrics = &self.inner.metrics;
+
+        // Encode the compacted data to a parquet file, executing the
+        // compaction plan as the record batches are consumed.
+        let (record_stream, compact_duration) = WaitTimedStream::new(record_stream);
+        let started_at = Instant::now();
+        let (data, md) = self
+            .inner
+            .store
+            .encode(Box::pin(record_stream), iox_metadata)
+            .await?;
+        let compact_duration = *compact_duration.lock();
+        metrics.compact_duration.record(compact_duration);
+        metrics
+            .encode_duration
+            .record(started_at.elapsed().saturating_sub(compact_duration));
+
+        // Save the parquet file to object storage.
+        //
+        // This call makes a single attempt, leaving the caller to retry.
+        let file_size = data.len();
+        let started_at = Instant::now();
+        self.inner.store.try_put(data.clone(), iox_metadata).await?;
+        metrics.upload_duration.record(started_at.elapsed());
 
         debug!(
             namespace_id = %self.namespace_id,
@@ -233,16 +550,6 @@ impl Context {
             "partition parquet uploaded"
         );
 
-        // Read the table schema from the catalog to act as a map of column name
-        // -> column IDs.
-        let table_schema = Backoff::new(&Default::default())
-            .retry_all_errors("get table schema", || async {
-                let mut repos = self.inner.catalog.repositories().await;
-                get_table_schema_by_id(self.table_id, repos.as_mut()).await
-            })
-            .await
-            .expect("retry forever");
-
         // Build the data that must be inserted into the parquet_files catalog
         // table in order to make the file visible to queriers.
         let parquet_table_data =
@@ -250,19 +557,78 @@ impl Context {
                 table_schema.columns.get(name).expect("unknown column").id
             });
 
-        (catalog_sort_key_update, parquet_table_data)
+        if let Some(expected) = expected {
+            verify_parquet(
+                &self.inner.store,
+                iox_metadata,
+                &parquet_table_data,
+                expected,
+            )
+            .await?;
+
+            debug!(
+                namespace_id = %self.namespace_id,
+                namespace_name = %self.namespace_name,
+                table_id = %self.table_id,
+                table_name = %self.table_name,
+                partition_id = %self.partition_id,
+                partition_key = %self.partition_key,
+                %object_store_id,
+                "partition parquet verified"
+            );
+        }
+
+        // Keep a local copy of the uploaded (and verified) file.
+        if let Some(cache) = &self.inner.file_cache {
+            cache.insert(object_store_id, data).await;
+        }
+
+        Ok(parquet_table_data)
     }
 
-    pub(crate) async fn update_database(
-        self,
-        sort_key_update: Option<SortKey>,
-        parquet_table_data: ParquetFileParams,
-    ) {
-        // Extract the object store ID to the local scope so that it can easily
-        // be referenced in debug logging to aid correlation of persist events
-        // for a specific file.
-        let object_store_id = parquet_table_data.object_store_id;
+    /// Delete the (possibly partially) uploaded parquet file
+    /// `object_store_id` at `path` after a failed attempt, and remove its
+    /// marker.
+    ///
+    /// Failures are logged, leaving the file to be resolved by its marker at
+    /// the next startup.
+    async fn discard(&self, object_store_id: Uuid, path: &ObjectPath) {
+        match self
+            .inner
+            .store
+            .object_store_for(self.namespace_id)
+            .delete(path)
+            .await
+        {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => {}
+            Err(e) => {
+                warn!(
+                    partition_id = %self.partition_id,
+                    %object_store_id,
+                    error = %e,
+                    "failed to delete parquet file of failed upload"
+                );
+                return;
+            }
+        }
 
+        if let Some(pending) = &self.inner.pending {
+            if let Err(e) = pending.finish(object_store_id).await {
+                warn!(
+                    partition_id = %self.partition_id,
+                    %object_store_id,
+                    error = %e,
+                    "failed to remove pending upload marker"
+                );
+            }
+        }
+    }
+
+    async fn update_database(
+        &self,
+        sort_key_update: Option<SortKey>,
+        parquet_files: Vec<ParquetFileParams>,
+    ) -> Result<(), PersistFailed> {
         debug!(
             namespace_id = %self.namespace_id,
             namespace_name = %self.namespace_name,
@@ -270,12 +636,15 @@ impl Context {
             table_name = %self.table_name,
             partition_id = %self.partition_id,
             partition_key = %self.partition_key,
-            %object_store_id,
-            ?parquet_table_data,
+            n_files = parquet_files.len(),
             ?sort_key_update,
             "updating catalog"
         );
 
+        self.inner
+            .state
+            .set_stage(self.partition_id, PersistStage::UpdatingCatalog);
+
         // If necessary, update the partition sort key in the catalog and update
         // the local cached copy in the PartitionData.
         //
@@ -284,17 +653,15 @@ impl Context {
         // key.
         if let Some(new_sort_key) = sort_key_update {
             let sort_key = new_sort_key.to_columns().collect::<Vec<_>>();
-            Backoff::new(&Default::default())
-                .retry_all_errors("update_sort_key", || async {
-                    let mut repos = self.inner.catalog.repositories().await;
-                    let _partition = repos
-                        .partitions()
-                        .update_sort_key(self.partition_id, &sort_key)
-                        .await?;
-                    Ok(()) as Result<(), iox_catalog::interface::Error>
-                })
-                .await
-                .expect("retry forever");
+            self.retry("update sort key", || async {
+                let mut repos = self.inner.catalog.repositories().await;
+                repos
+                    .partitions()
+                    .update_sort_key(self.partition_id, &sort_key)
+                    .await
+                    .map(|_partition| ())
+            })
+            .await?;
 
             // Update the sort key in the partition cache.
             let old_key;
@@ -317,21 +684,24 @@ impl Context {
                 table_name = %self.table_name,
                 partition_id = %self.partition_id,
                 partition_key = %self.partition_key,
-                %object_store_id,
                 old_sort_key = ?sort_key,
                 %new_sort_key,
                 "adjusted partition sort key"
             );
         }
 
-        // Add the parquet file to the catalog.
+        // Add the parquet files to the catalog.
         //
         // This has the effect of allowing the queriers to "discover" the
-        // parquet file by polling / querying the catalog.
-        Backoff::new(&Default::default())
-            .retry_all_errors("add parquet file to catalog", || async {
-                let mut repos = self.inner.catalog.repositories().await;
-                let parquet_file = repos
+        // parquet files by polling / querying the catalog.
+        //
+        // The files are added in a single transaction, so that a job that
+        // fails part way through can be retried without adding any file
+        // twice.
+        self.retry("add parquet file", || async {
+            let mut txn = self.inner.catalog.start_transaction().await?;
+            for parquet_table_data in &parquet_files {
+                let parquet_file = txn
                     .parquet_files()
                     .create(parquet_table_data.clone())
                     .await?;
@@ -343,17 +713,71 @@ impl Context {
                     table_name = %self.table_name,
                     partition_id = %self.partition_id,
                     partition_key = %self.partition_key,
-                    %object_store_id,
+                    object_store_id = %parquet_table_data.object_store_id,
                     ?parquet_table_data,
                     parquet_file_id=?parquet_file.id,
                     "parquet file added to catalog"
                 );
+            }
+            txn.commit().await
+        })
+        .await?;
+
+        // Record the data persisted by this job.
+        for parquet_table_data in &parquet_files {
+            self.inner.metrics.files.inc(1);
+            self.inner
+                .metrics
+                .file_bytes
+                .inc(parquet_table_data.file_size_bytes as u64);
+            self.inner
+                .metrics
+                .file_rows
+                .inc(parquet_table_data.row_count as u64);
+        }
 
-                // compiler insisted on getting told the type of the error :shrug:
-                Ok(()) as Result<(), iox_catalog::interface::Error>
-            })
-            .await
-            .expect("retry forever");
+        // The files are now committed, and no longer need resolving should the
+        // ingester crash. A marker that fails to be removed is resolved as
+        // committed at the next startup.
+        if let Some(pending) = &self.inner.pending {
+            for parquet_table_data in &parquet_files {
+                let object_store_id = parquet_table_data.object_store_id;
+                if let Err(e) = pending.finish(object_store_id).await {
+                    warn!(
+                        partition_id = %self.partition_id,
+                        %object_store_id,
+                        error = %e,
+                        "failed to remove pending upload marker"
+                    );
+                }
+            }
+        }
+
+        info!(
+            n_files = parquet_files.len(),
+            namespace_id = %self.namespace_id,
+            namespace_name = %self.namespace_name,
+            table_id = %self.table_id,
+            table_name = %self.table_name,
+            partition_id = %self.partition_id,
+            partition_key = %self.partition_key,
+            "persisted partition"
+        );
+
+        Ok(())
+    }
+
+    /// Mark the persisted data as complete in the partition, notifying all
+    /// observers of this persistence task, and the subscribers to persist
+    /// completions of the `object_store_ids` of the parquet files written.
+    fn mark_complete(self, object_store_ids: Vec<Uuid>) {
+        let completion = PersistCompletion {
+            namespace_id: self.namespace_id,
+            table_id: self.table_id,
+            partition_id: self.partition_id,
+            max_sequence_number: self.data.max_sequence_number(),
+            object_store_ids,
+        };
 
         // Mark the partition as having completed persistence, causing it to
         // release the reference to the in-flight persistence data it is
@@ -364,21 +788,143 @@ impl Context {
         // the persisted data will be dropped "shortly".
         self.partition.lock().mark_persisted(self.data);
 
-        info!(
-            %object_store_id,
+        // Notify all observers of this persistence task
+        self.complete.notify_waiters();
+        self.inner.notifier.notify(completion);
+    }
+
+    /// Return the [`PersistRequest`] this [`Context`] was constructed from.
+    fn into_request(self) -> PersistRequest {
+        PersistRequest {
+            complete: self.complete,
+            partition: self.partition,
+            data: self.data,
+            job_id: self.job_id,
+        }
+    }
+
+    /// Call `f` until it succeeds, backing off between failed attempts at the
+    /// `operation`, or until this job exhausts its attempts.
+    async fn retry<T, E, F, R>(&self, operation: &'static str, mut f: F) -> Result<T, PersistFailed>
+    where
+        F: FnMut() -> R,
+        R: Future<Output = Result<T, E>>,
+        E: Display,
+    {
+        loop {
+            let error = match f().await {
+                Ok(v) => return Ok(v),
+                Err(e) => e.to_string(),
+            };
+            self.retry_after_failure(operation, error).await?;
+        }
+    }
+
+    /// Record the failed attempt at `operation`, and wait for the backoff
+    /// before the next attempt.
+    ///
+    /// Returns [`PersistFailed`] if this job has exhausted its attempts.
+    async fn retry_after_failure(
+        &self,
+        operation: &'static str,
+        error: String,
+    ) -> Result<(), PersistFailed> {
+        self.inner
+            .state
+            .record_failure(self.partition_id, operation, &error);
+
+        let (attempts, delay) = {
+            let mut a = self.attempts.lock();
+            a.failures += 1;
+            (a.failures, a.backoff.next())
+        };
+
+        let delay = match delay {
+            Some(d) if attempts < self.inner.max_attempts.get() => d,
+            _ => {
+                return Err(PersistFailed {
+                    operation,
+                    attempts,
+                    error,
+                })
+            }
+        };
+
+        warn!(
             namespace_id = %self.namespace_id,
             namespace_name = %self.namespace_name,
             table_id = %self.table_id,
             table_name = %self.table_name,
             partition_id = %self.partition_id,
             partition_key = %self.partition_key,
-            "persisted partition"
+            %error,
+            operation,
+            attempts,
+            backoff_secs = delay.as_secs_f64(),
+            "persist request failed, retrying"
         );
+        tokio::time::sleep(delay).await;
 
-        // Notify all observers of this persistence task
-        self.complete.notify_waiters();
+        Ok(())
     }
 }
 
-// TODO(test): persist
 // TODO(test): persist completion notification
+
+#[cfg(test)]
+mod tests {
+    use arrow_util::assert_batches_eq;
+
+    use super::*;
+    use crate::persist::test_util::persist_lines;
+
+    /// Persisting a partition writes a parquet file sorted on the partition
+    /// sort key without duplicate rows, extending the sort key in the catalog
+    /// (and the partition) with the new tag columns.
+    #[tokio::test]
+    async fn test_persist_sorted_deduplicated() {
+        // The last write overwrites the value of the first.
+        let persisted = persist_lines(
+            &[
+                "bananas,region=asia,city=tokyo v=1 10",
+                "bananas,region=africa,city=cairo v=2 20",
+                "bananas,region=asia,city=tokyo v=3 10",
+            ],
+            None,
+        )
+        .await;
+
+        // The new tag column is appended to the sort key, before the time
+        // column.
+        let want = SortKey::from_columns(["region", "city", "time"]);
+        let got = persisted
+            .catalog
+            .repositories()
+            .await
+            .partitions()
+            .get_by_id(persisted.partition_id)
+            .await
+            .expect("get partition")
+            .expect("partition must exist");
+        assert_eq!(got.sort_key(), Some(want.clone()));
+        let sort_key = persisted.partition.lock().sort_key().clone();
+        assert_eq!(sort_key.get().await, Some(want));
+
+        // The persisted rows are sorted on the sort key, retaining only the
+        // last write of the duplicated row.
+        let files = persisted.files().await;
+        assert_eq!(files.len(), 1);
+        let (_meta, batches) = persisted.read(&files[0]).await;
+        assert_batches_eq!(
+            [
+                "+-------+--------+--------------------------------+---+",
+                "| city  | region | time                           | v |",
+                "+-------+--------+--------------------------------+---+",
+                "| cairo | africa | 1970-01-01T00:00:00.000000020Z | 2 |",
+                "| tokyo | asia   | 1970-01-01T00:00:00.000000010Z | 3 |",
+                "+-------+--------+--------------------------------+---+",
+            ],
+            &batches
+        );
+    }
+}
@@ -0,0 +1,330 @@
+//! A bounded local disk cache of the parquet files written by the ingester.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use bytes::Bytes;
+use metric::{U64Counter, U64Gauge};
+use observability_deps::tracing::*;
+use parking_lot::Mutex;
+use uuid::Uuid;
+
+/// The subdirectory of the WAL directory holding the cached files - the WAL
+/// ignores the directories alongside its segment files.
+pub(crate) const FILE_CACHE_DIRECTORY: &str = "parquet_cache";
+
+/// The file extension of a cached parquet file, named after its object store
+/// ID.
+const FILE_EXTENSION: &str = "parquet";
+
+/// The file extension of a cached file being written.
+const FILE_TMP_EXTENSION: &str = "tmp";
+
+/// A parquet file held in a [`ParquetFileCache`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CachedFile {
+    pub(crate) object_store_id: Uuid,
+    /// The local path of the cached copy of the file.
+    pub(crate) path: PathBuf,
+    pub(crate) size: u64,
+}
+
+/// A write-through cache of the parquet files persisted by the ingester,
+/// holding a copy of each file in a local directory.
+///
+/// Queries of recently persisted data (by a querier co-located with the
+/// ingester, sharing the directory) can read the cached copy rather than
+/// downloading the file from object storage (see the querier's
+/// `IngesterFileCacheStore`). The cached files are listed by
+/// [`ParquetFileCache::files()`].
+///
+/// The cache holds at most `max_bytes` of files, evicting the files cached
+/// longest ago first. Parquet files are immutable, so a cached copy is always
+/// identical to the file in object storage, but it may outlive the file
+/// itself (such as once it is compacted) until evicted. Failing to cache a
+/// file never fails its persistence.
+#[derive(Debug)]
+pub(crate) struct ParquetFileCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    state: Mutex<CacheState>,
+
+    bytes_gauge: U64Gauge,
+    files_gauge: U64Gauge,
+    evictions: U64Counter,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    /// The size of each cached file, by object store ID.
+    files: HashMap<Uuid, u64>,
+    /// The cached files, in the order they were cached.
+    order: VecDeque<Uuid>,
+    bytes: u64,
+}
+
+impl ParquetFileCache {
+    /// Cache up to `max_bytes` of files in `dir`, creating it if necessary.
+    ///
+    /// The files left in `dir` by a previous instance are retained, in the
+    /// order they were written, subject to `max_bytes`.
+    pub(crate) async fn new(
+        dir: PathBuf,
+        max_bytes: u64,
+        metrics: &metric::Registry,
+    ) -> Result<Self, io::Error> {
+        let files = read_files(dir.clone()).await?;
+
+        let gauge = metrics.register_metric::<U64Gauge>(
+            "ingester_persist_file_cache",
+            "size of the local cache of persisted parquet files",
+        );
+        let cache = Self {
+            dir,
+            max_bytes,
+            state: Default::default(),
+            bytes_gauge: gauge.recorder(&[("unit", "bytes")]),
+            files_gauge: gauge.recorder(&[("unit", "files")]),
+            evictions: metrics
+                .register_metric::<U64Counter>(
+                    "ingester_persist_file_cache_evictions",
+                    "number of parquet files evicted from the local file cache",
+                )
+                .recorder(&[]),
+        };
+
+        let n = files.len();
+        for (object_store_id, size) in files {
+            cache.admit(object_store_id, size).await;
+        }
+        if n > 0 {
+            info!(
+                n_files = n,
+                cached_bytes = cache.state.lock().bytes,
+                "loaded persisted parquet file cache"
+            );
+        }
+
+        Ok(cache)
+    }
+
+    /// Write a copy of the parquet file `object_store_id` holding `data` to
+    /// the cache, evicting the oldest files to make space.
+    ///
+    /// A file larger than the cache is not cached.
+    pub(super) async fn insert(&self, object_store_id: Uuid, data: Bytes) {
+        let size = data.len() as u64;
+        if size > self.max_bytes {
+            debug!(%object_store_id, size, "parquet file too large to cache");
+            return;
+        }
+
+        let path = self.file_path(object_store_id);
+        let tmp = path.with_extension(FILE_TMP_EXTENSION);
+        let res = tokio::task::spawn_blocking(move || {
+            std::fs::write(&tmp, data)?;
+            std::fs::rename(&tmp, &path)
+        })
+        .await
+        .expect("parquet file cache write task panicked");
+
+        match res {
+            Ok(()) => self.admit(object_store_id, size).await,
+            Err(e) => warn!(%object_store_id, error=%e, "failed to cache parquet file"),
+        }
+    }
+
+    /// Return the cached files, in the order they were cached.
+    pub(crate) fn files(&self) -> Vec<CachedFile> {
+        let state = self.state.lock();
+        state
+            .order
+            .iter()
+            .map(|id| CachedFile {
+                object_store_id: *id,
+                path: self.file_path(*id),
+                size: state.files[id],
+            })
+            .collect()
+    }
+
+    /// Return the directory holding the cached files.
+    pub(crate) fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Record the file `object_store_id` of `size` bytes written to the cache,
+    /// and delete the files evicted to make space for it.
+    async fn admit(&self, object_store_id: Uuid, size: u64) {
+        let evicted = {
+            let mut state = self.state.lock();
+            state.remove(object_store_id);
+            state.files.insert(object_store_id, size);
+            state.order.push_back(object_store_id);
+            state.bytes += size;
+
+            let mut evicted = Vec::new();
+            while state.bytes > self.max_bytes {
+                let id = *state.order.front().expect("cache over budget is empty");
+                state.remove(id);
+                evicted.push(id);
+            }
+            self.set_gauges(&state);
+            evicted
+        };
+
+        for id in evicted {
+            debug!(object_store_id=%id, "evicting cached parquet file");
+            self.evictions.inc(1);
+            self.delete(id).await;
+        }
+    }
+
+    async fn delete(&self, object_store_id: Uuid) {
+        let path = self.file_path(object_store_id);
+        let res = tokio::task::spawn_blocking(move || match std::fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        })
+        .await
+        .expect("parquet file cache remove task panicked");
+
+        if let Err(e) = res {
+            warn!(%object_store_id, error=%e, "failed to remove cached parquet file");
+        }
+    }
+
+    fn set_gauges(&self, state: &CacheState) {
+        self.bytes_gauge.set(state.bytes);
+        self.files_gauge.set(state.files.len() as u64);
+    }
+
+    fn file_path(&self, object_store_id: Uuid) -> PathBuf {
+        self.dir
+            .join(object_store_id.to_string())
+            .with_extension(FILE_EXTENSION)
+    }
+}
+
+impl CacheState {
+    /// Remove `object_store_id` from the cache, returning true if it was
+    /// cached.
+    fn remove(&mut self, object_store_id: Uuid) -> bool {
+        let size = match self.files.remove(&object_store_id) {
+            Some(v) => v,
+            None => return false,
+        };
+        self.order.retain(|v| *v != object_store_id);
+        self.bytes -= size;
+        true
+    }
+}
+
+/// Create `dir` if necessary, and read the object store ID and size of each
+/// cached file in it, oldest first.
+///
+/// Partially written files are removed, and unknown files are ignored.
+async fn read_files(dir: PathBuf) -> Result<Vec<(Uuid, u64)>, io::Error> {
+    tokio::task::spawn_blocking(move || {
+        std::fs::create_dir_all(&dir)?;
+
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            match path.extension().and_then(|v| v.to_str()) {
+                Some(FILE_EXTENSION) => {}
+                Some(FILE_TMP_EXTENSION) => {
+                    std::fs::remove_file(&path)?;
+                    continue;
+                }
+                _ => continue,
+            }
+
+            let object_store_id = match path
+                .file_stem()
+                .and_then(|v| v.to_str())
+                .and_then(|v| v.parse::<Uuid>().ok())
+            {
+                Some(v) => v,
+                None => continue,
+            };
+            let meta = std::fs::metadata(&path)?;
+            let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            files.push((modified, object_store_id, meta.len()));
+        }
+
+        files.sort_unstable();
+        Ok(files.into_iter().map(|(_, id, size)| (id, size)).collect())
+    })
+    .await
+    .expect("parquet file cache read task panicked")
+}
+
+#[cfg(test)]
+mod tests {
+    use metric::{Attributes, Metric};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let metrics = metric::Registry::default();
+        let cache = ParquetFileCache::new(dir.path().join("cache"), 10, &metrics)
+            .await
+            .unwrap();
+
+        let ids = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+        cache.insert(ids[0], Bytes::from_static(b"bananas")).await;
+        cache.insert(ids[1], Bytes::from_static(b"ok")).await;
+
+        // A file larger than the cache is not cached.
+        cache
+            .insert(Uuid::new_v4(), Bytes::from_static(b"platanos!!!"))
+            .await;
+
+        let files = cache.files();
+        assert_eq!(
+            files.iter().map(|f| f.object_store_id).collect::<Vec<_>>(),
+            ids[..2]
+        );
+        assert_eq!(std::fs::read(&files[0].path).unwrap(), b"bananas");
+
+        // Caching another file evicts the oldest.
+        cache.insert(ids[2], Bytes::from_static(b"abc")).await;
+        let files = cache.files();
+        assert_eq!(
+            files.iter().map(|f| f.object_store_id).collect::<Vec<_>>(),
+            ids[1..]
+        );
+        assert!(!cache.file_path(ids[0]).exists());
+
+        let gauge = |unit| {
+            metrics
+                .get_instrument::<Metric<U64Gauge>>("ingester_persist_file_cache")
+                .unwrap()
+                .get_observer(&Attributes::from(&[("unit", unit)]))
+                .unwrap()
+                .fetch()
+        };
+        assert_eq!(gauge("bytes"), 5);
+        assert_eq!(gauge("files"), 2);
+
+        // The cached files are retained by a new cache of the directory,
+        // subject to its size.
+        drop(cache);
+        let cache =
+            ParquetFileCache::new(dir.path().join("cache"), 4, &metric::Registry::default())
+                .await
+                .unwrap();
+        let files = cache.files();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].object_store_id, ids[2]);
+        assert_eq!(files[0].size, 3);
+        assert!(!cache.file_path(ids[1]).exists());
+    }
+}
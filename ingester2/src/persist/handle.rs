@@ -1,24 +1,49 @@
-use std::sync::Arc;
+use std::{num::NonZeroUsize, sync::Arc, time::Duration};
 
+use data_types::{NamespaceId, PartitionId};
 use iox_catalog::interface::Catalog;
 use iox_query::exec::Executor;
-use observability_deps::tracing::info;
+use iox_time::{SystemProvider, TimeProvider};
+use observability_deps::tracing::{info, warn};
 use parking_lot::Mutex;
 use parquet_file::storage::ParquetStorage;
 use thiserror::Error;
 use tokio::sync::{
-    mpsc::{self},
-    Notify,
+    broadcast,
+    mpsc::{self, error::TrySendError},
+    watch, Notify,
 };
 
-use crate::buffer_tree::partition::{persisting::PersistingData, PartitionData};
+use crate::{
+    buffer_tree::partition::{persisting::PersistingData, PartitionData},
+    ingest_state::{IngestState, IngestStateError},
+    init::{IngesterIdentity, PersistWorkers},
+};
+
+use super::{
+    actor::PersistActor,
+    audit::{PersistAudit, PersistAuditRecord},
+    completion::{CompletionNotifier, PersistCompletion},
+    context::PersistRequest,
+    file_cache::{CachedFile, ParquetFileCache},
+    orphans::{find_orphaned_files, OrphanError, OrphanedFile},
+    pending::PendingUploads,
+    priority::PersistPriority,
+    quarantine::Quarantine,
+    scaling::{PersistStats, WorkerBounds},
+    state::PersistState,
+};
 
-use super::{actor::PersistActor, context::PersistRequest};
+/// How often a saturated submission queue is checked for recovery.
+const SATURATION_RECOVERY_INTERVAL: Duration = Duration::from_millis(100);
 
 #[derive(Debug, Error)]
 pub(crate) enum PersistError {
     #[error("persist queue is full")]
     QueueFull,
+
+    #[error("invalid persist worker bounds: min {min}, max {max}")]
+    InvalidWorkerBounds { min: usize, max: usize },
 }
 
 /// A persistence task submission handle.
@@ -41,7 +66,8 @@ pub(crate) enum PersistError {
 /// # Topology
 ///
 /// The persist actor is uses an internal work group to parallelise persistence
-/// operations up to `n_workers` number of parallel tasks.
+/// operations up to `n_workers` number of parallel tasks (see "Scaling"
+/// below).
 ///
 /// Submitting a persistence request places the job into a bounded queue,
 /// providing a buffer for persistence requests to wait for an available worker.
@@ -101,6 +127,28 @@ pub(crate) enum PersistError {
 /// this prevents a "hot" / backlogged worker with a full worker queue from
 /// blocking tasks from being passed through to workers with spare capacity.
 ///
+/// # Prioritisation
+///
+/// The submission queue is consumed in order, but each worker executes the
+/// jobs in its queue in the order given by `priority` - [`OldestFirst`]
+/// executes the jobs persisting the oldest data first (and of those, the
+/// largest first), so under a backlog the ingester persists the data retaining
+/// the oldest WAL segments before more recent data. The jobs of a partition
+/// are always executed in the order they were enqueued, regardless of the
+/// policy.
+///
+/// # Scaling
+///
+/// When configured with [`PersistWorkers::Autoscale`], the number of workers
+/// is periodically adjusted between the configured bounds, starting at the
+/// minimum. Enough workers are started to complete the outstanding persist
+/// jobs within the target latency (estimated from the duration of recently
+/// completed jobs), while surplus workers are stopped one at a time.
+///
+/// The bounds can be changed at runtime by calling
+/// [`PersistHandle::set_worker_bounds()`] - a fixed number of workers is
+/// configured by setting equal bounds. The queue depths cannot be changed.
+///
 /// # Parallelism & Partition Serialisation
 ///
 /// Persistence jobs are parallelised across partitions, with up to at most
@@ -109,11 +157,105 @@ pub(crate) enum PersistError {
 /// Because updates of a partition's [`SortKey`] are not commutative, they must
 /// be serialised. For this reason, persist operations for given partition are
 /// always placed in the same worker queue, ensuring they execute sequentially.
+/// A partition with outstanding jobs keeps being assigned to the same worker
+/// when the number of workers changes, and a surplus worker is only stopped
+/// once no partitions are assigned to it.
+///
+/// Independently of the worker assignment, at most one job of a partition is
+/// passed to the workers at once - the jobs of a partition enqueued while
+/// another is running (such as when WAL rotation, hot partition persistence
+/// and shutdown persist the same partition) are held by the [`PersistActor`]
+/// in enqueue order until it completes, so they never compact overlapping
+/// snapshots of the partition concurrently.
+///
+/// # Saturation
+///
+/// If the submission queue is full when a persist task is enqueued,
+/// [`IngestStateError::PersistSaturated`] is set in the [`IngestState`],
+/// causing writes to be rejected until at least half of the submission queue
+/// is free again - accepting more writes would only grow the buffered data the
+/// ingester cannot persist.
+///
+/// # Namespace Limits
+///
+/// If `namespace_max_jobs` is set, at most that many persist jobs of a single
+/// namespace are passed to the workers at once - the subsequent jobs of the
+/// namespace are held by the [`PersistActor`] until its running jobs complete.
+/// This prevents a namespace with many (or very large) partitions from
+/// occupying all the workers, delaying the persistence of other namespaces,
+/// and so the truncation of the WAL.
+///
+/// # Verification
+///
+/// If `verify_parquet` is true, each uploaded parquet file is read back and
+/// checked to decode to the row count and time range of the data it was
+/// written from, before it is added to the catalog and the data is marked as
+/// persisted (allowing the WAL segments containing it to be dropped). A file
+/// that fails verification is uploaded again, as for a failed upload.
+///
+/// # Uncommitted Uploads
+///
+/// If `pending` is provided, a marker is durably recorded before each parquet
+/// file is uploaded, and removed once the file is added to the catalog (or
+/// deleted after a failed attempt). The files left marked by a crash are
+/// resolved by [`PendingUploads::reconcile()`], which must be called before
+/// the [`PersistActor`] is started.
+///
+/// # File Cache
+///
+/// If `file_cache` is provided, a copy of each successfully uploaded parquet
+/// file is written to it, and the cached files are listed by
+/// [`PersistHandle::cached_files()`]. The copy of a file that is not added to
+/// the catalog (by a job that is quarantined) is retained until evicted.
+///
+/// # Failures
+///
+/// The catalog and object store requests made by a persist job are retried
+/// with a backoff. A job that fails `max_attempts` times is quarantined - its
+/// data remains buffered in the partition, and the WAL segments containing it
+/// are retained - together with the subsequent jobs of its partition, which
+/// must be persisted after it. Quarantined jobs are re-enqueued by calling
+/// [`PersistHandle::requeue_quarantined()`].
+///
+/// # Completion Notifications
+///
+/// Each completed persist job is broadcast to the subscribers obtained from
+/// [`PersistHandle::subscribe_completions()`], identifying the partition, the
+/// sequence number watermark of the persisted writes, and the parquet files
+/// they were written to - allowing a caller to await the durability of data
+/// in object storage.
+///
+/// # Audit
+///
+/// The outcome of each executed persist job - its partition, sequence number
+/// range, parquet files or error, and duration - is retained in a bounded
+/// in-memory log returned by [`PersistHandle::audit_records()`], recording
+/// when each partition was last persisted, and why it failed.
 ///
 /// [`SortKey`]: schema::sort::SortKey
+/// [`OldestFirst`]: super::priority::OldestFirst
 #[derive(Debug, Clone)]
 pub(crate) struct PersistHandle {
     tx: mpsc::Sender<PersistRequest>,
+    submission_queue_depth: usize,
+    ingest_state: Arc<IngestState>,
+
+    /// The bounds on the number of workers, observed by the [`PersistActor`].
+    bounds: Arc<watch::Sender<WorkerBounds>>,
+    state: Arc<PersistState>,
+    quarantine: Arc<Quarantine>,
+    notifier: Arc<CompletionNotifier>,
+    audit: Arc<PersistAudit>,
+
+    /// The catalog and object store the parquet files are persisted to.
+    catalog: Arc<dyn Catalog>,
+    store: ParquetStorage,
+
+    file_cache: Option<Arc<ParquetFileCache>>,
+
+    /// The source of the current time, against which the age of orphaned
+    /// objects is measured.
+    time_provider: Arc<dyn TimeProvider>,
 }
 
 impl PersistHandle {
@@ -121,28 +263,220 @@ impl PersistHandle {
     ///
     /// The caller should call [`PersistActor::run()`] in a separate
     /// thread / task to start the persistence executor.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `workers` allows fewer than 1 worker, or a minimum above the
+    /// maximum.
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         submission_queue_depth: usize,
-        n_workers: usize,
+        workers: PersistWorkers,
         worker_queue_depth: usize,
+        priority: Arc<dyn PersistPriority>,
         exec: Arc<Executor>,
         store: ParquetStorage,
         catalog: Arc<dyn Catalog>,
+        ingest_state: Arc<IngestState>,
+        max_file_bytes: Option<NonZeroUsize>,
+        max_attempts: NonZeroUsize,
+        namespace_max_jobs: Option<NonZeroUsize>,
+        verify_parquet: bool,
+        pending: Option<PendingUploads>,
+        file_cache: Option<Arc<ParquetFileCache>>,
+        identity: IngesterIdentity,
+        metrics: &metric::Registry,
     ) -> (Self, PersistActor) {
+        let bounds = WorkerBounds::from(workers);
+        assert!(bounds.is_valid(), "persist workers require 0 < min <= max");
+
         let (tx, rx) = mpsc::channel(submission_queue_depth);
 
         // Log the important configuration parameters of the persist subsystem.
         info!(
             submission_queue_depth,
-            n_workers,
+            min_workers = bounds.min,
+            max_workers = bounds.max,
             worker_queue_depth,
-            max_queued_tasks = submission_queue_depth + (n_workers * worker_queue_depth),
+            ?priority,
+            max_queued_tasks = submission_queue_depth + (bounds.max * worker_queue_depth),
+            ?max_file_bytes,
+            max_attempts,
+            ?namespace_max_jobs,
+            verify_parquet,
+            pending_uploads = pending.is_some(),
+            file_cache = file_cache.is_some(),
+            host = %identity.host,
+            version = %identity.version,
             "initialised persist task"
         );
 
-        let actor = PersistActor::new(rx, exec, store, catalog, n_workers, worker_queue_depth);
+        let (bounds_tx, bounds_rx) = watch::channel(bounds);
+        let state = Arc::new(PersistState::new(metrics));
+        let quarantine = Arc::new(Quarantine::new(metrics));
+        let notifier = Arc::new(CompletionNotifier::default());
+        let audit = Arc::new(PersistAudit::default());
+
+        let actor = PersistActor::new(
+            rx,
+            exec,
+            store.clone(),
+            Arc::clone(&catalog),
+            bounds_rx,
+            worker_queue_depth,
+            priority,
+            Arc::clone(&state),
+            Arc::clone(&quarantine),
+            Arc::clone(&notifier),
+            Arc::clone(&audit),
+            max_file_bytes,
+            max_attempts,
+            namespace_max_jobs,
+            verify_parquet,
+            pending,
+            file_cache.clone(),
+            identity,
+            metrics,
+        );
+
+        (
+            Self {
+                tx,
+                submission_queue_depth,
+                ingest_state,
+                bounds: Arc::new(bounds_tx),
+                state,
+                quarantine,
+                notifier,
+                audit,
+                catalog,
+                store,
+                file_cache,
+                time_provider: Arc::new(SystemProvider::new()),
+            },
+            actor,
+        )
+    }
+
+    /// Measure the age of orphaned objects against the current time of
+    /// `time_provider`.
+    #[cfg(test)]
+    pub(crate) fn with_time_provider(mut self, time_provider: Arc<dyn TimeProvider>) -> Self {
+        self.time_provider = time_provider;
+        self
+    }
+
+    /// Change the bounds within which the number of persist workers is
+    /// scaled.
+    ///
+    /// The new bounds are applied asynchronously by the [`PersistActor`].
+    pub(crate) fn set_worker_bounds(&self, bounds: WorkerBounds) -> Result<(), PersistError> {
+        if !bounds.is_valid() {
+            return Err(PersistError::InvalidWorkerBounds {
+                min: bounds.min,
+                max: bounds.max,
+            });
+        }
+
+        self.bounds.send_replace(bounds);
+        Ok(())
+    }
+
+    /// Return the bounds within which the number of persist workers is scaled.
+    pub(crate) fn worker_bounds(&self) -> WorkerBounds {
+        *self.bounds.borrow()
+    }
+
+    /// Return the counters describing the persist workers.
+    pub(crate) fn stats(&self) -> &PersistStats {
+        self.state.stats()
+    }
+
+    /// Return the state of the outstanding persist jobs.
+    pub(crate) fn state(&self) -> &Arc<PersistState> {
+        &self.state
+    }
+
+    /// Return the quarantined persist jobs.
+    pub(crate) fn quarantine(&self) -> &Quarantine {
+        &self.quarantine
+    }
+
+    /// Receive a [`PersistCompletion`] for each persist job completing from
+    /// now on.
+    pub(crate) fn subscribe_completions(&self) -> broadcast::Receiver<PersistCompletion> {
+        self.notifier.subscribe()
+    }
+
+    /// Return the recorded outcomes of the most recently executed persist
+    /// jobs of `partition_id` (or of all partitions, if [`None`]), oldest
+    /// first.
+    pub(crate) fn audit_records(
+        &self,
+        partition_id: Option<PartitionId>,
+    ) -> Vec<PersistAuditRecord> {
+        self.audit.records(partition_id)
+    }
+
+    /// Return the directory of the local file cache and the parquet files it
+    /// holds, oldest first, or [`None`] if the cache is disabled.
+    pub(crate) fn cached_files(&self) -> Option<(&std::path::Path, Vec<CachedFile>)> {
+        self.file_cache.as_ref().map(|v| (v.dir(), v.files()))
+    }
+
+    /// Re-enqueue the quarantined persist jobs of `partition_id` (or of all
+    /// partitions, if [`None`]), returning the number of jobs re-enqueued.
+    ///
+    /// The jobs of each partition are enqueued in the order they must be
+    /// persisted, waiting for space to become available in the submission
+    /// queue. The callers waiting on the completion of a re-enqueued job are
+    /// notified once it succeeds.
+    pub(crate) async fn requeue_quarantined(&self, partition_id: Option<PartitionId>) -> usize {
+        let jobs = self.quarantine.take(partition_id);
+        let n = jobs.len();
+
+        for req in jobs {
+            let req = req.with_job_id(self.state.enqueue());
+            info!(
+                partition_id = %req.partition_id(),
+                job_id = req.job_id(),
+                "re-enqueuing quarantined persist job"
+            );
+            self.tx
+                .send(req)
+                .await
+                .expect("no persist worker tasks running");
+        }
 
-        (Self { tx }, actor)
+        n
+    }
+
+    /// Find the objects under the object store prefix of `namespace_id`, at
+    /// least `min_age` old, that are not parquet files in the catalog -
+    /// deleting them if `delete` is true, or only reporting them otherwise.
+    ///
+    /// Objects are orphaned by a persist job that uploads a file but fails to
+    /// add it to the catalog, and is not cleaned up (such as when an ingester
+    /// loses the local disk holding its pending upload markers). Deleting
+    /// objects younger than [`MIN_ORPHAN_DELETE_AGE`], which may be the files
+    /// of running persist jobs, is rejected.
+    ///
+    /// [`MIN_ORPHAN_DELETE_AGE`]: super::orphans::MIN_ORPHAN_DELETE_AGE
+    pub(crate) async fn find_orphaned_files(
+        &self,
+        namespace_id: NamespaceId,
+        min_age: Duration,
+        delete: bool,
+    ) -> Result<Vec<OrphanedFile>, OrphanError> {
+        find_orphaned_files(
+            &*self.catalog,
+            &self.store,
+            &*self.time_provider,
+            namespace_id,
+            min_age,
+            delete,
+        )
+        .await
     }
 
     /// Place `data` from `partition` into the persistence queue.
@@ -170,9 +504,28 @@ impl PersistHandle {
         data: PersistingData,
     ) -> Arc<Notify> {
         // Build the persist task request
-        let r = PersistRequest::new(partition, data);
+        let r = PersistRequest::new(partition, data, self.state.enqueue());
         let notify = r.complete_notification();
 
+        let r = match self.tx.try_send(r) {
+            Ok(()) => return notify,
+            Err(TrySendError::Full(r)) => r,
+            Err(TrySendError::Closed(_)) => panic!("no persist worker tasks running"),
+        };
+
+        // The submission queue is full - reject writes until it drains.
+        if self.ingest_state.set(IngestStateError::PersistSaturated) {
+            warn!(
+                submission_queue_depth = self.submission_queue_depth,
+                "persist queue saturated, rejecting writes"
+            );
+            tokio::spawn(clear_saturation(
+                self.tx.clone(),
+                self.submission_queue_depth,
+                Arc::clone(&self.ingest_state),
+            ));
+        }
+
         self.tx
             .send(r)
             .await
@@ -181,3 +534,258 @@ impl PersistHandle {
         notify
     }
 }
+
+/// Wait for at least half of the submission queue of `tx` to be free, and then
+/// clear [`IngestStateError::PersistSaturated`] in `ingest_state`.
+async fn clear_saturation(
+    tx: mpsc::Sender<PersistRequest>,
+    submission_queue_depth: usize,
+    ingest_state: Arc<IngestState>,
+) {
+    let want = (submission_queue_depth / 2).max(1);
+    while tx.capacity() < want && !tx.is_closed() {
+        tokio::time::sleep(SATURATION_RECOVERY_INTERVAL).await;
+    }
+
+    ingest_state.unset(IngestStateError::PersistSaturated);
+    info!("persist queue recovered, accepting writes");
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use metric::{Attributes, Metric, U64Counter, U64Gauge};
+    use object_store::memory::InMemory;
+    use schema::sort::SortKey;
+    use test_helpers::timeout::FutureTimeout;
+    use wal::test_utils::{FaultInjector, FaultyObjectStore};
+
+    use super::*;
+    use crate::persist::{
+        audit::PersistOutcome,
+        test_util::{buffer_lines, persist_lines},
+    };
+
+    /// A partition estimated to exceed the maximum file size is persisted as
+    /// several files of non-overlapping time ranges.
+    #[tokio::test]
+    async fn test_persist_split_oversized() {
+        let persisted = persist_lines(
+            &[
+                "bananas,region=asia v=1 10",
+                "bananas,region=africa v=2 20",
+                "bananas,region=asia,city=tokyo v=3 30",
+                "bananas,region=africa v=4 40",
+            ],
+            Some(NonZeroUsize::new(1).unwrap()),
+        )
+        .await;
+
+        // Each timestamp is written to a separate file.
+        let mut files = persisted.files().await;
+        files.sort_unstable_by_key(|f| f.min_time);
+        assert_eq!(
+            files
+                .iter()
+                .map(|f| (f.min_time.get(), f.max_time.get()))
+                .collect::<Vec<_>>(),
+            [(10, 10), (20, 20), (30, 30), (40, 40)]
+        );
+
+        // The sort key is extended with the column of the third file.
+        let want = SortKey::from_columns(["region", "city", "time"]);
+        let sort_key = persisted.partition.lock().sort_key().clone();
+        assert_eq!(sort_key.get().await, Some(want));
+
+        // Each file holds the rows of its time range, and describes them in
+        // its metadata.
+        for (file, want_v) in files.iter().zip(["1", "2", "3", "4"]) {
+            let (meta, batches) = persisted.read(file).await;
+            let meta = meta
+                .decode()
+                .expect("should decode IOx metadata")
+                .read_iox_metadata_new()
+                .expect("should read IOxMetadata");
+            assert_eq!(meta.object_store_id, file.object_store_id);
+            assert_eq!(meta.partition_id, persisted.partition_id);
+            assert_eq!(meta.max_sequence_number.get(), 4);
+
+            // The provenance of the file records the range of the persisted
+            // writes.
+            let provenance = meta.provenance.expect("should record provenance");
+            assert_eq!(provenance.min_sequence_number.get(), 1);
+            assert_eq!(provenance.max_sequence_number.get(), 4);
+            assert_eq!(provenance.persisted_at, meta.creation_timestamp);
+
+            assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 1);
+            let v = arrow_util::display::pretty_format_batches(&batches).unwrap();
+            assert!(v.contains(&format!("| {want_v} |")), "{v}");
+        }
+    }
+
+    /// The subscribers to persist completions are notified of the persisted
+    /// partition, its sequence number watermark and the files written.
+    #[tokio::test]
+    async fn test_persist_completion_notification() {
+        let p = buffer_lines(
+            &["bananas,region=asia v=1 10", "bananas,region=africa v=2 20"],
+            Some(NonZeroUsize::new(1).unwrap()),
+            NonZeroUsize::new(10).unwrap(),
+            Arc::new(InMemory::new()),
+            &metric::Registry::default(),
+        )
+        .await;
+
+        let mut rx = p.persist.subscribe_completions();
+        let started = p.enqueue().await;
+        p.wait_persisted(started).await;
+
+        let got = rx
+            .recv()
+            .with_timeout_panic(Duration::from_secs(5))
+            .await
+            .expect("completion must be received");
+        assert_eq!(got.partition_id, p.partition_id);
+        assert_eq!(got.max_sequence_number.get(), 2);
+
+        let mut want = p
+            .files()
+            .await
+            .into_iter()
+            .map(|f| f.object_store_id)
+            .collect::<Vec<_>>();
+        let mut object_store_ids = got.object_store_ids;
+        want.sort_unstable();
+        object_store_ids.sort_unstable();
+        assert_eq!(want.len(), 2);
+        assert_eq!(object_store_ids, want);
+
+        // No further completions are broadcast.
+        assert_matches!(rx.try_recv(), Err(broadcast::error::TryRecvError::Empty));
+    }
+
+    /// A persist job that exhausts its attempts is quarantined, retaining its
+    /// data in the partition, and is persisted once re-enqueued.
+    #[tokio::test]
+    async fn test_persist_quarantine_requeue() {
+        let metrics = metric::Registry::default();
+        let faults = Arc::new(FaultInjector::new());
+        let p = buffer_lines(
+            &["bananas,region=asia v=1 10"],
+            None,
+            NonZeroUsize::new(1).unwrap(),
+            Arc::new(FaultyObjectStore::new(
+                Arc::new(InMemory::new()),
+                Arc::clone(&faults),
+            )),
+            &metrics,
+        )
+        .await;
+
+        let quarantined_jobs = || {
+            metrics
+                .get_instrument::<Metric<U64Gauge>>("ingester_persist_quarantined_jobs")
+                .expect("metric not registered")
+                .get_observer(&Attributes::from(&[]))
+                .expect("no metric observer")
+                .fetch()
+        };
+        let counter = |name: &'static str, key: &'static str, value: &'static str| {
+            metrics
+                .get_instrument::<Metric<U64Counter>>(name)
+                .expect("metric not registered")
+                .get_observer(&Attributes::from(&[(key, value)]))
+                .map(|v| v.fetch())
+                .unwrap_or_default()
+        };
+
+        // Fail the (only) attempt at uploading the parquet file.
+        faults.short_write_after(0);
+        let started = p.enqueue().await;
+
+        async {
+            while p.persist.quarantine().snapshot().is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }
+        .with_timeout_panic(Duration::from_secs(5))
+        .await;
+
+        let snapshot = p.persist.quarantine().snapshot();
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].partition_id, p.partition_id);
+        assert_eq!(snapshot[0].jobs, 1);
+        assert!(
+            snapshot[0]
+                .error
+                .starts_with("upload parquet failed after 1 attempts"),
+            "{}",
+            snapshot[0].error
+        );
+        assert_eq!(quarantined_jobs(), 1);
+        assert_eq!(
+            counter("ingester_persist_write_failures", "cause", "upload"),
+            1
+        );
+        assert_eq!(
+            counter(
+                "ingester_persist_job_failures",
+                "operation",
+                "upload parquet"
+            ),
+            1
+        );
+
+        // The job is no longer outstanding, but its data was not persisted.
+        assert_eq!(p.persist.stats().outstanding(), 0);
+        assert_eq!(p.partition.lock().completed_persistence_count(), started);
+        assert!(p.files().await.is_empty());
+
+        // Once re-enqueued, the job succeeds.
+        assert_eq!(p.persist.requeue_quarantined(None).await, 1);
+        p.wait_persisted(started).await;
+
+        assert_eq!(p.files().await.len(), 1);
+        assert!(p.persist.quarantine().snapshot().is_empty());
+        assert_eq!(quarantined_jobs(), 0);
+
+        // Only the persisted file is reported as output.
+        assert_eq!(counter("ingester_persist_output", "unit", "files"), 1);
+        assert_eq!(counter("ingester_persist_output", "unit", "rows"), 1);
+        assert_eq!(
+            counter("ingester_persist_output", "unit", "bytes"),
+            p.files().await[0].file_size_bytes as u64
+        );
+
+        // Both executions of the job are recorded in the audit log.
+        let records = async {
+            loop {
+                let records = p.persist.audit_records(Some(p.partition_id));
+                if records.len() == 2 {
+                    break records;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }
+        .with_timeout_panic(Duration::from_secs(5))
+        .await;
+        assert_matches!(
+            &records[0].outcome,
+            PersistOutcome::Quarantined { error } if error.starts_with("upload parquet failed")
+        );
+        assert_eq!(
+            records[1].outcome,
+            PersistOutcome::Persisted {
+                object_store_ids: vec![p.files().await[0].object_store_id],
+            }
+        );
+        assert_eq!(
+            records[1].min_sequence_number,
+            records[1].max_sequence_number
+        );
+        assert!(p
+            .persist
+            .audit_records(Some(PartitionId::new(p.partition_id.get() + 1)))
+            .is_empty());
+    }
+}
@@ -0,0 +1,178 @@
+//! Persistence of "hot" partitions, independently of WAL rotation.
+
+use std::{sync::Arc, time::Duration};
+
+use futures::{stream, StreamExt};
+use iox_time::{SystemProvider, TimeProvider};
+use observability_deps::tracing::*;
+
+use super::{handle::PersistHandle, policy::PersistPolicies};
+use crate::buffer_tree::BufferTree;
+
+/// How often the buffered partitions are checked against the
+/// [`HotPartitionThresholds`].
+const HOT_PARTITION_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The buffered data limits above which a partition is "hot" and persisted
+/// immediately, rather than waiting for the next WAL rotation.
+///
+/// Persisting hot partitions early bounds the memory used by skewed workloads,
+/// where a small number of partitions receive most of the writes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct HotPartitionThresholds {
+    /// The approximate size of the buffered data, in bytes.
+    pub(crate) max_bytes: Option<usize>,
+    /// The number of buffered rows.
+    pub(crate) max_rows: Option<usize>,
+}
+
+impl HotPartitionThresholds {
+    /// Returns true if any threshold is set.
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.max_bytes.is_some() || self.max_rows.is_some()
+    }
+
+    /// Returns true if a partition buffering `buffered_bytes` bytes of data in
+    /// `buffered_rows` rows reaches any of the thresholds.
+    pub(crate) fn is_hot(&self, buffered_bytes: usize, buffered_rows: usize) -> bool {
+        self.max_bytes.map_or(false, |v| buffered_bytes >= v)
+            || self.max_rows.map_or(false, |v| buffered_rows >= v)
+    }
+}
+
+/// Periodically enqueue the persistence of all partitions in `buffer` that are
+/// hot according to `thresholds`, or reach the triggers of the
+/// [`PersistPolicies`] overriding them for their namespace or table.
+///
+/// If `cold_data_age` is set, only the rows of a partition with a timestamp
+/// older than `cold_data_age` are persisted, retaining the recent rows (that
+/// are likely still being written to) buffered - unless the recent rows alone
+/// reach the triggers, or there are no older rows, when all the rows are
+/// persisted. This allows a partition receiving continuous writes to persist
+/// the data it is no longer receiving in larger, non-overlapping files.
+///
+/// This task runs independently of the WAL rotation, including during WAL
+/// replay. Persisting a hot partition does not remove its data from the WAL -
+/// the WAL segments containing it are dropped by the next WAL rotation as
+/// usual, after waiting for any outstanding hot partition persistence to
+/// complete.
+pub(crate) async fn hot_partition_persist(
+    buffer: Arc<BufferTree>,
+    persist: PersistHandle,
+    thresholds: HotPartitionThresholds,
+    policies: Arc<PersistPolicies>,
+    cold_data_age: Option<Duration>,
+) {
+    let mut interval = tokio::time::interval(HOT_PARTITION_CHECK_INTERVAL);
+    let time_provider = SystemProvider::new();
+
+    loop {
+        interval.tick().await;
+
+        if !thresholds.is_enabled() && policies.is_empty() {
+            continue;
+        }
+
+        // The rows with a timestamp before the cutoff are cold.
+        let cutoff = cold_data_age.map(|age| {
+            time_provider
+                .now()
+                .checked_sub(age)
+                .map_or(i64::MIN, |v| v.timestamp_nanos())
+        });
+
+        let n = stream::iter(buffer.partitions())
+            .filter_map(|p| {
+                let policies = Arc::clone(&policies);
+                async move {
+                    let data = {
+                        let mut guard = p.lock();
+                        let buffered_bytes = guard.buffered_bytes();
+                        let buffered_rows = guard.buffered_rows();
+                        let triggers =
+                            policies.triggers(guard.namespace_id(), guard.table_id(), thresholds);
+                        if !triggers.should_persist(
+                            guard.buffered_age(),
+                            buffered_bytes,
+                            buffered_rows,
+                        ) {
+                            return None;
+                        }
+
+                        debug!(
+                            partition_id = %guard.partition_id(),
+                            buffered_bytes,
+                            buffered_rows,
+                            cold_only = cutoff.is_some(),
+                            "persisting hot partition"
+                        );
+
+                        // Persist the cold rows first, and then the recent
+                        // rows if they still reach the triggers - the data of
+                        // the partition is persisted in order either way.
+                        let mut data = Vec::with_capacity(2);
+                        if let Some(cutoff) = cutoff {
+                            data.extend(guard.mark_persisting_before(cutoff));
+                        }
+                        if cutoff.is_none()
+                            || triggers.should_persist(
+                                guard.buffered_age(),
+                                guard.buffered_bytes(),
+                                guard.buffered_rows(),
+                            )
+                        {
+                            data.extend(guard.mark_persisting());
+                        }
+                        data
+                    };
+                    (!data.is_empty()).then_some((p, data))
+                }
+            })
+            // Serialise adding partitions to the persist queue, applying
+            // backpressure if the persist workers are saturated.
+            .then(|(p, data)| {
+                let persist = persist.clone();
+                // The completion notification is not needed - the WAL rotation
+                // waits for all persist operations to complete.
+                async move {
+                    for data in data {
+                        persist.queue_persist(Arc::clone(&p), data).await;
+                    }
+                }
+            })
+            .count()
+            .await;
+
+        if n > 0 {
+            info!(n_partitions = n, "queued hot partitions for persist");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_hot() {
+        let disabled = HotPartitionThresholds::default();
+        assert!(!disabled.is_enabled());
+        assert!(!disabled.is_hot(usize::MAX, usize::MAX));
+
+        let rows = HotPartitionThresholds {
+            max_bytes: None,
+            max_rows: Some(2),
+        };
+        assert!(rows.is_enabled());
+        assert!(rows.is_hot(0, 2));
+        assert!(!rows.is_hot(usize::MAX, 1));
+
+        let both = HotPartitionThresholds {
+            max_bytes: Some(1024),
+            max_rows: Some(2),
+        };
+        assert!(both.is_hot(1024, 0));
+        assert!(both.is_hot(0, 3));
+        assert!(!both.is_hot(1023, 1));
+    }
+}
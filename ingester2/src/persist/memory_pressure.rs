@@ -0,0 +1,121 @@
+//! Persistence of the largest partitions when the memory used by the buffered
+//! data exceeds the soft limit.
+
+use std::{sync::Arc, time::Duration};
+
+use metric::U64Counter;
+use observability_deps::tracing::*;
+
+use super::handle::PersistHandle;
+use crate::{
+    buffer_tree::{
+        memory::{MemoryTracker, MemoryUsage},
+        BufferTree,
+    },
+    ingest_state::{IngestState, IngestStateError},
+};
+
+/// How often the memory used by the [`BufferTree`] is measured.
+const MEMORY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Periodically measure the memory used by the data in `buffer`, recording it
+/// in `tracker`.
+///
+/// When the memory used exceeds the soft limit of `tracker` (or the hard limit,
+/// if no soft limit is set), the partitions buffering the most data are
+/// persisted until the expected usage, once all in-flight persist operations
+/// complete, is below the limit.
+///
+/// While the memory used reaches the hard limit of `tracker`,
+/// [`IngestStateError::MemoryLimit`] is set in `ingest_state`, rejecting
+/// writes - this task must be running for the hard limit to be enforced.
+pub(crate) async fn memory_pressure_persist(
+    buffer: Arc<BufferTree>,
+    persist: PersistHandle,
+    tracker: Arc<MemoryTracker>,
+    ingest_state: Arc<IngestState>,
+    metrics: Arc<metric::Registry>,
+) {
+    let persist_count = metrics
+        .register_metric::<U64Counter>(
+            "ingester_memory_pressure_persist",
+            "number of partitions persisted to reduce the memory used by the buffer",
+        )
+        .recorder(&[]);
+
+    let mut interval = tokio::time::interval(MEMORY_CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let usage = MemoryUsage::new(buffer.partitions());
+        tracker.observe(&usage);
+
+        if tracker.is_over_hard_limit() {
+            if ingest_state.set(IngestStateError::MemoryLimit) {
+                warn!(
+                    total_bytes = usage.total_bytes(),
+                    "buffer memory hard limit reached, rejecting writes"
+                );
+            }
+        } else if ingest_state.unset(IngestStateError::MemoryLimit) {
+            info!(
+                total_bytes = usage.total_bytes(),
+                "buffer memory below hard limit, accepting writes"
+            );
+        }
+
+        // Without a soft limit, partitions are persisted once the hard limit
+        // is reached, so that the writes rejected (or the WAL replay blocked)
+        // by it make progress without waiting for the next WAL rotation.
+        let limits = tracker.limits();
+        let soft_limit = match limits.soft_limit_bytes.or(limits.hard_limit_bytes) {
+            Some(v) => v,
+            None => continue,
+        };
+
+        // The memory held by in-flight persist operations is released as they
+        // complete, so only the remainder has to be freed by starting new
+        // persist operations.
+        let mut excess_bytes = usage
+            .total_bytes()
+            .saturating_sub(soft_limit)
+            .saturating_sub(usage.persisting_bytes());
+        if excess_bytes == 0 {
+            continue;
+        }
+
+        warn!(
+            total_bytes = usage.total_bytes(),
+            persisting_bytes = usage.persisting_bytes(),
+            soft_limit,
+            "buffer memory soft limit exceeded, persisting largest partitions"
+        );
+
+        let mut n = 0;
+        for p in usage.into_partitions_by_size() {
+            if excess_bytes == 0 {
+                break;
+            }
+
+            // The partition may have been persisted since it was measured.
+            let data = match p.partition.lock().mark_persisting() {
+                Some(v) => v,
+                None => continue,
+            };
+
+            excess_bytes = excess_bytes.saturating_sub(p.buffered_bytes);
+            n += 1;
+
+            // The completion notification is not needed - the memory is
+            // measured again at the next check.
+            persist.queue_persist(p.partition, data).await;
+        }
+
+        persist_count.inc(n);
+        info!(
+            n_partitions = n,
+            "queued partitions for persist to release memory"
+        );
+    }
+}
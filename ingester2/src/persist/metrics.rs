@@ -0,0 +1,167 @@
+//! Metrics describing the execution of persist jobs.
+
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use arrow::{datatypes::SchemaRef, error::Result as ArrowResult, record_batch::RecordBatch};
+use datafusion::physical_plan::{RecordBatchStream, SendableRecordBatchStream};
+use futures::Stream;
+use metric::{DurationHistogram, Metric, U64Counter};
+use parking_lot::Mutex;
+use pin_project::pin_project;
+
+/// The durations of the stages of persist jobs, the data they persist, and
+/// their failures.
+#[derive(Debug)]
+pub(super) struct PersistMetrics {
+    /// The time from a job being enqueued to a worker starting it.
+    pub(super) queue_duration: DurationHistogram,
+    /// The time spent sorting and deduplicating the data of a parquet file.
+    pub(super) compact_duration: DurationHistogram,
+    /// The time spent encoding the compacted data of a parquet file.
+    pub(super) encode_duration: DurationHistogram,
+    /// The time spent writing an encoded parquet file to object storage.
+    pub(super) upload_duration: DurationHistogram,
+
+    /// The parquet files added to the catalog, and their data.
+    pub(super) files: U64Counter,
+    pub(super) file_bytes: U64Counter,
+    pub(super) file_rows: U64Counter,
+
+    /// The failed attempts at writing a parquet file, by cause.
+    write_failures: Metric<U64Counter>,
+    /// The quarantined jobs, by the operation that exhausted their attempts.
+    job_failures: Metric<U64Counter>,
+}
+
+impl PersistMetrics {
+    pub(super) fn new(metrics: &metric::Registry) -> Self {
+        let duration = metrics.register_metric::<DurationHistogram>(
+            "ingester_persist_duration",
+            "duration of the stages of persist jobs",
+        );
+        let output = metrics.register_metric::<U64Counter>(
+            "ingester_persist_output",
+            "parquet files added to the catalog by persist jobs, and the data they hold",
+        );
+
+        Self {
+            queue_duration: duration.recorder(&[("stage", "queued")]),
+            compact_duration: duration.recorder(&[("stage", "compact")]),
+            encode_duration: duration.recorder(&[("stage", "encode")]),
+            upload_duration: duration.recorder(&[("stage", "upload")]),
+            files: output.recorder(&[("unit", "files")]),
+            file_bytes: output.recorder(&[("unit", "bytes")]),
+            file_rows: output.recorder(&[("unit", "rows")]),
+            write_failures: metrics.register_metric::<U64Counter>(
+                "ingester_persist_write_failures",
+                "number of failed attempts at writing a parquet file, by cause",
+            ),
+            job_failures: metrics.register_metric::<U64Counter>(
+                "ingester_persist_job_failures",
+                "number of quarantined persist jobs, by the operation that failed",
+            ),
+        }
+    }
+
+    /// Record a failed attempt at writing a parquet file, due to `cause`.
+    pub(super) fn write_failed(&self, cause: &'static str) {
+        self.write_failures.recorder(&[("cause", cause)]).inc(1);
+    }
+
+    /// Record the quarantining of a job, after failing `operation` too many
+    /// times (or waiting for an earlier job of its partition).
+    pub(super) fn job_failed(&self, operation: &'static str) {
+        self.job_failures
+            .recorder(&[("operation", operation)])
+            .inc(1);
+    }
+}
+
+/// A [`RecordBatchStream`] adapter accumulating the time its consumer waits
+/// for each record batch of the wrapped stream.
+///
+/// The compaction plan of a persist job executes as its output stream is
+/// polled, so the time spent waiting on the compacted stream separates the
+/// compaction from the encoding of the parquet file consuming it.
+#[pin_project]
+pub(super) struct WaitTimedStream {
+    #[pin]
+    inner: SendableRecordBatchStream,
+    waiting_since: Option<Instant>,
+    waited: Arc<Mutex<Duration>>,
+}
+
+impl WaitTimedStream {
+    /// Wrap `inner`, returning the stream and the total time spent waiting on
+    /// it, updated as it is consumed.
+    pub(super) fn new(inner: SendableRecordBatchStream) -> (Self, Arc<Mutex<Duration>>) {
+        let waited = Arc::new(Mutex::new(Duration::ZERO));
+        let s = Self {
+            inner,
+            waiting_since: None,
+            waited: Arc::clone(&waited),
+        };
+        (s, waited)
+    }
+}
+
+impl Stream for WaitTimedStream {
+    type Item = ArrowResult<RecordBatch>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let started = *this.waiting_since.get_or_insert_with(Instant::now);
+
+        let res = this.inner.poll_next(cx);
+        if res.is_ready() {
+            *this.waited.lock() += started.elapsed();
+            *this.waiting_since = None;
+        }
+        res
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl RecordBatchStream for WaitTimedStream {
+    fn schema(&self) -> SchemaRef {
+        self.inner.schema()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion_util::MemoryStream;
+    use futures::StreamExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait_timed_stream() {
+        let batch = mutable_batch_lp::lines_to_batches("bananas v=1 10", 0)
+            .unwrap()
+            .remove("bananas")
+            .unwrap()
+            .to_arrow(schema::Projection::All)
+            .unwrap();
+
+        let (stream, waited) = WaitTimedStream::new(Box::pin(MemoryStream::new(vec![
+            batch.clone(),
+            batch.clone(),
+        ])));
+        assert_eq!(*waited.lock(), Duration::ZERO);
+        assert_eq!(stream.schema(), batch.schema());
+
+        // The batches of the wrapped stream are passed through unchanged.
+        let got = stream.collect::<Vec<_>>().await;
+        assert_eq!(got.len(), 2);
+        assert!(got.into_iter().all(|b| b.unwrap() == batch));
+    }
+}
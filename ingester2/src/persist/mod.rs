@@ -1,4 +1,22 @@
 mod actor;
+pub(crate) mod audit;
 pub(super) mod compact;
+pub(crate) mod completion;
 mod context;
+pub(crate) mod file_cache;
 pub(crate) mod handle;
+pub(crate) mod hot_partition;
+pub(crate) mod memory_pressure;
+mod metrics;
+pub(crate) mod orphans;
+pub(crate) mod pending;
+pub(crate) mod policy;
+pub(crate) mod priority;
+pub(crate) mod quarantine;
+pub(crate) mod scaling;
+mod serialize;
+pub(crate) mod state;
+#[cfg(test)]
+mod test_util;
+mod throttle;
+mod verify;
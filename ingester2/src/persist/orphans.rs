@@ -0,0 +1,311 @@
+//! Finding the objects under the object store prefix of a namespace that are
+//! not referenced by the catalog.
+
+use std::{collections::HashSet, time::Duration};
+
+use data_types::NamespaceId;
+use futures::TryStreamExt;
+use iox_catalog::interface::Catalog;
+use iox_time::{Time, TimeProvider};
+use object_store::path::Path as ObjectPath;
+use observability_deps::tracing::*;
+use parquet_file::storage::ParquetStorage;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// The age below which an object is not considered orphaned, if not
+/// specified.
+///
+/// A parquet file is uploaded before it is added to the catalog, so a recent
+/// object not found in the catalog may be being persisted.
+pub(crate) const DEFAULT_ORPHAN_MIN_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The age below which an object is never deleted as orphaned.
+///
+/// A persist job retries adding its uploaded files to the catalog (with a
+/// backoff) for as long as the catalog is unavailable, so a younger object may
+/// yet be committed.
+pub(crate) const MIN_ORPHAN_DELETE_AGE: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Error)]
+pub(crate) enum OrphanError {
+    #[error(
+        "refusing to delete objects younger than {:?} (requested min age {:?}), which may be \
+        uploaded by running persist jobs",
+        MIN_ORPHAN_DELETE_AGE,
+        min_age
+    )]
+    MinAgeTooShort { min_age: Duration },
+
+    #[error("failed to list objects: {0}")]
+    List(#[from] object_store::Error),
+
+    #[error("failed to read parquet files from catalog: {0}")]
+    Catalog(#[from] iox_catalog::interface::Error),
+}
+
+/// An object not referenced by any parquet file in the catalog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct OrphanedFile {
+    pub(crate) path: ObjectPath,
+    pub(crate) size: usize,
+    pub(crate) age: Duration,
+    /// True if the object was deleted.
+    pub(crate) deleted: bool,
+}
+
+/// Find the objects in `store` under the prefix of `namespace_id` that are at
+/// least `min_age` old (as of the current time of `time_provider`), and are
+/// not a parquet file in `catalog` (including those marked for deletion, which
+/// are removed by the garbage collector).
+///
+/// If `delete` is true the orphaned objects are deleted, otherwise they are
+/// only reported. Deleting objects younger than [`MIN_ORPHAN_DELETE_AGE`] is
+/// rejected. The orphaned objects are returned ordered by path. A failure to
+/// delete an object is logged, and reported as not deleted.
+pub(crate) async fn find_orphaned_files(
+    catalog: &dyn Catalog,
+    store: &ParquetStorage,
+    time_provider: &dyn TimeProvider,
+    namespace_id: NamespaceId,
+    min_age: Duration,
+    delete: bool,
+) -> Result<Vec<OrphanedFile>, OrphanError> {
+    if delete && min_age < MIN_ORPHAN_DELETE_AGE {
+        return Err(OrphanError::MinAgeTooShort { min_age });
+    }
+
+    let object_store = store.object_store_for(namespace_id);
+    let prefix = store.namespace_prefix(namespace_id);
+    let now = time_provider.now();
+
+    // Read the objects before the catalog, so that any object uploaded and
+    // committed in between is found in the catalog.
+    let objects = object_store
+        .list(Some(&prefix))
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    let referenced = catalog
+        .repositories()
+        .await
+        .parquet_files()
+        .list_by_namespace(namespace_id)
+        .await?
+        .into_iter()
+        .map(|f| f.object_store_id)
+        .collect::<HashSet<_>>();
+
+    let mut orphans = Vec::new();
+    for object in objects {
+        let age = now
+            .checked_duration_since(Time::from_date_time(object.last_modified))
+            .unwrap_or_default();
+        if age < min_age {
+            continue;
+        }
+
+        if object_store_id(&object.location).map_or(false, |id| referenced.contains(&id)) {
+            continue;
+        }
+
+        let deleted = delete
+            && match object_store.delete(&object.location).await {
+                Ok(()) | Err(object_store::Error::NotFound { .. }) => true,
+                Err(e) => {
+                    warn!(
+                        %namespace_id,
+                        location = %object.location,
+                        error = %e,
+                        "failed to delete orphaned object"
+                    );
+                    false
+                }
+            };
+
+        info!(
+            %namespace_id,
+            location = %object.location,
+            size = object.size,
+            age_secs = age.as_secs(),
+            deleted,
+            "found orphaned object"
+        );
+
+        orphans.push(OrphanedFile {
+            path: object.location,
+            size: object.size,
+            age,
+            deleted,
+        });
+    }
+
+    orphans.sort_by(|a, b| a.path.as_ref().cmp(b.path.as_ref()));
+    Ok(orphans)
+}
+
+/// Parse the object store ID from the name of a parquet file at `path`.
+fn object_store_id(path: &ObjectPath) -> Option<Uuid> {
+    let name = path.parts().last()?;
+    name.as_ref().strip_suffix(".parquet")?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use assert_matches::assert_matches;
+    use bytes::Bytes;
+    use data_types::{
+        ColumnId, ColumnSet, CompactionLevel, ParquetFileParams, SequenceNumber, ShardIndex,
+        Timestamp,
+    };
+    use iox_catalog::mem::MemCatalog;
+    use iox_time::{MockProvider, SystemProvider};
+    use object_store::{memory::InMemory, ObjectStore};
+    use parquet_file::{storage::StorageId, ParquetFilePath};
+
+    use super::*;
+    use crate::test_util::populate_catalog;
+
+    #[tokio::test]
+    async fn test_find_orphaned_files() {
+        let metrics = Arc::new(metric::Registry::default());
+        let catalog: Arc<dyn Catalog> = Arc::new(MemCatalog::new(Arc::clone(&metrics)));
+        let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let store = ParquetStorage::new(Arc::clone(&object_store), StorageId::from("iox"));
+
+        let (shard_id, namespace_id, table_id) =
+            populate_catalog(&*catalog, ShardIndex::new(1), "platanos", "bananas").await;
+        let partition_id = catalog
+            .repositories()
+            .await
+            .partitions()
+            .create_or_get("p1".into(), shard_id, table_id)
+            .await
+            .unwrap()
+            .id;
+
+        // A committed file, a committed file marked for deletion, a file that
+        // was never committed, and a stray object that is not a parquet file.
+        let mut paths = Vec::new();
+        for committed in [Some(false), Some(true), None] {
+            let object_store_id = Uuid::new_v4();
+            let path = ParquetFilePath::new(
+                namespace_id,
+                table_id,
+                shard_id,
+                partition_id,
+                object_store_id,
+            )
+            .object_store_path();
+            object_store
+                .put(&path, Bytes::from_static(b"bananas"))
+                .await
+                .unwrap();
+            paths.push(path);
+
+            let to_delete = match committed {
+                Some(v) => v,
+                None => continue,
+            };
+            let mut repos = catalog.repositories().await;
+            let file = repos
+                .parquet_files()
+                .create(ParquetFileParams {
+                    shard_id,
+                    namespace_id,
+                    table_id,
+                    partition_id,
+                    object_store_id,
+                    max_sequence_number: SequenceNumber::new(1),
+                    min_time: Timestamp::new(1),
+                    max_time: Timestamp::new(2),
+                    file_size_bytes: 7,
+                    row_count: 1,
+                    compaction_level: CompactionLevel::Initial,
+                    created_at: Timestamp::new(1),
+                    column_set: ColumnSet::new([ColumnId::new(1)]),
+                })
+                .await
+                .unwrap();
+            if to_delete {
+                repos
+                    .parquet_files()
+                    .flag_for_delete(file.id)
+                    .await
+                    .unwrap();
+            }
+        }
+        let stray = ObjectPath::from(format!("{namespace_id}/bananas.txt"));
+        object_store
+            .put(&stray, Bytes::from_static(b"bananas"))
+            .await
+            .unwrap();
+
+        // And an uncommitted file of another namespace, which is ignored.
+        let other = ObjectPath::from(format!(
+            "{}/{}.parquet",
+            namespace_id.get() + 1,
+            Uuid::new_v4()
+        ));
+        object_store
+            .put(&other, Bytes::from_static(b"bananas"))
+            .await
+            .unwrap();
+
+        let time_provider = MockProvider::new(SystemProvider::new().now());
+        let find = |min_age, delete| {
+            find_orphaned_files(
+                &*catalog,
+                &store,
+                &time_provider,
+                namespace_id,
+                min_age,
+                delete,
+            )
+        };
+
+        // Recent objects are ignored.
+        assert!(find(MIN_ORPHAN_DELETE_AGE, false).await.unwrap().is_empty());
+
+        // Once old enough, a dry run reports the orphaned objects, without
+        // deleting them.
+        time_provider.inc(MIN_ORPHAN_DELETE_AGE * 2);
+        let got = find(MIN_ORPHAN_DELETE_AGE, false).await.unwrap();
+        assert_eq!(
+            got.iter().map(|f| f.path.clone()).collect::<Vec<_>>(),
+            [paths[2].clone(), stray.clone()]
+        );
+        assert!(got
+            .iter()
+            .all(|f| !f.deleted && f.size == 7 && f.age >= MIN_ORPHAN_DELETE_AGE * 2));
+        assert!(object_store.head(&paths[2]).await.is_ok());
+        assert!(find(DEFAULT_ORPHAN_MIN_AGE, false)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Objects that may still be committed by a persist job are never
+        // deleted.
+        assert_matches!(
+            find(Duration::ZERO, true).await,
+            Err(OrphanError::MinAgeTooShort { .. })
+        );
+        assert!(object_store.head(&paths[2]).await.is_ok());
+
+        // Older objects are deleted.
+        let got = find(MIN_ORPHAN_DELETE_AGE, true).await.unwrap();
+        assert_eq!(got.len(), 2);
+        assert!(got.iter().all(|f| f.deleted));
+        assert!(object_store.head(&paths[2]).await.is_err());
+        assert!(object_store.head(&stray).await.is_err());
+
+        // Leaving the referenced files, and those of other namespaces.
+        for path in [&paths[0], &paths[1], &other] {
+            assert!(object_store.head(path).await.is_ok());
+        }
+        assert!(find(MIN_ORPHAN_DELETE_AGE, true).await.unwrap().is_empty());
+    }
+}
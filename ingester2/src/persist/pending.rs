@@ -0,0 +1,336 @@
+//! Durable markers of the parquet files uploaded to object storage, but not
+//! (yet) added to the catalog.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use iox_catalog::interface::Catalog;
+use object_store::path::Path as ObjectPath;
+use observability_deps::tracing::*;
+use parquet_file::storage::ParquetStorage;
+use uuid::Uuid;
+
+/// The subdirectory of the WAL directory holding the markers - the WAL ignores
+/// the directories alongside its segment files.
+pub(crate) const PENDING_UPLOADS_DIRECTORY: &str = "pending_uploads";
+
+/// The file extension of a marker file, named after the object store ID of the
+/// parquet file it marks.
+const MARKER_EXTENSION: &str = "upload";
+
+/// The file extension of a marker file being written.
+const MARKER_TMP_EXTENSION: &str = "tmp";
+
+/// A set of marker files in a local directory, each recording a parquet file
+/// that may have been (partially) uploaded to object storage, but was not
+/// known to have been added to the catalog.
+///
+/// A marker is durably written before the upload of a file starts, and
+/// removed once the file is committed to the catalog (or discarded after a
+/// failed attempt), so that a crash in between leaves a record of the object
+/// to be resolved by [`PendingUploads::reconcile()`] at the next startup,
+/// instead of an object no component knows about.
+#[derive(Debug)]
+pub(crate) struct PendingUploads {
+    dir: PathBuf,
+}
+
+/// The outcome of [`PendingUploads::reconcile()`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Reconciled {
+    /// Files found in the catalog, whose markers were removed.
+    pub(crate) committed: usize,
+    /// Files not found in the catalog, deleted from object storage.
+    pub(crate) deleted: usize,
+    /// Files that could not be resolved, whose markers were retained for the
+    /// next startup.
+    pub(crate) retained: usize,
+}
+
+impl PendingUploads {
+    /// Keep the markers in `dir`, creating it if necessary.
+    pub(crate) async fn new(dir: PathBuf) -> Result<Self, io::Error> {
+        let d = dir.clone();
+        tokio::task::spawn_blocking(move || std::fs::create_dir_all(d))
+            .await
+            .expect("pending upload directory task panicked")?;
+
+        Ok(Self { dir })
+    }
+
+    /// Durably record the upload of the parquet file `object_store_id` to
+    /// `path`, before it starts.
+    pub(super) async fn begin(
+        &self,
+        object_store_id: Uuid,
+        path: &ObjectPath,
+    ) -> Result<(), io::Error> {
+        let marker = self.marker_path(object_store_id);
+        let tmp = marker.with_extension(MARKER_TMP_EXTENSION);
+        let dir = self.dir.clone();
+        let contents = path.to_string();
+
+        // The marker is written to a temporary file and renamed, so a marker
+        // is never partially written.
+        tokio::task::spawn_blocking(move || {
+            std::fs::write(&tmp, contents)?;
+            std::fs::File::open(&tmp)?.sync_all()?;
+            std::fs::rename(&tmp, &marker)?;
+            std::fs::File::open(&dir)?.sync_all()
+        })
+        .await
+        .expect("pending upload marker write task panicked")
+    }
+
+    /// Remove the marker of the parquet file `object_store_id`, once it has
+    /// been committed to the catalog or deleted from object storage.
+    pub(super) async fn finish(&self, object_store_id: Uuid) -> Result<(), io::Error> {
+        remove_file(self.marker_path(object_store_id)).await
+    }
+
+    /// Resolve the parquet files left marked by a previous instance of the
+    /// ingester.
+    ///
+    /// A marked file found in the catalog was committed before the marker was
+    /// removed, and is kept. Any other marked file was never committed, and is
+    /// deleted from object storage - its data was not marked as persisted, so
+    /// it is still in the WAL and persisted again once replayed.
+    ///
+    /// This MUST be called before any persist job starts, otherwise the
+    /// uploads of running jobs may be deleted.
+    pub(crate) async fn reconcile(
+        &self,
+        catalog: &dyn Catalog,
+        store: &ParquetStorage,
+    ) -> Result<Reconciled, io::Error> {
+        let mut reconciled = Reconciled::default();
+
+        for (object_store_id, path) in read_markers(self.dir.clone()).await? {
+            let committed = catalog
+                .repositories()
+                .await
+                .parquet_files()
+                .get_by_object_store_id(object_store_id)
+                .await;
+
+            match committed {
+                Ok(Some(_)) => {
+                    debug!(%object_store_id, %path, "pending parquet upload was committed");
+                    reconciled.committed += 1;
+                }
+                Ok(None) => match store.object_store_for_location(&path).delete(&path).await {
+                    Ok(()) | Err(object_store::Error::NotFound { .. }) => {
+                        info!(%object_store_id, %path, "deleted uncommitted parquet upload");
+                        reconciled.deleted += 1;
+                    }
+                    Err(e) => {
+                        warn!(
+                            %object_store_id,
+                            %path,
+                            error=%e,
+                            "failed to delete uncommitted parquet upload"
+                        );
+                        reconciled.retained += 1;
+                        continue;
+                    }
+                },
+                Err(e) => {
+                    warn!(
+                        %object_store_id,
+                        %path,
+                        error=%e,
+                        "failed to resolve pending parquet upload"
+                    );
+                    reconciled.retained += 1;
+                    continue;
+                }
+            }
+
+            self.finish(object_store_id).await?;
+        }
+
+        if reconciled != Reconciled::default() {
+            info!(
+                committed = reconciled.committed,
+                deleted = reconciled.deleted,
+                retained = reconciled.retained,
+                "reconciled pending parquet uploads"
+            );
+        }
+
+        Ok(reconciled)
+    }
+
+    fn marker_path(&self, object_store_id: Uuid) -> PathBuf {
+        self.dir
+            .join(object_store_id.to_string())
+            .with_extension(MARKER_EXTENSION)
+    }
+}
+
+/// Read the object store ID and path of each marker in `dir`.
+///
+/// Partially written markers are removed - the upload they mark never
+/// started. Markers that cannot be parsed are logged and skipped.
+async fn read_markers(dir: PathBuf) -> Result<Vec<(Uuid, ObjectPath)>, io::Error> {
+    tokio::task::spawn_blocking(move || {
+        let mut markers = Vec::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            match path.extension().and_then(|v| v.to_str()) {
+                Some(MARKER_EXTENSION) => {}
+                Some(MARKER_TMP_EXTENSION) => {
+                    std::fs::remove_file(&path)?;
+                    continue;
+                }
+                _ => continue,
+            }
+
+            match parse_marker(&path) {
+                Some(v) => markers.push(v),
+                None => warn!(
+                    path=%path.display(),
+                    "ignoring invalid pending parquet upload marker"
+                ),
+            }
+        }
+        Ok(markers)
+    })
+    .await
+    .expect("pending upload marker read task panicked")
+}
+
+fn parse_marker(path: &Path) -> Option<(Uuid, ObjectPath)> {
+    let object_store_id = path.file_stem()?.to_str()?.parse().ok()?;
+    let contents = std::fs::read_to_string(path).ok()?;
+    let object_path = ObjectPath::parse(contents.trim()).ok()?;
+    Some((object_store_id, object_path))
+}
+
+async fn remove_file(path: PathBuf) -> Result<(), io::Error> {
+    tokio::task::spawn_blocking(move || match std::fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    })
+    .await
+    .expect("pending upload marker remove task panicked")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+    use data_types::{
+        ColumnId, ColumnSet, CompactionLevel, ParquetFileParams, SequenceNumber, Timestamp,
+    };
+    use iox_catalog::mem::MemCatalog;
+    use object_store::{memory::InMemory, ObjectStore};
+    use parquet_file::storage::StorageId;
+
+    use super::*;
+    use crate::test_util::populate_catalog;
+
+    #[tokio::test]
+    async fn test_reconcile() {
+        let dir = tempfile::tempdir().unwrap();
+        let metrics = Arc::new(metric::Registry::default());
+        let catalog: Arc<dyn Catalog> = Arc::new(MemCatalog::new(Arc::clone(&metrics)));
+        let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let store = ParquetStorage::new(Arc::clone(&object_store), StorageId::from("iox"));
+
+        let (shard_id, namespace_id, table_id) = populate_catalog(
+            &*catalog,
+            data_types::ShardIndex::new(1),
+            "platanos",
+            "bananas",
+        )
+        .await;
+        let partition_id = catalog
+            .repositories()
+            .await
+            .partitions()
+            .create_or_get("p1".into(), shard_id, table_id)
+            .await
+            .unwrap()
+            .id;
+
+        let pending = PendingUploads::new(dir.path().join("pending"))
+            .await
+            .unwrap();
+
+        // A file committed to the catalog before the crash, an uploaded but
+        // uncommitted file, and a file whose upload never completed.
+        let committed = Uuid::new_v4();
+        let uncommitted = Uuid::new_v4();
+        let missing = Uuid::new_v4();
+        for id in [committed, uncommitted, missing] {
+            let path = ObjectPath::from(format!("iox/{id}.parquet"));
+            pending.begin(id, &path).await.unwrap();
+            if id != missing {
+                object_store
+                    .put(&path, Bytes::from_static(b"bananas"))
+                    .await
+                    .unwrap();
+            }
+        }
+        catalog
+            .repositories()
+            .await
+            .parquet_files()
+            .create(ParquetFileParams {
+                shard_id,
+                namespace_id,
+                table_id,
+                partition_id,
+                object_store_id: committed,
+                max_sequence_number: SequenceNumber::new(1),
+                min_time: Timestamp::new(1),
+                max_time: Timestamp::new(2),
+                file_size_bytes: 7,
+                row_count: 1,
+                compaction_level: CompactionLevel::Initial,
+                created_at: Timestamp::new(1),
+                column_set: ColumnSet::new([ColumnId::new(1)]),
+            })
+            .await
+            .unwrap();
+
+        // A partially written marker is discarded.
+        std::fs::write(
+            dir.path().join("pending").join("partial.tmp"),
+            "iox/partial",
+        )
+        .unwrap();
+
+        let got = pending.reconcile(&*catalog, &store).await.unwrap();
+        assert_eq!(
+            got,
+            Reconciled {
+                committed: 1,
+                deleted: 2,
+                retained: 0,
+            }
+        );
+
+        // Only the committed file remains in object storage.
+        let committed_path = ObjectPath::from(format!("iox/{committed}.parquet"));
+        let uncommitted_path = ObjectPath::from(format!("iox/{uncommitted}.parquet"));
+        assert!(object_store.head(&committed_path).await.is_ok());
+        assert!(object_store.head(&uncommitted_path).await.is_err());
+
+        // And all the markers were removed.
+        assert_eq!(
+            std::fs::read_dir(dir.path().join("pending"))
+                .unwrap()
+                .count(),
+            0
+        );
+        assert_eq!(
+            pending.reconcile(&*catalog, &store).await.unwrap(),
+            Reconciled::default()
+        );
+    }
+}
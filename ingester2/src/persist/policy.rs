@@ -0,0 +1,227 @@
+//! Per-namespace and per-table overrides of the partition persistence
+//! triggers, sourced from the catalog.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use data_types::{NamespaceId, PersistPolicy, TableId};
+use iox_catalog::interface::Catalog;
+use observability_deps::tracing::*;
+use parking_lot::RwLock;
+use tokio::time::MissedTickBehavior;
+
+use super::hot_partition::HotPartitionThresholds;
+
+/// How often the persist policies are read from the catalog.
+pub(crate) const POLICY_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The conditions under which the data buffered for a partition is persisted
+/// without waiting for the next WAL rotation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct PersistTriggers {
+    /// The age of the oldest buffered write.
+    pub(crate) max_age: Option<Duration>,
+    /// The amount of buffered data.
+    pub(crate) thresholds: HotPartitionThresholds,
+}
+
+impl PersistTriggers {
+    /// Returns true if any trigger is set.
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.max_age.is_some() || self.thresholds.is_enabled()
+    }
+
+    /// Returns true if a partition buffering `buffered_bytes` bytes of data in
+    /// `buffered_rows` rows, the oldest of which was buffered `buffered_age`
+    /// ago, reaches any of the triggers.
+    pub(crate) fn should_persist(
+        &self,
+        buffered_age: Option<Duration>,
+        buffered_bytes: usize,
+        buffered_rows: usize,
+    ) -> bool {
+        self.thresholds.is_hot(buffered_bytes, buffered_rows)
+            || matches!((self.max_age, buffered_age), (Some(max), Some(age)) if age >= max)
+    }
+
+    /// Override the triggers set in `policy`.
+    ///
+    /// Negative values are invalid, and ignored.
+    fn apply(&mut self, policy: &PersistPolicy) {
+        if let Some(v) = policy.max_age_ms.and_then(|v| u64::try_from(v).ok()) {
+            self.max_age = Some(Duration::from_millis(v));
+        }
+        if let Some(v) = policy.max_bytes.and_then(|v| usize::try_from(v).ok()) {
+            self.thresholds.max_bytes = Some(v);
+        }
+        if let Some(v) = policy.max_rows.and_then(|v| usize::try_from(v).ok()) {
+            self.thresholds.max_rows = Some(v);
+        }
+    }
+}
+
+/// The [`PersistPolicy`] overrides defined in the catalog, keyed by the
+/// namespace and table (or [`None`] for all tables in the namespace) they
+/// apply to.
+#[derive(Debug, Default)]
+pub(crate) struct PersistPolicies {
+    policies: RwLock<HashMap<(NamespaceId, Option<TableId>), PersistPolicy>>,
+}
+
+impl PersistPolicies {
+    /// Replace all the policies with `policies`.
+    pub(crate) fn set(&self, policies: impl IntoIterator<Item = PersistPolicy>) {
+        let policies = policies
+            .into_iter()
+            .map(|p| ((p.namespace_id, p.table_id), p))
+            .collect();
+        *self.policies.write() = policies;
+    }
+
+    /// Returns true if no policies are defined.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.policies.read().is_empty()
+    }
+
+    /// Resolve the [`PersistTriggers`] of the partitions of `table_id` in
+    /// `namespace_id`.
+    ///
+    /// Each trigger set by the table policy takes precedence over the
+    /// namespace policy, which takes precedence over the `default` thresholds.
+    pub(crate) fn triggers(
+        &self,
+        namespace_id: NamespaceId,
+        table_id: TableId,
+        default: HotPartitionThresholds,
+    ) -> PersistTriggers {
+        let mut triggers = PersistTriggers {
+            max_age: None,
+            thresholds: default,
+        };
+
+        let policies = self.policies.read();
+        for key in [(namespace_id, None), (namespace_id, Some(table_id))] {
+            if let Some(p) = policies.get(&key) {
+                triggers.apply(p);
+            }
+        }
+
+        triggers
+    }
+}
+
+/// Periodically read the persist policies from `catalog` into `policies`.
+///
+/// If the catalog request fails, the previously read policies are retained
+/// until the next refresh.
+pub(crate) async fn refresh_persist_policies(
+    policies: Arc<PersistPolicies>,
+    catalog: Arc<dyn Catalog>,
+    interval: Duration,
+) {
+    let mut interval = tokio::time::interval(interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        match catalog.repositories().await.persist_policies().list().await {
+            Ok(v) => {
+                debug!(n_policies = v.len(), "refreshed persist policies");
+                policies.set(v);
+            }
+            Err(e) => warn!(error=%e, "failed to read persist policies"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMESPACE_ID: NamespaceId = NamespaceId::new(1);
+    const TABLE_ID: TableId = TableId::new(2);
+
+    const DEFAULT: HotPartitionThresholds = HotPartitionThresholds {
+        max_bytes: Some(1024),
+        max_rows: None,
+    };
+
+    #[test]
+    fn test_should_persist() {
+        let triggers = PersistTriggers {
+            max_age: Some(Duration::from_secs(60)),
+            thresholds: DEFAULT,
+        };
+        assert!(triggers.is_enabled());
+        assert!(triggers.should_persist(Some(Duration::from_secs(60)), 0, 0));
+        assert!(triggers.should_persist(None, 1024, 0));
+        assert!(!triggers.should_persist(Some(Duration::from_secs(59)), 1023, 0));
+        assert!(!triggers.should_persist(None, 0, 0));
+
+        assert!(!PersistTriggers::default().is_enabled());
+    }
+
+    #[test]
+    fn test_triggers() {
+        let policies = PersistPolicies::default();
+
+        // Without policies, the default thresholds apply.
+        assert_eq!(
+            policies.triggers(NAMESPACE_ID, TABLE_ID, DEFAULT),
+            PersistTriggers {
+                max_age: None,
+                thresholds: DEFAULT,
+            }
+        );
+
+        policies.set([
+            PersistPolicy {
+                namespace_id: NAMESPACE_ID,
+                table_id: None,
+                max_age_ms: Some(60_000),
+                max_rows: Some(100),
+                max_bytes: None,
+            },
+            PersistPolicy {
+                namespace_id: NAMESPACE_ID,
+                table_id: Some(TABLE_ID),
+                max_age_ms: None,
+                max_rows: Some(10),
+                max_bytes: Some(-1),
+            },
+        ]);
+
+        // The table policy overrides the namespace policy, which overrides the
+        // defaults, ignoring invalid values.
+        assert_eq!(
+            policies.triggers(NAMESPACE_ID, TABLE_ID, DEFAULT),
+            PersistTriggers {
+                max_age: Some(Duration::from_secs(60)),
+                thresholds: HotPartitionThresholds {
+                    max_bytes: Some(1024),
+                    max_rows: Some(10),
+                },
+            }
+        );
+
+        // Other tables in the namespace use the namespace policy.
+        assert_eq!(
+            policies.triggers(NAMESPACE_ID, TableId::new(42), DEFAULT),
+            PersistTriggers {
+                max_age: Some(Duration::from_secs(60)),
+                thresholds: HotPartitionThresholds {
+                    max_bytes: Some(1024),
+                    max_rows: Some(100),
+                },
+            }
+        );
+
+        // And other namespaces use the defaults.
+        assert_eq!(
+            policies
+                .triggers(NamespaceId::new(42), TABLE_ID, DEFAULT)
+                .max_age,
+            None
+        );
+    }
+}
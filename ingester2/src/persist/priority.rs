@@ -0,0 +1,259 @@
+//! Ordering the persist jobs queued for a worker.
+
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, VecDeque},
+    fmt::Debug,
+    sync::Arc,
+};
+
+use data_types::{PartitionId, SequenceNumber};
+use parking_lot::Mutex;
+use tokio::sync::{Notify, Semaphore};
+
+/// The properties of a queued persist job that its priority is derived from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct JobAttributes {
+    /// The ID assigned to the job, increasing in enqueue order.
+    pub(crate) job_id: u64,
+
+    /// The smallest [`SequenceNumber`] of the writes persisted by the job.
+    ///
+    /// The WAL segment containing this write (and all later segments) cannot
+    /// be dropped until the job completes.
+    pub(crate) min_sequence_number: SequenceNumber,
+
+    /// The estimated size of the persisted data, in bytes.
+    pub(crate) size: usize,
+}
+
+/// A policy ordering the persist jobs queued for a worker.
+///
+/// Only the jobs of different partitions are ordered by the policy - the jobs
+/// of a partition are always executed in the order they were enqueued.
+pub(crate) trait PersistPriority: Debug + Send + Sync {
+    /// Returns [`Ordering::Less`] if the job `a` should be executed before
+    /// `b`.
+    fn cmp(&self, a: &JobAttributes, b: &JobAttributes) -> Ordering;
+}
+
+/// Execute the job persisting the oldest data first, and of the jobs
+/// persisting data of the same age, the largest first.
+///
+/// Under a backlog this persists the data retaining the oldest WAL segments
+/// (and so the data most likely to fall outside the retention period) first,
+/// and then the data releasing the most memory.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct OldestFirst;
+
+impl PersistPriority for OldestFirst {
+    fn cmp(&self, a: &JobAttributes, b: &JobAttributes) -> Ordering {
+        a.min_sequence_number
+            .cmp(&b.min_sequence_number)
+            .then_with(|| b.size.cmp(&a.size))
+            .then_with(|| a.job_id.cmp(&b.job_id))
+    }
+}
+
+/// Execute the jobs in the order they were enqueued.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Fifo;
+
+impl PersistPriority for Fifo {
+    fn cmp(&self, a: &JobAttributes, b: &JobAttributes) -> Ordering {
+        a.job_id.cmp(&b.job_id)
+    }
+}
+
+/// A bounded queue of persist jobs, consumed by a single worker in the order
+/// given by a [`PersistPriority`].
+///
+/// The next job is chosen from the oldest queued job of each partition, so
+/// the jobs of a partition are consumed in the order they were pushed.
+#[derive(Debug)]
+pub(super) struct PriorityQueue<T> {
+    state: Mutex<QueueState<T>>,
+    priority: Arc<dyn PersistPriority>,
+
+    /// A permit for each free slot in the queue.
+    space: Semaphore,
+    /// Wakes the consumer when a job is pushed, or the queue is closed.
+    ready: Notify,
+}
+
+#[derive(Debug)]
+struct QueueState<T> {
+    /// The queued jobs of each partition, oldest first.
+    partitions: HashMap<PartitionId, VecDeque<(JobAttributes, T)>>,
+    closed: bool,
+}
+
+impl<T> PriorityQueue<T> {
+    /// Initialise an empty queue holding at most `depth` jobs.
+    pub(super) fn new(depth: usize, priority: Arc<dyn PersistPriority>) -> Self {
+        Self {
+            state: Mutex::new(QueueState {
+                partitions: Default::default(),
+                closed: false,
+            }),
+            priority,
+            space: Semaphore::new(depth),
+            ready: Notify::new(),
+        }
+    }
+
+    /// Enqueue the `job` of `partition_id`, waiting for a free slot if the
+    /// queue is full.
+    pub(super) async fn push(&self, partition_id: PartitionId, attributes: JobAttributes, job: T) {
+        // The permit is returned by pop() once the job leaves the queue.
+        self.space
+            .acquire()
+            .await
+            .expect("persist queue semaphore closed")
+            .forget();
+
+        self.state
+            .lock()
+            .partitions
+            .entry(partition_id)
+            .or_default()
+            .push_back((attributes, job));
+        self.ready.notify_one();
+    }
+
+    /// Dequeue the job with the highest priority, waiting for a job to be
+    /// pushed if the queue is empty.
+    ///
+    /// Returns [`None`] once the queue is closed and empty.
+    pub(super) async fn pop(&self) -> Option<T> {
+        loop {
+            {
+                let mut state = self.state.lock();
+                if let Some(job) = state.pop(&*self.priority) {
+                    drop(state);
+                    self.space.add_permits(1);
+                    return Some(job);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+
+            // A notification sent between releasing the lock and waiting is
+            // retained, so no push is missed.
+            self.ready.notified().await;
+        }
+    }
+
+    /// Stop the consumer once the queued jobs have been consumed.
+    pub(super) fn close(&self) {
+        self.state.lock().closed = true;
+        self.ready.notify_one();
+    }
+}
+
+impl<T> QueueState<T> {
+    fn pop(&mut self, priority: &dyn PersistPriority) -> Option<T> {
+        let partition_id = self
+            .partitions
+            .iter()
+            .map(|(id, jobs)| (*id, &jobs.front().expect("empty partition queue").0))
+            .min_by(|a, b| priority.cmp(a.1, b.1))?
+            .0;
+
+        let jobs = self.partitions.get_mut(&partition_id)?;
+        let (_, job) = jobs.pop_front()?;
+        if jobs.is_empty() {
+            self.partitions.remove(&partition_id);
+        }
+        Some(job)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::FutureExt;
+
+    use super::*;
+
+    fn attrs(job_id: u64, min_sequence_number: i64, size: usize) -> JobAttributes {
+        JobAttributes {
+            job_id,
+            min_sequence_number: SequenceNumber::new(min_sequence_number),
+            size,
+        }
+    }
+
+    #[test]
+    fn test_oldest_first() {
+        let mut jobs = vec![
+            attrs(1, 20, 100),
+            attrs(2, 10, 100),
+            attrs(3, 10, 500),
+            attrs(4, 30, 900),
+            attrs(5, 10, 500),
+        ];
+        jobs.sort_by(|a, b| OldestFirst.cmp(a, b));
+        assert_eq!(
+            jobs.iter().map(|v| v.job_id).collect::<Vec<_>>(),
+            [3, 5, 2, 1, 4]
+        );
+
+        jobs.sort_by(|a, b| Fifo.cmp(a, b));
+        assert_eq!(
+            jobs.iter().map(|v| v.job_id).collect::<Vec<_>>(),
+            [1, 2, 3, 4, 5]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_priority_queue() {
+        let queue = PriorityQueue::new(4, Arc::new(OldestFirst));
+        let p1 = PartitionId::new(1);
+        let p2 = PartitionId::new(2);
+
+        // The second job of p1 holds the oldest data, but cannot be consumed
+        // before the first job of its partition.
+        queue.push(p1, attrs(1, 30, 10), "p1-a").await;
+        queue.push(p2, attrs(2, 20, 10), "p2-a").await;
+        queue.push(p1, attrs(3, 10, 10), "p1-b").await;
+        queue.push(p2, attrs(4, 40, 10), "p2-b").await;
+
+        // The queue is full.
+        assert!(queue
+            .push(p2, attrs(5, 50, 10), "p2-c")
+            .now_or_never()
+            .is_none());
+
+        assert_eq!(queue.pop().await, Some("p2-a"));
+        assert_eq!(queue.pop().await, Some("p1-a"));
+        assert_eq!(queue.pop().await, Some("p1-b"));
+        assert_eq!(queue.pop().await, Some("p2-b"));
+
+        // Closing the queue stops the consumer once it is empty.
+        queue.push(p2, attrs(5, 50, 10), "p2-c").await;
+        queue.close();
+        assert_eq!(queue.pop().await, Some("p2-c"));
+        assert_eq!(queue.pop().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_priority_queue_wakes_consumer() {
+        let queue = Arc::new(PriorityQueue::new(1, Arc::new(Fifo)));
+
+        let consumer = tokio::spawn({
+            let queue = Arc::clone(&queue);
+            async move { queue.pop().await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        queue.push(PartitionId::new(1), attrs(1, 1, 1), 42).await;
+        let got = tokio::time::timeout(Duration::from_secs(5), consumer)
+            .await
+            .expect("consumer not woken")
+            .unwrap();
+        assert_eq!(got, Some(42));
+    }
+}
@@ -0,0 +1,106 @@
+//! Holding the persist jobs that repeatedly failed, until they are
+//! re-enqueued by an operator.
+
+use std::{collections::BTreeMap, time::Instant};
+
+use data_types::PartitionId;
+use metric::U64Gauge;
+use parking_lot::Mutex;
+
+use super::context::PersistRequest;
+
+/// A persist job removed from the persist queue.
+#[derive(Debug)]
+struct QuarantinedJob {
+    req: PersistRequest,
+    error: String,
+    at: Instant,
+}
+
+/// The persist jobs that failed too many times to be retried, keyed by
+/// partition.
+///
+/// The data of a quarantined job is neither dropped nor persisted - it remains
+/// in the partition (and is returned by queries), and the WAL segments
+/// containing it are retained, until the job is re-enqueued and succeeds.
+///
+/// Because the data of a partition must be persisted in order, the jobs of a
+/// partition enqueued after a quarantined job are quarantined behind it.
+#[derive(Debug)]
+pub(crate) struct Quarantine {
+    jobs: Mutex<BTreeMap<PartitionId, Vec<QuarantinedJob>>>,
+    jobs_gauge: U64Gauge,
+}
+
+impl Quarantine {
+    pub(super) fn new(metrics: &metric::Registry) -> Self {
+        Self {
+            jobs: Default::default(),
+            jobs_gauge: metrics
+                .register_metric::<U64Gauge>(
+                    "ingester_persist_quarantined_jobs",
+                    "number of persist jobs quarantined after failing too many times",
+                )
+                .recorder(&[]),
+        }
+    }
+
+    /// Quarantine the persist job `req`, which failed with `error`.
+    pub(super) fn insert(&self, req: PersistRequest, error: String) {
+        let mut jobs = self.jobs.lock();
+        let partition = jobs.entry(req.partition_id()).or_default();
+        partition.push(QuarantinedJob {
+            req,
+            error,
+            at: Instant::now(),
+        });
+        // Retain the persist order of the partition's jobs.
+        partition.sort_by_key(|j| j.req.batch_ident());
+
+        self.jobs_gauge.inc(1);
+    }
+
+    /// Remove the quarantined jobs of `partition_id` (or of all partitions, if
+    /// [`None`]), in the order they must be persisted.
+    pub(super) fn take(&self, partition_id: Option<PartitionId>) -> Vec<PersistRequest> {
+        let taken = {
+            let mut jobs = self.jobs.lock();
+            match partition_id {
+                Some(id) => jobs.remove(&id).unwrap_or_default(),
+                None => std::mem::take(&mut *jobs).into_values().flatten().collect(),
+            }
+        };
+
+        self.jobs_gauge.dec(taken.len() as _);
+        taken.into_iter().map(|j| j.req).collect()
+    }
+
+    /// Describe the partitions with quarantined jobs, ordered by partition ID.
+    pub(crate) fn snapshot(&self) -> Vec<QuarantinedPartition> {
+        self.jobs
+            .lock()
+            .iter()
+            .map(|(partition_id, jobs)| QuarantinedPartition {
+                partition_id: *partition_id,
+                jobs: jobs.len(),
+                error: jobs
+                    .iter()
+                    .max_by_key(|j| j.at)
+                    .map(|j| j.error.clone())
+                    .unwrap_or_default(),
+                quarantined_at: jobs.iter().map(|j| j.at).min().unwrap_or_else(Instant::now),
+            })
+            .collect()
+    }
+}
+
+/// A partition with quarantined persist jobs.
+#[derive(Debug, Clone)]
+pub(crate) struct QuarantinedPartition {
+    pub(crate) partition_id: PartitionId,
+    pub(crate) jobs: usize,
+    /// The error of the most recently quarantined job.
+    pub(crate) error: String,
+    /// The time the oldest job was quarantined.
+    pub(crate) quarantined_at: Instant,
+}
@@ -0,0 +1,207 @@
+//! Scaling of the number of persist worker tasks.
+
+use std::{
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use crate::init::PersistWorkers;
+
+/// The target latency applied when the workers are first configured with a
+/// fixed count, and later allowed to scale at runtime.
+pub(crate) const DEFAULT_TARGET_LATENCY: Duration = Duration::from_secs(30);
+
+/// How often the number of persist workers is re-evaluated.
+pub(super) const AUTOSCALE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The bounds within which the number of persist workers is scaled.
+///
+/// A `min` equal to `max` runs a fixed number of workers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct WorkerBounds {
+    /// The lowest number of workers - at least 1.
+    pub(crate) min: usize,
+    /// The highest number of workers - at least `min`.
+    pub(crate) max: usize,
+    /// The duration within which the outstanding persist jobs should
+    /// complete, above which more workers are started.
+    pub(crate) target_latency: Duration,
+}
+
+impl WorkerBounds {
+    /// Returns true if these bounds can be applied.
+    pub(crate) fn is_valid(&self) -> bool {
+        self.min > 0 && self.min <= self.max
+    }
+}
+
+impl From<PersistWorkers> for WorkerBounds {
+    fn from(v: PersistWorkers) -> Self {
+        match v {
+            PersistWorkers::Fixed(n) => Self {
+                min: n,
+                max: n,
+                target_latency: DEFAULT_TARGET_LATENCY,
+            },
+            PersistWorkers::Autoscale {
+                min,
+                max,
+                target_latency,
+            } => Self {
+                min,
+                max,
+                target_latency,
+            },
+        }
+    }
+}
+
+/// Counters describing the persist workers, shared between the
+/// [`PersistHandle`], [`PersistActor`] and worker tasks.
+///
+/// [`PersistHandle`]: super::handle::PersistHandle
+/// [`PersistActor`]: super::actor::PersistActor
+#[derive(Debug, Default)]
+pub(crate) struct PersistStats {
+    /// The number of persist jobs enqueued but not yet completed.
+    outstanding: AtomicUsize,
+    /// The number of persist jobs completed.
+    completed: AtomicU64,
+    /// The total duration of the completed persist jobs, in nanoseconds.
+    busy_nanos: AtomicU64,
+    /// The number of active workers, assigned the jobs of partitions without
+    /// outstanding jobs.
+    workers: AtomicUsize,
+}
+
+impl PersistStats {
+    pub(super) fn enqueued(&self) {
+        self.outstanding.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn completed(&self, duration: Duration) {
+        self.outstanding.fetch_sub(1, Ordering::Relaxed);
+        self.busy_nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+        self.completed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record the removal of an outstanding job that did not complete.
+    pub(super) fn removed(&self) {
+        self.outstanding.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn set_workers(&self, n: usize) {
+        self.workers.store(n, Ordering::Relaxed);
+    }
+
+    /// The number of persist jobs enqueued but not yet completed.
+    pub(crate) fn outstanding(&self) -> usize {
+        self.outstanding.load(Ordering::Relaxed)
+    }
+
+    /// The number of active workers.
+    pub(crate) fn workers(&self) -> usize {
+        self.workers.load(Ordering::Relaxed)
+    }
+
+    /// Return the number of completed persist jobs, and their total duration.
+    pub(super) fn snapshot(&self) -> (u64, Duration) {
+        (
+            self.completed.load(Ordering::Relaxed),
+            Duration::from_nanos(self.busy_nanos.load(Ordering::Relaxed)),
+        )
+    }
+}
+
+/// Compute the number of workers to run, given `current` workers, the number
+/// of `outstanding` persist jobs and the `mean_latency` of the persist jobs
+/// completed since the last evaluation, if any.
+///
+/// The number of workers needed to complete the outstanding jobs within the
+/// target latency is started immediately, while idle workers are stopped one
+/// at a time, to avoid oscillating between sizes.
+pub(super) fn desired_workers(
+    current: usize,
+    bounds: &WorkerBounds,
+    outstanding: usize,
+    mean_latency: Option<Duration>,
+) -> usize {
+    let needed = match mean_latency {
+        Some(l) => (outstanding as f64 * l.as_secs_f64() / bounds.target_latency.as_secs_f64())
+            .ceil() as usize,
+        // No jobs completed, but some are waiting for a worker.
+        None if outstanding > current => current + 1,
+        None if outstanding == 0 => 0,
+        None => current,
+    };
+
+    let next = match needed {
+        v if v > current => v,
+        v if v < current => current - 1,
+        _ => current,
+    };
+
+    next.clamp(bounds.min, bounds.max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOUNDS: WorkerBounds = WorkerBounds {
+        min: 2,
+        max: 8,
+        target_latency: Duration::from_secs(10),
+    };
+
+    #[test]
+    fn test_desired_workers() {
+        // 12 jobs taking 5s each complete within 10s on 6 workers.
+        assert_eq!(
+            desired_workers(2, &BOUNDS, 12, Some(Duration::from_secs(5))),
+            6
+        );
+
+        // Never more than the maximum.
+        assert_eq!(
+            desired_workers(2, &BOUNDS, 100, Some(Duration::from_secs(5))),
+            8
+        );
+
+        // Surplus workers are stopped one at a time, down to the minimum.
+        assert_eq!(
+            desired_workers(6, &BOUNDS, 1, Some(Duration::from_secs(5))),
+            5
+        );
+        assert_eq!(desired_workers(3, &BOUNDS, 0, None), 2);
+        assert_eq!(desired_workers(2, &BOUNDS, 0, None), 2);
+
+        // Without a latency measurement, a worker is added while jobs are
+        // waiting, and the workers are kept while they are all busy.
+        assert_eq!(desired_workers(2, &BOUNDS, 5, None), 3);
+        assert_eq!(desired_workers(4, &BOUNDS, 3, None), 4);
+
+        // A change of bounds is applied regardless of load.
+        let fixed = WorkerBounds {
+            min: 4,
+            max: 4,
+            ..BOUNDS
+        };
+        assert_eq!(desired_workers(8, &fixed, 100, None), 4);
+        assert_eq!(desired_workers(1, &fixed, 0, None), 4);
+    }
+
+    #[test]
+    fn test_bounds_validity() {
+        assert!(BOUNDS.is_valid());
+        assert!(WorkerBounds::from(PersistWorkers::Fixed(1)).is_valid());
+        assert!(!WorkerBounds::from(PersistWorkers::Fixed(0)).is_valid());
+        assert!(!WorkerBounds {
+            min: 9,
+            max: 8,
+            ..BOUNDS
+        }
+        .is_valid());
+    }
+}
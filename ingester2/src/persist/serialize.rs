@@ -0,0 +1,113 @@
+//! Serialising the persist jobs of each partition.
+
+use std::collections::{HashMap, VecDeque};
+
+use data_types::PartitionId;
+use metric::U64Gauge;
+
+/// Allows at most one persist job of each partition to be dispatched to the
+/// persist workers at once.
+///
+/// Persist jobs of a partition are triggered independently (by WAL rotation,
+/// hot partition persistence, memory pressure, shutdown, ...) and each
+/// compacts a snapshot of the partition's data and updates its sort key -
+/// neither of which may race with another job of the same partition.
+///
+/// The worker assignment of a partition already serialises its jobs while the
+/// assignment holds, but this makes the guarantee independent of the worker
+/// routing, and stops the queued jobs of one partition occupying the queue
+/// slots of its worker while they wait.
+///
+/// The jobs of a partition with a dispatched job are held back in enqueue
+/// order, and dispatched one at a time as the dispatched job completes.
+#[derive(Debug)]
+pub(super) struct PartitionSerializer<T> {
+    /// The jobs waiting for the dispatched job of their partition, oldest
+    /// first, keyed by the partitions with a dispatched job.
+    partitions: HashMap<PartitionId, VecDeque<T>>,
+    waiting_gauge: U64Gauge,
+}
+
+impl<T> PartitionSerializer<T> {
+    pub(super) fn new(metrics: &metric::Registry) -> Self {
+        Self {
+            partitions: Default::default(),
+            waiting_gauge: metrics
+                .register_metric::<U64Gauge>(
+                    "ingester_persist_partition_waiting_jobs",
+                    "number of persist jobs waiting for a running persist job of their partition",
+                )
+                .recorder(&[]),
+        }
+    }
+
+    /// Admit the job `job` of `partition_id`, returning it if no other job of
+    /// the partition is dispatched, or holding it back until
+    /// [`Self::complete()`] is called for the partition.
+    pub(super) fn admit(&mut self, partition_id: PartitionId, job: T) -> Option<T> {
+        match self.partitions.get_mut(&partition_id) {
+            Some(waiting) => {
+                waiting.push_back(job);
+                self.waiting_gauge.inc(1);
+                None
+            }
+            None => {
+                self.partitions.insert(partition_id, VecDeque::new());
+                Some(job)
+            }
+        }
+    }
+
+    /// Record the completion of the dispatched job of `partition_id`,
+    /// returning the next job of the partition to dispatch, if any was held
+    /// back.
+    pub(super) fn complete(&mut self, partition_id: PartitionId) -> Option<T> {
+        let waiting = self.partitions.get_mut(&partition_id)?;
+        match waiting.pop_front() {
+            Some(job) => {
+                self.waiting_gauge.dec(1);
+                Some(job)
+            }
+            None => {
+                self.partitions.remove(&partition_id);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const P_A: PartitionId = PartitionId::new(1);
+    const P_B: PartitionId = PartitionId::new(2);
+
+    #[test]
+    fn test_serialize() {
+        let metrics = metric::Registry::default();
+        let mut s = PartitionSerializer::new(&metrics);
+
+        assert_eq!(s.admit(P_A, 1), Some(1));
+        // Partition A has a dispatched job, holding its next jobs back.
+        assert_eq!(s.admit(P_A, 2), None);
+        assert_eq!(s.admit(P_A, 3), None);
+        // Without affecting other partitions.
+        assert_eq!(s.admit(P_B, 4), Some(4));
+
+        // The held jobs are dispatched in order, one at a time.
+        assert_eq!(s.complete(P_A), Some(2));
+        assert_eq!(s.admit(P_A, 5), None);
+        assert_eq!(s.complete(P_B), None);
+        assert_eq!(s.complete(P_A), Some(3));
+        assert_eq!(s.complete(P_A), Some(5));
+        assert_eq!(s.complete(P_A), None);
+
+        // Once idle, the next job of the partition is dispatched immediately.
+        assert_eq!(s.admit(P_A, 6), Some(6));
+        assert_eq!(s.complete(P_A), None);
+
+        assert!(s.partitions.is_empty());
+        assert_eq!(s.waiting_gauge.fetch(), 0);
+    }
+}
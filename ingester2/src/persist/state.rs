@@ -0,0 +1,476 @@
+//! Tracking of the persist jobs outstanding in the persist subsystem, for
+//! operators to diagnose a persist backlog.
+
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap, VecDeque},
+    fmt::Display,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use data_types::PartitionId;
+use metric::{Metric, U64Counter, U64Gauge};
+use parking_lot::Mutex;
+
+use super::scaling::PersistStats;
+
+/// The number of persist failures retained by [`PersistState`].
+const MAX_RECENT_FAILURES: usize = 20;
+
+/// How often the [`PersistState`] is reported as metrics.
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The stage of persistence of a partition with outstanding persist jobs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PersistStage {
+    /// Waiting in the queue of the worker the partition is assigned to.
+    Queued,
+    /// Sorting and deduplicating the data.
+    Compacting,
+    /// Writing the parquet file to object storage.
+    Uploading,
+    /// Updating the sort key and adding the parquet file to the catalog.
+    UpdatingCatalog,
+}
+
+impl PersistStage {
+    const ALL: [Self; 4] = [
+        Self::Queued,
+        Self::Compacting,
+        Self::Uploading,
+        Self::UpdatingCatalog,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Compacting => "compacting",
+            Self::Uploading => "uploading",
+            Self::UpdatingCatalog => "updating_catalog",
+        }
+    }
+}
+
+/// The worker a partition with outstanding persist jobs is assigned to.
+#[derive(Debug)]
+struct Assignment {
+    worker: usize,
+    /// The number of jobs for the partition enqueued to, or being executed by,
+    /// the worker.
+    jobs: usize,
+    stage: PersistStage,
+    stage_started_at: Instant,
+}
+
+/// A failed attempt at a catalog or object store request made by a persist
+/// job, which is retried until the job is quarantined.
+#[derive(Debug, Clone)]
+pub(crate) struct PersistFailure {
+    pub(crate) partition_id: PartitionId,
+    pub(crate) operation: &'static str,
+    pub(crate) error: String,
+    pub(crate) at: Instant,
+}
+
+/// The state of the persist jobs outstanding in the persist subsystem, shared
+/// between the [`PersistHandle`], [`PersistActor`] and worker tasks.
+///
+/// [`PersistHandle`]: super::handle::PersistHandle
+/// [`PersistActor`]: super::actor::PersistActor
+#[derive(Debug)]
+pub(crate) struct PersistState {
+    /// The ID to assign to the next enqueued job, and the time each
+    /// outstanding job was enqueued, keyed by job ID.
+    ///
+    /// Job IDs are assigned in enqueue order, so the first entry is the oldest
+    /// outstanding job.
+    jobs: Mutex<(u64, BTreeMap<u64, Instant>)>,
+
+    /// The worker each partition with outstanding persist jobs is assigned
+    /// to.
+    assignments: Mutex<HashMap<PartitionId, Assignment>>,
+
+    /// The most recent failures, oldest first.
+    failures: Mutex<VecDeque<PersistFailure>>,
+    failure_count: Metric<U64Counter>,
+
+    stats: PersistStats,
+}
+
+impl PersistState {
+    pub(super) fn new(metrics: &metric::Registry) -> Self {
+        Self {
+            jobs: Default::default(),
+            assignments: Default::default(),
+            failures: Default::default(),
+            failure_count: metrics.register_metric::<U64Counter>(
+                "ingester_persist_catalog_failures",
+                "number of failed catalog and object store requests made by persist jobs, by operation",
+            ),
+            stats: Default::default(),
+        }
+    }
+
+    /// The counters used to scale the persist workers.
+    pub(crate) fn stats(&self) -> &PersistStats {
+        &self.stats
+    }
+
+    /// Record the enqueuing of a new persist job, returning its job ID.
+    pub(super) fn enqueue(&self) -> u64 {
+        self.stats.enqueued();
+
+        let mut jobs = self.jobs.lock();
+        let id = jobs.0;
+        jobs.0 += 1;
+        jobs.1.insert(id, Instant::now());
+        id
+    }
+
+    /// Return the time the outstanding persist job `job_id` was enqueued.
+    pub(super) fn enqueued_at(&self, job_id: u64) -> Option<Instant> {
+        self.jobs.lock().1.get(&job_id).copied()
+    }
+
+    /// Assign a persist job for `partition_id` to the worker its outstanding
+    /// jobs are assigned to, or the worker returned by `pick` if it has none.
+    pub(super) fn assign(&self, partition_id: PartitionId, pick: impl FnOnce() -> usize) -> usize {
+        let mut assignments = self.assignments.lock();
+        let a = assignments
+            .entry(partition_id)
+            .or_insert_with(|| Assignment {
+                worker: pick(),
+                jobs: 0,
+                stage: PersistStage::Queued,
+                stage_started_at: Instant::now(),
+            });
+        a.jobs += 1;
+        a.worker
+    }
+
+    /// Returns true if any partition is assigned to `worker`.
+    pub(super) fn is_assigned(&self, worker: usize) -> bool {
+        self.assignments.lock().values().any(|a| a.worker == worker)
+    }
+
+    /// Record the persist job of `partition_id` entering `stage`.
+    pub(super) fn set_stage(&self, partition_id: PartitionId, stage: PersistStage) {
+        if let Some(a) = self.assignments.lock().get_mut(&partition_id) {
+            a.stage = stage;
+            a.stage_started_at = Instant::now();
+        }
+    }
+
+    /// Record the completion of the persist job `job_id` for `partition_id`,
+    /// which took `duration` to execute.
+    pub(super) fn complete(&self, partition_id: PartitionId, job_id: u64, duration: Duration) {
+        self.unassign(partition_id, job_id);
+        self.stats.completed(duration);
+    }
+
+    /// Record the removal of the persist job `job_id` for `partition_id`
+    /// without completing it, as it was quarantined.
+    ///
+    /// If the job is re-enqueued, it is assigned a new job ID.
+    pub(super) fn remove(&self, partition_id: PartitionId, job_id: u64) {
+        self.unassign(partition_id, job_id);
+        self.stats.removed();
+    }
+
+    fn unassign(&self, partition_id: PartitionId, job_id: u64) {
+        if let Entry::Occupied(mut e) = self.assignments.lock().entry(partition_id) {
+            let a = e.get_mut();
+            a.jobs -= 1;
+            if a.jobs == 0 {
+                e.remove();
+            } else {
+                // The next job of the partition waits in the worker queue.
+                a.stage = PersistStage::Queued;
+                a.stage_started_at = Instant::now();
+            }
+        }
+
+        self.jobs.lock().1.remove(&job_id);
+    }
+
+    /// Record a failed attempt at the catalog or object store `operation`
+    /// made by the persist job of `partition_id`.
+    pub(super) fn record_failure(
+        &self,
+        partition_id: PartitionId,
+        operation: &'static str,
+        error: &dyn Display,
+    ) {
+        self.failure_count
+            .recorder([("operation", operation.into())])
+            .inc(1);
+
+        let mut failures = self.failures.lock();
+        if failures.len() == MAX_RECENT_FAILURES {
+            failures.pop_front();
+        }
+        failures.push_back(PersistFailure {
+            partition_id,
+            operation,
+            error: error.to_string(),
+            at: Instant::now(),
+        });
+    }
+
+    /// Describe the outstanding persist jobs.
+    pub(crate) fn snapshot(&self) -> PersistSnapshot {
+        let (outstanding_jobs, oldest_enqueued_at) = {
+            let jobs = self.jobs.lock();
+            (jobs.1.len(), jobs.1.values().next().copied())
+        };
+
+        let mut partitions = self
+            .assignments
+            .lock()
+            .iter()
+            .map(|(partition_id, a)| PartitionSnapshot {
+                partition_id: *partition_id,
+                worker: a.worker,
+                jobs: a.jobs,
+                stage: a.stage,
+                stage_started_at: a.stage_started_at,
+            })
+            .collect::<Vec<_>>();
+        partitions.sort_unstable_by_key(|p| p.partition_id);
+
+        // Include the active workers without jobs, and the retiring workers
+        // with jobs.
+        let n_workers = partitions
+            .iter()
+            .map(|p| p.worker + 1)
+            .max()
+            .unwrap_or_default()
+            .max(self.stats.workers());
+        let mut worker_jobs = vec![0; n_workers];
+        for p in &partitions {
+            worker_jobs[p.worker] += p.jobs;
+        }
+        let workers = worker_jobs
+            .into_iter()
+            .enumerate()
+            .map(|(id, jobs)| WorkerSnapshot {
+                id,
+                active: id < self.stats.workers(),
+                jobs,
+            })
+            .collect();
+
+        PersistSnapshot {
+            outstanding_jobs,
+            oldest_enqueued_at,
+            workers,
+            partitions,
+            recent_failures: self.failures.lock().iter().cloned().collect(),
+        }
+    }
+}
+
+/// A point-in-time description of the outstanding persist jobs.
+#[derive(Debug)]
+pub(crate) struct PersistSnapshot {
+    /// The number of persist jobs enqueued but not yet completed.
+    pub(crate) outstanding_jobs: usize,
+    /// The time the oldest outstanding job was enqueued, if any.
+    pub(crate) oldest_enqueued_at: Option<Instant>,
+    /// The persist workers, ordered by worker ID.
+    pub(crate) workers: Vec<WorkerSnapshot>,
+    /// The partitions with persist jobs assigned to a worker, ordered by
+    /// partition ID.
+    pub(crate) partitions: Vec<PartitionSnapshot>,
+    /// The most recent failures, oldest first.
+    pub(crate) recent_failures: Vec<PersistFailure>,
+}
+
+impl PersistSnapshot {
+    /// The number of outstanding jobs not yet assigned to a worker, waiting in
+    /// the submission queue.
+    pub(crate) fn submission_queue_jobs(&self) -> usize {
+        let assigned = self.partitions.iter().map(|p| p.jobs).sum::<usize>();
+        self.outstanding_jobs.saturating_sub(assigned)
+    }
+}
+
+/// The jobs assigned to a persist worker.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct WorkerSnapshot {
+    pub(crate) id: usize,
+    /// False if the worker is retiring, and is stopped once its jobs complete.
+    pub(crate) active: bool,
+    pub(crate) jobs: usize,
+}
+
+/// A partition with persist jobs assigned to a worker.
+#[derive(Debug)]
+pub(crate) struct PartitionSnapshot {
+    pub(crate) partition_id: PartitionId,
+    pub(crate) worker: usize,
+    pub(crate) jobs: usize,
+    /// The stage of the oldest job of the partition.
+    pub(crate) stage: PersistStage,
+    pub(crate) stage_started_at: Instant,
+}
+
+/// Periodically report the outstanding persist jobs in `state` as metrics.
+pub(crate) async fn report_persist_state(state: Arc<PersistState>, metrics: Arc<metric::Registry>) {
+    let outstanding = metrics
+        .register_metric::<U64Gauge>(
+            "ingester_persist_outstanding_jobs",
+            "number of persist jobs enqueued but not yet completed",
+        )
+        .recorder(&[]);
+    let submission_queue = metrics
+        .register_metric::<U64Gauge>(
+            "ingester_persist_submission_queue_jobs",
+            "number of persist jobs waiting in the submission queue",
+        )
+        .recorder(&[]);
+    let oldest_age = metrics
+        .register_metric::<U64Gauge>(
+            "ingester_persist_oldest_job_age_seconds",
+            "how long the oldest outstanding persist job has been enqueued",
+        )
+        .recorder(&[]);
+    let max_worker_jobs = metrics
+        .register_metric::<U64Gauge>(
+            "ingester_persist_max_worker_jobs",
+            "number of persist jobs assigned to the busiest persist worker",
+        )
+        .recorder(&[]);
+    let partitions = metrics.register_metric::<U64Gauge>(
+        "ingester_persist_partitions",
+        "number of partitions with persist jobs assigned to a worker, by stage",
+    );
+    let partitions =
+        PersistStage::ALL.map(|s| (s, partitions.recorder([("stage", s.name().into())])));
+
+    let mut interval = tokio::time::interval(REPORT_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let snapshot = state.snapshot();
+        outstanding.set(snapshot.outstanding_jobs as _);
+        submission_queue.set(snapshot.submission_queue_jobs() as _);
+        oldest_age.set(
+            snapshot
+                .oldest_enqueued_at
+                .map_or(0, |v| v.elapsed().as_secs()),
+        );
+        max_worker_jobs.set(
+            snapshot
+                .workers
+                .iter()
+                .map(|w| w.jobs)
+                .max()
+                .unwrap_or_default() as _,
+        );
+        for (stage, gauge) in &partitions {
+            gauge.set(
+                snapshot
+                    .partitions
+                    .iter()
+                    .filter(|p| p.stage == *stage)
+                    .count() as _,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot() {
+        let metrics = metric::Registry::default();
+        let state = PersistState::new(&metrics);
+        state.stats().set_workers(2);
+
+        let p1 = PartitionId::new(1);
+        let p2 = PartitionId::new(2);
+
+        // Three jobs, one still in the submission queue.
+        let j1 = state.enqueue();
+        let j2 = state.enqueue();
+        let _j3 = state.enqueue();
+        assert_eq!(state.assign(p1, || 1), 1);
+        // A partition with outstanding jobs keeps its worker.
+        assert_eq!(state.assign(p1, || 0), 1);
+        state.set_stage(p1, PersistStage::UpdatingCatalog);
+
+        let snapshot = state.snapshot();
+        assert_eq!(snapshot.outstanding_jobs, 3);
+        assert_eq!(snapshot.submission_queue_jobs(), 1);
+        assert!(snapshot.oldest_enqueued_at.is_some());
+        assert_eq!(
+            snapshot.workers,
+            [
+                WorkerSnapshot {
+                    id: 0,
+                    active: true,
+                    jobs: 0
+                },
+                WorkerSnapshot {
+                    id: 1,
+                    active: true,
+                    jobs: 2
+                },
+            ]
+        );
+        assert_eq!(snapshot.partitions.len(), 1);
+        assert_eq!(snapshot.partitions[0].partition_id, p1);
+        assert_eq!(snapshot.partitions[0].stage, PersistStage::UpdatingCatalog);
+
+        // Completing the first job leaves the next queued on the same worker.
+        state.complete(p1, j1, Duration::from_secs(1));
+        let snapshot = state.snapshot();
+        assert_eq!(snapshot.outstanding_jobs, 2);
+        assert_eq!(snapshot.partitions[0].jobs, 1);
+        assert_eq!(snapshot.partitions[0].stage, PersistStage::Queued);
+
+        // A retiring worker with jobs is reported as inactive.
+        state.stats().set_workers(1);
+        assert_eq!(state.assign(p2, || 0), 0);
+        let snapshot = state.snapshot();
+        assert_eq!(snapshot.workers.len(), 2);
+        assert!(!snapshot.workers[1].active);
+        assert_eq!(snapshot.submission_queue_jobs(), 0);
+
+        state.complete(p1, j2, Duration::from_secs(1));
+        assert!(!state.is_assigned(1));
+        assert!(state.is_assigned(0));
+    }
+
+    #[test]
+    fn test_recent_failures() {
+        let metrics = metric::Registry::default();
+        let state = PersistState::new(&metrics);
+
+        for i in 0..(MAX_RECENT_FAILURES + 5) {
+            state.record_failure(PartitionId::new(i as _), "add parquet file", &"bananas");
+        }
+
+        let failures = state.snapshot().recent_failures;
+        assert_eq!(failures.len(), MAX_RECENT_FAILURES);
+        // The oldest failures are dropped.
+        assert_eq!(failures[0].partition_id, PartitionId::new(5));
+        assert_eq!(failures[0].operation, "add parquet file");
+        assert_eq!(failures[0].error, "bananas");
+
+        let count = metrics
+            .get_instrument::<Metric<U64Counter>>("ingester_persist_catalog_failures")
+            .expect("metric not registered")
+            .get_observer(&metric::Attributes::from(&[(
+                "operation",
+                "add parquet file",
+            )]))
+            .expect("no metric observer")
+            .fetch();
+        assert_eq!(count, MAX_RECENT_FAILURES as u64 + 5);
+    }
+}
@@ -0,0 +1,243 @@
+//! Helpers for testing the persistence of buffered partitions.
+
+use std::{num::NonZeroUsize, sync::Arc, time::Duration};
+
+use arrow::record_batch::RecordBatch;
+use data_types::{ColumnType, ParquetFile, PartitionId, PartitionKey, ShardIndex};
+use datafusion::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use dml::DmlOperation;
+use iox_catalog::{interface::Catalog, mem::MemCatalog};
+use iox_query::exec::Executor;
+use object_store::{memory::InMemory, ObjectStore};
+use parking_lot::Mutex;
+use parquet_file::{
+    metadata::IoxParquetMetaData,
+    storage::{ParquetStorage, StorageId},
+    ParquetFilePath,
+};
+use schema::sort::SortKey;
+use test_helpers::timeout::FutureTimeout;
+
+use super::{handle::PersistHandle, priority::OldestFirst};
+use crate::{
+    buffer_tree::{
+        namespace::name_resolver::mock::MockNamespaceNameProvider,
+        partition::{
+            persisting::BatchIdent, resolver::mock::MockPartitionProvider, PartitionData,
+            SortKeyState,
+        },
+        table::name_resolver::mock::MockTableNameProvider,
+        BufferTree,
+    },
+    deferred_load::DeferredLoad,
+    dml_sink::DmlSink,
+    ingest_state::IngestState,
+    init::PersistWorkers,
+    test_util::{make_write_op, populate_catalog},
+};
+
+const TABLE_NAME: &str = "bananas";
+const NAMESPACE_NAME: &str = "platanos";
+
+/// A partition with buffered data, and the [`PersistHandle`] to persist it
+/// with, built by [`buffer_lines()`].
+pub(super) struct TestPartition {
+    pub(super) catalog: Arc<dyn Catalog>,
+    pub(super) object_store: Arc<dyn ObjectStore>,
+    pub(super) partition_id: PartitionId,
+    pub(super) partition: Arc<Mutex<PartitionData>>,
+    pub(super) persist: PersistHandle,
+}
+
+impl TestPartition {
+    /// Enqueue a persist job for the buffered data of the partition,
+    /// returning the persistence count to pass to
+    /// [`Self::wait_persisted()`].
+    pub(super) async fn enqueue(&self) -> BatchIdent {
+        let (data, started) = {
+            let mut guard = self.partition.lock();
+            let started = guard.completed_persistence_count();
+            (guard.mark_persisting().expect("must have data"), started)
+        };
+        self.persist
+            .queue_persist(Arc::clone(&self.partition), data)
+            .await;
+        started
+    }
+
+    /// Wait for the persisted data to be released from the partition, which
+    /// happens once all the parquet files are added to the catalog.
+    pub(super) async fn wait_persisted(&self, started: BatchIdent) {
+        async {
+            while self.partition.lock().completed_persistence_count() == started {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }
+        .with_timeout_panic(Duration::from_secs(5))
+        .await;
+    }
+
+    /// Return the parquet files of the partition in the catalog.
+    pub(super) async fn files(&self) -> Vec<ParquetFile> {
+        self.catalog
+            .repositories()
+            .await
+            .parquet_files()
+            .list_by_partition_not_to_delete(self.partition_id)
+            .await
+            .expect("list parquet files")
+    }
+
+    /// Read the contents of `file` from object storage.
+    pub(super) async fn read(&self, file: &ParquetFile) -> (IoxParquetMetaData, Vec<RecordBatch>) {
+        let bytes = self
+            .object_store
+            .get(&ParquetFilePath::from(file).object_store_path())
+            .await
+            .expect("parquet file must exist")
+            .bytes()
+            .await
+            .expect("read parquet file");
+
+        let meta = IoxParquetMetaData::from_file_bytes(bytes.clone())
+            .expect("should decode")
+            .expect("should contain metadata");
+        let batches = ParquetRecordBatchReaderBuilder::try_new(bytes)
+            .expect("should init builder")
+            .build()
+            .expect("should create reader")
+            .collect::<Result<Vec<_>, _>>()
+            .expect("should read batches");
+
+        (meta, batches)
+    }
+}
+
+/// Buffer each of `lines` as a separate write to a partition with a sort
+/// key of `["region", "time"]`, and persist it, waiting for the persist
+/// job to complete.
+pub(super) async fn persist_lines(
+    lines: &[&str],
+    max_file_bytes: Option<NonZeroUsize>,
+) -> TestPartition {
+    let p = buffer_lines(
+        lines,
+        max_file_bytes,
+        NonZeroUsize::new(10).unwrap(),
+        Arc::new(InMemory::new()),
+        &metric::Registry::default(),
+    )
+    .await;
+
+    let started = p.enqueue().await;
+    p.wait_persisted(started).await;
+    p
+}
+
+/// Buffer each of `lines` as a separate write to a partition with a sort
+/// key of `["region", "time"]`, to be persisted to `object_store`.
+pub(super) async fn buffer_lines(
+    lines: &[&str],
+    max_file_bytes: Option<NonZeroUsize>,
+    max_attempts: NonZeroUsize,
+    object_store: Arc<dyn ObjectStore>,
+    metrics: &metric::Registry,
+) -> TestPartition {
+    let catalog: Arc<dyn Catalog> =
+        Arc::new(MemCatalog::new(Arc::new(metric::Registry::default())));
+    let (shard_id, namespace_id, table_id) =
+        populate_catalog(&*catalog, ShardIndex::new(1), NAMESPACE_NAME, TABLE_NAME).await;
+
+    // The partition already has a sort key, which does not include the
+    // "city" tag.
+    let partition_key = PartitionKey::from("p1");
+    let partition_id = {
+        let mut repos = catalog.repositories().await;
+        for (name, column_type) in [
+            ("region", ColumnType::Tag),
+            ("city", ColumnType::Tag),
+            ("v", ColumnType::F64),
+            ("time", ColumnType::Time),
+        ] {
+            repos
+                .columns()
+                .create_or_get(name, table_id, column_type)
+                .await
+                .expect("create column");
+        }
+        let p = repos
+            .partitions()
+            .create_or_get(partition_key.clone(), shard_id, table_id)
+            .await
+            .expect("create partition");
+        repos
+            .partitions()
+            .update_sort_key(p.id, &["region", "time"])
+            .await
+            .expect("update sort key")
+            .id
+    };
+
+    let buffer = BufferTree::new(
+        Arc::new(MockNamespaceNameProvider::default()),
+        Arc::new(MockTableNameProvider::new(TABLE_NAME)),
+        Arc::new(
+            MockPartitionProvider::default().with_partition(PartitionData::new(
+                partition_id,
+                partition_key.clone(),
+                namespace_id,
+                Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                    NAMESPACE_NAME.into()
+                })),
+                table_id,
+                Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                    TABLE_NAME.into()
+                })),
+                SortKeyState::Provided(Some(SortKey::from_columns(["region", "time"]))),
+            )),
+        ),
+        Default::default(),
+    );
+
+    for (seq, line) in lines.iter().enumerate() {
+        buffer
+            .apply(DmlOperation::Write(make_write_op(
+                &partition_key,
+                namespace_id,
+                TABLE_NAME,
+                table_id,
+                seq as i64 + 1,
+                line,
+            )))
+            .await
+            .expect("write should succeed");
+    }
+
+    let (persist, actor) = PersistHandle::new(
+        1,
+        PersistWorkers::Fixed(1),
+        1,
+        Arc::new(OldestFirst),
+        Arc::new(Executor::new_testing()),
+        ParquetStorage::new(Arc::clone(&object_store), StorageId::from("iox")),
+        Arc::clone(&catalog),
+        Arc::new(IngestState::new(metrics)),
+        max_file_bytes,
+        max_attempts,
+        None,
+        true,
+        None,
+        None,
+        Default::default(),
+        metrics,
+    );
+    tokio::spawn(actor.run());
+
+    TestPartition {
+        catalog,
+        object_store,
+        partition_id,
+        partition: buffer.partitions().next().expect("partition must exist"),
+        persist,
+    }
+}
@@ -0,0 +1,142 @@
+//! Bounding the persist jobs of a single namespace executed concurrently.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    num::NonZeroUsize,
+};
+
+use data_types::NamespaceId;
+use metric::U64Gauge;
+
+/// Limits the number of persist jobs of each namespace dispatched to the
+/// persist workers at once, so that the enormous partitions of one namespace
+/// cannot occupy all the workers and starve the persistence (and thus the WAL
+/// truncation) of the other namespaces.
+///
+/// The jobs of a namespace at its limit are held back in enqueue order, and
+/// dispatched as its dispatched jobs complete - the jobs of a partition are
+/// therefore still dispatched in order.
+#[derive(Debug)]
+pub(super) struct NamespaceThrottle<T> {
+    max_jobs: Option<NonZeroUsize>,
+
+    /// The number of dispatched, uncompleted jobs of each namespace.
+    dispatched: HashMap<NamespaceId, usize>,
+
+    /// The jobs held back, by namespace, oldest first.
+    throttled: HashMap<NamespaceId, VecDeque<T>>,
+    throttled_gauge: U64Gauge,
+}
+
+impl<T> NamespaceThrottle<T> {
+    /// Limit each namespace to `max_jobs` dispatched jobs, or dispatch all
+    /// jobs immediately if [`None`].
+    pub(super) fn new(max_jobs: Option<NonZeroUsize>, metrics: &metric::Registry) -> Self {
+        Self {
+            max_jobs,
+            dispatched: Default::default(),
+            throttled: Default::default(),
+            throttled_gauge: metrics
+                .register_metric::<U64Gauge>(
+                    "ingester_persist_throttled_jobs",
+                    "number of persist jobs held back by the per-namespace job limit",
+                )
+                .recorder(&[]),
+        }
+    }
+
+    /// Admit the job `job` of `namespace_id`, returning it if it can be
+    /// dispatched now, or holding it back until [`Self::complete()`] is
+    /// called for the namespace.
+    pub(super) fn admit(&mut self, namespace_id: NamespaceId, job: T) -> Option<T> {
+        let max = match self.max_jobs {
+            Some(v) => v.get(),
+            None => return Some(job),
+        };
+
+        let n = self.dispatched.entry(namespace_id).or_default();
+        if *n < max {
+            *n += 1;
+            return Some(job);
+        }
+
+        self.throttled
+            .entry(namespace_id)
+            .or_default()
+            .push_back(job);
+        self.throttled_gauge.inc(1);
+        None
+    }
+
+    /// Record the completion of a dispatched job of `namespace_id`, returning
+    /// the next job of the namespace to dispatch, if any was held back.
+    pub(super) fn complete(&mut self, namespace_id: NamespaceId) -> Option<T> {
+        if let Some(queue) = self.throttled.get_mut(&namespace_id) {
+            // The completed job's slot is passed to the next job.
+            let job = queue.pop_front();
+            if queue.is_empty() {
+                self.throttled.remove(&namespace_id);
+            }
+            if job.is_some() {
+                self.throttled_gauge.dec(1);
+                return job;
+            }
+        }
+
+        if let Some(n) = self.dispatched.get_mut(&namespace_id) {
+            *n -= 1;
+            if *n == 0 {
+                self.dispatched.remove(&namespace_id);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NS_A: NamespaceId = NamespaceId::new(1);
+    const NS_B: NamespaceId = NamespaceId::new(2);
+
+    #[test]
+    fn test_throttle() {
+        let metrics = metric::Registry::default();
+        let mut t = NamespaceThrottle::new(NonZeroUsize::new(2), &metrics);
+
+        assert_eq!(t.admit(NS_A, 1), Some(1));
+        assert_eq!(t.admit(NS_A, 2), Some(2));
+        // The limit of namespace A is reached, holding its next jobs back.
+        assert_eq!(t.admit(NS_A, 3), None);
+        assert_eq!(t.admit(NS_A, 4), None);
+        // Without affecting other namespaces.
+        assert_eq!(t.admit(NS_B, 5), Some(5));
+
+        // The held jobs are dispatched in order as jobs complete.
+        assert_eq!(t.complete(NS_A), Some(3));
+        assert_eq!(t.complete(NS_B), None);
+        assert_eq!(t.complete(NS_A), Some(4));
+        assert_eq!(t.complete(NS_A), None);
+        assert_eq!(t.admit(NS_A, 6), Some(6));
+        assert_eq!(t.admit(NS_A, 7), None);
+        assert_eq!(t.complete(NS_A), Some(7));
+        assert_eq!(t.complete(NS_A), None);
+        assert_eq!(t.complete(NS_A), None);
+
+        assert!(t.dispatched.is_empty());
+        assert!(t.throttled.is_empty());
+    }
+
+    #[test]
+    fn test_throttle_disabled() {
+        let metrics = metric::Registry::default();
+        let mut t = NamespaceThrottle::new(None, &metrics);
+
+        for i in 0..10 {
+            assert_eq!(t.admit(NS_A, i), Some(i));
+        }
+        assert_eq!(t.complete(NS_A), None);
+        assert!(t.dispatched.is_empty());
+    }
+}
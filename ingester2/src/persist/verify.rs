@@ -0,0 +1,218 @@
+//! Reading back uploaded parquet files to check they hold the persisted data.
+
+use data_types::ParquetFileParams;
+use datafusion::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use iox_query::util::compute_timenanosecond_min_max;
+use parquet_file::{metadata::IoxMetadata, storage::ParquetStorage, ParquetFilePath};
+use thiserror::Error;
+
+use crate::query_adaptor::QueryAdaptor;
+
+/// A mismatch between an uploaded parquet file and the data it was written
+/// from, or a failure to read it back.
+#[derive(Debug, Error)]
+pub(super) enum VerifyError {
+    #[error("failed to read back parquet file: {0}")]
+    Read(#[from] object_store::Error),
+
+    #[error("failed to decode parquet file: {0}")]
+    Decode(String),
+
+    #[error("parquet file is {got} bytes, uploaded {want}")]
+    FileSize { got: usize, want: i64 },
+
+    #[error("parquet file holds {got} rows, expected {want}")]
+    RowCount { got: usize, want: i64 },
+
+    #[error("parquet file holds {got} rows, more than the {max} rows persisted")]
+    TooManyRows { got: usize, max: usize },
+
+    #[error(
+        "parquet file spans time range [{got_min}, {got_max}], expected [{want_min}, {want_max}]"
+    )]
+    TimeRange {
+        got_min: i64,
+        got_max: i64,
+        want_min: i64,
+        want_max: i64,
+    },
+}
+
+/// The row count and time range of the data written to a parquet file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Expected {
+    /// The number of rows written, before deduplication.
+    max_rows: usize,
+    min_time: i64,
+    max_time: i64,
+}
+
+impl Expected {
+    /// Describe the data of `part`, as persisted.
+    pub(super) fn new(part: &QueryAdaptor) -> Self {
+        let batches = part.record_batches();
+        let time = compute_timenanosecond_min_max(batches.iter().map(|b| b.as_ref()))
+            .expect("persisted data has a valid time column");
+
+        Self {
+            max_rows: batches.iter().map(|b| b.num_rows()).sum(),
+            min_time: time.min,
+            max_time: time.max,
+        }
+    }
+}
+
+/// Read back the parquet file described by `meta` and `params` from `store`,
+/// checking that it decodes to the number of rows and time range recorded in
+/// `params`, and that these match the `expected` data it was written from.
+///
+/// The file is decoded in its entirety, catching corruption of the data pages
+/// as well as the footer.
+pub(super) async fn verify_parquet(
+    store: &ParquetStorage,
+    meta: &IoxMetadata,
+    params: &ParquetFileParams,
+    expected: Expected,
+) -> Result<(), VerifyError> {
+    let path = store.object_store_path(&ParquetFilePath::from(meta));
+    let bytes = store
+        .object_store_for(meta.namespace_id)
+        .get(&path)
+        .await?
+        .bytes()
+        .await?;
+
+    if bytes.len() as i64 != params.file_size_bytes {
+        return Err(VerifyError::FileSize {
+            got: bytes.len(),
+            want: params.file_size_bytes,
+        });
+    }
+
+    let batches = ParquetRecordBatchReaderBuilder::try_new(bytes)
+        .and_then(|b| b.build())
+        .map_err(|e| VerifyError::Decode(e.to_string()))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| VerifyError::Decode(e.to_string()))?;
+
+    let rows = batches.iter().map(|b| b.num_rows()).sum::<usize>();
+    if rows as i64 != params.row_count {
+        return Err(VerifyError::RowCount {
+            got: rows,
+            want: params.row_count,
+        });
+    }
+    // Deduplication may only remove rows.
+    if rows > expected.max_rows {
+        return Err(VerifyError::TooManyRows {
+            got: rows,
+            max: expected.max_rows,
+        });
+    }
+
+    // Deduplication never removes every row of a timestamp, so the time range
+    // is that of the persisted data.
+    let time = compute_timenanosecond_min_max(batches.iter())
+        .map_err(|e| VerifyError::Decode(e.to_string()))?;
+    if (time.min, time.max) != (expected.min_time, expected.max_time)
+        || (params.min_time.get(), params.max_time.get()) != (time.min, time.max)
+    {
+        return Err(VerifyError::TimeRange {
+            got_min: time.min,
+            got_max: time.max,
+            want_min: expected.min_time,
+            want_max: expected.max_time,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use assert_matches::assert_matches;
+    use data_types::{CompactionLevel, NamespaceId, PartitionId, SequenceNumber, ShardId, TableId};
+    use datafusion_util::MemoryStream;
+    use iox_time::Time;
+    use object_store::{memory::InMemory, ObjectStore};
+    use parquet_file::storage::StorageId;
+
+    use super::*;
+
+    fn meta() -> IoxMetadata {
+        IoxMetadata {
+            object_store_id: Default::default(),
+            creation_timestamp: Time::from_timestamp_nanos(42),
+            namespace_id: NamespaceId::new(1),
+            namespace_name: "platanos".into(),
+            shard_id: ShardId::new(2),
+            table_id: TableId::new(3),
+            table_name: "bananas".into(),
+            partition_id: PartitionId::new(4),
+            partition_key: "p1".into(),
+            max_sequence_number: SequenceNumber::new(11),
+            compaction_level: CompactionLevel::Initial,
+            sort_key: None,
+            provenance: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verify_parquet() {
+        let batch = mutable_batch_lp::lines_to_batches(
+            "bananas,region=asia v=1 10\nbananas,region=africa v=2 20",
+            0,
+        )
+        .unwrap()
+        .remove("bananas")
+        .unwrap()
+        .to_arrow(schema::Projection::All)
+        .unwrap();
+        let part = QueryAdaptor::new(PartitionId::new(4), vec![Arc::new(batch.clone())]);
+        let expected = Expected::new(&part);
+
+        let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let store = ParquetStorage::new(Arc::clone(&object_store), StorageId::from("iox"));
+        let meta = meta();
+        let (md, file_size) = store
+            .upload(Box::pin(MemoryStream::new(vec![batch])), &meta)
+            .await
+            .expect("upload should succeed");
+        let params = meta.to_parquet_file(PartitionId::new(4), file_size, &md, |_| {
+            data_types::ColumnId::new(1)
+        });
+
+        verify_parquet(&store, &meta, &params, expected)
+            .await
+            .expect("uploaded file should verify");
+
+        // A file spanning a different time range than the persisted data is
+        // rejected.
+        let mut other = expected;
+        other.max_time = 30;
+        assert_matches!(
+            verify_parquet(&store, &meta, &params, other).await,
+            Err(VerifyError::TimeRange { got_max: 20, .. })
+        );
+
+        // As is a truncated file.
+        let path = ParquetFilePath::from(&meta).object_store_path();
+        let bytes = object_store
+            .get(&path)
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        object_store
+            .put(&path, bytes.slice(..bytes.len() / 2))
+            .await
+            .unwrap();
+        assert_matches!(
+            verify_parquet(&store, &meta, &params, expected).await,
+            Err(VerifyError::FileSize { .. })
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use async_trait::async_trait;
 use data_types::{NamespaceId, TableId};
 use observability_deps::tracing::*;
+use predicate::Predicate;
 use trace::span::{Span, SpanRecorder};
 
 use super::{QueryError, QueryExec};
@@ -24,6 +25,7 @@ impl QueryExec for QueryRunner {
         namespace_id: NamespaceId,
         table_id: TableId,
         columns: Vec<String>,
+        predicate: Option<Predicate>,
         span: Option<Span>,
     ) -> Result<Self::Response, QueryError> {
         let mut _span_recorder = SpanRecorder::new(span);
@@ -32,6 +34,7 @@ impl QueryExec for QueryRunner {
             namespace_id=%namespace_id,
             table_id=%table_id,
             columns=?columns,
+            predicate=?predicate,
             "executing query"
         );
 
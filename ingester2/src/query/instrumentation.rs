@@ -2,6 +2,7 @@ use async_trait::async_trait;
 use data_types::{NamespaceId, TableId};
 use iox_time::{SystemProvider, TimeProvider};
 use metric::{DurationHistogram, Metric};
+use predicate::Predicate;
 use trace::span::Span;
 
 use super::QueryExec;
@@ -57,13 +58,14 @@ where
         namespace_id: NamespaceId,
         table_id: TableId,
         columns: Vec<String>,
+        predicate: Option<Predicate>,
         span: Option<Span>,
     ) -> Result<Self::Response, QueryError> {
         let t = self.time_provider.now();
 
         let res = self
             .inner
-            .query_exec(namespace_id, table_id, columns, span)
+            .query_exec(namespace_id, table_id, columns, predicate, span)
             .await;
 
         if let Some(delta) = self.time_provider.now().checked_duration_since(t) {
@@ -72,6 +74,8 @@ where
                 Err(QueryError::TableNotFound { .. } | QueryError::NamespaceNotFound { .. }) => {
                     self.query_duration_error_not_found.record(delta)
                 }
+                // The query was not executed.
+                Err(QueryError::NotReady(_)) => {}
             };
         }
 
@@ -105,7 +109,7 @@ mod tests {
 
                     // Call the decorator and assert the return value
                     let got = decorator
-                        .query_exec(NamespaceId::new(42), TableId::new(24), vec![], None)
+                        .query_exec(NamespaceId::new(42), TableId::new(24), vec![], None, None)
                         .await;
                     assert_matches!(got, $($want_ret)+);
 
@@ -0,0 +1,432 @@
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use arrow::{datatypes::SchemaRef, error::ArrowError, record_batch::RecordBatch};
+use async_trait::async_trait;
+use data_types::{NamespaceId, TableId};
+use datafusion::physical_plan::{RecordBatchStream, SendableRecordBatchStream};
+use futures::Stream;
+use metric::{U64Counter, U64Gauge};
+use observability_deps::tracing::*;
+use pin_project::pin_project;
+use predicate::Predicate;
+use trace::span::Span;
+
+use super::{
+    partition_response::PartitionResponse,
+    response::{PartitionStream, QueryResponse},
+    QueryError, QueryExec,
+};
+
+/// The limits on the memory used by the [`RecordBatch`] returned by queries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct QueryMemoryLimits {
+    /// The maximum number of bytes returned by a single query.
+    pub(crate) per_query_bytes: Option<usize>,
+
+    /// The maximum number of bytes held by all in-flight queries.
+    pub(crate) global_bytes: Option<usize>,
+}
+
+/// A [`QueryExec`] decorator that fails queries returning more data than
+/// allowed by the configured [`QueryMemoryLimits`].
+///
+/// The memory used by each [`RecordBatch`] snapshot from a partition is
+/// reserved as it is streamed, and released once the query response is
+/// dropped. A query exceeding either limit is failed with an
+/// [`ArrowError::MemoryError`] in its response stream, and no further
+/// partitions are read for it.
+#[derive(Debug)]
+pub(crate) struct QueryExecMemoryLimit<T> {
+    inner: T,
+    limits: QueryMemoryLimits,
+    pool: Arc<MemoryPool>,
+}
+
+impl<T> QueryExecMemoryLimit<T> {
+    pub(crate) fn new(inner: T, limits: QueryMemoryLimits, metrics: &metric::Registry) -> Self {
+        let used_bytes = metrics
+            .register_metric::<U64Gauge>(
+                "ingester_query_memory_bytes",
+                "bytes of data held by in-flight query responses",
+            )
+            .recorder(&[]);
+        let limit_exceeded = metrics.register_metric::<U64Counter>(
+            "ingester_query_memory_limit_exceeded",
+            "number of queries failed for exceeding a query memory limit",
+        );
+
+        Self {
+            inner,
+            limits,
+            pool: Arc::new(MemoryPool {
+                limit_bytes: limits.global_bytes,
+                used: AtomicUsize::new(0),
+                used_bytes,
+                per_query_exceeded: limit_exceeded.recorder(&[("limit", "per_query")]),
+                global_exceeded: limit_exceeded.recorder(&[("limit", "global")]),
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl<T> QueryExec for QueryExecMemoryLimit<T>
+where
+    T: QueryExec<Response = QueryResponse>,
+{
+    type Response = QueryResponse;
+
+    async fn query_exec(
+        &self,
+        namespace_id: NamespaceId,
+        table_id: TableId,
+        columns: Vec<String>,
+        predicate: Option<Predicate>,
+        span: Option<Span>,
+    ) -> Result<Self::Response, QueryError> {
+        let response = self
+            .inner
+            .query_exec(namespace_id, table_id, columns, predicate, span)
+            .await?;
+
+        if self.limits == QueryMemoryLimits::default() {
+            return Ok(response);
+        }
+
+        let reservation = Arc::new(Reservation {
+            pool: Arc::clone(&self.pool),
+            limit_bytes: self.limits.per_query_bytes,
+            reserved: AtomicUsize::new(0),
+            exceeded: AtomicBool::new(false),
+        });
+
+        Ok(QueryResponse::new(PartitionStream::new(
+            LimitedPartitionStream {
+                inner: response.into_partition_stream(),
+                reservation,
+            },
+        )))
+    }
+}
+
+/// The memory reserved by all in-flight queries.
+#[derive(Debug)]
+struct MemoryPool {
+    limit_bytes: Option<usize>,
+    used: AtomicUsize,
+
+    used_bytes: U64Gauge,
+    per_query_exceeded: U64Counter,
+    global_exceeded: U64Counter,
+}
+
+impl MemoryPool {
+    /// Reserve `bytes`, or return an error if doing so would exceed the
+    /// global limit.
+    fn try_reserve(&self, bytes: usize) -> Result<(), ArrowError> {
+        let res = self
+            .used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                let new = used + bytes;
+                match self.limit_bytes {
+                    Some(limit) if new > limit => None,
+                    _ => Some(new),
+                }
+            });
+
+        match res {
+            Ok(_) => {
+                self.used_bytes.inc(bytes as u64);
+                Ok(())
+            }
+            Err(used) => {
+                let limit = self.limit_bytes.expect("reservation failed without limit");
+                self.global_exceeded.inc(1);
+                warn!(
+                    used_bytes = used,
+                    limit_bytes = limit,
+                    "query memory limit exceeded"
+                );
+                Err(ArrowError::MemoryError(format!(
+                    "ingester query memory limit of {limit} bytes exceeded \
+                    ({used} bytes held by in-flight queries)"
+                )))
+            }
+        }
+    }
+
+    fn release(&self, bytes: usize) {
+        self.used.fetch_sub(bytes, Ordering::AcqRel);
+        self.used_bytes.dec(bytes as u64);
+    }
+}
+
+/// The memory reserved by a single query, released from the [`MemoryPool`]
+/// when dropped.
+#[derive(Debug)]
+struct Reservation {
+    pool: Arc<MemoryPool>,
+    limit_bytes: Option<usize>,
+    reserved: AtomicUsize,
+
+    /// Set once a limit has been exceeded by this query.
+    exceeded: AtomicBool,
+}
+
+impl Reservation {
+    /// Reserve `bytes` for this query, or return an error if doing so would
+    /// exceed the per-query or global limit.
+    fn try_grow(&self, bytes: usize) -> Result<(), ArrowError> {
+        let reserved = self.reserved.load(Ordering::Acquire) + bytes;
+        if let Some(limit) = self.limit_bytes {
+            if reserved > limit {
+                self.exceeded.store(true, Ordering::Release);
+                self.pool.per_query_exceeded.inc(1);
+                debug!(
+                    reserved_bytes = reserved,
+                    limit_bytes = limit,
+                    "per-query memory limit exceeded"
+                );
+                return Err(ArrowError::MemoryError(format!(
+                    "query exceeded the per-query memory limit of {limit} bytes"
+                )));
+            }
+        }
+
+        if let Err(e) = self.pool.try_reserve(bytes) {
+            self.exceeded.store(true, Ordering::Release);
+            return Err(e);
+        }
+
+        self.reserved.fetch_add(bytes, Ordering::AcqRel);
+        Ok(())
+    }
+
+    fn is_exceeded(&self) -> bool {
+        self.exceeded.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.pool.release(*self.reserved.get_mut());
+    }
+}
+
+/// A stream of [`PartitionResponse`] that reserves the memory used by each
+/// [`RecordBatch`] returned, ending without reading any further partitions
+/// once a limit has been exceeded.
+#[pin_project]
+struct LimitedPartitionStream<S> {
+    #[pin]
+    inner: S,
+    reservation: Arc<Reservation>,
+}
+
+impl<S> Stream for LimitedPartitionStream<S>
+where
+    S: Stream<Item = PartitionResponse>,
+{
+    type Item = PartitionResponse;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        if this.reservation.is_exceeded() {
+            return Poll::Ready(None);
+        }
+
+        this.inner.poll_next(cx).map(|v| {
+            v.map(|p| {
+                let id = p.id();
+                let max_persisted = p.max_persisted_sequence_number();
+                PartitionResponse::new(
+                    Box::pin(ReservedBatchStream {
+                        inner: p.into_record_batch_stream(),
+                        reservation: Arc::clone(this.reservation),
+                    }),
+                    id,
+                    max_persisted,
+                )
+            })
+        })
+    }
+}
+
+/// A [`RecordBatchStream`] reserving the memory used by each [`RecordBatch`]
+/// it yields.
+#[pin_project]
+struct ReservedBatchStream {
+    #[pin]
+    inner: SendableRecordBatchStream,
+    reservation: Arc<Reservation>,
+}
+
+impl Stream for ReservedBatchStream {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        match this.inner.poll_next(cx) {
+            Poll::Ready(Some(Ok(batch))) => Poll::Ready(Some(
+                this.reservation
+                    .try_grow(batch.get_array_memory_size())
+                    .map(|_| batch),
+            )),
+            v => v,
+        }
+    }
+}
+
+impl RecordBatchStream for ReservedBatchStream {
+    fn schema(&self) -> SchemaRef {
+        self.inner.schema()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use data_types::PartitionId;
+    use datafusion_util::MemoryStream;
+    use futures::StreamExt;
+    use metric::{Attributes, Metric};
+    use mutable_batch_lp::test_helpers::lp_to_mutable_batch;
+    use schema::Projection;
+
+    use super::*;
+
+    fn batch() -> RecordBatch {
+        lp_to_mutable_batch("bananas v=1i 1")
+            .1
+            .to_arrow(Projection::All)
+            .unwrap()
+    }
+
+    /// A [`QueryExec`] returning a single batch for each of `n` partitions.
+    #[derive(Debug)]
+    struct Partitions(i64);
+
+    #[async_trait]
+    impl QueryExec for Partitions {
+        type Response = QueryResponse;
+
+        async fn query_exec(
+            &self,
+            _namespace_id: NamespaceId,
+            _table_id: TableId,
+            _columns: Vec<String>,
+            _predicate: Option<Predicate>,
+            _span: Option<Span>,
+        ) -> Result<Self::Response, QueryError> {
+            let partitions = (0..self.0)
+                .map(|id| {
+                    PartitionResponse::new(
+                        Box::pin(MemoryStream::new(vec![batch()])),
+                        PartitionId::new(id),
+                        None,
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            Ok(QueryResponse::new(PartitionStream::new(
+                futures::stream::iter(partitions),
+            )))
+        }
+    }
+
+    async fn query<T>(exec: &T) -> impl Stream<Item = Result<RecordBatch, ArrowError>>
+    where
+        T: QueryExec<Response = QueryResponse>,
+    {
+        exec.query_exec(NamespaceId::new(1), TableId::new(2), vec![], None, None)
+            .await
+            .expect("query should succeed")
+            .into_record_batches()
+    }
+
+    fn used_bytes(metrics: &metric::Registry) -> u64 {
+        metrics
+            .get_instrument::<Metric<U64Gauge>>("ingester_query_memory_bytes")
+            .expect("metric not registered")
+            .get_observer(&Attributes::from(&[]))
+            .expect("no metric observer")
+            .fetch()
+    }
+
+    fn exceeded(metrics: &metric::Registry, limit: &'static str) -> u64 {
+        metrics
+            .get_instrument::<Metric<U64Counter>>("ingester_query_memory_limit_exceeded")
+            .expect("metric not registered")
+            .get_observer(&Attributes::from(&[("limit", limit)]))
+            .expect("no metric observer")
+            .fetch()
+    }
+
+    #[tokio::test]
+    async fn test_per_query_limit() {
+        let metrics = metric::Registry::default();
+        let size = batch().get_array_memory_size();
+
+        let exec = QueryExecMemoryLimit::new(
+            Partitions(4),
+            QueryMemoryLimits {
+                per_query_bytes: Some(2 * size),
+                global_bytes: None,
+            },
+            &metrics,
+        );
+
+        // The third batch exceeds the limit, and the remaining partition is
+        // never read.
+        let got = query(&exec).await.collect::<Vec<_>>().await;
+        assert_matches!(
+            got.as_slice(),
+            [Ok(_), Ok(_), Err(ArrowError::MemoryError(_))]
+        );
+        assert_eq!(exceeded(&metrics, "per_query"), 1);
+        assert_eq!(exceeded(&metrics, "global"), 0);
+
+        // The memory is released once the response is dropped.
+        assert_eq!(used_bytes(&metrics), 0);
+    }
+
+    #[tokio::test]
+    async fn test_global_limit() {
+        let metrics = metric::Registry::default();
+        let size = batch().get_array_memory_size();
+
+        let exec = QueryExecMemoryLimit::new(
+            Partitions(1),
+            QueryMemoryLimits {
+                per_query_bytes: None,
+                global_bytes: Some(size),
+            },
+            &metrics,
+        );
+
+        // The memory of the first query is held until its response is
+        // dropped.
+        let mut first = Box::pin(query(&exec).await);
+        assert_matches!(first.next().await, Some(Ok(_)));
+        assert_eq!(used_bytes(&metrics), size as u64);
+
+        let got = query(&exec).await.collect::<Vec<_>>().await;
+        assert_matches!(got.as_slice(), [Err(ArrowError::MemoryError(_))]);
+        assert_eq!(exceeded(&metrics, "global"), 1);
+
+        drop(first);
+        assert_eq!(used_bytes(&metrics), 0);
+
+        let got = query(&exec).await.collect::<Vec<_>>().await;
+        assert_matches!(got.as_slice(), [Ok(_)]);
+    }
+}
@@ -9,6 +9,8 @@ pub(crate) mod response;
 
 pub(crate) mod exec;
 pub(crate) mod instrumentation;
+pub(crate) mod memory_limit;
+pub(crate) mod readiness;
 pub(crate) mod tracing;
 
 #[cfg(test)]
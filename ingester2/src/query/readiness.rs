@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use data_types::{NamespaceId, TableId};
+use predicate::Predicate;
+use trace::span::Span;
+
+use super::{QueryError, QueryExec};
+use crate::init::ReadinessProbe;
+
+/// A [`QueryExec`] decorator that rejects all queries with
+/// [`QueryError::NotReady`] until its [`ReadinessProbe`] reports the ingester
+/// as ready, preventing incomplete results from being returned while the WAL is
+/// being replayed.
+#[derive(Debug)]
+pub(crate) struct QueryExecReadiness<T> {
+    inner: T,
+    probe: Arc<ReadinessProbe>,
+}
+
+impl<T> QueryExecReadiness<T> {
+    pub(crate) fn new(inner: T, probe: Arc<ReadinessProbe>) -> Self {
+        Self { inner, probe }
+    }
+}
+
+#[async_trait]
+impl<T> QueryExec for QueryExecReadiness<T>
+where
+    T: QueryExec,
+{
+    type Response = T::Response;
+
+    async fn query_exec(
+        &self,
+        namespace_id: NamespaceId,
+        table_id: TableId,
+        columns: Vec<String>,
+        predicate: Option<Predicate>,
+        span: Option<Span>,
+    ) -> Result<Self::Response, QueryError> {
+        self.probe.check().map_err(QueryError::NotReady)?;
+        self.inner
+            .query_exec(namespace_id, table_id, columns, predicate, span)
+            .await
+    }
+}
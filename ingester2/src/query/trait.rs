@@ -2,9 +2,12 @@ use std::{fmt::Debug, ops::Deref, sync::Arc};
 
 use async_trait::async_trait;
 use data_types::{NamespaceId, TableId};
+use predicate::Predicate;
 use thiserror::Error;
 use trace::span::Span;
 
+use crate::init::Readiness;
+
 #[derive(Debug, Error)]
 #[allow(missing_copy_implementations)]
 pub(crate) enum QueryError {
@@ -13,6 +16,9 @@ pub(crate) enum QueryError {
 
     #[error("table id {1} not found in namespace id {0}")]
     TableNotFound(NamespaceId, TableId),
+
+    #[error("ingester not ready: {0}")]
+    NotReady(Readiness),
 }
 
 #[async_trait]
@@ -24,6 +30,7 @@ pub(crate) trait QueryExec: Send + Sync + Debug {
         namespace_id: NamespaceId,
         table_id: TableId,
         columns: Vec<String>,
+        predicate: Option<Predicate>,
         span: Option<Span>,
     ) -> Result<Self::Response, QueryError>;
 }
@@ -40,10 +47,11 @@ where
         namespace_id: NamespaceId,
         table_id: TableId,
         columns: Vec<String>,
+        predicate: Option<Predicate>,
         span: Option<Span>,
     ) -> Result<Self::Response, QueryError> {
         self.deref()
-            .query_exec(namespace_id, table_id, columns, span)
+            .query_exec(namespace_id, table_id, columns, predicate, span)
             .await
     }
 }
@@ -3,18 +3,43 @@
 
 use std::{any::Any, sync::Arc};
 
-use arrow::record_batch::RecordBatch;
-use arrow_util::util::ensure_schema;
-use data_types::{ChunkId, ChunkOrder, DeletePredicate, PartitionId, TableSummary};
-use datafusion::error::DataFusionError;
+use arrow::{
+    array::{Array, BooleanArray, TimestampNanosecondArray, UInt64Array},
+    compute::{
+        and, concat_batches, eq_dyn_utf8_scalar, filter_record_batch, gt_eq_scalar,
+        lexsort_to_indices, lt_scalar, take, SortColumn, SortOptions,
+    },
+    record_batch::RecordBatch,
+};
+use arrow_util::util::{ensure_schema, merge_record_batches};
+use data_types::{
+    ChunkId, ChunkOrder, DeletePredicate, PartitionId, TableSummary, TimestampMinMax,
+    TimestampRange,
+};
+use datafusion::{
+    error::DataFusionError,
+    logical_expr::{BinaryExpr, Operator},
+    physical_plan::{
+        expressions::{col, PhysicalSortExpr},
+        metrics::Count,
+    },
+    prelude::Expr,
+    scalar::ScalarValue,
+};
 use iox_query::{
     exec::{stringset::StringSet, IOxSessionContext},
-    util::{compute_timenanosecond_min_max, create_basic_summary},
+    provider::RecordBatchDeduplicator,
+    util::{
+        compute_timenanosecond_min_max, compute_timenanosecond_min_max_for_one_record_batch,
+        create_basic_summary,
+    },
     QueryChunk, QueryChunkData, QueryChunkMeta,
 };
 use once_cell::sync::OnceCell;
 use predicate::Predicate;
-use schema::{merge::merge_record_batch_schemas, sort::SortKey, Projection, Schema};
+use schema::{
+    merge::merge_record_batch_schemas, sort::SortKey, Projection, Schema, TIME_COLUMN_NAME,
+};
 
 /// A queryable wrapper over a set of ordered [`RecordBatch`] snapshot from a
 /// single [`PartitionData`].
@@ -70,17 +95,23 @@ impl QueryAdaptor {
         }
     }
 
+    /// Project the [`RecordBatch`] instances in this [`QueryAdaptor`] to the
+    /// columns in `selection`, in the order they are specified.
+    ///
+    /// Columns in `selection` that do not exist in a [`RecordBatch`] are
+    /// ignored, and a [`RecordBatch`] containing none of the selected columns
+    /// is omitted from the output.
     pub(crate) fn project_selection(&self, selection: Projection<'_>) -> Vec<RecordBatch> {
         // Project the column selection across all RecordBatch
         self.data
             .iter()
-            .map(|data| {
+            .filter_map(|data| {
                 let batch = data.as_ref();
                 let schema = batch.schema();
 
                 // Apply selection to in-memory batch
                 match selection {
-                    Projection::All => batch.clone(),
+                    Projection::All => Some(batch.clone()),
                     Projection::Some(columns) => {
                         let projection = columns
                             .iter()
@@ -89,13 +120,233 @@ impl QueryAdaptor {
                                 schema.index_of(column_name).ok()
                             })
                             .collect::<Vec<_>>();
-                        batch.project(&projection).expect("bug in projection")
+
+                        // A RecordBatch cannot be constructed without any
+                        // columns.
+                        if projection.is_empty() {
+                            return None;
+                        }
+
+                        Some(batch.project(&projection).expect("bug in projection"))
                     }
                 }
             })
             .collect()
     }
 
+    /// Discard the [`RecordBatch`] instances containing no rows with a
+    /// timestamp within `range`, returning [`None`] if no data remains.
+    ///
+    /// The retained [`RecordBatch`] are not filtered - they MAY contain rows
+    /// outside of `range`.
+    pub(crate) fn prune_time_range(self, range: TimestampRange) -> Option<Self> {
+        let data = self
+            .data
+            .into_iter()
+            .filter(|b| b.num_rows() > 0)
+            .filter(|b| {
+                let (min, max) = compute_timenanosecond_min_max_for_one_record_batch(b)
+                    .expect("buffered data has a valid time column");
+                TimestampMinMax::new(min, max).overlaps(range)
+            })
+            .collect::<Vec<_>>();
+
+        if data.is_empty() {
+            return None;
+        }
+
+        Some(Self::new(self.partition_id, data))
+    }
+
+    /// Discard the rows that do not match the time range or the tag equality
+    /// expressions (`tag = 'value'`) of `predicate`, returning [`None`] if no
+    /// rows remain.
+    ///
+    /// All other expressions in `predicate` are not evaluated - the rows
+    /// returned MAY NOT match them, and the caller is responsible for applying
+    /// the full predicate.
+    pub(crate) fn filter(self, predicate: &Predicate) -> Option<Self> {
+        let schema = self.schema();
+        let tags = predicate
+            .exprs
+            .iter()
+            .filter_map(tag_equality)
+            .filter(|(column, _)| schema.tags_iter().any(|f| f.name() == *column))
+            .collect::<Vec<_>>();
+
+        if predicate.range.is_none() && tags.is_empty() {
+            return Some(self);
+        }
+
+        self.filter_rows(predicate.range, &tags)
+    }
+
+    /// Split the rows into consecutive, non-overlapping time ranges, each
+    /// holding approximately `max_bytes` of data at most.
+    ///
+    /// The size of the data is estimated from the memory used by the
+    /// [`RecordBatch`] instances. Rows sharing a single timestamp are never
+    /// split, and MAY exceed `max_bytes`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_bytes` is 0.
+    pub(crate) fn split_time_ranges(self, max_bytes: usize) -> Vec<Self> {
+        assert!(max_bytes > 0, "cannot split into empty time ranges");
+
+        let n = (self.estimate_size() + max_bytes - 1) / max_bytes;
+        let TimestampMinMax { min, max } = compute_timenanosecond_min_max(
+            self.data
+                .iter()
+                .filter(|b| b.num_rows() > 0)
+                .map(|b| b.as_ref()),
+        )
+        .expect("buffered data has a valid time column");
+        if n <= 1 || min == max {
+            return vec![self];
+        }
+
+        // The width of each of the n ranges spanning [min, max].
+        //
+        // A range is always narrower than [min, max], so a range that still
+        // holds too much data is split again.
+        let width = ((max as i128 - min as i128) / n as i128 + 1).min(i64::MAX as i128) as i64;
+        (0..n as i64)
+            .map(|i| min.saturating_add(i.saturating_mul(width)))
+            .take_while(|&start| start <= max)
+            .filter_map(|start| {
+                self.filter_rows(
+                    Some(TimestampRange::new(start, start.saturating_add(width))),
+                    &[],
+                )
+            })
+            .flat_map(|part| part.split_time_ranges(max_bytes))
+            .collect()
+    }
+
+    /// Discard the rows that do not match the time `range` or the `tags`
+    /// equality expressions, returning [`None`] if no rows remain.
+    fn filter_rows(&self, range: Option<TimestampRange>, tags: &[(&str, &str)]) -> Option<Self> {
+        let data = self
+            .data
+            .iter()
+            .filter_map(|batch| {
+                let mask = filter_mask(batch, range, tags);
+                let batch = filter_record_batch(batch, &mask).expect("filter mask length mismatch");
+                (batch.num_rows() > 0).then(|| Arc::new(batch))
+            })
+            .collect::<Vec<_>>();
+
+        if data.is_empty() {
+            return None;
+        }
+
+        Some(Self::new(self.partition_id, data))
+    }
+
+    /// Returns the estimated size in bytes of the [`RecordBatch`] instances in
+    /// this [`QueryAdaptor`].
+    pub(crate) fn estimate_size(&self) -> usize {
+        self.data
+            .iter()
+            .flat_map(|b| b.columns())
+            .map(|c| c.get_array_memory_size())
+            .sum()
+    }
+
+    /// Merge the rows sharing a primary key, if the [`RecordBatch`] instances
+    /// in this [`QueryAdaptor`] overlap in time.
+    ///
+    /// A partition that is being persisted returns both the persisting
+    /// snapshot(s) and the newer buffered data, which may contain writes to
+    /// the same primary key. When any of these [`RecordBatch`] overlap, they
+    /// are combined into a single [`RecordBatch`] (sorted by primary key)
+    /// containing one row per primary key - the value of each field is taken
+    /// from the most recently written row in which it is not null, matching
+    /// the deduplication applied during persistence.
+    ///
+    /// Duplicate rows within a single [`RecordBatch`] are only merged when
+    /// the [`RecordBatch`] overlaps another.
+    pub(crate) fn deduplicate(self) -> Self {
+        if !self.has_overlaps() {
+            return self;
+        }
+
+        let schema = self.schema();
+        let arrow_schema = schema.as_arrow();
+
+        // Combine the batches in write order, padding missing columns with
+        // nulls.
+        let batch = merge_record_batches(&arrow_schema, self.data.clone())
+            .expect("schema handling broken")
+            .expect("query adaptor contains data");
+
+        // Sort by the primary key, with the position of each row as the final
+        // key so that rows sharing a primary key remain in write order, as
+        // the deduplicator takes the last non-null value of each field.
+        let primary_key = schema.primary_key();
+        let mut sort_columns = primary_key
+            .iter()
+            .map(|&name| SortColumn {
+                values: Arc::clone(
+                    batch.column(
+                        arrow_schema
+                            .index_of(name)
+                            .expect("primary key column exists"),
+                    ),
+                ),
+                options: Some(SortOptions::default()),
+            })
+            .collect::<Vec<_>>();
+        sort_columns.push(SortColumn {
+            values: Arc::new(UInt64Array::from_iter_values(0..batch.num_rows() as u64)),
+            options: None,
+        });
+
+        let indices = lexsort_to_indices(&sort_columns, None).expect("sortable primary key");
+        let columns = batch
+            .columns()
+            .iter()
+            .map(|c| take(c.as_ref(), &indices, None))
+            .collect::<Result<Vec<_>, _>>()
+            .expect("sorted indices are in bounds");
+        let sorted = RecordBatch::try_new(Arc::clone(&arrow_schema), columns)
+            .expect("sorting retains the schema");
+
+        let sort_keys = primary_key
+            .iter()
+            .map(|&name| PhysicalSortExpr {
+                expr: col(name, &arrow_schema).expect("primary key column exists"),
+                options: SortOptions::default(),
+            })
+            .collect();
+        let mut dedupe = RecordBatchDeduplicator::new(sort_keys, Count::new(), None);
+        let mut output = vec![dedupe.push(sorted).expect("deduplication failed")];
+        output.extend(dedupe.finish().expect("deduplication failed"));
+
+        let batch =
+            concat_batches(&arrow_schema, &output).expect("deduplicated batches share a schema");
+
+        Self::new(self.partition_id, vec![Arc::new(batch)])
+    }
+
+    /// Returns true if the timestamp range of any [`RecordBatch`] in this
+    /// [`QueryAdaptor`] overlaps that of another.
+    fn has_overlaps(&self) -> bool {
+        let mut ranges = self
+            .data
+            .iter()
+            .filter(|b| b.num_rows() > 0)
+            .map(|b| {
+                compute_timenanosecond_min_max_for_one_record_batch(b)
+                    .expect("buffered data has a valid time column")
+            })
+            .collect::<Vec<_>>();
+        ranges.sort_unstable();
+
+        ranges.windows(2).any(|w| w[1].0 <= w[0].1)
+    }
+
     /// Returns the [`RecordBatch`] instances in this [`QueryAdaptor`].
     pub(crate) fn record_batches(&self) -> &[Arc<RecordBatch>] {
         self.data.as_ref()
@@ -108,6 +359,69 @@ impl QueryAdaptor {
     }
 }
 
+/// Returns the column name and value of a `column = 'value'` (or `'value' =
+/// column`) string equality expression.
+fn tag_equality(expr: &Expr) -> Option<(&str, &str)> {
+    match expr {
+        Expr::BinaryExpr(BinaryExpr {
+            left,
+            op: Operator::Eq,
+            right,
+        }) => match (left.as_ref(), right.as_ref()) {
+            (Expr::Column(c), Expr::Literal(ScalarValue::Utf8(Some(v))))
+            | (Expr::Literal(ScalarValue::Utf8(Some(v))), Expr::Column(c)) => {
+                Some((c.name.as_str(), v.as_str()))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Evaluate the time `range` and the `tags` equality expressions against
+/// `batch`, returning a mask selecting the matching rows.
+///
+/// A row with no value for one of the `tags` never matches.
+fn filter_mask(
+    batch: &RecordBatch,
+    range: Option<TimestampRange>,
+    tags: &[(&str, &str)],
+) -> BooleanArray {
+    let mut mask = BooleanArray::from(vec![true; batch.num_rows()]);
+
+    if let Some(range) = range {
+        let time = batch
+            .column(
+                batch
+                    .schema()
+                    .index_of(TIME_COLUMN_NAME)
+                    .expect("buffered data has a time column"),
+            )
+            .as_any()
+            .downcast_ref::<TimestampNanosecondArray>()
+            .expect("time column is a nanosecond timestamp");
+
+        let in_range = and(
+            &gt_eq_scalar(time, range.start()).expect("comparable time column"),
+            &lt_scalar(time, range.end()).expect("comparable time column"),
+        )
+        .expect("equal length masks");
+        mask = and(&mask, &in_range).expect("equal length masks");
+    }
+
+    for (column, value) in tags {
+        let matches = match batch.schema().index_of(column) {
+            Ok(idx) => eq_dyn_utf8_scalar(batch.column(idx).as_ref(), value)
+                .expect("tag columns are strings"),
+            // The tag is not set for any of the rows in this batch.
+            Err(_) => BooleanArray::from(vec![false; batch.num_rows()]),
+        };
+        mask = and(&mask, &matches).expect("equal length masks");
+    }
+
+    mask
+}
+
 impl QueryChunkMeta for QueryAdaptor {
     fn summary(&self) -> Arc<TableSummary> {
         Arc::clone(self.summary.get_or_init(|| {
@@ -210,3 +524,65 @@ impl QueryChunk for QueryAdaptor {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use mutable_batch_lp::lines_to_batches;
+
+    use super::*;
+
+    fn batch(lines: &str) -> Arc<RecordBatch> {
+        Arc::new(
+            lines_to_batches(lines, 0)
+                .unwrap()
+                .get("bananas")
+                .unwrap()
+                .to_arrow(Projection::All)
+                .unwrap(),
+        )
+    }
+
+    fn time_ranges(parts: &[QueryAdaptor]) -> Vec<(i64, i64)> {
+        parts
+            .iter()
+            .map(|p| {
+                let r =
+                    compute_timenanosecond_min_max(p.record_batches().iter().map(|b| b.as_ref()))
+                        .unwrap();
+                (r.min, r.max)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_split_time_ranges() {
+        let data = QueryAdaptor::new(
+            PartitionId::new(1),
+            vec![
+                batch("bananas v=1 10\nbananas v=2 40"),
+                batch("bananas v=3 20\nbananas v=4 40"),
+            ],
+        );
+        let size = data.estimate_size();
+
+        // Data within the limit is not split.
+        let parts = data.clone().split_time_ranges(size);
+        assert_eq!(time_ranges(&parts), [(10, 40)]);
+
+        // Otherwise the rows are split into consecutive time ranges, until
+        // each is within the limit, or holds a single timestamp.
+        let parts = data.split_time_ranges(1);
+        assert_eq!(time_ranges(&parts), [(10, 10), (20, 20), (40, 40)]);
+        assert_eq!(
+            parts
+                .iter()
+                .map(|p| p
+                    .record_batches()
+                    .iter()
+                    .map(|b| b.num_rows())
+                    .sum::<usize>())
+                .collect::<Vec<_>>(),
+            [1, 1, 2]
+        );
+    }
+}
@@ -1,26 +1,41 @@
 //! gRPC service implementations for `ingester`.
 
+mod buffer_debug;
+mod concurrency;
+mod persist;
 mod query;
 mod rpc_write;
+mod write_status;
 
 use std::{fmt::Debug, sync::Arc};
 
 use arrow_flight::flight_service_server::FlightServiceServer;
 use generated_types::influxdata::iox::{
     catalog::v1::catalog_service_server::CatalogServiceServer,
-    ingester::v1::write_service_server::WriteServiceServer,
+    ingester::v1::{
+        buffer_debug_service_server::BufferDebugServiceServer,
+        persist_service_server::PersistServiceServer, write_service_server::WriteServiceServer,
+        write_status_service_server::WriteStatusServiceServer,
+    },
 };
 use iox_catalog::interface::Catalog;
 use service_grpc_catalog::CatalogService;
+use tonic::codec::CompressionEncoding;
 
 use crate::{
-    dml_sink::DmlSink,
-    init::IngesterRpcInterface,
+    buffer_tree::BufferTree,
+    dml_sink::{DmlSink, SequenceTracker},
+    ingest_state::IngestState,
+    init::{IngesterRpcInterface, QueryConcurrency, Readiness, ReadinessProbe},
+    persist::handle::PersistHandle,
     query::{response::QueryResponse, QueryExec},
     timestamp_oracle::TimestampOracle,
 };
 
-use self::rpc_write::RpcWrite;
+use self::{
+    buffer_debug::BufferDebug, persist::PersistAdmin, rpc_write::RpcWrite,
+    write_status::WriteStatus,
+};
 
 /// This type is responsible for injecting internal dependencies that SHOULD NOT
 /// leak outside of the ingester crate into public gRPC handlers.
@@ -31,8 +46,13 @@ use self::rpc_write::RpcWrite;
 pub(crate) struct GrpcDelegate<D, Q> {
     dml_sink: Arc<D>,
     query_exec: Arc<Q>,
+    buffer: Arc<BufferTree>,
     timestamp: Arc<TimestampOracle>,
+    sequence_tracker: Arc<SequenceTracker>,
     catalog: Arc<dyn Catalog>,
+    readiness: Arc<ReadinessProbe>,
+    ingest_state: Arc<IngestState>,
+    persist: PersistHandle,
     metrics: Arc<metric::Registry>,
 }
 
@@ -42,18 +62,29 @@ where
     Q: QueryExec<Response = QueryResponse> + 'static,
 {
     /// Initialise a new [`GrpcDelegate`].
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         dml_sink: Arc<D>,
         query_exec: Arc<Q>,
+        buffer: Arc<BufferTree>,
         timestamp: Arc<TimestampOracle>,
+        sequence_tracker: Arc<SequenceTracker>,
         catalog: Arc<dyn Catalog>,
+        readiness: Arc<ReadinessProbe>,
+        ingest_state: Arc<IngestState>,
+        persist: PersistHandle,
         metrics: Arc<metric::Registry>,
     ) -> Self {
         Self {
             dml_sink,
             query_exec,
+            buffer,
             timestamp,
+            sequence_tracker,
             catalog,
+            readiness,
+            ingest_state,
+            persist,
             metrics,
         }
     }
@@ -69,6 +100,9 @@ where
     type CatalogHandler = CatalogService;
     type WriteHandler = RpcWrite<Arc<D>>;
     type FlightHandler = query::FlightService<Arc<Q>>;
+    type BufferDebugHandler = BufferDebug;
+    type PersistHandler = PersistAdmin;
+    type WriteStatusHandler = WriteStatus;
 
     /// Acquire a [`CatalogService`] gRPC service implementation.
     ///
@@ -92,12 +126,49 @@ where
     /// [`FlightService`]: arrow_flight::flight_service_server::FlightService
     fn query_service(
         &self,
-        max_simultaneous_requests: usize,
+        concurrency: QueryConcurrency,
+        compression: Option<CompressionEncoding>,
     ) -> FlightServiceServer<Self::FlightHandler> {
-        FlightServiceServer::new(query::FlightService::new(
+        let service = FlightServiceServer::new(query::FlightService::new(
             Arc::clone(&self.query_exec),
-            max_simultaneous_requests,
+            concurrency,
+            Arc::clone(&self.ingest_state),
             &self.metrics,
+        ));
+
+        match compression {
+            Some(v) => service.send_compressed(v).accept_compressed(v),
+            None => service,
+        }
+    }
+
+    /// Return a [`BufferDebugService`] gRPC implementation.
+    ///
+    /// [`BufferDebugService`]: generated_types::influxdata::iox::ingester::v1::buffer_debug_service_server::BufferDebugService
+    fn buffer_debug_service(&self) -> BufferDebugServiceServer<Self::BufferDebugHandler> {
+        BufferDebugServiceServer::new(BufferDebug::new(Arc::clone(&self.buffer)))
+    }
+
+    /// Return a [`PersistService`] gRPC implementation.
+    ///
+    /// [`PersistService`]: generated_types::influxdata::iox::ingester::v1::persist_service_server::PersistService
+    fn persist_service(&self) -> PersistServiceServer<Self::PersistHandler> {
+        PersistServiceServer::new(PersistAdmin::new(self.persist.clone()))
+    }
+
+    /// Return a [`WriteStatusService`] gRPC implementation.
+    ///
+    /// [`WriteStatusService`]: generated_types::influxdata::iox::ingester::v1::write_status_service_server::WriteStatusService
+    fn write_status_service(&self) -> WriteStatusServiceServer<Self::WriteStatusHandler> {
+        WriteStatusServiceServer::new(WriteStatus::new(
+            Arc::clone(&self.timestamp),
+            Arc::clone(&self.sequence_tracker),
+            Arc::clone(&self.buffer),
         ))
     }
+
+    /// Return the current [`Readiness`] of the ingester.
+    fn readiness(&self) -> Readiness {
+        self.readiness.readiness()
+    }
 }
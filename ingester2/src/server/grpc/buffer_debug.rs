@@ -0,0 +1,191 @@
+use std::sync::Arc;
+
+use generated_types::influxdata::iox::ingester::v1::{
+    self as proto, buffer_debug_service_server::BufferDebugService,
+};
+use tonic::{Request, Response};
+
+use crate::buffer_tree::{partition::PartitionData, BufferTree};
+
+/// A gRPC [`BufferDebugService`] handler, describing the shape of the data
+/// held in a [`BufferTree`].
+///
+/// Deferred namespace & table names are never loaded by this handler - an
+/// unresolved name is reported as
+/// [`UNRESOLVED_DISPLAY_STRING`](crate::deferred_load::UNRESOLVED_DISPLAY_STRING).
+#[derive(Debug)]
+pub(crate) struct BufferDebug {
+    buffer: Arc<BufferTree>,
+}
+
+impl BufferDebug {
+    /// Instantiate a new [`BufferDebug`] describing the contents of `buffer`.
+    pub(crate) fn new(buffer: Arc<BufferTree>) -> Self {
+        Self { buffer }
+    }
+}
+
+#[tonic::async_trait]
+impl BufferDebugService for BufferDebug {
+    /// Describe the namespaces, tables and partitions in the [`BufferTree`].
+    async fn get_buffer_tree(
+        &self,
+        _request: Request<proto::GetBufferTreeRequest>,
+    ) -> Result<Response<proto::GetBufferTreeResponse>, tonic::Status> {
+        let mut namespaces = self
+            .buffer
+            .namespaces()
+            .into_iter()
+            .map(|ns| {
+                let mut tables = ns
+                    .tables()
+                    .into_iter()
+                    .map(|t| {
+                        let mut partitions = t
+                            .partitions()
+                            .into_iter()
+                            .map(|p| describe_partition(&p.lock()))
+                            .collect::<Vec<_>>();
+                        partitions.sort_unstable_by_key(|p| p.partition_id);
+
+                        proto::BufferedTable {
+                            table_id: t.table_id().get(),
+                            table_name: t.table_name().to_string(),
+                            partitions,
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                tables.sort_unstable_by_key(|t| t.table_id);
+
+                proto::BufferedNamespace {
+                    namespace_id: ns.namespace_id().get(),
+                    namespace_name: ns.namespace_name().to_string(),
+                    tables,
+                }
+            })
+            .collect::<Vec<_>>();
+        namespaces.sort_unstable_by_key(|ns| ns.namespace_id);
+
+        Ok(Response::new(proto::GetBufferTreeResponse { namespaces }))
+    }
+}
+
+/// Summarise the data held in `p`.
+fn describe_partition(p: &PartitionData) -> proto::BufferedPartition {
+    let timestamps = p.timestamp_range();
+
+    proto::BufferedPartition {
+        partition_id: p.partition_id().get(),
+        partition_key: p.partition_key().to_string(),
+        buffered_rows: p.buffered_rows() as u64,
+        buffered_bytes: p.buffered_bytes() as u64,
+        persisting_bytes: p.persisting_bytes() as u64,
+        persisting_batches: p.persisting_batches() as u64,
+        min_timestamp: timestamps.map(|v| v.min),
+        max_timestamp: timestamps.map(|v| v.max),
+        buffered_max_sequence_number: p.buffered_max_sequence_number().map(|v| v.get()),
+        persisting_max_sequence_number: p.persisting_max_sequence_number().map(|v| v.get()),
+        started_persistence_count: p.started_persistence_count().get(),
+        completed_persistence_count: p.completed_persistence_count().get(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use data_types::{NamespaceId, PartitionId, PartitionKey, TableId};
+    use dml::DmlOperation;
+
+    use super::*;
+    use crate::{
+        buffer_tree::{
+            namespace::name_resolver::mock::MockNamespaceNameProvider,
+            partition::{resolver::mock::MockPartitionProvider, SortKeyState},
+            table::name_resolver::mock::MockTableNameProvider,
+        },
+        deferred_load::{self, DeferredLoad},
+        dml_sink::DmlSink,
+        test_util::make_write_op,
+    };
+
+    const NAMESPACE_ID: NamespaceId = NamespaceId::new(42);
+    const TABLE_ID: TableId = TableId::new(44);
+
+    #[tokio::test]
+    async fn test_get_buffer_tree() {
+        let buffer = Arc::new(BufferTree::new(
+            Arc::new(MockNamespaceNameProvider::default()),
+            Arc::new(MockTableNameProvider::new("bananas")),
+            Arc::new(
+                MockPartitionProvider::default().with_partition(PartitionData::new(
+                    PartitionId::new(1),
+                    PartitionKey::from("p1"),
+                    NAMESPACE_ID,
+                    Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                        "platanos".into()
+                    })),
+                    TABLE_ID,
+                    Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                        "bananas".into()
+                    })),
+                    SortKeyState::Provided(None),
+                )),
+            ),
+            Default::default(),
+        ));
+
+        let handler = BufferDebug::new(Arc::clone(&buffer));
+
+        // An empty buffer is described as such.
+        let got = handler
+            .get_buffer_tree(Request::new(proto::GetBufferTreeRequest {}))
+            .await
+            .expect("rpc call should succeed")
+            .into_inner();
+        assert!(got.namespaces.is_empty());
+
+        buffer
+            .apply(DmlOperation::Write(make_write_op(
+                &PartitionKey::from("p1"),
+                NAMESPACE_ID,
+                "bananas",
+                TABLE_ID,
+                42,
+                "bananas,region=asia v=1i 10\nbananas,region=europe v=2i 20",
+            )))
+            .await
+            .expect("write should succeed");
+
+        let got = handler
+            .get_buffer_tree(Request::new(proto::GetBufferTreeRequest {}))
+            .await
+            .expect("rpc call should succeed")
+            .into_inner();
+
+        let [ns] = <[_; 1]>::try_from(got.namespaces).expect("one namespace");
+        assert_eq!(ns.namespace_id, NAMESPACE_ID.get());
+        // The namespace name is reported without forcing it to load.
+        assert!(
+            ns.namespace_name == "bananas"
+                || ns.namespace_name == deferred_load::UNRESOLVED_DISPLAY_STRING
+        );
+
+        let [table] = <[_; 1]>::try_from(ns.tables).expect("one table");
+        assert_eq!(table.table_id, TABLE_ID.get());
+
+        let [p] = <[_; 1]>::try_from(table.partitions).expect("one partition");
+        assert_eq!(p.partition_id, 1);
+        assert_eq!(p.partition_key, "p1");
+        assert_eq!(p.buffered_rows, 2);
+        assert!(p.buffered_bytes > 0);
+        assert_eq!(p.persisting_bytes, 0);
+        assert_eq!(p.persisting_batches, 0);
+        assert_eq!(p.min_timestamp, Some(10));
+        assert_eq!(p.max_timestamp, Some(20));
+        assert_eq!(p.buffered_max_sequence_number, Some(42));
+        assert_eq!(p.persisting_max_sequence_number, None);
+        assert_eq!(p.started_persistence_count, 0);
+        assert_eq!(p.completed_persistence_count, 0);
+    }
+}
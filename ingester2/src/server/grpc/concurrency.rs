@@ -0,0 +1,284 @@
+//! Limiting of the number of queries executing concurrently.
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use metric::{U64Counter, U64Gauge};
+use observability_deps::tracing::*;
+
+use crate::{ingest_state::IngestState, init::QueryConcurrency};
+
+/// A limit on the number of queries executing concurrently, rejecting queries
+/// in excess of it.
+///
+/// A query holds a [`QueryPermit`] until its response has been streamed (or
+/// the response stream is dropped).
+///
+/// When configured with [`QueryConcurrency::Adaptive`], the limit is adjusted
+/// as queries complete: it is decreased by a quarter when a query takes longer
+/// than the target latency, or while the ingester is under memory pressure
+/// (any [`IngestState`] condition is set), and increased by one when a query
+/// completes within the target latency.
+#[derive(Debug)]
+pub(crate) struct QueryLimiter {
+    mode: QueryConcurrency,
+    ingest_state: Arc<IngestState>,
+
+    /// The number of queries currently holding a [`QueryPermit`].
+    in_flight: AtomicUsize,
+    /// The current number of queries allowed to execute concurrently.
+    limit: AtomicUsize,
+
+    in_flight_gauge: U64Gauge,
+    limit_gauge: U64Gauge,
+    /// Number of queries rejected due to the limit being reached.
+    rejected: U64Counter,
+}
+
+impl QueryLimiter {
+    /// Initialise a new [`QueryLimiter`] applying `mode`, reading the memory
+    /// pressure of the ingester from `ingest_state`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mode` is [`QueryConcurrency::Adaptive`] with a `min` of 0 or
+    /// above `max`.
+    pub(crate) fn new(
+        mode: QueryConcurrency,
+        ingest_state: Arc<IngestState>,
+        metrics: &metric::Registry,
+    ) -> Self {
+        let limit = match mode {
+            QueryConcurrency::Fixed(n) => n,
+            QueryConcurrency::Adaptive { min, max, .. } => {
+                assert!(
+                    min > 0 && min <= max,
+                    "adaptive query concurrency requires 0 < min <= max"
+                );
+                max
+            }
+        };
+
+        let in_flight_gauge = metrics
+            .register_metric::<U64Gauge>(
+                "ingester_query_in_flight",
+                "number of queries currently executing or streaming their response",
+            )
+            .recorder(&[]);
+        let limit_gauge = metrics
+            .register_metric::<U64Gauge>(
+                "ingester_query_concurrency_limit",
+                "number of queries allowed to execute concurrently",
+            )
+            .recorder(&[]);
+        limit_gauge.set(limit as _);
+        let rejected = metrics
+            .register_metric::<U64Counter>(
+                "query_request_limit_rejected",
+                "number of query requests rejected due to exceeding parallel request limit",
+            )
+            .recorder(&[]);
+
+        Self {
+            mode,
+            ingest_state,
+            in_flight: AtomicUsize::new(0),
+            limit: AtomicUsize::new(limit),
+            in_flight_gauge,
+            limit_gauge,
+            rejected,
+        }
+    }
+
+    /// Acquire a [`QueryPermit`], or return [`None`] if the limit has been
+    /// reached.
+    pub(crate) fn try_acquire(self: &Arc<Self>) -> Option<QueryPermit> {
+        let acquired = self
+            .in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (n < self.limit.load(Ordering::Acquire)).then_some(n + 1)
+            });
+
+        match acquired {
+            Ok(_) => {
+                self.in_flight_gauge.inc(1);
+                Some(QueryPermit {
+                    limiter: Arc::clone(self),
+                    started_at: Instant::now(),
+                })
+            }
+            Err(_) => {
+                self.rejected.inc(1);
+                None
+            }
+        }
+    }
+
+    /// Release a permit held by a query for `latency`, adjusting the limit if
+    /// adaptive.
+    fn release(&self, latency: Duration) {
+        self.in_flight.fetch_sub(1, Ordering::AcqRel);
+        self.in_flight_gauge.dec(1);
+
+        let (min, max, target_latency) = match self.mode {
+            QueryConcurrency::Fixed(_) => return,
+            QueryConcurrency::Adaptive {
+                min,
+                max,
+                target_latency,
+            } => (min, max, target_latency),
+        };
+
+        let overloaded = latency > target_latency || self.ingest_state.read().is_err();
+        let adjust = |limit: usize| {
+            if overloaded {
+                (limit - (limit + 3) / 4).max(min)
+            } else {
+                (limit + 1).min(max)
+            }
+        };
+
+        // The closure always returns Some, so the update cannot fail.
+        let old = self
+            .limit
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |v| Some(adjust(v)))
+            .unwrap_or_else(|v| v);
+        let new = adjust(old);
+        self.limit_gauge.set(new as _);
+
+        if overloaded && new < old {
+            debug!(
+                ?latency,
+                old_limit = old,
+                new_limit = new,
+                "decreased query concurrency limit"
+            );
+        }
+    }
+}
+
+/// A permit to execute a query, released (and the query latency observed by
+/// the [`QueryLimiter`]) when dropped.
+#[derive(Debug)]
+pub(crate) struct QueryPermit {
+    limiter: Arc<QueryLimiter>,
+    started_at: Instant,
+}
+
+impl Drop for QueryPermit {
+    fn drop(&mut self) {
+        self.limiter.release(self.started_at.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use metric::Metric;
+
+    use super::*;
+    use crate::ingest_state::IngestStateError;
+
+    fn limit(metrics: &metric::Registry) -> u64 {
+        metrics
+            .get_instrument::<Metric<U64Gauge>>("ingester_query_concurrency_limit")
+            .expect("metric not registered")
+            .get_observer(&metric::Attributes::from(&[]))
+            .expect("no metric observer")
+            .fetch()
+    }
+
+    #[test]
+    fn test_fixed_limit() {
+        let metrics = metric::Registry::default();
+        let limiter = Arc::new(QueryLimiter::new(
+            QueryConcurrency::Fixed(2),
+            Arc::new(IngestState::new(&metrics)),
+            &metrics,
+        ));
+
+        let a = limiter.try_acquire().expect("under limit");
+        let _b = limiter.try_acquire().expect("under limit");
+        assert!(limiter.try_acquire().is_none());
+
+        // Releasing a permit allows another query to execute.
+        drop(a);
+        assert!(limiter.try_acquire().is_some());
+
+        let rejected = metrics
+            .get_instrument::<Metric<U64Counter>>("query_request_limit_rejected")
+            .expect("metric not registered")
+            .get_observer(&metric::Attributes::from(&[]))
+            .expect("no metric observer")
+            .fetch();
+        assert_eq!(rejected, 1);
+        assert_eq!(limit(&metrics), 2);
+    }
+
+    #[test]
+    fn test_adaptive_limit() {
+        let metrics = metric::Registry::default();
+        let ingest_state = Arc::new(IngestState::new(&metrics));
+        let limiter = Arc::new(QueryLimiter::new(
+            QueryConcurrency::Adaptive {
+                min: 2,
+                max: 8,
+                target_latency: Duration::from_secs(60),
+            },
+            Arc::clone(&ingest_state),
+            &metrics,
+        ));
+        assert_eq!(limit(&metrics), 8);
+
+        // Queries completing within the target latency do not grow the limit
+        // beyond the maximum.
+        drop(limiter.try_acquire().unwrap());
+        assert_eq!(limit(&metrics), 8);
+
+        // Memory pressure decreases the limit by a quarter per completed
+        // query, down to the minimum.
+        ingest_state.set(IngestStateError::MemoryLimit);
+        drop(limiter.try_acquire().unwrap());
+        assert_eq!(limit(&metrics), 6);
+        for _ in 0..10 {
+            drop(limiter.try_acquire().unwrap());
+        }
+        assert_eq!(limit(&metrics), 2);
+
+        let a = limiter.try_acquire().unwrap();
+        let _b = limiter.try_acquire().unwrap();
+        assert!(limiter.try_acquire().is_none());
+
+        // Once the pressure is relieved, the limit recovers one query at a
+        // time.
+        ingest_state.unset(IngestStateError::MemoryLimit);
+        drop(a);
+        assert_eq!(limit(&metrics), 3);
+    }
+
+    #[test]
+    fn test_adaptive_limit_latency() {
+        let metrics = metric::Registry::default();
+        let limiter = QueryLimiter::new(
+            QueryConcurrency::Adaptive {
+                min: 1,
+                max: 4,
+                target_latency: Duration::from_millis(100),
+            },
+            Arc::new(IngestState::new(&metrics)),
+            &metrics,
+        );
+
+        // Simulate the completion of slow queries.
+        limiter.in_flight.fetch_add(2, Ordering::AcqRel);
+        limiter.in_flight_gauge.inc(2);
+        limiter.release(Duration::from_secs(1));
+        assert_eq!(limit(&metrics), 3);
+        limiter.release(Duration::from_secs(1));
+        assert_eq!(limit(&metrics), 2);
+    }
+}
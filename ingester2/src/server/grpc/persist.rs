@@ -0,0 +1,719 @@
+use std::{pin::Pin, time::Duration};
+
+use data_types::{NamespaceId, PartitionId};
+use futures::Stream;
+use generated_types::influxdata::iox::ingester::v1::{
+    self as proto, persist_service_server::PersistService,
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tonic::{Request, Response};
+
+use crate::persist::{
+    audit::PersistOutcome,
+    completion::PersistCompletion,
+    handle::PersistHandle,
+    orphans::{OrphanError, DEFAULT_ORPHAN_MIN_AGE},
+    scaling::WorkerBounds,
+    state::PersistStage,
+};
+
+type TonicStream<T> = Pin<Box<dyn Stream<Item = Result<T, tonic::Status>> + Send + 'static>>;
+
+/// A gRPC [`PersistService`] handler, describing and reconfiguring the persist
+/// subsystem at runtime.
+#[derive(Debug)]
+pub(crate) struct PersistAdmin {
+    persist: PersistHandle,
+}
+
+impl PersistAdmin {
+    /// Instantiate a new [`PersistAdmin`] configuring the persist subsystem
+    /// of `persist`.
+    pub(crate) fn new(persist: PersistHandle) -> Self {
+        Self { persist }
+    }
+}
+
+#[tonic::async_trait]
+impl PersistService for PersistAdmin {
+    type WatchPersistedStream = TonicStream<proto::PersistedPartition>;
+
+    /// Change the bounds on the number of persist workers.
+    async fn set_persist_workers(
+        &self,
+        request: Request<proto::SetPersistWorkersRequest>,
+    ) -> Result<Response<proto::SetPersistWorkersResponse>, tonic::Status> {
+        let request = request.into_inner();
+
+        let current_workers = self.persist.stats().workers();
+        let bounds = WorkerBounds {
+            min: request.min_workers as usize,
+            max: request.max_workers as usize,
+            target_latency: request
+                .target_latency_ms
+                .map(Duration::from_millis)
+                .unwrap_or_else(|| self.persist.worker_bounds().target_latency),
+        };
+
+        self.persist
+            .set_worker_bounds(bounds)
+            .map_err(|e| tonic::Status::invalid_argument(e.to_string()))?;
+
+        Ok(Response::new(proto::SetPersistWorkersResponse {
+            min_workers: bounds.min as u64,
+            max_workers: bounds.max as u64,
+            target_latency_ms: bounds.target_latency.as_millis() as u64,
+            current_workers: current_workers as u64,
+        }))
+    }
+
+    /// Describe the outstanding persist jobs, recent failures and quarantined
+    /// jobs.
+    async fn get_persist_state(
+        &self,
+        _request: Request<proto::GetPersistStateRequest>,
+    ) -> Result<Response<proto::GetPersistStateResponse>, tonic::Status> {
+        let snapshot = self.persist.state().snapshot();
+
+        Ok(Response::new(proto::GetPersistStateResponse {
+            outstanding_jobs: snapshot.outstanding_jobs as u64,
+            submission_queue_jobs: snapshot.submission_queue_jobs() as u64,
+            oldest_job_age_ms: snapshot
+                .oldest_enqueued_at
+                .map(|v| v.elapsed().as_millis() as u64),
+            workers: snapshot
+                .workers
+                .iter()
+                .map(|w| proto::PersistWorker {
+                    worker_id: w.id as u64,
+                    active: w.active,
+                    jobs: w.jobs as u64,
+                })
+                .collect(),
+            partitions: snapshot
+                .partitions
+                .iter()
+                .map(|p| proto::PersistingPartition {
+                    partition_id: p.partition_id.get(),
+                    worker_id: p.worker as u64,
+                    jobs: p.jobs as u64,
+                    stage: match p.stage {
+                        PersistStage::Queued => proto::PersistStage::Queued,
+                        PersistStage::Compacting => proto::PersistStage::Compacting,
+                        PersistStage::Uploading => proto::PersistStage::Uploading,
+                        PersistStage::UpdatingCatalog => proto::PersistStage::UpdatingCatalog,
+                    } as i32,
+                    stage_duration_ms: p.stage_started_at.elapsed().as_millis() as u64,
+                })
+                .collect(),
+            recent_failures: snapshot
+                .recent_failures
+                .into_iter()
+                .map(|f| proto::PersistFailure {
+                    partition_id: f.partition_id.get(),
+                    operation: f.operation.to_string(),
+                    error: f.error,
+                    age_ms: f.at.elapsed().as_millis() as u64,
+                })
+                .collect(),
+            quarantined: self
+                .persist
+                .quarantine()
+                .snapshot()
+                .into_iter()
+                .map(|q| proto::QuarantinedPartition {
+                    partition_id: q.partition_id.get(),
+                    jobs: q.jobs as u64,
+                    error: q.error,
+                    age_ms: q.quarantined_at.elapsed().as_millis() as u64,
+                })
+                .collect(),
+        }))
+    }
+
+    /// Re-enqueue the quarantined persist jobs of a partition, or of all
+    /// partitions.
+    async fn requeue_quarantined_jobs(
+        &self,
+        request: Request<proto::RequeueQuarantinedJobsRequest>,
+    ) -> Result<Response<proto::RequeueQuarantinedJobsResponse>, tonic::Status> {
+        let partition_id = request.into_inner().partition_id.map(PartitionId::new);
+
+        let jobs = self.persist.requeue_quarantined(partition_id).await;
+
+        Ok(Response::new(proto::RequeueQuarantinedJobsResponse {
+            jobs: jobs as u64,
+        }))
+    }
+
+    /// Find (and optionally delete) the objects of a namespace that are not
+    /// parquet files in the catalog.
+    async fn find_orphaned_files(
+        &self,
+        request: Request<proto::FindOrphanedFilesRequest>,
+    ) -> Result<Response<proto::FindOrphanedFilesResponse>, tonic::Status> {
+        let request = request.into_inner();
+        let min_age = request
+            .min_age_ms
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_ORPHAN_MIN_AGE);
+
+        let files = self
+            .persist
+            .find_orphaned_files(
+                NamespaceId::new(request.namespace_id),
+                min_age,
+                request.delete,
+            )
+            .await
+            .map_err(|e| match e {
+                OrphanError::MinAgeTooShort { .. } => {
+                    tonic::Status::invalid_argument(e.to_string())
+                }
+                OrphanError::List(_) | OrphanError::Catalog(_) => {
+                    tonic::Status::internal(e.to_string())
+                }
+            })?;
+
+        Ok(Response::new(proto::FindOrphanedFilesResponse {
+            files: files
+                .into_iter()
+                .map(|f| proto::OrphanedFile {
+                    path: f.path.to_string(),
+                    size_bytes: f.size as u64,
+                    age_ms: f.age.as_millis() as u64,
+                    deleted: f.deleted,
+                })
+                .collect(),
+        }))
+    }
+
+    /// Stream the partitions persisted from now on.
+    async fn watch_persisted(
+        &self,
+        request: Request<proto::WatchPersistedRequest>,
+    ) -> Result<Response<Self::WatchPersistedStream>, tonic::Status> {
+        let request = request.into_inner();
+
+        Ok(Response::new(watch_persisted(
+            self.persist.subscribe_completions(),
+            request.namespace_id.map(NamespaceId::new),
+            request.partition_id.map(PartitionId::new),
+        )))
+    }
+
+    /// List the parquet files held in the local file cache.
+    async fn list_cached_files(
+        &self,
+        _request: Request<proto::ListCachedFilesRequest>,
+    ) -> Result<Response<proto::ListCachedFilesResponse>, tonic::Status> {
+        let (dir, files) = self
+            .persist
+            .cached_files()
+            .ok_or_else(|| tonic::Status::failed_precondition("parquet file cache disabled"))?;
+
+        Ok(Response::new(proto::ListCachedFilesResponse {
+            directory: dir.display().to_string(),
+            files: files
+                .into_iter()
+                .map(|f| proto::CachedFile {
+                    object_store_id: f.object_store_id.to_string(),
+                    path: f.path.display().to_string(),
+                    size_bytes: f.size,
+                })
+                .collect(),
+        }))
+    }
+
+    /// List the outcomes of the most recently executed persist jobs.
+    async fn list_persist_audit(
+        &self,
+        request: Request<proto::ListPersistAuditRequest>,
+    ) -> Result<Response<proto::ListPersistAuditResponse>, tonic::Status> {
+        let partition_id = request.into_inner().partition_id.map(PartitionId::new);
+
+        Ok(Response::new(proto::ListPersistAuditResponse {
+            records: self
+                .persist
+                .audit_records(partition_id)
+                .into_iter()
+                .map(|r| {
+                    let (object_store_ids, error) = match r.outcome {
+                        PersistOutcome::Persisted { object_store_ids } => (
+                            object_store_ids.iter().map(ToString::to_string).collect(),
+                            None,
+                        ),
+                        PersistOutcome::Quarantined { error } => (vec![], Some(error)),
+                    };
+                    proto::PersistAuditRecord {
+                        namespace_id: r.namespace_id.get(),
+                        partition_id: r.partition_id.get(),
+                        job_id: r.job_id,
+                        min_sequence_number: r.min_sequence_number.get(),
+                        max_sequence_number: r.max_sequence_number.get(),
+                        duration_ms: r.duration.as_millis() as u64,
+                        age_ms: r.finished_at.elapsed().as_millis() as u64,
+                        object_store_ids,
+                        error,
+                    }
+                })
+                .collect(),
+        }))
+    }
+}
+
+/// Stream the completions received by `rx` of the partitions of
+/// `namespace_id` and/or `partition_id` (or all partitions if [`None`]),
+/// ending with an error if `rx` misses any completions.
+fn watch_persisted(
+    rx: broadcast::Receiver<PersistCompletion>,
+    namespace_id: Option<NamespaceId>,
+    partition_id: Option<PartitionId>,
+) -> TonicStream<proto::PersistedPartition> {
+    Box::pin(futures::stream::unfold(Some(rx), move |rx| async move {
+        let mut rx = rx?;
+        loop {
+            let c = match rx.recv().await {
+                Ok(v) => v,
+                Err(RecvError::Lagged(n)) => {
+                    let e = tonic::Status::data_loss(format!(
+                        "missed {n} persist completions, watch again to resume"
+                    ));
+                    return Some((Err(e), None));
+                }
+                Err(RecvError::Closed) => return None,
+            };
+
+            if namespace_id.map_or(false, |v| v != c.namespace_id)
+                || partition_id.map_or(false, |v| v != c.partition_id)
+            {
+                continue;
+            }
+
+            let p = proto::PersistedPartition {
+                namespace_id: c.namespace_id.get(),
+                table_id: c.table_id.get(),
+                partition_id: c.partition_id.get(),
+                max_sequence_number: c.max_sequence_number.get(),
+                object_store_ids: c.object_store_ids.iter().map(|v| v.to_string()).collect(),
+            };
+            return Some((Ok(p), Some(rx)));
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{num::NonZeroUsize, sync::Arc};
+
+    use assert_matches::assert_matches;
+    use bytes::Bytes;
+    use data_types::{SequenceNumber, TableId};
+    use futures::StreamExt;
+    use iox_catalog::mem::MemCatalog;
+    use iox_query::exec::Executor;
+    use iox_time::{MockProvider, SystemProvider, TimeProvider};
+    use object_store::{memory::InMemory, path::Path as ObjectPath, ObjectStore};
+    use parquet_file::storage::{ParquetStorage, StorageId};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        ingest_state::IngestState,
+        init::PersistWorkers,
+        persist::{file_cache::ParquetFileCache, priority::OldestFirst},
+    };
+
+    #[tokio::test]
+    async fn test_set_persist_workers() {
+        let metrics = metric::Registry::default();
+        let (persist, _actor) = PersistHandle::new(
+            1,
+            PersistWorkers::Fixed(2),
+            1,
+            Arc::new(OldestFirst),
+            Arc::new(Executor::new_testing()),
+            ParquetStorage::new(Arc::new(InMemory::new()), StorageId::from("iox")),
+            Arc::new(MemCatalog::new(Arc::new(metric::Registry::default()))),
+            Arc::new(IngestState::new(&metrics)),
+            None,
+            NonZeroUsize::new(10).unwrap(),
+            None,
+            false,
+            None,
+            None,
+            Default::default(),
+            &metrics,
+        );
+        let handler = PersistAdmin::new(persist.clone());
+
+        let got = handler
+            .set_persist_workers(Request::new(proto::SetPersistWorkersRequest {
+                min_workers: 1,
+                max_workers: 8,
+                target_latency_ms: Some(5_000),
+            }))
+            .await
+            .expect("rpc call should succeed")
+            .into_inner();
+        assert_eq!(got.min_workers, 1);
+        assert_eq!(got.max_workers, 8);
+        assert_eq!(got.target_latency_ms, 5_000);
+        assert_eq!(got.current_workers, 2);
+        assert_eq!(
+            persist.worker_bounds(),
+            WorkerBounds {
+                min: 1,
+                max: 8,
+                target_latency: Duration::from_secs(5),
+            }
+        );
+
+        // The target latency is retained if not specified.
+        let got = handler
+            .set_persist_workers(Request::new(proto::SetPersistWorkersRequest {
+                min_workers: 4,
+                max_workers: 4,
+                target_latency_ms: None,
+            }))
+            .await
+            .expect("rpc call should succeed")
+            .into_inner();
+        assert_eq!(got.target_latency_ms, 5_000);
+
+        // Invalid bounds are rejected, leaving the bounds unchanged.
+        let err = handler
+            .set_persist_workers(Request::new(proto::SetPersistWorkersRequest {
+                min_workers: 0,
+                max_workers: 4,
+                target_latency_ms: None,
+            }))
+            .await
+            .expect_err("invalid bounds should be rejected");
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        assert_eq!(persist.worker_bounds().min, 4);
+    }
+
+    #[tokio::test]
+    async fn test_get_persist_state() {
+        let metrics = metric::Registry::default();
+        let (persist, _actor) = PersistHandle::new(
+            1,
+            PersistWorkers::Fixed(2),
+            1,
+            Arc::new(OldestFirst),
+            Arc::new(Executor::new_testing()),
+            ParquetStorage::new(Arc::new(InMemory::new()), StorageId::from("iox")),
+            Arc::new(MemCatalog::new(Arc::new(metric::Registry::default()))),
+            Arc::new(IngestState::new(&metrics)),
+            None,
+            NonZeroUsize::new(10).unwrap(),
+            None,
+            false,
+            None,
+            None,
+            Default::default(),
+            &metrics,
+        );
+        let handler = PersistAdmin::new(persist);
+
+        let got = handler
+            .get_persist_state(Request::new(proto::GetPersistStateRequest {}))
+            .await
+            .expect("rpc call should succeed")
+            .into_inner();
+
+        // No jobs are outstanding on the two idle workers.
+        assert_eq!(got.outstanding_jobs, 0);
+        assert_eq!(got.submission_queue_jobs, 0);
+        assert_eq!(got.oldest_job_age_ms, None);
+        assert_eq!(
+            got.workers,
+            [
+                proto::PersistWorker {
+                    worker_id: 0,
+                    active: true,
+                    jobs: 0,
+                },
+                proto::PersistWorker {
+                    worker_id: 1,
+                    active: true,
+                    jobs: 0,
+                },
+            ]
+        );
+        assert!(got.partitions.is_empty());
+        assert!(got.recent_failures.is_empty());
+        assert!(got.quarantined.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_requeue_quarantined_jobs() {
+        let metrics = metric::Registry::default();
+        let (persist, _actor) = PersistHandle::new(
+            1,
+            PersistWorkers::Fixed(1),
+            1,
+            Arc::new(OldestFirst),
+            Arc::new(Executor::new_testing()),
+            ParquetStorage::new(Arc::new(InMemory::new()), StorageId::from("iox")),
+            Arc::new(MemCatalog::new(Arc::new(metric::Registry::default()))),
+            Arc::new(IngestState::new(&metrics)),
+            None,
+            NonZeroUsize::new(10).unwrap(),
+            None,
+            false,
+            None,
+            None,
+            Default::default(),
+            &metrics,
+        );
+        let handler = PersistAdmin::new(persist);
+
+        // Without quarantined jobs, nothing is re-enqueued.
+        for partition_id in [None, Some(42)] {
+            let got = handler
+                .requeue_quarantined_jobs(Request::new(proto::RequeueQuarantinedJobsRequest {
+                    partition_id,
+                }))
+                .await
+                .expect("rpc call should succeed")
+                .into_inner();
+            assert_eq!(got.jobs, 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_persist_audit() {
+        let metrics = metric::Registry::default();
+        let (persist, _actor) = PersistHandle::new(
+            1,
+            PersistWorkers::Fixed(1),
+            1,
+            Arc::new(OldestFirst),
+            Arc::new(Executor::new_testing()),
+            ParquetStorage::new(Arc::new(InMemory::new()), StorageId::from("iox")),
+            Arc::new(MemCatalog::new(Arc::new(metric::Registry::default()))),
+            Arc::new(IngestState::new(&metrics)),
+            None,
+            NonZeroUsize::new(10).unwrap(),
+            None,
+            false,
+            None,
+            None,
+            Default::default(),
+            &metrics,
+        );
+        let handler = PersistAdmin::new(persist);
+
+        // Without executed jobs, nothing is recorded.
+        for partition_id in [None, Some(42)] {
+            let got = handler
+                .list_persist_audit(Request::new(proto::ListPersistAuditRequest {
+                    partition_id,
+                }))
+                .await
+                .expect("rpc call should succeed")
+                .into_inner();
+            assert!(got.records.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_find_orphaned_files() {
+        let metrics = metric::Registry::default();
+        let object_store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let (persist, _actor) = PersistHandle::new(
+            1,
+            PersistWorkers::Fixed(1),
+            1,
+            Arc::new(OldestFirst),
+            Arc::new(Executor::new_testing()),
+            ParquetStorage::new(Arc::clone(&object_store), StorageId::from("iox")),
+            Arc::new(MemCatalog::new(Arc::new(metric::Registry::default()))),
+            Arc::new(IngestState::new(&metrics)),
+            None,
+            NonZeroUsize::new(10).unwrap(),
+            None,
+            false,
+            None,
+            None,
+            Default::default(),
+            &metrics,
+        );
+        let time_provider = Arc::new(MockProvider::new(SystemProvider::new().now()));
+        let handler =
+            PersistAdmin::new(persist.with_time_provider(Arc::clone(&time_provider) as _));
+
+        let path = ObjectPath::from("1/2/3/4/00000000-0000-0000-0000-000000000000.parquet");
+        object_store
+            .put(&path, Bytes::from_static(b"bananas"))
+            .await
+            .unwrap();
+
+        let request = |min_age_ms, delete| {
+            Request::new(proto::FindOrphanedFilesRequest {
+                namespace_id: 1,
+                min_age_ms,
+                delete,
+            })
+        };
+
+        // The object is too recent to be considered orphaned by default.
+        let got = handler
+            .find_orphaned_files(request(None, true))
+            .await
+            .expect("rpc call should succeed")
+            .into_inner();
+        assert!(got.files.is_empty());
+
+        // A dry run reports the object, which is not in the catalog.
+        let got = handler
+            .find_orphaned_files(request(Some(0), false))
+            .await
+            .expect("rpc call should succeed")
+            .into_inner();
+        assert_matches!(
+            got.files.as_slice(),
+            [proto::OrphanedFile { path: p, size_bytes: 7, deleted: false, .. }] => {
+                assert_eq!(p, path.as_ref());
+            }
+        );
+        assert!(object_store.head(&path).await.is_ok());
+
+        // Deleting objects that may belong to running persist jobs is
+        // rejected.
+        let err = handler
+            .find_orphaned_files(request(Some(0), true))
+            .await
+            .expect_err("rpc call should fail");
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+        assert!(object_store.head(&path).await.is_ok());
+
+        // Older objects are deleted.
+        let min_age = Duration::from_secs(2 * 60 * 60);
+        time_provider.inc(min_age);
+        let got = handler
+            .find_orphaned_files(request(Some(min_age.as_millis() as u64), true))
+            .await
+            .expect("rpc call should succeed")
+            .into_inner();
+        assert_matches!(
+            got.files.as_slice(),
+            [proto::OrphanedFile { deleted: true, .. }]
+        );
+        assert!(object_store.head(&path).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_list_cached_files() {
+        let metrics = metric::Registry::default();
+        let dir = tempfile::tempdir().unwrap();
+        let id = Uuid::new_v4();
+        std::fs::write(dir.path().join(format!("{id}.parquet")), b"bananas").unwrap();
+
+        let handler = |file_cache| {
+            let (persist, _actor) = PersistHandle::new(
+                1,
+                PersistWorkers::Fixed(1),
+                1,
+                Arc::new(OldestFirst),
+                Arc::new(Executor::new_testing()),
+                ParquetStorage::new(Arc::new(InMemory::new()), StorageId::from("iox")),
+                Arc::new(MemCatalog::new(Arc::new(metric::Registry::default()))),
+                Arc::new(IngestState::new(&metrics)),
+                None,
+                NonZeroUsize::new(10).unwrap(),
+                None,
+                false,
+                None,
+                file_cache,
+                Default::default(),
+                &metrics,
+            );
+            PersistAdmin::new(persist)
+        };
+
+        // The request fails if the cache is disabled.
+        let err = handler(None)
+            .list_cached_files(Request::new(proto::ListCachedFilesRequest {}))
+            .await
+            .expect_err("cache is disabled");
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+
+        // The file left in the cache directory is listed.
+        let cache = ParquetFileCache::new(dir.path().to_path_buf(), 100, &metrics)
+            .await
+            .unwrap();
+        let got = handler(Some(Arc::new(cache)))
+            .list_cached_files(Request::new(proto::ListCachedFilesRequest {}))
+            .await
+            .expect("rpc call should succeed")
+            .into_inner();
+        assert_eq!(got.directory, dir.path().display().to_string());
+        assert_eq!(
+            got.files,
+            [proto::CachedFile {
+                object_store_id: id.to_string(),
+                path: dir
+                    .path()
+                    .join(format!("{id}.parquet"))
+                    .display()
+                    .to_string(),
+                size_bytes: 7,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_watch_persisted() {
+        let completion = |namespace_id, partition_id, max_sequence_number| PersistCompletion {
+            namespace_id: NamespaceId::new(namespace_id),
+            table_id: TableId::new(1),
+            partition_id: PartitionId::new(partition_id),
+            max_sequence_number: SequenceNumber::new(max_sequence_number),
+            object_store_ids: vec![Uuid::nil()],
+        };
+
+        // Only the completions of the requested namespace are streamed.
+        let (tx, rx) = broadcast::channel(2);
+        let mut stream = watch_persisted(rx, Some(NamespaceId::new(1)), None);
+        tx.send(completion(2, 10, 1)).unwrap();
+        tx.send(completion(1, 11, 2)).unwrap();
+
+        let got = stream
+            .next()
+            .await
+            .unwrap()
+            .expect("should stream completion");
+        assert_eq!(
+            got,
+            proto::PersistedPartition {
+                namespace_id: 1,
+                table_id: 1,
+                partition_id: 11,
+                max_sequence_number: 2,
+                object_store_ids: vec![Uuid::nil().to_string()],
+            }
+        );
+
+        // The stream ends once the completions are no longer broadcast.
+        drop(tx);
+        assert!(stream.next().await.is_none());
+
+        // A subscriber that misses completions observes an error, ending the
+        // stream.
+        let (tx, rx) = broadcast::channel(2);
+        let mut stream = watch_persisted(rx, None, Some(PartitionId::new(10)));
+        for i in 0..3 {
+            tx.send(completion(1, 10, i)).unwrap();
+        }
+        let err = stream
+            .next()
+            .await
+            .unwrap()
+            .expect_err("should miss completions");
+        assert_eq!(err.code(), tonic::Code::DataLoss);
+        assert!(stream.next().await.is_none());
+    }
+}
@@ -12,17 +12,27 @@ use arrow_util::optimize::{
 use data_types::{NamespaceId, PartitionId, TableId};
 use flatbuffers::FlatBufferBuilder;
 use futures::{Stream, StreamExt};
-use generated_types::influxdata::iox::ingester::v1::{self as proto, PartitionStatus};
-use metric::U64Counter;
+use generated_types::{
+    google::FieldViolation,
+    influxdata::iox::ingester::v1::{self as proto, PartitionStatus},
+};
 use observability_deps::tracing::*;
 use pin_project::pin_project;
+use predicate::Predicate;
 use prost::Message;
 use thiserror::Error;
-use tokio::sync::{Semaphore, TryAcquireError};
 use tonic::{Request, Response, Streaming};
 use trace::{ctx::SpanContext, span::SpanExt};
 
-use crate::query::{response::QueryResponse, QueryError, QueryExec};
+use super::concurrency::QueryLimiter;
+use crate::{
+    ingest_state::IngestState,
+    init::QueryConcurrency,
+    query::{
+        response::{PartitionStream, QueryResponse},
+        QueryError, QueryExec,
+    },
+};
 
 /// Error states for the query RPC handler.
 ///
@@ -40,6 +50,11 @@ enum Error {
     #[error("invalid flight ticket: {0}")]
     InvalidTicket(#[from] prost::DecodeError),
 
+    /// The [`proto::Predicate`] within the Flight ticket cannot be converted
+    /// into a [`Predicate`].
+    #[error("invalid query predicate: {0}")]
+    InvalidPredicate(FieldViolation),
+
     /// The [`proto::IngesterQueryResponseMetadata`] response metadata being
     /// returned to the RPC caller cannot be serialised into the protobuf
     /// response format.
@@ -53,6 +68,20 @@ enum Error {
     /// The number of simultaneous queries being executed has been reached.
     #[error("simultaneous query limit exceeded")]
     RequestLimit,
+
+    /// The app metadata of a DoExchange request message cannot be
+    /// deserialised into a [`proto::IngesterQueryExchangeRequest`].
+    #[error("invalid exchange request: {0}")]
+    InvalidExchangeRequest(prost::DecodeError),
+
+    /// The first DoExchange request message does not contain a
+    /// [`proto::IngesterQueryRequest`].
+    #[error("exchange request contains no query")]
+    MissingExchangeQuery,
+
+    /// An error was observed in the DoExchange request stream.
+    #[error("exchange request stream error: {0}")]
+    ExchangeStream(tonic::Status),
 }
 
 /// Map a query-execution error into a [`tonic::Status`].
@@ -62,6 +91,8 @@ impl From<QueryError> for tonic::Status {
 
         let code = match e {
             QueryError::TableNotFound(_, _) | QueryError::NamespaceNotFound(_) => Code::NotFound,
+            // The client should retry once the ingester has replayed its WAL.
+            QueryError::NotReady(_) => Code::Unavailable,
         };
 
         Self::new(code, e.to_string())
@@ -74,10 +105,17 @@ impl From<Error> for tonic::Status {
         use tonic::Code;
 
         let code = match e {
-            Error::InvalidTicket(_) => {
+            Error::InvalidTicket(_)
+            | Error::InvalidPredicate(_)
+            | Error::InvalidExchangeRequest(_)
+            | Error::MissingExchangeQuery => {
                 debug!(error=%e, "invalid flight query ticket");
                 Code::InvalidArgument
             }
+            Error::Stream(ArrowError::MemoryError(_)) => {
+                warn!(error=%e, "flight query exceeded memory limit");
+                Code::ResourceExhausted
+            }
             Error::Stream(_) | Error::SerialiseResponse(_) => {
                 error!(error=%e, "flight query response error");
                 Code::Internal
@@ -86,6 +124,10 @@ impl From<Error> for tonic::Status {
                 warn!("simultaneous query limit exceeded");
                 Code::ResourceExhausted
             }
+            Error::ExchangeStream(ref s) => {
+                debug!(error=%e, "flight exchange request stream error");
+                s.code()
+            }
         };
 
         Self::new(code, e.to_string())
@@ -101,31 +143,21 @@ pub(crate) struct FlightService<Q> {
     /// ingester services.
     ///
     /// This allows the ingester to drop a portion of requests when experiencing
-    /// an unusual flood of requests
-    request_sem: Semaphore,
-
-    /// Number of queries rejected due to lack of available `request_sem`
-    /// permit.
-    query_request_limit_rejected: U64Counter,
+    /// an unusual flood of requests, or when queries slow down or memory runs
+    /// short if the limit is adaptive.
+    limiter: Arc<QueryLimiter>,
 }
 
 impl<Q> FlightService<Q> {
     pub(super) fn new(
         query_handler: Q,
-        max_simultaneous_requests: usize,
+        concurrency: QueryConcurrency,
+        ingest_state: Arc<IngestState>,
         metrics: &metric::Registry,
     ) -> Self {
-        let query_request_limit_rejected = metrics
-            .register_metric::<U64Counter>(
-                "query_request_limit_rejected",
-                "number of query requests rejected due to exceeding parallel request limit",
-            )
-            .recorder(&[]);
-
         Self {
             query_handler,
-            request_sem: Semaphore::new(max_simultaneous_requests),
-            query_request_limit_rejected,
+            limiter: Arc::new(QueryLimiter::new(concurrency, ingest_state, metrics)),
         }
     }
 }
@@ -158,44 +190,10 @@ where
     ) -> Result<Response<Self::DoGetStream>, tonic::Status> {
         let span_ctx: Option<SpanContext> = request.extensions().get().cloned();
 
-        // Acquire and hold a permit for the duration of this request, or return
-        // an error if the existing requests have already exhausted the
-        // allocation.
-        //
-        // Our goal is to limit the number of concurrently executing queries as
-        // a rough way of ensuring we don't explode memory by trying to do too
-        // much at the same time.
-        let _permit = match self.request_sem.try_acquire() {
-            Ok(p) => p,
-            Err(TryAcquireError::NoPermits) => {
-                warn!("simultaneous request limit exceeded - dropping query request");
-                self.query_request_limit_rejected.inc(1);
-                return Err(Error::RequestLimit)?;
-            }
-            Err(e) => panic!("request limiter error: {}", e),
-        };
-
         let ticket = request.into_inner();
         let request = proto::IngesterQueryRequest::decode(&*ticket.ticket).map_err(Error::from)?;
 
-        // Extract the namespace/table identifiers
-        let namespace_id = NamespaceId::new(request.namespace_id);
-        let table_id = TableId::new(request.table_id);
-
-        // Predicate pushdown is part of the API, but not implemented.
-        if let Some(p) = request.predicate {
-            warn!(predicate=?p, "ignoring query predicate (unsupported)");
-        }
-
-        let response = self
-            .query_handler
-            .query_exec(
-                namespace_id,
-                table_id,
-                request.columns,
-                span_ctx.child_span("ingester query"),
-            )
-            .await?;
+        let response = self.query(request, span_ctx).await?;
 
         let output = FlightFrameCodec::new(FlatIngesterQueryResponseStream::from(response));
 
@@ -250,14 +248,148 @@ where
         Err(tonic::Status::unimplemented("Not yet implemented"))
     }
 
+    /// Execute the [`proto::IngesterQueryRequest`] in the first
+    /// [`proto::IngesterQueryExchangeRequest`] received, streaming the
+    /// partitions of the response as the client grants credit for them.
     async fn do_exchange(
         &self,
-        _request: Request<Streaming<FlightData>>,
+        request: Request<Streaming<FlightData>>,
     ) -> Result<Response<Self::DoExchangeStream>, tonic::Status> {
-        Err(tonic::Status::unimplemented("Not yet implemented"))
+        let span_ctx: Option<SpanContext> = request.extensions().get().cloned();
+
+        let mut requests = request.into_inner();
+        let first = requests
+            .message()
+            .await?
+            .ok_or(Error::MissingExchangeQuery)?;
+        let exchange = proto::IngesterQueryExchangeRequest::decode(&*first.app_metadata)
+            .map_err(Error::InvalidExchangeRequest)?;
+        let request = exchange.query.ok_or(Error::MissingExchangeQuery)?;
+
+        let response = self.query(request, span_ctx).await?;
+
+        let output = FlightFrameCodec::new(partition_credit_gate(
+            FlatIngesterQueryResponseStream::from(response),
+            requests,
+            exchange.partition_credit,
+        ));
+
+        Ok(Response::new(Box::pin(output) as Self::DoExchangeStream))
+    }
+}
+
+impl<Q> FlightService<Q>
+where
+    Q: QueryExec<Response = QueryResponse>,
+{
+    /// Execute `request`, subject to the simultaneous request limit.
+    async fn query(
+        &self,
+        request: proto::IngesterQueryRequest,
+        span_ctx: Option<SpanContext>,
+    ) -> Result<QueryResponse, tonic::Status> {
+        // Acquire and hold a permit for the duration of this request, or return
+        // an error if the existing requests have already exhausted the
+        // allocation.
+        //
+        // Our goal is to limit the number of concurrently executing queries as
+        // a rough way of ensuring we don't explode memory by trying to do too
+        // much at the same time.
+        let permit = match self.limiter.try_acquire() {
+            Some(p) => p,
+            None => {
+                warn!("simultaneous request limit exceeded - dropping query request");
+                return Err(Error::RequestLimit)?;
+            }
+        };
+
+        // Extract the namespace/table identifiers
+        let namespace_id = NamespaceId::new(request.namespace_id);
+        let table_id = TableId::new(request.table_id);
+
+        // The predicate is used to filter the data returned - the querier
+        // applies the expressions the ingester does not evaluate.
+        let predicate = request
+            .predicate
+            .map(Predicate::try_from)
+            .transpose()
+            .map_err(Error::InvalidPredicate)?;
+
+        let response = self
+            .query_handler
+            .query_exec(
+                namespace_id,
+                table_id,
+                request.columns,
+                predicate,
+                span_ctx.child_span("ingester query"),
+            )
+            .await?;
+
+        // The permit is held until the response stream is dropped, so that the
+        // limit (and the latency observed by it) covers streaming the
+        // response to the client.
+        let partitions = response.into_partition_stream().map(move |p| {
+            let _permit = &permit;
+            p
+        });
+
+        Ok(QueryResponse::new(PartitionStream::new(partitions)))
     }
 }
 
+/// Gate the partitions in `inner` on the credit granted by the client, as the
+/// sum of `initial_credit` and the `partition_credit` of each
+/// [`proto::IngesterQueryExchangeRequest`] received from `requests`.
+///
+/// Each partition consumes one credit - once exhausted, the next partition is
+/// not read from `inner` until more credit is granted. The stream ends if
+/// `requests` ends while no credit is available.
+fn partition_credit_gate<R>(
+    inner: FlatIngesterQueryResponseStream,
+    requests: R,
+    initial_credit: u64,
+) -> impl Stream<Item = Result<FlatIngesterQueryResponse, Error>> + Send
+where
+    R: Stream<Item = Result<FlightData, tonic::Status>> + Send + Unpin + 'static,
+{
+    futures::stream::unfold(
+        (inner.peekable(), requests, initial_credit),
+        |(mut inner, mut requests, mut credit)| async move {
+            // Wait for credit before starting the next partition.
+            let next = Pin::new(&mut inner).peek().await;
+            if matches!(
+                next,
+                Some(Ok(FlatIngesterQueryResponse::StartPartition { .. }))
+            ) {
+                while credit == 0 {
+                    let err = match requests.next().await {
+                        Some(Ok(msg)) => {
+                            match proto::IngesterQueryExchangeRequest::decode(&*msg.app_metadata) {
+                                Ok(v) => {
+                                    credit = v.partition_credit;
+                                    continue;
+                                }
+                                Err(e) => Error::InvalidExchangeRequest(e),
+                            }
+                        }
+                        Some(Err(e)) => Error::ExchangeStream(e),
+                        None => {
+                            debug!("exchange request stream closed, ending response");
+                            return None;
+                        }
+                    };
+                    return Some((Err(err), (inner, requests, credit)));
+                }
+                credit -= 1;
+            }
+
+            let next = inner.next().await?.map_err(Error::from);
+            Some((next, (inner, requests, credit)))
+        },
+    )
+}
+
 /// A stream of [`FlatIngesterQueryResponse`], itself a flattened version of
 /// [`QueryResponse`].
 type FlatIngesterQueryResponseStream =
@@ -347,15 +479,19 @@ impl From<QueryResponse> for FlatIngesterQueryResponseStream {
 #[pin_project]
 struct FlightFrameCodec {
     #[pin]
-    inner: Pin<Box<dyn Stream<Item = Result<FlatIngesterQueryResponse, ArrowError>> + Send>>,
+    inner: Pin<Box<dyn Stream<Item = Result<FlatIngesterQueryResponse, Error>> + Send>>,
     done: bool,
     buffer: Vec<FlightData>,
 }
 
 impl FlightFrameCodec {
-    fn new(inner: FlatIngesterQueryResponseStream) -> Self {
+    fn new<S, E>(inner: S) -> Self
+    where
+        S: Stream<Item = Result<FlatIngesterQueryResponse, E>> + Send + 'static,
+        E: Into<Error>,
+    {
         Self {
-            inner,
+            inner: inner.map(|v| v.map_err(Into::into)).boxed(),
             done: false,
             buffer: vec![],
         }
@@ -387,7 +523,7 @@ impl Stream for FlightFrameCodec {
                 }
                 Poll::Ready(Some(Err(e))) => {
                     *this.done = true;
-                    let e = Error::Stream(e).into();
+                    let e = e.into();
                     Poll::Ready(Some(Err(e)))
                 }
                 Poll::Ready(Some(Ok(FlatIngesterQueryResponse::StartPartition {
@@ -447,8 +583,9 @@ fn build_none_flight_msg() -> Vec<u8> {
 #[cfg(test)]
 mod tests {
     use arrow::{error::ArrowError, ipc::MessageHeader};
+    use assert_matches::assert_matches;
     use data_types::PartitionId;
-    use futures::StreamExt;
+    use futures::{FutureExt, StreamExt};
     use generated_types::influxdata::iox::ingester::v1::{self as proto};
     use mutable_batch_lp::test_helpers::lp_to_mutable_batch;
     use schema::Projection;
@@ -458,6 +595,34 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_not_ready_is_unavailable() {
+        let status = tonic::Status::from(QueryError::NotReady(crate::Readiness::ReplayFailed));
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(status.message(), "ingester not ready: WAL replay failed");
+    }
+
+    #[test]
+    fn test_invalid_predicate_is_invalid_argument() {
+        let status = tonic::Status::from(Error::InvalidPredicate(FieldViolation {
+            field: "exprs".to_string(),
+            description: "bananas".to_string(),
+        }));
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(
+            status.message(),
+            "invalid query predicate: Violation for field \"exprs\": bananas"
+        );
+    }
+
+    #[test]
+    fn test_memory_limit_is_resource_exhausted() {
+        let status = tonic::Status::from(Error::Stream(ArrowError::MemoryError(
+            "bananas".to_string(),
+        )));
+        assert_eq!(status.code(), Code::ResourceExhausted);
+    }
+
     #[tokio::test]
     async fn test_get_stream_empty() {
         assert_get_stream(vec![], vec![]).await;
@@ -566,6 +731,90 @@ mod tests {
         .await;
     }
 
+    fn start_partition(id: i64) -> Result<FlatIngesterQueryResponse, ArrowError> {
+        Ok(FlatIngesterQueryResponse::StartPartition {
+            partition_id: PartitionId::new(id),
+            status: PartitionStatus {
+                parquet_max_sequence_number: None,
+            },
+        })
+    }
+
+    fn credit(partition_credit: u64) -> Result<FlightData, tonic::Status> {
+        Ok(FlightData {
+            app_metadata: proto::IngesterQueryExchangeRequest {
+                query: None,
+                partition_credit,
+            }
+            .encode_to_vec(),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_partition_credit_gate() {
+        let schema = lp_to_mutable_batch("table z=1 0")
+            .1
+            .to_arrow(Projection::All)
+            .unwrap()
+            .schema();
+
+        let inner = Box::pin(futures::stream::iter(vec![
+            start_partition(1),
+            Ok(FlatIngesterQueryResponse::StartSnapshot { schema }),
+            start_partition(2),
+            start_partition(3),
+            start_partition(4),
+        ]));
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        let mut gate = Box::pin(partition_credit_gate(inner, rx, 1));
+
+        // The first partition is streamed using the initial credit.
+        assert_matches!(
+            gate.next().await,
+            Some(Ok(FlatIngesterQueryResponse::StartPartition { partition_id, .. })) => {
+                assert_eq!(partition_id, PartitionId::new(1));
+            }
+        );
+        assert_matches!(
+            gate.next().await,
+            Some(Ok(FlatIngesterQueryResponse::StartSnapshot { .. }))
+        );
+
+        // The next partition is not started until credit is granted.
+        assert!(gate.next().now_or_never().is_none());
+        tx.unbounded_send(credit(2)).unwrap();
+        for want in [2, 3] {
+            assert_matches!(
+                gate.next().await,
+                Some(Ok(FlatIngesterQueryResponse::StartPartition { partition_id, .. })) => {
+                    assert_eq!(partition_id, PartitionId::new(want));
+                }
+            );
+        }
+
+        // Closing the request stream with no credit remaining ends the
+        // response.
+        drop(tx);
+        assert!(gate.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_partition_credit_gate_invalid_request() {
+        let inner = Box::pin(futures::stream::iter(vec![start_partition(1)]));
+        let requests = futures::stream::iter(vec![Ok(FlightData {
+            app_metadata: vec![0xff],
+            ..Default::default()
+        })]);
+
+        let got = FlightFrameCodec::new(partition_credit_gate(inner, requests, 0))
+            .collect::<Vec<_>>()
+            .await;
+        assert_matches!(got.as_slice(), [Err(e)] => {
+            assert_eq!(e.code(), Code::InvalidArgument);
+        });
+    }
+
     struct DecodedFlightData {
         header_type: MessageHeader,
         app_metadata: proto::IngesterQueryResponseMetadata,
@@ -603,8 +852,13 @@ mod tests {
 
     #[tokio::test]
     async fn limits_concurrent_queries() {
-        let mut flight =
-            FlightService::new(MockQueryExec::default(), 100, &metric::Registry::default());
+        let metrics = metric::Registry::default();
+        let flight = FlightService::new(
+            MockQueryExec::default(),
+            QueryConcurrency::Fixed(100),
+            Arc::new(IngestState::new(&metrics)),
+            &metrics,
+        );
 
         let req = tonic::Request::new(Ticket { ticket: vec![] });
         match flight.do_get(req).await {
@@ -614,7 +868,12 @@ mod tests {
             }
         }
 
-        flight.request_sem = Semaphore::new(0);
+        let flight = FlightService::new(
+            MockQueryExec::default(),
+            QueryConcurrency::Fixed(0),
+            Arc::new(IngestState::new(&metrics)),
+            &metrics,
+        );
 
         let req = tonic::Request::new(Ticket { ticket: vec![] });
         match flight.do_get(req).await {
@@ -9,7 +9,11 @@ use mutable_batch::writer;
 use mutable_batch_pb::decode::decode_database_batch;
 use observability_deps::tracing::*;
 use thiserror::Error;
-use tonic::{Request, Response};
+use tonic::{metadata::MetadataValue, Request, Response};
+use trace::{
+    ctx::SpanContext,
+    span::{SpanExt, SpanRecorder},
+};
 
 use crate::{
     dml_sink::{DmlError, DmlSink},
@@ -54,7 +58,53 @@ impl From<DmlError> for tonic::Status {
     fn from(e: DmlError) -> Self {
         match e {
             DmlError::Buffer(e) => map_write_error(e),
+            // The WAL has reached its size limit - the client should retry
+            // once persisted data has been removed from the WAL.
+            DmlError::Wal(wal::Error::WalFull { .. }) => Self::resource_exhausted(e.to_string()),
             DmlError::Wal(_) => Self::internal(e.to_string()),
+            // The ingester cannot currently make durable progress on writes -
+            // the client should back off and retry, and the reason is
+            // returned in the "overloaded-reason" metadata.
+            DmlError::Overloaded(reason) => {
+                let mut status = Self::resource_exhausted(e.to_string());
+                status.metadata_mut().insert(
+                    "overloaded-reason",
+                    MetadataValue::from_static(reason.as_str()),
+                );
+                status
+            }
+            // The client should back off for the duration specified in the
+            // "retry-after" metadata, in whole seconds.
+            DmlError::RateLimited { retry_after, .. } => {
+                let mut status = Self::resource_exhausted(e.to_string());
+                let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                status.metadata_mut().insert(
+                    "retry-after",
+                    secs.to_string()
+                        .parse()
+                        .expect("integer is valid metadata value"),
+                );
+                status
+            }
+            // As with a type mismatch in the buffer, a schema conflict should
+            // have been rejected by schema validation in the router, and as
+            // such it is an internal system failure.
+            DmlError::SchemaConflict { .. } => Self::internal(e.to_string()),
+            // The client should retry against another ingester, or once this
+            // ingester has replayed its WAL.
+            DmlError::NotReady(_) => Self::unavailable(e.to_string()),
+            // The client should retry against another (or a restarted)
+            // ingester.
+            DmlError::ShuttingDown => Self::unavailable(e.to_string()),
+            // The write is applied locally, but not held by enough peers -
+            // the client should retry it.
+            DmlError::Replication { .. } => Self::unavailable(e.to_string()),
+            // The client should retry once the catalog recovers, or against
+            // another ingester.
+            DmlError::CatalogUnavailable => Self::unavailable(e.to_string()),
+            // The router should stop routing writes to the deleted namespace
+            // once its namespace cache is refreshed.
+            DmlError::NamespaceDeleted(_) => Self::not_found(e.to_string()),
         }
     }
 }
@@ -86,6 +136,10 @@ fn map_write_error(e: mutable_batch::Error) -> tonic::Status {
     }
 }
 
+/// The gRPC metadata key carrying the client-supplied idempotency key of a
+/// write.
+const IDEMPOTENCY_KEY_HEADER: &str = "iox-idempotency-key";
+
 /// A gRPC [`WriteService`] handler.
 ///
 /// This handler accepts writes from an upstream, and applies them to the
@@ -120,6 +174,17 @@ where
             .map(|v| v.to_string())
             .unwrap_or_else(|| "<unknown>".to_string());
 
+        // Extract the tracing context propagated by the client, if any.
+        let span_ctx: Option<SpanContext> = request.extensions().get().cloned();
+        let mut span_recorder = SpanRecorder::new(span_ctx.child_span("ingester write"));
+
+        // Extract the idempotency key of this write, if any.
+        let idempotency_key = request
+            .metadata()
+            .get(IDEMPOTENCY_KEY_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(ToString::to_string);
+
         // Extract the write payload
         let payload = request.into_inner().payload.ok_or(RpcError::NoPayload)?;
 
@@ -142,6 +207,22 @@ where
             "received rpc write"
         );
 
+        let sequence_number = self.timestamp.next();
+        let mut meta = DmlMeta::sequenced(
+            Sequence {
+                shard_index: TRANSITION_SHARD_INDEX, // TODO: remove this from DmlMeta
+                sequence_number,
+            },
+            iox_time::Time::MAX, // TODO: remove this from DmlMeta
+            // Spans emitted by the write path are children of this request's
+            // span.
+            span_recorder.span().map(|s| s.ctx.clone()),
+            42, // TODO: remove this from DmlMeta
+        );
+        if let Some(key) = idempotency_key {
+            meta = meta.with_idempotency_key(key);
+        }
+
         // Reconstruct the DML operation
         let op = DmlWrite::new(
             namespace_id,
@@ -150,18 +231,7 @@ where
                 .map(|(k, v)| (TableId::new(k), v))
                 .collect(),
             partition_key,
-            DmlMeta::sequenced(
-                Sequence {
-                    shard_index: TRANSITION_SHARD_INDEX, // TODO: remove this from DmlMeta
-                    sequence_number: self.timestamp.next(),
-                },
-                iox_time::Time::MAX, // TODO: remove this from DmlMeta
-                // The tracing context should be propagated over the RPC boundary.
-                //
-                // See https://github.com/influxdata/influxdb_iox/issues/6177
-                None,
-                42, // TODO: remove this from DmlMeta
-            ),
+            meta,
         );
 
         // Apply the DML op to the in-memory buffer.
@@ -169,17 +239,21 @@ where
             Ok(()) => {}
             Err(e) => {
                 error!(error=%e, "failed to apply DML op");
+                span_recorder.error(e.to_string());
                 return Err(e.into())?;
             }
         }
 
-        Ok(Response::new(proto::WriteResponse {}))
+        span_recorder.ok("write applied");
+        Ok(Response::new(proto::WriteResponse {
+            sequence_number: sequence_number.get() as u64,
+        }))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
+    use std::{sync::Arc, time::Duration};
 
     use assert_matches::assert_matches;
     use generated_types::influxdata::pbdata::v1::{
@@ -188,7 +262,10 @@ mod tests {
     };
 
     use super::*;
-    use crate::dml_sink::mock_sink::MockDmlSink;
+    use data_types::ColumnType;
+    use trace::{RingBufferTraceCollector, TraceCollector};
+
+    use crate::{dml_sink::mock_sink::MockDmlSink, ingest_state::IngestStateError};
 
     const NAMESPACE_ID: NamespaceId = NamespaceId::new(42);
     const PARTITION_KEY: &str = "bananas";
@@ -347,15 +424,17 @@ mod tests {
             }),
         };
 
-        handler
+        let r1 = handler
             .write(Request::new(req.clone()))
             .await
-            .expect("write should succeed");
+            .expect("write should succeed")
+            .into_inner();
 
-        handler
+        let r2 = handler
             .write(Request::new(req))
             .await
-            .expect("write should succeed");
+            .expect("write should succeed")
+            .into_inner();
 
         assert_matches!(
             *mock.get_calls(),
@@ -363,7 +442,209 @@ mod tests {
                 let w1 = w1.meta().sequence().unwrap().sequence_number.get();
                 let w2 = w2.meta().sequence().unwrap().sequence_number.get();
                 assert!(w1 < w2);
+
+                // The assigned sequence numbers are returned to the client.
+                assert_eq!(r1.sequence_number, w1 as u64);
+                assert_eq!(r2.sequence_number, w2 as u64);
             }
         );
     }
+
+    /// Assert the client-supplied idempotency key is propagated to the op.
+    #[tokio::test]
+    async fn test_rpc_write_idempotency_key() {
+        let mock = Arc::new(MockDmlSink::default().with_apply_return(vec![Ok(()), Ok(())]));
+        let timestamp = Arc::new(TimestampOracle::new(0));
+        let handler = RpcWrite::new(Arc::clone(&mock), timestamp);
+
+        let req = proto::WriteRequest {
+            payload: Some(DatabaseBatch {
+                database_id: NAMESPACE_ID.get(),
+                partition_key: PARTITION_KEY.to_string(),
+                table_batches: vec![TableBatch {
+                    table_id: 42,
+                    columns: vec![Column {
+                        column_name: "time".to_string(),
+                        semantic_type: SemanticType::Time.into(),
+                        values: Some(Values {
+                            i64_values: vec![4242],
+                            f64_values: vec![],
+                            u64_values: vec![],
+                            string_values: vec![],
+                            bool_values: vec![],
+                            bytes_values: vec![],
+                            packed_string_values: None,
+                            interned_string_values: None,
+                        }),
+                        null_mask: vec![0],
+                    }],
+                    row_count: 1,
+                }],
+            }),
+        };
+
+        let mut with_key = Request::new(req.clone());
+        with_key
+            .metadata_mut()
+            .insert(IDEMPOTENCY_KEY_HEADER, "bananas".parse().unwrap());
+        handler.write(with_key).await.expect("write should succeed");
+
+        handler
+            .write(Request::new(req))
+            .await
+            .expect("write should succeed");
+
+        assert_matches!(
+            *mock.get_calls(),
+            [DmlOperation::Write(ref w1), DmlOperation::Write(ref w2)] => {
+                assert_eq!(w1.meta().idempotency_key(), Some("bananas"));
+                assert_eq!(w2.meta().idempotency_key(), None);
+            }
+        );
+    }
+
+    /// Assert the client's tracing context is propagated to the op, as the
+    /// parent of the span covering the write.
+    #[tokio::test]
+    async fn test_rpc_write_span_context() {
+        let mock = Arc::new(MockDmlSink::default().with_apply_return(vec![Ok(())]));
+        let timestamp = Arc::new(TimestampOracle::new(0));
+        let handler = RpcWrite::new(Arc::clone(&mock), timestamp);
+
+        let traces: Arc<dyn TraceCollector> = Arc::new(RingBufferTraceCollector::new(5));
+        let span_ctx = SpanContext::new(Arc::clone(&traces));
+
+        let mut req = Request::new(proto::WriteRequest {
+            payload: Some(DatabaseBatch {
+                database_id: NAMESPACE_ID.get(),
+                partition_key: PARTITION_KEY.to_string(),
+                table_batches: vec![TableBatch {
+                    table_id: 42,
+                    columns: vec![Column {
+                        column_name: "time".to_string(),
+                        semantic_type: SemanticType::Time.into(),
+                        values: Some(Values {
+                            i64_values: vec![4242],
+                            f64_values: vec![],
+                            u64_values: vec![],
+                            string_values: vec![],
+                            bool_values: vec![],
+                            bytes_values: vec![],
+                            packed_string_values: None,
+                            interned_string_values: None,
+                        }),
+                        null_mask: vec![0],
+                    }],
+                    row_count: 1,
+                }],
+            }),
+        });
+        req.extensions_mut().insert(span_ctx.clone());
+        handler.write(req).await.expect("write should succeed");
+
+        let spans = traces
+            .as_any()
+            .downcast_ref::<RingBufferTraceCollector>()
+            .expect("unexpected collector impl")
+            .spans();
+        let span = assert_matches!(&*spans, [s] => s, "expected 1 span");
+        assert_eq!(span.name, "ingester write");
+        assert_eq!(span.ctx.parent_span_id, Some(span_ctx.span_id));
+
+        assert_matches!(
+            *mock.get_calls(),
+            [DmlOperation::Write(ref w)] => {
+                let got = w.meta().span_context().expect("op should be traced");
+                assert_eq!(got.span_id, span.ctx.span_id);
+            }
+        );
+    }
+
+    #[test]
+    fn test_wal_full_is_resource_exhausted() {
+        let status = tonic::Status::from(DmlError::Wal(wal::Error::WalFull {
+            current_bytes: 42,
+            max_bytes: 42,
+        }));
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+
+        let status = tonic::Status::from(DmlError::Wal(wal::Error::TailLagged { skipped: 1 }));
+        assert_eq!(status.code(), tonic::Code::Internal);
+    }
+
+    #[test]
+    fn test_rate_limited_is_resource_exhausted() {
+        let status = tonic::Status::from(DmlError::RateLimited {
+            namespace_id: NamespaceId::new(42),
+            retry_after: Duration::from_millis(1500),
+        });
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+        assert_eq!(status.metadata().get("retry-after").unwrap(), "2");
+    }
+
+    #[test]
+    fn test_schema_conflict_is_internal() {
+        let status = tonic::Status::from(DmlError::SchemaConflict {
+            table_id: TableId::new(42),
+            column: "v".to_string(),
+            existing: ColumnType::I64,
+            got: ColumnType::F64,
+        });
+        assert_eq!(status.code(), tonic::Code::Internal);
+        assert_eq!(
+            status.message(),
+            "column v in table 42 is of type i64, but the write contains type f64"
+        );
+    }
+
+    #[test]
+    fn test_not_ready_is_unavailable() {
+        let status = tonic::Status::from(DmlError::NotReady(crate::Readiness::ReplayingWal {
+            segments_replayed: 1,
+            segments_total: 3,
+            ops_applied: 42,
+        }));
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+        assert_eq!(
+            status.message(),
+            "ingester not ready: replaying WAL (1 of 3 segments, 42 ops applied)"
+        );
+    }
+
+    #[test]
+    fn test_replication_is_unavailable() {
+        let status = tonic::Status::from(DmlError::Replication {
+            acked: 1,
+            required: 2,
+        });
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+        assert_eq!(
+            status.message(),
+            "op replicated to 1 peers, but 2 are required"
+        );
+    }
+
+    #[test]
+    fn test_catalog_unavailable_is_unavailable() {
+        let status = tonic::Status::from(DmlError::CatalogUnavailable);
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+        assert_eq!(status.message(), "catalog unavailable");
+    }
+
+    #[test]
+    fn test_namespace_deleted_is_not_found() {
+        let status = tonic::Status::from(DmlError::NamespaceDeleted(NamespaceId::new(42)));
+        assert_eq!(status.code(), tonic::Code::NotFound);
+        assert_eq!(status.message(), "namespace 42 has been deleted");
+    }
+
+    #[test]
+    fn test_overloaded_is_resource_exhausted() {
+        let status = tonic::Status::from(DmlError::Overloaded(IngestStateError::PersistSaturated));
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+        assert_eq!(
+            status.metadata().get("overloaded-reason").unwrap(),
+            "persist_saturated"
+        );
+    }
 }
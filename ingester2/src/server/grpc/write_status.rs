@@ -0,0 +1,190 @@
+use std::sync::Arc;
+
+use generated_types::influxdata::iox::ingester::v1::{
+    self as proto, write_status_service_server::WriteStatusService,
+};
+use tonic::{Request, Response};
+
+use crate::{
+    buffer_tree::BufferTree, dml_sink::SequenceTracker, timestamp_oracle::TimestampOracle,
+};
+
+/// A gRPC [`WriteStatusService`] handler, reporting the progress of the
+/// writes accepted by the ingester through the write path.
+///
+/// A write is identified by the sequence number assigned to it, and is:
+///
+///   * Durable: while it is being applied, once committed to the WAL.
+///   * Buffered: once applied, while any data with a sequence number no
+///     greater than it remains unpersisted.
+///   * Persisted: once all the data with a sequence number no greater than it
+///     has been persisted.
+///
+/// A write that is rejected after being committed to the WAL completes
+/// without being buffered, and is misreported as buffered or persisted - only
+/// the status of the writes acknowledged to the client is meaningful.
+#[derive(Debug)]
+pub(crate) struct WriteStatus {
+    timestamp: Arc<TimestampOracle>,
+    tracker: Arc<SequenceTracker>,
+    buffer: Arc<BufferTree>,
+}
+
+impl WriteStatus {
+    /// Instantiate a new [`WriteStatus`] reporting the progress of the ops
+    /// assigned sequence numbers by `timestamp`, tracked by `tracker`, and
+    /// applied to `buffer`.
+    pub(crate) fn new(
+        timestamp: Arc<TimestampOracle>,
+        tracker: Arc<SequenceTracker>,
+        buffer: Arc<BufferTree>,
+    ) -> Self {
+        Self {
+            timestamp,
+            tracker,
+            buffer,
+        }
+    }
+
+    fn status(&self, n: u64) -> proto::WriteStatus {
+        if n == 0 || n > self.timestamp.last_value() {
+            return proto::WriteStatus::Unknown;
+        }
+
+        // The op stops being durable as it completes, so the durable check
+        // MUST come first to observe this transition without a gap.
+        if self.tracker.is_durable(n) {
+            return proto::WriteStatus::Durable;
+        }
+        if !self.tracker.is_completed(n) {
+            return proto::WriteStatus::Unknown;
+        }
+
+        // Any data from the op is in a partition that existed when it
+        // completed, and remains unpersisted (buffered or persisting) until
+        // persisted.
+        match self.buffer.min_unpersisted_sequence_number() {
+            Some(v) if v.get() as u64 <= n => proto::WriteStatus::Buffered,
+            _ => proto::WriteStatus::Persisted,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl WriteStatusService for WriteStatus {
+    /// Report the status of the write assigned the requested sequence number.
+    async fn get_write_status(
+        &self,
+        request: Request<proto::GetWriteStatusRequest>,
+    ) -> Result<Response<proto::GetWriteStatusResponse>, tonic::Status> {
+        let status = self.status(request.into_inner().sequence_number);
+
+        Ok(Response::new(proto::GetWriteStatusResponse {
+            status: status as i32,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use data_types::{NamespaceId, PartitionId, PartitionKey, TableId};
+    use dml::DmlOperation;
+
+    use super::*;
+    use crate::{
+        buffer_tree::{
+            namespace::name_resolver::mock::MockNamespaceNameProvider,
+            partition::{resolver::mock::MockPartitionProvider, PartitionData, SortKeyState},
+            table::name_resolver::mock::MockTableNameProvider,
+        },
+        deferred_load::DeferredLoad,
+        dml_sink::DmlSink,
+        test_util::make_write_op,
+    };
+
+    const NAMESPACE_ID: NamespaceId = NamespaceId::new(42);
+    const TABLE_ID: TableId = TableId::new(44);
+
+    async fn get_status(handler: &WriteStatus, sequence_number: u64) -> proto::WriteStatus {
+        let got = handler
+            .get_write_status(Request::new(proto::GetWriteStatusRequest {
+                sequence_number,
+            }))
+            .await
+            .expect("rpc call should succeed")
+            .into_inner();
+        proto::WriteStatus::from_i32(got.status).expect("invalid status")
+    }
+
+    #[tokio::test]
+    async fn test_get_write_status() {
+        let buffer = Arc::new(BufferTree::new(
+            Arc::new(MockNamespaceNameProvider::default()),
+            Arc::new(MockTableNameProvider::new("bananas")),
+            Arc::new(
+                MockPartitionProvider::default().with_partition(PartitionData::new(
+                    PartitionId::new(1),
+                    PartitionKey::from("p1"),
+                    NAMESPACE_ID,
+                    Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                        "platanos".into()
+                    })),
+                    TABLE_ID,
+                    Arc::new(DeferredLoad::new(Duration::from_secs(1), async {
+                        "bananas".into()
+                    })),
+                    SortKeyState::Provided(None),
+                )),
+            ),
+            Default::default(),
+        ));
+        let timestamp = Arc::new(TimestampOracle::new(0));
+        let tracker = Arc::new(SequenceTracker::default());
+        let handler = WriteStatus::new(
+            Arc::clone(&timestamp),
+            Arc::clone(&tracker),
+            Arc::clone(&buffer),
+        );
+
+        // A sequence number not yet assigned is unknown.
+        assert_eq!(get_status(&handler, 1).await, proto::WriteStatus::Unknown);
+
+        let n1 = timestamp.next();
+        let n2 = timestamp.next();
+        assert_eq!(get_status(&handler, 1).await, proto::WriteStatus::Unknown);
+
+        // Once committed to the WAL, the write is durable.
+        tracker.mark_durable(1);
+        assert_eq!(get_status(&handler, 1).await, proto::WriteStatus::Durable);
+
+        // And once applied to the buffer and completed, it is buffered.
+        buffer
+            .apply(DmlOperation::Write(make_write_op(
+                &PartitionKey::from("p1"),
+                NAMESPACE_ID,
+                "bananas",
+                TABLE_ID,
+                n1.get(),
+                "bananas,region=asia v=1i 10",
+            )))
+            .await
+            .expect("write should succeed");
+        tracker.observe(1);
+        assert_eq!(get_status(&handler, 1).await, proto::WriteStatus::Buffered);
+
+        // The write assigned the next sequence number has not completed.
+        assert_eq!(n2.get(), 2);
+        assert_eq!(get_status(&handler, 2).await, proto::WriteStatus::Unknown);
+
+        // The write remains buffered while it is persisting, and is persisted
+        // once the persist operation completes.
+        let partition = buffer.partitions().next().expect("partition must exist");
+        let data = partition.lock().mark_persisting().expect("must have data");
+        assert_eq!(get_status(&handler, 1).await, proto::WriteStatus::Buffered);
+
+        partition.lock().mark_persisted(data);
+        assert_eq!(get_status(&handler, 1).await, proto::WriteStatus::Persisted);
+    }
+}
@@ -1,9 +1,10 @@
 use std::collections::BTreeMap;
 
 use data_types::{
-    NamespaceId, PartitionKey, Sequence, SequenceNumber, ShardId, ShardIndex, TableId,
+    DeletePredicate, NamespaceId, NonEmptyString, PartitionKey, Sequence, SequenceNumber, ShardId,
+    ShardIndex, TableId, TimestampRange,
 };
-use dml::{DmlMeta, DmlWrite};
+use dml::{DmlDelete, DmlMeta, DmlWrite};
 use iox_catalog::interface::Catalog;
 use mutable_batch_lp::lines_to_batches;
 use schema::Projection;
@@ -55,6 +56,32 @@ pub(crate) fn make_write_op(
     )
 }
 
+/// Construct a [`DmlDelete`] of all data between timestamps 1 and 2 in
+/// `table_name` (or all tables, if [`None`]).
+pub(crate) fn make_delete_op(
+    namespace_id: NamespaceId,
+    table_name: Option<&str>,
+    sequence_number: i64,
+) -> DmlDelete {
+    DmlDelete::new(
+        namespace_id,
+        DeletePredicate {
+            range: TimestampRange::new(1, 2),
+            exprs: vec![],
+        },
+        table_name.and_then(|t| NonEmptyString::new(t)),
+        DmlMeta::sequenced(
+            Sequence {
+                shard_index: ShardIndex::new(i32::MAX),
+                sequence_number: SequenceNumber::new(sequence_number),
+            },
+            iox_time::Time::MIN,
+            None,
+            42,
+        ),
+    )
+}
+
 pub(crate) async fn populate_catalog(
     catalog: &dyn Catalog,
     shard_index: ShardIndex,
@@ -34,6 +34,20 @@ impl TimestampOracle {
 
         SequenceNumber::new(v as i64)
     }
+
+    /// Return the greatest value returned by [`TimestampOracle::next()`] so
+    /// far, or the initial / advanced `last_value` if greater.
+    pub(crate) fn last_value(&self) -> u64 {
+        self.0.load(Ordering::Relaxed) - 1
+    }
+
+    /// Ensure all subsequent [`SequenceNumber`] values are greater than
+    /// `last_value`.
+    ///
+    /// Has no effect if a greater value has already been returned.
+    pub(crate) fn advance(&self, last_value: u64) {
+        self.0.fetch_max(last_value + 1, Ordering::Relaxed);
+    }
 }
 
 #[cfg(test)]
@@ -55,6 +69,18 @@ mod tests {
         assert_eq!(oracle.next().get(), 42);
     }
 
+    #[test]
+    fn test_advance() {
+        let oracle = TimestampOracle::new(0);
+        oracle.advance(41);
+        assert_eq!(oracle.next().get(), 42);
+
+        // Advancing backwards has no effect.
+        oracle.advance(10);
+        assert_eq!(oracle.next().get(), 43);
+        assert_eq!(oracle.last_value(), 43);
+    }
+
     /// A property test ensuring that for N threads competing to sequence M
     /// operations, a total order of operations is derived from consecutive
     /// timestamps returned by a single [`TimestampOracle`] instance.
@@ -5,5 +5,6 @@
 //! [`DmlOperation`]: dml::DmlOperation
 
 pub(crate) mod rotate_task;
+pub(crate) mod sequence_checkpoint;
 mod traits;
 pub(crate) mod wal_sink;
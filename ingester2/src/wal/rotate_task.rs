@@ -1,28 +1,146 @@
 use futures::{stream, StreamExt};
 use observability_deps::tracing::*;
 use std::{future, sync::Arc, time::Duration};
+use tokio::sync::oneshot;
+use wal::EvictionPolicy;
 
-use crate::{buffer_tree::BufferTree, persist::handle::PersistHandle};
+use crate::{
+    buffer_tree::BufferTree,
+    ingest_state::{IngestState, IngestStateError},
+    persist::handle::PersistHandle,
+};
 
 /// [`PERSIST_ENQUEUE_CONCURRENCY`] defines the parallelism used when acquiring
 /// partition locks and marking the partition as persisting.
 const PERSIST_ENQUEUE_CONCURRENCY: usize = 10;
 
+/// How often the WAL disk usage is checked against the eviction policy.
+const DISK_PRESSURE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often in-flight persist operations started outside of a rotation are
+/// checked for completion.
+const OUTSTANDING_PERSIST_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long to wait before retrying a failed rotation when shutting down.
+const SHUTDOWN_ROTATION_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the persist backlog is checked while a rotation is delayed.
+const BACKLOG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Delays the periodic WAL rotation while the persist backlog is too large.
+///
+/// Each rotation enqueues the persistence of all buffered data - rotating
+/// while the persist workers are already behind only grows the backlog (and
+/// the number of small files it produces), so the rotation is delayed until
+/// the backlog drains instead.
+#[derive(Debug, Clone)]
+pub(crate) struct RotationBacklogLimit {
+    /// The number of outstanding persist jobs above which the rotation is
+    /// delayed.
+    pub(crate) max_outstanding_jobs: usize,
+
+    /// If set, writes are rejected with
+    /// [`IngestStateError::PersistBacklog`] while the rotation is delayed.
+    pub(crate) backpressure: Option<Arc<IngestState>>,
+}
+
 /// Rotate the `wal` segment file every `period` duration of time.
+///
+/// If an `eviction` policy is given, the WAL is also rotated (forcing the
+/// persistence of all buffered data) as soon as the policy reports it to be
+/// under disk pressure, and once persisted, the oldest closed segments are
+/// evicted until the pressure is relieved.
+///
+/// If a `backlog_limit` is given, a periodic rotation is delayed while the
+/// number of outstanding persist jobs exceeds it, after which the rotation
+/// period starts over. Rotations forced by disk pressure or shutdown are never
+/// delayed.
+///
+/// Once a value is sent over `shutdown`, a final rotation persists all
+/// buffered data and drops the persisted segment, after which this function
+/// returns. The caller MUST stop writes to the WAL before signalling shutdown,
+/// or writes that land after the final rotation will require a replay.
 pub(crate) async fn periodic_rotation(
     wal: wal::Wal,
     period: Duration,
     buffer: Arc<BufferTree>,
     persist: PersistHandle,
+    eviction: Option<Arc<dyn EvictionPolicy>>,
+    backlog_limit: Option<RotationBacklogLimit>,
+    mut shutdown: oneshot::Receiver<()>,
 ) {
     let handle = wal.rotation_handle();
     let mut interval = tokio::time::interval(period);
+    let mut pressure_check = tokio::time::interval(DISK_PRESSURE_CHECK_INTERVAL);
+    let mut shutting_down = false;
 
     loop {
-        interval.tick().await;
-        info!("rotating wal file");
+        if shutting_down {
+            // The final rotation failed - retry it without waiting for the
+            // next rotation period.
+            tokio::time::sleep(SHUTDOWN_ROTATION_RETRY_INTERVAL).await;
+        } else {
+            let mut periodic = false;
+            tokio::select! {
+                _ = interval.tick() => {
+                    periodic = true;
+                }
+                res = &mut shutdown => {
+                    // The sender is only dropped without sending when the
+                    // ingester is dropped, aborting this task.
+                    if res.is_err() {
+                        return;
+                    }
+                    info!("shutdown requested, persisting all buffered data");
+                    shutting_down = true;
+                }
+                _ = pressure_check.tick(), if eviction.is_some() => {
+                    let disk_usage_bytes = wal.disk_usage_bytes();
+                    match &eviction {
+                        Some(policy) if policy.under_pressure(disk_usage_bytes) => {}
+                        _ => continue,
+                    }
+                    warn!(
+                        disk_usage_bytes,
+                        "wal under disk pressure, forcing rotation and persistence"
+                    );
+                    // Start the periodic rotation interval over
+                    interval.reset();
+                }
+            }
 
-        let stats = handle.rotate().await.expect("failed to rotate WAL");
+            if periodic {
+                if let Some(limit) = &backlog_limit {
+                    match wait_for_backlog(&persist, limit, &mut shutdown).await {
+                        BacklogWait::Drained { delayed: false } => {}
+                        BacklogWait::Drained { delayed: true } => {
+                            // Start the periodic rotation interval over,
+                            // rather than rotating for each missed tick.
+                            interval.reset();
+                        }
+                        BacklogWait::Shutdown => {
+                            info!("shutdown requested, persisting all buffered data");
+                            shutting_down = true;
+                        }
+                        BacklogWait::Aborted => return,
+                    }
+                }
+                if !shutting_down {
+                    info!("rotating wal file");
+                }
+            }
+        }
+
+        // A failed rotation (such as when there is no disk space to preallocate
+        // the next segment) leaves the current segment open for writes, so
+        // retry at the next tick rather than crashing.
+        let stats = match handle.rotate().await {
+            Ok(v) => v,
+            Err(e) => {
+                error!(error=%e, "failed to rotate wal");
+                continue;
+            }
+        };
         debug!(
             closed_id = %stats.id(),
             segment_bytes = stats.size(),
@@ -70,7 +188,13 @@ pub(crate) async fn periodic_rotation(
         //
         // TODO: this properly as described above.
 
-        tokio::time::sleep(Duration::from_secs(5)).await;
+        //
+        // When shutting down, writes are stopped (and in-flight writes have
+        // completed) before the final rotation, so there is nothing to wait
+        // for.
+        if !shutting_down {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        }
 
         // Drain the BufferTree of partition data and persist each one.
         //
@@ -87,6 +211,25 @@ pub(crate) async fn periodic_rotation(
         // is rotated, all outstanding writes + queries complete, and all then
         // partitions are marked as persisting.
 
+        // Persist operations may already be in-flight for partitions (such as
+        // hot partitions persisted early) which have no further data to
+        // persist in this rotation. Their data may be in the rotated segment,
+        // so record them in order to wait for their completion below.
+        let outstanding = buffer
+            .partitions()
+            .filter_map(|p| {
+                let started = {
+                    let guard = p.lock();
+                    let started = guard.started_persistence_count();
+                    if guard.completed_persistence_count() >= started {
+                        return None;
+                    }
+                    started
+                };
+                Some((p, started))
+            })
+            .collect::<Vec<_>>();
+
         let notifications = stream::iter(buffer.partitions())
             .filter_map(|p| {
                 async move {
@@ -121,25 +264,122 @@ pub(crate) async fn periodic_rotation(
         );
 
         // Wait for all the persist completion notifications.
+        //
+        // A quarantined persist job is not complete until it is re-enqueued
+        // and succeeds, retaining this segment (and blocking further
+        // rotations) until then.
         for n in notifications {
             n.notified().await;
         }
 
+        // And for the persist operations that were already in-flight.
+        for (p, started) in outstanding {
+            loop {
+                let complete = p.lock().completed_persistence_count() >= started;
+                if complete {
+                    break;
+                }
+                tokio::time::sleep(OUTSTANDING_PERSIST_POLL_INTERVAL).await;
+            }
+        }
+
         debug!(
             closed_id = %stats.id(),
             "partitions persisted"
         );
 
-        handle
-            .delete(stats.id())
-            .await
-            .expect("failed to drop wal segment");
+        // A segment that fails to be dropped here is left on disk, where it
+        // can be evicted later under disk pressure.
+        match handle.delete(stats.id()).await {
+            Ok(()) => info!(
+                closed_id = %stats.id(),
+                "dropped persisted wal segment"
+            ),
+            Err(e) => error!(
+                error=%e,
+                closed_id = %stats.id(),
+                "failed to drop persisted wal segment"
+            ),
+        }
 
-        info!(
-            closed_id = %stats.id(),
-            "dropped persisted wal segment"
-        );
+        // All data buffered before the rotation is now persisted, so the
+        // contents of every segment up to the one just closed are too.
+        if let Some(eviction) = &eviction {
+            match wal.evict_oldest(eviction.as_ref(), stats.id()).await {
+                Ok(evicted) if !evicted.is_empty() => warn!(
+                    ?evicted,
+                    disk_usage_bytes = wal.disk_usage_bytes(),
+                    "evicted persisted wal segments under disk pressure"
+                ),
+                Ok(_) => {}
+                Err(e) => error!(error=%e, "failed to evict wal segments"),
+            }
+        }
+
+        if shutting_down {
+            info!(
+                closed_id = %stats.id(),
+                "persisted all buffered data for shutdown"
+            );
+            return;
+        }
+    }
+}
+
+/// The outcome of [`wait_for_backlog()`].
+#[derive(Debug)]
+enum BacklogWait {
+    /// The persist backlog is within the limit, after delaying the rotation
+    /// if `delayed` is true.
+    Drained { delayed: bool },
+    /// Shutdown was requested while waiting.
+    Shutdown,
+    /// The shutdown sender was dropped, aborting the rotation task.
+    Aborted,
+}
+
+/// Wait until the number of outstanding persist jobs of `persist` is within
+/// `limit`, or shutdown is requested.
+async fn wait_for_backlog(
+    persist: &PersistHandle,
+    limit: &RotationBacklogLimit,
+    shutdown: &mut oneshot::Receiver<()>,
+) -> BacklogWait {
+    let outstanding = persist.stats().outstanding();
+    if outstanding <= limit.max_outstanding_jobs {
+        return BacklogWait::Drained { delayed: false };
+    }
+
+    warn!(
+        outstanding_jobs = outstanding,
+        max_outstanding_jobs = limit.max_outstanding_jobs,
+        "persist backlog too large, delaying wal rotation"
+    );
+    if let Some(state) = &limit.backpressure {
+        state.set(IngestStateError::PersistBacklog);
+    }
+
+    let res = loop {
+        tokio::select! {
+            _ = tokio::time::sleep(BACKLOG_POLL_INTERVAL) => {}
+            res = &mut *shutdown => {
+                break match res {
+                    Ok(()) => BacklogWait::Shutdown,
+                    Err(_) => BacklogWait::Aborted,
+                };
+            }
+        }
+
+        if persist.stats().outstanding() <= limit.max_outstanding_jobs {
+            info!("persist backlog drained, resuming wal rotation");
+            break BacklogWait::Drained { delayed: true };
+        }
+    };
+
+    if let Some(state) = &limit.backpressure {
+        state.unset(IngestStateError::PersistBacklog);
     }
+    res
 }
 
 // TODO(test): rotate task
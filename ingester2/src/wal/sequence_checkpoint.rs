@@ -0,0 +1,156 @@
+//! Checkpointing of the [`TimestampOracle`] high-water mark, keeping
+//! [`SequenceNumber`] values strictly monotonic across restarts.
+//!
+//! The sequence numbers of new writes continue from the highest sequence
+//! number found in the WAL when it is replayed, but once a WAL segment is
+//! deleted, the sequence numbers within it are no longer observed at startup.
+//! The checkpoint records the greatest value handed out by the
+//! [`TimestampOracle`] so it is never reused.
+//!
+//! [`SequenceNumber`]: data_types::SequenceNumber
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use observability_deps::tracing::*;
+
+use crate::timestamp_oracle::TimestampOracle;
+
+/// The name of the checkpoint file, kept within the WAL directory.
+const CHECKPOINT_FILE_NAME: &str = "sequence_number.checkpoint";
+
+/// The name of the file a new checkpoint is written to, before being renamed
+/// over [`CHECKPOINT_FILE_NAME`].
+const CHECKPOINT_TMP_FILE_NAME: &str = "sequence_number.checkpoint.tmp";
+
+/// How often the high-water mark of the [`TimestampOracle`] is checkpointed.
+///
+/// A WAL segment is deleted no sooner than 5 seconds after it is rotated (see
+/// [`periodic_rotation()`]), so checkpointing more frequently ensures every
+/// sequence number in a deleted segment is covered by a checkpoint.
+///
+/// [`periodic_rotation()`]: super::rotate_task::periodic_rotation
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Read the checkpointed high-water mark from the WAL directory `dir`,
+/// returning [`None`] if no checkpoint has been written.
+pub(crate) async fn read_checkpoint(dir: &Path) -> Result<Option<u64>, io::Error> {
+    let path = dir.join(CHECKPOINT_FILE_NAME);
+    tokio::task::spawn_blocking(move || match std::fs::read_to_string(&path) {
+        Ok(v) => v
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    })
+    .await
+    .expect("checkpoint read task panicked")
+}
+
+/// Durably record `value` as the high-water mark in the WAL directory `dir`.
+///
+/// The checkpoint is written to a temporary file and renamed over the
+/// previous checkpoint, so a crash never leaves a partially written
+/// checkpoint behind.
+pub(crate) async fn write_checkpoint(dir: &Path, value: u64) -> Result<(), io::Error> {
+    let dir = dir.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let tmp = dir.join(CHECKPOINT_TMP_FILE_NAME);
+        std::fs::write(&tmp, value.to_string())?;
+        std::fs::File::open(&tmp)?.sync_all()?;
+        std::fs::rename(&tmp, dir.join(CHECKPOINT_FILE_NAME))?;
+        std::fs::File::open(&dir)?.sync_all()
+    })
+    .await
+    .expect("checkpoint write task panicked")
+}
+
+/// Periodically checkpoint the high-water mark of `oracle` into the WAL
+/// directory `dir`, whenever it has advanced.
+///
+/// A failed checkpoint is logged and retried at the next tick.
+pub(crate) async fn periodic_checkpoint(dir: PathBuf, oracle: Arc<TimestampOracle>) {
+    let mut interval = tokio::time::interval(CHECKPOINT_INTERVAL);
+    let mut last_written = None;
+
+    loop {
+        interval.tick().await;
+
+        let value = oracle.last_value();
+        if last_written == Some(value) {
+            continue;
+        }
+
+        match write_checkpoint(&dir, value).await {
+            Ok(()) => {
+                trace!(value, "checkpointed sequence number");
+                last_written = Some(value);
+            }
+            Err(e) => error!(error=%e, value, "failed to checkpoint sequence number"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_helpers::timeout::FutureTimeout;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_checkpoint_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert_eq!(read_checkpoint(dir.path()).await.unwrap(), None);
+
+        write_checkpoint(dir.path(), 42).await.unwrap();
+        assert_eq!(read_checkpoint(dir.path()).await.unwrap(), Some(42));
+
+        // A later checkpoint replaces the earlier one.
+        write_checkpoint(dir.path(), 4242).await.unwrap();
+        assert_eq!(read_checkpoint(dir.path()).await.unwrap(), Some(4242));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(CHECKPOINT_FILE_NAME), "bananas").unwrap();
+
+        let err = read_checkpoint(dir.path()).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn test_periodic_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let oracle = Arc::new(TimestampOracle::new(0));
+        for _ in 0..10 {
+            oracle.next();
+        }
+
+        let task = tokio::spawn(periodic_checkpoint(
+            dir.path().to_path_buf(),
+            Arc::clone(&oracle),
+        ));
+
+        // The first tick completes immediately.
+        async {
+            loop {
+                if read_checkpoint(dir.path()).await.unwrap() == Some(10) {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }
+        .with_timeout_panic(Duration::from_secs(5))
+        .await;
+
+        task.abort();
+    }
+}
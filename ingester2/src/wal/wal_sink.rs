@@ -1,8 +1,15 @@
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use dml::DmlOperation;
-use generated_types::influxdata::iox::wal::v1::sequenced_wal_op::Op;
+use generated_types::influxdata::iox::{delete::v1::DeletePayload, wal::v1::sequenced_wal_op::Op};
+use iox_time::{SystemProvider, TimeProvider};
 use mutable_batch_pb::encode::encode_write;
-use wal::SequencedWalOp;
+use trace::{
+    ctx::SpanContext,
+    span::{SpanExt, SpanRecorder},
+};
+use wal::{SequencedWalOp, TraceContext};
 
 use crate::dml_sink::{DmlError, DmlSink};
 
@@ -10,49 +17,77 @@ use super::traits::WalAppender;
 
 /// A [`DmlSink`] decorator that ensures any [`DmlOperation`] is committed to
 /// the write-ahead log before passing the operation to the inner [`DmlSink`].
+///
+/// # Cancellation Safety
+///
+/// Once a [`DmlOperation`] is committed to the write-ahead log it MUST be
+/// passed to the inner [`DmlSink`] so that it becomes readable - failing to do
+/// so means writes would randomly appear after replaying the WAL (see
+/// <https://github.com/influxdata/influxdb_iox/issues/6281>).
+///
+/// To guarantee this, the WAL commit and the call to the inner [`DmlSink`] are
+/// driven to completion by a detached task, which continues to run if the
+/// caller stops polling the [`DmlSink::apply()`] future. The caller observes
+/// the result of the task, if it is still waiting for it.
+///
+/// # Tracing
+///
+/// The WAL commit of a traced op is recorded as a "wal append" child span of
+/// the op's tracing context, covering both the write and the fsync that makes
+/// it durable (which the WAL performs together for each batch of ops).
 #[derive(Debug)]
 pub(crate) struct WalSink<T, W = wal::WalWriter> {
     /// The inner chain of [`DmlSink`] that a [`DmlOperation`] is passed to once
     /// committed to the write-ahead log.
-    inner: T,
+    inner: Arc<T>,
 
     /// The write-ahead log implementation.
-    wal: W,
+    wal: Arc<W>,
 }
 
 impl<T, W> WalSink<T, W> {
     /// Initialise a new [`WalSink`] that appends [`DmlOperation`] to `W` and
     /// on success, passes the op through to `T`.
     pub(crate) fn new(inner: T, wal: W) -> Self {
-        Self { inner, wal }
+        Self {
+            inner: Arc::new(inner),
+            wal: Arc::new(wal),
+        }
     }
 }
 
 #[async_trait]
 impl<T, W> DmlSink for WalSink<T, W>
 where
-    T: DmlSink,
+    T: DmlSink + 'static,
     W: WalAppender + 'static,
 {
     type Error = DmlError;
 
     async fn apply(&self, op: DmlOperation) -> Result<(), Self::Error> {
-        // TODO: cancellation safety
-        //
-        // See https://github.com/influxdata/influxdb_iox/issues/6281.
-        //
-        // Once an item is in the WAL, it should be passed into the inner
-        // DmlSink so that is becomes readable - failing to do this means writes
-        // will randomly appear after replaying the WAL.
-        //
-        // This can happen If the caller stops polling just after the WAL commit
-        // future completes and before the inner DmlSink call returns Ready.
-
-        // Append the operation to the WAL
-        self.wal.append(&op).await?;
-
-        // And once durable, pass it to the inner handler.
-        self.inner.apply(op).await.map_err(Into::into)
+        let inner = Arc::clone(&self.inner);
+        let wal = Arc::clone(&self.wal);
+
+        // Run the WAL commit and the inner apply call in a detached task, so
+        // that the caller stopping polling this future between the two cannot
+        // leave the op durable but not applied.
+        tokio::spawn(async move {
+            // Append the operation to the WAL
+            {
+                let mut recorder =
+                    SpanRecorder::new(op.meta().span_context().child_span("wal append"));
+                if let Err(e) = wal.append(&op).await {
+                    recorder.error(e.to_string());
+                    return Err(DmlError::Wal(e));
+                }
+                recorder.ok("op durable");
+            }
+
+            // And once durable, pass it to the inner handler.
+            inner.apply(op).await.map_err(Into::into)
+        })
+        .await
+        .expect("wal sink apply task panicked")
     }
 }
 
@@ -70,12 +105,18 @@ impl WalAppender for wal::WalWriter {
 
         let wal_op = match op {
             DmlOperation::Write(w) => Op::Write(encode_write(namespace_id.get(), w)),
-            DmlOperation::Delete(_) => unreachable!(),
+            DmlOperation::Delete(d) => Op::Delete(DeletePayload {
+                database_id: namespace_id.get(),
+                table_name: d.table_name().map(ToString::to_string).unwrap_or_default(),
+                predicate: Some(d.predicate().clone().into()),
+            }),
         };
 
         self.write_op(SequencedWalOp {
             sequence_number,
             op: wal_op,
+            trace_context: op.meta().span_context().map(encode_trace_context),
+            ingest_timestamp_ns: Some(SystemProvider::new().now().timestamp_nanos()),
         })
         .await?;
 
@@ -83,15 +124,34 @@ impl WalAppender for wal::WalWriter {
     }
 }
 
+/// Convert `ctx` into the form recorded in the WAL, from which it is restored
+/// during replay.
+fn encode_trace_context(ctx: &SpanContext) -> TraceContext {
+    let trace_id = ctx.trace_id.get();
+    TraceContext {
+        trace_id_high: (trace_id >> 64) as u64,
+        trace_id_low: trace_id as u64,
+        span_id: ctx.span_id.get(),
+        parent_span_id: ctx.parent_span_id.map(|id| id.get()).unwrap_or_default(),
+        sampled: ctx.sampled,
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
+    use std::time::Duration;
 
     use assert_matches::assert_matches;
     use data_types::{NamespaceId, PartitionKey, TableId};
+    use futures::FutureExt;
+    use tokio::sync::Semaphore;
+    use trace::{span::SpanStatus, RingBufferTraceCollector, TraceCollector};
     use wal::Wal;
 
-    use crate::{dml_sink::mock_sink::MockDmlSink, test_util::make_write_op};
+    use crate::{
+        dml_sink::mock_sink::MockDmlSink,
+        test_util::{make_delete_op, make_write_op},
+    };
 
     use super::*;
 
@@ -168,4 +228,134 @@ mod tests {
 
         assert_eq!(want, *payload);
     }
+
+    #[tokio::test]
+    async fn test_append_traced() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let traces: Arc<dyn TraceCollector> = Arc::new(RingBufferTraceCollector::new(5));
+        let span_ctx = SpanContext::new(Arc::clone(&traces));
+
+        let mut op = DmlOperation::Write(make_write_op(
+            &PartitionKey::from("p1"),
+            NAMESPACE_ID,
+            TABLE_NAME,
+            TABLE_ID,
+            42,
+            r#"bananas,region=Madrid temp=35 4242424242"#,
+        ));
+        op.set_meta(op.meta().clone().with_span_context(Some(span_ctx.clone())));
+
+        let inner = Arc::new(MockDmlSink::default().with_apply_return(vec![Ok(())]));
+        let wal = Wal::new(dir.path())
+            .await
+            .expect("failed to initialise WAL");
+        let wal_sink = WalSink::new(Arc::clone(&inner), wal.write_handle().await);
+
+        wal_sink.apply(op).await.expect("wal should not error");
+
+        // The WAL commit is recorded as a child span of the op's context.
+        let spans = traces
+            .as_any()
+            .downcast_ref::<RingBufferTraceCollector>()
+            .expect("unexpected collector impl")
+            .spans();
+        let span = assert_matches!(&*spans, [s] => s, "expected 1 span");
+        assert_eq!(span.name, "wal append");
+        assert_eq!(span.status, SpanStatus::Ok);
+        assert_eq!(span.ctx.parent_span_id, Some(span_ctx.span_id));
+    }
+
+    #[tokio::test]
+    async fn test_append_delete() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let op = make_delete_op(NAMESPACE_ID, Some(TABLE_NAME), 42);
+
+        {
+            let inner = Arc::new(MockDmlSink::default().with_apply_return(vec![Ok(())]));
+            let wal = Wal::new(dir.path())
+                .await
+                .expect("failed to initialise WAL");
+            let wal_sink = WalSink::new(Arc::clone(&inner), wal.write_handle().await);
+
+            wal_sink
+                .apply(DmlOperation::Delete(op.clone()))
+                .await
+                .expect("wal should not error");
+            assert_eq!(inner.get_calls().len(), 1);
+        }
+
+        // Read the op back
+        let wal = Wal::new(dir.path())
+            .await
+            .expect("failed to initialise WAL");
+        let read_handle = wal.read_handle();
+        let files = read_handle.closed_segments().await;
+        let file = assert_matches!(&*files, [f] => f, "expected 1 file");
+        let mut reader = read_handle
+            .reader_for_segment(file.id())
+            .await
+            .expect("failed to obtain reader");
+
+        let read_op = reader
+            .next_op()
+            .await
+            .expect("failed to read op")
+            .expect("expected 1 op");
+        assert_eq!(read_op.sequence_number, 42);
+        let payload =
+            assert_matches!(read_op.op, Op::Delete(d) => d, "expected DML delete WAL entry");
+
+        assert_eq!(payload.database_id, NAMESPACE_ID.get());
+        assert_eq!(payload.table_name, TABLE_NAME);
+        assert_eq!(payload.predicate, Some(op.predicate().clone().into()));
+    }
+
+    /// A [`WalAppender`] that blocks each append until a permit is added to
+    /// its semaphore.
+    #[derive(Debug)]
+    struct BlockingWal(Arc<Semaphore>);
+
+    #[async_trait]
+    impl WalAppender for BlockingWal {
+        async fn append(&self, _op: &DmlOperation) -> Result<(), wal::Error> {
+            self.0.acquire().await.unwrap().forget();
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_apply_is_completed() {
+        let inner = Arc::new(MockDmlSink::default().with_apply_return(vec![Ok(())]));
+        let wal = Arc::new(Semaphore::new(0));
+        let wal_sink = WalSink::new(Arc::clone(&inner), BlockingWal(Arc::clone(&wal)));
+
+        let op = make_write_op(
+            &PartitionKey::from("p1"),
+            NAMESPACE_ID,
+            TABLE_NAME,
+            TABLE_ID,
+            42,
+            r#"bananas,region=Madrid temp=35 4242424242"#,
+        );
+
+        // Start applying the op, and stop polling it while the WAL commit is
+        // outstanding.
+        assert!(wal_sink
+            .apply(DmlOperation::Write(op))
+            .now_or_never()
+            .is_none());
+
+        // Once the WAL commit completes, the op must still be applied to the
+        // inner sink.
+        wal.add_permits(1);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while inner.get_calls().is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("cancelled op was not applied");
+    }
 }
@@ -0,0 +1,23 @@
+CREATE TABLE IF NOT EXISTS persist_policy (
+    id BIGINT GENERATED ALWAYS AS IDENTITY,
+    namespace_id BIGINT NOT NULL,
+    table_id BIGINT DEFAULT NULL,
+    max_age_ms BIGINT DEFAULT NULL,
+    max_rows BIGINT DEFAULT NULL,
+    max_bytes BIGINT DEFAULT NULL,
+    PRIMARY KEY (id)
+);
+
+-- At most one policy per namespace (with a NULL table_id) and per table.
+CREATE UNIQUE INDEX IF NOT EXISTS persist_policy_unique_idx
+    ON persist_policy (namespace_id, (COALESCE(table_id, 0)));
+
+ALTER TABLE
+    IF EXISTS persist_policy
+ADD
+    FOREIGN KEY (namespace_id) REFERENCES namespace (id) MATCH SIMPLE ON UPDATE NO ACTION ON DELETE NO ACTION NOT VALID;
+
+ALTER TABLE
+    IF EXISTS persist_policy
+ADD
+    FOREIGN KEY (table_id) REFERENCES table_name (id) MATCH SIMPLE ON UPDATE NO ACTION ON DELETE NO ACTION NOT VALID;
@@ -4,9 +4,9 @@ use async_trait::async_trait;
 use data_types::{
     Column, ColumnSchema, ColumnType, ColumnTypeCount, CompactionLevel, Namespace, NamespaceId,
     NamespaceSchema, ParquetFile, ParquetFileId, ParquetFileParams, Partition, PartitionId,
-    PartitionKey, PartitionParam, ProcessedTombstone, QueryPool, QueryPoolId, SequenceNumber,
-    Shard, ShardId, ShardIndex, SkippedCompaction, Table, TableId, TablePartition, TableSchema,
-    Timestamp, Tombstone, TombstoneId, TopicId, TopicMetadata,
+    PartitionKey, PartitionParam, PersistPolicy, ProcessedTombstone, QueryPool, QueryPoolId,
+    SequenceNumber, Shard, ShardId, ShardIndex, SkippedCompaction, Table, TableId, TablePartition,
+    TableSchema, Timestamp, Tombstone, TombstoneId, TopicId, TopicMetadata,
 };
 use iox_time::TimeProvider;
 use snafu::{OptionExt, Snafu};
@@ -263,6 +263,9 @@ pub trait RepoCollection: Send + Sync + Debug {
 
     /// Repository for [processed tombstones](data_types::ProcessedTombstone).
     fn processed_tombstones(&mut self) -> &mut dyn ProcessedTombstoneRepo;
+
+    /// Repository for [persist policies](data_types::PersistPolicy).
+    fn persist_policies(&mut self) -> &mut dyn PersistPolicyRepo;
 }
 
 /// Functions for working with topics in the catalog.
@@ -569,6 +572,10 @@ pub trait ParquetFileRepo: Send + Sync {
         namespace_id: NamespaceId,
     ) -> Result<Vec<ParquetFile>>;
 
+    /// List all parquet files within a given namespace, including those
+    /// marked as [`to_delete`](ParquetFile::to_delete).
+    async fn list_by_namespace(&mut self, namespace_id: NamespaceId) -> Result<Vec<ParquetFile>>;
+
     /// List all parquet files within a given table that are NOT marked as
     /// [`to_delete`](ParquetFile::to_delete).
     async fn list_by_table_not_to_delete(&mut self, table_id: TableId) -> Result<Vec<ParquetFile>>;
@@ -686,6 +693,13 @@ pub trait ProcessedTombstoneRepo: Send + Sync {
         tombstone_id: TombstoneId,
     ) -> Result<bool>;
 
+    /// List the processed tombstones of any of the given tombstone ids,
+    /// ordered by tombstone id and parquet file id.
+    async fn list_by_tombstone_ids(
+        &mut self,
+        tombstone_ids: &[TombstoneId],
+    ) -> Result<Vec<ProcessedTombstone>>;
+
     /// Return count
     async fn count(&mut self) -> Result<i64>;
 
@@ -693,6 +707,17 @@ pub trait ProcessedTombstoneRepo: Send + Sync {
     async fn count_by_tombstone_id(&mut self, tombstone_id: TombstoneId) -> Result<i64>;
 }
 
+/// Functions for working with ingester persist policies in the catalog
+#[async_trait]
+pub trait PersistPolicyRepo: Send + Sync {
+    /// Create the persist policy for its namespace or table, or replace the
+    /// existing one.
+    async fn upsert(&mut self, policy: PersistPolicy) -> Result<PersistPolicy>;
+
+    /// List all persist policies.
+    async fn list(&mut self) -> Result<Vec<PersistPolicy>>;
+}
+
 /// Gets the namespace schema including all tables and columns.
 pub async fn get_schema_by_id<R>(id: NamespaceId, repos: &mut R) -> Result<NamespaceSchema>
 where
@@ -913,6 +938,7 @@ pub(crate) mod test_helpers {
         test_recent_highest_throughput_partitions(Arc::clone(&catalog)).await;
         test_update_to_compaction_level_1(Arc::clone(&catalog)).await;
         test_processed_tombstones(Arc::clone(&catalog)).await;
+        test_persist_policies(Arc::clone(&catalog)).await;
         test_list_by_partiton_not_to_delete(Arc::clone(&catalog)).await;
         test_txn_isolation(Arc::clone(&catalog)).await;
         test_txn_drop(Arc::clone(&catalog)).await;
@@ -2336,12 +2362,31 @@ pub(crate) mod test_helpers {
             .unwrap();
         assert_eq!(vec![f1.clone(), f3.clone()], files);
 
+        // test list_by_namespace, which includes the file marked for deletion
+        let mut files = repos
+            .parquet_files()
+            .list_by_namespace(namespace2.id)
+            .await
+            .unwrap();
+        files.sort_by_key(|f| f.id);
+        assert_eq!(
+            files.iter().map(|f| f.id).collect::<Vec<_>>(),
+            vec![f1.id, f2.id, f3.id]
+        );
+        assert!(files[1].to_delete.is_some());
+
         let files = repos
             .parquet_files()
             .list_by_namespace_not_to_delete(NamespaceId::new(i64::MAX))
             .await
             .unwrap();
         assert!(files.is_empty());
+        let files = repos
+            .parquet_files()
+            .list_by_namespace(NamespaceId::new(i64::MAX))
+            .await
+            .unwrap();
+        assert!(files.is_empty());
 
         // test count_by_overlaps_with_level_0
         // not time overlap
@@ -4078,6 +4123,24 @@ pub(crate) mod test_helpers {
             .unwrap();
         assert!(exist);
 
+        // test list_by_tombstone_ids
+        let processed = repos
+            .processed_tombstones()
+            .list_by_tombstone_ids(&[t1.id, t3.id])
+            .await
+            .unwrap();
+        let processed: Vec<_> = processed
+            .into_iter()
+            .map(|pt| (pt.tombstone_id, pt.parquet_file_id))
+            .collect();
+        assert_eq!(processed, vec![(t3.id, p1.id), (t3.id, p2.id)]);
+        let processed = repos
+            .processed_tombstones()
+            .list_by_tombstone_ids(&[])
+            .await
+            .unwrap();
+        assert!(processed.is_empty());
+
         // test count
         let count = repos.processed_tombstones().count().await.unwrap();
         assert_eq!(count, 3);
@@ -4120,6 +4183,64 @@ pub(crate) mod test_helpers {
         assert_eq!(count, 0);
     }
 
+    async fn test_persist_policies(catalog: Arc<dyn Catalog>) {
+        let mut repos = catalog.repositories().await;
+        let topic = repos.topics().create_or_get("foo").await.unwrap();
+        let pool = repos.query_pools().create_or_get("foo").await.unwrap();
+        let namespace = repos
+            .namespaces()
+            .create("namespace_persist_policy_test", None, topic.id, pool.id)
+            .await
+            .unwrap();
+        let table = repos
+            .tables()
+            .create_or_get("test_table", namespace.id)
+            .await
+            .unwrap();
+
+        let namespace_policy = PersistPolicy {
+            namespace_id: namespace.id,
+            table_id: None,
+            max_age_ms: Some(60_000),
+            max_rows: None,
+            max_bytes: None,
+        };
+        let table_policy = PersistPolicy {
+            namespace_id: namespace.id,
+            table_id: Some(table.id),
+            max_age_ms: None,
+            max_rows: Some(1_000),
+            max_bytes: Some(1024),
+        };
+
+        let got = repos
+            .persist_policies()
+            .upsert(namespace_policy)
+            .await
+            .unwrap();
+        assert_eq!(got, namespace_policy);
+        let got = repos.persist_policies().upsert(table_policy).await.unwrap();
+        assert_eq!(got, table_policy);
+
+        // Replacing the table policy leaves the namespace policy unchanged.
+        let table_policy = PersistPolicy {
+            max_rows: None,
+            ..table_policy
+        };
+        repos.persist_policies().upsert(table_policy).await.unwrap();
+
+        let mut got = repos
+            .persist_policies()
+            .list()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|v| v.namespace_id == namespace.id)
+            .collect::<Vec<_>>();
+        got.sort_unstable();
+        assert_eq!(got, [namespace_policy, table_policy]);
+    }
+
     async fn test_txn_isolation(catalog: Arc<dyn Catalog>) {
         let barrier = Arc::new(tokio::sync::Barrier::new(2));
 
@@ -4,9 +4,9 @@
 use crate::{
     interface::{
         sealed::TransactionFinalize, Catalog, ColumnRepo, ColumnTypeMismatchSnafu, Error,
-        NamespaceRepo, ParquetFileRepo, PartitionRepo, ProcessedTombstoneRepo, QueryPoolRepo,
-        RepoCollection, Result, ShardRepo, TableRepo, TombstoneRepo, TopicMetadataRepo,
-        Transaction,
+        NamespaceRepo, ParquetFileRepo, PartitionRepo, PersistPolicyRepo, ProcessedTombstoneRepo,
+        QueryPoolRepo, RepoCollection, Result, ShardRepo, TableRepo, TombstoneRepo,
+        TopicMetadataRepo, Transaction,
     },
     metrics::MetricDecorator,
     DEFAULT_MAX_COLUMNS_PER_TABLE, DEFAULT_MAX_TABLES,
@@ -15,9 +15,9 @@ use async_trait::async_trait;
 use data_types::{
     Column, ColumnId, ColumnType, ColumnTypeCount, CompactionLevel, Namespace, NamespaceId,
     ParquetFile, ParquetFileId, ParquetFileParams, Partition, PartitionId, PartitionKey,
-    PartitionParam, ProcessedTombstone, QueryPool, QueryPoolId, SequenceNumber, Shard, ShardId,
-    ShardIndex, SkippedCompaction, Table, TableId, TablePartition, Timestamp, Tombstone,
-    TombstoneId, TopicId, TopicMetadata,
+    PartitionParam, PersistPolicy, ProcessedTombstone, QueryPool, QueryPoolId, SequenceNumber,
+    Shard, ShardId, ShardIndex, SkippedCompaction, Table, TableId, TablePartition, Timestamp,
+    Tombstone, TombstoneId, TopicId, TopicMetadata,
 };
 use iox_time::{SystemProvider, TimeProvider};
 use observability_deps::tracing::warn;
@@ -69,6 +69,7 @@ struct MemCollections {
     tombstones: Vec<Tombstone>,
     parquet_files: Vec<ParquetFile>,
     processed_tombstones: Vec<ProcessedTombstone>,
+    persist_policies: Vec<PersistPolicy>,
 }
 
 #[derive(Debug)]
@@ -229,6 +230,10 @@ impl RepoCollection for MemTxn {
     fn processed_tombstones(&mut self) -> &mut dyn ProcessedTombstoneRepo {
         self
     }
+
+    fn persist_policies(&mut self) -> &mut dyn PersistPolicyRepo {
+        self
+    }
 }
 
 #[async_trait]
@@ -1185,6 +1190,23 @@ impl ParquetFileRepo for MemTxn {
         Ok(parquet_files)
     }
 
+    async fn list_by_namespace(&mut self, namespace_id: NamespaceId) -> Result<Vec<ParquetFile>> {
+        let stage = self.stage();
+
+        let table_ids: HashSet<_> = stage
+            .tables
+            .iter()
+            .filter_map(|table| (table.namespace_id == namespace_id).then_some(table.id))
+            .collect();
+        let parquet_files: Vec<_> = stage
+            .parquet_files
+            .iter()
+            .filter(|f| table_ids.contains(&f.table_id))
+            .cloned()
+            .collect();
+        Ok(parquet_files)
+    }
+
     async fn list_by_table_not_to_delete(&mut self, table_id: TableId) -> Result<Vec<ParquetFile>> {
         let stage = self.stage();
 
@@ -1580,6 +1602,23 @@ impl ProcessedTombstoneRepo for MemTxn {
             .any(|f| f.parquet_file_id == parquet_file_id && f.tombstone_id == tombstone_id))
     }
 
+    async fn list_by_tombstone_ids(
+        &mut self,
+        tombstone_ids: &[TombstoneId],
+    ) -> Result<Vec<ProcessedTombstone>> {
+        let stage = self.stage();
+
+        let mut processed: Vec<_> = stage
+            .processed_tombstones
+            .iter()
+            .filter(|pt| tombstone_ids.contains(&pt.tombstone_id))
+            .copied()
+            .collect();
+        processed.sort_by_key(|pt| (pt.tombstone_id, pt.parquet_file_id));
+
+        Ok(processed)
+    }
+
     async fn count(&mut self) -> Result<i64> {
         let stage = self.stage();
 
@@ -1604,6 +1643,41 @@ impl ProcessedTombstoneRepo for MemTxn {
     }
 }
 
+#[async_trait]
+impl PersistPolicyRepo for MemTxn {
+    async fn upsert(&mut self, policy: PersistPolicy) -> Result<PersistPolicy> {
+        let stage = self.stage();
+
+        if !stage.namespaces.iter().any(|n| n.id == policy.namespace_id) {
+            return Err(Error::NamespaceNotFoundById {
+                id: policy.namespace_id,
+            });
+        }
+        if let Some(id) = policy.table_id {
+            if !stage.tables.iter().any(|t| t.id == id) {
+                return Err(Error::TableNotFound { id });
+            }
+        }
+
+        match stage
+            .persist_policies
+            .iter_mut()
+            .find(|p| p.namespace_id == policy.namespace_id && p.table_id == policy.table_id)
+        {
+            Some(p) => *p = policy,
+            None => stage.persist_policies.push(policy),
+        }
+
+        Ok(policy)
+    }
+
+    async fn list(&mut self) -> Result<Vec<PersistPolicy>> {
+        let stage = self.stage();
+
+        Ok(stage.persist_policies.clone())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
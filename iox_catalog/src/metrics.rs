@@ -2,16 +2,16 @@
 
 use crate::interface::{
     sealed::TransactionFinalize, ColumnRepo, NamespaceRepo, ParquetFileRepo, PartitionRepo,
-    ProcessedTombstoneRepo, QueryPoolRepo, RepoCollection, Result, ShardRepo, TableRepo,
-    TombstoneRepo, TopicMetadataRepo,
+    PersistPolicyRepo, ProcessedTombstoneRepo, QueryPoolRepo, RepoCollection, Result, ShardRepo,
+    TableRepo, TombstoneRepo, TopicMetadataRepo,
 };
 use async_trait::async_trait;
 use data_types::{
     Column, ColumnType, ColumnTypeCount, CompactionLevel, Namespace, NamespaceId, ParquetFile,
     ParquetFileId, ParquetFileParams, Partition, PartitionId, PartitionKey, PartitionParam,
-    ProcessedTombstone, QueryPool, QueryPoolId, SequenceNumber, Shard, ShardId, ShardIndex,
-    SkippedCompaction, Table, TableId, TablePartition, Timestamp, Tombstone, TombstoneId, TopicId,
-    TopicMetadata,
+    PersistPolicy, ProcessedTombstone, QueryPool, QueryPoolId, SequenceNumber, Shard, ShardId,
+    ShardIndex, SkippedCompaction, Table, TableId, TablePartition, Timestamp, Tombstone,
+    TombstoneId, TopicId, TopicMetadata,
 };
 use iox_time::{SystemProvider, TimeProvider};
 use metric::{DurationHistogram, Metric};
@@ -54,6 +54,7 @@ where
         + TombstoneRepo
         + ProcessedTombstoneRepo
         + ParquetFileRepo
+        + PersistPolicyRepo
         + Debug,
     P: TimeProvider,
 {
@@ -96,6 +97,10 @@ where
     fn processed_tombstones(&mut self) -> &mut dyn ProcessedTombstoneRepo {
         self
     }
+
+    fn persist_policies(&mut self) -> &mut dyn PersistPolicyRepo {
+        self
+    }
 }
 
 #[async_trait]
@@ -275,6 +280,7 @@ decorate!(
         "parquet_flag_for_delete_by_retention" = flag_for_delete_by_retention(&mut self) -> Result<Vec<ParquetFileId>>;
         "parquet_list_by_shard_greater_than" = list_by_shard_greater_than(&mut self, shard_id: ShardId, sequence_number: SequenceNumber) -> Result<Vec<ParquetFile>>;
         "parquet_list_by_namespace_not_to_delete" = list_by_namespace_not_to_delete(&mut self, namespace_id: NamespaceId) -> Result<Vec<ParquetFile>>;
+        "parquet_list_by_namespace" = list_by_namespace(&mut self, namespace_id: NamespaceId) -> Result<Vec<ParquetFile>>;
         "parquet_list_by_table_not_to_delete" = list_by_table_not_to_delete(&mut self, table_id: TableId) -> Result<Vec<ParquetFile>>;
         "parquet_delete_old" = delete_old(&mut self, older_than: Timestamp) -> Result<Vec<ParquetFile>>;
         "parquet_delete_old_ids_only" = delete_old_ids_only(&mut self, older_than: Timestamp) -> Result<Vec<ParquetFileId>>;
@@ -297,7 +303,16 @@ decorate!(
     methods = [
         "processed_tombstone_create" = create(&mut self, parquet_file_id: ParquetFileId, tombstone_id: TombstoneId) -> Result<ProcessedTombstone>;
         "processed_tombstone_exist" = exist(&mut self, parquet_file_id: ParquetFileId, tombstone_id: TombstoneId) -> Result<bool>;
+        "processed_tombstone_list_by_tombstone_ids" = list_by_tombstone_ids(&mut self, tombstone_ids: &[TombstoneId]) -> Result<Vec<ProcessedTombstone>>;
         "processed_tombstone_count" = count(&mut self) -> Result<i64>;
         "processed_tombstone_count_by_tombstone_id" = count_by_tombstone_id(&mut self, tombstone_id: TombstoneId) -> Result<i64>;
     ]
 );
+
+decorate!(
+    impl_trait = PersistPolicyRepo,
+    methods = [
+        "persist_policy_upsert" = upsert(&mut self, policy: PersistPolicy) -> Result<PersistPolicy>;
+        "persist_policy_list" = list(&mut self) -> Result<Vec<PersistPolicy>>;
+    ]
+);
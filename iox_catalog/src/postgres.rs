@@ -3,9 +3,9 @@
 use crate::{
     interface::{
         self, sealed::TransactionFinalize, Catalog, ColumnRepo, ColumnTypeMismatchSnafu, Error,
-        NamespaceRepo, ParquetFileRepo, PartitionRepo, ProcessedTombstoneRepo, QueryPoolRepo,
-        RepoCollection, Result, ShardRepo, TableRepo, TombstoneRepo, TopicMetadataRepo,
-        Transaction,
+        NamespaceRepo, ParquetFileRepo, PartitionRepo, PersistPolicyRepo, ProcessedTombstoneRepo,
+        QueryPoolRepo, RepoCollection, Result, ShardRepo, TableRepo, TombstoneRepo,
+        TopicMetadataRepo, Transaction,
     },
     metrics::MetricDecorator,
     DEFAULT_MAX_COLUMNS_PER_TABLE, DEFAULT_MAX_TABLES,
@@ -14,9 +14,9 @@ use async_trait::async_trait;
 use data_types::{
     Column, ColumnType, ColumnTypeCount, CompactionLevel, Namespace, NamespaceId, ParquetFile,
     ParquetFileId, ParquetFileParams, Partition, PartitionId, PartitionKey, PartitionParam,
-    ProcessedTombstone, QueryPool, QueryPoolId, SequenceNumber, Shard, ShardId, ShardIndex,
-    SkippedCompaction, Table, TableId, TablePartition, Timestamp, Tombstone, TombstoneId, TopicId,
-    TopicMetadata,
+    PersistPolicy, ProcessedTombstone, QueryPool, QueryPoolId, SequenceNumber, Shard, ShardId,
+    ShardIndex, SkippedCompaction, Table, TableId, TablePartition, Timestamp, Tombstone,
+    TombstoneId, TopicId, TopicMetadata,
 };
 use iox_time::{SystemProvider, TimeProvider};
 use observability_deps::tracing::{debug, info, warn};
@@ -519,6 +519,10 @@ impl RepoCollection for PostgresTxn {
     fn processed_tombstones(&mut self) -> &mut dyn ProcessedTombstoneRepo {
         self
     }
+
+    fn persist_policies(&mut self) -> &mut dyn PersistPolicyRepo {
+        self
+    }
 }
 
 #[async_trait]
@@ -1714,6 +1718,27 @@ WHERE table_name.namespace_id = $1
         .map_err(|e| Error::SqlxError { source: e })
     }
 
+    async fn list_by_namespace(&mut self, namespace_id: NamespaceId) -> Result<Vec<ParquetFile>> {
+        // Deliberately doesn't use `SELECT *` to avoid the performance hit of fetching the large
+        // `parquet_metadata` column!!
+        sqlx::query_as::<_, ParquetFile>(
+            r#"
+SELECT parquet_file.id, parquet_file.shard_id, parquet_file.namespace_id,
+       parquet_file.table_id, parquet_file.partition_id, parquet_file.object_store_id,
+       parquet_file.max_sequence_number, parquet_file.min_time,
+       parquet_file.max_time, parquet_file.to_delete, parquet_file.file_size_bytes,
+       parquet_file.row_count, parquet_file.compaction_level, parquet_file.created_at, parquet_file.column_set
+FROM parquet_file
+INNER JOIN table_name on table_name.id = parquet_file.table_id
+WHERE table_name.namespace_id = $1;
+             "#,
+        )
+        .bind(namespace_id) // $1
+        .fetch_all(&mut self.inner)
+        .await
+        .map_err(|e| Error::SqlxError { source: e })
+    }
+
     async fn list_by_table_not_to_delete(&mut self, table_id: TableId) -> Result<Vec<ParquetFile>> {
         // Deliberately doesn't use `SELECT *` to avoid the performance hit of fetching the large
         // `parquet_metadata` column!!
@@ -2124,6 +2149,26 @@ WHERE parquet_file_id = $1
         Ok(read_result.count > 0)
     }
 
+    async fn list_by_tombstone_ids(
+        &mut self,
+        tombstone_ids: &[TombstoneId],
+    ) -> Result<Vec<ProcessedTombstone>> {
+        let ids: Vec<_> = tombstone_ids.iter().map(|t| t.get()).collect();
+
+        sqlx::query_as::<_, ProcessedTombstone>(
+            r#"
+SELECT *
+FROM processed_tombstone
+WHERE tombstone_id = ANY($1)
+ORDER BY tombstone_id, parquet_file_id;
+            "#,
+        )
+        .bind(&ids[..]) // $1
+        .fetch_all(&mut self.inner)
+        .await
+        .map_err(|e| Error::SqlxError { source: e })
+    }
+
     async fn count(&mut self) -> Result<i64> {
         let read_result =
             sqlx::query_as::<_, Count>(r#"SELECT count(1) as count FROM processed_tombstone;"#)
@@ -2147,6 +2192,52 @@ WHERE parquet_file_id = $1
     }
 }
 
+#[async_trait]
+impl PersistPolicyRepo for PostgresTxn {
+    async fn upsert(&mut self, policy: PersistPolicy) -> Result<PersistPolicy> {
+        let rec = sqlx::query_as::<_, PersistPolicy>(
+            r#"
+INSERT INTO persist_policy ( namespace_id, table_id, max_age_ms, max_rows, max_bytes )
+VALUES ( $1, $2, $3, $4, $5 )
+ON CONFLICT ( namespace_id, (COALESCE(table_id, 0)) )
+DO UPDATE SET
+    max_age_ms = EXCLUDED.max_age_ms,
+    max_rows = EXCLUDED.max_rows,
+    max_bytes = EXCLUDED.max_bytes
+RETURNING *;
+        "#,
+        )
+        .bind(policy.namespace_id) // $1
+        .bind(policy.table_id) // $2
+        .bind(policy.max_age_ms) // $3
+        .bind(policy.max_rows) // $4
+        .bind(policy.max_bytes) // $5
+        .fetch_one(&mut self.inner)
+        .await
+        .map_err(|e| {
+            if is_fk_violation(&e) {
+                Error::ForeignKeyViolation { source: e }
+            } else {
+                Error::SqlxError { source: e }
+            }
+        })?;
+
+        Ok(rec)
+    }
+
+    async fn list(&mut self) -> Result<Vec<PersistPolicy>> {
+        sqlx::query_as::<_, PersistPolicy>(
+            r#"
+SELECT *
+FROM persist_policy;
+        "#,
+        )
+        .fetch_all(&mut self.inner)
+        .await
+        .map_err(|e| Error::SqlxError { source: e })
+    }
+}
+
 /// The error code returned by Postgres for a unique constraint violation.
 ///
 /// See <https://www.postgresql.org/docs/9.2/errcodes-appendix.html>
@@ -5,6 +5,7 @@ pub(crate) mod context;
 pub mod field;
 pub mod fieldlist;
 mod non_null_checker;
+mod query_memory_budget;
 mod query_tracing;
 mod schema_pivot;
 pub mod seriesset;
@@ -21,6 +22,7 @@ use datafusion::{
     self,
     execution::{
         context::SessionState,
+        disk_manager::DiskManagerConfig,
         runtime_env::{RuntimeConfig, RuntimeEnv},
     },
     logical_expr::{expr_rewriter::normalize_col, Extension},
@@ -31,7 +33,10 @@ use datafusion::{
 pub use context::{IOxSessionConfig, IOxSessionContext, SessionContextIOxExt};
 use schema_pivot::SchemaPivotNode;
 
-use self::{non_null_checker::NonNullCheckerNode, split::StreamSplitNode};
+use self::{
+    non_null_checker::NonNullCheckerNode, query_memory_budget::QueryMemoryBudget,
+    split::StreamSplitNode,
+};
 
 /// Configuration for an Executor
 #[derive(Debug, Clone)]
@@ -44,6 +49,23 @@ pub struct ExecutorConfig {
 
     /// Object stores
     pub object_stores: HashMap<StorageId, Arc<DynObjectStore>>,
+
+    /// Memory budget in bytes shared by all executions, or [`None`] for
+    /// no limit.
+    ///
+    /// Executions that would exceed the budget fail with
+    /// [`DataFusionError::ResourcesExhausted`].
+    ///
+    /// [`DataFusionError::ResourcesExhausted`]: datafusion::error::DataFusionError::ResourcesExhausted
+    pub mem_pool_size: Option<usize>,
+
+    /// Memory budget in bytes of each [`ExecutorType::Query`] execution, or
+    /// [`None`] for no per-query limit.
+    ///
+    /// Each query reserves its budget from [`Self::mem_pool_size`] until it
+    /// is dropped, so a query fails if it exceeds its own budget, or if the
+    /// shared budget is already reserved by other queries.
+    pub per_query_mem_pool_size: Option<usize>,
 }
 
 #[derive(Debug)]
@@ -100,6 +122,10 @@ pub struct Executor {
     /// The DataFusion [RuntimeEnv] (including memory manager and disk
     /// manager) used for all executions
     runtime: Arc<RuntimeEnv>,
+
+    /// The budget of [`ExecutorConfig::mem_pool_size`] from which queries
+    /// reserve their [`ExecutorConfig::per_query_mem_pool_size`]
+    query_memory_budget: Option<Arc<QueryMemoryBudget>>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -119,6 +145,8 @@ impl Executor {
             num_threads,
             target_query_partitions: num_threads,
             object_stores: HashMap::default(),
+            mem_pool_size: None,
+            per_query_mem_pool_size: None,
         })
     }
 
@@ -134,6 +162,8 @@ impl Executor {
             num_threads: 1,
             target_query_partitions: 1,
             object_stores: HashMap::default(),
+            mem_pool_size: None,
+            per_query_mem_pool_size: None,
         };
         let executors = Arc::new(DedicatedExecutors::new_testing());
         Self::new_with_config_and_executors(config, executors)
@@ -151,7 +181,10 @@ impl Executor {
     ) -> Self {
         assert_eq!(config.num_threads, executors.num_threads);
 
-        let runtime_config = RuntimeConfig::new();
+        let mut runtime_config = RuntimeConfig::new();
+        if let Some(mem_pool_size) = config.mem_pool_size {
+            runtime_config = runtime_config.with_memory_limit(mem_pool_size, 1.0);
+        }
 
         for (id, store) in &config.object_stores {
             runtime_config
@@ -160,11 +193,15 @@ impl Executor {
         }
 
         let runtime = Arc::new(RuntimeEnv::new(runtime_config).expect("creating runtime"));
+        let query_memory_budget = config
+            .mem_pool_size
+            .map(|mem_pool_size| Arc::new(QueryMemoryBudget::new(mem_pool_size)));
 
         Self {
             executors,
             config,
             runtime,
+            query_memory_budget,
         }
     }
 
@@ -173,8 +210,34 @@ impl Executor {
     /// Note that this context (and all its clones) will be shut down once `Executor` is dropped.
     pub fn new_execution_config(&self, executor_type: ExecutorType) -> IOxSessionConfig {
         let exec = self.executor(executor_type).clone();
-        IOxSessionConfig::new(exec, Arc::clone(&self.runtime))
-            .with_target_partitions(self.config.target_query_partitions)
+        let config = match (executor_type, self.config.per_query_mem_pool_size) {
+            (ExecutorType::Query, Some(per_query_mem_pool_size)) => {
+                let config =
+                    IOxSessionConfig::new(exec, self.new_query_runtime(per_query_mem_pool_size));
+                match &self.query_memory_budget {
+                    Some(budget) => {
+                        config.with_memory_reservation(budget.reserve(per_query_mem_pool_size))
+                    }
+                    None => config,
+                }
+            }
+            _ => IOxSessionConfig::new(exec, Arc::clone(&self.runtime)),
+        };
+        config.with_target_partitions(self.config.target_query_partitions)
+    }
+
+    /// Create a [RuntimeEnv] for a single query, with a memory manager
+    /// limited to `mem_pool_size` bytes but otherwise sharing the object
+    /// stores and disk manager of the shared runtime.
+    fn new_query_runtime(&self, mem_pool_size: usize) -> Arc<RuntimeEnv> {
+        let runtime_config = RuntimeConfig::new()
+            .with_object_store_registry(Arc::clone(&self.runtime.object_store_registry))
+            .with_disk_manager(DiskManagerConfig::new_existing(Arc::clone(
+                &self.runtime.disk_manager,
+            )))
+            .with_memory_limit(mem_pool_size, 1.0);
+
+        Arc::new(RuntimeEnv::new(runtime_config).expect("creating runtime"))
     }
 
     /// Get IOx context from DataFusion state.
@@ -342,7 +405,9 @@ mod tests {
     };
     use datafusion::{
         datasource::{provider_as_source, MemTable},
+        error::DataFusionError,
         logical_expr::LogicalPlanBuilder,
+        physical_plan::{empty::EmptyExec, ExecutionPlan},
     };
     use stringset::StringSet;
 
@@ -351,6 +416,77 @@ mod tests {
     use crate::plan::stringset::StringSetPlan;
     use arrow::record_batch::RecordBatch;
 
+    #[tokio::test]
+    async fn executor_per_query_runtime() {
+        let config = ExecutorConfig {
+            num_threads: 1,
+            target_query_partitions: 1,
+            object_stores: HashMap::default(),
+            mem_pool_size: Some(1024),
+            per_query_mem_pool_size: Some(512),
+        };
+        let exec = Executor::new_with_config_and_executors(
+            config,
+            Arc::new(DedicatedExecutors::new_testing()),
+        );
+
+        // Each query has its own runtime (and memory budget)...
+        let q1 = exec.new_context(ExecutorType::Query).inner().runtime_env();
+        let q2 = exec.new_context(ExecutorType::Query).inner().runtime_env();
+        assert!(!Arc::ptr_eq(&q1, &q2));
+        assert!(!Arc::ptr_eq(&q1, &exec.runtime));
+
+        // ...sharing the object stores of the shared runtime.
+        assert!(Arc::ptr_eq(
+            &q1.object_store_registry,
+            &exec.runtime.object_store_registry
+        ));
+
+        // Other executions use the shared runtime.
+        let reorg = exec.new_context(ExecutorType::Reorg).inner().runtime_env();
+        assert!(Arc::ptr_eq(&reorg, &exec.runtime));
+    }
+
+    #[tokio::test]
+    async fn executor_per_query_budget_reserved_from_shared_budget() {
+        let config = ExecutorConfig {
+            num_threads: 1,
+            target_query_partitions: 1,
+            object_stores: HashMap::default(),
+            mem_pool_size: Some(1024),
+            per_query_mem_pool_size: Some(512),
+        };
+        let exec = Executor::new_with_config_and_executors(
+            config,
+            Arc::new(DedicatedExecutors::new_testing()),
+        );
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, true)]));
+        let plan: Arc<dyn ExecutionPlan> = Arc::new(EmptyExec::new(false, schema));
+
+        // Two queries reserve the whole shared budget...
+        let q1 = exec.new_context(ExecutorType::Query);
+        let q2 = exec.new_context(ExecutorType::Query);
+        q1.collect(Arc::clone(&plan)).await.unwrap();
+        q2.collect(Arc::clone(&plan)).await.unwrap();
+
+        // ...so a third query can not execute...
+        let q3 = exec.new_context(ExecutorType::Query);
+        let err = q3.collect(Arc::clone(&plan)).await.unwrap_err();
+        assert!(
+            matches!(err, DataFusionError::ResourcesExhausted(_)),
+            "expected ResourcesExhausted, got {err:?}"
+        );
+
+        // ...until another query completes.
+        drop(q1);
+        let q4 = exec.new_context(ExecutorType::Query);
+        q4.collect(Arc::clone(&plan)).await.unwrap();
+
+        // Other executions do not reserve a per-query budget.
+        let reorg = exec.new_context(ExecutorType::Reorg);
+        reorg.collect(plan).await.unwrap();
+    }
+
     #[tokio::test]
     async fn executor_known_string_set_plan_ok() {
         let expected_strings = to_set(&["Foo", "Bar"]);
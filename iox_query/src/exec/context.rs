@@ -10,6 +10,7 @@ use crate::{
     exec::{
         fieldlist::{FieldList, IntoFieldList},
         non_null_checker::NonNullCheckerExec,
+        query_memory_budget::QueryMemoryReservation,
         query_tracing::TracedStream,
         schema_pivot::{SchemaPivotExec, SchemaPivotNode},
         seriesset::{
@@ -205,6 +206,15 @@ impl IOxSessionConfig {
         Self { span_ctx, ..self }
     }
 
+    /// Hold the memory budget `reservation` of this query until the session
+    /// and all plans executed by it are dropped
+    pub(super) fn with_memory_reservation(self, reservation: QueryMemoryReservation) -> Self {
+        Self {
+            session_config: self.session_config.with_extension(Arc::new(reservation)),
+            ..self
+        }
+    }
+
     /// Create an ExecutionContext suitable for executing DataFusion plans
     pub fn build(self) -> IOxSessionContext {
         let state = SessionState::with_config_rt(self.session_config, self.runtime)
@@ -396,6 +406,16 @@ impl IOxSessionContext {
             .span()
             .map(|span| span.child("execute_stream_partitioned"));
 
+        if let Some(reservation) = self
+            .inner
+            .state
+            .read()
+            .config
+            .get_extension::<QueryMemoryReservation>()
+        {
+            reservation.check()?;
+        }
+
         let task_context = Arc::new(TaskContext::from(self.inner()));
 
         self.run(async move {
@@ -0,0 +1,122 @@
+//! The memory budget shared by the queries of an [`Executor`](super::Executor).
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use datafusion::error::{DataFusionError, Result};
+
+/// A memory budget of `limit` bytes, from which each query reserves its own
+/// memory budget for as long as it executes.
+///
+/// The memory used by a query is limited to its own budget by the DataFusion
+/// memory manager of its runtime, so the memory used by all queries holding a
+/// [`QueryMemoryReservation`] is limited to the shared budget.
+#[derive(Debug)]
+pub(crate) struct QueryMemoryBudget {
+    limit: usize,
+    reserved: AtomicUsize,
+}
+
+impl QueryMemoryBudget {
+    /// Create a budget of `limit` bytes.
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            reserved: AtomicUsize::new(0),
+        }
+    }
+
+    /// Reserve `bytes` of the budget until the returned reservation is
+    /// dropped.
+    ///
+    /// The reservation is [`QueryMemoryReservation::Exhausted`] if less than
+    /// `bytes` of the budget are left.
+    pub(crate) fn reserve(self: &Arc<Self>, bytes: usize) -> QueryMemoryReservation {
+        match self
+            .reserved
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |reserved| {
+                let new_reserved = reserved.checked_add(bytes)?;
+                (new_reserved <= self.limit).then_some(new_reserved)
+            }) {
+            Ok(_) => QueryMemoryReservation::Reserved {
+                budget: Arc::clone(self),
+                bytes,
+            },
+            Err(reserved) => QueryMemoryReservation::Exhausted(format!(
+                "Failed to reserve {} bytes for query with {} bytes already reserved by other \
+                queries - maximum available is {}",
+                bytes,
+                reserved,
+                self.limit.saturating_sub(reserved),
+            )),
+        }
+    }
+
+    /// The number of bytes currently reserved by queries.
+    pub(crate) fn reserved(&self) -> usize {
+        self.reserved.load(Ordering::SeqCst)
+    }
+}
+
+/// The memory budget a query reserved from a [`QueryMemoryBudget`], released
+/// when dropped.
+#[derive(Debug)]
+pub(crate) enum QueryMemoryReservation {
+    /// `bytes` of `budget` are reserved for the query.
+    Reserved {
+        budget: Arc<QueryMemoryBudget>,
+        bytes: usize,
+    },
+
+    /// The shared budget was exhausted, so the query must not execute.
+    Exhausted(String),
+}
+
+impl QueryMemoryReservation {
+    /// Returns [`DataFusionError::ResourcesExhausted`] if the query could not
+    /// reserve its memory budget.
+    pub(crate) fn check(&self) -> Result<()> {
+        match self {
+            Self::Reserved { .. } => Ok(()),
+            Self::Exhausted(msg) => Err(DataFusionError::ResourcesExhausted(msg.clone())),
+        }
+    }
+}
+
+impl Drop for QueryMemoryReservation {
+    fn drop(&mut self) {
+        if let Self::Reserved { budget, bytes } = self {
+            budget.reserved.fetch_sub(*bytes, Ordering::SeqCst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserve() {
+        let budget = Arc::new(QueryMemoryBudget::new(100));
+
+        let r1 = budget.reserve(60);
+        r1.check().unwrap();
+        assert_eq!(budget.reserved(), 60);
+
+        let r2 = budget.reserve(60);
+        assert!(matches!(
+            r2.check(),
+            Err(DataFusionError::ResourcesExhausted(_))
+        ));
+        assert_eq!(budget.reserved(), 60);
+        drop(r2);
+        assert_eq!(budget.reserved(), 60);
+
+        drop(r1);
+        assert_eq!(budget.reserved(), 0);
+        budget.reserve(100).check().unwrap();
+        assert_eq!(budget.reserved(), 0);
+    }
+}
@@ -89,6 +89,8 @@ impl TestCatalog {
                     parquet_store.id(),
                     Arc::clone(parquet_store.object_store()),
                 )]),
+                mem_pool_size: None,
+                per_query_mem_pool_size: None,
             },
             exec,
         ));
@@ -609,6 +611,7 @@ impl TestPartition {
             max_sequence_number,
             compaction_level: CompactionLevel::Initial,
             sort_key: Some(sort_key.clone()),
+            provenance: None,
         };
         let real_file_size_bytes = create_parquet_file(
             ParquetStorage::new(
@@ -1,13 +1,19 @@
 use async_trait::async_trait;
-use clap_blocks::ingester2::Ingester2Config;
+use clap_blocks::ingester2::{
+    Ingester2Config, ParquetCompression, QueryResponseCompression, ReplicationPolicy,
+};
 use hyper::{Body, Request, Response};
-use ingester2::{IngesterGuard, IngesterRpcInterface};
+use ingester2::{
+    IngesterConfig, IngesterGuard, IngesterIdentity, IngesterRpcInterface, PersistWorkers,
+    QueryConcurrency, Readiness,
+};
 use iox_catalog::interface::Catalog;
 use iox_query::exec::Executor;
 use ioxd_common::{
     add_service,
     http::error::{HttpApiError, HttpApiErrorCode, HttpApiErrorSource},
-    rpc::RpcBuilderInput,
+    reexport::tonic_health::ServingStatus,
+    rpc::{service_name, RpcBuilderInput},
     serve_builder,
     server_type::{CommonServerState, RpcError, ServerType},
     setup_builder,
@@ -21,6 +27,7 @@ use std::{
 };
 use thiserror::Error;
 use tokio_util::sync::CancellationToken;
+use tonic::codec::CompressionEncoding;
 use trace::TraceCollector;
 
 #[derive(Debug, Error)]
@@ -36,7 +43,10 @@ struct IngesterServerType<I: IngesterRpcInterface> {
     shutdown: CancellationToken,
     metrics: Arc<Registry>,
     trace_collector: Option<Arc<dyn TraceCollector>>,
-    max_simultaneous_queries: usize,
+    query_concurrency: QueryConcurrency,
+    query_response_compression: Option<CompressionEncoding>,
+    enable_buffer_debug_rpc: bool,
+    enable_persist_admin_rpc: bool,
 }
 
 impl<I: IngesterRpcInterface> IngesterServerType<I> {
@@ -44,14 +54,20 @@ impl<I: IngesterRpcInterface> IngesterServerType<I> {
         server: IngesterGuard<I>,
         metrics: Arc<Registry>,
         common_state: &CommonServerState,
-        max_simultaneous_queries: usize,
+        query_concurrency: QueryConcurrency,
+        query_response_compression: Option<CompressionEncoding>,
+        enable_buffer_debug_rpc: bool,
+        enable_persist_admin_rpc: bool,
     ) -> Self {
         Self {
             server,
             shutdown: CancellationToken::new(),
             metrics,
             trace_collector: common_state.trace_collector(),
-            max_simultaneous_queries,
+            query_concurrency,
+            query_response_compression,
+            enable_buffer_debug_rpc,
+            enable_persist_admin_rpc,
         }
     }
 }
@@ -86,22 +102,73 @@ impl<I: IngesterRpcInterface + Sync + Send + Debug + 'static> ServerType for Ing
     async fn server_grpc(self: Arc<Self>, builder_input: RpcBuilderInput) -> Result<(), RpcError> {
         let builder = setup_builder!(builder_input, self);
 
+        let write_service = self.server.rpc().write_service();
+        let query_service = self
+            .server
+            .rpc()
+            .query_service(self.query_concurrency, self.query_response_compression);
+        let gated_services = [service_name(&write_service), service_name(&query_service)];
+
         add_service!(builder, self.server.rpc().catalog_service());
-        add_service!(builder, self.server.rpc().write_service());
-        add_service!(
-            builder,
-            self.server
-                .rpc()
-                .query_service(self.max_simultaneous_queries)
-        );
+        add_service!(builder, write_service);
+        add_service!(builder, self.server.rpc().write_status_service());
+        add_service!(builder, query_service);
+
+        let builder = if self.enable_buffer_debug_rpc {
+            add_service!(builder, self.server.rpc().buffer_debug_service());
+            builder
+        } else {
+            builder
+        };
+
+        let builder = if self.enable_persist_admin_rpc {
+            add_service!(builder, self.server.rpc().persist_service());
+            builder
+        } else {
+            builder
+        };
+
+        // Report the write and query services as not serving until the
+        // ingester has replayed its WAL and accepts requests.
+        if !self.server.rpc().readiness().is_ready() {
+            let mut health_reporter = builder.health_reporter.clone();
+            for name in gated_services {
+                health_reporter
+                    .set_service_status(name, ServingStatus::NotServing)
+                    .await;
+            }
+
+            let this = Arc::clone(&self);
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(READINESS_POLL_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    match this.server.rpc().readiness() {
+                        Readiness::Ready => break,
+                        // The ingester never becomes ready.
+                        Readiness::ReplayFailed => return,
+                        Readiness::ReplayingWal { .. } => {}
+                    }
+                }
+
+                for name in gated_services {
+                    health_reporter
+                        .set_service_status(name, ServingStatus::Serving)
+                        .await;
+                }
+            });
+        }
 
         serve_builder!(builder);
 
         Ok(())
     }
 
+    /// Wait for shutdown to be requested, then gracefully stop the ingester,
+    /// persisting all buffered data.
     async fn join(self: Arc<Self>) {
         self.shutdown.cancelled().await;
+        self.server.shutdown().await;
     }
 
     fn shutdown(&self) {
@@ -139,7 +206,11 @@ impl HttpApiErrorSource for IoxHttpError {
 
 const PERSIST_BACKGROUND_FETCH_TIME: Duration = Duration::from_secs(30);
 
-/// Instantiate an ingester server type
+/// How often the readiness of the ingester is checked until it is ready.
+const READINESS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Instantiate an ingester server type, recording `version` as the build
+/// version of the parquet files it persists.
 pub async fn create_ingester_server_type(
     common_state: &CommonServerState,
     catalog: Arc<dyn Catalog>,
@@ -147,18 +218,82 @@ pub async fn create_ingester_server_type(
     ingester_config: &Ingester2Config,
     exec: Arc<Executor>,
     object_store: ParquetStorage,
+    version: &str,
 ) -> Result<Arc<dyn ServerType>> {
     let grpc = ingester2::new(
         catalog,
         Arc::clone(&metrics),
-        PERSIST_BACKGROUND_FETCH_TIME,
-        ingester_config.wal_directory.clone(),
-        Duration::from_secs(ingester_config.wal_rotation_period_seconds),
         exec,
-        ingester_config.persist_submission_queue_depth,
-        ingester_config.persist_max_parallelism,
-        ingester_config.persist_worker_queue_depth,
         object_store,
+        IngesterConfig {
+            persist_background_fetch_time: PERSIST_BACKGROUND_FETCH_TIME,
+            partition_cache_size: ingester_config.partition_cache_size,
+            partition_cache_max_bytes: ingester_config.partition_cache_max_bytes,
+            partition_cache_ttl: ingester_config.partition_cache_ttl,
+            wal_directory: ingester_config.wal_directory.clone(),
+            wal_rotation_period: Duration::from_secs(ingester_config.wal_rotation_period_seconds),
+            wal_replay_concurrency: ingester_config.wal_replay_concurrency,
+            wal_replay_namespace_concurrency: ingester_config.wal_replay_namespace_concurrency,
+            wal_preallocate_bytes: ingester_config.wal_preallocate_bytes,
+            wal_max_bytes: ingester_config.wal_max_bytes,
+            wal_eviction_threshold_bytes: ingester_config.wal_eviction_threshold_bytes,
+            wal_rotation_max_persist_backlog: ingester_config.wal_rotation_max_persist_backlog,
+            wal_rotation_backlog_reject_writes: ingester_config.wal_rotation_backlog_reject_writes,
+            persist_submission_queue_depth: ingester_config.persist_submission_queue_depth,
+            persist_workers: if ingester_config.persist_autoscale {
+                // The number of workers never drops below 1, nor exceeds the
+                // configured maximum.
+                let max = ingester_config.persist_max_parallelism.max(1);
+                PersistWorkers::Autoscale {
+                    min: ingester_config.persist_min_parallelism.get().min(max),
+                    max,
+                    target_latency: ingester_config.persist_autoscale_target_latency,
+                }
+            } else {
+                PersistWorkers::Fixed(ingester_config.persist_max_parallelism)
+            },
+            persist_worker_queue_depth: ingester_config.persist_worker_queue_depth,
+            persist_hot_partition_bytes: ingester_config.persist_hot_partition_bytes,
+            persist_hot_partition_rows: ingester_config.persist_hot_partition_rows,
+            persist_hot_partition_cold_age: ingester_config.persist_hot_partition_cold_age,
+            persist_parquet_compression: match ingester_config.persist_parquet_compression {
+                ParquetCompression::Uncompressed => {
+                    parquet_file::serialize::ParquetCompression::Uncompressed
+                }
+                ParquetCompression::Snappy => parquet_file::serialize::ParquetCompression::Snappy,
+                ParquetCompression::Zstd => parquet_file::serialize::ParquetCompression::Zstd,
+            },
+            persist_parquet_dictionary: !ingester_config.disable_persist_parquet_dictionary,
+            persist_parquet_tag_indexes: !ingester_config.disable_persist_parquet_tag_indexes,
+            persist_max_file_bytes: ingester_config.persist_max_parquet_file_bytes,
+            persist_max_attempts: ingester_config.persist_max_attempts,
+            persist_namespace_max_jobs: ingester_config.persist_namespace_max_jobs,
+            persist_verify_parquet: ingester_config.persist_verify_parquet,
+            persist_file_cache_bytes: ingester_config.persist_file_cache_bytes,
+            identity: IngesterIdentity {
+                host: ingester_config
+                    .host_identity
+                    .clone()
+                    .or_else(|| std::env::var("HOSTNAME").ok())
+                    .unwrap_or_else(|| "unknown".to_string()),
+                version: version.to_string(),
+            },
+            partition_buffer_max_bytes: ingester_config.partition_buffer_max_bytes,
+            partition_buffer_max_rows: ingester_config.partition_buffer_max_rows,
+            buffer_soft_limit_bytes: ingester_config.buffer_soft_limit_bytes,
+            buffer_hard_limit_bytes: ingester_config.buffer_hard_limit_bytes,
+            namespace_write_rows_per_second: ingester_config.namespace_write_rows_per_second,
+            namespace_write_bytes_per_second: ingester_config.namespace_write_bytes_per_second,
+            idempotency_cache_size: ingester_config.idempotency_cache_size,
+            query_memory_limit_bytes: ingester_config.query_memory_limit_bytes,
+            query_memory_global_limit_bytes: ingester_config.query_memory_global_limit_bytes,
+            replication_peers: ingester_config.replication_peers.clone(),
+            replication_policy: match ingester_config.replication_policy {
+                ReplicationPolicy::All => ingester2::ReplicationPolicy::All,
+                ReplicationPolicy::Quorum => ingester2::ReplicationPolicy::Quorum,
+                ReplicationPolicy::Async => ingester2::ReplicationPolicy::Async,
+            },
+        },
     )
     .await?;
 
@@ -166,6 +301,26 @@ pub async fn create_ingester_server_type(
         grpc,
         metrics,
         common_state,
-        ingester_config.concurrent_query_limit,
+        if ingester_config.adaptive_query_concurrency {
+            // The adaptive limit never drops below 1, nor exceeds the
+            // configured limit.
+            let max = ingester_config.concurrent_query_limit.max(1);
+            QueryConcurrency::Adaptive {
+                min: ingester_config
+                    .adaptive_query_concurrency_min
+                    .get()
+                    .min(max),
+                max,
+                target_latency: ingester_config.adaptive_query_target_latency,
+            }
+        } else {
+            QueryConcurrency::Fixed(ingester_config.concurrent_query_limit)
+        },
+        match ingester_config.query_response_compression {
+            QueryResponseCompression::None => None,
+            QueryResponseCompression::Gzip => Some(CompressionEncoding::Gzip),
+        },
+        ingester_config.enable_buffer_debug_rpc,
+        ingester_config.enable_persist_admin_rpc,
     )))
 }
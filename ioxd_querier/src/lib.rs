@@ -195,17 +195,27 @@ pub async fn create_querier_server_type(
         )),
     };
 
-    let database = Arc::new(
-        QuerierDatabase::new(
-            catalog_cache,
-            Arc::clone(&args.metric_registry),
-            args.exec,
-            ingester_connection,
-            args.querier_config.max_concurrent_queries(),
-            args.querier_config.max_table_query_bytes(),
-        )
-        .await?,
-    );
+    let mut database = QuerierDatabase::new(
+        catalog_cache,
+        Arc::clone(&args.metric_registry),
+        args.exec,
+        ingester_connection,
+        args.querier_config.max_concurrent_queries(),
+        args.querier_config.max_table_query_bytes(),
+    )
+    .await?;
+    if let Some(max_concurrent_queries) = args.querier_config.max_concurrent_queries_per_namespace()
+    {
+        database = database.with_namespace_query_limits(
+            max_concurrent_queries,
+            args.querier_config.max_queued_queries_per_namespace(),
+        )?;
+    }
+    if let Some(ram_pool_bytes) = args.querier_config.query_result_cache_bytes() {
+        database = database
+            .with_result_cache(ram_pool_bytes, args.querier_config.query_result_cache_ttl());
+    }
+    let database = Arc::new(database);
     let querier_handler = Arc::new(QuerierHandlerImpl::new(
         args.catalog,
         Arc::clone(&database),
@@ -53,6 +53,11 @@ impl ParquetFilePath {
         }
     }
 
+    /// Get the namespace the file belongs to.
+    pub fn namespace_id(&self) -> NamespaceId {
+        self.namespace_id
+    }
+
     /// Get object-store path.
     pub fn object_store_path(&self) -> Path {
         let Self {
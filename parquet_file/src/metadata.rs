@@ -296,6 +296,32 @@ pub struct IoxMetadata {
 
     /// Sort key of this chunk
     pub sort_key: Option<SortKey>,
+
+    /// The ingester that persisted this file, if recorded.
+    ///
+    /// Files written by the compactor or by an external process carry no
+    /// provenance.
+    pub provenance: Option<Provenance>,
+}
+
+/// The ingester that persisted a parquet file, and the writes it persisted,
+/// for auditing the origin of the data in a file.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Provenance {
+    /// The identity of the ingester host.
+    pub host: Arc<str>,
+
+    /// The build version (and commit) of the ingester.
+    pub version: Arc<str>,
+
+    /// The smallest WAL sequence number of the writes persisted in the file.
+    pub min_sequence_number: SequenceNumber,
+
+    /// The largest WAL sequence number of the writes persisted in the file.
+    pub max_sequence_number: SequenceNumber,
+
+    /// Timestamp when the ingester persisted the file.
+    pub persisted_at: Time,
 }
 
 impl IoxMetadata {
@@ -339,6 +365,13 @@ impl IoxMetadata {
             max_sequence_number: self.max_sequence_number.get(),
             sort_key,
             compaction_level: self.compaction_level as i32,
+            provenance: self.provenance.as_ref().map(|p| proto::Provenance {
+                host: p.host.to_string(),
+                version: p.version.to_string(),
+                min_sequence_number: p.min_sequence_number.get(),
+                max_sequence_number: p.max_sequence_number.get(),
+                persisted_at: Some(p.persisted_at.date_time().into()),
+            }),
         };
 
         let mut buf = Vec::new();
@@ -372,6 +405,23 @@ impl IoxMetadata {
             builder.build()
         });
 
+        // provenance
+        let provenance = proto_msg
+            .provenance
+            .map(|p| {
+                Ok(Provenance {
+                    host: Arc::from(p.host),
+                    version: Arc::from(p.version),
+                    min_sequence_number: SequenceNumber::new(p.min_sequence_number),
+                    max_sequence_number: SequenceNumber::new(p.max_sequence_number),
+                    persisted_at: decode_timestamp_from_field(
+                        p.persisted_at,
+                        "provenance.persisted_at",
+                    )?,
+                })
+            })
+            .transpose()?;
+
         Ok(Self {
             object_store_id: parse_uuid(&proto_msg.object_store_id)?.ok_or_else(|| {
                 Error::IoxMetadataFieldMissing {
@@ -393,6 +443,7 @@ impl IoxMetadata {
                     compaction_level: proto_msg.compaction_level,
                 },
             )?,
+            provenance,
         })
     }
 
@@ -414,6 +465,7 @@ impl IoxMetadata {
             max_sequence_number: SequenceNumber::new(1),
             compaction_level: CompactionLevel::Initial,
             sort_key: None,
+            provenance: None,
         }
     }
 
@@ -1013,6 +1065,25 @@ mod tests {
             max_sequence_number: SequenceNumber::new(6),
             compaction_level: CompactionLevel::Initial,
             sort_key: Some(sort_key),
+            provenance: None,
+        };
+
+        let proto = iox_metadata.to_protobuf().unwrap();
+
+        let iox_metadata_again = IoxMetadata::from_protobuf(&proto).unwrap();
+
+        assert_eq!(iox_metadata, iox_metadata_again);
+
+        // The provenance of an ingester file round trips too.
+        let iox_metadata = IoxMetadata {
+            provenance: Some(Provenance {
+                host: Arc::from("ingester-0"),
+                version: Arc::from("0.1.0, revision 1234"),
+                min_sequence_number: SequenceNumber::new(2),
+                max_sequence_number: SequenceNumber::new(6),
+                persisted_at: Time::from_timestamp(3240, 0).unwrap(),
+            }),
+            ..iox_metadata
         };
 
         let proto = iox_metadata.to_protobuf().unwrap();
@@ -1037,6 +1108,7 @@ mod tests {
             max_sequence_number: SequenceNumber::new(11),
             compaction_level: CompactionLevel::FileNonOverlapped,
             sort_key: None,
+            provenance: None,
         };
 
         let array = StringArray::from_iter([Some("bananas")]);
@@ -1057,9 +1129,10 @@ mod tests {
         let batch = RecordBatch::try_new(schema, vec![data, timestamps]).unwrap();
         let stream = Box::pin(MemoryStream::new(vec![batch.clone()]));
 
-        let (bytes, file_meta) = crate::serialize::to_parquet_bytes(stream, &meta)
-            .await
-            .expect("should serialize");
+        let (bytes, file_meta) =
+            crate::serialize::to_parquet_bytes(stream, &meta, Default::default())
+                .await
+                .expect("should serialize");
 
         // Verify if the parquet file meta data has values
         assert!(!file_meta.row_groups.is_empty());
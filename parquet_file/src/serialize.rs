@@ -4,7 +4,7 @@
 
 use std::{io::Write, sync::Arc};
 
-use arrow::error::ArrowError;
+use arrow::{datatypes::SchemaRef, error::ArrowError};
 use datafusion::physical_plan::SendableRecordBatchStream;
 use datafusion_util::config::BATCH_SIZE;
 use futures::{pin_mut, TryStreamExt};
@@ -13,8 +13,13 @@ use parquet::{
     arrow::ArrowWriter,
     basic::Compression,
     errors::ParquetError,
-    file::{metadata::KeyValue, properties::WriterProperties},
+    file::{
+        metadata::KeyValue,
+        properties::{EnabledStatistics, WriterProperties},
+    },
+    schema::types::ColumnPath,
 };
+use schema::Schema;
 use thiserror::Error;
 
 use crate::metadata::{IoxMetadata, METADATA_KEY};
@@ -27,6 +32,56 @@ pub const ROW_GROUP_WRITE_SIZE: usize = 1024 * 1024;
 #[allow(clippy::assertions_on_constants)]
 const _: () = assert!(ROW_GROUP_WRITE_SIZE % BATCH_SIZE == 0);
 
+/// The compression codec applied to the column chunks of a parquet file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParquetCompression {
+    /// No compression.
+    Uncompressed,
+    /// Snappy compression - cheap to encode and decode, at the cost of a
+    /// lower compression ratio.
+    Snappy,
+    /// Zstandard compression, using the default compression level.
+    #[default]
+    Zstd,
+}
+
+impl From<ParquetCompression> for Compression {
+    fn from(v: ParquetCompression) -> Self {
+        match v {
+            ParquetCompression::Uncompressed => Self::UNCOMPRESSED,
+            ParquetCompression::Snappy => Self::SNAPPY,
+            ParquetCompression::Zstd => Self::ZSTD,
+        }
+    }
+}
+
+/// Options controlling the encoding of the parquet files written by
+/// [`to_parquet()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriterOptions {
+    /// The compression codec applied to all columns.
+    pub compression: ParquetCompression,
+
+    /// Dictionary encode the columns, falling back to plain encoding once a
+    /// column's dictionary grows too large.
+    pub dictionary_enabled: bool,
+
+    /// Write a bloom filter, and page-level statistics (from which the page
+    /// index is built), for each tag column, allowing readers to prune row
+    /// groups and pages on tag equality predicates without decoding them.
+    pub tag_indexes: bool,
+}
+
+impl Default for WriterOptions {
+    fn default() -> Self {
+        Self {
+            compression: ParquetCompression::Zstd,
+            dictionary_enabled: true,
+            tag_indexes: false,
+        }
+    }
+}
+
 /// [`RecordBatch`] to Parquet serialisation errors.
 ///
 /// [`RecordBatch`]: arrow::record_batch::RecordBatch
@@ -69,6 +124,10 @@ pub enum CodecError {
 /// yielded by the stream must be of the same schema, or this call will return
 /// an error.
 ///
+/// The file is encoded using the codec and dictionary settings of `options`,
+/// indexing the tag columns (identified by the IOx column types in the schema
+/// metadata) if [`WriterOptions::tag_indexes`] is set.
+///
 /// IOx metadata is encoded into the parquet file's metadata under the key
 /// [`METADATA_KEY`], with a base64-wrapped, protobuf serialized
 /// [`proto::IoxMetadata`] structure.
@@ -92,6 +151,7 @@ pub enum CodecError {
 pub async fn to_parquet<W>(
     batches: SendableRecordBatchStream,
     meta: &IoxMetadata,
+    options: WriterOptions,
     sink: W,
 ) -> Result<parquet::format::FileMetaData, CodecError>
 where
@@ -105,7 +165,7 @@ where
     pin_mut!(stream);
 
     // Serialize the IoxMetadata to the protobuf bytes.
-    let props = writer_props(meta)?;
+    let props = writer_props(meta, &schema, options)?;
     let write_batch_size = props.write_batch_size();
     let max_row_group_size = props.max_row_group_size();
 
@@ -142,6 +202,7 @@ where
 pub async fn to_parquet_bytes(
     batches: SendableRecordBatchStream,
     meta: &IoxMetadata,
+    options: WriterOptions,
 ) -> Result<(Vec<u8>, parquet::format::FileMetaData), CodecError> {
     let mut bytes = vec![];
 
@@ -153,7 +214,7 @@ pub async fn to_parquet_bytes(
     );
 
     // Serialize the record batches into the in-memory buffer
-    let meta = to_parquet(batches, meta, &mut bytes).await?;
+    let meta = to_parquet(batches, meta, options, &mut bytes).await?;
     bytes.shrink_to_fit();
 
     trace!(?partition_id, ?meta, "generated parquet file metadata");
@@ -163,16 +224,34 @@ pub async fn to_parquet_bytes(
 
 /// Helper to construct [`WriterProperties`] for the [`ArrowWriter`],
 /// serialising the given [`IoxMetadata`] and embedding it as a key=value
-/// property keyed by [`METADATA_KEY`].
-fn writer_props(meta: &IoxMetadata) -> Result<WriterProperties, prost::EncodeError> {
-    let builder = WriterProperties::builder()
+/// property keyed by [`METADATA_KEY`], and encoding the file of `schema` as
+/// configured by `options`.
+fn writer_props(
+    meta: &IoxMetadata,
+    schema: &SchemaRef,
+    options: WriterOptions,
+) -> Result<WriterProperties, prost::EncodeError> {
+    let mut builder = WriterProperties::builder()
         .set_key_value_metadata(Some(vec![KeyValue {
             key: METADATA_KEY.to_string(),
             value: Some(meta.to_base64()?),
         }]))
-        .set_compression(Compression::ZSTD)
+        .set_compression(options.compression.into())
+        .set_dictionary_enabled(options.dictionary_enabled)
         .set_max_row_group_size(ROW_GROUP_WRITE_SIZE);
 
+    if options.tag_indexes {
+        // A schema without IOx column types has no tag columns.
+        if let Ok(schema) = Schema::try_from(Arc::clone(schema)) {
+            for tag in schema.tags_iter() {
+                let column = ColumnPath::from(tag.name().as_str());
+                builder = builder
+                    .set_column_bloom_filter_enabled(column.clone(), true)
+                    .set_column_statistics_enabled(column, EnabledStatistics::Page);
+            }
+        }
+    }
+
     Ok(builder.build())
 }
 
@@ -181,7 +260,8 @@ mod tests {
     use super::*;
     use crate::metadata::IoxParquetMetaData;
     use arrow::{
-        array::{ArrayRef, StringArray},
+        array::{ArrayRef, DictionaryArray, StringArray, TimestampNanosecondArray},
+        datatypes::Int32Type,
         record_batch::RecordBatch,
     };
     use bytes::Bytes;
@@ -189,6 +269,7 @@ mod tests {
     use datafusion::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
     use datafusion_util::MemoryStream;
     use iox_time::Time;
+    use schema::builder::SchemaBuilder;
     use std::sync::Arc;
 
     #[tokio::test]
@@ -206,12 +287,13 @@ mod tests {
             max_sequence_number: SequenceNumber::new(11),
             compaction_level: CompactionLevel::FileNonOverlapped,
             sort_key: None,
+            provenance: None,
         };
 
         let batch = RecordBatch::try_from_iter([("a", to_string_array(&["value"]))]).unwrap();
         let stream = Box::pin(MemoryStream::new(vec![batch.clone()]));
 
-        let (bytes, _file_meta) = to_parquet_bytes(stream, &meta)
+        let (bytes, _file_meta) = to_parquet_bytes(stream, &meta, WriterOptions::default())
             .await
             .expect("should serialize");
 
@@ -244,6 +326,117 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_writer_options() {
+        let meta = IoxMetadata {
+            object_store_id: Default::default(),
+            creation_timestamp: Time::from_timestamp_nanos(42),
+            namespace_id: NamespaceId::new(1),
+            namespace_name: "bananas".into(),
+            shard_id: ShardId::new(2),
+            table_id: TableId::new(3),
+            table_name: "platanos".into(),
+            partition_id: PartitionId::new(4),
+            partition_key: "potato".into(),
+            max_sequence_number: SequenceNumber::new(11),
+            compaction_level: CompactionLevel::FileNonOverlapped,
+            sort_key: None,
+            provenance: None,
+        };
+
+        let batch =
+            RecordBatch::try_from_iter([("a", to_string_array(&["value", "value"]))]).unwrap();
+
+        for (compression, dictionary_enabled) in [
+            (ParquetCompression::Zstd, true),
+            (ParquetCompression::Snappy, false),
+            (ParquetCompression::Uncompressed, true),
+        ] {
+            let options = WriterOptions {
+                compression,
+                dictionary_enabled,
+                tag_indexes: false,
+            };
+            let stream = Box::pin(MemoryStream::new(vec![batch.clone()]));
+            let (bytes, _file_meta) = to_parquet_bytes(stream, &meta, options)
+                .await
+                .expect("should serialize");
+
+            let builder = ParquetRecordBatchReaderBuilder::try_new(Bytes::from(bytes))
+                .expect("should init builder");
+            let column = builder.metadata().row_group(0).column(0);
+            assert_eq!(column.compression(), Compression::from(compression));
+            assert_eq!(
+                column.dictionary_page_offset().is_some(),
+                dictionary_enabled
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tag_indexes() {
+        let meta = IoxMetadata {
+            object_store_id: Default::default(),
+            creation_timestamp: Time::from_timestamp_nanos(42),
+            namespace_id: NamespaceId::new(1),
+            namespace_name: "bananas".into(),
+            shard_id: ShardId::new(2),
+            table_id: TableId::new(3),
+            table_name: "platanos".into(),
+            partition_id: PartitionId::new(4),
+            partition_key: "potato".into(),
+            max_sequence_number: SequenceNumber::new(11),
+            compaction_level: CompactionLevel::FileNonOverlapped,
+            sort_key: None,
+            provenance: None,
+        };
+
+        let schema = SchemaBuilder::new()
+            .tag("region")
+            .timestamp()
+            .build()
+            .unwrap()
+            .as_arrow();
+        let batch = RecordBatch::try_new(
+            Arc::clone(&schema),
+            vec![
+                Arc::new(
+                    ["africa", "asia"]
+                        .into_iter()
+                        .collect::<DictionaryArray<Int32Type>>(),
+                ),
+                Arc::new(TimestampNanosecondArray::from(vec![1, 2])),
+            ],
+        )
+        .unwrap();
+
+        let options = WriterOptions {
+            tag_indexes: true,
+            ..Default::default()
+        };
+
+        // Only the tag column is bloom filtered.
+        let region = ColumnPath::from("region");
+        let time = ColumnPath::from("time");
+        let props = writer_props(&meta, &schema, options).unwrap();
+        assert!(props.bloom_filter_properties(&region).is_some());
+        assert!(props.bloom_filter_properties(&time).is_none());
+        assert_eq!(props.statistics_enabled(&region), EnabledStatistics::Page);
+        let props = writer_props(&meta, &schema, WriterOptions::default()).unwrap();
+        assert!(props.bloom_filter_properties(&region).is_none());
+
+        // And the page index is written for it.
+        let stream = Box::pin(MemoryStream::new(vec![batch]));
+        let (bytes, _file_meta) = to_parquet_bytes(stream, &meta, options)
+            .await
+            .expect("should serialize");
+        let builder = ParquetRecordBatchReaderBuilder::try_new(Bytes::from(bytes))
+            .expect("should init builder");
+        let column = builder.metadata().row_group(0).column(0);
+        assert_eq!(column.column_path(), &region);
+        assert!(column.column_index_offset().is_some());
+    }
+
     fn to_string_array(strs: &[&str]) -> ArrayRef {
         let array: StringArray = strs.iter().map(|s| Some(*s)).collect();
         Arc::new(array)
@@ -3,7 +3,7 @@
 
 use crate::{
     metadata::{IoxMetadata, IoxParquetMetaData},
-    serialize::{self, CodecError},
+    serialize::{self, CodecError, WriterOptions},
     ParquetFilePath,
 };
 use arrow::{
@@ -11,10 +11,11 @@ use arrow::{
     record_batch::RecordBatch,
 };
 use bytes::Bytes;
+use data_types::NamespaceId;
 use datafusion::{
     datasource::{listing::PartitionedFile, object_store::ObjectStoreUrl},
     error::DataFusionError,
-    execution::context::TaskContext,
+    execution::{context::TaskContext, runtime_env::RuntimeEnv},
     physical_plan::{
         file_format::{FileScanConfig, ParquetExec},
         ExecutionPlan, SendableRecordBatchStream, Statistics,
@@ -22,10 +23,11 @@ use datafusion::{
     prelude::SessionContext,
 };
 use datafusion_util::config::iox_session_config;
-use object_store::{DynObjectStore, ObjectMeta};
+use object_store::{path::Path, DynObjectStore, ObjectMeta};
 use observability_deps::tracing::*;
 use schema::Projection;
 use std::{
+    collections::HashMap,
     sync::Arc,
     time::{Duration, Instant},
 };
@@ -74,6 +76,35 @@ impl std::fmt::Display for StorageId {
     }
 }
 
+/// The location of the parquet files of a namespace stored apart from those of
+/// the other namespaces of a [`ParquetStorage`], such as to meet the data
+/// residency requirements of a tenant.
+///
+/// The files are stored under the namespace ID within `prefix`, so a prefix
+/// must not itself begin with the ID of a namespace stored at the root of the
+/// same object store.
+#[derive(Debug, Clone, Default)]
+pub struct NamespaceLocation {
+    object_store: Option<Arc<DynObjectStore>>,
+    prefix: Option<Path>,
+}
+
+impl NamespaceLocation {
+    /// Store the files of the namespace in `object_store`, rather than the
+    /// object store of the [`ParquetStorage`].
+    pub fn with_object_store(mut self, object_store: Arc<DynObjectStore>) -> Self {
+        self.object_store = Some(object_store);
+        self
+    }
+
+    /// Store the files of the namespace under `prefix`, rather than at the
+    /// root of the object store.
+    pub fn with_prefix(mut self, prefix: Path) -> Self {
+        self.prefix = Some(prefix);
+        self
+    }
+}
+
 /// Inputs required to build a [`ParquetExec`] for one or multiple files.
 ///
 /// The files shall be grouped by [`object_store_url`](Self::object_store_url). For each each object store, you shall
@@ -157,20 +188,134 @@ pub struct ParquetStorage {
 
     /// Storage ID to hook it into DataFusion.
     id: StorageId,
+
+    /// Encoding options of the parquet files written by
+    /// [`ParquetStorage::upload()`].
+    writer_options: WriterOptions,
+
+    /// The namespaces whose files are stored apart from the default location.
+    namespaces: Arc<HashMap<NamespaceId, NamespaceLocation>>,
 }
 
 impl ParquetStorage {
     /// Initialise a new [`ParquetStorage`] using `object_store` as the
     /// persistence layer.
     pub fn new(object_store: Arc<DynObjectStore>, id: StorageId) -> Self {
-        Self { object_store, id }
+        Self {
+            object_store,
+            id,
+            writer_options: WriterOptions::default(),
+            namespaces: Default::default(),
+        }
+    }
+
+    /// Encode the parquet files pushed to object storage using
+    /// `writer_options`.
+    pub fn with_writer_options(mut self, writer_options: WriterOptions) -> Self {
+        self.writer_options = writer_options;
+        self
+    }
+
+    /// Store the parquet files of `namespace_id` at `location`, rather than
+    /// the default location.
+    ///
+    /// The files are only found through a [`ParquetStorage`] with the same
+    /// location. The querier, compactor and garbage collector do not support
+    /// namespace locations yet, so this must not be used for namespaces they
+    /// serve.
+    pub fn with_namespace_location(
+        mut self,
+        namespace_id: NamespaceId,
+        location: NamespaceLocation,
+    ) -> Self {
+        Arc::make_mut(&mut self.namespaces).insert(namespace_id, location);
+        self
     }
 
     /// Get underlying object store.
+    ///
+    /// This is the object store of all namespaces without a
+    /// [`NamespaceLocation`] - see [`Self::object_store_for()`].
     pub fn object_store(&self) -> &Arc<DynObjectStore> {
         &self.object_store
     }
 
+    /// Get the object store holding the parquet files of `namespace_id`.
+    pub fn object_store_for(&self, namespace_id: NamespaceId) -> &Arc<DynObjectStore> {
+        self.namespaces
+            .get(&namespace_id)
+            .and_then(|v| v.object_store.as_ref())
+            .unwrap_or(&self.object_store)
+    }
+
+    /// Get the object store holding the object at `location`, a path returned
+    /// by [`Self::object_store_path()`] or listed under
+    /// [`Self::namespace_prefix()`].
+    pub fn object_store_for_location(&self, location: &Path) -> &Arc<DynObjectStore> {
+        self.namespaces
+            .keys()
+            .find(|id| {
+                location
+                    .prefix_match(&self.namespace_prefix(**id))
+                    .is_some()
+            })
+            .map(|id| self.object_store_for(*id))
+            .unwrap_or(&self.object_store)
+    }
+
+    /// Get the path under which the parquet files of `namespace_id` are
+    /// stored, within [`Self::object_store_for()`].
+    pub fn namespace_prefix(&self, namespace_id: NamespaceId) -> Path {
+        self.with_prefix(namespace_id, Path::from(namespace_id.to_string()))
+    }
+
+    /// Get the object store path of the parquet file at `path`, within the
+    /// object store of its namespace (see [`Self::object_store_for()`]).
+    pub fn object_store_path(&self, path: &ParquetFilePath) -> Path {
+        self.with_prefix(path.namespace_id(), path.object_store_path())
+    }
+
+    /// Get the DataFusion URL of the object store holding the parquet files of
+    /// `namespace_id`, registered by [`Self::register_object_stores()`].
+    pub fn object_store_url(&self, namespace_id: NamespaceId) -> ObjectStoreUrl {
+        let url = match self.namespaces.get(&namespace_id) {
+            Some(NamespaceLocation {
+                object_store: Some(_),
+                ..
+            }) => format!("iox://{}-ns{}/", self.id, namespace_id),
+            _ => format!("iox://{}/", self.id),
+        };
+        ObjectStoreUrl::parse(url).expect("valid object store URL")
+    }
+
+    /// Register the object stores of the namespaces with a distinct
+    /// [`NamespaceLocation`] object store in `runtime_env`.
+    ///
+    /// The default [`Self::object_store()`] is registered separately by the
+    /// caller, under [`Self::id()`].
+    pub fn register_object_stores(&self, runtime_env: &RuntimeEnv) {
+        for (namespace_id, location) in self.namespaces.iter() {
+            if let Some(object_store) = &location.object_store {
+                runtime_env.register_object_store(
+                    "iox",
+                    format!("{}-ns{}", self.id, namespace_id),
+                    Arc::clone(object_store),
+                );
+            }
+        }
+    }
+
+    fn with_prefix(&self, namespace_id: NamespaceId, path: Path) -> Path {
+        match self
+            .namespaces
+            .get(&namespace_id)
+            .and_then(|v| v.prefix.as_ref())
+        {
+            Some(prefix) => prefix.parts().chain(path.parts()).collect(),
+            None => path,
+        }
+    }
+
     /// Get ID.
     pub fn id(&self) -> StorageId {
         self.id
@@ -185,6 +330,7 @@ impl ParquetStorage {
         task_ctx
             .runtime_env()
             .register_object_store("iox", self.id, object_store);
+        self.register_object_stores(&task_ctx.runtime_env());
 
         session_ctx
     }
@@ -203,8 +349,38 @@ impl ParquetStorage {
         batches: SendableRecordBatchStream,
         meta: &IoxMetadata,
     ) -> Result<(IoxParquetMetaData, usize), UploadError> {
-        let start = Instant::now();
+        self.upload_inner(batches, meta, true).await
+    }
 
+    /// Push `batches`, a stream of [`RecordBatch`] instances, to object
+    /// storage, making a single attempt to write the file.
+    ///
+    /// Unlike [`Self::upload()`], object store errors are returned as
+    /// [`UploadError::Upload`], leaving the caller to decide whether to retry.
+    ///
+    /// [`RecordBatch`]: arrow::record_batch::RecordBatch
+    pub async fn try_upload(
+        &self,
+        batches: SendableRecordBatchStream,
+        meta: &IoxMetadata,
+    ) -> Result<(IoxParquetMetaData, usize), UploadError> {
+        self.upload_inner(batches, meta, false).await
+    }
+
+    /// Encode `batches`, a stream of [`RecordBatch`] instances, into an
+    /// in-memory parquet file described by `meta`, to be written to object
+    /// storage by [`Self::try_put()`].
+    ///
+    /// Together, these calls are equivalent to [`Self::try_upload()`], allowing
+    /// the caller to observe the encoding and the upload of the file
+    /// separately.
+    ///
+    /// [`RecordBatch`]: arrow::record_batch::RecordBatch
+    pub async fn encode(
+        &self,
+        batches: SendableRecordBatchStream,
+        meta: &IoxMetadata,
+    ) -> Result<(Bytes, IoxParquetMetaData), UploadError> {
         // Stream the record batches into a parquet file.
         //
         // It would be nice to stream the encoded parquet to disk for this and
@@ -213,7 +389,8 @@ impl ParquetStorage {
         //
         // This is not a huge concern, as the resulting parquet files are
         // currently smallish on average.
-        let (data, parquet_file_meta) = serialize::to_parquet_bytes(batches, meta).await?;
+        let (data, parquet_file_meta) =
+            serialize::to_parquet_bytes(batches, meta, self.writer_options).await?;
 
         // Read the IOx-specific parquet metadata from the file metadata
         let parquet_meta =
@@ -224,11 +401,29 @@ impl ParquetStorage {
             "IoxParquetMetaData coverted from Row Group Metadata (aka FileMetaData)"
         );
 
-        // Derive the correct object store path from the metadata.
-        let path = ParquetFilePath::from(meta).object_store_path();
+        Ok((Bytes::from(data), parquet_meta))
+    }
+
+    /// Write the parquet file `data`, encoded by [`Self::encode()`] for
+    /// `meta`, to object storage, making a single attempt.
+    pub async fn try_put(&self, data: Bytes, meta: &IoxMetadata) -> Result<(), UploadError> {
+        let path = self.object_store_path(&ParquetFilePath::from(meta));
+        self.object_store_for(meta.namespace_id)
+            .put(&path, data)
+            .await
+            .map_err(UploadError::Upload)
+    }
 
+    async fn upload_inner(
+        &self,
+        batches: SendableRecordBatchStream,
+        meta: &IoxMetadata,
+        retry: bool,
+    ) -> Result<(IoxParquetMetaData, usize), UploadError> {
+        let start = Instant::now();
+
+        let (data, parquet_meta) = self.encode(batches, meta).await?;
         let file_size = data.len();
-        let data = Bytes::from(data);
 
         debug!(
             file_size,
@@ -239,13 +434,16 @@ impl ParquetStorage {
             "Uploading parquet to object store"
         );
 
-        // Retry uploading the file endlessly.
+        // Retry uploading the file endlessly, if requested.
         //
         // This is abort-able by the user by dropping the upload() future.
         //
         // Cloning `data` is a ref count inc, rather than a data copy.
         let mut retried = false;
-        while let Err(e) = self.object_store.put(&path, data.clone()).await {
+        while let Err(e) = self.try_put(data.clone(), meta).await {
+            if !retry {
+                return Err(e);
+            }
             warn!(error=%e, ?meta, "failed to upload parquet file to object storage, retrying");
             tokio::time::sleep(Duration::from_secs(1)).await;
             retried = true;
@@ -268,10 +466,9 @@ impl ParquetStorage {
     /// [`ParquetExec`]: datafusion::physical_plan::file_format::ParquetExec
     pub fn parquet_exec_input(&self, path: &ParquetFilePath, file_size: usize) -> ParquetExecInput {
         ParquetExecInput {
-            object_store_url: ObjectStoreUrl::parse(format!("iox://{}/", self.id))
-                .expect("valid object store URL"),
+            object_store_url: self.object_store_url(path.namespace_id()),
             object_meta: ObjectMeta {
-                location: path.object_store_path(),
+                location: self.object_store_path(path),
                 // we don't care about the "last modified" field
                 last_modified: Default::default(),
                 size: file_size,
@@ -335,6 +532,57 @@ mod tests {
         assert_eq!(got_iox_meta, meta);
     }
 
+    #[tokio::test]
+    async fn test_namespace_location() {
+        let default_store: Arc<DynObjectStore> =
+            Arc::new(object_store::memory::InMemory::default());
+        let eu_store: Arc<DynObjectStore> = Arc::new(object_store::memory::InMemory::default());
+
+        let meta = meta();
+        let store = ParquetStorage::new(Arc::clone(&default_store), StorageId::from("iox"))
+            .with_namespace_location(
+                meta.namespace_id,
+                NamespaceLocation::default()
+                    .with_object_store(Arc::clone(&eu_store))
+                    .with_prefix(Path::from("eu/iox")),
+            );
+
+        let batch = RecordBatch::try_from_iter([("a", to_string_array(&["value"]))]).unwrap();
+        let (_iox_md, file_size) = upload(&store, &meta, batch.clone()).await;
+
+        // The file is written under the prefix, to the store of the namespace.
+        let path = store.object_store_path(&(&meta).into());
+        assert_eq!(
+            path.as_ref(),
+            format!("eu/iox/1/3/2/4/{}.parquet", meta.object_store_id)
+        );
+        assert!(eu_store.head(&path).await.is_ok());
+        assert!(default_store
+            .head(&ParquetFilePath::from(&meta).object_store_path())
+            .await
+            .is_err());
+
+        assert_eq!(
+            store.namespace_prefix(meta.namespace_id).as_ref(),
+            "eu/iox/1"
+        );
+        assert!(Arc::ptr_eq(
+            store.object_store_for_location(&path),
+            &eu_store
+        ));
+
+        // Other namespaces are stored at the default location.
+        let other = NamespaceId::new(2);
+        assert_eq!(store.namespace_prefix(other).as_ref(), "2");
+        assert!(Arc::ptr_eq(store.object_store_for(other), &default_store));
+
+        // The file is read back from the store of the namespace.
+        let got = download(&store, &meta, Projection::All, batch.schema(), file_size)
+            .await
+            .unwrap();
+        assert_eq!(got, batch);
+    }
+
     #[tokio::test]
     async fn test_simple_roundtrip() {
         let batch = RecordBatch::try_from_iter([("a", to_string_array(&["value"]))]).unwrap();
@@ -566,6 +814,7 @@ mod tests {
             max_sequence_number: SequenceNumber::new(11),
             compaction_level: CompactionLevel::FileNonOverlapped,
             sort_key: None,
+            provenance: None,
         }
     }
 
@@ -62,6 +62,7 @@ async fn test_decoded_iox_metadata() {
         max_sequence_number: SequenceNumber::new(11),
         compaction_level: CompactionLevel::FileNonOverlapped,
         sort_key: None,
+        provenance: None,
     };
 
     let mut schema_builder = SchemaBuilder::new();
@@ -203,6 +204,7 @@ async fn test_empty_parquet_file_panic() {
         max_sequence_number: SequenceNumber::new(11),
         compaction_level: CompactionLevel::FileNonOverlapped,
         sort_key: None,
+        provenance: None,
     };
 
     let batch = RecordBatch::try_from_iter(data).unwrap();
@@ -297,6 +299,7 @@ async fn test_decoded_many_columns_with_null_cols_iox_metadata() {
         max_sequence_number: SequenceNumber::new(11),
         compaction_level: CompactionLevel::FileNonOverlapped,
         sort_key: Some(sort_key),
+        provenance: None,
     };
 
     let mut schema_builder = SchemaBuilder::new();
@@ -385,6 +388,7 @@ async fn test_derive_parquet_file_params() {
         max_sequence_number: SequenceNumber::new(11),
         compaction_level: CompactionLevel::FileNonOverlapped,
         sort_key: None,
+        provenance: None,
     };
 
     // Build a schema that contains the IOx metadata, ensuring it is correctly
@@ -1,7 +1,7 @@
 use datafusion::arrow::{
     array::{
         as_boolean_array, as_dictionary_array, as_primitive_array, as_string_array, Array,
-        ArrayAccessor, StringArray,
+        ArrayAccessor, ArrayRef, StringArray,
     },
     datatypes::{Float64Type, Int32Type, Int64Type, TimestampNanosecondType, UInt64Type},
     record_batch::RecordBatch,
@@ -9,11 +9,15 @@ use datafusion::arrow::{
 use influxdb_line_protocol::{builder::FieldValue, FieldValue as LPFieldValue};
 use schema::{InfluxColumnType, InfluxFieldType, Schema};
 
-/// Converts a [`RecordBatch`] into line protocol lines.
+use crate::Precision;
+
+/// Converts a [`RecordBatch`] into line protocol lines, with
+/// timestamps in the specified `precision`.
 pub(crate) fn convert_to_lines(
     measurement_name: &str,
     iox_schema: &Schema,
     batch: &RecordBatch,
+    precision: Precision,
 ) -> Result<Vec<u8>, String> {
     let mut lp_builder = influxdb_line_protocol::LineProtocolBuilder::new();
 
@@ -43,7 +47,9 @@ pub(crate) fn convert_to_lines(
         });
 
         let ts = timestamp_value(iox_schema, index, batch)?;
-        lp_builder = lp_fields.timestamp(ts).close_line();
+        lp_builder = lp_fields
+            .timestamp(precision.convert_timestamp(ts))
+            .close_line();
     }
 
     Ok(lp_builder.build())
@@ -60,23 +66,31 @@ fn tags_values_iter<'a>(
         .enumerate()
         .filter_map(move |(column_index, (influx_column_type, field))| {
             if influx_column_type == InfluxColumnType::Tag {
-                // tags are always dictionaries
-                let arr = as_dictionary_array::<Int32Type>(batch.column(column_index))
-                    .downcast_dict::<StringArray>()
-                    .expect("Tag was not a string dictionary array");
-
                 // If the value of this column is not null, return it.
-                if arr.is_valid(row_index) {
-                    return Some(TagColumn {
-                        name: field.name(),
-                        value: arr.value(row_index),
-                    });
-                }
+                return tag_value(batch, column_index, row_index).map(|value| TagColumn {
+                    name: field.name(),
+                    value,
+                });
             }
             None
         })
 }
 
+/// Returns the value of the tag column at `column_index` for the
+/// specified row, or `None` if it is null
+pub(crate) fn tag_value(
+    batch: &RecordBatch,
+    column_index: usize,
+    row_index: usize,
+) -> Option<&str> {
+    // tags are always dictionaries
+    let arr = as_dictionary_array::<Int32Type>(batch.column(column_index))
+        .downcast_dict::<StringArray>()
+        .expect("Tag was not a string dictionary array");
+
+    arr.is_valid(row_index).then(|| arr.value(row_index))
+}
+
 /// Represents a particular column along with code that knows how to add that value to a builder.
 struct TagColumn<'a> {
     name: &'a str,
@@ -93,39 +107,53 @@ fn field_values_iter<'a>(
         .iter()
         .enumerate()
         .filter_map(move |(column_index, (influx_column_type, field))| {
-            // Skip any value that is NULL
-            let arr = batch.column(column_index);
-            if !arr.is_valid(row_index) {
-                return None;
-            }
-
-            let name = field.name();
-
-            // Extract the value from the relevant array and convert it
-            let value = match influx_column_type {
-                InfluxColumnType::Field(InfluxFieldType::Float) => {
-                    LPFieldValue::F64(as_primitive_array::<Float64Type>(arr).value(row_index))
-                }
-                InfluxColumnType::Field(InfluxFieldType::Integer) => {
-                    LPFieldValue::I64(as_primitive_array::<Int64Type>(arr).value(row_index))
-                }
-                InfluxColumnType::Field(InfluxFieldType::UInteger) => {
-                    LPFieldValue::U64(as_primitive_array::<UInt64Type>(arr).value(row_index))
-                }
-                InfluxColumnType::Field(InfluxFieldType::String) => {
-                    LPFieldValue::String(as_string_array(arr).value(row_index).into())
-                }
-                InfluxColumnType::Field(InfluxFieldType::Boolean) => {
-                    LPFieldValue::Boolean(as_boolean_array(arr).value(row_index))
-                }
-                // not a field
+            // not a field
+            let field_type = match influx_column_type {
+                InfluxColumnType::Field(field_type) => field_type,
                 InfluxColumnType::Tag | InfluxColumnType::Timestamp => return None,
             };
 
-            Some(FieldColumn { name, value })
+            // Skip any value that is NULL
+            let value = field_value(field_type, batch.column(column_index), row_index)?;
+
+            Some(FieldColumn {
+                name: field.name(),
+                value,
+            })
         })
 }
 
+/// Extracts the value of the field column `arr` for the specified
+/// row, or `None` if it is null
+pub(crate) fn field_value(
+    field_type: InfluxFieldType,
+    arr: &ArrayRef,
+    row_index: usize,
+) -> Option<LPFieldValue<'_>> {
+    if !arr.is_valid(row_index) {
+        return None;
+    }
+
+    // Extract the value from the relevant array and convert it
+    let value = match field_type {
+        InfluxFieldType::Float => {
+            LPFieldValue::F64(as_primitive_array::<Float64Type>(arr).value(row_index))
+        }
+        InfluxFieldType::Integer => {
+            LPFieldValue::I64(as_primitive_array::<Int64Type>(arr).value(row_index))
+        }
+        InfluxFieldType::UInteger => {
+            LPFieldValue::U64(as_primitive_array::<UInt64Type>(arr).value(row_index))
+        }
+        InfluxFieldType::String => {
+            LPFieldValue::String(as_string_array(arr).value(row_index).into())
+        }
+        InfluxFieldType::Boolean => LPFieldValue::Boolean(as_boolean_array(arr).value(row_index)),
+    };
+
+    Some(value)
+}
+
 /// Represents a particular Field column's value in a way that knows how to format
 struct FieldColumn<'a> {
     name: &'a str,
@@ -145,7 +173,7 @@ impl<'a> FieldValue for FieldColumn<'a> {
 }
 
 /// Find the timestamp value for the specified row
-fn timestamp_value<'a>(
+pub(crate) fn timestamp_value<'a>(
     iox_schema: &'a Schema,
     row_index: usize,
     batch: &'a RecordBatch,
@@ -232,8 +260,13 @@ m,tag2=multi_field bool_field=false,str_field="blargh" 610
         let record_batch = mutable_batch.to_arrow(selection).unwrap();
         let iox_schema = mutable_batch.schema(selection).unwrap();
 
-        let output_lp = convert_to_lines(&table_name, &iox_schema, &record_batch)
-            .expect("error converting lines");
+        let output_lp = convert_to_lines(
+            &table_name,
+            &iox_schema,
+            &record_batch,
+            Precision::Nanoseconds,
+        )
+        .expect("error converting lines");
         let output_lp = String::from_utf8_lossy(&output_lp);
 
         let lp = lp.trim();
@@ -0,0 +1,252 @@
+//! An output sink that splits the converted output across multiple
+//! files
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// A [`Write`] implementation that splits its output into multiple
+/// files of at most `max_bytes` bytes and / or `max_lines` lines each,
+/// for example so that exported line protocol can be re-imported
+/// through a write API that limits request sizes.
+///
+/// Lines are never split across files: a single line longer than
+/// `max_bytes` is written to a file on its own.
+///
+/// Given a base path of `out.lp`, the files are named `out-00001.lp`,
+/// `out-00002.lp`, and so on. Call [`ChunkedFileWriter::finish`] once
+/// all data has been written to flush the last file.
+#[derive(Debug)]
+pub struct ChunkedFileWriter {
+    /// The directory, file stem and extension of the output files
+    directory: PathBuf,
+    stem: String,
+    extension: Option<String>,
+
+    max_bytes: Option<u64>,
+    max_lines: Option<u64>,
+
+    /// The file currently being written to, if any
+    current: Option<BufWriter<File>>,
+    current_bytes: u64,
+    current_lines: u64,
+
+    /// Data of a line that has not been terminated yet
+    partial_line: Vec<u8>,
+
+    /// All files created so far
+    files: Vec<PathBuf>,
+}
+
+impl ChunkedFileWriter {
+    /// Create a new writer deriving its file names from `base_path`.
+    /// Without limits (see [`Self::with_max_bytes`] and
+    /// [`Self::with_max_lines`]) all output goes to a single file.
+    pub fn new(base_path: impl AsRef<Path>) -> Self {
+        let base_path = base_path.as_ref();
+        let directory = base_path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let stem = base_path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "out".to_string());
+        let extension = base_path
+            .extension()
+            .map(|s| s.to_string_lossy().to_string());
+
+        Self {
+            directory,
+            stem,
+            extension,
+            max_bytes: None,
+            max_lines: None,
+            current: None,
+            current_bytes: 0,
+            current_lines: 0,
+            partial_line: vec![],
+            files: vec![],
+        }
+    }
+
+    /// Start a new file rather than letting a file grow beyond
+    /// `max_bytes`
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Start a new file rather than writing more than `max_lines`
+    /// lines to a file
+    pub fn with_max_lines(mut self, max_lines: u64) -> Self {
+        self.max_lines = Some(max_lines);
+        self
+    }
+
+    /// The files created so far
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Writes any remaining (unterminated) line, flushes and syncs the
+    /// last file and returns the paths of all files written, in order
+    pub fn finish(mut self) -> io::Result<Vec<PathBuf>> {
+        if !self.partial_line.is_empty() {
+            let line = std::mem::take(&mut self.partial_line);
+            self.write_line(&line)?;
+        }
+
+        if let Some(current) = self.current.take() {
+            current.into_inner()?.sync_all()?;
+        }
+
+        Ok(self.files)
+    }
+
+    /// Writes a single line (including its terminator, if any),
+    /// starting a new file first if it would not fit into the current
+    /// one
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        let len = line.len() as u64;
+
+        let exceeds_bytes = self
+            .max_bytes
+            .map(|max_bytes| self.current_bytes + len > max_bytes)
+            .unwrap_or(false);
+        let exceeds_lines = self
+            .max_lines
+            .map(|max_lines| self.current_lines + 1 > max_lines)
+            .unwrap_or(false);
+
+        // never leave a file empty, even if the line is too long
+        let start_new_file =
+            self.current.is_none() || ((exceeds_bytes || exceeds_lines) && self.current_lines > 0);
+        if start_new_file {
+            self.start_new_file()?;
+        }
+
+        self.current
+            .as_mut()
+            .expect("file was just created")
+            .write_all(line)?;
+        self.current_bytes += len;
+        self.current_lines += 1;
+
+        Ok(())
+    }
+
+    /// Closes the current file, if any, and opens the next one
+    fn start_new_file(&mut self) -> io::Result<()> {
+        if let Some(current) = self.current.take() {
+            current.into_inner()?.sync_all()?;
+        }
+
+        let mut file_name = format!("{}-{:05}", self.stem, self.files.len() + 1);
+        if let Some(extension) = &self.extension {
+            file_name.push('.');
+            file_name.push_str(extension);
+        }
+        let path = self.directory.join(file_name);
+
+        self.current = Some(BufWriter::new(File::create(&path)?));
+        self.current_bytes = 0;
+        self.current_lines = 0;
+        self.files.push(path);
+
+        Ok(())
+    }
+}
+
+impl Write for ChunkedFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut remaining = buf;
+
+        while let Some(pos) = remaining.iter().position(|b| *b == b'\n') {
+            let (line, rest) = remaining.split_at(pos + 1);
+            if self.partial_line.is_empty() {
+                self.write_line(line)?;
+            } else {
+                let mut partial_line = std::mem::take(&mut self.partial_line);
+                partial_line.extend_from_slice(line);
+                self.write_line(&partial_line)?;
+            }
+            remaining = rest;
+        }
+
+        self.partial_line.extend_from_slice(remaining);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.current.as_mut() {
+            Some(current) => current.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_by_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = ChunkedFileWriter::new(dir.path().join("out.lp")).with_max_lines(2);
+
+        writer.write_all(b"m f=1 1\nm f=2 2\nm f=").unwrap();
+        writer.write_all(b"3 3\nm f=4 4").unwrap();
+        let files = writer.finish().unwrap();
+
+        assert_eq!(
+            files,
+            vec![
+                dir.path().join("out-00001.lp"),
+                dir.path().join("out-00002.lp")
+            ]
+        );
+        assert_eq!(read(&files[0]), "m f=1 1\nm f=2 2\n");
+        assert_eq!(read(&files[1]), "m f=3 3\nm f=4 4");
+    }
+
+    #[test]
+    fn split_by_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = ChunkedFileWriter::new(dir.path().join("out.lp")).with_max_bytes(10);
+
+        writer
+            .write_all(b"m f=1 1\nm f=2 2\nthis line is too long\nm f=3 3\n")
+            .unwrap();
+        let files = writer.finish().unwrap();
+
+        let contents: Vec<_> = files.iter().map(|f| read(f)).collect();
+        assert_eq!(
+            contents,
+            vec![
+                "m f=1 1\n",
+                "m f=2 2\n",
+                "this line is too long\n",
+                "m f=3 3\n"
+            ]
+        );
+    }
+
+    #[test]
+    fn no_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = ChunkedFileWriter::new(dir.path().join("out.lp"));
+
+        writer.write_all(b"m f=1 1\nm f=2 2\n").unwrap();
+        let files = writer.finish().unwrap();
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(read(&files[0]), "m f=1 1\nm f=2 2\n");
+    }
+
+    fn read(path: &Path) -> String {
+        std::fs::read_to_string(path).unwrap()
+    }
+}
@@ -0,0 +1,306 @@
+//! Simple tag / field predicates that can be pushed down into the
+//! parquet scan so that only matching rows are converted.
+
+use datafusion::{
+    prelude::{col, lit, Expr},
+    scalar::ScalarValue,
+};
+use std::str::FromStr;
+
+use crate::Error;
+
+/// Comparison operators supported by [`Filter`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+impl Op {
+    /// The textual representation of this operator
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Eq => "=",
+            Self::NotEq => "!=",
+            Self::Lt => "<",
+            Self::LtEq => "<=",
+            Self::Gt => ">",
+            Self::GtEq => ">=",
+        }
+    }
+}
+
+/// A single `<column> <op> <value>` predicate such as `host = "a"` or
+/// `value > 10`.
+///
+/// String values must be quoted with either single or double
+/// quotes. Unquoted values are interpreted as booleans (`true` /
+/// `false`), integers or floats, in that order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    column: String,
+    op: Op,
+    value: ScalarValue,
+}
+
+impl Filter {
+    /// Create a new filter comparing `column` to `value`
+    pub fn new(column: impl Into<String>, op: Op, value: impl Into<ScalarValue>) -> Self {
+        Self {
+            column: column.into(),
+            op,
+            value: value.into(),
+        }
+    }
+
+    /// The column this filter applies to
+    pub fn column(&self) -> &str {
+        &self.column
+    }
+
+    /// Convert this filter into a DataFusion [`Expr`]
+    pub fn to_expr(&self) -> Expr {
+        let column = col(&self.column);
+        let value = lit(self.value.clone());
+        match self.op {
+            Op::Eq => column.eq(value),
+            Op::NotEq => column.not_eq(value),
+            Op::Lt => column.lt(value),
+            Op::LtEq => column.lt_eq(value),
+            Op::Gt => column.gt(value),
+            Op::GtEq => column.gt_eq(value),
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = Error;
+
+    fn from_str(predicate: &str) -> Result<Self, Self::Err> {
+        let invalid = |message: &str| Error::InvalidPredicate {
+            predicate: predicate.to_string(),
+            message: message.to_string(),
+        };
+
+        let op_start = predicate
+            .find(|c| matches!(c, '=' | '!' | '<' | '>'))
+            .ok_or_else(|| invalid("expected one of =, !=, <, <=, >, >="))?;
+
+        let rest = &predicate[op_start..];
+        let (op, op_len) = if rest.starts_with(">=") {
+            (Op::GtEq, 2)
+        } else if rest.starts_with("<=") {
+            (Op::LtEq, 2)
+        } else if rest.starts_with("!=") {
+            (Op::NotEq, 2)
+        } else if rest.starts_with('=') {
+            (Op::Eq, 1)
+        } else if rest.starts_with('>') {
+            (Op::Gt, 1)
+        } else if rest.starts_with('<') {
+            (Op::Lt, 1)
+        } else {
+            return Err(invalid("expected one of =, !=, <, <=, >, >="));
+        };
+
+        let column = predicate[..op_start].trim();
+        let column = unquote(column).unwrap_or(column);
+        if column.is_empty() {
+            return Err(invalid("missing column name"));
+        }
+
+        let value = rest[op_len..].trim();
+        if value.is_empty() {
+            return Err(invalid("missing value"));
+        }
+
+        let value = if let Some(s) = unquote(value) {
+            ScalarValue::from(s)
+        } else if value == "true" || value == "false" {
+            ScalarValue::from(value == "true")
+        } else if let Ok(v) = value.parse::<i64>() {
+            ScalarValue::from(v)
+        } else if let Ok(v) = value.parse::<f64>() {
+            ScalarValue::from(v)
+        } else {
+            return Err(invalid("string values must be quoted"));
+        };
+
+        Ok(Self::new(column, op, value))
+    }
+}
+
+/// Returns the contents of `s` if it is surrounded by matching single
+/// or double quotes
+fn unquote(s: &str) -> Option<&str> {
+    ['"', '\'']
+        .into_iter()
+        .find_map(|quote| s.strip_prefix(quote).and_then(|s| s.strip_suffix(quote)))
+}
+
+/// Combine `filters` into a single conjunction, returning `None` if
+/// `filters` is empty
+pub fn conjunction<'a>(filters: impl IntoIterator<Item = &'a Filter>) -> Option<Expr> {
+    filters
+        .into_iter()
+        .map(Filter::to_expr)
+        .reduce(|accum, expr| accum.and(expr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{convert_file_with_options, open_local_file, ConverterOptions};
+    use data_types::{CompactionLevel, NamespaceId, PartitionId, SequenceNumber, ShardId, TableId};
+    use datafusion::{
+        parquet::{arrow::ArrowWriter, file::properties::WriterProperties, format::KeyValue},
+        physical_plan::collect,
+    };
+    use iox_time::Time;
+    use mutable_batch_lp::lines_to_batches;
+    use parquet_file::metadata::{IoxMetadata, METADATA_KEY};
+    use schema::Projection;
+    use std::{fs::File, path::Path, sync::Arc};
+
+    #[test]
+    fn parse_string() {
+        let filter: Filter = r#"host = "a""#.parse().unwrap();
+        assert_eq!(filter, Filter::new("host", Op::Eq, "a"));
+
+        let filter: Filter = "host!='b c'".parse().unwrap();
+        assert_eq!(filter, Filter::new("host", Op::NotEq, "b c"));
+    }
+
+    #[test]
+    fn parse_numbers() {
+        let filter: Filter = "value > 10".parse().unwrap();
+        assert_eq!(filter, Filter::new("value", Op::Gt, 10_i64));
+
+        let filter: Filter = "value<=1.5".parse().unwrap();
+        assert_eq!(filter, Filter::new("value", Op::LtEq, 1.5_f64));
+
+        let filter: Filter = "value >= -3".parse().unwrap();
+        assert_eq!(filter, Filter::new("value", Op::GtEq, -3_i64));
+    }
+
+    #[test]
+    fn parse_bool() {
+        let filter: Filter = "active = true".parse().unwrap();
+        assert_eq!(filter, Filter::new("active", Op::Eq, true));
+    }
+
+    #[test]
+    fn parse_errors() {
+        for predicate in ["host", "= 4", "host =", "host = a"] {
+            let err = predicate.parse::<Filter>().unwrap_err();
+            assert!(
+                matches!(err, Error::InvalidPredicate { .. }),
+                "unexpected error for {}: {}",
+                predicate,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn conjunction_of_filters() {
+        let filters = vec![
+            Filter::new("host", Op::Eq, "a"),
+            Filter::new("value", Op::Gt, 10_i64),
+        ];
+        let expr = conjunction(&filters).unwrap();
+        assert_eq!(
+            expr,
+            col("host").eq(lit("a")).and(col("value").gt(lit(10_i64)))
+        );
+
+        assert!(conjunction(&Vec::<Filter>::new()).is_none());
+    }
+
+    #[tokio::test]
+    async fn convert_with_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("m.parquet");
+
+        // row groups of 2 rows: [1, 2], [3, 4], [5, 6]
+        write_parquet(
+            &path,
+            "m,host=a value=1 1\n\
+             m,host=b value=2 2\n\
+             m,host=a value=3 3\n\
+             m,host=b value=4 4\n\
+             m,host=a value=5 5\n\
+             m,host=b value=6 6\n",
+            2,
+        );
+
+        let filter: Filter = "value > 3".parse().unwrap();
+        let options = ConverterOptions::default().with_predicate(filter.to_expr());
+        let output = convert_file_with_options(&path, &options, vec![])
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "m,host=b value=4 4\nm,host=a value=5 5\nm,host=b value=6 6\n"
+        );
+
+        // the first row group can not match and is never decoded, the
+        // second is decoded and filtered row by row
+        let reader = open_local_file(&path)
+            .await
+            .unwrap()
+            .with_predicate(filter.to_expr());
+        let plan = reader.plan(reader.schema()).unwrap();
+        let batches = collect(Arc::clone(&plan), reader.task_ctx()).await.unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 3);
+
+        let parquet_exec = &plan.children()[0];
+        let row_groups_pruned = parquet_exec
+            .metrics()
+            .unwrap()
+            .sum_by_name("row_groups_pruned")
+            .unwrap();
+        assert_eq!(row_groups_pruned.as_usize(), 1);
+    }
+
+    /// Writes `lp`, which must contain a single measurement, to `path`
+    /// as an IOx parquet file with at most `row_group_size` rows in
+    /// each row group
+    fn write_parquet(path: &Path, lp: &str, row_group_size: usize) {
+        let (table_name, mutable_batch) =
+            lines_to_batches(lp, 0).unwrap().into_iter().next().unwrap();
+        let batch = mutable_batch.to_arrow(Projection::All).unwrap();
+
+        let meta = IoxMetadata {
+            object_store_id: Default::default(),
+            creation_timestamp: Time::from_timestamp_nanos(42),
+            namespace_id: NamespaceId::new(1),
+            namespace_name: "ns".into(),
+            shard_id: ShardId::new(2),
+            table_id: TableId::new(3),
+            table_name: table_name.into(),
+            partition_id: PartitionId::new(4),
+            partition_key: "potato".into(),
+            max_sequence_number: SequenceNumber::new(11),
+            compaction_level: CompactionLevel::Initial,
+            sort_key: None,
+            provenance: None,
+        };
+        let props = WriterProperties::builder()
+            .set_key_value_metadata(Some(vec![KeyValue {
+                key: METADATA_KEY.to_string(),
+                value: Some(meta.to_base64().unwrap()),
+            }]))
+            .set_max_row_group_size(row_group_size)
+            .build();
+
+        let file = File::create(path).unwrap();
+        let mut writer = ArrowWriter::try_new(file, batch.schema(), Some(props)).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+    }
+}
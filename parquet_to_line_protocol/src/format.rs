@@ -0,0 +1,449 @@
+//! Output formats that converted parquet data can be written as
+
+use datafusion::arrow::record_batch::RecordBatch;
+use influxdb_line_protocol::FieldValue as LPFieldValue;
+use observability_deps::tracing::warn;
+use schema::{InfluxColumnType, Schema};
+use serde_json::{Map, Value};
+use std::{fmt::Debug, str::FromStr, sync::Arc};
+
+use crate::{
+    batch::{convert_to_lines, field_value, tag_value, timestamp_value},
+    validate::check_round_trip,
+    Error, Validation,
+};
+
+/// Converts [`RecordBatch`]es read from an IOx parquet file into some
+/// textual output format.
+///
+/// Batches are converted in parallel, so implementations must not
+/// rely on state carried between calls to [`OutputFormat::convert`].
+pub trait OutputFormat: Debug + Send + Sync {
+    /// Bytes to emit once, before any converted batch (e.g. a CSV
+    /// header row). Defaults to nothing.
+    fn header(&self, _measurement_name: &str, _iox_schema: &Schema) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Converts all rows in `batch`
+    fn convert(
+        &self,
+        measurement_name: &str,
+        iox_schema: &Schema,
+        batch: &RecordBatch,
+    ) -> Result<Vec<u8>, String>;
+}
+
+/// The built in output formats
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// InfluxDB line protocol
+    #[default]
+    LineProtocol,
+    /// Comma separated values, with a header row
+    Csv,
+    /// One JSON object per row
+    JsonLines,
+}
+
+impl Format {
+    /// Return the [`OutputFormat`] implementation for this format
+    pub fn output_format(&self) -> Arc<dyn OutputFormat> {
+        match self {
+            Self::LineProtocol => Arc::new(LineProtocolFormat::default()),
+            Self::Csv => Arc::new(CsvFormat),
+            Self::JsonLines => Arc::new(JsonLinesFormat),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lp" | "line_protocol" => Ok(Self::LineProtocol),
+            "csv" => Ok(Self::Csv),
+            "json" | "jsonl" => Ok(Self::JsonLines),
+            _ => Err(Error::UnknownFormat {
+                format: s.to_string(),
+            }),
+        }
+    }
+}
+
+/// Writes InfluxDB line protocol
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LineProtocolFormat {
+    /// precision of the written timestamps
+    precision: Precision,
+
+    /// whether to start the output with a `# precision=..` comment
+    precision_hint: bool,
+
+    /// how the converted lines are checked
+    validation: Validation,
+}
+
+impl LineProtocolFormat {
+    /// Write timestamps with `precision` rather than nanoseconds, to
+    /// match the `precision` parameter the data will be written back
+    /// with (e.g. `/api/v2/write?precision=ms`).
+    ///
+    /// Timestamps are rounded down to the requested precision.
+    pub fn with_precision(mut self, precision: Precision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// If `precision_hint` is true, start the output with a comment
+    /// line recording the timestamp precision, such as
+    /// `# precision=ms`. Line protocol parsers ignore comments.
+    pub fn with_precision_hint(mut self, precision_hint: bool) -> Self {
+        self.precision_hint = precision_hint;
+        self
+    }
+
+    /// Check that every converted line parses back to the row it was
+    /// converted from, for example to catch tag keys, tag values or
+    /// string fields that were not escaped correctly.
+    ///
+    /// This roughly doubles the cost of conversion.
+    pub fn with_validation(mut self, validation: Validation) -> Self {
+        self.validation = validation;
+        self
+    }
+}
+
+impl OutputFormat for LineProtocolFormat {
+    fn header(&self, _measurement_name: &str, _iox_schema: &Schema) -> Option<Vec<u8>> {
+        self.precision_hint
+            .then(|| format!("# precision={}\n", self.precision.as_str()).into_bytes())
+    }
+
+    fn convert(
+        &self,
+        measurement_name: &str,
+        iox_schema: &Schema,
+        batch: &RecordBatch,
+    ) -> Result<Vec<u8>, String> {
+        let lines = convert_to_lines(measurement_name, iox_schema, batch, self.precision)?;
+
+        if self.validation != Validation::Off {
+            let problems =
+                check_round_trip(measurement_name, iox_schema, batch, self.precision, &lines);
+            if !problems.is_empty() {
+                if self.validation == Validation::Strict {
+                    return Err(format!(
+                        "Converted line protocol does not round trip: {}",
+                        problems.join(", ")
+                    ));
+                }
+
+                for problem in problems {
+                    warn!(%measurement_name, %problem, "Converted line protocol does not round trip");
+                }
+            }
+        }
+
+        Ok(lines)
+    }
+}
+
+/// The precision of timestamps in the output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Precision {
+    Seconds,
+    Milliseconds,
+    Microseconds,
+    #[default]
+    Nanoseconds,
+}
+
+impl Precision {
+    /// Returns the number of nanoseconds in one unit of this precision
+    fn timestamp_base(&self) -> i64 {
+        match self {
+            Self::Seconds => 1_000_000_000,
+            Self::Milliseconds => 1_000_000,
+            Self::Microseconds => 1_000,
+            Self::Nanoseconds => 1,
+        }
+    }
+
+    /// Converts the nanosecond timestamp `ts` to this precision,
+    /// rounding down
+    pub fn convert_timestamp(&self, ts: i64) -> i64 {
+        ts.div_euclid(self.timestamp_base())
+    }
+
+    /// The name of this precision as used in the InfluxDB write API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Seconds => "s",
+            Self::Milliseconds => "ms",
+            Self::Microseconds => "us",
+            Self::Nanoseconds => "ns",
+        }
+    }
+}
+
+impl FromStr for Precision {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "s" => Ok(Self::Seconds),
+            "ms" => Ok(Self::Milliseconds),
+            "us" => Ok(Self::Microseconds),
+            "ns" => Ok(Self::Nanoseconds),
+            _ => Err(Error::UnknownPrecision {
+                precision: s.to_string(),
+            }),
+        }
+    }
+}
+
+/// Writes CSV with one column per IOx column (in schema order),
+/// preceded by a `measurement` column. NULL values are written as
+/// empty cells.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CsvFormat;
+
+impl OutputFormat for CsvFormat {
+    fn header(&self, _measurement_name: &str, iox_schema: &Schema) -> Option<Vec<u8>> {
+        let mut out = String::from("measurement");
+        for (_, field) in iox_schema.iter() {
+            out.push(',');
+            push_csv_escaped(&mut out, field.name());
+        }
+        out.push('\n');
+        Some(out.into_bytes())
+    }
+
+    fn convert(
+        &self,
+        measurement_name: &str,
+        iox_schema: &Schema,
+        batch: &RecordBatch,
+    ) -> Result<Vec<u8>, String> {
+        let mut out = String::new();
+
+        for row_index in 0..batch.num_rows() {
+            push_csv_escaped(&mut out, measurement_name);
+
+            for (column_index, (influx_column_type, _)) in iox_schema.iter().enumerate() {
+                out.push(',');
+                match influx_column_type {
+                    InfluxColumnType::Tag => {
+                        if let Some(value) = tag_value(batch, column_index, row_index) {
+                            push_csv_escaped(&mut out, value);
+                        }
+                    }
+                    InfluxColumnType::Field(field_type) => {
+                        if let Some(value) =
+                            field_value(field_type, batch.column(column_index), row_index)
+                        {
+                            push_csv_field(&mut out, value);
+                        }
+                    }
+                    InfluxColumnType::Timestamp => {
+                        out.push_str(&timestamp_value(iox_schema, row_index, batch)?.to_string())
+                    }
+                }
+            }
+            out.push('\n');
+        }
+
+        Ok(out.into_bytes())
+    }
+}
+
+/// Appends the unquoted representation of `value` to `out`
+fn push_csv_field(out: &mut String, value: LPFieldValue<'_>) {
+    match value {
+        LPFieldValue::I64(v) => out.push_str(&v.to_string()),
+        LPFieldValue::U64(v) => out.push_str(&v.to_string()),
+        LPFieldValue::F64(v) => out.push_str(&v.to_string()),
+        LPFieldValue::String(v) => push_csv_escaped(out, v.as_str()),
+        LPFieldValue::Boolean(v) => out.push_str(&v.to_string()),
+    }
+}
+
+/// Appends `value` to `out`, quoting it if it contains characters
+/// that are special in CSV
+fn push_csv_escaped(out: &mut String, value: &str) {
+    if value.contains(|c| matches!(c, ',' | '"' | '\n' | '\r')) {
+        out.push('"');
+        out.push_str(&value.replace('"', "\"\""));
+        out.push('"');
+    } else {
+        out.push_str(value);
+    }
+}
+
+/// Writes one JSON object per row of the form:
+///
+/// ```json
+/// {"measurement":"cpu","tags":{"host":"a"},"fields":{"usage":1.5},"time":1000}
+/// ```
+///
+/// NULL tags and fields are omitted.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonLinesFormat;
+
+impl OutputFormat for JsonLinesFormat {
+    fn convert(
+        &self,
+        measurement_name: &str,
+        iox_schema: &Schema,
+        batch: &RecordBatch,
+    ) -> Result<Vec<u8>, String> {
+        let mut out = vec![];
+
+        for row_index in 0..batch.num_rows() {
+            let mut tags = Map::new();
+            let mut fields = Map::new();
+
+            for (column_index, (influx_column_type, field)) in iox_schema.iter().enumerate() {
+                match influx_column_type {
+                    InfluxColumnType::Tag => {
+                        if let Some(value) = tag_value(batch, column_index, row_index) {
+                            tags.insert(field.name().to_string(), value.into());
+                        }
+                    }
+                    InfluxColumnType::Field(field_type) => {
+                        if let Some(value) =
+                            field_value(field_type, batch.column(column_index), row_index)
+                        {
+                            fields.insert(field.name().to_string(), json_value(value));
+                        }
+                    }
+                    InfluxColumnType::Timestamp => {}
+                }
+            }
+
+            let mut row = Map::new();
+            row.insert("measurement".to_string(), measurement_name.into());
+            row.insert("tags".to_string(), Value::Object(tags));
+            row.insert("fields".to_string(), Value::Object(fields));
+            row.insert(
+                "time".to_string(),
+                timestamp_value(iox_schema, row_index, batch)?.into(),
+            );
+
+            serde_json::to_writer(&mut out, &Value::Object(row)).map_err(|e| e.to_string())?;
+            out.push(b'\n');
+        }
+
+        Ok(out)
+    }
+}
+
+fn json_value(value: LPFieldValue<'_>) -> Value {
+    match value {
+        LPFieldValue::I64(v) => v.into(),
+        LPFieldValue::U64(v) => v.into(),
+        // non finite floats can not be represented and become null
+        LPFieldValue::F64(v) => v.into(),
+        LPFieldValue::String(v) => v.as_str().into(),
+        LPFieldValue::Boolean(v) => v.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutable_batch_lp::lines_to_batches;
+    use schema::Projection;
+
+    #[test]
+    fn csv() {
+        let output = convert(
+            &CsvFormat,
+            r#"m,tag=a,tag2=b float_field=64 450
+m,tag=c str_field="x,\"y" 550"#,
+        );
+
+        assert_eq!(
+            output,
+            "measurement,float_field,str_field,tag,tag2,time\n\
+             m,64,,a,b,450\n\
+             m,,\"x,\"\"y\",c,,550\n"
+        );
+    }
+
+    #[test]
+    fn json_lines() {
+        let output = convert(
+            &JsonLinesFormat,
+            r#"m,tag=a float_field=64 450
+m,tag=b bool_field=true,int_field=3i 550"#,
+        );
+
+        assert_eq!(
+            output,
+            r#"{"fields":{"float_field":64.0},"measurement":"m","tags":{"tag":"a"},"time":450}
+{"fields":{"bool_field":true,"int_field":3},"measurement":"m","tags":{"tag":"b"},"time":550}
+"#
+        );
+    }
+
+    #[test]
+    fn line_protocol_precision() {
+        let format = LineProtocolFormat::default()
+            .with_precision(Precision::Milliseconds)
+            .with_precision_hint(true);
+        let output = convert(
+            &format,
+            r#"m,tag=a float_field=64 1672531200123456789
+m,tag=b float_field=65 -1500000"#,
+        );
+
+        assert_eq!(
+            output,
+            "# precision=ms\n\
+             m,tag=a float_field=64 1672531200123\n\
+             m,tag=b float_field=65 -2\n"
+        );
+    }
+
+    #[test]
+    fn parse_precision() {
+        assert_eq!("s".parse::<Precision>().unwrap(), Precision::Seconds);
+        assert_eq!("us".parse::<Precision>().unwrap(), Precision::Microseconds);
+        assert!(matches!(
+            "m".parse::<Precision>(),
+            Err(Error::UnknownPrecision { .. })
+        ));
+    }
+
+    #[test]
+    fn parse_format() {
+        assert_eq!("lp".parse::<Format>().unwrap(), Format::LineProtocol);
+        assert_eq!("csv".parse::<Format>().unwrap(), Format::Csv);
+        assert_eq!("jsonl".parse::<Format>().unwrap(), Format::JsonLines);
+        assert!(matches!(
+            "xml".parse::<Format>(),
+            Err(Error::UnknownFormat { .. })
+        ));
+    }
+
+    /// Parses `lp`, and converts it using `format`, including the header
+    fn convert(format: &dyn OutputFormat, lp: &str) -> String {
+        let mutable_batches = lines_to_batches(lp, 0).expect("Error parsing line protocol");
+        let (table_name, mutable_batch) = mutable_batches.into_iter().next().unwrap();
+
+        let selection = Projection::All;
+        let record_batch = mutable_batch.to_arrow(selection).unwrap();
+        let iox_schema = mutable_batch.schema(selection).unwrap();
+
+        let mut output = format.header(&table_name, &iox_schema).unwrap_or_default();
+        output.extend(
+            format
+                .convert(&table_name, &iox_schema, &record_batch)
+                .expect("error converting"),
+        );
+        String::from_utf8(output).unwrap()
+    }
+}
@@ -0,0 +1,78 @@
+//! Reports what is in an IOx parquet file without converting it
+
+use data_types::{NamespaceId, PartitionId, PartitionKey, TableId, TimestampMinMax};
+use datafusion::{physical_plan::ColumnStatistics, scalar::ScalarValue};
+use parquet_file::metadata::Provenance;
+use schema::{InfluxColumnType, Schema, TIME_COLUMN_NAME};
+use std::sync::Arc;
+
+/// Information about an IOx parquet file, decoded only from its
+/// footer (metadata and statistics). See [`inspect`](crate::inspect).
+#[derive(Debug, Clone)]
+pub struct FileInfo {
+    /// The measurement (table) name
+    pub measurement_name: Arc<str>,
+
+    /// The name of the namespace the data belongs to
+    pub namespace_name: Arc<str>,
+
+    /// Catalog IDs of the namespace, table and partition
+    pub namespace_id: NamespaceId,
+    pub table_id: TableId,
+    pub partition_id: PartitionId,
+
+    /// The partition key of the data
+    pub partition_key: PartitionKey,
+
+    /// Total number of rows in the file
+    pub row_count: usize,
+
+    /// The columns of the file, in schema order
+    pub columns: Vec<ColumnInfo>,
+
+    /// The minimum and maximum timestamp in the file, if known
+    pub time_range: Option<TimestampMinMax>,
+
+    /// The ingester that persisted the file and the writes it holds,
+    /// if recorded
+    pub provenance: Option<Provenance>,
+}
+
+/// A column of an IOx parquet file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnInfo {
+    /// The column name
+    pub name: String,
+
+    /// The InfluxDB data model type (tag, field type or timestamp)
+    pub influx_type: InfluxColumnType,
+}
+
+/// Returns the columns of `iox_schema`
+pub(crate) fn columns(iox_schema: &Schema) -> Vec<ColumnInfo> {
+    iox_schema
+        .iter()
+        .map(|(influx_type, field)| ColumnInfo {
+            name: field.name().to_string(),
+            influx_type,
+        })
+        .collect()
+}
+
+/// Extracts the range of the time column from the per column
+/// statistics of `iox_schema`, if present
+pub(crate) fn time_range(
+    iox_schema: &Schema,
+    column_statistics: Option<&[ColumnStatistics]>,
+) -> Option<TimestampMinMax> {
+    let time_index = iox_schema.find_index_of(TIME_COLUMN_NAME)?;
+    let stats = column_statistics?.get(time_index)?;
+
+    match (&stats.min_value, &stats.max_value) {
+        (
+            Some(ScalarValue::TimestampNanosecond(Some(min), _)),
+            Some(ScalarValue::TimestampNanosecond(Some(max), _)),
+        ) if min <= max => Some(TimestampMinMax::new(*min, *max)),
+        _ => None,
+    }
+}
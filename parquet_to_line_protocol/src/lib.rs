@@ -1,37 +1,77 @@
 //! Code that can convert between parquet files and line protocol
 
+use bytes::Bytes;
 use datafusion::{
-    arrow::datatypes::SchemaRef as ArrowSchemaRef,
+    arrow::{
+        compute::SortOptions, datatypes::SchemaRef as ArrowSchemaRef, ipc::writer::FileWriter,
+    },
+    common::ToDFSchema,
     config::ConfigOptions,
     datasource::{
         file_format::{parquet::ParquetFormat, FileFormat},
-        listing::PartitionedFile,
+        listing::{FileRange, PartitionedFile},
         object_store::ObjectStoreUrl,
     },
-    execution::context::TaskContext,
+    execution::context::{ExecutionProps, TaskContext},
+    optimizer::simplify_expressions::{ExprSimplifier, SimplifyContext},
+    physical_expr::create_physical_expr,
     physical_plan::{
+        coalesce_partitions::CoalescePartitionsExec,
         execute_stream,
+        expressions::{col, PhysicalSortExpr},
         file_format::{FileScanConfig, ParquetExec},
-        SendableRecordBatchStream, Statistics,
+        filter::FilterExec,
+        sorts::sort::SortExec,
+        ExecutionPlan, PhysicalExpr, SendableRecordBatchStream, Statistics,
     },
-    prelude::{SessionConfig, SessionContext},
+    prelude::{Expr, SessionConfig, SessionContext},
 };
-use futures::StreamExt;
+use futures::{stream::BoxStream, StreamExt, TryStreamExt};
 use object_store::{
     local::LocalFileSystem, path::Path as ObjectStorePath, ObjectMeta, ObjectStore,
 };
 use parquet_file::metadata::{IoxMetadata, METADATA_KEY};
-use schema::Schema;
+use schema::{merge::SchemaMerger, Schema, TIME_COLUMN_NAME};
 use snafu::{OptionExt, ResultExt, Snafu};
 use std::{
-    io::Write,
+    io::{BufWriter, Write},
+    num::NonZeroUsize,
     path::{Path, PathBuf},
     result::Result,
     sync::Arc,
+    time::Instant,
 };
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 
 mod batch;
-use batch::convert_to_lines;
+
+mod chunked;
+pub use chunked::ChunkedFileWriter;
+
+mod format;
+pub use format::{CsvFormat, Format, JsonLinesFormat, LineProtocolFormat, OutputFormat, Precision};
+
+mod inspect;
+pub use inspect::{ColumnInfo, FileInfo};
+
+mod merge;
+
+mod validate;
+pub use validate::Validation;
+
+mod stats;
+pub use stats::ConversionStats;
+
+mod remote;
+
+mod progress;
+pub use progress::{Progress, ProgressObserver};
+
+mod options;
+pub use options::{ConverterOptions, DEFAULT_BATCH_SIZE, DEFAULT_OUTPUT_BUFFER_SIZE};
+
+mod filter;
+pub use filter::{conjunction, Filter, Op};
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -79,22 +119,512 @@ pub enum Error {
 
     #[snafu(display("IO Error: {}", source))]
     IO { source: std::io::Error },
+
+    #[snafu(display("Invalid predicate '{}': {}", predicate, message))]
+    InvalidPredicate { predicate: String, message: String },
+
+    #[snafu(display("Unknown output format '{}', expected one of lp, csv, json", format))]
+    UnknownFormat { format: String },
+
+    #[snafu(display(
+        "Unknown timestamp precision '{}', expected one of s, ms, us, ns",
+        precision
+    ))]
+    UnknownPrecision { precision: String },
+
+    #[snafu(display(
+        "Unknown validation mode '{}', expected one of off, warn, strict",
+        validation
+    ))]
+    UnknownValidation { validation: String },
+
+    #[snafu(display("No parquet files to convert"))]
+    NoFiles,
+
+    #[snafu(display(
+        "Can not merge files from different tables: expected '{}', found '{}'",
+        expected,
+        actual
+    ))]
+    MismatchedTables { expected: String, actual: String },
+
+    #[snafu(display("Error merging IOx schemas: {}", source))]
+    MergingSchemas { source: schema::merge::Error },
+
+    #[snafu(display("Error planning merge: {}", source))]
+    Merge {
+        source: datafusion::error::DataFusionError,
+    },
+
+    #[snafu(display("Error reading parquet statistics: {}", source))]
+    ReadingStatistics {
+        source: datafusion::error::DataFusionError,
+    },
+
+    #[snafu(display("Invalid URL '{}': {}", url, source))]
+    InvalidUrl {
+        url: String,
+        source: url::ParseError,
+    },
+
+    #[snafu(display("Invalid encoding of path in URL '{}': {}", url, source))]
+    UrlPathEncoding {
+        url: String,
+        source: std::str::Utf8Error,
+    },
+
+    #[snafu(display("Invalid path in URL '{}': {}", url, source))]
+    UrlPath {
+        url: String,
+        source: object_store::path::Error,
+    },
+
+    #[snafu(display("Unsupported URL scheme: {}", scheme))]
+    UnsupportedUrlScheme { scheme: String },
+
+    #[snafu(display("Error creating object store for '{}': {}", url, source))]
+    CreatingObjectStore {
+        url: String,
+        source: object_store::Error,
+    },
+
+    #[snafu(display("Invalid glob pattern '{}': {}", pattern, source))]
+    InvalidGlob {
+        pattern: String,
+        source: glob::PatternError,
+    },
+
+    #[snafu(display("Error planning sort: {}", source))]
+    Sort {
+        source: datafusion::error::DataFusionError,
+    },
+
+    #[snafu(display("Error writing Arrow IPC: {}", source))]
+    ArrowIpc {
+        source: datafusion::arrow::error::ArrowError,
+    },
+
+    #[snafu(display("Error planning predicate: {}", source))]
+    Predicate {
+        source: datafusion::error::DataFusionError,
+    },
 }
 
 /// Converts a parquet file that was written by IOx from the local
 /// file system path specified to line protocol and writes those bytes
 /// to `output`, returning the writer on success
-pub async fn convert_file<W, P>(path: P, mut output: W) -> Result<W, Error>
+pub async fn convert_file<W, P>(path: P, output: W) -> Result<W, Error>
 where
     P: AsRef<Path>,
     W: Write,
 {
-    let path = path.as_ref();
-    let object_store_path =
-        ObjectStorePath::from_filesystem_path(path).context(PathSnafu { path })?;
+    convert_file_with_options(path, &ConverterOptions::default(), output).await
+}
 
+/// Like [`convert_file`] but configured by `options`, for example to
+/// only convert rows matching a predicate or to write CSV instead of
+/// line protocol.
+///
+/// The output is buffered (see
+/// [`ConverterOptions::with_output_buffer_size`]) and flushed before
+/// the writer is returned.
+pub async fn convert_file_with_options<W, P>(
+    path: P,
+    options: &ConverterOptions,
+    output: W,
+) -> Result<W, Error>
+where
+    P: AsRef<Path>,
+    W: Write,
+{
+    let (output, _stats) = convert_file_with_stats(path, options, output).await?;
+    Ok(output)
+}
+
+/// Like [`convert_file_with_options`] but also returns statistics
+/// about the conversion, so that callers can log and verify that the
+/// export is complete
+pub async fn convert_file_with_stats<W, P>(
+    path: P,
+    options: &ConverterOptions,
+    output: W,
+) -> Result<(W, ConversionStats), Error>
+where
+    P: AsRef<Path>,
+    W: Write,
+{
+    let start = Instant::now();
+    let chunks = convert_chunks(path.as_ref(), options).await?;
+    write_chunks(chunks, options, output, start).await
+}
+
+/// Like [`convert_file`] but writes to a [`tokio::io::AsyncWrite`],
+/// so it can be used from async code without blocking the runtime
+pub async fn convert_file_async<W, P>(path: P, output: W) -> Result<W, Error>
+where
+    P: AsRef<Path>,
+    W: AsyncWrite + Unpin,
+{
+    convert_file_async_with_options(path, &ConverterOptions::default(), output).await
+}
+
+/// Like [`convert_file_with_options`] but writes to a
+/// [`tokio::io::AsyncWrite`].
+///
+/// Each batch is written as soon as it (and all batches before it)
+/// have been converted, so the output is in the same order as the
+/// rows are read. This is the order of the rows in the file unless it
+/// is read in several partitions (see
+/// [`ConverterOptions::with_read_partitions`]).
+pub async fn convert_file_async_with_options<W, P>(
+    path: P,
+    options: &ConverterOptions,
+    output: W,
+) -> Result<W, Error>
+where
+    P: AsRef<Path>,
+    W: AsyncWrite + Unpin,
+{
+    let start = Instant::now();
+    let chunks = convert_chunks(path.as_ref(), options).await?;
+    let (output, _stats) = write_chunks_async(chunks, options, output, start).await?;
+    Ok(output)
+}
+
+/// Converts a parquet file that was written by IOx from the local
+/// file system path specified to a stream of line protocol chunks.
+///
+/// Batches are converted in parallel but the chunks are yielded in
+/// the same order as the rows are read, so the stream can be piped
+/// directly into an HTTP response or any other async sink without
+/// buffering the whole output. Rows are read in file order unless the
+/// file is read in several partitions (see
+/// [`ConverterOptions::with_read_partitions`]).
+pub async fn convert_stream<P>(path: P) -> Result<BoxStream<'static, Result<Bytes, Error>>, Error>
+where
+    P: AsRef<Path>,
+{
+    convert_stream_with_options(path, &ConverterOptions::default()).await
+}
+
+/// Like [`convert_stream`] but configured by `options`
+pub async fn convert_stream_with_options<P>(
+    path: P,
+    options: &ConverterOptions,
+) -> Result<BoxStream<'static, Result<Bytes, Error>>, Error>
+where
+    P: AsRef<Path>,
+{
+    let chunks = convert_chunks(path.as_ref(), options).await?;
+    Ok(chunks.map_ok(|chunk| chunk.data).boxed())
+}
+
+/// Converts the parquet file at `path`, see [`convert_stream`]
+async fn convert_chunks(
+    path: &Path,
+    options: &ConverterOptions,
+) -> Result<BoxStream<'static, Result<Chunk, Error>>, Error> {
+    convert_reader(open_local_file(path).await?, options).await
+}
+
+/// Converts the parquet file read by `reader`
+async fn convert_reader(
+    reader: ParquetFileReader,
+    options: &ConverterOptions,
+) -> Result<BoxStream<'static, Result<Chunk, Error>>, Error> {
     // Fire up a parquet reader, read the batches, and then convert
     // them asynchronously in parallel
+    let reader = reader.with_options(options);
+
+    // Determines the measurement name from the IOx metadata
+    let (iox_meta, iox_schema) = iox_metadata_and_schema(&reader.schema())?;
+
+    Ok(convert_batches(
+        reader.read().await?,
+        iox_meta.table_name,
+        Arc::new(iox_schema),
+        options,
+    ))
+}
+
+/// Reads a parquet file that was written by IOx from the local file
+/// system path specified as a stream of record batches with the IOx
+/// schema, without converting them to text. Only the predicate and
+/// batch size of `options` are used.
+///
+/// This allows feeding exported data directly into Arrow based tools.
+pub async fn read_batches<P>(
+    path: P,
+    options: &ConverterOptions,
+) -> Result<SendableRecordBatchStream, Error>
+where
+    P: AsRef<Path>,
+{
+    let reader = open_local_file(path.as_ref()).await?.with_options(options);
+
+    // ensures this is a file written by IOx
+    let (_iox_meta, iox_schema) = iox_metadata_and_schema(&reader.schema())?;
+
+    execute_stream(reader.plan(iox_schema.as_arrow())?, reader.task_ctx())
+        .await
+        .context(ExecutingStreamSnafu)
+}
+
+/// Writes the record batches of a parquet file that was written by IOx
+/// (see [`read_batches`]) to `output` in the [Arrow IPC file format],
+/// returning the writer on success.
+///
+/// [Arrow IPC file format]: https://arrow.apache.org/docs/format/Columnar.html#ipc-file-format
+pub async fn convert_file_to_arrow_ipc<W, P>(
+    path: P,
+    options: &ConverterOptions,
+    output: W,
+) -> Result<W, Error>
+where
+    P: AsRef<Path>,
+    W: Write,
+{
+    let mut batches = read_batches(path, options).await?;
+
+    let output = BufWriter::with_capacity(options.output_buffer_size(), output);
+    let mut writer = FileWriter::try_new(output, &batches.schema()).context(ArrowIpcSnafu)?;
+    while let Some(batch) = batches.next().await {
+        writer
+            .write(&batch.context(ArrowIpcSnafu)?)
+            .context(ArrowIpcSnafu)?;
+    }
+    writer.finish().context(ArrowIpcSnafu)?;
+
+    let output = writer.into_inner().context(ArrowIpcSnafu)?;
+    output.into_inner().map_err(|e| Error::IO {
+        source: e.into_error(),
+    })
+}
+
+/// Converts several parquet files written by IOx for the same table
+/// (such as all the files of a partition), writing the converted data
+/// to `output` and returning the writer on success.
+///
+/// Unlike converting each file in turn, the rows from all files are
+/// merged, sorted on their primary key (tags, then time) and
+/// deduplicated, so points that appear in more than one (overlapping)
+/// file are only emitted once. When duplicates have different field
+/// values, values from files later in `paths` take precedence.
+pub async fn convert_files_merged<W, P>(
+    paths: &[P],
+    options: &ConverterOptions,
+    output: W,
+) -> Result<W, Error>
+where
+    P: AsRef<Path>,
+    W: Write,
+{
+    let (output, _stats) = convert_files_merged_with_stats(paths, options, output).await?;
+    Ok(output)
+}
+
+/// Like [`convert_files_merged`] but also returns statistics about the
+/// conversion
+pub async fn convert_files_merged_with_stats<W, P>(
+    paths: &[P],
+    options: &ConverterOptions,
+    output: W,
+) -> Result<(W, ConversionStats), Error>
+where
+    P: AsRef<Path>,
+    W: Write,
+{
+    let start = Instant::now();
+    let chunks = convert_chunks_merged(paths, options).await?;
+    write_chunks(chunks, options, output, start).await
+}
+
+/// Like [`convert_files_merged`] but returns a stream of converted
+/// chunks. See [`convert_stream`] for details.
+pub async fn convert_stream_merged<P>(
+    paths: &[P],
+    options: &ConverterOptions,
+) -> Result<BoxStream<'static, Result<Bytes, Error>>, Error>
+where
+    P: AsRef<Path>,
+{
+    let chunks = convert_chunks_merged(paths, options).await?;
+    Ok(chunks.map_ok(|chunk| chunk.data).boxed())
+}
+
+/// Merges and converts the parquet files at `paths`, see
+/// [`convert_files_merged`]
+async fn convert_chunks_merged<P>(
+    paths: &[P],
+    options: &ConverterOptions,
+) -> Result<BoxStream<'static, Result<Chunk, Error>>, Error>
+where
+    P: AsRef<Path>,
+{
+    let mut readers = Vec::with_capacity(paths.len());
+    for path in paths {
+        readers.push(open_local_file(path.as_ref()).await?);
+    }
+
+    convert_readers_merged(readers, options).await
+}
+
+/// Merges and converts the parquet files read by `readers`
+async fn convert_readers_merged(
+    readers: Vec<ParquetFileReader>,
+    options: &ConverterOptions,
+) -> Result<BoxStream<'static, Result<Chunk, Error>>, Error> {
+    let readers: Vec<_> = readers
+        .into_iter()
+        .map(|reader| reader.with_options(options))
+        .collect();
+    let mut table_name: Option<Arc<str>> = None;
+    let mut merger = SchemaMerger::new();
+
+    for reader in &readers {
+        let (iox_meta, iox_schema) = iox_metadata_and_schema(&reader.schema())?;
+
+        match &table_name {
+            Some(table_name) if table_name != &iox_meta.table_name => {
+                return MismatchedTablesSnafu {
+                    expected: table_name.to_string(),
+                    actual: iox_meta.table_name.to_string(),
+                }
+                .fail()
+            }
+            Some(_) => {}
+            None => table_name = Some(Arc::clone(&iox_meta.table_name)),
+        }
+
+        merger = merger.merge(&iox_schema).context(MergingSchemasSnafu)?;
+    }
+
+    let table_name = table_name.context(NoFilesSnafu)?;
+    let iox_schema = merger.build();
+
+    let batches = merge::read_merged(&readers, &iox_schema, options.sort_by_time()).await?;
+
+    Ok(convert_batches(batches, table_name, iox_schema, options))
+}
+
+/// Converts the parquet files written by IOx identified by `url`, such
+/// as `s3://bucket/path/*.parquet` or `file:///path/to/file.parquet`,
+/// writing the converted data to `output` and returning the writer and
+/// statistics about the conversion on success.
+///
+/// The path of the URL may contain glob patterns (`*`, `?` and
+/// `[..]`) that are resolved by listing the object store. When more
+/// than one file matches, the files are merged as described for
+/// [`convert_files_merged`].
+///
+/// `s3://` URLs require the `aws` feature and are configured using
+/// the standard `AWS_*` environment variables.
+pub async fn convert_url<W>(
+    url: &str,
+    options: &ConverterOptions,
+    output: W,
+) -> Result<(W, ConversionStats), Error>
+where
+    W: Write,
+{
+    let start = Instant::now();
+    let mut readers = remote::open_url(url).await?;
+
+    let chunks = if readers.len() == 1 {
+        convert_reader(readers.remove(0), options).await?
+    } else {
+        convert_readers_merged(readers, options).await?
+    };
+    write_chunks(chunks, options, output, start).await
+}
+
+/// Reads the IOx metadata, schema and statistics of the parquet file
+/// at `path` on the local file system, without decoding any data
+/// pages
+pub async fn inspect<P>(path: P) -> Result<FileInfo, Error>
+where
+    P: AsRef<Path>,
+{
+    let reader = open_local_file(path.as_ref()).await?;
+    let (iox_meta, iox_schema) = iox_metadata_and_schema(&reader.schema())?;
+    let statistics = reader.statistics().await?;
+
+    Ok(FileInfo {
+        measurement_name: iox_meta.table_name,
+        namespace_name: iox_meta.namespace_name,
+        namespace_id: iox_meta.namespace_id,
+        table_id: iox_meta.table_id,
+        partition_id: iox_meta.partition_id,
+        partition_key: iox_meta.partition_key,
+        row_count: statistics.num_rows.unwrap_or_default(),
+        columns: inspect::columns(&iox_schema),
+        time_range: inspect::time_range(&iox_schema, statistics.column_statistics.as_deref()),
+        provenance: iox_meta.provenance,
+    })
+}
+
+/// A chunk of converted output and statistics about the data it was
+/// converted from
+struct Chunk {
+    data: Bytes,
+    stats: ConversionStats,
+}
+
+/// Writes all chunks from `stream` to `output`, in order, buffering
+/// as configured by `options`, and totals their statistics. The
+/// conversion is assumed to have started at `start`.
+async fn write_chunks<W>(
+    mut stream: BoxStream<'static, Result<Chunk, Error>>,
+    options: &ConverterOptions,
+    output: W,
+    start: Instant,
+) -> Result<(W, ConversionStats), Error>
+where
+    W: Write,
+{
+    let mut stats = ConversionStats::default();
+    let mut output = BufWriter::with_capacity(options.output_buffer_size(), output);
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        output.write_all(&chunk.data).context(IOSnafu)?;
+        stats.add(&chunk.stats);
+    }
+
+    let output = output.into_inner().map_err(|e| Error::IO {
+        source: e.into_error(),
+    })?;
+    stats.elapsed = start.elapsed();
+
+    Ok((output, stats))
+}
+
+/// Like [`write_chunks`] but for a [`tokio::io::AsyncWrite`]
+async fn write_chunks_async<W>(
+    mut stream: BoxStream<'static, Result<Chunk, Error>>,
+    options: &ConverterOptions,
+    output: W,
+    start: Instant,
+) -> Result<(W, ConversionStats), Error>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut stats = ConversionStats::default();
+    let mut output = tokio::io::BufWriter::with_capacity(options.output_buffer_size(), output);
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        output.write_all(&chunk.data).await.context(IOSnafu)?;
+        stats.add(&chunk.stats);
+    }
+    output.flush().await.context(IOSnafu)?;
+    stats.elapsed = start.elapsed();
+
+    Ok((output.into_inner(), stats))
+}
+
+/// Opens the parquet file at `path` on the local file system
+async fn open_local_file(path: &Path) -> Result<ParquetFileReader, Error> {
+    let object_store_path =
+        ObjectStorePath::from_filesystem_path(path).context(PathSnafu { path })?;
 
     let object_store = Arc::new(LocalFileSystem::new()) as Arc<dyn ObjectStore>;
     let object_store_url = ObjectStoreUrl::local_filesystem();
@@ -104,10 +634,12 @@ where
         .await
         .context(ObjectStorePathSnafu { object_store_path })?;
 
-    let reader = ParquetFileReader::try_new(object_store, object_store_url, object_meta).await?;
+    ParquetFileReader::try_new(object_store, object_store_url, object_meta).await
+}
 
-    // Determines the measurement name from the IOx metadata
-    let schema = reader.schema();
+/// Decodes the [`IoxMetadata`] and IOx [`Schema`] from the arrow
+/// schema of a parquet file written by IOx
+fn iox_metadata_and_schema(schema: &ArrowSchemaRef) -> Result<(IoxMetadata, Schema), Error> {
     let encoded_meta = schema
         .metadata
         .get(METADATA_KEY)
@@ -118,37 +650,72 @@ where
     // Attempt to extract the IOx schema from the schema stored in the
     // parquet file. This schema is where information such as what
     // columns are tags and fields is stored
-    let iox_schema: Schema = schema.try_into().context(SchemaSnafu)?;
+    let iox_schema: Schema = Arc::clone(schema).try_into().context(SchemaSnafu)?;
 
-    let iox_schema = Arc::new(iox_schema);
+    Ok((iox_meta, iox_schema))
+}
 
-    let measurement_name = iox_meta.table_name;
+/// Converts `batches` using the output format in `options`
+fn convert_batches(
+    batches: SendableRecordBatchStream,
+    measurement_name: Arc<str>,
+    iox_schema: Arc<Schema>,
+    options: &ConverterOptions,
+) -> BoxStream<'static, Result<Chunk, Error>> {
+    let output_format = Arc::clone(options.format());
+    let header = output_format.header(&measurement_name, &iox_schema);
+
+    // progress is accumulated in output order, header included
+    let observer = options.progress().cloned();
+    let mut progress = Progress {
+        bytes_written: header.as_ref().map(|h| h.len() as u64).unwrap_or_default(),
+        ..Default::default()
+    };
+    let header = header.map(|header| {
+        Ok(Chunk {
+            stats: ConversionStats::for_header(&header),
+            data: Bytes::from(header),
+        })
+    });
 
-    // now convert the record batches to line protocol, in parallel
-    let mut lp_stream = reader
-        .read()
-        .await?
-        .map(|batch| {
+    // now convert the record batches to the output format, in parallel
+    let lp_stream = batches
+        .map(move |batch| {
             let iox_schema = Arc::clone(&iox_schema);
             let measurement_name = Arc::clone(&measurement_name);
+            let output_format = Arc::clone(&output_format);
             tokio::task::spawn(async move {
                 batch
                     .map_err(|e| format!("Something bad happened reading batch: {}", e))
-                    .and_then(|batch| convert_to_lines(&measurement_name, &iox_schema, &batch))
+                    .and_then(|batch| {
+                        let data = output_format.convert(&measurement_name, &iox_schema, &batch)?;
+                        let stats = ConversionStats::for_batch(&iox_schema, &batch, &data);
+                        Ok(Chunk {
+                            data: Bytes::from(data),
+                            stats,
+                        })
+                    })
             })
         })
         // run some number of futures in parallel
-        .buffered(num_cpus::get());
-
-    // but print them to the output stream in the same order
-    while let Some(data) = lp_stream.next().await {
-        let data = data
-            .context(TaskSnafu)?
-            .map_err(|message| Error::Conversion { message })?;
-
-        output.write_all(&data).context(IOSnafu)?;
-    }
-    Ok(output)
+        .buffered(options.concurrency())
+        // but yield them in the same order
+        .map(move |data| {
+            let chunk = data
+                .context(TaskSnafu)?
+                .map_err(|message| Error::Conversion { message })?;
+
+            if let Some(observer) = &observer {
+                progress.rows_read += chunk.stats.rows_converted;
+                progress.bytes_written += chunk.stats.bytes_written;
+                progress.batches_completed += 1;
+                observer.batch_completed(&progress);
+            }
+
+            Ok(chunk)
+        });
+
+    futures::stream::iter(header).chain(lp_stream).boxed()
 }
 
 /// Handles the details of interacting with parquet libraries /
@@ -163,8 +730,18 @@ pub struct ParquetFileReader {
     schema: ArrowSchemaRef,
 
     /// number of rows to read in each batch (can pick small to
-    /// increase parallelism). Defaults to 1000
+    /// increase parallelism). Defaults to [`DEFAULT_BATCH_SIZE`]
     batch_size: usize,
+
+    /// Optional predicate; only rows matching it are returned
+    predicate: Option<Expr>,
+
+    /// Whether [`Self::read`] returns rows sorted by time
+    sort_by_time: bool,
+
+    /// number of byte ranges the file is split into, each read in
+    /// its own partition
+    partitions: NonZeroUsize,
 }
 
 impl ParquetFileReader {
@@ -189,26 +766,88 @@ impl ParquetFileReader {
             object_store_url,
             object_meta,
             schema,
-            batch_size: 1000,
+            batch_size: DEFAULT_BATCH_SIZE,
+            predicate: None,
+            sort_by_time: false,
+            partitions: NonZeroUsize::new(1).unwrap(),
         })
     }
 
+    /// Only return rows that match `predicate`. The predicate is also
+    /// used to prune row groups based on their statistics.
+    pub fn with_predicate(mut self, predicate: Expr) -> Self {
+        self.predicate = Some(predicate);
+        self
+    }
+
+    /// Set the number of rows read in each batch
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Return rows sorted by time from [`Self::read`]
+    pub fn with_sort_by_time(mut self, sort_by_time: bool) -> Self {
+        self.sort_by_time = sort_by_time;
+        self
+    }
+
+    /// Split the file into `partitions` byte ranges, which are read in
+    /// parallel. Each row group is read by the partition its data
+    /// starts in, so row groups are never split.
+    ///
+    /// When there is more than one partition, [`Self::read`] returns
+    /// rows in the order they are decoded rather than in file order
+    /// (unless they are sorted by time).
+    pub fn with_partitions(mut self, partitions: NonZeroUsize) -> Self {
+        self.partitions = partitions;
+        self
+    }
+
+    /// Apply the batch size, predicate, time sorting and read
+    /// partitions from `options`
+    pub fn with_options(mut self, options: &ConverterOptions) -> Self {
+        self.batch_size = options.batch_size();
+        self.predicate = options.predicate().cloned();
+        self.sort_by_time = options.sort_by_time();
+        self.partitions = options.read_partitions();
+        self
+    }
+
     // retrieves the Arrow schema for this file
     pub fn schema(&self) -> ArrowSchemaRef {
         Arc::clone(&self.schema)
     }
 
+    /// Read the row count and per column statistics from the
+    /// parquet footer
+    pub async fn statistics(&self) -> Result<Statistics, Error> {
+        ParquetFormat::default()
+            .infer_stats(&self.object_store, self.schema(), &self.object_meta)
+            .await
+            .context(ReadingStatisticsSnafu)
+    }
+
     /// read the parquet file as a stream
     pub async fn read(&self) -> Result<SendableRecordBatchStream, Error> {
+        let mut plan = self.plan(self.schema())?;
+        if self.sort_by_time {
+            plan = sort_by_time(plan)?;
+        }
+
+        execute_stream(plan, self.task_ctx())
+            .await
+            .context(ExecutingStreamSnafu)
+    }
+
+    /// Create a plan that reads the parquet file as `file_schema`,
+    /// which may be a superset of the columns in the file (missing
+    /// columns are filled with NULLs)
+    pub fn plan(&self, file_schema: ArrowSchemaRef) -> Result<Arc<dyn ExecutionPlan>, Error> {
         let base_config = FileScanConfig {
             object_store_url: self.object_store_url.clone(),
-            file_schema: self.schema(),
-            file_groups: vec![vec![PartitionedFile {
-                object_meta: self.object_meta.clone(),
-                partition_values: vec![],
-                range: None,
-                extensions: None,
-            }]],
+            file_schema: Arc::clone(&file_schema),
+            file_groups: self.file_groups(),
             statistics: Statistics::default(),
             projection: None,
             limit: None,
@@ -217,10 +856,63 @@ impl ParquetFileReader {
             config_options: ConfigOptions::new().into_shareable(),
         };
 
-        // set up enough datafusion context to do the real read session
-        let predicate = None;
+        let predicate = self
+            .predicate
+            .clone()
+            .map(|predicate| coerce(predicate, &file_schema))
+            .transpose()?;
         let metadata_size_hint = None;
-        let exec = ParquetExec::new(base_config, predicate, metadata_size_hint);
+        let exec: Arc<dyn ExecutionPlan> = Arc::new(ParquetExec::new(
+            base_config,
+            predicate.clone(),
+            metadata_size_hint,
+        ));
+
+        // The parquet scan only uses the predicate to prune row
+        // groups, so apply it to the remaining rows as well
+        let exec: Arc<dyn ExecutionPlan> = match predicate {
+            Some(predicate) => {
+                let physical_predicate = physical_expr(&predicate, &file_schema)?;
+                Arc::new(FilterExec::try_new(physical_predicate, exec).context(PredicateSnafu)?)
+            }
+            None => exec,
+        };
+
+        Ok(exec)
+    }
+
+    /// Splits the file into one file group per partition. The
+    /// parquet reader only reads the row groups whose data starts
+    /// within the range of a group, so each row group is read exactly
+    /// once.
+    fn file_groups(&self) -> Vec<Vec<PartitionedFile>> {
+        let file = |range| PartitionedFile {
+            object_meta: self.object_meta.clone(),
+            partition_values: vec![],
+            range,
+            extensions: None,
+        };
+
+        let size = self.object_meta.size as i64;
+        if self.partitions.get() == 1 || size == 0 {
+            return vec![vec![file(None)]];
+        }
+
+        let partitions = self.partitions.get() as i64;
+        let range_size = (size + partitions - 1) / partitions;
+        (0..partitions)
+            .map(|i| i * range_size)
+            .take_while(|start| *start < size)
+            .map(|start| {
+                let end = (start + range_size).min(size);
+                vec![file(Some(FileRange { start, end }))]
+            })
+            .collect()
+    }
+
+    /// set up enough datafusion context to execute plans created by
+    /// [`Self::plan`]
+    pub fn task_ctx(&self) -> Arc<TaskContext> {
         let session_config = SessionConfig::new().with_batch_size(self.batch_size);
         let session_ctx = SessionContext::with_config(session_config);
 
@@ -229,9 +921,56 @@ impl ParquetFileReader {
         task_ctx
             .runtime_env()
             .register_object_store("iox", "iox", object_store);
-
-        execute_stream(Arc::new(exec), task_ctx)
-            .await
-            .context(ExecutingStreamSnafu)
+        task_ctx
     }
 }
+
+/// Apply type coercion to `predicate` so that it matches `schema`
+/// (e.g. comparing a float column to an integer literal)
+fn coerce(predicate: Expr, schema: &ArrowSchemaRef) -> Result<Expr, Error> {
+    let df_schema = Arc::clone(schema)
+        .to_dfschema_ref()
+        .context(PredicateSnafu)?;
+    let props = ExecutionProps::new();
+    let simplifier =
+        ExprSimplifier::new(SimplifyContext::new(&props).with_schema(Arc::clone(&df_schema)));
+    simplifier
+        .coerce(predicate, df_schema)
+        .context(PredicateSnafu)
+}
+
+/// Create a physical expression for `predicate`, evaluated against
+/// `schema`
+fn physical_expr(
+    predicate: &Expr,
+    schema: &ArrowSchemaRef,
+) -> Result<Arc<dyn PhysicalExpr>, Error> {
+    let df_schema = Arc::clone(schema)
+        .to_dfschema_ref()
+        .context(PredicateSnafu)?;
+    create_physical_expr(predicate, &df_schema, schema, &ExecutionProps::new())
+        .context(PredicateSnafu)
+}
+
+/// Sorts the output of `plan` by time, ascending
+fn sort_by_time(plan: Arc<dyn ExecutionPlan>) -> Result<Arc<dyn ExecutionPlan>, Error> {
+    let schema = plan.schema();
+    let sort_exprs = vec![PhysicalSortExpr {
+        expr: col(TIME_COLUMN_NAME, &schema).context(SortSnafu)?,
+        options: SortOptions {
+            descending: false,
+            nulls_first: false,
+        },
+    }];
+
+    // sort across all partitions, not within each one
+    let input: Arc<dyn ExecutionPlan> = if plan.output_partitioning().partition_count() > 1 {
+        Arc::new(CoalescePartitionsExec::new(plan))
+    } else {
+        plan
+    };
+
+    Ok(Arc::new(
+        SortExec::try_new(sort_exprs, input, None).context(SortSnafu)?,
+    ))
+}
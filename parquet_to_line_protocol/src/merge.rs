@@ -0,0 +1,120 @@
+//! Merges the data of several IOx parquet files into a single sorted
+//! and deduplicated stream
+
+use datafusion::{
+    arrow::compute::SortOptions,
+    physical_plan::{
+        coalesce_partitions::CoalescePartitionsExec,
+        execute_stream,
+        expressions::{col, Literal, PhysicalSortExpr},
+        projection::ProjectionExec,
+        sorts::sort::SortExec,
+        union::UnionExec,
+        ExecutionPlan, PhysicalExpr, SendableRecordBatchStream,
+    },
+    scalar::ScalarValue,
+};
+use iox_query::{provider::DeduplicateExec, util::arrow_pk_sort_exprs};
+use schema::Schema;
+use snafu::{OptionExt, ResultExt};
+use std::sync::Arc;
+
+use crate::{Error, ExecutingStreamSnafu, MergeSnafu, NoFilesSnafu, ParquetFileReader};
+
+/// Name of the column used to order rows from different files that
+/// have the same primary key, so later files take precedence
+const FILE_ORDER_COLUMN: &str = "__file_order";
+
+/// Reads all `readers` as `iox_schema` (the merged schema of all the
+/// files), returning a stream sorted on the primary key with
+/// duplicates removed.
+///
+/// When rows with the same primary key appear in several files, the
+/// last non-null value of each field (in the order of `readers`) is
+/// used.
+///
+/// If `sort_by_time` is true, the deduplicated rows are sorted by time
+/// instead of by primary key.
+pub(crate) async fn read_merged(
+    readers: &[ParquetFileReader],
+    iox_schema: &Schema,
+    sort_by_time: bool,
+) -> Result<SendableRecordBatchStream, Error> {
+    let task_ctx = readers.first().context(NoFilesSnafu)?.task_ctx();
+    let file_schema = iox_schema.as_arrow();
+
+    // tag every row with the position of the file it came from
+    let inputs = readers
+        .iter()
+        .enumerate()
+        .map(|(file_order, reader)| {
+            let plan = reader.plan(Arc::clone(&file_schema))?;
+            with_file_order(plan, file_order as u64)
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let input: Arc<dyn ExecutionPlan> = Arc::new(CoalescePartitionsExec::new(Arc::new(
+        UnionExec::new(inputs),
+    )));
+    let input_schema = input.schema();
+
+    // sort on the primary key, breaking ties by file order
+    let pk_sort_exprs = arrow_pk_sort_exprs(iox_schema.primary_key(), &input_schema);
+    let mut sort_exprs = pk_sort_exprs.clone();
+    sort_exprs.push(PhysicalSortExpr {
+        expr: col(FILE_ORDER_COLUMN, &input_schema).context(MergeSnafu)?,
+        options: SortOptions {
+            descending: false,
+            nulls_first: false,
+        },
+    });
+    let sort = Arc::new(SortExec::try_new(sort_exprs, input, None).context(MergeSnafu)?);
+
+    let dedup: Arc<dyn ExecutionPlan> = Arc::new(DeduplicateExec::new(sort, pk_sort_exprs));
+
+    // and finally remove the file order column again
+    let dedup_schema = dedup.schema();
+    let projection = file_schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let expr = col(field.name(), &dedup_schema).context(MergeSnafu)?;
+            Ok((expr, field.name().to_string()))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let mut plan: Arc<dyn ExecutionPlan> =
+        Arc::new(ProjectionExec::try_new(projection, dedup).context(MergeSnafu)?);
+    if sort_by_time {
+        plan = crate::sort_by_time(plan)?;
+    }
+
+    execute_stream(plan, task_ctx)
+        .await
+        .context(ExecutingStreamSnafu)
+}
+
+/// Appends a constant [`FILE_ORDER_COLUMN`] with value `file_order`
+/// to the output of `plan`
+fn with_file_order(
+    plan: Arc<dyn ExecutionPlan>,
+    file_order: u64,
+) -> Result<Arc<dyn ExecutionPlan>, Error> {
+    let schema = plan.schema();
+    let mut exprs = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let expr = col(field.name(), &schema).context(MergeSnafu)?;
+            Ok((expr, field.name().to_string()))
+        })
+        .collect::<Result<Vec<(Arc<dyn PhysicalExpr>, String)>, Error>>()?;
+
+    exprs.push((
+        Arc::new(Literal::new(ScalarValue::UInt64(Some(file_order)))),
+        FILE_ORDER_COLUMN.to_string(),
+    ));
+
+    Ok(Arc::new(
+        ProjectionExec::try_new(exprs, plan).context(MergeSnafu)?,
+    ))
+}
@@ -0,0 +1,202 @@
+//! Options controlling how parquet files are converted
+
+use datafusion::prelude::Expr;
+use std::{fmt::Debug, num::NonZeroUsize, sync::Arc};
+
+use crate::{LineProtocolFormat, OutputFormat, ProgressObserver};
+
+/// The default number of rows decoded into each batch
+pub const DEFAULT_BATCH_SIZE: usize = 1000;
+
+/// The default capacity of the buffer used when writing output
+pub const DEFAULT_OUTPUT_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Options for converting parquet files, built using the `with_*`
+/// methods:
+///
+/// ```
+/// # use parquet_to_line_protocol::{ConverterOptions, Format};
+/// let options = ConverterOptions::default()
+///     .with_batch_size(10_000)
+///     .with_concurrency(2)
+///     .with_format(Format::Csv.output_format());
+/// ```
+///
+/// Smaller batches and more concurrent conversion tasks increase
+/// throughput at the cost of memory.
+#[derive(Clone)]
+pub struct ConverterOptions {
+    /// number of rows to read in each batch
+    batch_size: usize,
+
+    /// maximum number of batches converted concurrently
+    concurrency: usize,
+
+    /// number of parts each file is split into and decoded in parallel
+    read_partitions: NonZeroUsize,
+
+    /// capacity of the buffer used when writing to a `Write`
+    output_buffer_size: usize,
+
+    /// Only rows matching this predicate are converted
+    predicate: Option<Expr>,
+
+    /// The format to convert to
+    format: Arc<dyn OutputFormat>,
+
+    /// Sort rows by time before converting them
+    sort_by_time: bool,
+
+    /// Notified as batches are converted
+    progress: Option<Arc<dyn ProgressObserver>>,
+}
+
+impl Debug for ConverterOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConverterOptions")
+            .field("batch_size", &self.batch_size)
+            .field("concurrency", &self.concurrency)
+            .field("read_partitions", &self.read_partitions)
+            .field("output_buffer_size", &self.output_buffer_size)
+            .field("predicate", &self.predicate)
+            .field("format", &self.format)
+            .field("sort_by_time", &self.sort_by_time)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
+}
+
+impl Default for ConverterOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: DEFAULT_BATCH_SIZE,
+            concurrency: num_cpus::get(),
+            read_partitions: NonZeroUsize::new(1).unwrap(),
+            output_buffer_size: DEFAULT_OUTPUT_BUFFER_SIZE,
+            predicate: None,
+            format: Arc::new(LineProtocolFormat::default()),
+            sort_by_time: false,
+            progress: None,
+        }
+    }
+}
+
+impl ConverterOptions {
+    /// Set the number of rows decoded into each batch. Defaults to
+    /// [`DEFAULT_BATCH_SIZE`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch_size` is 0.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "batch size must be greater than 0");
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Set the maximum number of batches that are converted
+    /// concurrently. Defaults to the number of CPUs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `concurrency` is 0.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        assert!(concurrency > 0, "concurrency must be greater than 0");
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Split each file into `read_partitions` byte ranges that are
+    /// decoded in parallel, each range containing the row groups that
+    /// start within it. Defaults to 1, reading files serially.
+    ///
+    /// Reading in parallel speeds up converting large files with many
+    /// row groups, but rows are no longer emitted in file order.
+    pub fn with_read_partitions(mut self, read_partitions: NonZeroUsize) -> Self {
+        self.read_partitions = read_partitions;
+        self
+    }
+
+    /// Set the capacity of the buffer used when writing the output to
+    /// a [`std::io::Write`]. Defaults to
+    /// [`DEFAULT_OUTPUT_BUFFER_SIZE`].
+    pub fn with_output_buffer_size(mut self, output_buffer_size: usize) -> Self {
+        self.output_buffer_size = output_buffer_size;
+        self
+    }
+
+    /// Only convert rows that match `predicate`.
+    ///
+    /// The predicate is pushed down into the parquet scan, so row
+    /// groups that can not match (based on their statistics) are not
+    /// decoded at all. See [`Filter`](crate::Filter) for building
+    /// simple tag / field predicates.
+    pub fn with_predicate(mut self, predicate: Expr) -> Self {
+        self.predicate = Some(predicate);
+        self
+    }
+
+    /// Convert to `format` instead of line protocol
+    pub fn with_format(mut self, format: Arc<dyn OutputFormat>) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// If `sort_by_time` is true, sort all rows by time before
+    /// converting them, so the output is in ascending time order as
+    /// required by some ingest pipelines.
+    ///
+    /// Sorting requires buffering the whole file (or all merged files)
+    /// in memory.
+    pub fn with_sort_by_time(mut self, sort_by_time: bool) -> Self {
+        self.sort_by_time = sort_by_time;
+        self
+    }
+
+    /// Report progress (rows read, bytes written, batches completed)
+    /// to `observer` as the conversion proceeds
+    pub fn with_progress(mut self, observer: Arc<dyn ProgressObserver>) -> Self {
+        self.progress = Some(observer);
+        self
+    }
+
+    /// The number of rows decoded into each batch
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    /// The maximum number of batches converted concurrently
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    /// The number of parts each file is split into when reading
+    pub fn read_partitions(&self) -> NonZeroUsize {
+        self.read_partitions
+    }
+
+    /// The capacity of the output buffer
+    pub fn output_buffer_size(&self) -> usize {
+        self.output_buffer_size
+    }
+
+    /// The predicate rows must match to be converted, if any
+    pub fn predicate(&self) -> Option<&Expr> {
+        self.predicate.as_ref()
+    }
+
+    /// The format to convert to
+    pub fn format(&self) -> &Arc<dyn OutputFormat> {
+        &self.format
+    }
+
+    /// Whether rows are sorted by time before being converted
+    pub fn sort_by_time(&self) -> bool {
+        self.sort_by_time
+    }
+
+    /// The observer notified of conversion progress, if any
+    pub fn progress(&self) -> Option<&Arc<dyn ProgressObserver>> {
+        self.progress.as_ref()
+    }
+}
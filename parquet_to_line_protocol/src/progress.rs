@@ -0,0 +1,87 @@
+//! Progress reporting for long running conversions
+
+use tokio::sync::{mpsc, watch};
+
+/// Cumulative progress of a conversion
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Number of rows read (and converted) so far
+    pub rows_read: u64,
+
+    /// Number of bytes of converted output produced so far
+    pub bytes_written: u64,
+
+    /// Number of batches converted so far
+    pub batches_completed: u64,
+}
+
+/// Receives progress updates from a conversion, for example to render
+/// a progress bar or to update metrics.
+///
+/// Implemented for closures, as well as for [`watch::Sender`] and
+/// [`mpsc::UnboundedSender`] so progress can be observed from another
+/// task.
+pub trait ProgressObserver: Send + Sync {
+    /// Called, in output order, after each batch has been converted
+    fn batch_completed(&self, progress: &Progress);
+}
+
+impl<F> ProgressObserver for F
+where
+    F: Fn(&Progress) + Send + Sync,
+{
+    fn batch_completed(&self, progress: &Progress) {
+        self(progress)
+    }
+}
+
+impl ProgressObserver for watch::Sender<Progress> {
+    fn batch_completed(&self, progress: &Progress) {
+        self.send_replace(*progress);
+    }
+}
+
+impl ProgressObserver for mpsc::UnboundedSender<Progress> {
+    fn batch_completed(&self, progress: &Progress) {
+        // the receiver going away does not stop the conversion
+        self.send(*progress).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn closure_observer() {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let observer = {
+            let seen = Arc::clone(&seen);
+            move |progress: &Progress| seen.lock().unwrap().push(*progress)
+        };
+
+        let progress = Progress {
+            rows_read: 10,
+            bytes_written: 100,
+            batches_completed: 1,
+        };
+        observer.batch_completed(&progress);
+
+        assert_eq!(*seen.lock().unwrap(), vec![progress]);
+    }
+
+    #[test]
+    fn watch_observer() {
+        let (tx, rx) = watch::channel(Progress::default());
+
+        let progress = Progress {
+            rows_read: 3,
+            bytes_written: 30,
+            batches_completed: 2,
+        };
+        tx.batch_completed(&progress);
+
+        assert_eq!(*rx.borrow(), progress);
+    }
+}
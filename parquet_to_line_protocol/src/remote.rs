@@ -0,0 +1,138 @@
+//! Opens the parquet files identified by an object store URL such as
+//! `s3://bucket/path/*.parquet` or `file:///path/to/file.parquet`
+
+use datafusion::datasource::object_store::ObjectStoreUrl;
+use futures::TryStreamExt;
+use glob::{MatchOptions, Pattern};
+use object_store::{
+    local::LocalFileSystem, path::Path as ObjectStorePath, ObjectMeta, ObjectStore,
+};
+use percent_encoding::percent_decode_str;
+use snafu::ResultExt;
+use std::sync::Arc;
+use url::Url;
+
+use crate::{
+    Error, InvalidGlobSnafu, InvalidUrlSnafu, ObjectStorePathSnafu, ParquetFileReader,
+    UrlPathEncodingSnafu, UrlPathSnafu,
+};
+
+/// Characters that make a path a glob pattern
+const GLOB_CHARS: &[char] = &['*', '?', '['];
+
+/// Opens all parquet files matching `url`, in path order. The path of
+/// the URL may contain glob patterns (`*`, `?` and `[..]`), which do
+/// not match across `/`.
+pub(crate) async fn open_url(url: &str) -> Result<Vec<ParquetFileReader>, Error> {
+    let parsed = Url::parse(url).context(InvalidUrlSnafu { url })?;
+    let object_store = object_store_for_url(&parsed)?;
+    let path = percent_decode_str(parsed.path())
+        .decode_utf8()
+        .context(UrlPathEncodingSnafu { url })?;
+    let path = ObjectStorePath::parse(path).context(UrlPathSnafu { url })?;
+
+    let objects = if path.as_ref().contains(GLOB_CHARS) {
+        list_matching(&object_store, &path).await?
+    } else {
+        let object_meta = object_store
+            .head(&path)
+            .await
+            .context(ObjectStorePathSnafu {
+                object_store_path: path,
+            })?;
+        vec![object_meta]
+    };
+
+    // the object store is registered under this URL by
+    // `ParquetFileReader::task_ctx`
+    let object_store_url = ObjectStoreUrl::parse("iox://iox/").expect("valid object store URL");
+
+    let mut readers = Vec::with_capacity(objects.len());
+    for object_meta in objects {
+        readers.push(
+            ParquetFileReader::try_new(
+                Arc::clone(&object_store),
+                object_store_url.clone(),
+                object_meta,
+            )
+            .await?,
+        );
+    }
+
+    Ok(readers)
+}
+
+/// Lists all objects whose location matches the glob pattern `path`
+async fn list_matching(
+    object_store: &Arc<dyn ObjectStore>,
+    path: &ObjectStorePath,
+) -> Result<Vec<ObjectMeta>, Error> {
+    let pattern = Pattern::new(path.as_ref()).context(InvalidGlobSnafu {
+        pattern: path.as_ref(),
+    })?;
+    let match_options = MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+
+    // only list below the part of the path that contains no patterns
+    let prefix: ObjectStorePath = path
+        .parts()
+        .take_while(|part| !part.as_ref().contains(GLOB_CHARS))
+        .collect();
+
+    let mut objects: Vec<_> = object_store
+        .list(Some(&prefix))
+        .await
+        .context(ObjectStorePathSnafu {
+            object_store_path: prefix.clone(),
+        })?
+        .try_filter(|object_meta| {
+            let matches = pattern.matches_with(object_meta.location.as_ref(), match_options);
+            futures::future::ready(matches)
+        })
+        .try_collect()
+        .await
+        .context(ObjectStorePathSnafu {
+            object_store_path: prefix,
+        })?;
+
+    objects.sort_by(|a, b| a.location.cmp(&b.location));
+    Ok(objects)
+}
+
+/// Creates the object store for the scheme (and bucket) of `url`
+fn object_store_for_url(url: &Url) -> Result<Arc<dyn ObjectStore>, Error> {
+    match url.scheme() {
+        "file" => Ok(Arc::new(LocalFileSystem::new())),
+        "s3" => new_s3(url),
+        scheme => Err(Error::UnsupportedUrlScheme {
+            scheme: scheme.to_string(),
+        }),
+    }
+}
+
+/// Creates an S3 object store for the bucket of `url`, configured
+/// (credentials, region, ..) from the standard `AWS_*` environment
+/// variables
+#[cfg(feature = "aws")]
+fn new_s3(url: &Url) -> Result<Arc<dyn ObjectStore>, Error> {
+    use object_store::aws::AmazonS3Builder;
+
+    let bucket = url.host_str().unwrap_or_default();
+    let store = AmazonS3Builder::from_env()
+        .with_bucket_name(bucket)
+        .build()
+        .context(crate::CreatingObjectStoreSnafu {
+            url: url.to_string(),
+        })?;
+
+    Ok(Arc::new(store))
+}
+
+#[cfg(not(feature = "aws"))]
+fn new_s3(_: &Url) -> Result<Arc<dyn ObjectStore>, Error> {
+    Err(Error::UnsupportedUrlScheme {
+        scheme: "s3 (recompile with the aws feature enabled)".to_string(),
+    })
+}
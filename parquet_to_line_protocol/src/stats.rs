@@ -0,0 +1,116 @@
+//! Statistics about a completed conversion
+
+use datafusion::arrow::record_batch::RecordBatch;
+use schema::{InfluxColumnType, Schema};
+use std::{collections::BTreeMap, time::Duration};
+
+/// Summary of a conversion, returned by
+/// [`convert_file_with_stats`](crate::convert_file_with_stats),
+/// [`convert_files_merged_with_stats`](crate::convert_files_merged_with_stats)
+/// and [`convert_url`](crate::convert_url) so that callers can log and
+/// verify that the export is complete
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConversionStats {
+    /// Number of rows read and converted
+    pub rows_converted: u64,
+
+    /// Number of lines of converted output, not including any header
+    pub lines_emitted: u64,
+
+    /// Total number of bytes of output, including any header
+    pub bytes_written: u64,
+
+    /// Number of NULL field values that were skipped
+    pub null_fields_skipped: u64,
+
+    /// Number of non-NULL values converted, per column
+    pub column_counts: BTreeMap<String, u64>,
+
+    /// Time taken by the whole conversion
+    pub elapsed: Duration,
+}
+
+impl ConversionStats {
+    /// Statistics for converting `batch` into `data`
+    pub(crate) fn for_batch(iox_schema: &Schema, batch: &RecordBatch, data: &[u8]) -> Self {
+        let mut stats = Self {
+            rows_converted: batch.num_rows() as u64,
+            lines_emitted: data.iter().filter(|b| **b == b'\n').count() as u64,
+            bytes_written: data.len() as u64,
+            ..Default::default()
+        };
+
+        for (field, column) in batch.schema().fields().iter().zip(batch.columns()) {
+            let null_count = column.null_count() as u64;
+            let is_field = iox_schema
+                .find_index_of(field.name())
+                .map(|idx| matches!(iox_schema.field(idx).0, InfluxColumnType::Field(_)))
+                .unwrap_or(false);
+            if is_field {
+                stats.null_fields_skipped += null_count;
+            }
+
+            stats
+                .column_counts
+                .insert(field.name().to_string(), column.len() as u64 - null_count);
+        }
+
+        stats
+    }
+
+    /// Statistics for writing a `header`
+    pub(crate) fn for_header(header: &[u8]) -> Self {
+        Self {
+            bytes_written: header.len() as u64,
+            ..Default::default()
+        }
+    }
+
+    /// Adds the counts of `other` to `self`
+    pub(crate) fn add(&mut self, other: &Self) {
+        self.rows_converted += other.rows_converted;
+        self.lines_emitted += other.lines_emitted;
+        self.bytes_written += other.bytes_written;
+        self.null_fields_skipped += other.null_fields_skipped;
+        for (column, count) in &other.column_counts {
+            *self.column_counts.entry(column.clone()).or_default() += count;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutable_batch_lp::lines_to_batches;
+    use schema::Projection;
+
+    #[test]
+    fn batch_stats() {
+        let lp = "m,tag=a f=1,g=2i 1\nm f=3 2\n";
+        let mutable_batch = lines_to_batches(lp, 0).unwrap().remove("m").unwrap();
+        let iox_schema = mutable_batch.schema(Projection::All).unwrap();
+        let batch = mutable_batch.to_arrow(Projection::All).unwrap();
+
+        let mut stats = ConversionStats::for_header(b"header\n");
+        stats.add(&ConversionStats::for_batch(
+            &iox_schema,
+            &batch,
+            lp.as_bytes(),
+        ));
+
+        assert_eq!(stats.rows_converted, 2);
+        assert_eq!(stats.lines_emitted, 2);
+        assert_eq!(stats.bytes_written, 7 + lp.len() as u64);
+        // g is NULL in the second row
+        assert_eq!(stats.null_fields_skipped, 1);
+        assert_eq!(
+            stats.column_counts,
+            BTreeMap::from([
+                ("f".to_string(), 2),
+                ("g".to_string(), 1),
+                ("tag".to_string(), 1),
+                ("time".to_string(), 2),
+            ])
+        );
+    }
+}
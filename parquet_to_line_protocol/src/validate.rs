@@ -0,0 +1,213 @@
+//! Checks that converted line protocol parses back to the data it
+//! was converted from
+
+use datafusion::arrow::record_batch::RecordBatch;
+use influxdb_line_protocol::{parse_lines, FieldValue as LPFieldValue, ParsedLine};
+use schema::{InfluxColumnType, Schema};
+use std::str::FromStr;
+
+use crate::{
+    batch::{field_value, tag_value, timestamp_value},
+    Error, Precision,
+};
+
+/// How converted line protocol is checked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Validation {
+    /// Do not check the output
+    #[default]
+    Off,
+
+    /// Re-parse the output and log a warning for each line that does
+    /// not match its source row
+    Warn,
+
+    /// Re-parse the output and fail the conversion if any line does not
+    /// match its source row
+    Strict,
+}
+
+impl FromStr for Validation {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "warn" => Ok(Self::Warn),
+            "strict" => Ok(Self::Strict),
+            _ => Err(Error::UnknownValidation {
+                validation: s.to_string(),
+            }),
+        }
+    }
+}
+
+/// Parses `lines`, the line protocol converted from `batch`, and
+/// returns a description of every line that does not parse or does
+/// not round trip to its source row. Line numbers are relative to the
+/// start of `lines`.
+pub(crate) fn check_round_trip(
+    measurement_name: &str,
+    iox_schema: &Schema,
+    batch: &RecordBatch,
+    precision: Precision,
+    lines: &[u8],
+) -> Vec<String> {
+    let lines = match std::str::from_utf8(lines) {
+        Ok(lines) => lines,
+        Err(e) => return vec![format!("output is not valid UTF-8: {}", e)],
+    };
+
+    let mut problems = vec![];
+    let mut parsed_lines = parse_lines(lines);
+    for row_index in 0..batch.num_rows() {
+        let line_number = row_index + 1;
+        match parsed_lines.next() {
+            Some(Ok(line)) => {
+                let mismatch = compare_row(
+                    measurement_name,
+                    iox_schema,
+                    batch,
+                    precision,
+                    row_index,
+                    &line,
+                );
+                if let Some(mismatch) = mismatch {
+                    problems.push(format!("line {}: {}", line_number, mismatch));
+                }
+            }
+            Some(Err(e)) => problems.push(format!("line {}: {}", line_number, e)),
+            None => {
+                problems.push(format!(
+                    "expected {} lines, found {}",
+                    batch.num_rows(),
+                    row_index
+                ));
+                return problems;
+            }
+        }
+    }
+
+    if parsed_lines.next().is_some() {
+        problems.push(format!("found more than {} lines", batch.num_rows()));
+    }
+
+    problems
+}
+
+/// Compares a parsed `line` to the row of `batch` it was converted
+/// from, describing the first difference found
+fn compare_row(
+    measurement_name: &str,
+    iox_schema: &Schema,
+    batch: &RecordBatch,
+    precision: Precision,
+    row_index: usize,
+    line: &ParsedLine<'_>,
+) -> Option<String> {
+    if line.series.measurement.as_str() != measurement_name {
+        return Some(format!(
+            "measurement '{}' does not match '{}'",
+            line.series.measurement, measurement_name
+        ));
+    }
+
+    let mut expected_tags = vec![];
+    let mut expected_fields = vec![];
+    for (column_index, (influx_column_type, field)) in iox_schema.iter().enumerate() {
+        match influx_column_type {
+            InfluxColumnType::Tag => {
+                if let Some(value) = tag_value(batch, column_index, row_index) {
+                    expected_tags.push((field.name().as_str(), value));
+                }
+            }
+            InfluxColumnType::Field(field_type) => {
+                if let Some(value) = field_value(field_type, batch.column(column_index), row_index)
+                {
+                    expected_fields.push((field.name().as_str(), value));
+                }
+            }
+            InfluxColumnType::Timestamp => {}
+        }
+    }
+
+    let mut tags: Vec<_> = line
+        .series
+        .tag_set
+        .iter()
+        .flatten()
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .collect();
+    tags.sort_unstable();
+    expected_tags.sort_unstable();
+    if tags != expected_tags {
+        return Some(format!("tags {:?} do not match {:?}", tags, expected_tags));
+    }
+
+    let fields: Vec<(&str, &LPFieldValue<'_>)> = line
+        .field_set
+        .iter()
+        .map(|(key, value)| (key.as_str(), value))
+        .collect();
+    let expected: Vec<(&str, &LPFieldValue<'_>)> = expected_fields
+        .iter()
+        .map(|(key, value)| (*key, value))
+        .collect();
+    if fields != expected {
+        return Some(format!("fields {:?} do not match {:?}", fields, expected));
+    }
+
+    let expected_timestamp = match timestamp_value(iox_schema, row_index, batch) {
+        Ok(ts) => precision.convert_timestamp(ts),
+        Err(e) => return Some(e),
+    };
+    if line.timestamp != Some(expected_timestamp) {
+        return Some(format!(
+            "timestamp {:?} does not match {}",
+            line.timestamp, expected_timestamp
+        ));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mutable_batch_lp::lines_to_batches;
+    use schema::Projection;
+
+    #[test]
+    fn round_trip() {
+        let lp = "m,tag=a\\ b f=1,s=\"x \\\"y\\\"\" 1\nm i=2i 2\n";
+        let (batch, iox_schema) = to_batch(lp);
+
+        let converted =
+            crate::batch::convert_to_lines("m", &iox_schema, &batch, Precision::Nanoseconds)
+                .unwrap();
+        let problems =
+            check_round_trip("m", &iox_schema, &batch, Precision::Nanoseconds, &converted);
+        assert!(problems.is_empty(), "{:?}", problems);
+    }
+
+    #[test]
+    fn mismatches() {
+        let (batch, iox_schema) = to_batch("m,tag=a f=1 1\nm,tag=b f=2 2\nm,tag=c f=3 3\n");
+
+        // unescaped space in the tag value, wrong field, missing line
+        let lines = b"m,tag=a x f=1 1\nm,tag=b f=3 2\n";
+        let problems = check_round_trip("m", &iox_schema, &batch, Precision::Nanoseconds, lines);
+
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(problems[0].starts_with("line 1: "), "{}", problems[0]);
+        assert!(problems[1].starts_with("line 2: fields"), "{}", problems[1]);
+        assert_eq!(problems[2], "expected 3 lines, found 2");
+    }
+
+    fn to_batch(lp: &str) -> (RecordBatch, Schema) {
+        let mutable_batch = lines_to_batches(lp, 0).unwrap().remove("m").unwrap();
+        let batch = mutable_batch.to_arrow(Projection::All).unwrap();
+        let iox_schema = mutable_batch.schema(Projection::All).unwrap();
+        (batch, iox_schema)
+    }
+}
@@ -0,0 +1,332 @@
+//! Read path for the local parquet file cache of a co-located ingester.
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    ops::Range,
+    path::PathBuf,
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use metric::U64Counter;
+use object_store::{
+    path::Path, Error as ObjectStoreError, GetResult, ListResult, MultipartId, ObjectMeta,
+    ObjectStore,
+};
+use observability_deps::tracing::warn;
+use tokio::io::AsyncWrite;
+use uuid::Uuid;
+
+const STORE_NAME: &str = "IngesterFileCacheStore";
+
+/// The file extension of a parquet file cached by the ingester.
+const FILE_EXTENSION: &str = "parquet";
+
+/// An [`ObjectStore`] decorator reading parquet files from the local file
+/// cache of a co-located ingester, falling back to the wrapped store for all
+/// files not cached.
+///
+/// The ingester caches each persisted file as `<object store ID>.parquet` in
+/// its cache directory, so the cached copy of an object is found by the file
+/// name of its object store path. Parquet files are immutable, so a cached
+/// copy is identical to the object. The ingester evicts files at any time; a
+/// file evicted before it is opened is read from the wrapped store instead.
+#[derive(Debug)]
+pub struct IngesterFileCacheStore {
+    inner: Arc<dyn ObjectStore>,
+    dir: PathBuf,
+
+    hit: U64Counter,
+    miss: U64Counter,
+}
+
+impl IngesterFileCacheStore {
+    /// Read the files cached by an ingester in `dir`, and all other objects
+    /// from `inner`.
+    pub fn new(
+        inner: Arc<dyn ObjectStore>,
+        dir: PathBuf,
+        metric_registry: &metric::Registry,
+    ) -> Self {
+        let reads = metric_registry.register_metric::<U64Counter>(
+            "querier_ingester_file_cache_reads",
+            "reads of parquet files from the local file cache of a co-located ingester",
+        );
+
+        Self {
+            inner,
+            dir,
+            hit: reads.recorder(&[("status", "hit")]),
+            miss: reads.recorder(&[("status", "miss")]),
+        }
+    }
+
+    /// The local path of the cached copy of the object at `location`, if it
+    /// is named like a parquet file cached by the ingester.
+    fn cached_path(&self, location: &Path) -> Option<PathBuf> {
+        let file_name = location.parts().last()?;
+        let (stem, extension) = file_name.as_ref().rsplit_once('.')?;
+        if extension != FILE_EXTENSION {
+            return None;
+        }
+        let object_store_id = Uuid::parse_str(stem).ok()?;
+
+        Some(self.dir.join(format!("{object_store_id}.{FILE_EXTENSION}")))
+    }
+
+    /// Open the cached copy of the object at `location`, if any.
+    async fn open(&self, location: &Path) -> Option<(File, PathBuf)> {
+        let path = self.cached_path(location)?;
+
+        let res = tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || File::open(path)
+        })
+        .await
+        .expect("opening cached file panicked");
+
+        match res {
+            Ok(file) => {
+                self.hit.inc(1);
+                Some((file, path))
+            }
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    warn!(
+                        error=%e,
+                        path=%path.display(),
+                        "failed to open cached parquet file of ingester"
+                    );
+                }
+                self.miss.inc(1);
+                None
+            }
+        }
+    }
+}
+
+/// Read `range` from the start of `file`.
+fn read_range(mut file: File, range: Range<usize>) -> io::Result<Bytes> {
+    let len = range.end.saturating_sub(range.start);
+    let mut buf = vec![0; len];
+
+    file.seek(SeekFrom::Start(range.start as u64))?;
+    file.read_exact(&mut buf)?;
+
+    Ok(buf.into())
+}
+
+impl std::fmt::Display for IngesterFileCacheStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "IngesterFileCacheStore({})", self.inner)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for IngesterFileCacheStore {
+    async fn put(&self, location: &Path, bytes: Bytes) -> Result<(), ObjectStoreError> {
+        self.inner.put(location, bytes).await
+    }
+
+    async fn put_multipart(
+        &self,
+        location: &Path,
+    ) -> Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>), ObjectStoreError> {
+        self.inner.put_multipart(location).await
+    }
+
+    async fn abort_multipart(
+        &self,
+        location: &Path,
+        multipart_id: &MultipartId,
+    ) -> Result<(), ObjectStoreError> {
+        self.inner.abort_multipart(location, multipart_id).await
+    }
+
+    async fn get(&self, location: &Path) -> Result<GetResult, ObjectStoreError> {
+        match self.open(location).await {
+            Some((file, path)) => Ok(GetResult::File(file, path)),
+            None => self.inner.get(location).await,
+        }
+    }
+
+    async fn get_range(
+        &self,
+        location: &Path,
+        range: Range<usize>,
+    ) -> Result<Bytes, ObjectStoreError> {
+        let (file, _path) = match self.open(location).await {
+            Some(cached) => cached,
+            None => return self.inner.get_range(location, range).await,
+        };
+
+        tokio::task::spawn_blocking(move || read_range(file, range))
+            .await
+            .expect("reading cached file panicked")
+            .map_err(|e| ObjectStoreError::Generic {
+                store: STORE_NAME,
+                source: Box::new(e),
+            })
+    }
+
+    async fn head(&self, location: &Path) -> Result<ObjectMeta, ObjectStoreError> {
+        self.inner.head(location).await
+    }
+
+    async fn delete(&self, location: &Path) -> Result<(), ObjectStoreError> {
+        self.inner.delete(location).await
+    }
+
+    async fn list(
+        &self,
+        prefix: Option<&Path>,
+    ) -> Result<BoxStream<'_, Result<ObjectMeta, ObjectStoreError>>, ObjectStoreError> {
+        self.inner.list(prefix).await
+    }
+
+    async fn list_with_delimiter(
+        &self,
+        prefix: Option<&Path>,
+    ) -> Result<ListResult, ObjectStoreError> {
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &Path, to: &Path) -> Result<(), ObjectStoreError> {
+        self.inner.copy(from, to).await
+    }
+
+    async fn copy_if_not_exists(&self, from: &Path, to: &Path) -> Result<(), ObjectStoreError> {
+        self.inner.copy_if_not_exists(from, to).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use metric::{Attributes, Metric};
+    use object_store::memory::InMemory;
+
+    use super::*;
+
+    const CACHED_DATA: &[u8] = b"cached";
+    const STORED_DATA: &[u8] = b"stored";
+
+    struct TestStore {
+        store: IngesterFileCacheStore,
+        metric_registry: metric::Registry,
+        cached: Path,
+        uncached: Path,
+        _dir: tempfile::TempDir,
+    }
+
+    impl TestStore {
+        async fn new() -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let inner = Arc::new(InMemory::new());
+            let metric_registry = metric::Registry::default();
+
+            let cached_id = Uuid::new_v4();
+            let cached = Path::from(format!("1/2/3/4/{cached_id}.parquet"));
+            let uncached = Path::from(format!("1/2/3/4/{}.parquet", Uuid::new_v4()));
+
+            std::fs::write(dir.path().join(format!("{cached_id}.parquet")), CACHED_DATA).unwrap();
+            for location in [&cached, &uncached] {
+                inner
+                    .put(location, Bytes::from_static(STORED_DATA))
+                    .await
+                    .unwrap();
+            }
+
+            let store =
+                IngesterFileCacheStore::new(inner, dir.path().to_path_buf(), &metric_registry);
+
+            Self {
+                store,
+                metric_registry,
+                cached,
+                uncached,
+                _dir: dir,
+            }
+        }
+
+        fn reads(&self, status: &'static str) -> u64 {
+            self.metric_registry
+                .get_instrument::<Metric<U64Counter>>("querier_ingester_file_cache_reads")
+                .unwrap()
+                .get_observer(&Attributes::from(&[("status", status)]))
+                .unwrap()
+                .fetch()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get() {
+        let test = TestStore::new().await;
+
+        let data = test
+            .store
+            .get(&test.cached)
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        assert_eq!(data.as_ref(), CACHED_DATA);
+
+        let data = test
+            .store
+            .get(&test.uncached)
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        assert_eq!(data.as_ref(), STORED_DATA);
+
+        assert_eq!(test.reads("hit"), 1);
+        assert_eq!(test.reads("miss"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_range() {
+        let test = TestStore::new().await;
+
+        let data = test.store.get_range(&test.cached, 1..4).await.unwrap();
+        assert_eq!(data.as_ref(), &CACHED_DATA[1..4]);
+
+        let data = test.store.get_range(&test.uncached, 1..4).await.unwrap();
+        assert_eq!(data.as_ref(), &STORED_DATA[1..4]);
+
+        test.store
+            .get_range(&test.cached, 1..100)
+            .await
+            .unwrap_err();
+
+        assert_eq!(test.reads("hit"), 2);
+        assert_eq!(test.reads("miss"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_only_parquet_files_cached() {
+        let test = TestStore::new().await;
+
+        let location = Path::from("1/2/3/4/not-a-uuid.parquet");
+        test.store
+            .put(&location, Bytes::from_static(STORED_DATA))
+            .await
+            .unwrap();
+        let data = test
+            .store
+            .get(&location)
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        assert_eq!(data.as_ref(), STORED_DATA);
+
+        assert_eq!(test.reads("hit"), 0);
+        assert_eq!(test.reads("miss"), 0);
+    }
+}
@@ -14,12 +14,14 @@ use self::{
     projected_schema::ProjectedSchemaCache, ram::RamSize, tombstones::TombstoneCache,
 };
 
+pub mod ingester_file;
 pub mod namespace;
 pub mod object_store;
 pub mod parquet_file;
 pub mod partition;
 pub mod processed_tombstones;
 pub mod projected_schema;
+pub mod query_result;
 mod ram;
 pub mod tombstones;
 
@@ -14,8 +14,13 @@ use cache_system::{
 use data_types::{ParquetFileId, TombstoneId};
 use iox_catalog::interface::Catalog;
 use iox_time::TimeProvider;
-use std::{collections::HashMap, mem::size_of_val, sync::Arc, time::Duration};
-use trace::span::Span;
+use std::{
+    collections::{HashMap, HashSet},
+    mem::size_of_val,
+    sync::Arc,
+    time::Duration,
+};
+use trace::span::{Span, SpanRecorder};
 
 use super::ram::RamSize;
 
@@ -40,6 +45,8 @@ type CacheT = Box<
 #[derive(Debug)]
 pub struct ProcessedTombstonesCache {
     cache: CacheT,
+    catalog: Arc<dyn Catalog>,
+    backoff_config: BackoffConfig,
 }
 
 impl ProcessedTombstonesCache {
@@ -52,9 +59,11 @@ impl ProcessedTombstonesCache {
         ram_pool: Arc<ResourcePool<RamSize>>,
         testing: bool,
     ) -> Self {
+        let loader_catalog = Arc::clone(&catalog);
+        let loader_backoff_config = backoff_config.clone();
         let loader = FunctionLoader::new(move |(parquet_file_id, tombstone_id), _extra: ()| {
-            let catalog = Arc::clone(&catalog);
-            let backoff_config = backoff_config.clone();
+            let catalog = Arc::clone(&loader_catalog);
+            let backoff_config = loader_backoff_config.clone();
 
             async move {
                 Backoff::new(&backoff_config)
@@ -100,10 +109,15 @@ impl ProcessedTombstonesCache {
             metric_registry,
         ));
 
-        Self { cache }
+        Self {
+            cache,
+            catalog,
+            backoff_config,
+        }
     }
 
     /// Check if the specified tombstone is mark as "processed" for the given parquet file.
+    #[cfg(test)]
     pub async fn exists(
         &self,
         parquet_file_id: ParquetFileId,
@@ -114,6 +128,76 @@ impl ProcessedTombstonesCache {
             .get((parquet_file_id, tombstone_id), ((), span))
             .await
     }
+
+    /// Return the (parquet file, tombstone) pairs of `pairs` for which the tombstone is marked as
+    /// "processed".
+    ///
+    /// The pairs that are not cached yet are resolved with a single catalog query for all their
+    /// tombstones (instead of one query per pair) and are cached afterwards.
+    pub async fn processed(
+        &self,
+        pairs: &[(ParquetFileId, TombstoneId)],
+        span: Option<Span>,
+    ) -> HashSet<(ParquetFileId, TombstoneId)> {
+        let span_recorder = SpanRecorder::new(span);
+
+        let mut processed = HashSet::new();
+        let mut uncached = vec![];
+        for &pair in pairs {
+            match self
+                .cache
+                .peek(
+                    pair,
+                    (
+                        (),
+                        span_recorder.child_span("cache PEEK processed_tombstone"),
+                    ),
+                )
+                .await
+            {
+                Some(true) => {
+                    processed.insert(pair);
+                }
+                Some(false) => {}
+                None => uncached.push(pair),
+            }
+        }
+
+        if uncached.is_empty() {
+            return processed;
+        }
+
+        let tombstone_ids: Vec<_> = uncached
+            .iter()
+            .map(|(_, tombstone_id)| *tombstone_id)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let in_catalog: HashSet<_> = Backoff::new(&self.backoff_config)
+            .retry_all_errors("list processed tombstones", || async {
+                self.catalog
+                    .repositories()
+                    .await
+                    .processed_tombstones()
+                    .list_by_tombstone_ids(&tombstone_ids)
+                    .await
+            })
+            .await
+            .expect("retry forever")
+            .into_iter()
+            .map(|pt| (pt.parquet_file_id, pt.tombstone_id))
+            .collect();
+
+        for pair in uncached {
+            let exists = in_catalog.contains(&pair);
+            self.cache.set(pair, exists).await;
+            if exists {
+                processed.insert(pair);
+            }
+        }
+
+        processed
+    }
 }
 
 #[derive(Debug)]
@@ -274,4 +358,67 @@ mod tests {
                 .await
         );
     }
+
+    #[tokio::test]
+    async fn test_processed_batched() {
+        let catalog = TestCatalog::new();
+
+        let ns = catalog.create_namespace_1hr_retention("ns").await;
+        let table = ns.create_table("table").await;
+        table.create_column("foo", ColumnType::F64).await;
+        table.create_column("time", ColumnType::Time).await;
+        let shard = ns.create_shard(1).await;
+        let partition = table.with_shard(&shard).create_partition("k").await;
+
+        let builder = TestParquetFileBuilder::default().with_line_protocol(TABLE_LINE_PROTOCOL);
+        let file1 = partition.create_parquet_file(builder.clone()).await;
+        let file2 = partition.create_parquet_file(builder).await;
+        let ts1 = table
+            .with_shard(&shard)
+            .create_tombstone(1, 1, 10, "foo=1")
+            .await;
+        let ts2 = table
+            .with_shard(&shard)
+            .create_tombstone(2, 1, 10, "foo=1")
+            .await;
+
+        ts1.mark_processed(&file1).await;
+        ts2.mark_processed(&file2).await;
+
+        let cache = ProcessedTombstonesCache::new(
+            catalog.catalog(),
+            BackoffConfig::default(),
+            catalog.time_provider(),
+            &catalog.metric_registry(),
+            test_ram_pool(),
+            true,
+        );
+
+        let pairs = [
+            (file1.parquet_file.id, ts1.tombstone.id),
+            (file1.parquet_file.id, ts2.tombstone.id),
+            (file2.parquet_file.id, ts1.tombstone.id),
+            (file2.parquet_file.id, ts2.tombstone.id),
+        ];
+
+        // all pairs are resolved by a single catalog query
+        let processed = cache.processed(&pairs, None).await;
+        assert_eq!(processed, HashSet::from([pairs[0], pairs[3]]));
+        assert_histogram_metric_count(
+            &catalog.metric_registry,
+            "processed_tombstone_list_by_tombstone_ids",
+            1,
+        );
+
+        // and are cached afterwards
+        let processed = cache.processed(&pairs, None).await;
+        assert_eq!(processed, HashSet::from([pairs[0], pairs[3]]));
+        assert!(cache.exists(pairs[0].0, pairs[0].1, None).await);
+        assert!(!cache.exists(pairs[1].0, pairs[1].1, None).await);
+        assert_histogram_metric_count(
+            &catalog.metric_registry,
+            "processed_tombstone_list_by_tombstone_ids",
+            1,
+        );
+    }
 }
@@ -0,0 +1,188 @@
+//! Query result cache.
+use std::{collections::HashMap, mem::size_of_val, sync::Arc, time::Duration};
+
+use cache_system::{
+    backend::{
+        policy::{
+            lru::{LruPolicy, ResourcePool},
+            ttl::{TtlPolicy, TtlProvider},
+            PolicyBackend,
+        },
+        CacheBackend,
+    },
+    resource_consumption::FunctionEstimator,
+};
+use iox_time::TimeProvider;
+use metric::U64Counter;
+use parking_lot::Mutex;
+use service_common::result_cache::{
+    QueryResult, QueryResultCache as QueryResultCacheTrait, QueryResultKey,
+};
+
+use super::ram::RamSize;
+
+const CACHE_ID: &str = "query_result";
+
+/// Cache of the results of queries, e.g. of dashboards repeatedly issuing the
+/// same queries.
+///
+/// Results are identified by the set of parquet files they were computed
+/// from (see [`QueryResultKey`]), so a result is not served anymore once the
+/// data of its tables changes. The TTL bounds how long a result is held,
+/// independent of how often it is served.
+///
+/// The cache has its own RAM pool, so that large results do not evict the
+/// catalog data and parquet files cached by the querier.
+#[derive(Debug)]
+pub struct QueryResultCache {
+    backend: Mutex<PolicyBackend<QueryResultKey, Arc<QueryResult>>>,
+    max_result_bytes: usize,
+    metric_hit: U64Counter,
+    metric_miss: U64Counter,
+}
+
+impl QueryResultCache {
+    /// Create new empty cache of up to `ram_pool_bytes`, holding each result
+    /// for up to `ttl`.
+    pub fn new(
+        ram_pool_bytes: usize,
+        ttl: Duration,
+        time_provider: Arc<dyn TimeProvider>,
+        metric_registry: Arc<metric::Registry>,
+    ) -> Self {
+        let ram_pool = Arc::new(ResourcePool::new(
+            "ram_query_result",
+            RamSize(ram_pool_bytes),
+            Arc::clone(&metric_registry),
+        ));
+
+        let mut backend = PolicyBackend::new(Box::new(HashMap::new()), time_provider);
+        backend.add_policy(TtlPolicy::new(
+            Arc::new(ConstantTtl(ttl)),
+            CACHE_ID,
+            &metric_registry,
+        ));
+        backend.add_policy(LruPolicy::new(
+            ram_pool,
+            CACHE_ID,
+            Arc::new(FunctionEstimator::new(
+                |k: &QueryResultKey, v: &Arc<QueryResult>| {
+                    RamSize(k.size() + size_of_val(v) + v.size())
+                },
+            )),
+        ));
+
+        let metric_get = metric_registry
+            .register_metric::<U64Counter>("query_result_cache_get", "Query result cache lookups");
+
+        Self {
+            backend: Mutex::new(backend),
+            max_result_bytes: ram_pool_bytes,
+            metric_hit: metric_get.recorder(&[("status", "hit")]),
+            metric_miss: metric_get.recorder(&[("status", "miss")]),
+        }
+    }
+}
+
+impl QueryResultCacheTrait for QueryResultCache {
+    fn get(&self, key: &QueryResultKey) -> Option<Arc<QueryResult>> {
+        let result = self.backend.lock().get(key);
+        match &result {
+            Some(_) => self.metric_hit.inc(1),
+            None => self.metric_miss.inc(1),
+        }
+        result
+    }
+
+    fn set(&self, key: QueryResultKey, result: Arc<QueryResult>) {
+        self.backend.lock().set(key, result);
+    }
+
+    fn max_result_bytes(&self) -> usize {
+        self.max_result_bytes
+    }
+}
+
+#[derive(Debug)]
+struct ConstantTtl(Duration);
+
+impl TtlProvider for ConstantTtl {
+    type K = QueryResultKey;
+    type V = Arc<QueryResult>;
+
+    fn expires_in(&self, _k: &Self::K, _v: &Self::V) -> Option<Duration> {
+        Some(self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::{
+        array::Int64Array,
+        datatypes::{DataType, Field, Schema},
+        record_batch::RecordBatch,
+    };
+    use datafusion::physical_plan::empty::EmptyExec;
+    use iox_time::{MockProvider, Time};
+    use service_common::result_cache::QueryResultCollector;
+
+    use super::*;
+
+    fn key(query: &str) -> QueryResultKey {
+        let schema = Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]));
+        QueryResultKey::try_new("ns", query, &EmptyExec::new(false, schema)).unwrap()
+    }
+
+    fn batch(n: i64) -> RecordBatch {
+        RecordBatch::try_from_iter([("a", Arc::new(Int64Array::from_iter_values(0..n)) as _)])
+            .unwrap()
+    }
+
+    #[test]
+    fn test_ttl() {
+        let time_provider = Arc::new(MockProvider::new(Time::from_timestamp_nanos(0)));
+        let metric_registry = Arc::new(metric::Registry::new());
+        let cache = Arc::new(QueryResultCache::new(
+            usize::MAX,
+            Duration::from_secs(10),
+            Arc::clone(&time_provider) as _,
+            Arc::clone(&metric_registry),
+        ));
+
+        assert!(cache.get(&key("q1")).is_none());
+
+        let mut collector = QueryResultCollector::new(Arc::clone(&cache) as _, key("q1"));
+        assert!(collector.push(&batch(3)));
+        collector.finish();
+
+        let result = cache.get(&key("q1")).unwrap();
+        assert_eq!(result.batches(), &[batch(3)]);
+        assert!(cache.get(&key("q2")).is_none());
+        assert_eq!(cache.metric_hit.fetch(), 1);
+        assert_eq!(cache.metric_miss.fetch(), 2);
+
+        time_provider.inc(Duration::from_secs(10));
+        assert!(cache.get(&key("q1")).is_none());
+    }
+
+    #[test]
+    fn test_size_limit() {
+        let time_provider = Arc::new(MockProvider::new(Time::from_timestamp_nanos(0)));
+        let cache = Arc::new(QueryResultCache::new(
+            10_000,
+            Duration::from_secs(10),
+            time_provider as _,
+            Arc::new(metric::Registry::new()),
+        ));
+
+        // Results are evicted to stay within the RAM pool.
+        for i in 0..10 {
+            let mut collector =
+                QueryResultCollector::new(Arc::clone(&cache) as _, key(&format!("q{i}")));
+            assert!(collector.push(&batch(500)));
+            collector.finish();
+        }
+        assert!(cache.get(&key("q0")).is_none());
+        assert!(cache.get(&key("q9")).is_some());
+    }
+}
@@ -19,6 +19,7 @@ use std::{collections::HashMap, mem, sync::Arc};
 use trace::span::Span;
 
 use super::ram::RamSize;
+use crate::tombstone::QuerierTombstone;
 
 const CACHE_ID: &str = "tombstone";
 
@@ -36,13 +37,22 @@ pub enum Error {
 pub struct CachedTombstones {
     /// Tombstones that were cached in the catalog
     pub tombstones: Arc<Vec<Arc<Tombstone>>>,
+
+    /// The same tombstones with their delete predicates parsed, so that the
+    /// predicates are parsed once per cache load rather than for every query.
+    querier_tombstones: Arc<Vec<QuerierTombstone>>,
 }
 impl CachedTombstones {
     fn new(tombstones: Vec<Tombstone>) -> Self {
         let tombstones: Vec<_> = tombstones.into_iter().map(Arc::new).collect();
+        let querier_tombstones: Vec<_> = tombstones
+            .iter()
+            .map(|t| QuerierTombstone::from(t.as_ref()))
+            .collect();
 
         Self {
             tombstones: Arc::new(tombstones),
+            querier_tombstones: Arc::new(querier_tombstones),
         }
     }
 
@@ -53,7 +63,10 @@ impl CachedTombstones {
             mem::size_of_val(&self.tombstones) +
             // Size of Arcs in Vec
             (self.tombstones.capacity() * mem::size_of::<Arc<Tombstone>>()) +
-            self.tombstones.iter().map(|t| t.size()).sum::<usize>()
+            self.tombstones.iter().map(|t| t.size()).sum::<usize>() +
+            // size of the parsed tombstones
+            mem::size_of_val(&self.querier_tombstones) +
+            self.querier_tombstones.iter().map(|t| t.size()).sum::<usize>()
     }
 
     /// return the underlying Tombestones
@@ -61,6 +74,11 @@ impl CachedTombstones {
         self.tombstones.iter().map(Arc::clone).collect()
     }
 
+    /// return the tombstones with their parsed delete predicates
+    pub(crate) fn querier_tombstones(&self) -> Vec<QuerierTombstone> {
+        self.querier_tombstones.iter().cloned().collect()
+    }
+
     /// Returns the greatest tombstone sequence number stored in this cache entry
     pub(crate) fn max_tombstone_sequence_number(&self) -> Option<SequenceNumber> {
         self.tombstones.iter().map(|f| f.sequence_number).max()
@@ -247,6 +265,42 @@ mod tests {
         assert_histogram_metric_count(&catalog.metric_registry, METRIC_NAME, 1);
     }
 
+    #[tokio::test]
+    async fn test_parsed_delete_predicates() {
+        let catalog = TestCatalog::new();
+
+        let ns = catalog.create_namespace_1hr_retention("ns").await;
+        let table1 = ns.create_table("table1").await;
+        let shard1 = ns.create_shard(1).await;
+
+        let table_and_shard = table1.with_shard(&shard1);
+        let table_id = table1.table.id;
+
+        let tombstone1 = table_and_shard.create_tombstone(7, 1, 100, "foo=1").await;
+
+        let cache = make_cache(&catalog);
+        let cached_tombstones = cache.get(table_id, None, None).await;
+
+        // The delete predicate is parsed once, when the entry is loaded
+        let querier_tombstones = cached_tombstones.querier_tombstones();
+        assert_eq!(querier_tombstones.len(), 1);
+        assert_eq!(
+            querier_tombstones[0].tombstone_id(),
+            tombstone1.tombstone.id
+        );
+        assert_eq!(
+            querier_tombstones[0].delete_predicate().expr_sql_string(),
+            r#""foo"=1"#
+        );
+
+        // and shared by every reader of the entry
+        let again = cache.get(table_id, None, None).await.querier_tombstones();
+        assert!(Arc::ptr_eq(
+            querier_tombstones[0].delete_predicate(),
+            again[0].delete_predicate()
+        ));
+    }
+
     #[tokio::test]
     async fn test_multiple_tables() {
         let catalog = TestCatalog::new();
@@ -288,8 +342,8 @@ mod tests {
 
         let cache = make_cache(&catalog);
 
-        let single_tombstone_size = 101;
-        let two_tombstone_size = 186;
+        let single_tombstone_size = 288;
+        let two_tombstone_size = 544;
         assert!(single_tombstone_size < two_tombstone_size);
 
         // Create tombstone 1
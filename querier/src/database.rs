@@ -1,22 +1,28 @@
 //! Database for the querier that contains all namespaces.
 
 use crate::{
-    cache::CatalogCache, chunk::ChunkAdapter, ingester::IngesterConnection,
-    namespace::QuerierNamespace, query_log::QueryLog, table::PruneMetrics,
+    cache::{query_result::QueryResultCache, CatalogCache},
+    chunk::ChunkAdapter,
+    ingester::IngesterConnection,
+    namespace::QuerierNamespace,
+    query_log::QueryLog,
+    table::PruneMetrics,
 };
 use async_trait::async_trait;
 use backoff::{Backoff, BackoffConfig};
 use data_types::{Namespace, ShardIndex};
 use iox_catalog::interface::Catalog;
 use iox_query::exec::Executor;
-use service_common::QueryNamespaceProvider;
+use service_common::{
+    admission::{NamespaceQueryLimiter, QueryPermit, QueryShedError},
+    result_cache::QueryResultCache as QueryResultCacheTrait,
+    QueryNamespaceProvider,
+};
 use sharder::JumpHash;
-use snafu::Snafu;
-use std::{collections::BTreeSet, sync::Arc};
+use snafu::{ensure, Snafu};
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
 use trace::span::{Span, SpanRecorder};
-use tracker::{
-    AsyncSemaphoreMetrics, InstrumentedAsyncOwnedSemaphorePermit, InstrumentedAsyncSemaphore,
-};
+use tracker::{AsyncSemaphoreMetrics, InstrumentedAsyncSemaphore};
 
 /// The number of entries to store in the circular query buffer log.
 ///
@@ -32,6 +38,12 @@ pub enum Error {
     },
     #[snafu(display("No shards loaded"))]
     NoShards,
+    #[snafu(display(
+        "Invalid per-namespace query concurrency {max_concurrent_queries}, must be between 1 and \
+        {}",
+        QuerierDatabase::MAX_CONCURRENT_QUERIES_MAX
+    ))]
+    InvalidNamespaceQueryConcurrency { max_concurrent_queries: usize },
 }
 
 /// Database for the querier.
@@ -49,7 +61,6 @@ pub struct QuerierDatabase {
     chunk_adapter: Arc<ChunkAdapter>,
 
     /// Metric registry
-    #[allow(dead_code)]
     metric_registry: Arc<metric::Registry>,
 
     /// Executor for queries.
@@ -68,6 +79,16 @@ pub struct QuerierDatabase {
     /// If the same namespace is requested twice for different queries, it is counted twice.
     query_execution_semaphore: Arc<InstrumentedAsyncSemaphore>,
 
+    /// Limits the number of active queries of each namespace, if configured.
+    ///
+    /// A query waits for the permit of its namespace before waiting for
+    /// [`Self::query_execution_semaphore`], so that the queries of a single
+    /// namespace can not occupy all of its permits.
+    namespace_query_limiter: Option<NamespaceQueryLimiter>,
+
+    /// Cache of query results, if enabled.
+    result_cache: Option<Arc<QueryResultCache>>,
+
     /// Sharder to determine which ingesters to query for a particular table and namespace.
     sharder: Arc<JumpHash<Arc<ShardIndex>>>,
 
@@ -86,11 +107,34 @@ impl QueryNamespaceProvider for QuerierDatabase {
         self.namespace(name, span).await
     }
 
-    async fn acquire_semaphore(&self, span: Option<Span>) -> InstrumentedAsyncOwnedSemaphorePermit {
-        Arc::clone(&self.query_execution_semaphore)
-            .acquire_owned(span)
+    async fn acquire_semaphore(
+        &self,
+        name: &str,
+        span: Option<Span>,
+    ) -> Result<QueryPermit, QueryShedError> {
+        let span_recorder = SpanRecorder::new(span);
+
+        let namespace_permit = match &self.namespace_query_limiter {
+            Some(limiter) => Some(
+                limiter
+                    .acquire(name, span_recorder.child_span("namespace semaphore"))
+                    .await?,
+            ),
+            None => None,
+        };
+
+        let permit = Arc::clone(&self.query_execution_semaphore)
+            .acquire_owned(span_recorder.child_span("global semaphore"))
             .await
-            .expect("Semaphore should not be closed by anyone")
+            .expect("Semaphore should not be closed by anyone");
+
+        Ok(QueryPermit::new(namespace_permit, permit))
+    }
+
+    fn result_cache(&self) -> Option<Arc<dyn QueryResultCacheTrait>> {
+        self.result_cache
+            .as_ref()
+            .map(|cache| Arc::clone(cache) as _)
     }
 }
 
@@ -147,12 +191,56 @@ impl QuerierDatabase {
             ingester_connection,
             query_log,
             query_execution_semaphore,
+            namespace_query_limiter: None,
+            result_cache: None,
             sharder,
             max_table_query_bytes,
             prune_metrics,
         })
     }
 
+    /// Limit the number of active queries of each namespace to
+    /// `max_concurrent_queries`, with up to `max_queued_queries` more waiting
+    /// before further queries of the namespace are rejected.
+    ///
+    /// Returns an error unless `max_concurrent_queries` is between 1 and
+    /// [`Self::MAX_CONCURRENT_QUERIES_MAX`].
+    pub fn with_namespace_query_limits(
+        self,
+        max_concurrent_queries: usize,
+        max_queued_queries: usize,
+    ) -> Result<Self, Error> {
+        ensure!(
+            (1..=Self::MAX_CONCURRENT_QUERIES_MAX).contains(&max_concurrent_queries),
+            InvalidNamespaceQueryConcurrencySnafu {
+                max_concurrent_queries
+            }
+        );
+
+        Ok(Self {
+            namespace_query_limiter: Some(NamespaceQueryLimiter::new(
+                max_concurrent_queries,
+                max_queued_queries,
+                &self.metric_registry,
+            )),
+            ..self
+        })
+    }
+
+    /// Cache the results of queries in a RAM pool of `ram_pool_bytes`, for up
+    /// to `ttl` each.
+    pub fn with_result_cache(self, ram_pool_bytes: usize, ttl: Duration) -> Self {
+        Self {
+            result_cache: Some(Arc::new(QueryResultCache::new(
+                ram_pool_bytes,
+                ttl,
+                self.catalog_cache.time_provider(),
+                Arc::clone(&self.metric_registry),
+            ))),
+            ..self
+        }
+    }
+
     /// Get namespace if it exists.
     ///
     /// This will await the internal namespace semaphore. Existence of namespaces is checked AFTER
@@ -324,6 +412,57 @@ mod tests {
         assert!(db.namespace("ns2", None).await.is_none());
     }
 
+    #[tokio::test]
+    async fn test_namespace_query_limits() {
+        let catalog = TestCatalog::new();
+        // QuerierDatabase::new returns an error if there are no shards in the catalog
+        catalog.create_shard(0).await;
+
+        let catalog_cache = Arc::new(CatalogCache::new_testing(
+            catalog.catalog(),
+            catalog.time_provider(),
+            catalog.metric_registry(),
+            catalog.object_store(),
+            &Handle::current(),
+        ));
+        let new_db = || {
+            QuerierDatabase::new(
+                Arc::clone(&catalog_cache),
+                catalog.metric_registry(),
+                catalog.exec(),
+                Some(create_ingester_connection_for_testing()),
+                QuerierDatabase::MAX_CONCURRENT_QUERIES_MAX,
+                usize::MAX,
+            )
+        };
+
+        // Out of range limits are rejected.
+        for max_concurrent_queries in [0, QuerierDatabase::MAX_CONCURRENT_QUERIES_MAX + 1] {
+            let res = new_db()
+                .await
+                .unwrap()
+                .with_namespace_query_limits(max_concurrent_queries, 0);
+            assert_error!(res, Error::InvalidNamespaceQueryConcurrency { .. });
+        }
+
+        let db = new_db()
+            .await
+            .unwrap()
+            .with_namespace_query_limits(1, 0)
+            .unwrap();
+
+        let permit = db.acquire_semaphore("ns1", None).await.unwrap();
+
+        // Further queries of the namespace are shed...
+        assert!(db.acquire_semaphore("ns1", None).await.is_err());
+
+        // ...without affecting other namespaces.
+        db.acquire_semaphore("ns2", None).await.unwrap();
+
+        drop(permit);
+        db.acquire_semaphore("ns1", None).await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_namespaces() {
         let catalog = TestCatalog::new();
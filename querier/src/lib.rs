@@ -24,7 +24,7 @@ mod system_tables;
 mod table;
 mod tombstone;
 
-pub use cache::CatalogCache as QuerierCatalogCache;
+pub use cache::{ingester_file::IngesterFileCacheStore, CatalogCache as QuerierCatalogCache};
 pub use database::{Error as QuerierDatabaseError, QuerierDatabase};
 pub use handler::{QuerierHandler, QuerierHandlerImpl};
 pub use ingester::{
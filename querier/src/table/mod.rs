@@ -405,7 +405,7 @@ impl QuerierTable {
             .reconciler
             .reconcile(
                 partitions,
-                tombstones.to_vec(),
+                tombstones.querier_tombstones(),
                 retention_delete_pred,
                 parquet_files,
                 span_recorder.child_span("reconcile"),
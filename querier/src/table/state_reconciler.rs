@@ -2,7 +2,7 @@
 
 mod interface;
 
-use data_types::{CompactionLevel, DeletePredicate, PartitionId, ShardId, Tombstone, TombstoneId};
+use data_types::{CompactionLevel, DeletePredicate, PartitionId, ShardId, TombstoneId};
 use iox_query::QueryChunk;
 use observability_deps::tracing::debug;
 use schema::sort::SortKey;
@@ -55,7 +55,7 @@ impl Reconciler {
     pub(crate) async fn reconcile(
         &self,
         ingester_partitions: Vec<IngesterPartition>,
-        tombstones: Vec<Arc<Tombstone>>,
+        tombstones: Vec<QuerierTombstone>,
         retention_delete_pred: Option<DeletePredicate>,
         parquet_files: Vec<QuerierChunk>,
         span: Option<Span>,
@@ -88,7 +88,7 @@ impl Reconciler {
     async fn build_chunks_from_parquet(
         &self,
         ingester_partitions: &[IngesterPartition],
-        tombstones: Vec<Arc<Tombstone>>,
+        tombstones: Vec<QuerierTombstone>,
         retention_delete_pred: Option<DeletePredicate>,
         parquet_files: Vec<QuerierChunk>,
         span: Option<Span>,
@@ -104,13 +104,10 @@ impl Reconciler {
 
         let tombstone_exclusion = tombstone_exclude_list(ingester_partitions, &tombstones);
 
-        let querier_tombstones: Vec<_> =
-            tombstones.into_iter().map(QuerierTombstone::from).collect();
-
         // match chunks and tombstones
         let mut tombstones_by_shard: HashMap<ShardId, Vec<QuerierTombstone>> = HashMap::new();
 
-        for tombstone in querier_tombstones {
+        for tombstone in tombstones {
             tombstones_by_shard
                 .entry(tombstone.shard_id())
                 .or_default()
@@ -131,72 +128,86 @@ impl Reconciler {
         let mut chunks: Vec<Box<dyn UpdatableQuerierChunk>> =
             Vec::with_capacity(parquet_files.len() + ingester_partitions.len());
 
-        let retention_expr_len = usize::from(retention_delete_pred.is_some());
-        for chunk in parquet_files.into_iter() {
-            let tombstones = tombstones_by_shard.get(&chunk.meta().shard_id());
-
-            let tombstones_len = if let Some(tombstones) = tombstones {
-                tombstones.len()
-            } else {
-                0
-            };
-            let mut delete_predicates = Vec::with_capacity(tombstones_len + retention_expr_len);
-
-            if let Some(tombstones) = tombstones {
-                for tombstone in tombstones {
-                    // check conditions that don't need catalog access first to avoid unnecessary
-                    // catalog load
-
-                    // Check if tombstone should be excluded based on the ingester response
-                    if tombstone_exclusion
-                        .contains(&(chunk.meta().partition_id(), tombstone.tombstone_id()))
-                    {
-                        continue;
-                    }
+        // Select the tombstones that may apply to each chunk, checking the conditions that don't
+        // need catalog access first to avoid unnecessary catalog load.
+        let candidates: Vec<Vec<&QuerierTombstone>> = parquet_files
+            .iter()
+            .map(|chunk| {
+                tombstones_by_shard
+                    .get(&chunk.meta().shard_id())
+                    .into_iter()
+                    .flatten()
+                    .filter(|tombstone| {
+                        // Check if tombstone should be excluded based on the ingester response
+                        if tombstone_exclusion
+                            .contains(&(chunk.meta().partition_id(), tombstone.tombstone_id()))
+                        {
+                            return false;
+                        }
+
+                        // Check if tombstone even applies to the sequence number range within the
+                        // parquet file. There
+                        // are the following cases here:
+                        //
+                        // 1. Tombstone comes before chunk min sequence number:
+                        //    There is no way the tombstone can affect the chunk.
+                        // 2. Tombstone comes after chunk max sequence number:
+                        //    Tombstone affects whole chunk (it might be marked as processed
+                        //    though, we'll check that further down).
+                        // 3. Tombstone is in the min-max sequence number range of the chunk:
+                        //    Technically the querier has NO way to determine the rows that are
+                        //    affected by the tombstone since we have no row-level sequence
+                        //    numbers. Such a file can be created by two sources -- the ingester
+                        //    and the compactor. The ingester must have materialized the tombstone
+                        //    while creating the parquet file, so the querier can skip it. The
+                        //    compactor also materialized the tombstones, so we can skip it as
+                        //    well. In the compactor case the tombstone will even be marked as
+                        //    processed.
+                        //
+                        // So the querier only needs to consider the tombstone in case 2.
+                        //
+                        // TODO: also consider time ranges
+                        // (https://github.com/influxdata/influxdb_iox/issues/4086)
+                        tombstone.sequence_number() > chunk.meta().max_sequence_number()
+                    })
+                    .collect()
+            })
+            .collect();
 
-                    // Check if tombstone even applies to the sequence number range within the
-                    // parquet file. There
-                    // are the following cases here:
-                    //
-                    // 1. Tombstone comes before chunk min sequence number:
-                    //    There is no way the tombstone can affect the chunk.
-                    // 2. Tombstone comes after chunk max sequence number:
-                    //    Tombstone affects whole chunk (it might be marked as processed though,
-                    //    we'll check that further down).
-                    // 3. Tombstone is in the min-max sequence number range of the chunk:
-                    //    Technically the querier has NO way to determine the rows that are
-                    //    affected by the tombstone since we have no row-level sequence numbers.
-                    //    Such a file can be created by two sources -- the ingester and the
-                    //    compactor. The ingester must have materialized the tombstone while
-                    //    creating the parquet file, so the querier can skip it. The compactor also
-                    //    materialized the tombstones, so we can skip it as well. In the compactor
-                    //    case the tombstone will even be marked as processed.
-                    //
-                    // So the querier only needs to consider the tombstone in case 2.
-                    if tombstone.sequence_number() <= chunk.meta().max_sequence_number() {
-                        continue;
-                    }
+        // check which of the remaining tombstones are marked as processed, resolving all
+        // (chunk, tombstone) pairs at once
+        let pairs: Vec<_> = parquet_files
+            .iter()
+            .zip(&candidates)
+            .flat_map(|(chunk, tombstones)| {
+                tombstones
+                    .iter()
+                    .map(|tombstone| (chunk.meta().parquet_file_id(), tombstone.tombstone_id()))
+            })
+            .collect();
+        let processed = if pairs.is_empty() {
+            HashSet::new()
+        } else {
+            self.chunk_adapter
+                .catalog_cache()
+                .processed_tombstones()
+                .processed(
+                    &pairs,
+                    span_recorder.child_span("cache GET processed_tombstones"),
+                )
+                .await
+        };
 
-                    // TODO: also consider time ranges
-                    // (https://github.com/influxdata/influxdb_iox/issues/4086)
-
-                    // check if tombstone is marked as processed
-                    if self
-                        .chunk_adapter
-                        .catalog_cache()
-                        .processed_tombstones()
-                        .exists(
-                            chunk.meta().parquet_file_id(),
-                            tombstone.tombstone_id(),
-                            span_recorder.child_span("cache GET exists processed_tombstone"),
-                        )
-                        .await
-                    {
-                        continue;
-                    }
+        let retention_expr_len = usize::from(retention_delete_pred.is_some());
+        for (chunk, tombstones) in parquet_files.into_iter().zip(candidates) {
+            let mut delete_predicates = Vec::with_capacity(tombstones.len() + retention_expr_len);
 
-                    delete_predicates.push(Arc::clone(tombstone.delete_predicate()));
+            for tombstone in tombstones {
+                if processed.contains(&(chunk.meta().parquet_file_id(), tombstone.tombstone_id())) {
+                    continue;
                 }
+
+                delete_predicates.push(Arc::clone(tombstone.delete_predicate()));
             }
 
             if let Some(retention_delete_pred) = retention_delete_pred.clone() {
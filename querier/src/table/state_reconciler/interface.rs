@@ -1,6 +1,6 @@
 //! Interface for reconciling Ingester and catalog state
 
-use crate::{chunk::QuerierChunk, ingester::IngesterPartition};
+use crate::{chunk::QuerierChunk, ingester::IngesterPartition, tombstone::QuerierTombstone};
 use data_types::{
     CompactionLevel, ParquetFile, PartitionId, SequenceNumber, ShardId, Tombstone, TombstoneId,
 };
@@ -115,6 +115,20 @@ impl TombstoneInfo for Tombstone {
     }
 }
 
+impl TombstoneInfo for QuerierTombstone {
+    fn id(&self) -> TombstoneId {
+        self.tombstone_id()
+    }
+
+    fn shard_id(&self) -> ShardId {
+        self.shard_id()
+    }
+
+    fn sequence_number(&self) -> SequenceNumber {
+        self.sequence_number()
+    }
+}
+
 impl TombstoneInfo for Arc<Tombstone> {
     fn id(&self) -> TombstoneId {
         self.id
@@ -38,6 +38,11 @@ impl QuerierTombstone {
     pub fn tombstone_id(&self) -> TombstoneId {
         self.tombstone_id
     }
+
+    /// Memory size in bytes, including the parsed delete predicate.
+    pub fn size(&self) -> usize {
+        std::mem::size_of_val(self) + self.delete_predicate.size()
+    }
 }
 
 impl From<&Tombstone> for QuerierTombstone {
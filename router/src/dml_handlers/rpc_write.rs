@@ -15,6 +15,7 @@ use sharder::RoundRobin;
 use std::{fmt::Debug, time::Duration};
 use thiserror::Error;
 use trace::ctx::SpanContext;
+use uuid::Uuid;
 
 /// Create a client to the ingester's write service.
 pub async fn write_service_client(
@@ -117,13 +118,23 @@ where
             payload: Some(encode_write(namespace_id.get(), &op)),
         };
 
+        // All attempts to write this op share an idempotency key, allowing the
+        // ingester to drop a retry of a write it applied but failed to
+        // acknowledge.
+        let idempotency_key = Uuid::new_v4();
+
         // Perform the gRPC write to an ingester.
         //
         // This includes a dirt simple retry mechanism that WILL need improving
         // (#6173).
         tokio::time::timeout(RPC_TIMEOUT, async {
             loop {
-                match self.endpoints.next().write(req.clone()).await {
+                match self
+                    .endpoints
+                    .next()
+                    .write(req.clone(), idempotency_key)
+                    .await
+                {
                     Ok(()) => break,
                     Err(e) => warn!(error=%e, "failed ingester rpc write"),
                 };
@@ -271,6 +282,9 @@ mod tests {
             .await;
         assert_matches!(got, Ok(_));
 
+        // Both attempts used the same idempotency key.
+        assert_eq!(client1.idempotency_keys(), client2.idempotency_keys());
+
         // Ensure client 2 observed a write.
         let call = {
             let mut calls = client2.calls();
@@ -2,21 +2,38 @@ use async_trait::async_trait;
 use generated_types::influxdata::iox::ingester::v1::{
     write_service_client::WriteServiceClient, WriteRequest,
 };
+use uuid::Uuid;
 
 use super::RpcWriteError;
 
+/// The gRPC metadata key carrying the idempotency key of a write, which the
+/// ingester uses to drop retries of a write it has already applied.
+pub(super) const IDEMPOTENCY_KEY_HEADER: &str = "iox-idempotency-key";
+
 /// An abstract RPC client that pushes `op` to an opaque receiver.
 #[async_trait]
 pub(super) trait WriteClient: Send + Sync + std::fmt::Debug {
     /// Write `op` and wait for a response.
-    async fn write(&self, op: WriteRequest) -> Result<(), RpcWriteError>;
+    ///
+    /// All attempts to write the same `op` MUST use the same
+    /// `idempotency_key`.
+    async fn write(&self, op: WriteRequest, idempotency_key: Uuid) -> Result<(), RpcWriteError>;
 }
 
 /// An implementation of [`WriteClient`] for the tonic gRPC client.
 #[async_trait]
 impl WriteClient for WriteServiceClient<client_util::connection::GrpcConnection> {
-    async fn write(&self, op: WriteRequest) -> Result<(), RpcWriteError> {
-        WriteServiceClient::write(&mut self.clone(), op).await?;
+    async fn write(&self, op: WriteRequest, idempotency_key: Uuid) -> Result<(), RpcWriteError> {
+        let mut req = tonic::Request::new(op);
+        req.metadata_mut().insert(
+            IDEMPOTENCY_KEY_HEADER,
+            idempotency_key
+                .to_string()
+                .parse()
+                .expect("uuid is valid metadata value"),
+        );
+
+        WriteServiceClient::write(&mut self.clone(), req).await?;
         Ok(())
     }
 }
@@ -32,6 +49,7 @@ pub(crate) mod mock {
     #[derive(Debug, Default)]
     struct State {
         calls: Vec<WriteRequest>,
+        idempotency_keys: Vec<Uuid>,
         ret: VecDeque<Result<(), RpcWriteError>>,
     }
 
@@ -46,6 +64,10 @@ pub(crate) mod mock {
             self.state.lock().calls.clone()
         }
 
+        pub(crate) fn idempotency_keys(&self) -> Vec<Uuid> {
+            self.state.lock().idempotency_keys.clone()
+        }
+
         pub(crate) fn with_ret(self, ret: impl Into<VecDeque<Result<(), RpcWriteError>>>) -> Self {
             self.state.lock().ret = ret.into();
             self
@@ -54,9 +76,14 @@ pub(crate) mod mock {
 
     #[async_trait]
     impl WriteClient for Arc<MockWriteClient> {
-        async fn write(&self, op: WriteRequest) -> Result<(), RpcWriteError> {
+        async fn write(
+            &self,
+            op: WriteRequest,
+            idempotency_key: Uuid,
+        ) -> Result<(), RpcWriteError> {
             let mut guard = self.state.lock();
             guard.calls.push(op);
+            guard.idempotency_keys.push(idempotency_key);
             guard.ret.pop_front().unwrap_or(Ok(()))
         }
     }
@@ -0,0 +1,247 @@
+//! Admission control of the queries of each namespace.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use metric::U64Counter;
+use parking_lot::Mutex;
+use trace::span::Span;
+use tracker::{
+    AsyncSemaphoreMetrics, InstrumentedAsyncOwnedSemaphorePermit, InstrumentedAsyncSemaphore,
+};
+
+/// A query was rejected because too many queries of its namespace are
+/// already executing or waiting to execute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryShedError {
+    namespace: String,
+    max_concurrent_queries: usize,
+    max_queued_queries: usize,
+}
+
+impl fmt::Display for QueryShedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "too many queries for namespace {} (limit: {} executing, {} waiting), try again later",
+            self.namespace, self.max_concurrent_queries, self.max_queued_queries
+        )
+    }
+}
+
+impl std::error::Error for QueryShedError {}
+
+impl From<QueryShedError> for tonic::Status {
+    fn from(e: QueryShedError) -> Self {
+        Self::resource_exhausted(e.to_string())
+    }
+}
+
+/// A permit to execute a query, released when dropped.
+#[derive(Debug)]
+pub struct QueryPermit {
+    _namespace: Option<NamespaceQueryPermit>,
+    _global: InstrumentedAsyncOwnedSemaphorePermit,
+}
+
+impl QueryPermit {
+    /// Combine the permit of the query's namespace (if the namespace
+    /// concurrency is limited) and the process-wide permit.
+    pub fn new(
+        namespace: Option<NamespaceQueryPermit>,
+        global: InstrumentedAsyncOwnedSemaphorePermit,
+    ) -> Self {
+        Self {
+            _namespace: namespace,
+            _global: global,
+        }
+    }
+}
+
+/// The admission state of a single namespace.
+#[derive(Debug)]
+struct NamespaceState {
+    semaphore: Arc<InstrumentedAsyncSemaphore>,
+
+    /// The number of queries holding or waiting for a permit of
+    /// [`Self::semaphore`].
+    admitted: AtomicUsize,
+}
+
+/// A permit of [`NamespaceQueryLimiter`], released when dropped.
+#[derive(Debug)]
+pub struct NamespaceQueryPermit {
+    _permit: InstrumentedAsyncOwnedSemaphorePermit,
+    _admitted: AdmittedGuard,
+}
+
+/// Decrements [`NamespaceState::admitted`] when dropped, including when the
+/// query is cancelled while waiting for its permit.
+#[derive(Debug)]
+struct AdmittedGuard(Arc<NamespaceState>);
+
+impl Drop for AdmittedGuard {
+    fn drop(&mut self) {
+        self.0.admitted.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Limits the number of concurrently executing queries of each namespace, so
+/// that the queries of one namespace can not starve those of all other
+/// namespaces of the process-wide query concurrency.
+///
+/// Queries in excess of the concurrency limit of their namespace wait for a
+/// permit in FIFO order, up to `max_queued_queries` of them. Further queries
+/// of the namespace are shed, failing immediately with a [`QueryShedError`]
+/// instead of piling up.
+///
+/// The state of a namespace is retained once it has been queried.
+#[derive(Debug)]
+pub struct NamespaceQueryLimiter {
+    max_concurrent_queries: usize,
+    max_queued_queries: usize,
+    namespaces: Mutex<HashMap<Arc<str>, Arc<NamespaceState>>>,
+    semaphore_metrics: Arc<AsyncSemaphoreMetrics>,
+    shed: U64Counter,
+}
+
+impl NamespaceQueryLimiter {
+    /// Allow up to `max_concurrent_queries` queries of each namespace to
+    /// execute at once, with up to `max_queued_queries` more waiting.
+    pub fn new(
+        max_concurrent_queries: usize,
+        max_queued_queries: usize,
+        metric_registry: &metric::Registry,
+    ) -> Self {
+        let semaphore_metrics = Arc::new(AsyncSemaphoreMetrics::new(
+            metric_registry,
+            &[("semaphore", "namespace_query_execution")],
+        ));
+        let shed = metric_registry
+            .register_metric::<U64Counter>(
+                "query_namespace_shed",
+                "number of queries rejected because too many queries of their namespace were \
+                executing and waiting",
+            )
+            .recorder(&[]);
+
+        Self {
+            max_concurrent_queries,
+            max_queued_queries,
+            namespaces: Default::default(),
+            semaphore_metrics,
+            shed,
+        }
+    }
+
+    /// Acquire a permit to execute a query of `namespace`, waiting for one
+    /// of its concurrently executing queries to complete if necessary.
+    pub async fn acquire(
+        &self,
+        namespace: &str,
+        span: Option<Span>,
+    ) -> Result<NamespaceQueryPermit, QueryShedError> {
+        let state = Arc::clone(
+            self.namespaces
+                .lock()
+                .entry(Arc::from(namespace))
+                .or_insert_with(|| {
+                    Arc::new(NamespaceState {
+                        semaphore: Arc::new(
+                            self.semaphore_metrics
+                                .new_semaphore(self.max_concurrent_queries),
+                        ),
+                        admitted: AtomicUsize::new(0),
+                    })
+                }),
+        );
+
+        let max_admitted = self
+            .max_concurrent_queries
+            .saturating_add(self.max_queued_queries);
+        if state
+            .admitted
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < max_admitted).then_some(n + 1)
+            })
+            .is_err()
+        {
+            self.shed.inc(1);
+            return Err(QueryShedError {
+                namespace: namespace.to_string(),
+                max_concurrent_queries: self.max_concurrent_queries,
+                max_queued_queries: self.max_queued_queries,
+            });
+        }
+        let admitted = AdmittedGuard(Arc::clone(&state));
+
+        let permit = Arc::clone(&state.semaphore)
+            .acquire_owned(span)
+            .await
+            .expect("Semaphore should not be closed by anyone");
+
+        Ok(NamespaceQueryPermit {
+            _permit: permit,
+            _admitted: admitted,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::FutureExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_limit_and_shed() {
+        let metrics = metric::Registry::default();
+        let limiter = NamespaceQueryLimiter::new(1, 1, &metrics);
+
+        let p1 = limiter.acquire("ns1", None).await.unwrap();
+
+        // The second query of the namespace waits for the first...
+        let mut waiting = Box::pin(limiter.acquire("ns1", None));
+        assert!((&mut waiting).now_or_never().is_none());
+
+        // ...and the third is shed.
+        let err = limiter.acquire("ns1", None).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "too many queries for namespace ns1 (limit: 1 executing, 1 waiting), try again later"
+        );
+        assert_eq!(limiter.shed.fetch(), 1);
+
+        // Other namespaces are unaffected.
+        let _p2 = limiter.acquire("ns2", None).await.unwrap();
+
+        // Completing the first query admits the waiting one.
+        drop(p1);
+        let p3 = tokio::time::timeout(Duration::from_secs(5), waiting)
+            .await
+            .expect("waiting query should be admitted")
+            .unwrap();
+
+        // A cancelled waiting query releases its queue slot.
+        let mut waiting = Box::pin(limiter.acquire("ns1", None));
+        assert!((&mut waiting).now_or_never().is_none());
+        drop(waiting);
+        let mut waiting = Box::pin(limiter.acquire("ns1", None));
+        assert!((&mut waiting).now_or_never().is_none());
+
+        drop(p3);
+        tokio::time::timeout(Duration::from_secs(5), waiting)
+            .await
+            .expect("waiting query should be admitted")
+            .unwrap();
+        assert_eq!(limiter.shed.fetch(), 1);
+    }
+}
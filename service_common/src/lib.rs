@@ -1,7 +1,9 @@
 //! Common methods for RPC service implementations
 
+pub mod admission;
 mod error;
 pub mod planner;
+pub mod result_cache;
 pub mod test_util;
 
 use std::sync::Arc;
@@ -9,7 +11,11 @@ use std::sync::Arc;
 use async_trait::async_trait;
 use iox_query::{exec::ExecutionContextProvider, QueryNamespace};
 use trace::span::Span;
-use tracker::InstrumentedAsyncOwnedSemaphorePermit;
+
+use crate::{
+    admission::{QueryPermit, QueryShedError},
+    result_cache::QueryResultCache,
+};
 
 /// Trait that allows the query engine (which includes flight and storage/InfluxRPC) to access a
 /// virtual set of namespaces.
@@ -23,8 +29,19 @@ pub trait QueryNamespaceProvider: std::fmt::Debug + Send + Sync + 'static {
     /// Get namespace if it exists.
     async fn db(&self, name: &str, span: Option<Span>) -> Option<Arc<Self::Db>>;
 
-    /// Acquire concurrency-limiting sempahore
-    async fn acquire_semaphore(&self, span: Option<Span>) -> InstrumentedAsyncOwnedSemaphorePermit;
+    /// Acquire concurrency-limiting sempahore for a query of the namespace `name`.
+    ///
+    /// Returns an error if the query is shed instead of waiting for a permit.
+    async fn acquire_semaphore(
+        &self,
+        name: &str,
+        span: Option<Span>,
+    ) -> Result<QueryPermit, QueryShedError>;
+
+    /// Cache of query results, if enabled.
+    fn result_cache(&self) -> Option<Arc<dyn QueryResultCache>> {
+        None
+    }
 }
 
 pub use error::datafusion_error_to_tonic_code;
@@ -0,0 +1,237 @@
+//! Caching the results of queries.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt::Debug,
+    hash::{Hash, Hasher},
+    mem::size_of_val,
+    sync::Arc,
+};
+
+use datafusion::{
+    arrow::record_batch::RecordBatch,
+    physical_plan::{displayable, empty::EmptyExec, file_format::ParquetExec, ExecutionPlan},
+};
+
+/// Identifies the result of a query against a specific set of chunks.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct QueryResultKey {
+    namespace: String,
+
+    /// The query text with normalized whitespace.
+    query: String,
+
+    /// Fingerprint of the physical plan of the query, including the parquet
+    /// files it reads.
+    fingerprint: u64,
+}
+
+impl QueryResultKey {
+    /// Identify the result of `query` against `namespace`, planned as
+    /// `plan`.
+    ///
+    /// Returns [`None`] if the result can not be cached because `plan` reads
+    /// anything but parquet files, e.g. unpersisted data of the ingesters or
+    /// system tables, which can change without changing the plan.
+    pub fn try_new(namespace: &str, query: &str, plan: &dyn ExecutionPlan) -> Option<Self> {
+        let mut hasher = DefaultHasher::new();
+        hash_leaves(plan, &mut hasher)?;
+
+        // The rest of the plan captures everything else the result depends
+        // on, e.g. delete predicates and folded `now()` calls.
+        displayable(plan).indent().to_string().hash(&mut hasher);
+
+        Some(Self {
+            namespace: namespace.to_string(),
+            query: query.split_whitespace().collect::<Vec<_>>().join(" "),
+            fingerprint: hasher.finish(),
+        })
+    }
+
+    /// Memory usage of the key in bytes.
+    pub fn size(&self) -> usize {
+        size_of_val(self) + self.namespace.capacity() + self.query.capacity()
+    }
+}
+
+/// Hash the parquet files read by `plan`, or return [`None`] if it reads any
+/// other source.
+fn hash_leaves(plan: &dyn ExecutionPlan, hasher: &mut impl Hasher) -> Option<()> {
+    let children = plan.children();
+    if children.is_empty() {
+        if let Some(parquet_exec) = plan.as_any().downcast_ref::<ParquetExec>() {
+            for file in parquet_exec.base_config().file_groups.iter().flatten() {
+                file.object_meta.location.as_ref().hash(hasher);
+                file.object_meta.size.hash(hasher);
+            }
+        } else if plan.as_any().downcast_ref::<EmptyExec>().is_none() {
+            return None;
+        }
+    }
+
+    for child in children {
+        hash_leaves(child.as_ref(), hasher)?;
+    }
+    Some(())
+}
+
+/// The result of a query.
+#[derive(Debug)]
+pub struct QueryResult {
+    batches: Vec<RecordBatch>,
+    size: usize,
+}
+
+impl QueryResult {
+    /// The record batches of the result.
+    pub fn batches(&self) -> &[RecordBatch] {
+        &self.batches
+    }
+
+    /// Memory usage of the result in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+/// A cache of query results.
+pub trait QueryResultCache: Debug + Send + Sync + 'static {
+    /// Get the cached result identified by `key`, if any.
+    fn get(&self, key: &QueryResultKey) -> Option<Arc<QueryResult>>;
+
+    /// Cache `result` as identified by `key`.
+    fn set(&self, key: QueryResultKey, result: Arc<QueryResult>);
+
+    /// The size in bytes above which results are not cached.
+    fn max_result_bytes(&self) -> usize;
+}
+
+/// Collects the record batches of a query result as they are streamed to
+/// the client, to cache the result once it is complete.
+#[derive(Debug)]
+pub struct QueryResultCollector {
+    cache: Arc<dyn QueryResultCache>,
+    key: QueryResultKey,
+    batches: Vec<RecordBatch>,
+    size: usize,
+}
+
+impl QueryResultCollector {
+    /// Collect the result identified by `key` into `cache`.
+    pub fn new(cache: Arc<dyn QueryResultCache>, key: QueryResultKey) -> Self {
+        Self {
+            cache,
+            key,
+            batches: vec![],
+            size: 0,
+        }
+    }
+
+    /// Add the next record batch of the result.
+    ///
+    /// Returns `false` if the result is too large to be cached, in which
+    /// case the collector should be dropped.
+    pub fn push(&mut self, batch: &RecordBatch) -> bool {
+        self.size += batch
+            .columns()
+            .iter()
+            .map(|array| array.get_array_memory_size())
+            .sum::<usize>();
+        if self.size > self.cache.max_result_bytes() {
+            return false;
+        }
+
+        self.batches.push(batch.clone());
+        true
+    }
+
+    /// Cache the complete result.
+    pub fn finish(self) {
+        let Self {
+            cache,
+            key,
+            batches,
+            size,
+        } = self;
+        cache.set(key, Arc::new(QueryResult { batches, size }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::{
+        arrow::{
+            array::Int64Array,
+            datatypes::{DataType, Field, Schema},
+        },
+        physical_plan::memory::MemoryExec,
+    };
+    use parking_lot::Mutex;
+
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct TestCache {
+        results: Mutex<Vec<(QueryResultKey, Arc<QueryResult>)>>,
+    }
+
+    impl QueryResultCache for TestCache {
+        fn get(&self, key: &QueryResultKey) -> Option<Arc<QueryResult>> {
+            self.results
+                .lock()
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| Arc::clone(v))
+        }
+
+        fn set(&self, key: QueryResultKey, result: Arc<QueryResult>) {
+            self.results.lock().push((key, result));
+        }
+
+        fn max_result_bytes(&self) -> usize {
+            1_000
+        }
+    }
+
+    fn schema() -> Arc<Schema> {
+        Arc::new(Schema::new(vec![Field::new("a", DataType::Int64, false)]))
+    }
+
+    fn batch(n: i64) -> RecordBatch {
+        RecordBatch::try_new(schema(), vec![Arc::new(Int64Array::from_iter_values(0..n))]).unwrap()
+    }
+
+    #[test]
+    fn test_key() {
+        let plan = EmptyExec::new(false, schema());
+        let a = QueryResultKey::try_new("ns", "select *\n  from t", &plan).unwrap();
+        let b = QueryResultKey::try_new("ns", "select * from t", &plan).unwrap();
+        assert_eq!(a, b);
+
+        let c = QueryResultKey::try_new("ns2", "select * from t", &plan).unwrap();
+        assert_ne!(a, c);
+
+        // In-memory data is never cached.
+        let plan = MemoryExec::try_new(&[vec![batch(1)]], schema(), None).unwrap();
+        assert!(QueryResultKey::try_new("ns", "select * from t", &plan).is_none());
+    }
+
+    #[test]
+    fn test_collector() {
+        let cache = Arc::new(TestCache::default());
+        let plan = EmptyExec::new(false, schema());
+        let key = QueryResultKey::try_new("ns", "select * from t", &plan).unwrap();
+
+        let mut collector = QueryResultCollector::new(Arc::clone(&cache) as _, key.clone());
+        assert!(collector.push(&batch(1)));
+        assert!(collector.push(&batch(2)));
+        collector.finish();
+
+        let result = cache.get(&key).unwrap();
+        assert_eq!(result.batches(), &[batch(1), batch(2)]);
+
+        // Results exceeding the size limit are not collected.
+        let mut collector = QueryResultCollector::new(Arc::clone(&cache) as _, key);
+        assert!(!collector.push(&batch(1_000)));
+    }
+}
@@ -4,11 +4,12 @@ use async_trait::async_trait;
 use iox_query::{exec::Executor, test::TestDatabase};
 use parking_lot::Mutex;
 use trace::span::Span;
-use tracker::{
-    AsyncSemaphoreMetrics, InstrumentedAsyncOwnedSemaphorePermit, InstrumentedAsyncSemaphore,
-};
+use tracker::{AsyncSemaphoreMetrics, InstrumentedAsyncSemaphore};
 
-use crate::QueryNamespaceProvider;
+use crate::{
+    admission::{QueryPermit, QueryShedError},
+    QueryNamespaceProvider,
+};
 
 #[derive(Debug)]
 pub struct TestDatabaseStore {
@@ -67,10 +68,15 @@ impl QueryNamespaceProvider for TestDatabaseStore {
         databases.get(name).cloned()
     }
 
-    async fn acquire_semaphore(&self, span: Option<Span>) -> InstrumentedAsyncOwnedSemaphorePermit {
-        Arc::clone(&self.query_semaphore)
+    async fn acquire_semaphore(
+        &self,
+        _name: &str,
+        span: Option<Span>,
+    ) -> Result<QueryPermit, QueryShedError> {
+        let permit = Arc::clone(&self.query_semaphore)
             .acquire_owned(span)
             .await
-            .unwrap()
+            .unwrap();
+        Ok(QueryPermit::new(None, permit))
     }
 }
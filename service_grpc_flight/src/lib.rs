@@ -23,7 +23,13 @@ use observability_deps::tracing::{debug, info, warn};
 use pin_project::{pin_project, pinned_drop};
 use prost::Message;
 use serde::Deserialize;
-use service_common::{datafusion_error_to_tonic_code, planner::Planner, QueryNamespaceProvider};
+use service_common::{
+    admission::QueryPermit,
+    datafusion_error_to_tonic_code,
+    planner::Planner,
+    result_cache::{QueryResultCache, QueryResultCollector, QueryResultKey},
+    QueryNamespaceProvider,
+};
 use snafu::{ResultExt, Snafu};
 use std::fmt::{Display, Formatter};
 use std::{fmt, fmt::Debug, pin::Pin, sync::Arc, task::Poll, time::Instant};
@@ -31,7 +37,6 @@ use tokio::task::JoinHandle;
 use tonic::{Request, Response, Streaming};
 use trace::{ctx::SpanContext, span::SpanExt};
 use trace_http::ctx::{RequestLogContext, RequestLogContextExt};
-use tracker::InstrumentedAsyncOwnedSemaphorePermit;
 
 #[allow(clippy::enum_variant_names)]
 #[derive(Debug, Snafu)]
@@ -208,7 +213,7 @@ where
     async fn run_query(
         &self,
         span_ctx: Option<SpanContext>,
-        permit: InstrumentedAsyncOwnedSemaphorePermit,
+        permit: QueryPermit,
         query: Query,
         namespace: String,
     ) -> Result<Response<TonicStream<FlightData>>, tonic::Status> {
@@ -219,27 +224,39 @@ where
             .ok_or_else(|| tonic::Status::not_found(format!("Unknown namespace: {namespace}")))?;
 
         let ctx = db.new_query_context(span_ctx);
-        let (query_completed_token, physical_plan) = match query {
+        let (query_completed_token, physical_plan, query_text) = match query {
             Query::Sql(sql_query) => {
                 let token = db.record_query(&ctx, "sql", Box::new(sql_query.clone()));
                 let plan = Planner::new(&ctx)
-                    .sql(sql_query)
+                    .sql(sql_query.clone())
                     .await
                     .context(PlanningSnafu)?;
-                (token, plan)
+                (token, plan, sql_query)
             }
             Query::InfluxQL(sql_query) => {
                 let token = db.record_query(&ctx, "influxql", Box::new(sql_query.clone()));
                 let plan = Planner::new(&ctx)
-                    .influxql(db, sql_query)
+                    .influxql(db, sql_query.clone())
                     .await
                     .context(PlanningSnafu)?;
-                (token, plan)
+                (token, plan, sql_query)
             }
         };
 
-        let output =
-            GetStream::new(ctx, physical_plan, namespace, query_completed_token, permit).await?;
+        let result_cache = self.server.result_cache().and_then(|cache| {
+            QueryResultKey::try_new(&namespace, &query_text, physical_plan.as_ref())
+                .map(|key| (cache, key))
+        });
+
+        let output = GetStream::new(
+            ctx,
+            physical_plan,
+            namespace,
+            query_completed_token,
+            permit,
+            result_cache,
+        )
+        .await?;
 
         Ok(Response::new(Box::pin(output) as TonicStream<FlightData>))
     }
@@ -290,8 +307,11 @@ where
 
         let permit = self
             .server
-            .acquire_semaphore(span_ctx.child_span("query rate limit semaphore"))
-            .await;
+            .acquire_semaphore(
+                &namespace_name,
+                span_ctx.child_span("query rate limit semaphore"),
+            )
+            .await?;
 
         // Log after we acquire the permit and are about to start execution
         let start = Instant::now();
@@ -373,7 +393,7 @@ struct GetStream {
     join_handle: JoinHandle<()>,
     done: bool,
     #[allow(dead_code)]
-    permit: InstrumentedAsyncOwnedSemaphorePermit,
+    permit: QueryPermit,
 }
 
 impl GetStream {
@@ -382,7 +402,8 @@ impl GetStream {
         physical_plan: Arc<dyn ExecutionPlan>,
         namespace_name: String,
         mut query_completed_token: QueryCompletedToken,
-        permit: InstrumentedAsyncOwnedSemaphorePermit,
+        permit: QueryPermit,
+        result_cache: Option<(Arc<dyn QueryResultCache>, QueryResultKey)>,
     ) -> Result<Self, tonic::Status> {
         // setup channel
         let (mut tx, rx) = futures::channel::mpsc::channel::<Result<FlightData, tonic::Status>>(1);
@@ -400,12 +421,27 @@ impl GetStream {
         prost::Message::encode(&app_metadata, &mut bytes).context(SerializationSnafu)?;
         schema_flight_data.app_metadata = bytes.to_vec();
 
-        let mut stream_record_batches = ctx
-            .execute_stream(Arc::clone(&physical_plan))
-            .await
-            .context(QuerySnafu {
-                namespace_name: &namespace_name,
-            })?;
+        let cached = result_cache
+            .as_ref()
+            .and_then(|(cache, key)| cache.get(key));
+        let (mut stream_record_batches, mut collector) = match cached {
+            Some(result) => {
+                debug!(%namespace_name, "Serving query result from cache");
+                let batches: Vec<_> = result.batches().iter().cloned().map(Ok).collect();
+                (futures::stream::iter(batches).boxed(), None)
+            }
+            None => {
+                let stream = ctx
+                    .execute_stream(Arc::clone(&physical_plan))
+                    .await
+                    .context(QuerySnafu {
+                        namespace_name: &namespace_name,
+                    })?;
+                let collector =
+                    result_cache.map(|(cache, key)| QueryResultCollector::new(cache, key));
+                (stream.boxed(), collector)
+            }
+        };
 
         let join_handle = tokio::spawn(async move {
             if tx.send(Ok(schema_flight_data)).await.is_err() {
@@ -416,6 +452,11 @@ impl GetStream {
             while let Some(batch_or_err) = stream_record_batches.next().await {
                 match batch_or_err {
                     Ok(batch) => {
+                        if collector.as_mut().map_or(false, |c| !c.push(&batch)) {
+                            // too large to cache
+                            collector = None;
+                        }
+
                         match prepare_batch_for_flight(&batch, Arc::clone(&schema)) {
                             Ok(batch) => {
                                 for batch in split_batch_for_grpc_response(batch) {
@@ -465,6 +506,9 @@ impl GetStream {
             }
 
             // if we get here, all is good
+            if let Some(collector) = collector {
+                collector.finish();
+            }
             query_completed_token.set_success()
         });
 
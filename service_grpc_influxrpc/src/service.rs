@@ -40,7 +40,10 @@ use iox_query::{
 };
 use observability_deps::tracing::{error, info, trace};
 use prost::{bytes::BytesMut, Message};
-use service_common::{datafusion_error_to_tonic_code, planner::Planner, QueryNamespaceProvider};
+use service_common::{
+    admission::QueryPermit, datafusion_error_to_tonic_code, planner::Planner,
+    QueryNamespaceProvider,
+};
 use snafu::{OptionExt, ResultExt, Snafu};
 use std::{
     collections::{BTreeSet, HashMap},
@@ -51,7 +54,6 @@ use tokio_stream::wrappers::ReceiverStream;
 use tonic::{metadata::MetadataMap, Response, Status};
 use trace::{ctx::SpanContext, span::SpanExt};
 use trace_http::ctx::{RequestLogContext, RequestLogContextExt};
-use tracker::InstrumentedAsyncOwnedSemaphorePermit;
 
 /// The size to which we limit our [`ReadResponse`] payloads.
 ///
@@ -351,11 +353,11 @@ where
         let span_ctx: Option<SpanContext> = req.extensions().get().cloned();
 
         let req = req.into_inner();
+        let db_name = get_namespace_name(&req)?;
         let permit = self
             .db_store
-            .acquire_semaphore(span_ctx.child_span("query rate limit semaphore"))
-            .await;
-        let db_name = get_namespace_name(&req)?;
+            .acquire_semaphore(&db_name, span_ctx.child_span("query rate limit semaphore"))
+            .await?;
         info!(
             %db_name,
             ?req.range,
@@ -394,12 +396,11 @@ where
         let external_span_ctx: Option<RequestLogContext> = req.extensions().get().cloned();
         let span_ctx: Option<SpanContext> = req.extensions().get().cloned();
         let req = req.into_inner();
+        let db_name = get_namespace_name(&req)?;
         let permit = self
             .db_store
-            .acquire_semaphore(span_ctx.child_span("query rate limit semaphore"))
-            .await;
-
-        let db_name = get_namespace_name(&req)?;
+            .acquire_semaphore(&db_name, span_ctx.child_span("query rate limit semaphore"))
+            .await?;
 
         info!(
             %db_name,
@@ -472,12 +473,11 @@ where
         let external_span_ctx: Option<RequestLogContext> = req.extensions().get().cloned();
         let span_ctx: Option<SpanContext> = req.extensions().get().cloned();
         let req = req.into_inner();
+        let db_name = get_namespace_name(&req)?;
         let permit = self
             .db_store
-            .acquire_semaphore(span_ctx.child_span("query rate limit semaphore"))
-            .await;
-
-        let db_name = get_namespace_name(&req)?;
+            .acquire_semaphore(&db_name, span_ctx.child_span("query rate limit semaphore"))
+            .await?;
         info!(
             %db_name,
             ?req.range,
@@ -555,12 +555,11 @@ where
         let span_ctx: Option<SpanContext> = req.extensions().get().cloned();
 
         let req = req.into_inner();
+        let db_name = get_namespace_name(&req)?;
         let permit = self
             .db_store
-            .acquire_semaphore(span_ctx.child_span("query rate limit semaphore"))
-            .await;
-
-        let db_name = get_namespace_name(&req)?;
+            .acquire_semaphore(&db_name, span_ctx.child_span("query rate limit semaphore"))
+            .await?;
         info!(
             %db_name,
             ?req.range,
@@ -620,12 +619,11 @@ where
         let span_ctx: Option<SpanContext> = req.extensions().get().cloned();
 
         let req = req.into_inner();
+        let db_name = get_namespace_name(&req)?;
         let permit = self
             .db_store
-            .acquire_semaphore(span_ctx.child_span("query rate limit semaphore"))
-            .await;
-
-        let db_name = get_namespace_name(&req)?;
+            .acquire_semaphore(&db_name, span_ctx.child_span("query rate limit semaphore"))
+            .await?;
         let tag_key = DecodedTagKey::try_from(req.tag_key.clone())
             .context(ConvertingTagKeyInTagValuesSnafu)?;
         info!(
@@ -722,12 +720,11 @@ where
         let span_ctx: Option<SpanContext> = req.extensions().get().cloned();
 
         let req = req.into_inner();
+        let db_name = get_namespace_name(&req)?;
         let permit = self
             .db_store
-            .acquire_semaphore(span_ctx.child_span("query rate limit semaphore"))
-            .await;
-
-        let db_name = get_namespace_name(&req)?;
+            .acquire_semaphore(&db_name, span_ctx.child_span("query rate limit semaphore"))
+            .await?;
         info!(
             %db_name,
             ?req.measurement_patterns,
@@ -832,12 +829,11 @@ where
         let span_ctx: Option<SpanContext> = req.extensions().get().cloned();
 
         let req = req.into_inner();
+        let db_name = get_namespace_name(&req)?;
         let permit = self
             .db_store
-            .acquire_semaphore(span_ctx.child_span("query rate limit semaphore"))
-            .await;
-
-        let db_name = get_namespace_name(&req)?;
+            .acquire_semaphore(&db_name, span_ctx.child_span("query rate limit semaphore"))
+            .await?;
         info!(
             %db_name,
             ?req.range,
@@ -888,12 +884,11 @@ where
         let span_ctx: Option<SpanContext> = req.extensions().get().cloned();
 
         let req = req.into_inner();
+        let db_name = get_namespace_name(&req)?;
         let permit = self
             .db_store
-            .acquire_semaphore(span_ctx.child_span("query rate limit semaphore"))
-            .await;
-
-        let db_name = get_namespace_name(&req)?;
+            .acquire_semaphore(&db_name, span_ctx.child_span("query rate limit semaphore"))
+            .await?;
         info!(
             %db_name,
             ?req.range,
@@ -955,12 +950,11 @@ where
         let span_ctx: Option<SpanContext> = req.extensions().get().cloned();
 
         let req = req.into_inner();
+        let db_name = get_namespace_name(&req)?;
         let permit = self
             .db_store
-            .acquire_semaphore(span_ctx.child_span("query rate limit semaphore"))
-            .await;
-
-        let db_name = get_namespace_name(&req)?;
+            .acquire_semaphore(&db_name, span_ctx.child_span("query rate limit semaphore"))
+            .await?;
         info!(
             %db_name,
             ?req.range,
@@ -1026,12 +1020,11 @@ where
         let span_ctx: Option<SpanContext> = req.extensions().get().cloned();
 
         let req = req.into_inner();
+        let db_name = get_namespace_name(&req)?;
         let permit = self
             .db_store
-            .acquire_semaphore(span_ctx.child_span("query rate limit semaphore"))
-            .await;
-
-        let db_name = get_namespace_name(&req)?;
+            .acquire_semaphore(&db_name, span_ctx.child_span("query rate limit semaphore"))
+            .await?;
         info!(
             %db_name,
             ?req.range,
@@ -1670,7 +1663,7 @@ impl<T, E: std::fmt::Debug> ErrorLogger for Result<T, E> {
 pub fn make_response<S, T, E>(
     stream: S,
     token: QueryCompletedToken,
-    permit: InstrumentedAsyncOwnedSemaphorePermit,
+    permit: QueryPermit,
 ) -> Result<Response<StreamWithPermit<QueryCompletedTokenStream<S, T, E>>>, Status>
 where
     S: Stream<Item = Result<T, E>> + Unpin,
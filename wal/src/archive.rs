@@ -0,0 +1,185 @@
+//! Archival of closed segment files to object storage.
+//!
+//! Segments uploaded by a [`SegmentArchiver`] are stored unmodified (still encrypted, if they
+//! were written encrypted) as `<prefix>/<segment id>.dat`, so restoring them into an empty
+//! directory produces a WAL directory that can be inspected with [`crate::inspect`] or replayed
+//! by opening it with [`Wal::new`](crate::Wal::new). This gives point-in-time recovery for as
+//! long as the archived segments are retained, independently of local disk space.
+
+use bytes::Bytes;
+use futures::TryStreamExt;
+use object_store::{path::Path as ObjectStorePath, ObjectStore};
+use snafu::prelude::*;
+use std::{path::Path, sync::Arc};
+
+use crate::{
+    build_segment_path, ArchiveDownloadSnafu, ArchiveListSnafu, ArchiveReadSegmentSnafu,
+    ArchiveRestoreSegmentSnafu, ArchiveUploadSnafu, ClosedSegment, Result, SegmentId,
+    SEGMENT_FILE_EXTENSION,
+};
+
+/// Uploads closed segment files to, and restores them from, a prefix of an [`ObjectStore`].
+#[derive(Debug, Clone)]
+pub struct SegmentArchiver {
+    object_store: Arc<dyn ObjectStore>,
+    prefix: ObjectStorePath,
+}
+
+impl SegmentArchiver {
+    /// Creates an archiver storing segments under `prefix` in `object_store`.
+    pub fn new(object_store: Arc<dyn ObjectStore>, prefix: ObjectStorePath) -> Self {
+        Self {
+            object_store,
+            prefix,
+        }
+    }
+
+    fn location(&self, id: SegmentId) -> ObjectStorePath {
+        self.prefix
+            .child(format!("{}.{}", id, SEGMENT_FILE_EXTENSION).as_str())
+    }
+
+    /// Uploads `segment`, replacing any previously archived copy of it.
+    pub async fn archive(&self, segment: &ClosedSegment) -> Result<()> {
+        let data = tokio::fs::read(&segment.path)
+            .await
+            .context(ArchiveReadSegmentSnafu {
+                path: &segment.path,
+            })?;
+
+        self.object_store
+            .put(&self.location(segment.id), Bytes::from(data))
+            .await
+            .context(ArchiveUploadSnafu { id: segment.id })
+    }
+
+    /// Lists the IDs of the archived segments, in order.
+    pub async fn list(&self) -> Result<Vec<SegmentId>> {
+        let objects: Vec<_> = self
+            .object_store
+            .list(Some(&self.prefix))
+            .await
+            .context(ArchiveListSnafu)?
+            .try_collect()
+            .await
+            .context(ArchiveListSnafu)?;
+
+        let mut ids: Vec<_> = objects
+            .iter()
+            .filter_map(|object| {
+                let filename = object.location.filename()?;
+                let id = filename.strip_suffix(&format!(".{}", SEGMENT_FILE_EXTENSION))?;
+                id.parse().ok().map(SegmentId::new)
+            })
+            .collect();
+        ids.sort_unstable();
+
+        Ok(ids)
+    }
+
+    /// Downloads the archived segment `id` into the directory `dir`, where it is named as it was
+    /// in the WAL directory it was archived from.
+    pub async fn restore(&self, id: SegmentId, dir: impl AsRef<Path>) -> Result<()> {
+        let data = self
+            .object_store
+            .get(&self.location(id))
+            .await
+            .context(ArchiveDownloadSnafu { id })?
+            .bytes()
+            .await
+            .context(ArchiveDownloadSnafu { id })?;
+
+        let path = build_segment_path(dir.as_ref(), id);
+        tokio::fs::write(&path, data)
+            .await
+            .context(ArchiveRestoreSegmentSnafu { path })
+    }
+
+    /// Downloads all archived segments with an ID in `from..=to` into `dir`, returning the
+    /// restored IDs. Opening `dir` as a WAL then replays the WAL as of the end of segment `to`.
+    pub async fn restore_range(
+        &self,
+        from: SegmentId,
+        to: SegmentId,
+        dir: impl AsRef<Path>,
+    ) -> Result<Vec<SegmentId>> {
+        let ids: Vec<_> = self
+            .list()
+            .await?
+            .into_iter()
+            .filter(|id| (from..=to).contains(id))
+            .collect();
+
+        for id in &ids {
+            self.restore(*id, dir.as_ref()).await?;
+        }
+
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SequencedWalOp, Wal};
+    use generated_types::influxdata::iox::wal::v1::{sequenced_wal_op::Op as WalOp, PersistOp};
+    use object_store::memory::InMemory;
+
+    #[tokio::test]
+    async fn archive_and_restore() {
+        let dir = test_helpers::tmp_dir().unwrap();
+        let archiver =
+            SegmentArchiver::new(Arc::new(InMemory::new()), ObjectStorePath::from("wal"));
+
+        // Write and archive two segments, deleting them locally
+        let wal = Wal::new(dir.path()).await.unwrap();
+        let writer = wal.write_handle().await;
+        let rotator = wal.rotation_handle();
+        let mut archived = vec![];
+        for sequence_number in [1, 2] {
+            writer.write_op(persist_op(sequence_number)).await.unwrap();
+            let closed = rotator.rotate().await.unwrap();
+            rotator
+                .archive_and_delete(closed.id(), &archiver)
+                .await
+                .unwrap();
+            assert!(!closed.path.exists());
+            archived.push(closed.id());
+        }
+        drop(writer);
+        drop(wal);
+
+        assert_eq!(archiver.list().await.unwrap(), archived);
+
+        // Restore only the first segment and replay it
+        let restore_dir = test_helpers::tmp_dir().unwrap();
+        let restored = archiver
+            .restore_range(archived[0], archived[0], restore_dir.path())
+            .await
+            .unwrap();
+        assert_eq!(restored, [archived[0]]);
+
+        let wal = Wal::new(restore_dir.path()).await.unwrap();
+        let mut reader = wal
+            .read_handle()
+            .reader_for_segment(archived[0])
+            .await
+            .unwrap();
+        assert_eq!(reader.next_op().await.unwrap().unwrap(), persist_op(1));
+        assert!(reader.next_op().await.unwrap().is_none());
+    }
+
+    fn persist_op(sequence_number: u64) -> SequencedWalOp {
+        SequencedWalOp {
+            sequence_number,
+            trace_context: None,
+            ingest_timestamp_ns: None,
+            op: WalOp::Persist(PersistOp {
+                namespace_id: 42,
+                parquet_file_uuid: "b4N4N4Z".into(),
+                partition_id: 43,
+                table_id: 44,
+            }),
+        }
+    }
+}
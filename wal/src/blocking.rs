@@ -1,4 +1,5 @@
 mod reader;
+pub(crate) use reader::TornTail;
 pub use reader::{ClosedSegmentFileReader, Error as ReaderError, Result as ReaderResult};
 
 mod writer;
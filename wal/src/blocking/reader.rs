@@ -1,4 +1,10 @@
-use crate::{FileTypeIdentifier, SegmentEntry, SegmentIdBytes, SequencedWalOp};
+use crate::{
+    encryption::{KeyId, KeyProvider, SegmentCipher, ENCRYPTED_FILE_TYPE_IDENTIFIER},
+    index::SegmentIndex,
+    CorruptEntry, FileTypeIdentifier, SegmentEntry, SegmentFooter, SegmentIdBytes, SequencedWalOp,
+    BOUND_ENTRIES_FORMAT_VERSION, CURRENT_FORMAT_VERSION, FILE_TYPE_IDENTIFIER, FLAG_ENCRYPTED,
+    FOOTER_MARKER, VERSIONED_FILE_TYPE_IDENTIFIER,
+};
 use byteorder::{BigEndian, ReadBytesExt};
 use crc32fast::Hasher;
 use generated_types::influxdata::iox::wal::v1::SequencedWalOp as ProtoSequencedWalOp;
@@ -7,11 +13,90 @@ use snafu::prelude::*;
 use snap::read::FrameDecoder;
 use std::{
     fs::File,
-    io::{self, BufReader, Read},
+    io::{self, BufReader, Read, Seek, SeekFrom},
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
-pub struct ClosedSegmentFileReader<R>(R);
+/// Size of the checksum and length preceding the data of each entry
+const ENTRY_HEADER_LEN: u64 = 8;
+
+pub struct ClosedSegmentFileReader<R> {
+    f: R,
+
+    /// Byte offset of the next byte read from `f`
+    offset: u64,
+
+    /// Byte offset of the end of the entry currently being read, once
+    /// its header has been read
+    entry_end: Option<u64>,
+
+    /// Skip (and record) damaged entries instead of returning errors
+    recovery: bool,
+
+    /// Damaged entries skipped so far
+    corrupt_entries: Vec<CorruptEntry>,
+
+    /// Sequence number of the last op read successfully
+    last_sequence_number: Option<u64>,
+
+    /// Supplies the key of encrypted segments
+    key_provider: Option<Arc<dyn KeyProvider>>,
+
+    /// Decrypts the entries, set when reading the header of an
+    /// encrypted segment
+    cipher: Option<SegmentCipher>,
+
+    /// The format version of the segment, once its header has been read
+    format_version: Option<u32>,
+
+    /// Byte offset of the first entry, once the header has been read
+    entries_start: u64,
+
+    /// The footer and its byte offset, once it has been read
+    footer: Option<(u64, SegmentFooter)>,
+
+    /// What has been read since the first entry, to check against the
+    /// footer; `None` after seeking elsewhere or skipping a damaged
+    /// entry
+    read_summary: Option<ReadSummary>,
+}
+
+/// A partially written entry at the end of a segment; see
+/// [`ClosedSegmentFileReader::find_torn_tail`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TornTail {
+    /// The byte offset of the start of the entry, and so the length of
+    /// the intact part of the segment
+    pub(crate) offset: u64,
+    /// The sequence number of the last intact entry, if there is one
+    pub(crate) previous_sequence_number: Option<u64>,
+}
+
+/// The entries read from a segment, in the form of a [`SegmentFooter`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct ReadSummary {
+    entry_count: u64,
+    min_sequence_number: Option<u64>,
+    max_sequence_number: Option<u64>,
+}
+
+impl ReadSummary {
+    fn add(&mut self, sequence_number: u64) {
+        self.entry_count += 1;
+        self.min_sequence_number = Some(
+            self.min_sequence_number
+                .map_or(sequence_number, |min| min.min(sequence_number)),
+        );
+        self.max_sequence_number = self.max_sequence_number.max(Some(sequence_number));
+    }
+
+    fn matches(&self, footer: &SegmentFooter) -> bool {
+        self.entry_count == footer.entry_count
+            && self.min_sequence_number == footer.min_sequence_number
+            && self.max_sequence_number == footer.max_sequence_number
+    }
+}
 
 impl ClosedSegmentFileReader<BufReader<File>> {
     pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
@@ -27,43 +112,156 @@ where
     R: Read,
 {
     pub fn new(f: R) -> Self {
-        Self(f)
+        Self {
+            f,
+            offset: 0,
+            entry_end: None,
+            recovery: false,
+            corrupt_entries: vec![],
+            last_sequence_number: None,
+            key_provider: None,
+            cipher: None,
+            format_version: None,
+            entries_start: 0,
+            footer: None,
+            read_summary: Some(ReadSummary::default()),
+        }
+    }
+
+    /// The format version of the segment, known once its header has
+    /// been read
+    pub fn format_version(&self) -> Option<u32> {
+        self.format_version
+    }
+
+    /// The footer of the segment, once it has been read by reaching
+    /// the end of the entries. Segments that were not closed cleanly,
+    /// or were written in a format version before the footer was
+    /// introduced, have none.
+    pub fn footer(&self) -> Option<SegmentFooter> {
+        self.footer.map(|(_, footer)| footer)
+    }
+
+    /// The byte offset of the footer, once it has been read.
+    pub fn footer_offset(&self) -> Option<u64> {
+        self.footer.map(|(offset, _)| offset)
+    }
+
+    /// Sets the provider of the keys needed to read encrypted
+    /// segments. Plaintext segments can be read without one.
+    pub fn with_key_provider(mut self, key_provider: Option<Arc<dyn KeyProvider>>) -> Self {
+        self.key_provider = key_provider;
+        self
+    }
+
+    /// In recovery mode, [`Self::next_ops`] skips entries that fail
+    /// their checksum or can't be decoded, recording them (see
+    /// [`Self::take_corrupt_entries`]) and continuing with the next
+    /// entry. A torn entry at the end of the segment ends the segment.
+    pub fn with_recovery(mut self, recovery: bool) -> Self {
+        self.recovery = recovery;
+        self
+    }
+
+    /// Returns the damaged entries skipped since the last call
+    pub fn take_corrupt_entries(&mut self) -> Vec<CorruptEntry> {
+        std::mem::take(&mut self.corrupt_entries)
     }
 
     fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
         let mut data = [0u8; N];
-        self.0
+        self.f
             .read_exact(&mut data)
             .context(UnableToReadArraySnafu { length: N })?;
+        self.offset += N as u64;
         Ok(data)
     }
 
     pub fn read_header(&mut self) -> Result<(FileTypeIdentifier, SegmentIdBytes)> {
-        Ok((self.read_array()?, self.read_array()?))
+        let file_type: FileTypeIdentifier = self.read_array()?;
+
+        let (format_version, flags) = match &file_type {
+            FILE_TYPE_IDENTIFIER => (1, 0),
+            ENCRYPTED_FILE_TYPE_IDENTIFIER => (1, FLAG_ENCRYPTED),
+            VERSIONED_FILE_TYPE_IDENTIFIER => {
+                let format_version = u32::from_be_bytes(self.read_array()?);
+                ensure!(
+                    format_version <= CURRENT_FORMAT_VERSION,
+                    UnsupportedFormatVersionSnafu { format_version }
+                );
+                (format_version, u32::from_be_bytes(self.read_array()?))
+            }
+            // Not a segment file; leave it to the caller to reject
+            _ => return Ok((file_type, self.read_array()?)),
+        };
+        self.format_version = Some(format_version);
+
+        let id = self.read_array()?;
+
+        if flags & FLAG_ENCRYPTED != 0 {
+            let key_id = KeyId::from_bytes(self.read_array()?);
+            let key = self
+                .key_provider
+                .as_ref()
+                .context(NoKeyProviderSnafu { key_id })?
+                .key(key_id)
+                .context(UnknownKeySnafu { key_id })?;
+            let bound_id = (format_version >= BOUND_ENTRIES_FORMAT_VERSION).then_some(id);
+            self.cipher = Some(SegmentCipher::new(key_id, &key, bound_id));
+        }
+        self.entries_start = self.offset;
+
+        Ok((file_type, id))
     }
 
-    fn one_entry(&mut self) -> Result<Option<SegmentEntry>> {
-        let expected_checksum = match self.0.read_u32::<BigEndian>() {
+    pub(crate) fn one_entry(&mut self) -> Result<Option<SegmentEntry>> {
+        let entry_start = self.offset;
+        self.entry_end = None;
+
+        let expected_checksum = match self.f.read_u32::<BigEndian>() {
             Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
             other => other.context(UnableToReadChecksumSnafu)?,
         };
 
         let expected_len = self
-            .0
+            .f
             .read_u32::<BigEndian>()
             .context(UnableToReadLengthSnafu)?
             .into();
 
-        let compressed_read = self.0.by_ref().take(expected_len);
-        let hashing_read = CrcReader::new(compressed_read);
-        let mut decompressing_read = FrameDecoder::new(hashing_read);
+        // The zeroed, preallocated space after the last entry of a
+        // segment that was not closed cleanly. A real entry is never
+        // empty.
+        if expected_checksum == 0 && expected_len == 0 {
+            return Ok(None);
+        }
+
+        self.offset = entry_start + ENTRY_HEADER_LEN;
+
+        if expected_checksum == 0 && expected_len == u64::from(FOOTER_MARKER) {
+            let footer = self.read_footer()?;
+            self.footer = Some((entry_start, footer));
+            return Ok(None);
+        }
+
+        self.entry_end = Some(self.offset + expected_len);
+
+        let compressed_read = self.f.by_ref().take(expected_len);
+        let mut hashing_read = CrcReader::new(compressed_read);
 
+        // Encrypted entries must be read in full before they can be
+        // decrypted and decompressed
         let mut data = Vec::with_capacity(100);
-        decompressing_read
-            .read_to_end(&mut data)
-            .context(UnableToReadDataSnafu)?;
+        let read_result = if self.cipher.is_some() {
+            hashing_read.read_to_end(&mut data)
+        } else {
+            FrameDecoder::new(&mut hashing_read).read_to_end(&mut data)
+        };
+
+        let (actual_compressed_len, actual_checksum) = hashing_read.checksum();
+        self.offset += actual_compressed_len;
 
-        let (actual_compressed_len, actual_checksum) = decompressing_read.get_mut().checksum();
+        read_result.context(UnableToReadDataSnafu)?;
 
         ensure!(
             expected_len == actual_compressed_len,
@@ -81,13 +279,101 @@ where
             }
         );
 
+        if let Some(cipher) = &self.cipher {
+            let compressed = cipher
+                .decrypt(&data, entry_start)
+                .context(UnableToDecryptDataSnafu)?;
+            data.clear();
+            FrameDecoder::new(compressed.as_slice())
+                .read_to_end(&mut data)
+                .context(UnableToReadDataSnafu)?;
+        }
+
         Ok(Some(SegmentEntry {
             checksum: expected_checksum,
             data,
         }))
     }
 
+    fn read_footer(&mut self) -> Result<SegmentFooter> {
+        let entry_count = u64::from_be_bytes(self.read_array()?);
+        let min_sequence_number = u64::from_be_bytes(self.read_array()?);
+        let max_sequence_number = u64::from_be_bytes(self.read_array()?);
+        let checksum = u32::from_be_bytes(self.read_array()?);
+
+        let range = |v| (entry_count > 0).then_some(v);
+        Ok(SegmentFooter {
+            entry_count,
+            min_sequence_number: range(min_sequence_number),
+            max_sequence_number: range(max_sequence_number),
+            checksum,
+        })
+    }
+
+    /// Returns the next op, if any.
+    ///
+    /// When the end of a segment with a footer is reached after reading
+    /// all its entries in order, the entries read are checked against
+    /// the footer. A mismatch is an error (or, in recovery mode, a
+    /// corrupt entry at the offset of the footer).
     pub fn next_ops(&mut self) -> Result<Option<SequencedWalOp>> {
+        loop {
+            let entry_offset = self.offset;
+
+            match self.one_op() {
+                Ok(Some(op)) => {
+                    self.last_sequence_number = Some(op.sequence_number);
+                    if let Some(summary) = &mut self.read_summary {
+                        summary.add(op.sequence_number);
+                    }
+                    return Ok(Some(op));
+                }
+                Ok(None) => {
+                    let mismatch = match (self.footer, self.read_summary.take()) {
+                        (Some((_, footer)), Some(read)) if !read.matches(&footer) => {
+                            FooterMismatchSnafu {
+                                footer,
+                                entry_count: read.entry_count,
+                                min_sequence_number: read.min_sequence_number,
+                                max_sequence_number: read.max_sequence_number,
+                            }
+                            .fail()
+                        }
+                        _ => Ok(None),
+                    };
+
+                    return match mismatch {
+                        Err(e) if self.recovery => {
+                            self.corrupt_entries.push(CorruptEntry {
+                                offset: entry_offset,
+                                previous_sequence_number: self.last_sequence_number,
+                                reason: e.to_string(),
+                            });
+                            Ok(None)
+                        }
+                        other => other,
+                    };
+                }
+                Err(e) if self.recovery => {
+                    self.read_summary = None;
+                    self.corrupt_entries.push(CorruptEntry {
+                        offset: entry_offset,
+                        previous_sequence_number: self.last_sequence_number,
+                        reason: e.to_string(),
+                    });
+
+                    if !self.skip_to_entry_end()? {
+                        // the entry was torn, which can only happen at
+                        // the end of the segment
+                        return Ok(None);
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn one_op(&mut self) -> Result<Option<SequencedWalOp>> {
         if let Some(entry) = self.one_entry()? {
             let decoded = ProtoSequencedWalOp::decode(&*entry.data)
                 .context(UnableToDeserializeDataSnafu)?
@@ -99,6 +385,127 @@ where
 
         Ok(None)
     }
+
+    /// Skips the rest of the entry currently being read, returning
+    /// false if the segment ends before the end of the entry.
+    fn skip_to_entry_end(&mut self) -> Result<bool> {
+        let entry_end = match self.entry_end {
+            Some(entry_end) => entry_end,
+            // the entry header itself was incomplete
+            None => return Ok(false),
+        };
+
+        let remaining = entry_end.saturating_sub(self.offset);
+        let skipped = io::copy(&mut self.f.by_ref().take(remaining), &mut io::sink())
+            .context(UnableToReadDataSnafu)?;
+        self.offset += skipped;
+
+        Ok(skipped == remaining)
+    }
+}
+
+impl<R> ClosedSegmentFileReader<R>
+where
+    R: Read + Seek,
+{
+    /// Moves to the entry starting at byte `offset`, such as one
+    /// recorded in a [`SegmentIndex`].
+    pub fn seek(&mut self, offset: u64) -> Result<()> {
+        self.f
+            .seek(SeekFrom::Start(offset))
+            .context(UnableToSeekSnafu { offset })?;
+        self.offset = offset;
+        self.entry_end = None;
+        self.read_summary = (offset == self.entries_start).then(ReadSummary::default);
+        Ok(())
+    }
+
+    /// Reads the rest of the segment looking for a partially written
+    /// last entry, left by a crash part way through appending it.
+    ///
+    /// An entry (or footer) that can't be read is torn if the segment
+    /// ends before the end of it, or it fails its checksum and is followed only by
+    /// zeroes (the unwritten, preallocated rest of the segment). Damage
+    /// anywhere else is left for the reader of the segment to report.
+    ///
+    /// Must not be used in recovery mode, which skips damaged entries.
+    pub(crate) fn find_torn_tail(&mut self) -> Result<Option<TornTail>> {
+        debug_assert!(!self.recovery);
+
+        loop {
+            let entry_offset = self.offset;
+
+            match self.next_ops() {
+                Ok(Some(_)) => {}
+                Ok(None) => return Ok(None),
+                Err(
+                    Error::UnableToReadChecksum { .. }
+                    | Error::UnableToReadLength { .. }
+                    | Error::UnableToReadArray { .. }
+                    | Error::UnableToReadData { .. }
+                    | Error::LengthMismatch { .. }
+                    | Error::ChecksumMismatch { .. },
+                ) => {
+                    if let Some(entry_end) = self.entry_end {
+                        if !self.only_zeroes_from(entry_end)? {
+                            return Ok(None);
+                        }
+                    }
+
+                    return Ok(Some(TornTail {
+                        offset: entry_offset,
+                        previous_sequence_number: self.last_sequence_number,
+                    }));
+                }
+                Err(_) => return Ok(None),
+            }
+        }
+    }
+
+    /// Whether the segment contains only zeroes from byte `offset` on.
+    fn only_zeroes_from(&mut self, offset: u64) -> Result<bool> {
+        self.seek(offset)?;
+
+        let mut buf = [0; 4096];
+        loop {
+            match self.f.read(&mut buf) {
+                Ok(0) => return Ok(true),
+                Ok(n) if buf[..n].iter().any(|b| *b != 0) => return Ok(false),
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e).context(UnableToReadDataSnafu),
+            }
+        }
+    }
+
+    /// Reads all entries of the segment to index them, then moves
+    /// back to the current entry. Entries skipped in recovery mode are
+    /// not reported by [`Self::take_corrupt_entries`].
+    pub(crate) fn build_index(&mut self) -> Result<SegmentIndex> {
+        let start = self.offset;
+        let last_sequence_number = self.last_sequence_number;
+        let corrupt_entries = std::mem::take(&mut self.corrupt_entries);
+
+        self.seek(self.entries_start)?;
+        let mut index = SegmentIndex::new(self.entries_start);
+        loop {
+            let entry_offset = self.offset;
+            match self.next_ops() {
+                Ok(Some(op)) => index.push(op.sequence_number, entry_offset, self.offset),
+                Ok(None) => break,
+                Err(e) => {
+                    self.corrupt_entries = corrupt_entries;
+                    return Err(e);
+                }
+            }
+        }
+
+        self.last_sequence_number = last_sequence_number;
+        self.corrupt_entries = corrupt_entries;
+        self.seek(start)?;
+
+        Ok(index)
+    }
 }
 
 struct CrcReader<R> {
@@ -175,6 +582,32 @@ pub enum Error {
         actual: u32,
     },
 
+    UnableToSeek {
+        source: io::Error,
+        offset: u64,
+    },
+
+    FooterMismatch {
+        footer: SegmentFooter,
+        entry_count: u64,
+        min_sequence_number: Option<u64>,
+        max_sequence_number: Option<u64>,
+    },
+
+    UnsupportedFormatVersion {
+        format_version: u32,
+    },
+
+    NoKeyProvider {
+        key_id: KeyId,
+    },
+
+    UnknownKey {
+        key_id: KeyId,
+    },
+
+    UnableToDecryptData,
+
     UnableToDecompressData {
         source: snap::Error,
     },
@@ -193,8 +626,9 @@ pub type Result<T, E = Error> = std::result::Result<T, E>;
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{SegmentId, FILE_TYPE_IDENTIFIER};
+    use crate::SegmentId;
     use byteorder::WriteBytesExt;
+    use generated_types::influxdata::iox::wal::v1::{sequenced_wal_op::Op as WalOp, PersistOp};
     use std::io::Write;
     use test_helpers::assert_error;
 
@@ -324,6 +758,280 @@ mod tests {
         assert!(entry.is_none());
     }
 
+    #[test]
+    fn format_versions() {
+        // Unversioned segments are version 1
+        let data = FakeSegmentFile::new().data();
+        let mut reader = ClosedSegmentFileReader::new(data.as_slice());
+        assert_eq!(reader.format_version(), None);
+        reader.read_header().unwrap();
+        assert_eq!(reader.format_version(), Some(1));
+
+        let mut data = VERSIONED_FILE_TYPE_IDENTIFIER.to_vec();
+        data.extend_from_slice(&CURRENT_FORMAT_VERSION.to_be_bytes());
+        data.extend_from_slice(&0_u32.to_be_bytes());
+        data.extend_from_slice(&SegmentId::new(5).as_bytes());
+        let mut reader = ClosedSegmentFileReader::new(data.as_slice());
+        let (_, id) = reader.read_header().unwrap();
+        assert_eq!(id, SegmentId::new(5).as_bytes());
+        assert_eq!(reader.format_version(), Some(CURRENT_FORMAT_VERSION));
+        assert!(reader.one_entry().unwrap().is_none());
+
+        // Segments written by a newer version of the WAL can't be read
+        let mut data = VERSIONED_FILE_TYPE_IDENTIFIER.to_vec();
+        data.extend_from_slice(&(CURRENT_FORMAT_VERSION + 1).to_be_bytes());
+        let mut reader = ClosedSegmentFileReader::new(data.as_slice());
+        assert_error!(
+            reader.read_header(),
+            Error::UnsupportedFormatVersion { format_version } if format_version == CURRENT_FORMAT_VERSION + 1
+        );
+    }
+
+    #[test]
+    fn recovery_skips_corrupt_entries() {
+        let mut segment_file = FakeSegmentFile::new();
+        segment_file.add_entry(FakeSegmentEntry::new(&encoded_op(1)));
+        let bad_entry_input = FakeSegmentEntry::new(&encoded_op(2));
+        let good_checksum = bad_entry_input.checksum();
+        segment_file.add_entry(bad_entry_input.with_checksum(good_checksum + 1));
+        segment_file.add_entry(FakeSegmentEntry::new(b"not a wal op"));
+        segment_file.add_entry(FakeSegmentEntry::new(&encoded_op(4)));
+
+        let data = segment_file.data();
+        let mut reader = ClosedSegmentFileReader::new(data.as_slice()).with_recovery(true);
+        reader.read_header().unwrap();
+
+        assert_eq!(reader.next_ops().unwrap().unwrap().sequence_number, 1);
+        assert_eq!(reader.next_ops().unwrap().unwrap().sequence_number, 4);
+        assert!(reader.next_ops().unwrap().is_none());
+
+        let corrupt = reader.take_corrupt_entries();
+        assert_eq!(corrupt.len(), 2, "{:?}", corrupt);
+        let first_len = 8 + FakeSegmentEntry::new(&encoded_op(1)).compressed_len() as u64;
+        assert_eq!(corrupt[0].offset, 16 + first_len);
+        assert_eq!(corrupt[0].previous_sequence_number, Some(1));
+        assert!(corrupt[0].reason.contains("ChecksumMismatch"));
+        assert_eq!(corrupt[1].previous_sequence_number, Some(1));
+
+        assert!(reader.take_corrupt_entries().is_empty());
+    }
+
+    #[test]
+    fn recovery_stops_at_torn_entry() {
+        let mut segment_file = FakeSegmentFile::new();
+        segment_file.add_entry(FakeSegmentEntry::new(&encoded_op(1)));
+        segment_file.add_entry(FakeSegmentEntry::new(&encoded_op(2)));
+
+        let mut data = segment_file.data();
+        data.truncate(data.len() - 3);
+        let mut reader = ClosedSegmentFileReader::new(data.as_slice()).with_recovery(true);
+        reader.read_header().unwrap();
+
+        assert_eq!(reader.next_ops().unwrap().unwrap().sequence_number, 1);
+        assert!(reader.next_ops().unwrap().is_none());
+
+        let corrupt = reader.take_corrupt_entries();
+        assert_eq!(corrupt.len(), 1, "{:?}", corrupt);
+        assert_eq!(corrupt[0].previous_sequence_number, Some(1));
+    }
+
+    #[test]
+    fn corrupt_entry_without_recovery_is_an_error() {
+        let mut segment_file = FakeSegmentFile::new();
+        let bad_entry_input = FakeSegmentEntry::new(&encoded_op(1));
+        let good_checksum = bad_entry_input.checksum();
+        segment_file.add_entry(bad_entry_input.with_checksum(good_checksum + 1));
+
+        let data = segment_file.data();
+        let mut reader = ClosedSegmentFileReader::new(data.as_slice());
+        reader.read_header().unwrap();
+
+        assert_error!(reader.next_ops(), Error::ChecksumMismatch { .. });
+        assert!(reader.take_corrupt_entries().is_empty());
+    }
+
+    #[test]
+    fn build_index_and_seek() {
+        let mut segment_file = FakeSegmentFile::new();
+        for sequence_number in [1, 3, 2, 4] {
+            segment_file.add_entry(FakeSegmentEntry::new(&encoded_op(sequence_number)));
+        }
+
+        let data = segment_file.data();
+        let mut reader = ClosedSegmentFileReader::new(io::Cursor::new(data));
+        reader.read_header().unwrap();
+
+        let index = reader.build_index().unwrap();
+        assert_eq!(index.len(), 4);
+
+        // Building the index doesn't move the reader
+        assert_eq!(reader.next_ops().unwrap().unwrap().sequence_number, 1);
+        assert_eq!(reader.build_index().unwrap(), index);
+        assert_eq!(reader.next_ops().unwrap().unwrap().sequence_number, 3);
+
+        reader.seek(index.offset_for(3)).unwrap();
+        assert_eq!(reader.next_ops().unwrap().unwrap().sequence_number, 3);
+        assert_eq!(reader.next_ops().unwrap().unwrap().sequence_number, 2);
+
+        reader.seek(index.offset_for(4)).unwrap();
+        assert_eq!(reader.next_ops().unwrap().unwrap().sequence_number, 4);
+        assert!(reader.next_ops().unwrap().is_none());
+
+        reader.seek(index.offset_for(5)).unwrap();
+        assert!(reader.next_ops().unwrap().is_none());
+    }
+
+    #[test]
+    fn preallocated_space_ends_segment() {
+        let mut segment_file = FakeSegmentFile::new();
+        segment_file.add_entry(FakeSegmentEntry::new(&encoded_op(1)));
+
+        let mut data = segment_file.data();
+        data.extend_from_slice(&[0; 100]);
+        let mut reader = ClosedSegmentFileReader::new(data.as_slice());
+        reader.read_header().unwrap();
+
+        assert_eq!(reader.next_ops().unwrap().unwrap().sequence_number, 1);
+        assert!(reader.next_ops().unwrap().is_none());
+    }
+
+    #[test]
+    fn torn_tail() {
+        let mut segment_file = FakeSegmentFile::new();
+        segment_file.add_entry(FakeSegmentEntry::new(&encoded_op(1)));
+        let intact_len = segment_file.data().len() as u64;
+        segment_file.add_entry(FakeSegmentEntry::new(&encoded_op(2)));
+        let data = segment_file.data();
+
+        let find = |data: Vec<u8>| {
+            let mut reader = ClosedSegmentFileReader::new(io::Cursor::new(data));
+            reader.read_header().unwrap();
+            reader.find_torn_tail().unwrap()
+        };
+        let torn = Some(TornTail {
+            offset: intact_len,
+            previous_sequence_number: Some(1),
+        });
+
+        // Complete segments have no torn tail
+        assert_eq!(find(data.clone()), None);
+
+        // The segment ends part way through the data or the header of the last entry
+        assert_eq!(find(data[..data.len() - 3].to_vec()), torn);
+        assert_eq!(find(data[..intact_len as usize + 6].to_vec()), torn);
+
+        // The end of the last entry was never written to the preallocated space
+        let mut preallocated = data.clone();
+        let len = preallocated.len();
+        preallocated[len - 3..].fill(0);
+        preallocated.extend_from_slice(&[0; 100]);
+        assert_eq!(find(preallocated), torn);
+
+        // A damaged entry followed by data is not a torn write
+        let mut damaged = FakeSegmentFile::new();
+        let bad_entry = FakeSegmentEntry::new(&encoded_op(1));
+        let good_checksum = bad_entry.checksum();
+        damaged.add_entry(bad_entry.with_checksum(good_checksum + 1));
+        damaged.add_entry(FakeSegmentEntry::new(&encoded_op(2)));
+        assert_eq!(find(damaged.data()), None);
+    }
+
+    #[test]
+    fn footer_is_read_and_checked() {
+        let mut segment_file = FakeSegmentFile::new();
+        for sequence_number in [3, 1, 2] {
+            segment_file.add_entry(FakeSegmentEntry::new(&encoded_op(sequence_number)));
+        }
+        let mut data = segment_file.data();
+        let footer_offset = data.len() as u64;
+        data.extend_from_slice(&footer_bytes(3, 1, 3));
+
+        let mut reader = ClosedSegmentFileReader::new(data.as_slice());
+        reader.read_header().unwrap();
+        while reader.next_ops().unwrap().is_some() {}
+
+        assert_eq!(
+            reader.footer(),
+            Some(SegmentFooter {
+                entry_count: 3,
+                min_sequence_number: Some(1),
+                max_sequence_number: Some(3),
+                checksum: 42,
+            })
+        );
+        assert_eq!(reader.footer_offset(), Some(footer_offset));
+    }
+
+    #[test]
+    fn footer_mismatch_detects_missing_entries() {
+        let mut segment_file = FakeSegmentFile::new();
+        segment_file.add_entry(FakeSegmentEntry::new(&encoded_op(1)));
+        let mut data = segment_file.data();
+        // The footer of a segment that had a second entry
+        data.extend_from_slice(&footer_bytes(2, 1, 2));
+
+        let mut reader = ClosedSegmentFileReader::new(data.as_slice());
+        reader.read_header().unwrap();
+        assert_eq!(reader.next_ops().unwrap().unwrap().sequence_number, 1);
+        assert_error!(
+            reader.next_ops(),
+            Error::FooterMismatch { entry_count, .. } if entry_count == 1
+        );
+
+        let mut reader = ClosedSegmentFileReader::new(data.as_slice()).with_recovery(true);
+        reader.read_header().unwrap();
+        assert_eq!(reader.next_ops().unwrap().unwrap().sequence_number, 1);
+        assert!(reader.next_ops().unwrap().is_none());
+        let corrupt = reader.take_corrupt_entries();
+        assert_eq!(corrupt.len(), 1, "{:?}", corrupt);
+        assert!(corrupt[0].reason.contains("FooterMismatch"));
+    }
+
+    #[test]
+    fn footer_is_not_checked_after_seeking() {
+        let mut segment_file = FakeSegmentFile::new();
+        for sequence_number in [1, 2] {
+            segment_file.add_entry(FakeSegmentEntry::new(&encoded_op(sequence_number)));
+        }
+        let mut data = segment_file.data();
+        data.extend_from_slice(&footer_bytes(2, 1, 2));
+
+        let mut reader = ClosedSegmentFileReader::new(io::Cursor::new(data));
+        reader.read_header().unwrap();
+        let index = reader.build_index().unwrap();
+
+        reader.seek(index.offset_for(2)).unwrap();
+        assert_eq!(reader.next_ops().unwrap().unwrap().sequence_number, 2);
+        assert!(reader.next_ops().unwrap().is_none());
+        assert!(reader.footer().is_some());
+    }
+
+    fn footer_bytes(entry_count: u64, min: u64, max: u64) -> Vec<u8> {
+        let mut f = Vec::new();
+        f.write_u32::<BigEndian>(0).unwrap();
+        f.write_u32::<BigEndian>(FOOTER_MARKER).unwrap();
+        f.write_u64::<BigEndian>(entry_count).unwrap();
+        f.write_u64::<BigEndian>(min).unwrap();
+        f.write_u64::<BigEndian>(max).unwrap();
+        f.write_u32::<BigEndian>(42).unwrap();
+        f
+    }
+
+    fn encoded_op(sequence_number: u64) -> Vec<u8> {
+        ProtoSequencedWalOp {
+            sequence_number,
+            trace_context: None,
+            ingest_timestamp_ns: None,
+            op: Some(WalOp::Persist(PersistOp {
+                namespace_id: 42,
+                parquet_file_uuid: "b4N4N4Z".into(),
+                partition_id: 43,
+                table_id: 44,
+            })),
+        }
+        .encode_to_vec()
+    }
+
     #[derive(Debug)]
     struct FakeSegmentFile {
         id: SegmentId,
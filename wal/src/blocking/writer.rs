@@ -1,10 +1,15 @@
-use crate::{ClosedSegment, SegmentId, WriteSummary, FILE_TYPE_IDENTIFIER};
+use crate::{
+    encryption::{KeyProvider, SegmentCipher},
+    fault::FaultInjector,
+    ClosedSegment, SegmentId, WriteSummary, CURRENT_FORMAT_VERSION, FLAG_ENCRYPTED, FOOTER_LEN,
+    FOOTER_MARKER, VERSIONED_FILE_TYPE_IDENTIFIER,
+};
 use byteorder::{BigEndian, WriteBytesExt};
 use crc32fast::Hasher;
 use snafu::prelude::*;
 use std::{
     fs::{File, OpenOptions},
-    io::{self, Write},
+    io::{self, Seek, SeekFrom, Write},
     mem, num,
     path::PathBuf,
     sync::{
@@ -18,15 +23,50 @@ pub struct OpenSegmentFileWriter {
     path: PathBuf,
     f: File,
     bytes_written: usize,
+    cipher: Option<SegmentCipher>,
+
+    /// The length the file was preallocated to, or 0
+    preallocated: u64,
+
+    /// Checksum of all bytes written, recorded in the footer
+    hasher: Hasher,
+
+    /// Number of entries written, and the range of their sequence
+    /// numbers, recorded in the footer
+    entry_count: u64,
+    min_sequence_number: u64,
+    max_sequence_number: u64,
+
+    /// Faults to inject into writes and syncs, in tests
+    faults: Option<Arc<FaultInjector>>,
 }
 
 impl OpenSegmentFileWriter {
     pub fn new_in_directory(
         dir: impl Into<PathBuf>,
         next_id_source: Arc<AtomicU64>,
+        key_provider: Option<&dyn KeyProvider>,
+        preallocate: Option<u64>,
     ) -> Result<Self> {
         let id = SegmentId::new(next_id_source.fetch_add(1, Ordering::Relaxed));
         let path = crate::build_segment_path(dir, id);
+        let mut writer = Self::new_at_path(path, id, key_provider)?;
+        if let Some(len) = preallocate {
+            writer.preallocate(len)?;
+        }
+        Ok(writer)
+    }
+
+    /// Creates the segment file `id` at `path`, in the current format version.
+    pub fn new_at_path(
+        path: PathBuf,
+        id: SegmentId,
+        key_provider: Option<&dyn KeyProvider>,
+    ) -> Result<Self> {
+        let cipher = key_provider.map(|key_provider| {
+            let (key_id, key) = key_provider.current_key();
+            SegmentCipher::new(key_id, &key, Some(id.as_bytes()))
+        });
 
         let mut f = OpenOptions::new()
             .write(true)
@@ -34,31 +74,95 @@ impl OpenSegmentFileWriter {
             .open(&path)
             .context(SegmentCreateSnafu)?;
 
-        f.write_all(FILE_TYPE_IDENTIFIER)
+        let mut hasher = Hasher::new();
+
+        f.write_all(VERSIONED_FILE_TYPE_IDENTIFIER)
+            .context(SegmentWriteFileTypeSnafu)?;
+        hasher.update(VERSIONED_FILE_TYPE_IDENTIFIER);
+        f.write_u32::<BigEndian>(CURRENT_FORMAT_VERSION)
             .context(SegmentWriteFileTypeSnafu)?;
-        let file_type_bytes_written = FILE_TYPE_IDENTIFIER.len();
+        hasher.update(&CURRENT_FORMAT_VERSION.to_be_bytes());
+        let flags = match cipher {
+            Some(_) => FLAG_ENCRYPTED,
+            None => 0,
+        };
+        f.write_u32::<BigEndian>(flags)
+            .context(SegmentWriteFileTypeSnafu)?;
+        hasher.update(&flags.to_be_bytes());
+        let file_type_bytes_written = VERSIONED_FILE_TYPE_IDENTIFIER.len()
+            + mem::size_of_val(&CURRENT_FORMAT_VERSION)
+            + mem::size_of_val(&flags);
 
         let id_bytes = id.as_bytes();
         f.write_all(&id_bytes).context(SegmentWriteIdSnafu)?;
+        hasher.update(&id_bytes);
         let id_bytes_written = id_bytes.len();
 
+        // Encrypted segments record which key they were encrypted with
+        let mut key_id_bytes_written = 0;
+        if let Some(cipher) = &cipher {
+            let key_id_bytes = cipher.key_id().as_bytes();
+            f.write_all(&key_id_bytes).context(SegmentWriteKeyIdSnafu)?;
+            hasher.update(&key_id_bytes);
+            key_id_bytes_written = key_id_bytes.len();
+        }
+
         f.sync_all().expect("fsync failure");
 
-        let bytes_written = file_type_bytes_written + id_bytes_written;
+        let bytes_written = file_type_bytes_written + id_bytes_written + key_id_bytes_written;
 
         Ok(Self {
             id,
             path,
             f,
             bytes_written,
+            cipher,
+            preallocated: 0,
+            hasher,
+            entry_count: 0,
+            min_sequence_number: u64::MAX,
+            max_sequence_number: 0,
+            faults: None,
         })
     }
 
+    /// Injects the faults armed in `faults` into the writes and syncs of
+    /// entries.
+    pub fn with_faults(mut self, faults: Option<Arc<FaultInjector>>) -> Self {
+        self.faults = faults;
+        self
+    }
+
     pub fn id(&self) -> SegmentId {
         self.id
     }
 
-    pub fn write(&mut self, data: &[u8]) -> Result<WriteSummary> {
+    /// The number of bytes written to the segment, including its header
+    pub fn bytes_written(&self) -> usize {
+        self.bytes_written
+    }
+
+    /// Allocates disk space for the file up to `len` bytes, so that
+    /// running out of space is reported here rather than by a later
+    /// [`Self::write`], and writes within that length don't change the
+    /// file size. The unused space is released by [`Self::close`].
+    ///
+    /// Space is only reserved on Linux; elsewhere the file is extended
+    /// without reserving space.
+    pub fn preallocate(&mut self, len: u64) -> Result<()> {
+        if len <= self.bytes_written as u64 {
+            return Ok(());
+        }
+
+        allocate(&self.f, len).context(SegmentPreallocateSnafu { len })?;
+        self.f.sync_all().expect("fsync failure");
+        self.preallocated = len;
+
+        Ok(())
+    }
+
+    /// Appends the encoded op `data`, which has `sequence_number`.
+    pub fn write(&mut self, data: &[u8], sequence_number: u64) -> Result<WriteSummary> {
         // Only designed to support chunks up to `u32::max` bytes long.
         let uncompressed_len = data.len();
         u32::try_from(uncompressed_len).context(ChunkSizeTooLargeSnafu {
@@ -70,6 +174,10 @@ impl OpenSegmentFileWriter {
         let mut encoder = snap::write::FrameEncoder::new(Vec::new());
         encoder.write_all(data).context(UnableToCompressDataSnafu)?;
         let compressed_data = encoder.into_inner().expect("cannot fail to flush to a Vec");
+        let compressed_data = match &self.cipher {
+            Some(cipher) => cipher.encrypt(&compressed_data, self.bytes_written as u64),
+            None => compressed_data,
+        };
         let actual_compressed_len = compressed_data.len();
         let actual_compressed_len =
             u32::try_from(actual_compressed_len).context(ChunkSizeTooLargeSnafu {
@@ -80,23 +188,42 @@ impl OpenSegmentFileWriter {
         hasher.update(&compressed_data);
         let checksum = hasher.finalize();
 
-        self.f
-            .write_u32::<BigEndian>(checksum)
-            .context(SegmentWriteChecksumSnafu)?;
-        self.f
-            .write_u32::<BigEndian>(actual_compressed_len)
-            .context(SegmentWriteLengthSnafu)?;
-        self.f
-            .write_all(&compressed_data)
-            .context(SegmentWriteDataSnafu)?;
+        // Remove any part of an entry that failed to be written, so the
+        // segment stays readable and later entries follow the last
+        // complete one
+        let written = self
+            .write_bytes(&checksum.to_be_bytes())
+            .context(SegmentWriteChecksumSnafu)
+            .and_then(|_| {
+                self.write_bytes(&actual_compressed_len.to_be_bytes())
+                    .context(SegmentWriteLengthSnafu)
+            })
+            .and_then(|_| {
+                self.write_bytes(&compressed_data)
+                    .context(SegmentWriteDataSnafu)
+            });
+        if let Err(e) = written {
+            self.discard_partial_entry();
+            return Err(e);
+        }
 
-        self.f.sync_all().expect("fsync failure");
+        self.hasher.update(&checksum.to_be_bytes());
+        self.hasher.update(&actual_compressed_len.to_be_bytes());
+        self.hasher.update(&compressed_data);
+        self.entry_count += 1;
+        self.min_sequence_number = self.min_sequence_number.min(sequence_number);
+        self.max_sequence_number = self.max_sequence_number.max(sequence_number);
 
         let bytes_written = mem::size_of_val(&checksum)
             + mem::size_of_val(&actual_compressed_len)
             + compressed_data.len();
         self.bytes_written += bytes_written;
 
+        // Writes within the preallocated space don't change the file
+        // size, so only the data needs syncing
+        self.sync(self.bytes_written as u64 <= self.preallocated)
+            .expect("fsync failure");
+
         Ok(WriteSummary {
             total_bytes: self.bytes_written,
             bytes_written,
@@ -105,13 +232,81 @@ impl OpenSegmentFileWriter {
         })
     }
 
+    fn write_bytes(&mut self, buf: &[u8]) -> io::Result<()> {
+        match &self.faults {
+            Some(faults) => faults.write_all(&mut self.f, buf),
+            None => self.f.write_all(buf),
+        }
+    }
+
+    fn sync(&self, data_only: bool) -> io::Result<()> {
+        if let Some(faults) = &self.faults {
+            faults.sync()?;
+        }
+        if data_only {
+            self.f.sync_data()
+        } else {
+            self.f.sync_all()
+        }
+    }
+
+    /// Truncates the file back to the end of the last complete entry,
+    /// zeroing any preallocated space after it.
+    fn discard_partial_entry(&mut self) {
+        let end = self.bytes_written as u64;
+        let mut discard = || -> io::Result<()> {
+            self.f.set_len(end)?;
+            self.f.seek(SeekFrom::Start(end))?;
+            if self.preallocated > end {
+                allocate(&self.f, self.preallocated)?;
+            }
+            Ok(())
+        };
+        discard().expect("failed to discard partially written entry");
+    }
+
+    /// Writes the footer and closes the segment.
+    ///
+    /// The footer is marked by a zero checksum and a length of
+    /// [`FOOTER_MARKER`], followed by the number of entries, their
+    /// minimum and maximum sequence numbers (zero if there are no
+    /// entries) and the checksum of all bytes before the footer.
     pub fn close(self) -> Result<ClosedSegment> {
         let Self {
             id,
             path,
-            bytes_written,
+            mut f,
+            mut bytes_written,
+            preallocated,
+            hasher,
+            entry_count,
+            min_sequence_number,
+            max_sequence_number,
             ..
         } = self;
+
+        let (min_sequence_number, max_sequence_number) = match entry_count {
+            0 => (0, 0),
+            _ => (min_sequence_number, max_sequence_number),
+        };
+        let mut footer = Vec::with_capacity(FOOTER_LEN);
+        footer.extend_from_slice(&0_u32.to_be_bytes());
+        footer.extend_from_slice(&FOOTER_MARKER.to_be_bytes());
+        footer.extend_from_slice(&entry_count.to_be_bytes());
+        footer.extend_from_slice(&min_sequence_number.to_be_bytes());
+        footer.extend_from_slice(&max_sequence_number.to_be_bytes());
+        footer.extend_from_slice(&hasher.finalize().to_be_bytes());
+        debug_assert_eq!(footer.len(), FOOTER_LEN);
+        f.write_all(&footer).context(SegmentWriteFooterSnafu)?;
+        bytes_written += footer.len();
+
+        // Release the unused preallocated space
+        if preallocated > bytes_written as u64 {
+            f.set_len(bytes_written as u64)
+                .context(SegmentTruncateSnafu)?;
+        }
+        f.sync_all().expect("fsync failure");
+
         Ok(ClosedSegment {
             id,
             path,
@@ -122,6 +317,24 @@ impl OpenSegmentFileWriter {
     }
 }
 
+#[cfg(target_os = "linux")]
+fn allocate(f: &File, len: u64) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let len =
+        libc::off_t::try_from(len).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    // SAFETY: the file descriptor is valid for the lifetime of `f`
+    match unsafe { libc::posix_fallocate(f.as_raw_fd(), 0, len) } {
+        0 => Ok(()),
+        errno => Err(io::Error::from_raw_os_error(errno)),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn allocate(f: &File, len: u64) -> io::Result<()> {
+    f.set_len(len)
+}
+
 #[derive(Debug, Snafu)]
 pub enum Error {
     SegmentCreate {
@@ -136,6 +349,23 @@ pub enum Error {
         source: io::Error,
     },
 
+    SegmentWriteKeyId {
+        source: io::Error,
+    },
+
+    SegmentPreallocate {
+        source: io::Error,
+        len: u64,
+    },
+
+    SegmentTruncate {
+        source: io::Error,
+    },
+
+    SegmentWriteFooter {
+        source: io::Error,
+    },
+
     SegmentWriteChecksum {
         source: io::Error,
     },
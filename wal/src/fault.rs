@@ -0,0 +1,135 @@
+//! Injection of I/O faults into the writing of segment files, for testing how failures are
+//! handled and recovered from; see [`crate::test_utils`].
+
+use std::{
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+/// Injects faults into the I/O of the segments of a [`Wal`](crate::Wal) it is configured for
+/// (with `WalOptions::with_fault_injector`), or of a
+/// [`FaultyObjectStore`](crate::test_utils::FaultyObjectStore).
+///
+/// Faults are armed by the test and triggered by the next I/O, so tests stay deterministic.
+#[derive(Debug, Default)]
+pub struct FaultInjector {
+    /// The number of bytes that can still be written before a write is cut short, if armed
+    short_write_after: Mutex<Option<u64>>,
+
+    /// The number of upcoming syncs that fail
+    failing_syncs: AtomicU64,
+
+    /// How long each write and sync is delayed by
+    delay: Mutex<Duration>,
+}
+
+impl FaultInjector {
+    /// Creates an injector with no faults armed.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cut short the write during which `bytes` more bytes have been written: the bytes up to
+    /// that point are written, and the write then fails.
+    pub fn short_write_after(&self, bytes: u64) {
+        *self.short_write_after.lock().expect("fault lock poisoned") = Some(bytes);
+    }
+
+    /// Fail the next sync of a segment file to disk. The WAL treats a failed sync as fatal, so
+    /// this stops the segment writer as a real failure would.
+    pub fn fail_next_sync(&self) {
+        self.failing_syncs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Delay every write and sync by `delay`, until set back to zero.
+    pub fn set_delay(&self, delay: Duration) {
+        *self.delay.lock().expect("fault lock poisoned") = delay;
+    }
+
+    /// The delay set by [`Self::set_delay`].
+    pub(crate) fn delay(&self) -> Duration {
+        *self.delay.lock().expect("fault lock poisoned")
+    }
+
+    /// How much of a write of `len` bytes succeeds before it is cut short, if it is.
+    pub(crate) fn short_write_len(&self, len: usize) -> Option<usize> {
+        let mut remaining = self.short_write_after.lock().expect("fault lock poisoned");
+        match *remaining {
+            Some(bytes) if bytes < len as u64 => {
+                *remaining = None;
+                Some(bytes as usize)
+            }
+            Some(bytes) => {
+                *remaining = Some(bytes - len as u64);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Writes all of `buf` to `w`, unless cut short.
+    pub(crate) fn write_all(&self, w: &mut impl Write, buf: &[u8]) -> io::Result<()> {
+        std::thread::sleep(self.delay());
+
+        match self.short_write_len(buf.len()) {
+            Some(len) => {
+                w.write_all(&buf[..len])?;
+                Err(io::Error::new(io::ErrorKind::Other, "injected short write"))
+            }
+            None => w.write_all(buf),
+        }
+    }
+
+    /// Called before each sync, failing it if armed to.
+    pub(crate) fn sync(&self) -> io::Result<()> {
+        std::thread::sleep(self.delay());
+
+        let failing = self
+            .failing_syncs
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+        match failing {
+            Ok(_) => Err(io::Error::new(
+                io::ErrorKind::Other,
+                "injected sync failure",
+            )),
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_write() {
+        let faults = FaultInjector::new();
+        let mut out = vec![];
+
+        faults.write_all(&mut out, b"abc").unwrap();
+        faults.short_write_after(4);
+        faults.write_all(&mut out, b"def").unwrap();
+        assert!(faults.write_all(&mut out, b"ghi").is_err());
+        assert_eq!(out, b"abcdefg");
+
+        // Disarmed once triggered
+        faults.write_all(&mut out, b"jkl").unwrap();
+        assert_eq!(out, b"abcdefgjkl");
+    }
+
+    #[test]
+    fn failing_syncs() {
+        let faults = FaultInjector::new();
+        faults.sync().unwrap();
+
+        faults.fail_next_sync();
+        faults.fail_next_sync();
+        assert!(faults.sync().is_err());
+        assert!(faults.sync().is_err());
+        faults.sync().unwrap();
+    }
+}
@@ -0,0 +1,81 @@
+//! In-memory index of the entries of a closed segment, used to seek a reader to the first entry
+//! at or above a sequence number without decoding the entries before it.
+
+/// Maps sequence numbers to the byte offsets of the entries of one segment.
+///
+/// Sequence numbers are not required to increase monotonically within a segment, so the index
+/// records, for each entry in file order, the largest sequence number seen up to and including
+/// that entry. Seeking to `n` then finds the first entry with a sequence number of at least `n`;
+/// every entry before it is below `n`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct SegmentIndex {
+    /// `(largest sequence number so far, entry offset)`, in file order
+    entries: Vec<(u64, u64)>,
+
+    /// Byte offset of the end of the last entry
+    end_offset: u64,
+}
+
+impl SegmentIndex {
+    /// Creates an empty index for a segment whose first entry starts at `offset`.
+    pub(crate) fn new(offset: u64) -> Self {
+        Self {
+            entries: vec![],
+            end_offset: offset,
+        }
+    }
+
+    /// Records the entry with `sequence_number` starting at `offset`, which must be after all
+    /// entries already recorded, and ending at `end_offset`.
+    pub(crate) fn push(&mut self, sequence_number: u64, offset: u64, end_offset: u64) {
+        debug_assert!(offset >= self.end_offset);
+        let max = self
+            .entries
+            .last()
+            .map_or(sequence_number, |(max, _)| sequence_number.max(*max));
+        self.entries.push((max, offset));
+        self.end_offset = end_offset;
+    }
+
+    /// Number of indexed entries.
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The byte offset of the first entry with a sequence number of at least `sequence_number`,
+    /// or the end of the segment if there is none.
+    pub(crate) fn offset_for(&self, sequence_number: u64) -> u64 {
+        let i = self
+            .entries
+            .partition_point(|(max, _)| *max < sequence_number);
+        self.entries
+            .get(i)
+            .map_or(self.end_offset, |(_, offset)| *offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offset_for() {
+        let mut index = SegmentIndex::new(16);
+        assert_eq!(index.offset_for(0), 16);
+
+        index.push(5, 16, 30);
+        index.push(7, 30, 40);
+        index.push(6, 40, 55);
+        index.push(9, 55, 60);
+        assert_eq!(index.len(), 4);
+
+        assert_eq!(index.offset_for(0), 16);
+        assert_eq!(index.offset_for(5), 16);
+        assert_eq!(index.offset_for(6), 30);
+        assert_eq!(index.offset_for(7), 30);
+        // The out of order entry 6 does not start a new run
+        assert_eq!(index.offset_for(8), 55);
+        assert_eq!(index.offset_for(9), 55);
+        assert_eq!(index.offset_for(10), 60);
+    }
+}
@@ -0,0 +1,212 @@
+//! Read-only inspection of the segment files of a WAL, for debugging replay issues.
+//!
+//! Unlike [`Wal::new`](crate::Wal::new), nothing here creates, modifies or deletes any files, so
+//! it is safe to point at the WAL directory of a live (or crashed) ingester.
+
+use generated_types::influxdata::iox::wal::v1::sequenced_wal_op::Op as WalOp;
+use snafu::prelude::*;
+use std::{io::Write, path::Path, sync::Arc};
+
+use crate::{
+    ClosedSegment, ClosedSegmentFileReader, CorruptEntry, KeyProvider, Result, SegmentId,
+    WriteOutputSnafu,
+};
+
+/// Summary of the contents of one segment file.
+#[derive(Debug, Clone)]
+pub struct SegmentSummary {
+    /// The segment
+    pub segment: ClosedSegment,
+    /// Number of entries that could be read
+    pub entry_count: u64,
+    /// Smallest sequence number of the entries, if there are any
+    pub min_sequence_number: Option<u64>,
+    /// Largest sequence number of the entries, if there are any
+    pub max_sequence_number: Option<u64>,
+    /// Damaged entries that were skipped
+    pub corrupt_entries: Vec<CorruptEntry>,
+}
+
+/// Lists the segment files in the WAL directory `dir`, ordered by ID.
+pub async fn list_segments(dir: impl AsRef<Path>) -> Result<Vec<ClosedSegment>> {
+    Ok(crate::read_segments(dir.as_ref())
+        .await?
+        .into_values()
+        .collect())
+}
+
+/// Reads every entry of `segment`, skipping damaged entries, and summarises them.
+///
+/// `key_provider` is needed to read encrypted segments.
+pub async fn summarize_segment(
+    segment: &ClosedSegment,
+    key_provider: Option<Arc<dyn KeyProvider>>,
+) -> Result<SegmentSummary> {
+    let mut reader = ClosedSegmentFileReader::from_path(&segment.path, true, key_provider).await?;
+
+    let mut entry_count = 0;
+    let mut min_sequence_number = None;
+    let mut max_sequence_number = None;
+    while let Some(op) = reader.next_op().await? {
+        entry_count += 1;
+        let sequence_number = op.sequence_number;
+        min_sequence_number =
+            Some(min_sequence_number.map_or(sequence_number, |min: u64| min.min(sequence_number)));
+        max_sequence_number = max_sequence_number.max(Some(sequence_number));
+    }
+
+    Ok(SegmentSummary {
+        segment: segment.clone(),
+        entry_count,
+        min_sequence_number,
+        max_sequence_number,
+        corrupt_entries: reader.take_corrupt_entries().await?,
+    })
+}
+
+/// Writes a human readable description of every entry of the segment `id` in the WAL directory
+/// `dir` to `out`, including the decoded contents of writes. Damaged entries are reported and
+/// skipped.
+///
+/// If `from_sequence_number` is given, the entries before the first one with a sequence number at
+/// or above it are skipped without being described (see
+/// [`ClosedSegmentFileReader::seek_to`]).
+pub async fn dump_segment(
+    dir: impl AsRef<Path>,
+    id: SegmentId,
+    key_provider: Option<Arc<dyn KeyProvider>>,
+    from_sequence_number: Option<u64>,
+    mut out: impl Write + Send,
+) -> Result<()> {
+    let path = crate::build_segment_path(dir.as_ref(), id);
+    let mut reader = ClosedSegmentFileReader::from_path(path, true, key_provider).await?;
+    if let Some(sequence_number) = from_sequence_number {
+        reader.seek_to(sequence_number).await?;
+    }
+
+    let mut index = 0;
+    while let Some(op) = reader.next_op().await? {
+        let kind = match &op.op {
+            WalOp::Write(_) => "write",
+            WalOp::Delete(_) => "delete",
+            WalOp::Persist(_) => "persist",
+        };
+        writeln!(
+            out,
+            "entry {}: sequence_number={} op={}",
+            index, op.sequence_number, kind
+        )
+        .context(WriteOutputSnafu)?;
+        if let Some(ingest_timestamp_ns) = op.ingest_timestamp_ns {
+            writeln!(out, "ingest_timestamp_ns={}", ingest_timestamp_ns)
+                .context(WriteOutputSnafu)?;
+        }
+        if let Some(ctx) = &op.trace_context {
+            writeln!(
+                out,
+                "trace_id={:x} span_id={:x}",
+                (u128::from(ctx.trace_id_high) << 64) | u128::from(ctx.trace_id_low),
+                ctx.span_id
+            )
+            .context(WriteOutputSnafu)?;
+        }
+        match &op.op {
+            WalOp::Write(batch) => writeln!(out, "{:#?}", batch),
+            WalOp::Delete(delete) => writeln!(out, "{:#?}", delete),
+            WalOp::Persist(persist) => writeln!(out, "{:#?}", persist),
+        }
+        .context(WriteOutputSnafu)?;
+        index += 1;
+    }
+
+    for corrupt in reader.take_corrupt_entries().await? {
+        writeln!(
+            out,
+            "corrupt entry at offset {} (after sequence_number {:?}): {}",
+            corrupt.offset, corrupt.previous_sequence_number, corrupt.reason
+        )
+        .context(WriteOutputSnafu)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SequencedWalOp, Wal};
+    use generated_types::influxdata::iox::wal::v1::PersistOp;
+
+    #[tokio::test]
+    async fn summarize_and_dump() {
+        let dir = test_helpers::tmp_dir().unwrap();
+
+        let wal = Wal::new(dir.path()).await.unwrap();
+        let writer = wal.write_handle().await;
+        writer.write_op(persist_op(7)).await.unwrap();
+        writer.write_op(persist_op(3)).await.unwrap();
+        let closed = wal.rotation_handle().rotate().await.unwrap();
+
+        // Both the closed and the new open segment are listed
+        let segments = list_segments(dir.path()).await.unwrap();
+        let ids: Vec<_> = segments.iter().map(|s| s.id()).collect();
+        assert_eq!(ids, [closed.id(), SegmentId::new(closed.id().get() + 1)]);
+
+        let summary = summarize_segment(&segments[0], None).await.unwrap();
+        assert_eq!(summary.entry_count, 2);
+        assert_eq!(summary.min_sequence_number, Some(3));
+        assert_eq!(summary.max_sequence_number, Some(7));
+        assert!(summary.corrupt_entries.is_empty());
+
+        let summary = summarize_segment(&segments[1], None).await.unwrap();
+        assert_eq!(summary.entry_count, 0);
+        assert_eq!(summary.min_sequence_number, None);
+
+        let mut out = vec![];
+        dump_segment(dir.path(), closed.id(), None, None, &mut out)
+            .await
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.starts_with("entry 0: sequence_number=7 op=persist\n"),
+            "{out}"
+        );
+        assert!(
+            out.contains("entry 1: sequence_number=3 op=persist\n"),
+            "{out}"
+        );
+        assert!(out.contains("parquet_file_uuid: \"b4N4N4Z\""), "{out}");
+
+        // Sequence number 3 follows 7, so seeking to 3 skips nothing
+        let mut out = vec![];
+        dump_segment(dir.path(), closed.id(), None, Some(3), &mut out)
+            .await
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.starts_with("entry 0: sequence_number=7 op=persist\n"),
+            "{out}"
+        );
+
+        // while seeking past the largest sequence number skips everything
+        let mut out = vec![];
+        dump_segment(dir.path(), closed.id(), None, Some(8), &mut out)
+            .await
+            .unwrap();
+        assert!(out.is_empty(), "{:?}", String::from_utf8(out));
+    }
+
+    fn persist_op(sequence_number: u64) -> SequencedWalOp {
+        SequencedWalOp {
+            sequence_number,
+            trace_context: None,
+            ingest_timestamp_ns: None,
+            op: WalOp::Persist(PersistOp {
+                namespace_id: 42,
+                parquet_file_uuid: "b4N4N4Z".into(),
+                partition_id: 43,
+                table_id: 44,
+            }),
+        }
+    }
+}
@@ -13,23 +13,43 @@
 //!
 //! This crate provides a local-disk WAL for the IOx ingestion pipeline.
 
+use data_types::SequenceNumber;
+use futures::{stream::BoxStream, StreamExt};
 use generated_types::{
     google::{FieldViolation, OptionalField},
     influxdata::iox::wal::v1::{
         sequenced_wal_op::Op as WalOp, SequencedWalOp as ProtoSequencedWalOp,
     },
 };
+use observability_deps::tracing::warn;
 use prost::Message;
 use snafu::prelude::*;
 use std::{
     collections::BTreeMap,
     io,
-    path::PathBuf,
-    sync::{atomic::AtomicU64, Arc},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
 };
-use tokio::sync::{mpsc, oneshot, RwLock};
+use tokio::sync::{broadcast, mpsc, oneshot, RwLock};
 
+pub mod archive;
 mod blocking;
+mod encryption;
+mod fault;
+mod index;
+mod migrate;
+pub use migrate::migrate;
+pub mod inspect;
+#[cfg(any(test, feature = "test_utils"))]
+pub mod test_utils;
+mod verify;
+pub use encryption::{EncryptionKey, KeyId, KeyIdBytes, KeyProvider, StaticKeyProvider};
+pub use generated_types::influxdata::iox::wal::v1::TraceContext;
+use index::SegmentIndex;
+pub use verify::{SegmentProblem, SegmentVerification};
 
 // TODO: Should have more variants / error types to avoid reusing these
 #[derive(Debug, Snafu)]
@@ -67,6 +87,10 @@ pub enum Error {
         source: io::Error,
     },
 
+    UnableToWriteEntry {
+        source: blocking::WriterError,
+    },
+
     UnableToReadDirectoryContents {
         source: io::Error,
         path: PathBuf,
@@ -79,6 +103,8 @@ pub enum Error {
 
     UnableToSendRequestToReaderTask,
 
+    UnableToSendRequestToWriterTask,
+
     UnableToReceiveResponseFromSenderTask {
         source: tokio::sync::oneshot::error::RecvError,
     },
@@ -95,11 +121,23 @@ pub enum Error {
         source: blocking::ReaderError,
     },
 
+    UnableToSeek {
+        source: blocking::ReaderError,
+    },
+
     InvalidId {
         filename: String,
         source: std::num::ParseIntError,
     },
 
+    UnableToCreateSegmentFile {
+        source: blocking::WriterError,
+    },
+
+    UnableToCloseSegmentFile {
+        source: blocking::WriterError,
+    },
+
     SegmentNotFound {
         id: SegmentId,
     },
@@ -113,6 +151,68 @@ pub enum Error {
         source: std::io::Error,
         path: PathBuf,
     },
+
+    TailLagged {
+        skipped: u64,
+    },
+
+    #[snafu(display(
+        "WAL is full: {} bytes used of a maximum of {}",
+        current_bytes,
+        max_bytes
+    ))]
+    WalFull {
+        current_bytes: u64,
+        max_bytes: u64,
+    },
+
+    WriteOutput {
+        source: std::io::Error,
+    },
+
+    ArchiveReadSegment {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    ArchiveUpload {
+        source: object_store::Error,
+        id: SegmentId,
+    },
+
+    ArchiveList {
+        source: object_store::Error,
+    },
+
+    ArchiveDownload {
+        source: object_store::Error,
+        id: SegmentId,
+    },
+
+    ArchiveRestoreSegment {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    MigrateWriteSegment {
+        source: blocking::WriterError,
+        id: SegmentId,
+    },
+
+    MigrateReplaceSegment {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    VerifyReadSegment {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    TruncateTornTail {
+        source: std::io::Error,
+        path: PathBuf,
+    },
 }
 
 /// A specialized `Result` for WAL-related errors
@@ -158,11 +258,156 @@ pub(crate) fn build_segment_path(dir: impl Into<PathBuf>, id: SegmentId) -> Path
 }
 
 /// The first bytes written into a segment file to identify it and its version.
-// TODO: What's the expected way of upgrading -- what happens when we need version 31?
 type FileTypeIdentifier = [u8; 8];
+/// Identifies a segment file of format version 1, which has no explicit version: the identifier
+/// is followed directly by the segment ID.
 const FILE_TYPE_IDENTIFIER: &FileTypeIdentifier = b"INFLUXV3";
+/// Identifies a segment file of format version 2 or later: the identifier is followed by the
+/// format version and [flags](FLAG_ENCRYPTED) (both big endian `u32`s), and then the segment ID.
+const VERSIONED_FILE_TYPE_IDENTIFIER: &FileTypeIdentifier = b"INFLUXWL";
+/// The segment format version written by this version of the WAL.
+///
+/// Version 3 added the [`SegmentFooter`] written when a segment is closed, version 4 bound
+/// encrypted entries to their segment and offset.
+pub const CURRENT_FORMAT_VERSION: u32 = 4;
+/// The first format version in which closed segments end with a [`SegmentFooter`].
+const FOOTER_FORMAT_VERSION: u32 = 3;
+/// The first format version in which the segment ID and offset of each encrypted entry are
+/// authenticated along with its data.
+const BOUND_ENTRIES_FORMAT_VERSION: u32 = 4;
+/// Stored in place of an entry's length, after a zero checksum, to mark the start of the footer.
+const FOOTER_MARKER: u32 = u32::MAX;
+/// Length of the footer, including its marker.
+const FOOTER_LEN: usize = 36;
+/// The oldest segment format version this version of the WAL can read. Segments in older
+/// formats must be migrated (see [`migrate()`]) by a WAL version that can read them.
+pub const MIN_SUPPORTED_FORMAT_VERSION: u32 = 1;
+/// Header flag set for segments whose entries are encrypted; the segment ID is then followed by
+/// the [`KeyId`] of the key used.
+const FLAG_ENCRYPTED: u32 = 1;
 /// File extension for segment files.
 const SEGMENT_FILE_EXTENSION: &str = "dat";
+/// How many written ops are buffered for each [`Wal::tail`] subscriber before it lags.
+const TAIL_BUFFER_SIZE: usize = 1_000;
+
+/// Options for a [`Wal`] opened with [`Wal::new_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct WalOptions {
+    key_provider: Option<Arc<dyn KeyProvider>>,
+    preallocate_bytes: Option<u64>,
+    max_total_bytes: Option<u64>,
+    faults: Option<Arc<fault::FaultInjector>>,
+}
+
+impl WalOptions {
+    /// Encrypt the segment files written with the current key of `key_provider`; see
+    /// [`Wal::new_encrypted`].
+    pub fn with_key_provider(mut self, key_provider: Arc<dyn KeyProvider>) -> Self {
+        self.key_provider = Some(key_provider);
+        self
+    }
+
+    /// Allocate the disk space for `bytes` of each segment file when it is opened, so that a full
+    /// disk is reported when rotating rather than part way through appending to a segment, and
+    /// appends within that space don't update the file size. A segment may grow past this size;
+    /// unused space is released when it is closed.
+    pub fn with_preallocation(mut self, bytes: u64) -> Self {
+        self.preallocate_bytes = Some(bytes);
+        self
+    }
+
+    /// Reject writes with [`Error::WalFull`] once the segment files of the WAL total `bytes`, so
+    /// the WAL cannot fill the disk. Space is freed by deleting closed segments, such as with
+    /// [`Wal::prune_up_to`].
+    ///
+    /// The limit is checked before each write using the size of the encoded op, so it may be
+    /// exceeded by up to the size of the ops being written concurrently.
+    pub fn with_max_total_bytes(mut self, bytes: u64) -> Self {
+        self.max_total_bytes = Some(bytes);
+        self
+    }
+
+    /// Inject the faults armed in `faults` into the writes and syncs of segment files.
+    #[cfg(any(test, feature = "test_utils"))]
+    pub fn with_fault_injector(mut self, faults: Arc<test_utils::FaultInjector>) -> Self {
+        self.faults = Some(faults);
+        self
+    }
+}
+
+/// Decides when the oldest closed segments of a [`Wal`] should be evicted to limit the disk space
+/// it uses; see [`Wal::evict_oldest`].
+pub trait EvictionPolicy: std::fmt::Debug + Send + Sync {
+    /// Whether a WAL whose segment files total `disk_usage_bytes` is under enough disk pressure
+    /// to evict segments.
+    fn under_pressure(&self, disk_usage_bytes: u64) -> bool;
+}
+
+/// An [`EvictionPolicy`] evicting segments while the WAL uses at least a fixed number of bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskUsageThreshold {
+    bytes: u64,
+}
+
+impl DiskUsageThreshold {
+    /// Evict segments while the segment files total at least `bytes`.
+    pub fn new(bytes: u64) -> Self {
+        Self { bytes }
+    }
+}
+
+impl EvictionPolicy for DiskUsageThreshold {
+    fn under_pressure(&self, disk_usage_bytes: u64) -> bool {
+        disk_usage_bytes >= self.bytes
+    }
+}
+
+/// The total size of the segment files of a [`Wal`], and its limit.
+#[derive(Debug)]
+struct DiskUsage {
+    bytes: AtomicU64,
+    max_bytes: Option<u64>,
+}
+
+impl DiskUsage {
+    fn new(bytes: u64, max_bytes: Option<u64>) -> Self {
+        Self {
+            bytes: AtomicU64::new(bytes),
+            max_bytes,
+        }
+    }
+
+    fn get(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Returns [`Error::WalFull`] if writing `additional` bytes would exceed the limit.
+    fn check(&self, additional: u64) -> Result<()> {
+        if let Some(max_bytes) = self.max_bytes {
+            let current_bytes = self.get();
+            ensure!(
+                current_bytes.saturating_add(additional) <= max_bytes,
+                WalFullSnafu {
+                    current_bytes,
+                    max_bytes
+                }
+            );
+        }
+        Ok(())
+    }
+
+    fn add(&self, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn sub(&self, bytes: u64) {
+        self.bytes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| {
+                Some(v.saturating_sub(bytes))
+            })
+            .ok();
+    }
+}
 
 /// The main type representing one WAL for one ingester instance.
 ///
@@ -180,8 +425,15 @@ pub struct Wal {
     closed_segments: RwLock<BTreeMap<SegmentId, ClosedSegment>>,
     open_segment: OpenSegmentFile,
     next_id_source: Arc<AtomicU64>,
+    key_provider: Option<Arc<dyn KeyProvider>>,
+    segment_indexes: SegmentIndexCache,
+    disk_usage: Arc<DiskUsage>,
 }
 
+/// The indexes of closed segments built by [`ClosedSegmentFileReader::seek_to`], shared by all
+/// readers of a [`Wal`] so each segment is indexed at most once.
+type SegmentIndexCache = Arc<Mutex<BTreeMap<SegmentId, Arc<SegmentIndex>>>>;
+
 impl Wal {
     /// Creates a `Wal` instance that manages files in the specified root directory.
     /// # Constraints
@@ -193,44 +445,42 @@ impl Wal {
     /// Similarly, editing or deleting files within a `Wal`'s root directory via some other
     /// mechanism is not supported.
     pub async fn new(root: impl Into<PathBuf>) -> Result<Self> {
+        Self::new_with_options(root, WalOptions::default()).await
+    }
+
+    /// Creates a `Wal` instance like [`Wal::new`] that encrypts the segment files it writes
+    /// with the current key of `key_provider`.
+    ///
+    /// Existing segments are read with the key recorded in their header, so `key_provider` must
+    /// still be able to supply the keys of all segments that have not been deleted. Plaintext
+    /// segments written before encryption was enabled remain readable.
+    pub async fn new_encrypted(
+        root: impl Into<PathBuf>,
+        key_provider: Arc<dyn KeyProvider>,
+    ) -> Result<Self> {
+        Self::new_with_options(root, WalOptions::default().with_key_provider(key_provider)).await
+    }
+
+    /// Creates a `Wal` instance like [`Wal::new`], configured by `options`.
+    pub async fn new_with_options(root: impl Into<PathBuf>, options: WalOptions) -> Result<Self> {
         let root = root.into();
+        let WalOptions {
+            key_provider,
+            preallocate_bytes,
+            max_total_bytes,
+            faults,
+        } = options;
+
         tokio::fs::create_dir_all(&root)
             .await
             .context(UnableToCreateWalDirSnafu { path: &root })?;
 
-        let mut dir = tokio::fs::read_dir(&root)
-            .await
-            .context(UnableToReadDirectoryContentsSnafu { path: &root })?;
-
-        // Closed segments must be ordered by ID, which is the order they were written in and the
-        // order they should be replayed in.
-        let mut closed_segments = BTreeMap::new();
+        let mut closed_segments = read_segments(&root).await?;
 
-        while let Some(child) = dir
-            .next_entry()
-            .await
-            .context(UnableToReadDirectoryContentsSnafu { path: &root })?
-        {
-            let metadata = child
-                .metadata()
-                .await
-                .context(UnableToReadFileMetadataSnafu)?;
-            if metadata.is_file() {
-                let child_path = child.path();
-                let filename = child_path
-                    .file_stem()
-                    .expect("WAL files created by IOx should have a file stem");
-                let filename = filename
-                    .to_str()
-                    .expect("WAL files created by IOx should be named with valid UTF-8");
-                let id = SegmentId::new(filename.parse().context(InvalidIdSnafu { filename })?);
-                let segment = ClosedSegment {
-                    id,
-                    path: child.path(),
-                    size: metadata.len(),
-                };
-                closed_segments.insert(id, segment);
-            }
+        // The newest segment was the open segment of the previous `Wal` on this directory, so its
+        // last entry may only have been partially written if that `Wal` crashed.
+        if let Some(segment) = closed_segments.values_mut().next_back() {
+            truncate_torn_tail(segment, key_provider.clone()).await?;
         }
 
         let next_id = closed_segments
@@ -240,22 +490,148 @@ impl Wal {
             .map(|id| id.get() + 1)
             .unwrap_or(0);
         let next_id_source = Arc::new(AtomicU64::new(next_id));
-        let open_segment =
-            OpenSegmentFile::new_in_directory(&root, Arc::clone(&next_id_source)).await?;
+        let disk_usage = Arc::new(DiskUsage::new(
+            closed_segments.values().map(|s| s.size).sum(),
+            max_total_bytes,
+        ));
+        let open_segment = OpenSegmentFile::new_in_directory(
+            &root,
+            Arc::clone(&next_id_source),
+            key_provider.clone(),
+            preallocate_bytes,
+            faults,
+            Arc::clone(&disk_usage),
+        )
+        .await?;
 
         Ok(Self {
             root,
             closed_segments: RwLock::new(closed_segments),
             open_segment,
             next_id_source,
+            key_provider,
+            segment_indexes: Default::default(),
+            disk_usage,
         })
     }
 
+    /// The total size in bytes of the segment files, which is limited by
+    /// [`WalOptions::with_max_total_bytes`].
+    pub fn disk_usage_bytes(&self) -> u64 {
+        self.disk_usage.get()
+    }
+
     /// Returns a handle to the WAL that enables commiting entries to the currently active segment.
     pub async fn write_handle(&self) -> WalWriter {
         self.open_segment.write_handle()
     }
 
+    /// Returns a stream of the [`SequencedWalOp`]s written to the WAL after this call, in the
+    /// order they were written to disk and continuing across segment rotations.
+    ///
+    /// Each op is yielded once it is durable. A subscriber that falls more than a fixed number
+    /// of ops behind the writers gets an [`Error::TailLagged`] reporting how many ops it missed,
+    /// after which the stream continues with the oldest op still buffered. The stream ends when
+    /// the `Wal` and all its [`WalWriter`]s are dropped.
+    pub fn tail(&self) -> BoxStream<'static, Result<SequencedWalOp>> {
+        let rx = self.open_segment.tail_tx.subscribe();
+        futures::stream::unfold(rx, |mut rx| async move {
+            match rx.recv().await {
+                Ok(op) => Some((Ok(op), rx)),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    Some((Err(Error::TailLagged { skipped }), rx))
+                }
+                Err(broadcast::error::RecvError::Closed) => None,
+            }
+        })
+        .boxed()
+    }
+
+    /// Deletes the closed segments whose entries all have a sequence number at or below
+    /// `watermark`, i.e. whose data has all been persisted, returning the IDs of the deleted
+    /// segments.
+    ///
+    /// Sequence numbers increase from one segment to the next, so segments are examined in order
+    /// and pruning stops at the first segment containing an entry above the watermark; that
+    /// segment and all later ones are kept whole. Finding the largest sequence number of a segment
+    /// requires reading it.
+    pub async fn prune_up_to(&self, watermark: SequenceNumber) -> Result<Vec<SegmentId>> {
+        // Nothing can be at or below a negative watermark
+        let watermark = match u64::try_from(watermark.get()) {
+            Ok(watermark) => watermark,
+            Err(_) => return Ok(vec![]),
+        };
+
+        let reader = self.read_handle();
+        let rotator = self.rotation_handle();
+        let mut pruned = vec![];
+
+        for segment in reader.closed_segments().await {
+            let mut segment_reader = reader.reader_for_segment(segment.id()).await?;
+            let mut persisted = true;
+            while let Some(op) = segment_reader.next_op().await? {
+                if op.sequence_number > watermark {
+                    persisted = false;
+                    break;
+                }
+            }
+
+            if !persisted {
+                break;
+            }
+
+            rotator.delete(segment.id()).await?;
+            pruned.push(segment.id());
+        }
+
+        Ok(pruned)
+    }
+
+    /// Checks every closed segment, reading all of its entries and comparing them and the bytes of
+    /// the file with the footer written when the segment was closed.
+    ///
+    /// Segments with problems are reported rather than returned as errors; errors are only
+    /// returned if a segment file could not be read at all.
+    pub async fn verify(&self) -> Result<Vec<SegmentVerification>> {
+        let segments = self.read_handle().closed_segments().await;
+        let key_provider = self.key_provider.clone();
+
+        tokio::task::spawn_blocking(move || {
+            segments
+                .iter()
+                .map(|segment| verify::verify_segment(segment, key_provider.clone()))
+                .collect()
+        })
+        .await
+        .expect("segment verification task panicked")
+    }
+
+    /// Deletes closed segments, oldest first, while `policy` reports the WAL to be under disk
+    /// pressure, returning the IDs of the deleted segments.
+    ///
+    /// Only segments up to and including `persisted_up_to` are deleted; the caller must have
+    /// confirmed that all of their contents are persisted, for example by persisting all buffered
+    /// data after rotating that segment.
+    pub async fn evict_oldest(
+        &self,
+        policy: &dyn EvictionPolicy,
+        persisted_up_to: SegmentId,
+    ) -> Result<Vec<SegmentId>> {
+        let rotator = self.rotation_handle();
+        let mut evicted = vec![];
+
+        for segment in self.read_handle().closed_segments().await {
+            if segment.id() > persisted_up_to || !policy.under_pressure(self.disk_usage_bytes()) {
+                break;
+            }
+
+            rotator.delete(segment.id()).await?;
+            evicted.push(segment.id());
+        }
+
+        Ok(evicted)
+    }
+
     /// Returns a handle to the WAL that enables listing and reading entries from closed segments.
     pub fn read_handle(&self) -> WalReader<'_> {
         WalReader(self)
@@ -268,21 +644,127 @@ impl Wal {
     }
 }
 
-/// Handle to the one currently open segment for users of the WAL to send [`SequencedWalOp`]s to.
-#[derive(Debug)]
-pub struct WalWriter(mpsc::Sender<OpenSegmentFileWriterRequest>);
+/// Reads the details of the segment files in `root`, ordered by ID, which is the order they were
+/// written in and the order they should be replayed in.
+async fn read_segments(root: &Path) -> Result<BTreeMap<SegmentId, ClosedSegment>> {
+    let mut dir = tokio::fs::read_dir(root)
+        .await
+        .context(UnableToReadDirectoryContentsSnafu { path: root })?;
 
-impl WalWriter {
-    async fn write(&self, data: &[u8]) -> Result<WriteSummary> {
-        OpenSegmentFile::one_command(&self.0, OpenSegmentFileWriterRequest::Write, data.to_vec())
+    let mut segments = BTreeMap::new();
+
+    while let Some(child) = dir
+        .next_entry()
+        .await
+        .context(UnableToReadDirectoryContentsSnafu { path: root })?
+    {
+        let metadata = child
+            .metadata()
             .await
+            .context(UnableToReadFileMetadataSnafu)?;
+        if metadata.is_file() {
+            let child_path = child.path();
+            // Other files (such as those written by the WAL's user) may be kept alongside the
+            // segment files.
+            if child_path.extension() != Some(SEGMENT_FILE_EXTENSION.as_ref()) {
+                continue;
+            }
+            let filename = child_path
+                .file_stem()
+                .expect("WAL files created by IOx should have a file stem");
+            let filename = filename
+                .to_str()
+                .expect("WAL files created by IOx should be named with valid UTF-8");
+            let id = SegmentId::new(filename.parse().context(InvalidIdSnafu { filename })?);
+            let segment = ClosedSegment {
+                id,
+                path: child.path(),
+                size: metadata.len(),
+            };
+            segments.insert(id, segment);
+        }
     }
 
+    Ok(segments)
+}
+
+/// Truncates a partially written last entry (see
+/// [`blocking::ClosedSegmentFileReader::find_torn_tail`]) off `segment`, updating its size, so
+/// that the rest of it can be replayed. The op in that entry is lost, but it was never
+/// acknowledged as written.
+async fn truncate_torn_tail(
+    segment: &mut ClosedSegment,
+    key_provider: Option<Arc<dyn KeyProvider>>,
+) -> Result<()> {
+    let path = segment.path.clone();
+    let torn = tokio::task::spawn_blocking(move || -> Result<Option<blocking::TornTail>> {
+        let mut reader = blocking::ClosedSegmentFileReader::from_path(&path)
+            .context(UnableToOpenFileSnafu { path: &path })?
+            .with_key_provider(key_provider);
+        // A segment with a damaged header is reported when it is replayed
+        if reader.read_header().is_err() {
+            return Ok(None);
+        }
+
+        let torn = match reader.find_torn_tail().context(UnableToReadEntriesSnafu)? {
+            Some(torn) => torn,
+            None => return Ok(None),
+        };
+
+        let f = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .context(TruncateTornTailSnafu { path: &path })?;
+        f.set_len(torn.offset)
+            .and_then(|_| f.sync_all())
+            .context(TruncateTornTailSnafu { path: &path })?;
+
+        Ok(Some(torn))
+    })
+    .await
+    .expect("torn tail truncation task panicked")?;
+
+    if let Some(torn) = torn {
+        warn!(
+            segment_id = %segment.id,
+            offset = torn.offset,
+            dropped_bytes = segment.size.saturating_sub(torn.offset),
+            previous_sequence_number = ?torn.previous_sequence_number,
+            "truncated partially written entry at the end of wal segment, dropping the op \
+             written after the previous sequence number"
+        );
+        segment.size = torn.offset;
+    }
+
+    Ok(())
+}
+
+/// Handle to the one currently open segment for users of the WAL to send [`SequencedWalOp`]s to.
+#[derive(Debug)]
+pub struct WalWriter {
+    tx: mpsc::Sender<OpenSegmentFileWriterRequest>,
+    tail_tx: broadcast::Sender<SequencedWalOp>,
+    disk_usage: Arc<DiskUsage>,
+}
+
+impl WalWriter {
     /// Writes one [`SequencedWalOp`] to disk and returns when it is durable.
+    ///
+    /// Returns [`Error::WalFull`] without writing the op if the WAL has reached its size limit
+    /// (see [`WalOptions::with_max_total_bytes`]).
     pub async fn write_op(&self, op: SequencedWalOp) -> Result<WriteSummary> {
+        // Only keep a copy of the op if someone is tailing the WAL
+        let tail_op = (self.tail_tx.receiver_count() > 0).then(|| op.clone());
+        let sequence_number = op.sequence_number;
         let proto = ProtoSequencedWalOp::from(op);
         let encoded = proto.encode_to_vec();
-        self.write(&encoded).await
+        self.disk_usage.check(encoded.len() as u64)?;
+        OpenSegmentFile::one_command(
+            &self.tx,
+            OpenSegmentFileWriterRequest::Write,
+            (encoded, sequence_number, tail_op),
+        )
+        .await?
     }
 }
 
@@ -305,7 +787,26 @@ impl<'a> WalReader<'a> {
     /// Opens a reader for a given segment from the WAL
     pub async fn reader_for_segment(&self, id: SegmentId) -> Result<ClosedSegmentFileReader> {
         let path = build_segment_path(&self.0.root, id);
-        ClosedSegmentFileReader::from_path(path).await
+        Ok(
+            ClosedSegmentFileReader::from_path(path, false, self.0.key_provider.clone())
+                .await?
+                .with_index_cache(Arc::clone(&self.0.segment_indexes)),
+        )
+    }
+
+    /// Opens a reader for a given segment from the WAL in recovery mode: entries that are
+    /// damaged (failing their checksum or not decodable) are skipped rather than returned as
+    /// errors, and can be retrieved with [`ClosedSegmentFileReader::take_corrupt_entries`].
+    pub async fn recovering_reader_for_segment(
+        &self,
+        id: SegmentId,
+    ) -> Result<ClosedSegmentFileReader> {
+        let path = build_segment_path(&self.0.root, id);
+        Ok(
+            ClosedSegmentFileReader::from_path(path, true, self.0.key_provider.clone())
+                .await?
+                .with_index_cache(Arc::clone(&self.0.segment_indexes)),
+        )
     }
 }
 
@@ -321,7 +822,7 @@ impl<'a> WalRotator<'a> {
             OpenSegmentFileWriterRequest::Rotate,
             (),
         )
-        .await?;
+        .await??;
         let previous_value = self
             .0
             .closed_segments
@@ -344,14 +845,43 @@ impl<'a> WalRotator<'a> {
             .await
             .remove(&id)
             .context(SegmentNotFoundSnafu { id })?;
+        self.0.disk_usage.sub(closed.size);
+        self.0
+            .segment_indexes
+            .lock()
+            .expect("segment index cache lock poisoned")
+            .remove(&id);
         std::fs::remove_file(&closed.path).context(DeleteClosedSegmentSnafu { path: closed.path })
     }
+
+    /// Uploads the specified segment with `archiver`, and deletes it from disk once the upload
+    /// has succeeded.
+    pub async fn archive_and_delete(
+        &self,
+        id: SegmentId,
+        archiver: &archive::SegmentArchiver,
+    ) -> Result<()> {
+        let segment = self
+            .0
+            .closed_segments
+            .read()
+            .await
+            .get(&id)
+            .cloned()
+            .context(SegmentNotFoundSnafu { id })?;
+        archiver.archive(&segment).await?;
+        self.delete(id).await
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct SequencedWalOp {
     pub sequence_number: u64,
     pub op: WalOp,
+    /// The tracing context of the request that wrote the op, if it was traced and recorded
+    pub trace_context: Option<TraceContext>,
+    /// The wall clock time the op was written, in nanoseconds since the epoch, if recorded
+    pub ingest_timestamp_ns: Option<i64>,
 }
 
 impl TryFrom<ProtoSequencedWalOp> for SequencedWalOp {
@@ -361,11 +891,15 @@ impl TryFrom<ProtoSequencedWalOp> for SequencedWalOp {
         let ProtoSequencedWalOp {
             sequence_number,
             op,
+            trace_context,
+            ingest_timestamp_ns,
         } = proto;
 
         Ok(Self {
             sequence_number,
             op: op.unwrap_field("op")?,
+            trace_context,
+            ingest_timestamp_ns,
         })
     }
 }
@@ -375,11 +909,15 @@ impl From<SequencedWalOp> for ProtoSequencedWalOp {
         let SequencedWalOp {
             sequence_number,
             op,
+            trace_context,
+            ingest_timestamp_ns,
         } = seq_op;
 
         Self {
             sequence_number,
             op: Some(op),
+            trace_context,
+            ingest_timestamp_ns,
         }
     }
 }
@@ -393,6 +931,31 @@ pub struct SegmentEntry {
     pub data: Vec<u8>,
 }
 
+/// The summary of its entries written at the end of a segment when it is closed, used to detect
+/// segments that were truncated or modified after being closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentFooter {
+    /// Number of entries in the segment
+    pub entry_count: u64,
+    /// Smallest sequence number of the entries, if there are any
+    pub min_sequence_number: Option<u64>,
+    /// Largest sequence number of the entries, if there are any
+    pub max_sequence_number: Option<u64>,
+    /// CRC32 checksum of all bytes of the segment before the footer
+    pub checksum: u32,
+}
+
+/// A damaged segment entry that was skipped when reading a segment in recovery mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptEntry {
+    /// Byte offset of the start of the entry within the segment file
+    pub offset: u64,
+    /// Sequence number of the last op read successfully before this entry, if any
+    pub previous_sequence_number: Option<u64>,
+    /// Why the entry could not be read
+    pub reason: String,
+}
+
 /// Summary information after a write
 #[derive(Debug, Copy, Clone)]
 pub struct WriteSummary {
@@ -408,8 +971,13 @@ pub struct WriteSummary {
 
 #[derive(Debug)]
 enum OpenSegmentFileWriterRequest {
-    Write(oneshot::Sender<WriteSummary>, Vec<u8>), // todo Bytes
-    Rotate(oneshot::Sender<ClosedSegment>, ()),
+    /// The encoded op to write, its sequence number, and the op itself to publish to
+    /// [`Wal::tail`] subscribers
+    Write(
+        oneshot::Sender<Result<WriteSummary>>,
+        (Vec<u8>, u64, Option<SequencedWalOp>),
+    ), // todo Bytes
+    Rotate(oneshot::Sender<Result<ClosedSegment>>, ()),
 }
 
 /// An open segment in a WAL.
@@ -417,53 +985,104 @@ enum OpenSegmentFileWriterRequest {
 struct OpenSegmentFile {
     tx: mpsc::Sender<OpenSegmentFileWriterRequest>,
     task: tokio::task::JoinHandle<Result<()>>,
+    tail_tx: broadcast::Sender<SequencedWalOp>,
+    disk_usage: Arc<DiskUsage>,
 }
 
 impl OpenSegmentFile {
     async fn new_in_directory(
         dir: impl Into<PathBuf>,
         next_id_source: Arc<AtomicU64>,
+        key_provider: Option<Arc<dyn KeyProvider>>,
+        preallocate_bytes: Option<u64>,
+        faults: Option<Arc<fault::FaultInjector>>,
+        disk_usage: Arc<DiskUsage>,
     ) -> Result<Self> {
         let dir = dir.into();
         let dir_for_closure = dir.clone();
         let (tx, rx) = mpsc::channel(10);
+        let (tail_tx, _) = broadcast::channel(TAIL_BUFFER_SIZE);
+        let task_tail_tx = tail_tx.clone();
+        let task_disk_usage = Arc::clone(&disk_usage);
         let task = tokio::task::spawn_blocking(move || {
-            Self::task_main(rx, dir_for_closure, next_id_source)
+            Self::task_main(
+                rx,
+                dir_for_closure,
+                next_id_source,
+                key_provider,
+                preallocate_bytes,
+                faults,
+                task_tail_tx,
+                task_disk_usage,
+            )
         });
         std::fs::File::open(&dir)
             .context(OpenSegmentDirectorySnafu { path: dir })?
             .sync_all()
             .expect("fsync failure");
-        Ok(Self { tx, task })
+        Ok(Self {
+            tx,
+            task,
+            tail_tx,
+            disk_usage,
+        })
     }
 
     fn task_main(
         mut rx: tokio::sync::mpsc::Receiver<OpenSegmentFileWriterRequest>,
         dir: PathBuf,
         next_id_source: Arc<AtomicU64>,
+        key_provider: Option<Arc<dyn KeyProvider>>,
+        preallocate_bytes: Option<u64>,
+        faults: Option<Arc<fault::FaultInjector>>,
+        tail_tx: broadcast::Sender<SequencedWalOp>,
+        disk_usage: Arc<DiskUsage>,
     ) -> Result<()> {
-        let new_writ =
-            || {
-                Ok(blocking::OpenSegmentFileWriter::new_in_directory(
-                    &dir,
-                    Arc::clone(&next_id_source),
-                )
-                .unwrap())
-            };
+        let new_writ = || {
+            let writer = blocking::OpenSegmentFileWriter::new_in_directory(
+                &dir,
+                Arc::clone(&next_id_source),
+                key_provider.as_deref(),
+                preallocate_bytes,
+            )
+            .context(UnableToCreateSegmentFileSnafu)?
+            .with_faults(faults.clone());
+            // The segment header
+            disk_usage.add(writer.bytes_written() as u64);
+            Ok(writer)
+        };
         let mut open_write = new_writ()?;
 
         while let Some(req) = rx.blocking_recv() {
             use OpenSegmentFileWriterRequest::*;
 
             match req {
-                Write(tx, data) => {
-                    let x = open_write.write(&data).unwrap();
-                    tx.send(x).unwrap();
+                Write(tx, (data, sequence_number, op)) => {
+                    let res = open_write
+                        .write(&data, sequence_number)
+                        .context(UnableToWriteEntrySnafu);
+                    if let Ok(x) = &res {
+                        disk_usage.add(x.bytes_written as u64);
+                        // Publish in the order ops are written; it doesn't matter if nobody
+                        // is tailing the WAL.
+                        if let Some(op) = op {
+                            tail_tx.send(op).ok();
+                        }
+                    }
+                    tx.send(res).unwrap();
                 }
 
                 Rotate(tx, ()) => {
-                    let old = std::mem::replace(&mut open_write, new_writ()?);
-                    let res = old.close().unwrap();
+                    // Keep appending to the open segment if a new one can't be created, e.g.
+                    // because there is no space to preallocate it.
+                    let res = new_writ().and_then(|new| {
+                        std::mem::replace(&mut open_write, new)
+                            .close()
+                            .context(UnableToCloseSegmentFileSnafu)
+                    });
+                    if res.is_ok() {
+                        disk_usage.add(FOOTER_LEN as u64);
+                    }
                     tx.send(res).unwrap();
                 }
             }
@@ -482,16 +1101,26 @@ impl OpenSegmentFile {
     {
         let (req_tx, req_rx) = oneshot::channel();
 
-        tx.send(req(req_tx, args)).await.unwrap();
-        Ok(req_rx.await.unwrap())
+        // The writer task only stops if it panicked, such as on a failed fsync
+        tx.send(req(req_tx, args))
+            .await
+            .ok()
+            .context(UnableToSendRequestToWriterTaskSnafu)?;
+        req_rx
+            .await
+            .context(UnableToReceiveResponseFromSenderTaskSnafu)
     }
 
     fn write_handle(&self) -> WalWriter {
-        WalWriter(self.tx.clone())
+        WalWriter {
+            tx: self.tx.clone(),
+            tail_tx: self.tail_tx.clone(),
+            disk_usage: Arc::clone(&self.disk_usage),
+        }
     }
 
     async fn rotate(&self) -> Result<ClosedSegment> {
-        Self::one_command(&self.tx, OpenSegmentFileWriterRequest::Rotate, ()).await
+        Self::one_command(&self.tx, OpenSegmentFileWriterRequest::Rotate, ()).await?
     }
 }
 
@@ -500,6 +1129,14 @@ enum ClosedSegmentFileReaderRequest {
     ReadHeader(oneshot::Sender<blocking::ReaderResult<(FileTypeIdentifier, SegmentIdBytes)>>),
 
     NextOps(oneshot::Sender<blocking::ReaderResult<Option<SequencedWalOp>>>),
+
+    TakeCorruptEntries(oneshot::Sender<Vec<CorruptEntry>>),
+
+    SeekTo {
+        sequence_number: u64,
+        index: Option<Arc<SegmentIndex>>,
+        tx: oneshot::Sender<blocking::ReaderResult<Arc<SegmentIndex>>>,
+    },
 }
 
 /// Enables reading a particular closed segment's entries.
@@ -508,35 +1145,59 @@ pub struct ClosedSegmentFileReader {
     id: SegmentId,
     tx: mpsc::Sender<ClosedSegmentFileReaderRequest>,
     task: tokio::task::JoinHandle<Result<()>>,
+    index_cache: Option<SegmentIndexCache>,
 }
 
 impl ClosedSegmentFileReader {
-    async fn from_path(path: impl Into<PathBuf>) -> Result<Self> {
+    async fn from_path(
+        path: impl Into<PathBuf>,
+        recovery: bool,
+        key_provider: Option<Arc<dyn KeyProvider>>,
+    ) -> Result<Self> {
         let path = path.into();
 
         let (tx, rx) = mpsc::channel::<ClosedSegmentFileReaderRequest>(10);
-        let task = tokio::task::spawn_blocking(|| Self::task_main(rx, path));
+        let task =
+            tokio::task::spawn_blocking(move || Self::task_main(rx, path, recovery, key_provider));
 
         let (file_type, id) = Self::one_command(&tx, ClosedSegmentFileReaderRequest::ReadHeader)
             .await?
             .context(UnableToReadFileHeaderSnafu)?;
 
         ensure!(
-            &file_type == FILE_TYPE_IDENTIFIER,
+            &file_type == FILE_TYPE_IDENTIFIER
+                || &file_type == VERSIONED_FILE_TYPE_IDENTIFIER
+                || &file_type == encryption::ENCRYPTED_FILE_TYPE_IDENTIFIER,
             SegmentFileIdentifierMismatchSnafu,
         );
 
         let id = SegmentId::from_bytes(id);
 
-        Ok(Self { id, tx, task })
+        Ok(Self {
+            id,
+            tx,
+            task,
+            index_cache: None,
+        })
+    }
+
+    /// Shares the segment indexes built by [`Self::seek_to`] through `index_cache`.
+    fn with_index_cache(mut self, index_cache: SegmentIndexCache) -> Self {
+        self.index_cache = Some(index_cache);
+        self
     }
 
     fn task_main(
         mut rx: mpsc::Receiver<ClosedSegmentFileReaderRequest>,
         path: PathBuf,
+        recovery: bool,
+        key_provider: Option<Arc<dyn KeyProvider>>,
     ) -> Result<()> {
         let mut reader = blocking::ClosedSegmentFileReader::from_path(&path)
-            .context(UnableToOpenFileSnafu { path })?;
+            .context(UnableToOpenFileSnafu { path })?
+            .with_recovery(recovery)
+            .with_key_provider(key_provider);
+        let mut own_index = None;
 
         while let Some(req) = rx.blocking_recv() {
             use ClosedSegmentFileReaderRequest::*;
@@ -550,6 +1211,27 @@ impl ClosedSegmentFileReader {
                 NextOps(tx) => {
                     tx.send(reader.next_ops()).ok();
                 }
+
+                TakeCorruptEntries(tx) => {
+                    tx.send(reader.take_corrupt_entries()).ok();
+                }
+
+                SeekTo {
+                    sequence_number,
+                    index,
+                    tx,
+                } => {
+                    let index = match index.or_else(|| own_index.clone()) {
+                        Some(index) => Ok(index),
+                        None => reader.build_index().map(Arc::new),
+                    };
+                    let result = index.and_then(|index| {
+                        reader.seek(index.offset_for(sequence_number))?;
+                        own_index = Some(Arc::clone(&index));
+                        Ok(index)
+                    });
+                    tx.send(result).ok();
+                }
             };
         }
 
@@ -579,6 +1261,49 @@ impl ClosedSegmentFileReader {
             .await?
             .context(UnableToReadNextOpsSnafu)
     }
+
+    /// Moves the reader to the first entry with a sequence number at or above `sequence_number`,
+    /// so that [`Self::next_op`] continues from there.
+    ///
+    /// All entries before that one have a lower sequence number. Sequence numbers need not
+    /// increase within a segment, so entries after it may still be lower.
+    ///
+    /// The first seek indexes the segment by reading it once; the index is kept in memory, and
+    /// shared with the other readers of the segment if this reader was opened through a
+    /// [`WalReader`].
+    pub async fn seek_to(&mut self, sequence_number: u64) -> Result<()> {
+        let cached = self.index_cache.as_ref().and_then(|cache| {
+            cache
+                .lock()
+                .expect("segment index cache lock poisoned")
+                .get(&self.id)
+                .cloned()
+        });
+
+        let index = Self::one_command(&self.tx, |tx| ClosedSegmentFileReaderRequest::SeekTo {
+            sequence_number,
+            index: cached,
+            tx,
+        })
+        .await?
+        .context(UnableToSeekSnafu)?;
+
+        if let Some(cache) = &self.index_cache {
+            cache
+                .lock()
+                .expect("segment index cache lock poisoned")
+                .entry(self.id)
+                .or_insert(index);
+        }
+
+        Ok(())
+    }
+
+    /// Return the damaged entries skipped by [`Self::next_op`] since the last call, if this
+    /// reader is in recovery mode (see [`WalReader::recovering_reader_for_segment`]).
+    pub async fn take_corrupt_entries(&mut self) -> Result<Vec<CorruptEntry>> {
+        Self::one_command(&self.tx, ClosedSegmentFileReaderRequest::TakeCorruptEntries).await
+    }
 }
 
 /// Metadata for a WAL segment that is no longer accepting writes, but can be read for replay
@@ -598,6 +1323,10 @@ impl ClosedSegment {
     pub fn size(&self) -> u64 {
         self.size
     }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
 }
 
 #[cfg(test)]
@@ -615,9 +1344,16 @@ mod tests {
     async fn segment_file_write_and_read_ops() {
         let dir = test_helpers::tmp_dir().unwrap();
         let next_id_source = Arc::new(AtomicU64::new(0));
-        let segment = OpenSegmentFile::new_in_directory(dir.path(), next_id_source)
-            .await
-            .unwrap();
+        let segment = OpenSegmentFile::new_in_directory(
+            dir.path(),
+            next_id_source,
+            None,
+            None,
+            None,
+            Arc::new(DiskUsage::new(0, None)),
+        )
+        .await
+        .unwrap();
         let writer = segment.write_handle();
 
         let w1 = test_data("m1,t=foo v=1i 1");
@@ -625,18 +1361,26 @@ mod tests {
 
         let op1 = SequencedWalOp {
             sequence_number: 0,
+            trace_context: None,
+            ingest_timestamp_ns: None,
             op: WalOp::Write(w1),
         };
         let op2 = SequencedWalOp {
             sequence_number: 1,
+            trace_context: None,
+            ingest_timestamp_ns: None,
             op: WalOp::Write(w2),
         };
         let op3 = SequencedWalOp {
             sequence_number: 2,
+            trace_context: None,
+            ingest_timestamp_ns: None,
             op: WalOp::Delete(test_delete()),
         };
         let op4 = SequencedWalOp {
             sequence_number: 2,
+            trace_context: None,
+            ingest_timestamp_ns: None,
             op: WalOp::Persist(test_persist()),
         };
 
@@ -647,7 +1391,7 @@ mod tests {
 
         let closed = segment.rotate().await.unwrap();
 
-        let mut reader = ClosedSegmentFileReader::from_path(&closed.path)
+        let mut reader = ClosedSegmentFileReader::from_path(&closed.path, false, None)
             .await
             .unwrap();
         let read_op1 = reader.next_op().await.unwrap().unwrap();
@@ -687,7 +1431,8 @@ mod tests {
         // No writes, but rotating is totally fine
         let wal_rotator = wal.rotation_handle();
         let closed_segment_details = wal_rotator.rotate().await.unwrap();
-        assert_eq!(closed_segment_details.size(), 16);
+        // The header and the footer
+        assert_eq!(closed_segment_details.size(), 24 + FOOTER_LEN as u64);
 
         // There's one closed segment
         let closed = wal_reader.closed_segments().await;
@@ -714,6 +1459,487 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn ignores_other_files() {
+        let dir = test_helpers::tmp_dir().unwrap();
+        std::fs::write(dir.path().join("bananas.checkpoint"), "42").unwrap();
+
+        let wal = Wal::new(dir.path()).await.unwrap();
+        let closed = wal.read_handle().closed_segments().await;
+        assert!(
+            closed.is_empty(),
+            "Expected empty closed segments; got {:?}",
+            closed
+        );
+    }
+
+    #[tokio::test]
+    async fn prune_up_to_watermark() {
+        let dir = test_helpers::tmp_dir().unwrap();
+        let wal = Wal::new(dir.path()).await.unwrap();
+        let writer = wal.write_handle().await;
+        let rotator = wal.rotation_handle();
+
+        let op = |sequence_number| SequencedWalOp {
+            sequence_number,
+            trace_context: None,
+            ingest_timestamp_ns: None,
+            op: WalOp::Persist(test_persist()),
+        };
+
+        writer.write_op(op(1)).await.unwrap();
+        writer.write_op(op(2)).await.unwrap();
+        let first = rotator.rotate().await.unwrap();
+        writer.write_op(op(3)).await.unwrap();
+        writer.write_op(op(4)).await.unwrap();
+        let second = rotator.rotate().await.unwrap();
+        // Written to the open segment, which is never pruned
+        writer.write_op(op(5)).await.unwrap();
+
+        // Negative watermarks prune nothing
+        assert!(wal
+            .prune_up_to(SequenceNumber::new(-1))
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Only part of the second segment is persisted
+        let pruned = wal.prune_up_to(SequenceNumber::new(3)).await.unwrap();
+        assert_eq!(pruned, [first.id()]);
+        assert!(!first.path.exists());
+
+        let pruned = wal.prune_up_to(SequenceNumber::new(5)).await.unwrap();
+        assert_eq!(pruned, [second.id()]);
+        assert!(wal.read_handle().closed_segments().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn op_metadata_round_trips() {
+        let dir = test_helpers::tmp_dir().unwrap();
+        let wal = Wal::new(dir.path()).await.unwrap();
+        let writer = wal.write_handle().await;
+
+        let traced = SequencedWalOp {
+            sequence_number: 1,
+            trace_context: Some(TraceContext {
+                trace_id_high: 1,
+                trace_id_low: 2,
+                span_id: 3,
+                parent_span_id: 0,
+                sampled: true,
+            }),
+            ingest_timestamp_ns: Some(1_670_000_000_000_000_000),
+            op: WalOp::Persist(test_persist()),
+        };
+        let untraced = SequencedWalOp {
+            sequence_number: 2,
+            trace_context: None,
+            ingest_timestamp_ns: None,
+            op: WalOp::Persist(test_persist()),
+        };
+        writer.write_op(traced.clone()).await.unwrap();
+        writer.write_op(untraced.clone()).await.unwrap();
+        let closed = wal.rotation_handle().rotate().await.unwrap();
+
+        let mut reader = wal
+            .read_handle()
+            .reader_for_segment(closed.id())
+            .await
+            .unwrap();
+        assert_eq!(reader.next_op().await.unwrap(), Some(traced));
+        assert_eq!(reader.next_op().await.unwrap(), Some(untraced));
+    }
+
+    #[tokio::test]
+    async fn max_total_bytes() {
+        let dir = test_helpers::tmp_dir().unwrap();
+        let op = |sequence_number| SequencedWalOp {
+            sequence_number,
+            trace_context: None,
+            ingest_timestamp_ns: None,
+            op: WalOp::Persist(test_persist()),
+        };
+
+        let wal =
+            Wal::new_with_options(dir.path(), WalOptions::default().with_max_total_bytes(100))
+                .await
+                .unwrap();
+        let writer = wal.write_handle().await;
+
+        // Write until the WAL is full
+        let mut written = 0;
+        let err = loop {
+            match writer.write_op(op(written)).await {
+                Ok(_) => written += 1,
+                Err(e) => break e,
+            }
+        };
+        assert!(
+            matches!(err, Error::WalFull { max_bytes: 100, .. }),
+            "{err}"
+        );
+        assert!(written > 0);
+
+        let size_on_disk = |dir: &Path| -> u64 {
+            std::fs::read_dir(dir)
+                .unwrap()
+                .map(|e| e.unwrap().metadata().unwrap().len())
+                .sum()
+        };
+        assert_eq!(wal.disk_usage_bytes(), size_on_disk(dir.path()));
+
+        // Deleting the segment frees up space
+        let closed = wal.rotation_handle().rotate().await.unwrap();
+        wal.rotation_handle().delete(closed.id()).await.unwrap();
+        assert_eq!(wal.disk_usage_bytes(), size_on_disk(dir.path()));
+        writer.write_op(op(written)).await.unwrap();
+
+        // The size of existing segments is counted when the WAL is reopened
+        drop(writer);
+        drop(wal);
+        let wal = Wal::new(dir.path()).await.unwrap();
+        wal.write_handle().await.write_op(op(0)).await.unwrap();
+        assert_eq!(wal.disk_usage_bytes(), size_on_disk(dir.path()));
+    }
+
+    #[tokio::test]
+    async fn torn_tail_is_truncated() {
+        let dir = test_helpers::tmp_dir().unwrap();
+        let op = |sequence_number| SequencedWalOp {
+            sequence_number,
+            trace_context: None,
+            ingest_timestamp_ns: None,
+            op: WalOp::Persist(test_persist()),
+        };
+
+        let (first, second) = {
+            let wal = Wal::new(dir.path()).await.unwrap();
+            let writer = wal.write_handle().await;
+            let first = writer.write_op(op(1)).await.unwrap();
+            let second = writer.write_op(op(2)).await.unwrap();
+            (first, second)
+        };
+
+        // Crash part way through writing the second op
+        let path = build_segment_path(dir.path(), second.segment_id);
+        let f = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        f.set_len(second.total_bytes as u64 - 5).unwrap();
+
+        let wal = Wal::new(dir.path()).await.unwrap();
+        let reader = wal.read_handle();
+        let segments = reader.closed_segments().await;
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].size(), first.total_bytes as u64);
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len(),
+            first.total_bytes as u64
+        );
+
+        let mut segment_reader = reader.reader_for_segment(segments[0].id()).await.unwrap();
+        assert_eq!(segment_reader.next_op().await.unwrap(), Some(op(1)));
+        assert_eq!(segment_reader.next_op().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn torn_tail_in_preallocated_space_is_truncated() {
+        let dir = test_helpers::tmp_dir().unwrap();
+        let op = |sequence_number| SequencedWalOp {
+            sequence_number,
+            trace_context: None,
+            ingest_timestamp_ns: None,
+            op: WalOp::Persist(test_persist()),
+        };
+
+        let (first, second) = {
+            let wal =
+                Wal::new_with_options(dir.path(), WalOptions::default().with_preallocation(4096))
+                    .await
+                    .unwrap();
+            let writer = wal.write_handle().await;
+            let first = writer.write_op(op(1)).await.unwrap();
+            let second = writer.write_op(op(2)).await.unwrap();
+            (first, second)
+        };
+
+        // The end of the second op never reached the disk
+        let path = build_segment_path(dir.path(), second.segment_id);
+        let mut data = std::fs::read(&path).unwrap();
+        data[second.total_bytes - 5..second.total_bytes].fill(0);
+        std::fs::write(&path, data).unwrap();
+
+        let wal = Wal::new(dir.path()).await.unwrap();
+        let reader = wal.read_handle();
+        let segments = reader.closed_segments().await;
+        assert_eq!(segments[0].size(), first.total_bytes as u64);
+
+        let mut segment_reader = reader.reader_for_segment(segments[0].id()).await.unwrap();
+        assert_eq!(segment_reader.next_op().await.unwrap(), Some(op(1)));
+        assert_eq!(segment_reader.next_op().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn evict_oldest() {
+        let dir = test_helpers::tmp_dir().unwrap();
+        let wal = Wal::new(dir.path()).await.unwrap();
+        let writer = wal.write_handle().await;
+        let rotator = wal.rotation_handle();
+
+        let op = |sequence_number| SequencedWalOp {
+            sequence_number,
+            trace_context: None,
+            ingest_timestamp_ns: None,
+            op: WalOp::Persist(test_persist()),
+        };
+
+        let mut closed = vec![];
+        for sequence_number in 0..3 {
+            writer.write_op(op(sequence_number)).await.unwrap();
+            closed.push(rotator.rotate().await.unwrap());
+        }
+
+        // Nothing is evicted without disk pressure
+        let policy = DiskUsageThreshold::new(wal.disk_usage_bytes() + 1);
+        assert!(wal
+            .evict_oldest(&policy, closed[2].id())
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Segments are evicted oldest first until the pressure is relieved
+        let policy = DiskUsageThreshold::new(wal.disk_usage_bytes() - closed[0].size());
+        let evicted = wal.evict_oldest(&policy, closed[2].id()).await.unwrap();
+        assert_eq!(evicted, [closed[0].id(), closed[1].id()]);
+
+        // Segments that are not known to be persisted are kept
+        let policy = DiskUsageThreshold::new(0);
+        assert!(wal
+            .evict_oldest(&policy, closed[1].id())
+            .await
+            .unwrap()
+            .is_empty());
+        let evicted = wal.evict_oldest(&policy, closed[2].id()).await.unwrap();
+        assert_eq!(evicted, [closed[2].id()]);
+    }
+
+    #[tokio::test]
+    async fn verify_segments() {
+        let dir = test_helpers::tmp_dir().unwrap();
+        let op = |sequence_number| SequencedWalOp {
+            sequence_number,
+            trace_context: None,
+            ingest_timestamp_ns: None,
+            op: WalOp::Persist(test_persist()),
+        };
+
+        let wal = Wal::new(dir.path()).await.unwrap();
+        let writer = wal.write_handle().await;
+        writer.write_op(op(1)).await.unwrap();
+        writer.write_op(op(2)).await.unwrap();
+        let first = wal.rotation_handle().rotate().await.unwrap();
+        writer.write_op(op(3)).await.unwrap();
+        let second = wal.rotation_handle().rotate().await.unwrap();
+
+        let verified = wal.verify().await.unwrap();
+        assert_eq!(verified.len(), 2);
+        assert!(verified.iter().all(|v| v.is_ok()), "{verified:?}");
+        assert_eq!(
+            verified[0].footer,
+            Some(SegmentFooter {
+                entry_count: 2,
+                min_sequence_number: Some(1),
+                max_sequence_number: Some(2),
+                checksum: verified[0].footer.unwrap().checksum,
+            })
+        );
+
+        // Flip a byte inside the first entry of the first segment, which the entry checksum and
+        // the footer both catch
+        let mut data = std::fs::read(&first.path).unwrap();
+        data[40] ^= 0xff;
+        std::fs::write(&first.path, data).unwrap();
+
+        // Cut the footer off the second segment
+        let data = std::fs::read(&second.path).unwrap();
+        std::fs::write(&second.path, &data[..data.len() - FOOTER_LEN]).unwrap();
+
+        let verified = wal.verify().await.unwrap();
+        assert!(
+            verified[0]
+                .problems
+                .iter()
+                .any(|p| matches!(p, SegmentProblem::ChecksumMismatch { .. })),
+            "{:?}",
+            verified[0].problems
+        );
+        assert!(verified[0]
+            .problems
+            .iter()
+            .any(|p| matches!(p, SegmentProblem::CorruptEntry(_))));
+        assert_eq!(verified[1].footer, None);
+        assert_eq!(verified[1].problems, [SegmentProblem::MissingFooter]);
+    }
+
+    #[tokio::test]
+    async fn preallocated_segments() {
+        let dir = test_helpers::tmp_dir().unwrap();
+        let op = |sequence_number| SequencedWalOp {
+            sequence_number,
+            trace_context: None,
+            ingest_timestamp_ns: None,
+            op: WalOp::Persist(test_persist()),
+        };
+
+        {
+            let wal =
+                Wal::new_with_options(dir.path(), WalOptions::default().with_preallocation(4096))
+                    .await
+                    .unwrap();
+            let writer = wal.write_handle().await;
+            let summary = writer.write_op(op(1)).await.unwrap();
+
+            let path = build_segment_path(dir.path(), summary.segment_id);
+            assert_eq!(std::fs::metadata(&path).unwrap().len(), 4096);
+
+            // Closing the segment writes the footer and releases the unused space
+            let closed = wal.rotation_handle().rotate().await.unwrap();
+            assert_eq!(closed.size(), (summary.total_bytes + FOOTER_LEN) as u64);
+            assert_eq!(std::fs::metadata(&path).unwrap().len(), closed.size());
+
+            // The new open segment is preallocated, and is left that way as if the ingester
+            // crashed
+            writer.write_op(op(2)).await.unwrap();
+        }
+
+        let wal = Wal::new(dir.path()).await.unwrap();
+        let reader = wal.read_handle();
+        let segments = reader.closed_segments().await;
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[1].size(), 4096);
+
+        let mut segment_reader = reader.reader_for_segment(segments[1].id()).await.unwrap();
+        assert_eq!(segment_reader.next_op().await.unwrap(), Some(op(2)));
+        assert_eq!(segment_reader.next_op().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn seek_to_sequence_number() {
+        let dir = test_helpers::tmp_dir().unwrap();
+        let wal = Wal::new(dir.path()).await.unwrap();
+        let writer = wal.write_handle().await;
+
+        let op = |sequence_number| SequencedWalOp {
+            sequence_number,
+            trace_context: None,
+            ingest_timestamp_ns: None,
+            op: WalOp::Persist(test_persist()),
+        };
+
+        for sequence_number in [1, 2, 3, 4] {
+            writer.write_op(op(sequence_number)).await.unwrap();
+        }
+        let closed = wal.rotation_handle().rotate().await.unwrap();
+
+        let reader = wal.read_handle();
+        let mut segment_reader = reader.reader_for_segment(closed.id()).await.unwrap();
+        segment_reader.seek_to(3).await.unwrap();
+        assert_eq!(segment_reader.next_op().await.unwrap(), Some(op(3)));
+
+        // Seeking backwards works too
+        segment_reader.seek_to(2).await.unwrap();
+        assert_eq!(segment_reader.next_op().await.unwrap(), Some(op(2)));
+
+        // The index is shared with later readers of the segment
+        assert!(wal
+            .segment_indexes
+            .lock()
+            .unwrap()
+            .contains_key(&closed.id()));
+        let mut segment_reader = reader.reader_for_segment(closed.id()).await.unwrap();
+        segment_reader.seek_to(4).await.unwrap();
+        assert_eq!(segment_reader.next_op().await.unwrap(), Some(op(4)));
+        assert_eq!(segment_reader.next_op().await.unwrap(), None);
+
+        segment_reader.seek_to(5).await.unwrap();
+        assert_eq!(segment_reader.next_op().await.unwrap(), None);
+
+        // and dropped with it
+        wal.rotation_handle().delete(closed.id()).await.unwrap();
+        assert!(wal.segment_indexes.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn encrypted_segments_survive_key_rotation() {
+        let dir = test_helpers::tmp_dir().unwrap();
+        let key_1 = EncryptionKey::new([1; 32]);
+        let key_2 = EncryptionKey::new([2; 32]);
+
+        let op1 = SequencedWalOp {
+            sequence_number: 0,
+            trace_context: None,
+            ingest_timestamp_ns: None,
+            op: WalOp::Write(test_data("m1,t=foo v=1i 1")),
+        };
+        let op2 = SequencedWalOp {
+            sequence_number: 1,
+            trace_context: None,
+            ingest_timestamp_ns: None,
+            op: WalOp::Write(test_data("m1,t=foo v=2i 2")),
+        };
+
+        // Write a segment encrypted with the first key
+        let wal = Wal::new_encrypted(
+            dir.path(),
+            Arc::new(StaticKeyProvider::new(KeyId::new(1), key_1.clone())),
+        )
+        .await
+        .unwrap();
+        wal.write_handle()
+            .await
+            .write_op(op1.clone())
+            .await
+            .unwrap();
+        let first = wal.rotation_handle().rotate().await.unwrap();
+        drop(wal);
+
+        // The segment does not contain the plaintext tag value
+        let contents = std::fs::read(&first.path).unwrap();
+        assert_eq!(&contents[..8], VERSIONED_FILE_TYPE_IDENTIFIER);
+        assert_eq!(contents[12..16], FLAG_ENCRYPTED.to_be_bytes());
+        assert!(!contents.windows(3).any(|w| w == b"foo"));
+
+        // Rotate to the second key, keeping the first to read old segments
+        let key_provider =
+            StaticKeyProvider::new(KeyId::new(2), key_2).with_previous_key(KeyId::new(1), key_1);
+        let wal = Wal::new_encrypted(dir.path(), Arc::new(key_provider))
+            .await
+            .unwrap();
+        wal.write_handle()
+            .await
+            .write_op(op2.clone())
+            .await
+            .unwrap();
+        let second = wal.rotation_handle().rotate().await.unwrap();
+
+        let wal_reader = wal.read_handle();
+        let mut reader = wal_reader.reader_for_segment(first.id()).await.unwrap();
+        assert_eq!(reader.next_op().await.unwrap().unwrap(), op1);
+        assert!(reader.next_op().await.unwrap().is_none());
+
+        let mut reader = wal_reader.reader_for_segment(second.id()).await.unwrap();
+        assert_eq!(reader.next_op().await.unwrap().unwrap(), op2);
+        assert!(reader.next_op().await.unwrap().is_none());
+        drop(wal);
+
+        // Encrypted segments can't be read without the keys
+        let wal = Wal::new(dir.path()).await.unwrap();
+        let err = wal
+            .read_handle()
+            .reader_for_segment(second.id())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::UnableToReadFileHeader { .. }), "{err}");
+    }
+
     fn test_data(lp: &str) -> DatabaseBatch {
         let batches = lines_to_batches(lp, 0).unwrap();
         let batches = batches
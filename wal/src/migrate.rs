@@ -0,0 +1,168 @@
+//! Rewrites segment files written in an older format version in the current format version.
+
+use generated_types::influxdata::iox::wal::v1::SequencedWalOp as ProtoSequencedWalOp;
+use prost::Message;
+use snafu::prelude::*;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    blocking, build_segment_path, read_segments, ClosedSegment, KeyProvider,
+    MigrateReplaceSegmentSnafu, MigrateWriteSegmentSnafu, Result,
+    SegmentFileIdentifierMismatchSnafu, SegmentId, UnableToOpenFileSnafu, UnableToReadEntriesSnafu,
+    UnableToReadFileHeaderSnafu, CURRENT_FORMAT_VERSION,
+};
+
+/// Subdirectory of the WAL directory that segments are rewritten into before replacing the
+/// originals. Directories are ignored when opening a WAL, so a migration interrupted part way
+/// through leaves the WAL readable.
+const STAGING_DIR: &str = "migrating";
+
+/// Rewrites every segment file in the WAL directory `dir` that is in a format version older than
+/// [`CURRENT_FORMAT_VERSION`] in the current format version, returning the IDs of the rewritten
+/// segments. Segments already in the current format are left untouched.
+///
+/// Rewritten segments are encrypted with the current key of `key_provider`, if given; it must
+/// also be able to supply the keys of any encrypted segments being migrated.
+///
+/// A [`Wal`](crate::Wal) must not be open on `dir` while it is migrated.
+pub async fn migrate(
+    dir: impl Into<PathBuf>,
+    key_provider: Option<Arc<dyn KeyProvider>>,
+) -> Result<Vec<SegmentId>> {
+    let dir = dir.into();
+    let segments = read_segments(&dir).await?;
+
+    tokio::task::spawn_blocking(move || migrate_segments(&dir, segments, key_provider))
+        .await
+        .expect("segment migration task panicked")
+}
+
+fn migrate_segments(
+    dir: &Path,
+    segments: BTreeMap<SegmentId, ClosedSegment>,
+    key_provider: Option<Arc<dyn KeyProvider>>,
+) -> Result<Vec<SegmentId>> {
+    let staging_dir = dir.join(STAGING_DIR);
+    std::fs::create_dir_all(&staging_dir)
+        .context(MigrateReplaceSegmentSnafu { path: &staging_dir })?;
+
+    let mut migrated = vec![];
+    for segment in segments.values() {
+        let mut reader = blocking::ClosedSegmentFileReader::from_path(&segment.path)
+            .context(UnableToOpenFileSnafu {
+                path: &segment.path,
+            })?
+            .with_key_provider(key_provider.clone());
+        reader.read_header().context(UnableToReadFileHeaderSnafu)?;
+
+        match reader.format_version() {
+            Some(CURRENT_FORMAT_VERSION) => continue,
+            Some(_) => {}
+            None => return SegmentFileIdentifierMismatchSnafu.fail(),
+        }
+
+        let staged_path = build_segment_path(&staging_dir, segment.id);
+        let mut writer = blocking::OpenSegmentFileWriter::new_at_path(
+            staged_path.clone(),
+            segment.id,
+            key_provider.as_deref(),
+        )
+        .context(MigrateWriteSegmentSnafu { id: segment.id })?;
+
+        while let Some(op) = reader.next_ops().context(UnableToReadEntriesSnafu)? {
+            let sequence_number = op.sequence_number;
+            writer
+                .write(
+                    &ProtoSequencedWalOp::from(op).encode_to_vec(),
+                    sequence_number,
+                )
+                .context(MigrateWriteSegmentSnafu { id: segment.id })?;
+        }
+        writer
+            .close()
+            .context(MigrateWriteSegmentSnafu { id: segment.id })?;
+
+        std::fs::rename(&staged_path, &segment.path).context(MigrateReplaceSegmentSnafu {
+            path: &segment.path,
+        })?;
+        migrated.push(segment.id);
+    }
+
+    std::fs::remove_dir(&staging_dir).context(MigrateReplaceSegmentSnafu { path: &staging_dir })?;
+
+    Ok(migrated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SequencedWalOp, Wal, FILE_TYPE_IDENTIFIER, VERSIONED_FILE_TYPE_IDENTIFIER};
+    use byteorder::{BigEndian, WriteBytesExt};
+    use crc32fast::Hasher;
+    use generated_types::influxdata::iox::wal::v1::{sequenced_wal_op::Op as WalOp, PersistOp};
+    use std::io::Write;
+
+    #[tokio::test]
+    async fn migrate_version_1_segment() {
+        let dir = test_helpers::tmp_dir().unwrap();
+
+        // Write a version 1 segment by hand
+        let op = SequencedWalOp {
+            sequence_number: 7,
+            trace_context: None,
+            ingest_timestamp_ns: None,
+            op: WalOp::Persist(PersistOp {
+                namespace_id: 42,
+                parquet_file_uuid: "b4N4N4Z".into(),
+                partition_id: 43,
+                table_id: 44,
+            }),
+        };
+        let mut encoder = snap::write::FrameEncoder::new(Vec::new());
+        encoder
+            .write_all(&ProtoSequencedWalOp::from(op.clone()).encode_to_vec())
+            .unwrap();
+        let compressed = encoder.into_inner().unwrap();
+        let mut hasher = Hasher::new();
+        hasher.update(&compressed);
+
+        let mut data = FILE_TYPE_IDENTIFIER.to_vec();
+        data.extend_from_slice(&SegmentId::new(3).as_bytes());
+        data.write_u32::<BigEndian>(hasher.finalize()).unwrap();
+        data.write_u32::<BigEndian>(compressed.len() as u32)
+            .unwrap();
+        data.extend_from_slice(&compressed);
+        let path = build_segment_path(dir.path(), SegmentId::new(3));
+        std::fs::write(&path, data).unwrap();
+
+        let migrated = migrate(dir.path(), None).await.unwrap();
+        assert_eq!(migrated, [SegmentId::new(3)]);
+        assert_eq!(
+            &std::fs::read(&path).unwrap()[..8],
+            VERSIONED_FILE_TYPE_IDENTIFIER
+        );
+        assert!(!dir.path().join(STAGING_DIR).exists());
+
+        // Migrating again has nothing to do
+        assert!(migrate(dir.path(), None).await.unwrap().is_empty());
+
+        // The migrated segment has the same entries
+        let wal = Wal::new(dir.path()).await.unwrap();
+        let mut reader = wal
+            .read_handle()
+            .reader_for_segment(SegmentId::new(3))
+            .await
+            .unwrap();
+        assert_eq!(reader.next_op().await.unwrap().unwrap(), op);
+        assert!(reader.next_op().await.unwrap().is_none());
+
+        // and now ends with a footer
+        let verified = wal.verify().await.unwrap();
+        assert!(verified[0].is_ok(), "{:?}", verified[0].problems);
+        assert_eq!(verified[0].footer.unwrap().entry_count, 1);
+    }
+}
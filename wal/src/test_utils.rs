@@ -0,0 +1,364 @@
+//! Utilities for testing how users of the WAL handle I/O failures and damaged segment files,
+//! enabled by the `test_utils` feature.
+//!
+//! * [`FaultInjector`] injects short writes, failed syncs and delays into the segment files of a
+//!   [`Wal`](crate::Wal) opened with
+//!   [`WalOptions::with_fault_injector`](crate::WalOptions::with_fault_injector).
+//! * [`FaultyObjectStore`] injects the same faults into an [`ObjectStore`], such as the one
+//!   segments are archived to.
+//! * [`SegmentBuilder`] writes segment files that are already damaged, as if by a crash or by
+//!   the disk, into a WAL directory.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use generated_types::influxdata::iox::wal::v1::SequencedWalOp as ProtoSequencedWalOp;
+use object_store::{
+    path::Path as ObjectStorePath, GetResult, ListResult, MultipartId, ObjectMeta, ObjectStore,
+};
+use prost::Message;
+use std::{fmt, ops::Range, path::Path, sync::Arc};
+use tokio::io::AsyncWrite;
+
+pub use crate::fault::FaultInjector;
+use crate::{blocking, build_segment_path, ClosedSegment, SegmentId, SequencedWalOp};
+
+/// An [`ObjectStore`] injecting the faults armed in a [`FaultInjector`] into the calls to the
+/// store it wraps.
+///
+/// Every call is delayed by the [`FaultInjector::set_delay`] delay. A short write stores the
+/// start of the object being put, as an interrupted upload to a store without atomic puts
+/// would, and fails the put.
+#[derive(Debug)]
+pub struct FaultyObjectStore {
+    inner: Arc<dyn ObjectStore>,
+    faults: Arc<FaultInjector>,
+}
+
+impl FaultyObjectStore {
+    /// Wraps `inner`, injecting the faults armed in `faults`.
+    pub fn new(inner: Arc<dyn ObjectStore>, faults: Arc<FaultInjector>) -> Self {
+        Self { inner, faults }
+    }
+
+    async fn delay(&self) {
+        tokio::time::sleep(self.faults.delay()).await;
+    }
+}
+
+impl fmt::Display for FaultyObjectStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FaultyObjectStore({})", self.inner)
+    }
+}
+
+#[async_trait]
+impl ObjectStore for FaultyObjectStore {
+    async fn put(&self, location: &ObjectStorePath, bytes: Bytes) -> object_store::Result<()> {
+        self.delay().await;
+
+        match self.faults.short_write_len(bytes.len()) {
+            Some(len) => {
+                self.inner.put(location, bytes.slice(..len)).await?;
+                Err(object_store::Error::Generic {
+                    store: "FaultyObjectStore",
+                    source: "injected short write".into(),
+                })
+            }
+            None => self.inner.put(location, bytes).await,
+        }
+    }
+
+    async fn put_multipart(
+        &self,
+        location: &ObjectStorePath,
+    ) -> object_store::Result<(MultipartId, Box<dyn AsyncWrite + Unpin + Send>)> {
+        self.delay().await;
+        self.inner.put_multipart(location).await
+    }
+
+    async fn abort_multipart(
+        &self,
+        location: &ObjectStorePath,
+        multipart_id: &MultipartId,
+    ) -> object_store::Result<()> {
+        self.inner.abort_multipart(location, multipart_id).await
+    }
+
+    async fn get(&self, location: &ObjectStorePath) -> object_store::Result<GetResult> {
+        self.delay().await;
+        self.inner.get(location).await
+    }
+
+    async fn get_range(
+        &self,
+        location: &ObjectStorePath,
+        range: Range<usize>,
+    ) -> object_store::Result<Bytes> {
+        self.delay().await;
+        self.inner.get_range(location, range).await
+    }
+
+    async fn head(&self, location: &ObjectStorePath) -> object_store::Result<ObjectMeta> {
+        self.delay().await;
+        self.inner.head(location).await
+    }
+
+    async fn delete(&self, location: &ObjectStorePath) -> object_store::Result<()> {
+        self.delay().await;
+        self.inner.delete(location).await
+    }
+
+    async fn list(
+        &self,
+        prefix: Option<&ObjectStorePath>,
+    ) -> object_store::Result<BoxStream<'_, object_store::Result<ObjectMeta>>> {
+        self.delay().await;
+        self.inner.list(prefix).await
+    }
+
+    async fn list_with_delimiter(
+        &self,
+        prefix: Option<&ObjectStorePath>,
+    ) -> object_store::Result<ListResult> {
+        self.delay().await;
+        self.inner.list_with_delimiter(prefix).await
+    }
+
+    async fn copy(&self, from: &ObjectStorePath, to: &ObjectStorePath) -> object_store::Result<()> {
+        self.delay().await;
+        self.inner.copy(from, to).await
+    }
+
+    async fn copy_if_not_exists(
+        &self,
+        from: &ObjectStorePath,
+        to: &ObjectStorePath,
+    ) -> object_store::Result<()> {
+        self.delay().await;
+        self.inner.copy_if_not_exists(from, to).await
+    }
+}
+
+/// Writes a segment file containing the given ops into a WAL directory, damaged as configured.
+///
+/// Panics on I/O errors.
+#[derive(Debug)]
+pub struct SegmentBuilder {
+    id: SegmentId,
+    ops: Vec<SequencedWalOp>,
+    corrupt_entries: Vec<usize>,
+    torn_bytes: Option<usize>,
+    footer: bool,
+}
+
+impl SegmentBuilder {
+    /// Builds the segment `id`, which is closed cleanly (with a footer) and undamaged unless
+    /// configured otherwise.
+    pub fn new(id: SegmentId) -> Self {
+        Self {
+            id,
+            ops: vec![],
+            corrupt_entries: vec![],
+            torn_bytes: None,
+            footer: true,
+        }
+    }
+
+    /// Appends `op` to the segment.
+    pub fn with_op(mut self, op: SequencedWalOp) -> Self {
+        self.ops.push(op);
+        self
+    }
+
+    /// Damages the data of the entry of the `index`th op, so that it fails its checksum.
+    pub fn with_corrupt_entry(mut self, index: usize) -> Self {
+        self.corrupt_entries.push(index);
+        self
+    }
+
+    /// Leaves the segment open (without a footer), as if the WAL crashed while writing it.
+    pub fn without_footer(mut self) -> Self {
+        self.footer = false;
+        self
+    }
+
+    /// Leaves the segment open with the last `bytes` bytes of its last entry missing, as if the
+    /// WAL crashed part way through writing it.
+    pub fn with_torn_tail(mut self, bytes: usize) -> Self {
+        self.torn_bytes = Some(bytes);
+        self.without_footer()
+    }
+
+    /// Writes the segment into the WAL directory `dir`, which must not already contain it.
+    pub fn build(self, dir: impl AsRef<Path>) -> ClosedSegment {
+        let path = build_segment_path(dir.as_ref(), self.id);
+        let mut writer =
+            blocking::OpenSegmentFileWriter::new_at_path(path.clone(), self.id, None).unwrap();
+
+        let mut entry_offsets = vec![];
+        for op in self.ops {
+            entry_offsets.push(writer.bytes_written());
+            let sequence_number = op.sequence_number;
+            writer
+                .write(
+                    &ProtoSequencedWalOp::from(op).encode_to_vec(),
+                    sequence_number,
+                )
+                .unwrap();
+        }
+        let entries_end = writer.bytes_written();
+
+        if self.footer {
+            writer.close().unwrap();
+        }
+
+        let mut data = std::fs::read(&path).unwrap();
+        for index in self.corrupt_entries {
+            // The first byte after the entry's checksum and length
+            data[entry_offsets[index] + 8] ^= 0xff;
+        }
+        if let Some(bytes) = self.torn_bytes {
+            data.truncate(entries_end - bytes);
+        }
+        std::fs::write(&path, &data).unwrap();
+
+        ClosedSegment {
+            id: self.id,
+            path,
+            size: data.len() as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{archive::SegmentArchiver, Error, Wal, WalOptions};
+    use generated_types::influxdata::iox::wal::v1::{sequenced_wal_op::Op as WalOp, PersistOp};
+    use object_store::memory::InMemory;
+
+    fn op(sequence_number: u64) -> SequencedWalOp {
+        SequencedWalOp {
+            sequence_number,
+            trace_context: None,
+            ingest_timestamp_ns: None,
+            op: WalOp::Persist(PersistOp {
+                namespace_id: 42,
+                parquet_file_uuid: "b4N4N4Z".into(),
+                partition_id: 43,
+                table_id: 44,
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn short_write_is_discarded() {
+        let dir = test_helpers::tmp_dir().unwrap();
+        let faults = Arc::new(FaultInjector::new());
+        let wal = Wal::new_with_options(
+            dir.path(),
+            WalOptions::default().with_fault_injector(Arc::clone(&faults)),
+        )
+        .await
+        .unwrap();
+        let writer = wal.write_handle().await;
+
+        writer.write_op(op(1)).await.unwrap();
+        faults.short_write_after(10);
+        let err = writer.write_op(op(2)).await.unwrap_err();
+        assert!(matches!(err, Error::UnableToWriteEntry { .. }), "{err}");
+        writer.write_op(op(3)).await.unwrap();
+
+        let closed = wal.rotation_handle().rotate().await.unwrap();
+        let mut reader = wal
+            .read_handle()
+            .reader_for_segment(closed.id())
+            .await
+            .unwrap();
+        assert_eq!(reader.next_op().await.unwrap(), Some(op(1)));
+        assert_eq!(reader.next_op().await.unwrap(), Some(op(3)));
+        assert_eq!(reader.next_op().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn failed_sync_stops_the_writer() {
+        let dir = test_helpers::tmp_dir().unwrap();
+        let faults = Arc::new(FaultInjector::new());
+        let wal = Wal::new_with_options(
+            dir.path(),
+            WalOptions::default().with_fault_injector(Arc::clone(&faults)),
+        )
+        .await
+        .unwrap();
+        let writer = wal.write_handle().await;
+
+        writer.write_op(op(1)).await.unwrap();
+        faults.fail_next_sync();
+        assert!(writer.write_op(op(2)).await.is_err());
+        assert!(writer.write_op(op(3)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn short_archive_upload() {
+        let dir = test_helpers::tmp_dir().unwrap();
+        let segment = SegmentBuilder::new(SegmentId::new(1))
+            .with_op(op(1))
+            .build(dir.path());
+
+        let faults = Arc::new(FaultInjector::new());
+        let store = Arc::new(InMemory::new());
+        let archiver = SegmentArchiver::new(
+            Arc::new(FaultyObjectStore::new(
+                Arc::clone(&store) as Arc<dyn ObjectStore>,
+                Arc::clone(&faults),
+            )),
+            ObjectStorePath::from("wal"),
+        );
+
+        faults.short_write_after(10);
+        assert!(archiver.archive(&segment).await.is_err());
+        let stored = store.get(&"wal/1.dat".into()).await.unwrap();
+        assert_eq!(stored.bytes().await.unwrap().len(), 10);
+
+        archiver.archive(&segment).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn damaged_segments() {
+        let dir = test_helpers::tmp_dir().unwrap();
+        SegmentBuilder::new(SegmentId::new(1))
+            .with_op(op(1))
+            .with_op(op(2))
+            .with_corrupt_entry(0)
+            .build(dir.path());
+        SegmentBuilder::new(SegmentId::new(2))
+            .with_op(op(3))
+            .with_op(op(4))
+            .with_torn_tail(3)
+            .build(dir.path());
+
+        let wal = Wal::new(dir.path()).await.unwrap();
+        let verified = wal.verify().await.unwrap();
+        assert!(!verified[0].is_ok());
+        // The torn tail was truncated when the WAL was opened, leaving a segment that was not
+        // closed cleanly
+        assert_eq!(verified[1].problems, [crate::SegmentProblem::MissingFooter]);
+
+        let mut reader = wal
+            .read_handle()
+            .recovering_reader_for_segment(SegmentId::new(1))
+            .await
+            .unwrap();
+        assert_eq!(reader.next_op().await.unwrap(), Some(op(2)));
+        assert_eq!(reader.take_corrupt_entries().await.unwrap().len(), 1);
+
+        let mut reader = wal
+            .read_handle()
+            .reader_for_segment(SegmentId::new(2))
+            .await
+            .unwrap();
+        assert_eq!(reader.next_op().await.unwrap(), Some(op(3)));
+        assert_eq!(reader.next_op().await.unwrap(), None);
+    }
+}
@@ -0,0 +1,127 @@
+//! Checks closed segment files against the footer written when they were closed.
+
+use crc32fast::Hasher;
+use snafu::prelude::*;
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::Path,
+    sync::Arc,
+};
+
+use crate::{
+    blocking, ClosedSegment, CorruptEntry, KeyProvider, Result, SegmentFooter,
+    UnableToOpenFileSnafu, UnableToReadEntriesSnafu, VerifyReadSegmentSnafu, FOOTER_FORMAT_VERSION,
+};
+
+/// The outcome of checking one closed segment with [`Wal::verify`](crate::Wal::verify).
+#[derive(Debug, Clone)]
+pub struct SegmentVerification {
+    /// The segment
+    pub segment: ClosedSegment,
+    /// The footer of the segment, if it has one
+    pub footer: Option<SegmentFooter>,
+    /// Everything found wrong with the segment; empty if it is intact
+    pub problems: Vec<SegmentProblem>,
+}
+
+impl SegmentVerification {
+    /// Whether no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Something wrong with a closed segment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SegmentProblem {
+    /// The header could not be read
+    UnreadableHeader(String),
+    /// A damaged entry, or entries that do not match the footer
+    CorruptEntry(CorruptEntry),
+    /// The segment is in a format version that has footers, but has none; it was not closed
+    /// cleanly, or has been truncated
+    MissingFooter,
+    /// The bytes before the footer do not match the checksum it records
+    ChecksumMismatch { expected: u32, actual: u32 },
+}
+
+/// Reads all of `segment` in recovery mode, reporting its damaged entries, and checks it against
+/// its footer.
+pub(crate) fn verify_segment(
+    segment: &ClosedSegment,
+    key_provider: Option<Arc<dyn KeyProvider>>,
+) -> Result<SegmentVerification> {
+    let mut reader = blocking::ClosedSegmentFileReader::from_path(&segment.path)
+        .context(UnableToOpenFileSnafu {
+            path: &segment.path,
+        })?
+        .with_recovery(true)
+        .with_key_provider(key_provider);
+
+    let mut problems = vec![];
+    if let Err(e) = reader.read_header() {
+        problems.push(SegmentProblem::UnreadableHeader(e.to_string()));
+        return Ok(SegmentVerification {
+            segment: segment.clone(),
+            footer: None,
+            problems,
+        });
+    }
+
+    // Damaged entries are skipped and recorded in recovery mode, so only
+    // I/O errors end the read early
+    while reader
+        .next_ops()
+        .context(UnableToReadEntriesSnafu)?
+        .is_some()
+    {}
+    problems.extend(
+        reader
+            .take_corrupt_entries()
+            .into_iter()
+            .map(SegmentProblem::CorruptEntry),
+    );
+
+    let footer = reader.footer();
+    match (footer, reader.footer_offset()) {
+        (Some(footer), Some(footer_offset)) => {
+            let actual = checksum_prefix(&segment.path, footer_offset)?;
+            if actual != footer.checksum {
+                problems.push(SegmentProblem::ChecksumMismatch {
+                    expected: footer.checksum,
+                    actual,
+                });
+            }
+        }
+        _ if reader.format_version() >= Some(FOOTER_FORMAT_VERSION) => {
+            problems.push(SegmentProblem::MissingFooter);
+        }
+        _ => {}
+    }
+
+    Ok(SegmentVerification {
+        segment: segment.clone(),
+        footer,
+        problems,
+    })
+}
+
+/// The checksum of the first `len` bytes of the file at `path`.
+fn checksum_prefix(path: &Path, len: u64) -> Result<u32> {
+    let f = File::open(path).context(UnableToOpenFileSnafu { path })?;
+    let mut f = f.take(len);
+
+    let mut hasher = Hasher::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        match f.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => hasher.update(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e).context(VerifyReadSegmentSnafu { path }),
+        }
+    }
+
+    Ok(hasher.finalize())
+}
@@ -1,5 +1,6 @@
 use data_types::{NamespaceId, TableId};
 use dml::DmlWrite;
+use futures::StreamExt;
 use generated_types::influxdata::{
     iox::wal::v1::sequenced_wal_op::Op as WalOp,
     pbdata::v1::{DatabaseBatch, TableBatch},
@@ -27,13 +28,13 @@ async fn crud() {
     // Can write an entry to the open segment
     let op = arbitrary_sequenced_wal_op(42);
     let summary = open.write_op(op).await.unwrap();
-    assert_eq!(summary.total_bytes, 122);
+    assert_eq!(summary.total_bytes, 130);
     assert_eq!(summary.bytes_written, 106);
 
     // Can write another entry; total_bytes accumulates
     let op = arbitrary_sequenced_wal_op(43);
     let summary = open.write_op(op).await.unwrap();
-    assert_eq!(summary.total_bytes, 228);
+    assert_eq!(summary.total_bytes, 236);
     assert_eq!(summary.bytes_written, 106);
 
     // Still no closed segments
@@ -47,7 +48,7 @@ async fn crud() {
     // Can't read entries from the open segment; have to rotate first
     let wal_rotator = wal.rotation_handle();
     let closed_segment_details = wal_rotator.rotate().await.unwrap();
-    assert_eq!(closed_segment_details.size(), 228);
+    assert_eq!(closed_segment_details.size(), 272);
 
     // There's one closed segment
     let closed = wal_reader.closed_segments().await;
@@ -163,10 +164,41 @@ async fn ordering() {
     assert_eq!(closed_segment_details.id().get(), 4);
 }
 
+#[tokio::test]
+async fn tail() {
+    let dir = test_helpers::tmp_dir().unwrap();
+
+    let wal = wal::Wal::new(dir.path()).await.unwrap();
+    let open = wal.write_handle().await;
+    let wal_rotator = wal.rotation_handle();
+
+    // Ops written before subscribing are not seen
+    open.write_op(arbitrary_sequenced_wal_op(41)).await.unwrap();
+
+    let mut tail = wal.tail();
+
+    // Ops are seen across segment rotations
+    open.write_op(arbitrary_sequenced_wal_op(42)).await.unwrap();
+    wal_rotator.rotate().await.unwrap();
+    open.write_op(arbitrary_sequenced_wal_op(43)).await.unwrap();
+
+    let op = tail.next().await.unwrap().unwrap();
+    assert_eq!(op, arbitrary_sequenced_wal_op(42));
+    let op = tail.next().await.unwrap().unwrap();
+    assert_eq!(op, arbitrary_sequenced_wal_op(43));
+
+    // The stream ends once the WAL and its writers are gone
+    drop(open);
+    drop(wal);
+    assert!(tail.next().await.is_none());
+}
+
 fn arbitrary_sequenced_wal_op(sequence_number: u64) -> SequencedWalOp {
     let w = test_data("m1,t=foo v=1i 1");
     SequencedWalOp {
         sequence_number,
+        trace_context: None,
+        ingest_timestamp_ns: None,
         op: WalOp::Write(w),
     }
 }